@@ -0,0 +1,596 @@
+//! Generic signed messages, as specified in
+//! [BIP 322](https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki).
+//!
+//! Unlike BIP 137 signed messages, a BIP 322 signature is a signed spend of a
+//! virtual output locked by the address's script pubkey. This proves
+//! ownership of segwit and taproot addresses. The `to_spend` transaction
+//! creates the output, committing to the message. The `to_sign` transaction
+//! spends it.
+//!
+//! The "simple" format is the witness of the `to_sign` input, and is used for
+//! witness addresses. The "full" format is the whole `to_sign` transaction,
+//! and is needed when the input has a script sig.
+//!
+//! Checking arbitrary scripts needs a script interpreter. Signing and
+//! verification are supported for single-key addresses: P2PKH, P2SH-P2WPKH,
+//! P2WPKH, and P2TR key path spends. Other addresses, and proofs of funds,
+//! error rather than report an invalid signature.
+
+use std::convert::TryFrom;
+
+use coins_bip32::ecdsa::{
+    signature::hazmat::{PrehashSigner, PrehashVerifier},
+    Signature, SigningKey, VerifyingKey,
+};
+use coins_core::{
+    hashes::{Digest, Hash160},
+    ser::{self, ByteFormat},
+    types::tx::Transaction,
+};
+use k256::{elliptic_curve::PrimeField, schnorr, Scalar};
+use thiserror::Error;
+
+use crate::{
+    hashes::tagged_hash,
+    types::{
+        amount::Amount,
+        legacy::{LegacySighashArgs, LegacyTx},
+        limits::{read_bytes, read_len, ReadLimits},
+        script::{
+            push_data, witness_program, Instructions, Script, ScriptPubkey, ScriptType, Witness,
+            WitnessStackItem,
+        },
+        tx::{BitcoinTransaction, BitcoinTx, Sighash, TxError},
+        txin::{BitcoinOutpoint, BitcoinTxIn},
+        txout::TxOut,
+        witness::{TaprootSighashArgs, WitnessSighashArgs, WitnessTransaction, WitnessTx},
+    },
+};
+
+/// The BIP 340 tag of the message hash.
+pub const MESSAGE_TAG: &str = "BIP0322-signed-message";
+
+/// BIP 322 errors
+#[derive(Debug, Error)]
+pub enum Bip322Error {
+    /// The script pubkey is not a supported type
+    #[error("Unsupported script pubkey")]
+    UnsupportedScript,
+
+    /// The `to_sign` transaction has more than one input
+    #[error("Proofs of funds are not supported")]
+    ProofOfFundsUnsupported,
+
+    /// The signing key does not own the script pubkey
+    #[error("The key does not own the script pubkey")]
+    WrongKey,
+
+    /// The signature is neither a witness nor a transaction
+    #[error("Malformed BIP 322 signature")]
+    Malformed,
+
+    /// The signature is not valid base64
+    #[error(transparent)]
+    Base64Error(#[from] base64::DecodeError),
+
+    /// Error calculating a sighash or serializing a transaction
+    #[error(transparent)]
+    TxError(#[from] TxError),
+
+    /// Error creating a signature
+    #[error(transparent)]
+    SignatureError(#[from] coins_bip32::ecdsa::Error),
+}
+
+/// A BIP 322 signature
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Bip322Signature {
+    /// The witness of the `to_sign` input. For witness addresses.
+    Simple(Witness),
+    /// The signed `to_sign` transaction. For addresses that need a script sig.
+    Full(BitcoinTx),
+}
+
+impl Bip322Signature {
+    /// Serialize the signature. A simple signature is serialized as a witness
+    /// is in a transaction. A full signature is the serialized transaction.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        match self {
+            Bip322Signature::Simple(witness) => {
+                ser::write_prefix_vec(&mut buf, witness).expect("no error writing to vec");
+            }
+            Bip322Signature::Full(tx) => {
+                tx.write_to(&mut buf).expect("no error writing to vec");
+            }
+        };
+        buf
+    }
+
+    /// Deserialize a signature. Bytes that are exactly one witness are a
+    /// simple signature. Otherwise they must be exactly one transaction.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Bip322Error> {
+        let limits = ReadLimits::default();
+
+        let mut reader = bytes;
+        let witness = read_len(&mut reader, "witness item count", limits.max_witness_items)
+            .and_then(|items| {
+                (0..items)
+                    .map(|_| {
+                        read_bytes(&mut reader, "witness item length", limits.max_script_len)
+                            .map(WitnessStackItem::new)
+                    })
+                    .collect::<Result<Witness, _>>()
+            });
+        if let Ok(witness) = witness {
+            if reader.is_empty() {
+                return Ok(Bip322Signature::Simple(witness));
+            }
+        }
+
+        let mut reader = bytes;
+        match BitcoinTx::read_from_with_limits(&mut reader, &limits) {
+            Ok(tx) if reader.is_empty() => Ok(Bip322Signature::Full(tx)),
+            _ => Err(Bip322Error::Malformed),
+        }
+    }
+
+    /// Serialize the signature as base64, as it is usually displayed.
+    pub fn to_base64(&self) -> String {
+        base64::encode(self.to_bytes())
+    }
+
+    /// Deserialize a base64 signature.
+    pub fn from_base64(sig: &str) -> Result<Self, Bip322Error> {
+        Self::from_bytes(&base64::decode(sig)?)
+    }
+}
+
+/// The message hash committed to by `to_spend`. The BIP 340 tagged hash of
+/// the message, with tag `MESSAGE_TAG`.
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    tagged_hash(MESSAGE_TAG, message)
+}
+
+/// The virtual `to_spend` transaction. Its only output has value 0, and is
+/// locked by `script_pubkey`. Its only input commits to the message hash.
+pub fn to_spend(script_pubkey: &ScriptPubkey, message: &[u8]) -> LegacyTx {
+    let mut script_sig = vec![0x00, 0x20]; // OP_0, PUSH_32
+    script_sig.extend(&message_hash(message));
+    let input = BitcoinTxIn::new(BitcoinOutpoint::null(), script_sig, 0);
+    let output = TxOut::new(Amount::ZERO, script_pubkey.clone());
+    LegacyTx::new(0, vec![input], vec![output], 0).expect("has inputs and outputs")
+}
+
+/// The unsigned virtual `to_sign` transaction. Its only input spends the
+/// output of `to_spend`, and its only output is an empty OP_RETURN.
+pub fn to_sign(to_spend: &LegacyTx) -> WitnessTx {
+    let input = BitcoinTxIn::new(BitcoinOutpoint::new(to_spend.txid(), 0), vec![], 0);
+    let output = TxOut::new(Amount::ZERO, vec![0x6a]);
+    <WitnessTx as Transaction>::new(0, vec![input], vec![output], 0)
+        .expect("has inputs and outputs")
+}
+
+/// The hash of a key, as committed to by P2PKH and P2WPKH
+fn key_hash(key: &[u8]) -> [u8; 20] {
+    let mut hash = [0u8; 20];
+    hash.copy_from_slice(&Hash160::digest(key));
+    hash
+}
+
+/// The taproot output key of a BIP 86 key path only output with `key` as its
+/// internal key
+fn tweak_key(key: &SigningKey) -> Result<schnorr::SigningKey, Bip322Error> {
+    let internal = schnorr::SigningKey::from_bytes(&key.to_bytes())?;
+    let tweak = tagged_hash("TapTweak", &internal.verifying_key().to_bytes());
+    let tweak = Option::<Scalar>::from(Scalar::from_repr(tweak.into()))
+        .ok_or_else(coins_bip32::ecdsa::Error::new)?;
+    let secret = Option::<Scalar>::from(Scalar::from_repr(internal.to_bytes()))
+        .expect("signing keys are valid scalars");
+    Ok(schnorr::SigningKey::from_bytes(
+        &(secret + tweak).to_bytes(),
+    )?)
+}
+
+/// Sign the BIP143 sighash of the P2WPKH `to_sign` input, and return its
+/// witness
+fn sign_wpkh(tx: &WitnessTx, key: &SigningKey) -> Result<Witness, Bip322Error> {
+    let key_bytes = key.verifying_key().to_bytes();
+    let args = WitnessSighashArgs {
+        index: 0,
+        sighash_flag: Sighash::All,
+        prevout_script: Script::from(&ScriptPubkey::p2pkh_from_hash(&key_hash(&key_bytes))),
+        prevout_value: Amount::ZERO,
+    };
+    let sighash = tx.witness_sighash(&args)?;
+    let sig: Signature = key.sign_prehash(&sighash)?;
+
+    let mut sig = sig.to_der().as_bytes().to_vec();
+    sig.push(Sighash::All.to_u8());
+    Ok(vec![sig.into(), key_bytes.to_vec().into()])
+}
+
+/// Sign `message` for the address with `script_pubkey`, using `key`.
+///
+/// P2WPKH and P2TR addresses get simple signatures. P2PKH and P2SH-P2WPKH
+/// addresses need a script sig, and get full signatures. P2TR addresses must
+/// be BIP 86 key path only outputs with `key` as their internal key.
+/// Schnorr signatures are made without auxiliary randomness.
+///
+/// Errors with `WrongKey` if `key` does not own the address, and with
+/// `UnsupportedScript` for other address types.
+pub fn sign(
+    key: &SigningKey,
+    script_pubkey: &ScriptPubkey,
+    message: &[u8],
+) -> Result<Bip322Signature, Bip322Error> {
+    let to_spend = to_spend(script_pubkey, message);
+    let mut tx = to_sign(&to_spend);
+
+    match script_pubkey.standard_type() {
+        ScriptType::Wpkh(_) => {
+            if script_pubkey != &ScriptPubkey::p2wpkh(key) {
+                return Err(Bip322Error::WrongKey);
+            }
+            Ok(Bip322Signature::Simple(sign_wpkh(&tx, key)?))
+        }
+        ScriptType::Sh(_) => {
+            if script_pubkey != &ScriptPubkey::p2sh_p2wpkh(key) {
+                return Err(Bip322Error::WrongKey);
+            }
+            let mut script_sig = vec![];
+            push_data(&mut script_sig, ScriptPubkey::p2wpkh(key).items());
+            tx.legacy_tx.vin[0].script_sig = script_sig.into();
+            tx.witnesses[0] = sign_wpkh(&tx, key)?;
+            Ok(Bip322Signature::Full(tx.into()))
+        }
+        ScriptType::Pkh(_) => {
+            if script_pubkey != &ScriptPubkey::p2pkh(key) {
+                return Err(Bip322Error::WrongKey);
+            }
+            let args = LegacySighashArgs {
+                index: 0,
+                sighash_flag: Sighash::All,
+                prevout_script: Script::from(script_pubkey),
+            };
+            let sighash = tx.as_legacy().consensus_sighash(&args)?;
+            let sig: Signature = key.sign_prehash(&sighash.to_internal())?;
+
+            let mut sig = sig.to_der().as_bytes().to_vec();
+            sig.push(Sighash::All.to_u8());
+            let mut script_sig = vec![];
+            push_data(&mut script_sig, &sig);
+            push_data(&mut script_sig, &key.verifying_key().to_bytes());
+            tx.legacy_tx.vin[0].script_sig = script_sig.into();
+            Ok(Bip322Signature::Full(tx.into_legacy().into()))
+        }
+        ScriptType::Tr(_) => {
+            let output_key = tweak_key(key)?;
+            if script_pubkey != &ScriptPubkey::p2tr(&output_key.verifying_key().to_bytes().into()) {
+                return Err(Bip322Error::WrongKey);
+            }
+            let args = TaprootSighashArgs {
+                index: 0,
+                sighash_flag: None,
+                prevouts: to_spend.outputs().to_vec(),
+                annex: None,
+                leaf_hash: None,
+                codesep_pos: 0xffff_ffff,
+            };
+            let sighash = tx.taproot_sighash(&args)?;
+            let sig = output_key.try_sign_prehashed(&sighash, &[0u8; 32])?;
+            Ok(Bip322Signature::Simple(vec![sig
+                .as_bytes()
+                .to_vec()
+                .into()]))
+        }
+        _ => Err(Bip322Error::UnsupportedScript),
+    }
+}
+
+/// Split a signature into its DER signature and sighash flag. `None` if
+/// either is invalid.
+fn parse_ecdsa_sig(sig: &[u8]) -> Option<(Signature, Sighash)> {
+    let (flag, der) = sig.split_last()?;
+    let flag = Sighash::from_u8(*flag).ok()?;
+    let sig = Signature::from_der(der).ok()?;
+    Some((sig, flag))
+}
+
+/// Verify the P2WPKH witness of the `to_sign` input
+fn verify_wpkh(tx: &WitnessTx, program: &[u8]) -> Result<bool, Bip322Error> {
+    let witness = &tx.witnesses()[0];
+    if witness.len() != 2 || witness[1].len() != 33 || key_hash(witness[1].items()) != program {
+        return Ok(false);
+    }
+    let (sig, flag) = match parse_ecdsa_sig(witness[0].items()) {
+        Some(parsed) => parsed,
+        None => return Ok(false),
+    };
+    let key = match VerifyingKey::from_sec1_bytes(witness[1].items()) {
+        Ok(key) => key,
+        Err(_) => return Ok(false),
+    };
+
+    let args = WitnessSighashArgs {
+        index: 0,
+        sighash_flag: flag,
+        prevout_script: Script::from(&ScriptPubkey::p2pkh_from_hash(&key_hash(
+            witness[1].items(),
+        ))),
+        prevout_value: Amount::ZERO,
+    };
+    let sighash = tx.witness_sighash(&args)?;
+    Ok(key.verify_prehash(&sighash, &sig).is_ok())
+}
+
+/// Verify the P2PKH script sig of the `to_sign` input
+fn verify_pkh(tx: &WitnessTx, script_pubkey: &ScriptPubkey) -> Result<bool, Bip322Error> {
+    if !tx.witnesses()[0].is_empty() {
+        return Ok(false);
+    }
+    let script_sig = tx.inputs()[0].script_sig.items();
+    let mut instructions = Instructions::new(script_sig);
+    let pushes: Vec<_> = instructions.by_ref().collect();
+    let (sig, key) = match pushes.as_slice() {
+        [(sig_op, sig), (key_op, key)] if *sig_op <= 0x4e && *key_op <= 0x4e => (sig, key),
+        _ => return Ok(false),
+    };
+    if instructions.truncated() || &ScriptPubkey::p2pkh_from_hash(&key_hash(key)) != script_pubkey {
+        return Ok(false);
+    }
+    let (sig, flag) = match parse_ecdsa_sig(sig) {
+        Some(parsed) => parsed,
+        None => return Ok(false),
+    };
+    let key = match VerifyingKey::from_sec1_bytes(key) {
+        Ok(key) => key,
+        Err(_) => return Ok(false),
+    };
+
+    let args = LegacySighashArgs {
+        index: 0,
+        sighash_flag: flag,
+        prevout_script: Script::from(script_pubkey),
+    };
+    let sighash = tx.as_legacy().consensus_sighash(&args)?;
+    Ok(key.verify_prehash(&sighash.to_internal(), &sig).is_ok())
+}
+
+/// Verify the P2TR key path witness of the `to_sign` input
+fn verify_tr(tx: &WitnessTx, prevout: &TxOut, output_key: &[u8; 32]) -> Result<bool, Bip322Error> {
+    let witness = &tx.witnesses()[0];
+    let (sig, annex) = match witness.as_slice() {
+        [sig] => (sig.items(), None),
+        [sig, annex] if annex.items().first() == Some(&0x50) => {
+            (sig.items(), Some(annex.items().to_vec()))
+        }
+        [] => return Ok(false),
+        // script path spends need an interpreter
+        _ => return Err(Bip322Error::UnsupportedScript),
+    };
+    let sighash_flag = match sig.len() {
+        64 => None,
+        65 if sig[64] != 0 => match Sighash::from_u8(sig[64]) {
+            Ok(flag) => Some(flag),
+            Err(_) => return Ok(false),
+        },
+        _ => return Ok(false),
+    };
+    let (sig, key) = match (
+        schnorr::Signature::try_from(&sig[..64]),
+        schnorr::VerifyingKey::from_bytes(output_key),
+    ) {
+        (Ok(sig), Ok(key)) => (sig, key),
+        _ => return Ok(false),
+    };
+
+    let args = TaprootSighashArgs {
+        index: 0,
+        sighash_flag,
+        prevouts: vec![prevout.clone()],
+        annex,
+        leaf_hash: None,
+        codesep_pos: 0xffff_ffff,
+    };
+    let sighash = tx.taproot_sighash(&args)?;
+    Ok(key.verify_prehashed(&sighash, &sig).is_ok())
+}
+
+/// Verify that `sig` is a BIP 322 signature over `message` by the owner of the
+/// address with `script_pubkey`. Returns false if the signature is
+/// well-formed, but invalid.
+///
+/// Errors with `UnsupportedScript` if the address is not a single-key type,
+/// or a P2TR signature spends its script path. Errors with
+/// `ProofOfFundsUnsupported` if a full signature has more than one input, and
+/// with `Malformed` if it has none.
+///
+/// Use an address encoder's `decode_address` to get the script pubkey of an
+/// address.
+pub fn verify(
+    script_pubkey: &ScriptPubkey,
+    message: &[u8],
+    sig: &Bip322Signature,
+) -> Result<bool, Bip322Error> {
+    let to_spend = to_spend(script_pubkey, message);
+    let tx = match sig {
+        Bip322Signature::Simple(witness) => {
+            let mut tx = to_sign(&to_spend);
+            tx.witnesses[0] = witness.clone();
+            tx
+        }
+        Bip322Signature::Full(tx) => {
+            let input = match tx.inputs() {
+                [input] => input,
+                [] => return Err(Bip322Error::Malformed),
+                _ => return Err(Bip322Error::ProofOfFundsUnsupported),
+            };
+            let outputs = tx.outputs();
+            if input.outpoint != BitcoinOutpoint::new(to_spend.txid(), 0)
+                || outputs.len() != 1
+                || outputs[0] != TxOut::new(Amount::ZERO, vec![0x6a])
+            {
+                return Ok(false);
+            }
+            tx.clone().into_witness()
+        }
+    };
+
+    let prevout = &to_spend.outputs()[0];
+    let script_sig = tx.inputs()[0].script_sig.items();
+    match script_pubkey.standard_type() {
+        ScriptType::Pkh(_) => verify_pkh(&tx, script_pubkey),
+        ScriptType::Wpkh(_) if script_sig.is_empty() => verify_wpkh(
+            &tx,
+            witness_program(script_pubkey.items()).expect("is wpkh").1,
+        ),
+        ScriptType::Sh(_) => {
+            let mut expected = vec![];
+            let redeem_script = &script_sig[script_sig.len().min(1)..];
+            push_data(&mut expected, redeem_script);
+            if expected != script_sig
+                || script_pubkey != &ScriptPubkey::p2sh(&Script::from(redeem_script))
+            {
+                return Ok(false);
+            }
+            match witness_program(redeem_script) {
+                Some((0, program)) if program.len() == 20 => verify_wpkh(&tx, program),
+                _ => Err(Bip322Error::UnsupportedScript),
+            }
+        }
+        ScriptType::Tr(output_key) if script_sig.is_empty() => verify_tr(&tx, prevout, &output_key),
+        ScriptType::Wpkh(_) | ScriptType::Tr(_) => Ok(false),
+        _ => Err(Bip322Error::UnsupportedScript),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The BIP 322 test vector key
+    const KEY: &str = "bb051cd0dda0246f33c5a9e133ebd8e7bc02a92af6c41adc131ccd7826c5b004";
+
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(&hex::decode(KEY).unwrap()).unwrap()
+    }
+
+    // bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l
+    fn p2wpkh() -> ScriptPubkey {
+        ScriptPubkey::new(hex::decode("00142b05d564e6a7a33c087f16e0f730d1440123799d").unwrap())
+    }
+
+    // bc1ppv609nr0vr25u07u95waq5lucwfm6tde4nydujnu8npg4q75mr5sxq8lt3
+    fn p2tr() -> ScriptPubkey {
+        ScriptPubkey::new(
+            hex::decode("51200b34f2cc6f60d54e3fdc2d1dd053fcc393bd2db9acc8de4a7c3cc28a83d4d8e9")
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn it_builds_virtual_txns() {
+        let cases = [
+            (
+                &b""[..],
+                "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1",
+                "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7",
+                "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6",
+            ),
+            (
+                &b"Hello World"[..],
+                "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a",
+                "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b",
+                "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf",
+            ),
+        ];
+        for (message, hash, to_spend_id, to_sign_id) in cases.iter() {
+            assert_eq!(hex::encode(message_hash(message)), *hash);
+            let to_spend = to_spend(&p2wpkh(), message);
+            assert_eq!(to_spend.txid().to_be_hex(), *to_spend_id);
+            assert_eq!(to_sign(&to_spend).txid().to_be_hex(), *to_sign_id);
+        }
+    }
+
+    #[test]
+    fn it_verifies_test_vectors() {
+        let cases = [
+            (
+                p2wpkh(),
+                &b""[..],
+                "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=",
+            ),
+            (
+                p2wpkh(),
+                &b"Hello World"[..],
+                "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=",
+            ),
+            (
+                p2tr(),
+                &b"Hello World"[..],
+                "AUHd69PrJQEv+oKTfZ8l+WROBHuy9HKrbFCJu7U1iK2iiEy1vMU5EfMtjc+VSHM7aU0SDbak5IUZRVno2P5mjSafAQ==",
+            ),
+        ];
+        for (script_pubkey, message, sig) in cases.iter() {
+            let sig = Bip322Signature::from_base64(sig).unwrap();
+            assert!(matches!(sig, Bip322Signature::Simple(_)));
+            assert!(verify(script_pubkey, message, &sig).unwrap());
+            assert!(!verify(script_pubkey, b"another message", &sig).unwrap());
+        }
+    }
+
+    #[test]
+    fn it_signs_and_verifies_messages() {
+        let key = key();
+        let message = b"This is an example of a signed message.";
+        let script_pubkeys = [
+            ScriptPubkey::p2pkh(&key),
+            ScriptPubkey::p2sh_p2wpkh(&key),
+            p2wpkh(),
+            p2tr(),
+        ];
+        for script_pubkey in script_pubkeys.iter() {
+            let sig = sign(&key, script_pubkey, message).unwrap();
+            let simple = matches!(
+                script_pubkey.standard_type(),
+                ScriptType::Wpkh(_) | ScriptType::Tr(_)
+            );
+            assert_eq!(matches!(sig, Bip322Signature::Simple(_)), simple);
+            assert_eq!(Bip322Signature::from_base64(&sig.to_base64()).unwrap(), sig);
+
+            assert!(verify(script_pubkey, message, &sig).unwrap());
+            assert!(!verify(script_pubkey, b"another message", &sig).unwrap());
+            // another address of the same key is not proven. A P2WPKH witness looks like a P2TR
+            // script path spend, which can not be checked
+            for other in script_pubkeys.iter().filter(|s| s != &script_pubkey) {
+                assert!(!verify(other, message, &sig).unwrap_or(false));
+            }
+        }
+
+        let other = SigningKey::from_bytes(&[0x11; 32]).unwrap();
+        assert!(matches!(
+            sign(&other, &p2wpkh(), message),
+            Err(Bip322Error::WrongKey)
+        ));
+        assert!(matches!(
+            sign(&key, &ScriptPubkey::p2wsh(&Script::null()), message),
+            Err(Bip322Error::UnsupportedScript)
+        ));
+        assert!(matches!(
+            Bip322Signature::from_bytes(&[0x01, 0x05, 0x00]),
+            Err(Bip322Error::Malformed)
+        ));
+
+        // a full signature without inputs
+        let sig = Bip322Signature::from_bytes(
+            &hex::decode("02000000000100010000000000000000016a00000000").unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(sig, Bip322Signature::Full(_)));
+        assert!(matches!(
+            verify(&p2wpkh(), message, &sig),
+            Err(Bip322Error::Malformed)
+        ));
+    }
+}
@@ -6,23 +6,119 @@
 //! They can simply use the builder transparently to produce the desired tx type.
 //!
 //! The builder is best accessed via the preconstructed network objects in `nets.rs`.
+//!
+//! Given a feerate and a change address, `build_funded` selects inputs from a set of UTXOs to pay
+//! the builder's outputs, and adds a change output for the remainder.
+//!
+//! Paying a silent payment address does not add an output immediately. The output commits to the
+//! inputs, so it is derived by `derive_silent_payments` once all inputs are added.
 
-use std::marker::PhantomData;
+use std::{
+    cmp::Ordering,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    marker::PhantomData,
+};
 
-use coins_core::{builder::TxBuilder, enc::AddressEncoder, types::tx::Transaction};
+use coins_core::{
+    builder::TxBuilder,
+    enc::{AddressEncoder, EncodingError},
+    hashes::MarkedDigestOutput,
+    ser::ByteFormat,
+    types::tx::Transaction,
+};
+use thiserror::Error;
 
 use crate::{
+    coinselect::{self, Candidate, CoinSelectError, SelectionParams},
     enc::encoder::{Address, BitcoinEncoderMarker},
+    silent_payments::{self, InputKey, SilentPaymentAddress, SilentPaymentError},
     types::{
+        amount::Amount,
         legacy::LegacyTx,
-        script::{ScriptPubkey, ScriptSig, Witness},
-        tx::{BitcoinTransaction, BitcoinTx},
-        txin::{BitcoinOutpoint, BitcoinTxIn},
+        locktime::LockTime,
+        script::{ScriptPubkey, ScriptSig, ScriptType, Witness, MAX_OP_RETURN_DATA},
+        tx::{BitcoinTransaction, BitcoinTx, TxError},
+        txin::{BitcoinOutpoint, BitcoinTxIn, RBF_SEQUENCE},
         txout::TxOut,
+        utxo::{SpendScript, Utxo},
         witness::{WitnessTransaction, WitnessTx},
+        FeeRate,
     },
 };
 
+/// The sequence number of inputs added by `build_funded`. Enables locktime, but not RBF. Use
+/// `set_rbf` to signal replaceability.
+pub const FUNDED_INPUT_SEQUENCE: u32 = 0xffff_fffe;
+
+/// Sequence numbers with this bit set do not encode a BIP68 relative timelock
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// Errors in funding a transaction with `build_funded`
+#[derive(Debug, Error)]
+pub enum BuilderError {
+    /// Error constructing the transaction
+    #[error(transparent)]
+    TxError(#[from] TxError),
+
+    /// Error selecting inputs. E.g. the UTXOs can not pay for the outputs and fee
+    #[error(transparent)]
+    CoinSelectError(#[from] CoinSelectError),
+
+    /// Error deriving silent payment outputs
+    #[error(transparent)]
+    SilentPaymentError(#[from] SilentPaymentError),
+
+    /// The OP_RETURN payload is larger than the policy limit
+    #[error("OP_RETURN data is {0} bytes. The limit is {}", MAX_OP_RETURN_DATA)]
+    OpReturnTooLarge(usize),
+
+    /// A recipient address passed to `pay_many` could not be decoded
+    #[error("Bad recipient at index {index}: {address}. {source}")]
+    BadRecipient {
+        /// The index of the recipient in the batch
+        index: usize,
+        /// The address that failed to decode
+        address: Address,
+        /// The decoding error
+        source: EncodingError,
+    },
+
+    /// `build_funded` was called without a feerate
+    #[error("No feerate set. Use `fee_rate` to set one")]
+    MissingFeeRate,
+
+    /// `build_funded` was called without a change address
+    #[error("No change address set. Use `change_address` to set one")]
+    MissingChangeAddress,
+
+    /// `build_funded` was called on a builder that already has inputs. The values of those inputs
+    /// are unknown, so they can not be accounted for
+    #[error("Builder already has inputs. build_funded selects all inputs")]
+    InputsAlreadySet,
+}
+
+/// Type alias for result with BuilderError
+pub type BuilderResult<T> = Result<T, BuilderError>;
+
+/// BIP69 orders txids by their big-endian (block explorer) representation
+fn bip69_input_order(a: &BitcoinTxIn, b: &BitcoinTxIn) -> Ordering {
+    let (a_txid, b_txid) = (a.outpoint.txid.as_slice(), b.outpoint.txid.as_slice());
+    a_txid
+        .iter()
+        .rev()
+        .cmp(b_txid.iter().rev())
+        .then(a.outpoint.idx.cmp(&b.outpoint.idx))
+}
+
+/// True if spending the UTXO requires a witness
+fn spends_witness(utxo: &Utxo) -> bool {
+    match (utxo.standard_type(), utxo.spend_script()) {
+        (ScriptType::Sh(_), SpendScript::Known(script)) => script.standard_type().is_witness(),
+        (script_type, _) => script_type.is_witness(),
+    }
+}
+
 /// This is a generic builder for Bitcoin transactions. It allows you to easily build legacy and
 /// witness transactions.
 ///
@@ -31,7 +127,7 @@ use crate::{
 ///
 /// It is parameterized with an address encoder, so that the same struct and logic can be used on
 /// mainnet and testnet.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BitcoinTxBuilder<T: AddressEncoder> {
     version: u32,
     vin: Vec<BitcoinTxIn>,
@@ -39,6 +135,11 @@ pub struct BitcoinTxBuilder<T: AddressEncoder> {
     locktime: u32,
     witnesses: Vec<Witness>,
     produce_witness: bool,
+    fee_rate: Option<FeeRate>,
+    change: Option<ScriptPubkey>,
+    rbf: Option<bool>,
+    bip69: bool,
+    silent_payments: Vec<(Amount, SilentPaymentAddress)>,
     encoder: PhantomData<fn(T) -> T>,
 }
 
@@ -66,10 +167,13 @@ where
         self
     }
 
-    /// Add an op_return output. Using this twice may render the transaction non-standard.
-    pub fn op_return(mut self, message: &[u8]) -> Self {
-        self.vout.push(TxOut::op_return(message));
-        self
+    /// Add an op_return output, pushing `data` in a single push. Errors if `data` is larger than
+    /// the `MAX_OP_RETURN_DATA` policy limit. Using this twice may render the transaction
+    /// non-standard.
+    pub fn op_return(self, data: &[u8]) -> BuilderResult<Self> {
+        let script_pubkey =
+            ScriptPubkey::op_return(data).ok_or(BuilderError::OpReturnTooLarge(data.len()))?;
+        Ok(self.pay_script_pubkey(Amount::ZERO, script_pubkey))
     }
 
     /// Set the script sig at a specific input. Do nothing if the vin is not that long.
@@ -82,13 +186,100 @@ where
         }
     }
 
+    /// Spend an outpoint with a sequence number that signals BIP125 replaceability
+    pub fn spend_replaceable<I>(self, prevout: I) -> Self
+    where
+        I: Into<BitcoinOutpoint>,
+    {
+        self.spend(prevout, RBF_SEQUENCE)
+    }
+
+    /// Set the locktime of the transaction. The locktime is only enforced if an input has a
+    /// sequence number below `0xffff_ffff`.
+    pub fn set_locktime(self, locktime: LockTime) -> Self {
+        self.locktime(locktime.to_consensus_u32())
+    }
+
+    /// Set whether the transaction signals BIP125 replaceability. Applies to all inputs when the
+    /// transaction is built. If true, sequence numbers above `RBF_SEQUENCE` are lowered to it.
+    /// If false, signaling sequence numbers are raised to `0xffff_fffe`, except for those
+    /// encoding a BIP68 relative timelock, which always signal.
+    pub fn set_rbf(mut self, rbf: bool) -> Self {
+        self.rbf = Some(rbf);
+        self
+    }
+
+    /// Sort inputs and outputs as specified in BIP69. Inputs are ordered by the big-endian txid
+    /// and index of their outpoint, and outputs by value and then script pubkey. Witnesses are
+    /// kept with their inputs. This does not affect inputs or outputs added later. See `set_bip69`
+    pub fn sort_bip69(mut self) -> Self {
+        self.apply_bip69();
+        self
+    }
+
+    fn apply_bip69(&mut self) {
+        if !self.witnesses.is_empty() {
+            self.witnesses.resize(self.vin.len(), Witness::default());
+            let mut pairs: Vec<_> = self.vin.drain(..).zip(self.witnesses.drain(..)).collect();
+            pairs.sort_by(|a, b| bip69_input_order(&a.0, &b.0));
+            let (vin, witnesses) = pairs.into_iter().unzip();
+            self.vin = vin;
+            self.witnesses = witnesses;
+        } else {
+            self.vin.sort_by(bip69_input_order);
+        }
+        self.vout.sort_by(|a, b| {
+            a.value
+                .cmp(&b.value)
+                .then_with(|| a.script_pubkey.items().cmp(b.script_pubkey.items()))
+        });
+    }
+
+    /// Set whether to sort inputs and outputs as specified in BIP69 when the transaction is
+    /// built. This includes the inputs and change output added by `build_funded`
+    pub fn set_bip69(mut self, bip69: bool) -> Self {
+        self.bip69 = bip69;
+        self
+    }
+
+    fn finalize(&mut self) {
+        self.apply_rbf();
+        if self.bip69 {
+            self.apply_bip69();
+        }
+    }
+
+    fn apply_rbf(&mut self) {
+        let relative_timelocks = self.version >= 2;
+        match self.rbf {
+            Some(true) => self
+                .vin
+                .iter_mut()
+                .filter(|input| input.sequence > RBF_SEQUENCE)
+                .for_each(|input| input.sequence = RBF_SEQUENCE),
+            Some(false) => self
+                .vin
+                .iter_mut()
+                .filter(|input| input.signals_rbf())
+                .filter(|input| {
+                    !relative_timelocks || input.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0
+                })
+                .for_each(|input| input.sequence = RBF_SEQUENCE + 1),
+            None => {}
+        }
+    }
+
     /// Consume self, produce a legacy tx. Discard any witness information in the builder
-    pub fn build_legacy(self) -> Result<LegacyTx, <LegacyTx as Transaction>::TxError> {
+    pub fn build_legacy(mut self) -> Result<LegacyTx, <LegacyTx as Transaction>::TxError> {
+        self.check_silent_payments()?;
+        self.finalize();
         LegacyTx::new(self.version, self.vin, self.vout, self.locktime)
     }
 
     /// Consume self, produce a witness tx
-    pub fn build_witness(self) -> Result<WitnessTx, <WitnessTx as Transaction>::TxError> {
+    pub fn build_witness(mut self) -> Result<WitnessTx, <WitnessTx as Transaction>::TxError> {
+        self.check_silent_payments()?;
+        self.finalize();
         <WitnessTx as WitnessTransaction>::new(
             self.version,
             self.vin,
@@ -99,11 +290,147 @@ where
     }
 
     /// Add an output paying `value` to `script_pubkey`
-    pub fn pay_script_pubkey(mut self, value: u64, script_pubkey: ScriptPubkey) -> Self {
+    pub fn pay_script_pubkey(mut self, value: Amount, script_pubkey: ScriptPubkey) -> Self {
         let output = TxOut::new(value, script_pubkey);
         self.vout.push(output);
         self
     }
+
+    /// Pay `value` to a silent payment address. The output is added by
+    /// `derive_silent_payments`
+    pub fn pay_silent_payment(mut self, value: Amount, recipient: SilentPaymentAddress) -> Self {
+        self.silent_payments.push((value, recipient));
+        self
+    }
+
+    /// Add an output for each `(value, address)` pair, in order. Silent payments are queued for
+    /// `derive_silent_payments`. If any address can not be decoded, no outputs are added, and the
+    /// error reports the index and address of the first bad recipient.
+    pub fn pay_many<I>(mut self, payments: I) -> BuilderResult<Self>
+    where
+        I: IntoIterator<Item = (Amount, Address)>,
+    {
+        let mut outputs = vec![];
+        let mut silent_payments = vec![];
+        for (index, (value, address)) in payments.into_iter().enumerate() {
+            let result = match address {
+                Address::Sp(_) => T::decode_silent_payment_address(&address)
+                    .map(|recipient| silent_payments.push((value, recipient))),
+                _ => T::try_decode_address(&address)
+                    .map(|script_pubkey| outputs.push(TxOut::new(value, script_pubkey))),
+            };
+            if let Err(source) = result {
+                return Err(BuilderError::BadRecipient {
+                    index,
+                    address,
+                    source,
+                });
+            }
+        }
+        self.vout.extend(outputs);
+        self.silent_payments.extend(silent_payments);
+        Ok(self)
+    }
+
+    /// Derive the outputs of silent payments, and add them to the builder. `keys` are the private
+    /// keys of the builder's eligible inputs. See `silent_payments::derive_outputs`.
+    ///
+    /// Call this after all inputs are added. The outputs commit to the inputs, so changing the
+    /// inputs afterwards makes the payments unrecoverable by their recipients.
+    pub fn derive_silent_payments(mut self, keys: &[InputKey]) -> BuilderResult<Self> {
+        if self.silent_payments.is_empty() {
+            return Ok(self);
+        }
+        let outpoints: Vec<_> = self.vin.iter().map(|input| input.outpoint).collect();
+        let recipients: Vec<_> = self
+            .silent_payments
+            .iter()
+            .map(|(_, recipient)| *recipient)
+            .collect();
+        let output_keys = silent_payments::derive_outputs(&outpoints, keys, &recipients)?;
+        for ((value, _), output_key) in self.silent_payments.drain(..).zip(output_keys.iter()) {
+            self.vout
+                .push(TxOut::new(value, ScriptPubkey::p2tr(output_key)));
+        }
+        Ok(self)
+    }
+
+    /// Error if silent payments have not been derived
+    fn check_silent_payments(&self) -> Result<(), TxError> {
+        if self.silent_payments.is_empty() {
+            Ok(())
+        } else {
+            Err(TxError::PendingSilentPayments)
+        }
+    }
+
+    /// Set the feerate used by `build_funded`
+    pub fn fee_rate(mut self, fee_rate: FeeRate) -> Self {
+        self.fee_rate = Some(fee_rate);
+        self
+    }
+
+    /// Set the address that receives change from `build_funded`
+    pub fn change_address(self, address: &Address) -> Self {
+        let script_pubkey = T::decode_address(address);
+        self.change_script_pubkey(script_pubkey)
+    }
+
+    /// Set the script pubkey that receives change from `build_funded`
+    pub fn change_script_pubkey(mut self, script_pubkey: ScriptPubkey) -> Self {
+        self.change = Some(script_pubkey);
+        self
+    }
+
+    /// Consume self, and produce a transaction paying the builder's outputs with inputs selected
+    /// from `utxos` by `coinselect::select_coins`. The remainder, less the fee at the builder's
+    /// feerate, is paid to the change address. If the remainder is too small to be worth a change
+    /// output, it is added to the fee instead.
+    ///
+    /// Errors if the feerate or change address are not set, if the builder already has inputs,
+    /// or if the UTXOs can not pay for the outputs and fee. Silent payments are not supported, as
+    /// their outputs depend on the selected inputs.
+    pub fn build_funded(mut self, utxos: &[Utxo]) -> BuilderResult<BitcoinTx> {
+        self.check_silent_payments()?;
+        let fee_rate = self.fee_rate.ok_or(BuilderError::MissingFeeRate)?;
+        let change = self
+            .change
+            .clone()
+            .ok_or(BuilderError::MissingChangeAddress)?;
+        if !self.vin.is_empty() {
+            return Err(BuilderError::InputsAlreadySet);
+        }
+
+        let candidates = utxos
+            .iter()
+            .cloned()
+            .map(Candidate::new)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let change_weight = TxOut::new(Amount::ZERO, change.clone()).serialized_length() * 4;
+        let change_utxo = Utxo::new(
+            Default::default(),
+            Amount::ZERO,
+            change.clone(),
+            SpendScript::None,
+        );
+        let mut params = SelectionParams::for_outputs(&self.vout, fee_rate)?;
+        if let Some(spend_weight) = coinselect::input_weight(&change_utxo) {
+            params = params.change_weights(change_weight, spend_weight);
+        }
+
+        let seed = RandomState::new().build_hasher().finish();
+        let selection = coinselect::select_coins(&candidates, &params, seed)?;
+
+        for utxo in selection.selected.iter() {
+            self.produce_witness |= spends_witness(utxo);
+            self = self.spend(utxo.outpoint, FUNDED_INPUT_SEQUENCE);
+        }
+        if let Some(value) = selection.change {
+            self = self.pay_script_pubkey(value, change);
+        }
+        Ok(self.build()?)
+    }
 }
 
 impl<T> TxBuilder for BitcoinTxBuilder<T>
@@ -121,6 +448,11 @@ where
             locktime: 0,
             witnesses: vec![],
             produce_witness: false,
+            fee_rate: None,
+            change: None,
+            rbf: None,
+            bip69: false,
+            silent_payments: vec![],
             encoder: PhantomData,
         }
     }
@@ -133,6 +465,11 @@ where
             locktime: tx.locktime(),
             witnesses: tx.witnesses().to_vec(),
             produce_witness: tx.is_witness(),
+            fee_rate: None,
+            change: None,
+            rbf: None,
+            bip69: false,
+            silent_payments: vec![],
             encoder: PhantomData,
         }
     }
@@ -145,6 +482,11 @@ where
             locktime: tx.locktime(),
             witnesses: tx.witnesses().to_vec(),
             produce_witness: tx.is_witness(),
+            fee_rate: None,
+            change: None,
+            rbf: None,
+            bip69: false,
+            silent_payments: vec![],
             encoder: PhantomData,
         }
     }
@@ -166,7 +508,13 @@ where
         self
     }
 
-    fn pay(self, value: u64, address: &Address) -> Self {
+    /// Panics if the address is invalid. Silent payments are queued for
+    /// `derive_silent_payments`
+    fn pay(self, value: Amount, address: &Address) -> Self {
+        if let Address::Sp(_) = address {
+            let recipient = T::decode_silent_payment_address(address).unwrap();
+            return self.pay_silent_payment(value, recipient);
+        }
         let script_pubkey = T::decode_address(address);
         self.pay_script_pubkey(value, script_pubkey)
     }
@@ -212,7 +560,9 @@ where
         self
     }
 
-    fn build(self) -> Result<Self::Transaction, <Self::Transaction as Transaction>::TxError> {
+    fn build(mut self) -> Result<Self::Transaction, <Self::Transaction as Transaction>::TxError> {
+        self.check_silent_payments()?;
+        self.finalize();
         if self.produce_witness || !self.witnesses.is_empty() {
             Ok(<WitnessTx as WitnessTransaction>::new(
                 self.version,
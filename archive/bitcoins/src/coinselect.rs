@@ -0,0 +1,802 @@
+//! Coin selection.
+//!
+//! Selects UTXOs to fund a set of payments at a feerate. Each candidate is valued at its
+//! effective value: its value less the fee to spend it. Three strategies are provided, and
+//! `select_coins` runs them all and keeps the result with the lowest waste, as Bitcoin Core does:
+//!
+//! - `branch_and_bound` searches for an input set that needs no change output
+//! - `knapsack` approximates the smallest input set that covers the payments and a change output
+//! - `single_random_draw` adds random inputs until the payments and a change output are covered
+//!
+//! The randomized strategies take a seed. Callers should pass a random seed, as deterministic
+//! selection can leak wallet information.
+
+use thiserror::Error;
+
+use crate::types::{
+    amount::Amount,
+    script::ScriptType,
+    txin::BitcoinOutpoint,
+    txout::TxOut,
+    utxo::{SpendScript, Utxo},
+    FeeRate,
+};
+use coins_core::ser::{prefix_byte_len, ByteFormat};
+
+/// The weight of a txin without its script sig: outpoint and sequence
+const TXIN_BASE_WEIGHT: usize = (36 + 4) * 4;
+/// A DER signature with its sighash flag, assuming low-R grinding
+const ECDSA_SIG_LEN: usize = 72;
+/// A compressed public key
+const PUBKEY_LEN: usize = 33;
+/// A BIP340 signature with the default sighash
+const SCHNORR_SIG_LEN: usize = 64;
+/// The number of iterations of the branch and bound search
+const BNB_TOTAL_TRIES: usize = 100_000;
+/// The number of iterations of the knapsack approximation
+const KNAPSACK_ITERATIONS: usize = 1000;
+
+/// Errors in coin selection
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CoinSelectError {
+    /// The candidates are not worth enough to fund the payments at the feerate
+    #[error("Insufficient funds. Have {available} sat of effective value. Need {needed} sat")]
+    InsufficientFunds {
+        /// The total effective value of the candidates
+        available: u64,
+        /// The value of the payments plus the fee for the tx without inputs
+        needed: u64,
+    },
+
+    /// The strategy found no solution. E.g. branch and bound found no changeless input set
+    #[error("No solution found")]
+    NoSolution,
+
+    /// The weight of spending a UTXO can not be estimated. Its spend script is missing or it is
+    /// not a standard type. Use `Candidate::with_weight` to provide it
+    #[error("Unknown input weight for {0:?}")]
+    UnknownInputWeight(BitcoinOutpoint),
+
+    /// A UTXO or payment value, or the total of the candidates or of the payments, is above
+    /// `Amount::MAX_MONEY`
+    #[error("Amount above the 21 million BTC limit")]
+    InvalidAmount,
+}
+
+/// Type alias for result with CoinSelectError
+pub type CoinSelectResult<T> = Result<T, CoinSelectError>;
+
+fn push_len(len: usize) -> usize {
+    match len {
+        0..=75 => 1,
+        76..=255 => 2,
+        _ => 3,
+    }
+}
+
+fn txin_weight(script_sig_len: usize, witness: &[usize]) -> usize {
+    let mut weight =
+        TXIN_BASE_WEIGHT + (prefix_byte_len(script_sig_len as u64) as usize + script_sig_len) * 4;
+    if !witness.is_empty() {
+        weight += prefix_byte_len(witness.len() as u64) as usize;
+        weight += witness
+            .iter()
+            .map(|len| prefix_byte_len(*len as u64) as usize + len)
+            .sum::<usize>();
+    }
+    weight
+}
+
+/// Estimate the weight of a txin spending a UTXO, including its signatures. Supports P2PKH,
+/// P2WPKH, P2SH-P2WPKH, P2TR key spends, and multisig redeem or witness scripts. Returns `None`
+/// for other types, or if the UTXO's spend script is missing.
+pub fn input_weight(utxo: &Utxo) -> Option<usize> {
+    let spend_script = match utxo.spend_script() {
+        SpendScript::Known(script) => Some(script),
+        _ => None,
+    };
+    match utxo.standard_type() {
+        ScriptType::Pkh(_) => Some(txin_weight(1 + ECDSA_SIG_LEN + 1 + PUBKEY_LEN, &[])),
+        ScriptType::Wpkh(_) => Some(txin_weight(0, &[ECDSA_SIG_LEN, PUBKEY_LEN])),
+        ScriptType::Tr(_) => Some(txin_weight(0, &[SCHNORR_SIG_LEN])),
+        ScriptType::Sh(_) => {
+            let script = spend_script?;
+            match script.standard_type() {
+                ScriptType::Wpkh(_) => Some(txin_weight(23, &[ECDSA_SIG_LEN, PUBKEY_LEN])),
+                ScriptType::Multisig { m, .. } => {
+                    let script_sig = 1
+                        + m as usize * (1 + ECDSA_SIG_LEN)
+                        + push_len(script.len())
+                        + script.len();
+                    Some(txin_weight(script_sig, &[]))
+                }
+                _ => None,
+            }
+        }
+        ScriptType::Wsh(_) => {
+            let script = spend_script?;
+            let m = match script.standard_type() {
+                ScriptType::Multisig { m, .. } => m as usize,
+                _ => return None,
+            };
+            let mut witness = vec![0];
+            witness.extend(std::iter::repeat_n(ECDSA_SIG_LEN, m));
+            witness.push(script.len());
+            Some(txin_weight(0, &witness))
+        }
+        _ => None,
+    }
+}
+
+/// A UTXO that may be selected, and the weight of the txin spending it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /// The UTXO
+    pub utxo: Utxo,
+    /// The weight of the txin spending the UTXO, including signatures
+    pub weight: usize,
+}
+
+impl Candidate {
+    /// Instantiate a candidate, estimating its weight with `input_weight`. Errors if the UTXO's
+    /// value is above `MAX_MONEY`
+    pub fn new(utxo: Utxo) -> CoinSelectResult<Self> {
+        if !utxo.value.is_valid_money() {
+            return Err(CoinSelectError::InvalidAmount);
+        }
+        let weight =
+            input_weight(&utxo).ok_or(CoinSelectError::UnknownInputWeight(utxo.outpoint))?;
+        Ok(Self { utxo, weight })
+    }
+
+    /// Instantiate a candidate with a known txin weight
+    pub fn with_weight(utxo: Utxo, weight: usize) -> Self {
+        Self { utxo, weight }
+    }
+
+    /// The value of the UTXO less the fee to spend it at `fee_rate`. May be negative
+    pub fn effective_value(&self, fee_rate: FeeRate) -> i64 {
+        self.utxo.value.to_sat() as i64 - fee_rate.fee_for_weight(self.weight) as i64
+    }
+}
+
+/// The payments to fund, and the costs of the transaction
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectionParams {
+    target: Amount,
+    fee_rate: FeeRate,
+    long_term_fee_rate: FeeRate,
+    base_weight: usize,
+    change_weight: usize,
+    change_spend_weight: usize,
+    dust_limit: Amount,
+}
+
+impl SelectionParams {
+    /// Fund `target` satoshis at `fee_rate`. `base_weight` is the weight of the transaction
+    /// without inputs or change. Change defaults to a P2WPKH output
+    pub fn new(target: Amount, fee_rate: FeeRate, base_weight: usize) -> Self {
+        Self {
+            target,
+            fee_rate,
+            long_term_fee_rate: fee_rate,
+            base_weight,
+            change_weight: 31 * 4,
+            change_spend_weight: txin_weight(0, &[ECDSA_SIG_LEN, PUBKEY_LEN]),
+            dust_limit: Amount::from_sat(294),
+        }
+    }
+
+    /// Fund a set of outputs at `fee_rate`. The base weight includes the version, locktime,
+    /// input and output counts, the outputs, and the segwit marker and flag. Errors if the
+    /// outputs pay more than `MAX_MONEY`
+    pub fn for_outputs(outputs: &[TxOut], fee_rate: FeeRate) -> CoinSelectResult<Self> {
+        let target = Amount::checked_sum(outputs.iter().map(|o| o.value))
+            .ok_or(CoinSelectError::InvalidAmount)?;
+        let mut base = 4 + 4 + 1; // version, locktime, input count
+        base += prefix_byte_len(outputs.len() as u64 + 1) as usize;
+        base += outputs
+            .iter()
+            .map(ByteFormat::serialized_length)
+            .sum::<usize>();
+        Ok(Self::new(target, fee_rate, base * 4 + 2))
+    }
+
+    /// Set the feerate expected when the change output will be spent. Spending more inputs now
+    /// is cheaper when it is above the current feerate. Defaults to the current feerate
+    pub fn long_term_fee_rate(mut self, fee_rate: FeeRate) -> Self {
+        self.long_term_fee_rate = fee_rate;
+        self
+    }
+
+    /// Set the weights of the change output, and of the txin that will later spend it
+    pub fn change_weights(mut self, output_weight: usize, spend_weight: usize) -> Self {
+        self.change_weight = output_weight;
+        self.change_spend_weight = spend_weight;
+        self
+    }
+
+    /// Set the smallest change output to create. Smaller change is added to the fee
+    pub fn dust_limit(mut self, dust_limit: Amount) -> Self {
+        self.dust_limit = dust_limit;
+        self
+    }
+
+    /// The total value of the payments
+    pub fn target(&self) -> Amount {
+        self.target
+    }
+
+    /// The feerate
+    pub fn fee_rate(&self) -> FeeRate {
+        self.fee_rate
+    }
+
+    /// The value the effective values of the inputs must reach: the payments, and the fee for
+    /// the transaction without inputs or change
+    fn target_effective(&self) -> i64 {
+        (self.target.to_sat() + self.fee_rate.fee_for_weight(self.base_weight)) as i64
+    }
+
+    /// The cost of creating a change output now, and spending it later
+    fn cost_of_change(&self) -> i64 {
+        (self.fee_rate.fee_for_weight(self.change_weight)
+            + self
+                .long_term_fee_rate
+                .fee_for_weight(self.change_spend_weight)) as i64
+    }
+
+    /// The smallest excess that can fund a change output
+    fn min_change(&self) -> i64 {
+        self.cost_of_change() + self.dust_limit.to_sat() as i64
+    }
+
+    /// The extra fee paid by spending an input now rather than at the long-term feerate
+    fn input_waste(&self, candidate: &Candidate) -> i64 {
+        self.fee_rate.fee_for_weight(candidate.weight) as i64
+            - self.long_term_fee_rate.fee_for_weight(candidate.weight) as i64
+    }
+}
+
+/// The result of coin selection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    /// The selected UTXOs
+    pub selected: Vec<Utxo>,
+    /// The value of the change output, if one should be created
+    pub change: Option<Amount>,
+    /// The fee paid by the transaction
+    pub fee: u64,
+    /// The waste metric: the cost of spending the inputs now rather than at the long-term
+    /// feerate, plus the cost of change, or the excess given to fees when there is none
+    pub waste: i64,
+}
+
+impl Selection {
+    fn new(
+        candidates: &[Candidate],
+        params: &SelectionParams,
+        selected: &[usize],
+        allow_change: bool,
+    ) -> CoinSelectResult<Self> {
+        let total = Amount::checked_sum(selected.iter().map(|i| candidates[*i].utxo.value))
+            .ok_or(CoinSelectError::InvalidAmount)?
+            .to_sat();
+        let target = params.target.to_sat();
+        let weight: usize = params.base_weight
+            + selected
+                .iter()
+                .map(|i| candidates[*i].weight)
+                .sum::<usize>();
+        let input_waste: i64 = selected
+            .iter()
+            .map(|i| params.input_waste(&candidates[*i]))
+            .sum();
+
+        let fee = params.fee_rate.fee_for_weight(weight);
+        if total < target + fee {
+            return Err(CoinSelectError::NoSolution);
+        }
+
+        let change_fee = params
+            .fee_rate
+            .fee_for_weight(weight + params.change_weight);
+        let change = total.checked_sub(target + change_fee);
+        let selected = selected
+            .iter()
+            .map(|i| candidates[*i].utxo.clone())
+            .collect();
+        match change {
+            Some(change) if allow_change && change >= params.dust_limit.to_sat() => Ok(Self {
+                selected,
+                change: Some(Amount::from_sat(change)),
+                fee: change_fee,
+                waste: input_waste + params.cost_of_change(),
+            }),
+            _ => {
+                let excess = total - target - fee;
+                Ok(Self {
+                    selected,
+                    change: None,
+                    fee: fee + excess,
+                    waste: input_waste + excess as i64,
+                })
+            }
+        }
+    }
+}
+
+/// A xorshift64* generator. Only used to randomize selection, not for anything secret
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        })
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn bool(&mut self) -> bool {
+        self.next() >> 63 == 1
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// The indices and effective values of candidates worth spending
+fn positive_candidates(candidates: &[Candidate], params: &SelectionParams) -> Vec<(usize, i64)> {
+    candidates
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, c.effective_value(params.fee_rate)))
+        .filter(|(_, value)| *value > 0)
+        .collect()
+}
+
+fn check_funds(pool: &[(usize, i64)], params: &SelectionParams) -> CoinSelectResult<()> {
+    let available: i64 = pool.iter().map(|(_, value)| value).sum();
+    if available < params.target_effective() {
+        return Err(CoinSelectError::InsufficientFunds {
+            available: available as u64,
+            needed: params.target_effective() as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Search for an input set whose effective value covers the target, without exceeding it by
+/// more than the cost of change. The result has no change output. Among solutions, the one with
+/// the lowest waste is chosen.
+pub fn branch_and_bound(
+    candidates: &[Candidate],
+    params: &SelectionParams,
+) -> CoinSelectResult<Selection> {
+    let mut pool = positive_candidates(candidates, params);
+    check_funds(&pool, params)?;
+    // largest first
+    pool.sort_by_key(|(_, value)| std::cmp::Reverse(*value));
+
+    let target = params.target_effective();
+    let upper = target + params.cost_of_change();
+    let wastes: Vec<i64> = pool
+        .iter()
+        .map(|(i, _)| params.input_waste(&candidates[*i]))
+        .collect();
+    let feerate_high = params.fee_rate > params.long_term_fee_rate;
+
+    let mut available: i64 = pool.iter().map(|(_, value)| value).sum();
+    let mut value = 0i64;
+    let mut waste = 0i64;
+    let mut selection: Vec<usize> = vec![];
+    let mut best: Option<Vec<usize>> = None;
+    let mut best_waste = i64::MAX;
+
+    let mut idx = 0;
+    for _ in 0..BNB_TOTAL_TRIES {
+        let mut backtrack = false;
+        if value + available < target || value > upper || (feerate_high && waste > best_waste) {
+            backtrack = true;
+        } else if value >= target {
+            let total_waste = waste + (value - target);
+            if total_waste <= best_waste {
+                best = Some(selection.clone());
+                best_waste = total_waste;
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let last = match selection.pop() {
+                Some(last) => last,
+                None => break,
+            };
+            // return the skipped candidates to the lookahead, then exclude the last one
+            idx -= 1;
+            while idx > last {
+                available += pool[idx].1;
+                idx -= 1;
+            }
+            value -= pool[idx].1;
+            waste -= wastes[idx];
+        } else {
+            available -= pool[idx].1;
+            // skip candidates equivalent to an excluded predecessor
+            let equivalent = idx > 0
+                && selection.last() != Some(&(idx - 1))
+                && pool[idx].1 == pool[idx - 1].1
+                && wastes[idx] == wastes[idx - 1];
+            if !equivalent {
+                selection.push(idx);
+                value += pool[idx].1;
+                waste += wastes[idx];
+            }
+        }
+        idx += 1;
+    }
+
+    let best: Vec<usize> = best
+        .ok_or(CoinSelectError::NoSolution)?
+        .into_iter()
+        .map(|i| pool[i].0)
+        .collect();
+    Selection::new(candidates, params, &best, false)
+}
+
+/// Randomly approximate the subset of `values` with the smallest sum that reaches `target`
+fn approximate_best_subset(
+    values: &[i64],
+    total: i64,
+    target: i64,
+    rng: &mut Rng,
+) -> (Vec<bool>, i64) {
+    let mut best = vec![true; values.len()];
+    let mut best_value = total;
+    let mut included = vec![false; values.len()];
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        included.iter_mut().for_each(|i| *i = false);
+        let mut sum = 0;
+        let mut reached = false;
+        for pass in 0..2 {
+            if reached {
+                break;
+            }
+            for (i, value) in values.iter().enumerate() {
+                let include = if pass == 0 { rng.bool() } else { !included[i] };
+                if !include {
+                    continue;
+                }
+                sum += value;
+                included[i] = true;
+                if sum >= target {
+                    reached = true;
+                    if sum < best_value {
+                        best_value = sum;
+                        best.copy_from_slice(&included);
+                    }
+                    sum -= value;
+                    included[i] = false;
+                }
+            }
+        }
+    }
+    (best, best_value)
+}
+
+/// Approximate the smallest input set that covers the target and a change output, falling back
+/// to the smallest single candidate that covers both.
+pub fn knapsack(
+    candidates: &[Candidate],
+    params: &SelectionParams,
+    seed: u64,
+) -> CoinSelectResult<Selection> {
+    let mut rng = Rng::new(seed);
+    let mut pool = positive_candidates(candidates, params);
+    check_funds(&pool, params)?;
+    rng.shuffle(&mut pool);
+
+    let target = params.target_effective();
+    let min_change = params.min_change();
+    let mut applicable = vec![];
+    let mut lowest_larger: Option<(usize, i64)> = None;
+    for (i, value) in pool.iter().copied() {
+        if value == target {
+            return Selection::new(candidates, params, &[i], true);
+        } else if value < target + min_change {
+            applicable.push((i, value));
+        } else if lowest_larger.is_none_or(|(_, larger)| value < larger) {
+            lowest_larger = Some((i, value));
+        }
+    }
+
+    let total_lower: i64 = applicable.iter().map(|(_, value)| value).sum();
+    let all: Vec<usize> = applicable.iter().map(|(i, _)| *i).collect();
+    if total_lower == target {
+        return Selection::new(candidates, params, &all, true);
+    }
+    if total_lower < target {
+        return match lowest_larger {
+            Some((i, _)) => Selection::new(candidates, params, &[i], true),
+            None => Err(CoinSelectError::NoSolution),
+        };
+    }
+
+    applicable.sort_by_key(|(_, value)| std::cmp::Reverse(*value));
+    let values: Vec<i64> = applicable.iter().map(|(_, value)| *value).collect();
+    let (mut best, mut best_value) =
+        approximate_best_subset(&values, total_lower, target, &mut rng);
+    if best_value != target && total_lower >= target + min_change {
+        let (subset, value) =
+            approximate_best_subset(&values, total_lower, target + min_change, &mut rng);
+        best = subset;
+        best_value = value;
+    }
+
+    if let Some((i, larger)) = lowest_larger {
+        if (best_value != target && best_value < target + min_change) || larger <= best_value {
+            return Selection::new(candidates, params, &[i], true);
+        }
+    }
+    let selected: Vec<usize> = applicable
+        .iter()
+        .zip(best.iter())
+        .filter(|(_, included)| **included)
+        .map(|((i, _), _)| *i)
+        .collect();
+    Selection::new(candidates, params, &selected, true)
+}
+
+/// Add randomly chosen candidates until the target and a change output are covered.
+pub fn single_random_draw(
+    candidates: &[Candidate],
+    params: &SelectionParams,
+    seed: u64,
+) -> CoinSelectResult<Selection> {
+    let mut rng = Rng::new(seed);
+    let mut pool = positive_candidates(candidates, params);
+    check_funds(&pool, params)?;
+    rng.shuffle(&mut pool);
+
+    let target = params.target_effective() + params.min_change();
+    let mut value = 0;
+    let mut selected = vec![];
+    for (i, ev) in pool {
+        selected.push(i);
+        value += ev;
+        if value >= target {
+            return Selection::new(candidates, params, &selected, true);
+        }
+    }
+    Err(CoinSelectError::NoSolution)
+}
+
+/// Run each strategy, and return the selection with the lowest waste. Branch and bound wins ties,
+/// as it avoids a change output.
+pub fn select_coins(
+    candidates: &[Candidate],
+    params: &SelectionParams,
+    seed: u64,
+) -> CoinSelectResult<Selection> {
+    let total = Amount::checked_sum(candidates.iter().map(|c| c.utxo.value));
+    if total.is_none() || !params.target.is_valid_money() {
+        return Err(CoinSelectError::InvalidAmount);
+    }
+
+    let results = vec![
+        branch_and_bound(candidates, params),
+        knapsack(candidates, params, seed),
+        single_random_draw(candidates, params, seed),
+    ];
+
+    let mut best: Option<Selection> = None;
+    let mut err = CoinSelectError::NoSolution;
+    for result in results.into_iter() {
+        match result {
+            Ok(selection) => {
+                if best.as_ref().is_none_or(|b| selection.waste < b.waste) {
+                    best = Some(selection);
+                }
+            }
+            Err(e @ CoinSelectError::InsufficientFunds { .. }) => err = e,
+            Err(_) => {}
+        }
+    }
+    best.ok_or(err)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::script::{Script, ScriptPubkey};
+
+    fn p2wpkh(value: u64, idx: u32) -> Candidate {
+        let utxo = Utxo::new(
+            BitcoinOutpoint::new(Default::default(), idx),
+            Amount::from_sat(value),
+            ScriptPubkey::new([&[0x00, 0x14][..], &[idx as u8; 20]].concat()),
+            SpendScript::None,
+        );
+        Candidate::new(utxo).unwrap()
+    }
+
+    fn params(target: u64) -> SelectionParams {
+        SelectionParams::new(Amount::from_sat(target), FeeRate(10.0), 200)
+    }
+
+    #[test]
+    fn it_estimates_input_weights() {
+        assert_eq!(p2wpkh(1000, 0).weight, 272);
+
+        let pkh = ScriptPubkey::new(
+            hex::decode("76a9140e5c3c8d420c7f11e88d76f7b860d471e6517a4488ac").unwrap(),
+        );
+        let utxo = Utxo::new(
+            Default::default(),
+            Amount::from_sat(1000),
+            pkh,
+            SpendScript::None,
+        );
+        assert_eq!(input_weight(&utxo), Some(592));
+
+        let tr = ScriptPubkey::p2tr(&[1; 32]);
+        let utxo = Utxo::new(
+            Default::default(),
+            Amount::from_sat(1000),
+            tr,
+            SpendScript::None,
+        );
+        assert_eq!(input_weight(&utxo), Some(230));
+
+        // 2-of-3 multisig
+        let script = Script::new(hex::decode("52210375e00eb72e29da82b89367947f29ef34afb75e8654f6ea368e0acdfd92976b7c2103a1b26313f430c4b15bb1fdce663207659d8cac749a0e53d70eff01874496feff2103c96d495bfdd5ba4145e3e046fee45e84a8a48ad05bd8dbb395c011a32cf9f88053ae").unwrap());
+        let mut utxo = Utxo::new(
+            Default::default(),
+            Amount::from_sat(1000),
+            ScriptPubkey::p2wsh(&script),
+            SpendScript::Missing,
+        );
+        assert_eq!(input_weight(&utxo), None);
+        assert!(Candidate::new(utxo.clone()).is_err());
+        assert!(utxo.set_spend_script(script.clone()));
+        // 164 + count + dummy + 2 sigs + script
+        assert_eq!(input_weight(&utxo), Some(164 + 1 + 1 + 2 * 73 + 1 + 105));
+
+        let mut utxo = Utxo::new(
+            Default::default(),
+            Amount::from_sat(1000),
+            ScriptPubkey::p2sh(&script),
+            SpendScript::Missing,
+        );
+        assert!(utxo.set_spend_script(script));
+        assert_eq!(
+            input_weight(&utxo),
+            Some((40 + 3 + 1 + 2 * 73 + 2 + 105) * 4)
+        );
+    }
+
+    #[test]
+    fn it_finds_changeless_solutions() {
+        // at 10 sat/vB a p2wpkh input costs 680 sat, and the base costs 500 sat
+        let candidates: Vec<Candidate> = [5_000, 30_000, 12_000, 20_680, 8_000]
+            .iter()
+            .enumerate()
+            .map(|(i, v)| p2wpkh(*v, i as u32))
+            .collect();
+
+        let selection = branch_and_bound(&candidates, &params(19_500)).unwrap();
+        assert_eq!(selection.selected, vec![candidates[3].utxo.clone()]);
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.fee, 1_180);
+        assert_eq!(selection.waste, 0);
+
+        let selection = select_coins(&candidates, &params(19_500), 7).unwrap();
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.waste, 0);
+
+        // no exact match
+        assert_eq!(
+            branch_and_bound(&candidates[..1], &params(1_000)),
+            Err(CoinSelectError::NoSolution)
+        );
+    }
+
+    #[test]
+    fn it_selects_with_change() {
+        let candidates: Vec<Candidate> = [5_000, 30_000, 12_000, 20_680, 8_000]
+            .iter()
+            .enumerate()
+            .map(|(i, v)| p2wpkh(*v, i as u32))
+            .collect();
+        let total: Amount = candidates.iter().map(|c| c.utxo.value).sum();
+
+        for seed in 0..20 {
+            for selection in [
+                knapsack(&candidates, &params(40_000), seed).unwrap(),
+                single_random_draw(&candidates, &params(40_000), seed).unwrap(),
+                select_coins(&candidates, &params(40_000), seed).unwrap(),
+            ]
+            .iter()
+            {
+                let input_value: Amount = selection.selected.iter().map(|u| u.value).sum();
+                let change = selection.change.unwrap_or_default();
+                assert_eq!(
+                    input_value.to_sat(),
+                    40_000 + change.to_sat() + selection.fee
+                );
+                assert!(input_value <= total);
+                if selection.change.is_some() {
+                    // base, inputs, and change output, rounded up
+                    let weight = 200 + 124 + 272 * selection.selected.len();
+                    assert_eq!(selection.fee, FeeRate(10.0).fee_for_weight(weight));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_rejects_insufficient_funds() {
+        let candidates = vec![p2wpkh(5_000, 0), p2wpkh(500, 1)];
+        let err = select_coins(&candidates, &params(10_000), 0).unwrap_err();
+        assert_eq!(
+            err,
+            CoinSelectError::InsufficientFunds {
+                available: 4_320,
+                needed: 10_500,
+            }
+        );
+        assert_eq!(candidates[1].effective_value(FeeRate(10.0)), -180);
+    }
+
+    #[test]
+    fn it_sizes_params_for_outputs() {
+        let outputs = vec![TxOut::new(
+            Amount::from_sat(1000),
+            ScriptPubkey::p2tr(&[1; 32]),
+        )];
+        let params = SelectionParams::for_outputs(&outputs, FeeRate(1.0)).unwrap();
+        assert_eq!(params.target(), Amount::from_sat(1000));
+        // version, locktime, counts, and one 43-byte output
+        assert_eq!(params.base_weight, (4 + 4 + 1 + 1 + 43) * 4 + 2);
+    }
+
+    #[test]
+    fn it_rejects_invalid_amounts() {
+        let mut utxo = p2wpkh(5_000, 0).utxo;
+        utxo.value = Amount::from_sat(u64::MAX);
+        assert_eq!(
+            Candidate::new(utxo.clone()).unwrap_err(),
+            CoinSelectError::InvalidAmount
+        );
+
+        let huge = Candidate::with_weight(utxo, 272);
+        let err = select_coins(&[huge, p2wpkh(5_000, 1)], &params(1_000), 0).unwrap_err();
+        assert_eq!(err, CoinSelectError::InvalidAmount);
+
+        let half = Amount::from_sat(Amount::MAX_MONEY.to_sat() / 2 + 1);
+        let outputs = vec![
+            TxOut::new(half, ScriptPubkey::p2tr(&[1; 32])),
+            TxOut::new(half, ScriptPubkey::p2tr(&[2; 32])),
+        ];
+        assert_eq!(
+            SelectionParams::for_outputs(&outputs, FeeRate(1.0)).unwrap_err(),
+            CoinSelectError::InvalidAmount
+        );
+    }
+}
@@ -24,6 +24,14 @@ pub mod network {
     pub type Encoder = crate::enc::SignetEncoder;
 }
 
+#[cfg(feature = "regtest")]
+pub mod network {
+    /// The default network, selected by feature flag
+    pub type Net = crate::nets::BitcoinRegtest;
+    /// The default encoder, selected by feature flag
+    pub type Encoder = crate::enc::RegtestEncoder;
+}
+
 impl std::str::FromStr for crate::enc::Address {
     type Err = <network::Encoder as AddressEncoder>::Error;
 
@@ -0,0 +1,148 @@
+//! Output script descriptor checksums, as specified in BIP 380.
+//!
+//! A descriptor may be followed by `#` and an 8-character checksum. Bitcoin Core requires the
+//! checksum when importing descriptors.
+
+use thiserror::Error;
+
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATORS: [u64; 5] = [
+    0xf5_dee5_1989,
+    0xa9_fdca_3312,
+    0x1b_ab10_e32d,
+    0x37_06b1_677a,
+    0x64_4d62_6ffd,
+];
+
+/// Errors in descriptor checksums
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum DescriptorError {
+    /// The descriptor contains a character outside of the descriptor character set
+    #[error("Invalid character in descriptor: {0:?}")]
+    InvalidCharacter(char),
+
+    /// The descriptor has no `#checksum` suffix
+    #[error("Descriptor has no checksum")]
+    MissingChecksum,
+
+    /// The checksum does not match the descriptor
+    #[error("Bad descriptor checksum. Got {got}. Expected {expected}")]
+    BadChecksum {
+        /// The checksum in the descriptor
+        got: String,
+        /// The checksum of the descriptor body
+        expected: String,
+    },
+}
+
+fn polymod(c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7_ffff_ffff) << 5) ^ val;
+    for (i, gen) in GENERATORS.iter().enumerate() {
+        if (c0 >> i) & 1 == 1 {
+            c ^= gen;
+        }
+    }
+    c
+}
+
+/// Compute the checksum of a descriptor without a `#checksum` suffix
+pub fn descriptor_checksum(desc: &str) -> Result<String, DescriptorError> {
+    let mut c = 1u64;
+    let mut cls = 0u64;
+    let mut clscount = 0;
+    for ch in desc.chars() {
+        let pos = INPUT_CHARSET
+            .find(ch)
+            .ok_or(DescriptorError::InvalidCharacter(ch))? as u64;
+        // the low 5 bits of each character, then the high bits of each group of 3
+        c = polymod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        clscount += 1;
+        if clscount == 3 {
+            c = polymod(c, cls);
+            cls = 0;
+            clscount = 0;
+        }
+    }
+    if clscount > 0 {
+        c = polymod(c, cls);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+
+    Ok((0..8)
+        .map(|i| CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Append `#checksum` to a descriptor. A descriptor that already has a checksum is verified and
+/// returned unchanged
+pub fn add_checksum(desc: &str) -> Result<String, DescriptorError> {
+    if desc.contains('#') {
+        verify_checksum(desc)?;
+        return Ok(desc.to_owned());
+    }
+    Ok(format!("{}#{}", desc, descriptor_checksum(desc)?))
+}
+
+/// Verify the `#checksum` suffix of a descriptor, and return the descriptor without it
+pub fn verify_checksum(desc: &str) -> Result<&str, DescriptorError> {
+    let idx = desc.rfind('#').ok_or(DescriptorError::MissingChecksum)?;
+    let (body, got) = (&desc[..idx], &desc[idx + 1..]);
+    let expected = descriptor_checksum(body)?;
+    if got != expected {
+        return Err(DescriptorError::BadChecksum {
+            got: got.to_owned(),
+            expected,
+        });
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_computes_checksums() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(
+            add_checksum("raw(deadbeef)").unwrap(),
+            "raw(deadbeef)#89f8spxm"
+        );
+        assert_eq!(
+            add_checksum("raw(deadbeef)#89f8spxm").unwrap(),
+            "raw(deadbeef)#89f8spxm"
+        );
+        assert_eq!(
+            verify_checksum("raw(deadbeef)#89f8spxm").unwrap(),
+            "raw(deadbeef)"
+        );
+    }
+
+    #[test]
+    fn it_rejects_bad_checksums() {
+        assert_eq!(
+            verify_checksum("raw(deadbeef)"),
+            Err(DescriptorError::MissingChecksum)
+        );
+        assert_eq!(
+            verify_checksum("raw(deedbeef)#89f8spxm"),
+            Err(DescriptorError::BadChecksum {
+                got: "89f8spxm".to_owned(),
+                expected: descriptor_checksum("raw(deedbeef)").unwrap(),
+            })
+        );
+        assert!(verify_checksum("raw(deadbeef)#").is_err());
+        assert!(add_checksum("raw(deadbeef)#89f8spxn").is_err());
+        assert_eq!(
+            descriptor_checksum("raw(deadbeef)\n"),
+            Err(DescriptorError::InvalidCharacter('\n'))
+        );
+    }
+}
@@ -1,34 +1,169 @@
 //! Contains simplified access to `bech32` and `base58check` encoder/decoder for Bitcoin
 //! addresses.
+//!
+//! Witness version 0 programs are encoded with bech32, and later versions with bech32m, as
+//! specified in BIP350.
 
-use bech32::Error as BechError;
+use bech32::{u5, Error as BechError, FromBase32, ToBase32};
 use coins_core::enc::{
     decode_bech32 as core_decode_bech32, encode_bech32 as core_encode_bech32, EncodingError,
     EncodingResult,
 };
 
-/// Encode a byte vector to bech32. This function expects `v` to be a witness program, and will
-/// return an `UnknownScriptType` if it does not meet the witness program format.
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+// The bech32m checksum constant. Bech32 uses 1
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+fn polymod(values: &[u8]) -> u32 {
+    const GEN: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    let mut chk: u32 = 1;
+    for value in values.iter() {
+        let top = chk >> 25;
+        chk = ((chk & 0x01ff_ffff) << 5) ^ *value as u32;
+        for (i, gen) in GEN.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= gen;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut v: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    v.push(0);
+    v.extend(hrp.bytes().map(|b| b & 0x1f));
+    v
+}
+
+/// Encode a witness version and program to bech32m.
+pub fn encode_bech32m(hrp: &str, version: u8, program: &[u8]) -> EncodingResult<String> {
+    let mut data = vec![u5::try_from_u8(version)?.to_u8()];
+    data.extend(program.to_base32().iter().map(|b| b.to_u8()));
+
+    let mut values = hrp_expand(hrp);
+    values.extend(&data);
+    values.extend(&[0u8; 6]);
+    let chk = polymod(&values) ^ BECH32M_CONST;
+    data.extend((0..6).map(|i| ((chk >> (5 * (5 - i))) & 0x1f) as u8));
+
+    let mut s = hrp.to_owned();
+    s.push('1');
+    s.extend(data.iter().map(|b| CHARSET[*b as usize] as char));
+    Ok(s)
+}
+
+/// Decode a witness version and program from a bech32m string. Caller specifies an expected
+/// HRP. If a different HRP is found, returns `WrongHrp`.
+pub fn decode_bech32m(expected_hrp: &str, s: &str) -> EncodingResult<(u8, Vec<u8>)> {
+    decode_bech32m_with_max_len(expected_hrp, s, 90)
+}
+
+/// Decode a version and payload from a bech32m string of at most `max_len` characters. BIP173
+/// limits addresses to 90 characters, but other formats, such as silent payment addresses, may
+/// be longer.
+pub(crate) fn decode_bech32m_with_max_len(
+    expected_hrp: &str,
+    s: &str,
+    max_len: usize,
+) -> EncodingResult<(u8, Vec<u8>)> {
+    if s.len() > max_len {
+        return Err(BechError::InvalidLength.into());
+    }
+    if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(BechError::MixedCase.into());
+    }
+    let s = s.to_ascii_lowercase();
+
+    let sep = s.rfind('1').ok_or(BechError::MissingSeparator)?;
+    let (hrp, rest) = s.split_at(sep);
+    let rest = &rest[1..];
+    if hrp.is_empty() || rest.len() < 7 {
+        return Err(BechError::InvalidLength.into());
+    }
+    if hrp != expected_hrp {
+        return Err(EncodingError::WrongHrp {
+            got: hrp.to_owned(),
+            expected: expected_hrp.to_owned(),
+        });
+    }
+
+    let data = rest
+        .chars()
+        .map(|c| {
+            CHARSET
+                .iter()
+                .position(|b| *b as char == c)
+                .map(|p| p as u8)
+                .ok_or(BechError::InvalidChar(c))
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    let mut values = hrp_expand(hrp);
+    values.extend(&data);
+    if polymod(&values) != BECH32M_CONST {
+        return Err(BechError::InvalidChecksum.into());
+    }
+
+    let data = &data[..data.len() - 6];
+    let payload = data[1..]
+        .iter()
+        .map(|b| u5::try_from_u8(*b))
+        .collect::<Result<Vec<u5>, _>>()?;
+    Ok((data[0], Vec::from_base32(&payload)?))
+}
+
+/// Encode a byte vector to bech32, or bech32m for witness versions 1 and above. This function
+/// expects `v` to be a witness program, and will return an `UnknownScriptType` if it does not
+/// meet the witness program format.
 pub fn encode_bech32(hrp: &str, v: &[u8]) -> EncodingResult<String> {
-    if v.len() < 2 || v.len() > 40 {
+    if v.len() < 2 || v.len() > 42 {
         return Err(BechError::InvalidLength.into());
     }
 
     let (version_and_len, payload) = v.split_at(2);
-    if version_and_len[0] > 16 || version_and_len[1] as usize != payload.len() {
+    if version_and_len[1] as usize != payload.len() {
         return Err(EncodingError::UnknownScriptType);
     };
 
-    core_encode_bech32(hrp, version_and_len[0], payload)
+    // The version is pushed by OP_0, or by OP_1 to OP_16
+    match version_and_len[0] {
+        0 => core_encode_bech32(hrp, 0, payload),
+        op @ 0x51..=0x60 => encode_bech32m(hrp, op - 0x50, payload),
+        _ => Err(EncodingError::UnknownScriptType),
+    }
 }
 
-/// Decode a witness program from a bech32 string. Caller specifies an expected HRP. If a
-/// different HRP is found, returns `WrongHrp`.
+/// Decode a witness program from a bech32 or bech32m string. Caller specifies an expected HRP.
+/// If a different HRP is found, returns `WrongHrp`. Version 0 programs must use bech32, and
+/// later versions bech32m. Returns `UnknownScriptType` if the checksum does not match the
+/// version.
 pub fn decode_bech32(expected_hrp: &str, s: &str) -> EncodingResult<Vec<u8>> {
-    let (version, data) = core_decode_bech32(expected_hrp, s)?;
+    let (version, data) = match core_decode_bech32(expected_hrp, s) {
+        Ok((0, data)) => (0, data),
+        Ok(_) => return Err(EncodingError::UnknownScriptType),
+        Err(EncodingError::BechError(BechError::InvalidChecksum)) => {
+            match decode_bech32m(expected_hrp, s)? {
+                (0, _) => return Err(EncodingError::UnknownScriptType),
+                decoded => decoded,
+            }
+        }
+        Err(e) => return Err(e),
+    };
+    if version > 16 {
+        return Err(EncodingError::UnknownScriptType);
+    }
 
-    // Encode as witness program: witness version 0, then len(payload), then payload.
-    let mut s: Vec<u8> = vec![version, data.len() as u8];
+    // Encode as witness program: the version opcode, then len(payload), then payload.
+    let op = if version == 0 { 0 } else { version + 0x50 };
+    let mut s: Vec<u8> = vec![op, data.len() as u8];
     s.extend(&data);
 
     Ok(s)
@@ -58,4 +193,38 @@ mod test {
             assert_eq!(*addr, reencoded);
         }
     }
+
+    #[test]
+    fn it_should_encode_and_decode_bech32m() {
+        // BIP350 test vectors
+        let cases = [
+            (
+                "bc",
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            ),
+            (
+                "tb",
+                "tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c",
+                "5120000000c4a5cad46221b2a187905e5266362b99d5e91c6ce24d165dab93e86433",
+            ),
+        ];
+        for case in cases.iter() {
+            let program = decode_bech32(case.0, case.1).unwrap();
+            assert_eq!(hex::encode(&program), case.2);
+            assert_eq!(encode_bech32(case.0, &program).unwrap(), case.1);
+        }
+
+        let errors = [
+            // v1 with a bech32 checksum
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd",
+            // v0 with a bech32m checksum
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh",
+            // bad checksum
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj1",
+        ];
+        for case in errors.iter() {
+            assert!(decode_bech32("bc", case).is_err());
+        }
+    }
 }
@@ -1,6 +1,7 @@
-//! Defines parameterized Bitcoin encoders for Mainnet, Testnet, and Signet.
+//! Defines parameterized Bitcoin encoders for Mainnet, Testnet, Signet, and Regtest, and an
+//! encoder for networks defined at runtime.
 
-use std::marker::PhantomData;
+use std::{borrow::Cow, convert::TryFrom, marker::PhantomData};
 
 use coins_core::{
     bases::{decode_base58, encode_base58},
@@ -10,6 +11,7 @@ use coins_core::{
 
 use crate::{
     enc::bases::{decode_bech32, encode_bech32},
+    silent_payments::SilentPaymentAddress,
     types::script::{ScriptPubkey, ScriptType},
 };
 
@@ -24,6 +26,10 @@ pub enum Address {
     Wpkh(String),
     /// Witness Pay to Scripthash
     Wsh(String),
+    /// Pay to Taproot
+    Tr(String),
+    /// Silent Payment. Has no script pubkey. Each payment derives a new taproot output
+    Sp(String),
 }
 
 impl std::fmt::Display for Address {
@@ -33,6 +39,8 @@ impl std::fmt::Display for Address {
             Address::Sh(s) => s,
             Address::Wpkh(s) => s,
             Address::Wsh(s) => s,
+            Address::Tr(s) => s,
+            Address::Sp(s) => s,
         };
         write!(f, "{}", addr)
     }
@@ -45,6 +53,8 @@ impl AsRef<str> for Address {
             Address::Sh(s) => s,
             Address::Wpkh(s) => s,
             Address::Wsh(s) => s,
+            Address::Tr(s) => s,
+            Address::Sp(s) => s,
         }
     }
 }
@@ -57,6 +67,8 @@ impl Address {
             Address::Sh(s) => s.clone(),
             Address::Wpkh(s) => s.clone(),
             Address::Wsh(s) => s.clone(),
+            Address::Tr(s) => s.clone(),
+            Address::Sp(s) => s.clone(),
         }
     }
 
@@ -68,7 +80,7 @@ impl Address {
 
 /// NetworkParams holds the encoding paramteres for a bitcoin-like network. Currently this is
 /// composed of the address version bytes for Legacy PKH and SH addresses, and the bech32
-/// human-readable prefix for witness addresses.
+/// human-readable prefixes for witness and silent payment addresses.
 pub trait NetworkParams {
     /// The BECH32 HRP. "bc" for mainnet.
     const HRP: &'static str;
@@ -76,12 +88,25 @@ pub trait NetworkParams {
     const PKH_VERSION: u8;
     /// The Legacy SH base58check version byte. 0x05 for mainnet.
     const SH_VERSION: u8;
+    /// The silent payment address HRP. "sp" for mainnet. None if the network does not support
+    /// silent payments.
+    const SP_HRP: Option<&'static str> = None;
 }
 
 /// Marker trait to simplify encoder representation elsewhere
 pub trait BitcoinEncoderMarker:
     AddressEncoder<Address = Address, Error = EncodingError, RecipientIdentifier = ScriptPubkey>
 {
+    /// Convert an address to its script pubkey. Unlike `decode_address`, this errors instead of
+    /// panicking if the address is invalid, is for another network, or does not match its type.
+    fn try_decode_address(addr: &Address) -> EncodingResult<ScriptPubkey>;
+
+    /// Encode a silent payment address
+    fn encode_silent_payment_address(addr: &SilentPaymentAddress) -> EncodingResult<Address>;
+
+    /// Decode a silent payment address to its keys. Errors if the address is invalid, is for
+    /// another network, or is not a silent payment address.
+    fn decode_silent_payment_address(addr: &Address) -> EncodingResult<SilentPaymentAddress>;
 }
 
 /// The standard encoder for Bitcoin networks. Parameterized by a `NetworkParams` type and an
@@ -89,54 +114,215 @@ pub trait BitcoinEncoderMarker:
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BitcoinEncoder<P: NetworkParams>(PhantomData<fn(P) -> P>);
 
+impl<P: NetworkParams> BitcoinEncoder<P> {
+    fn params() -> DynamicEncoder {
+        DynamicEncoder::from_params::<P>()
+    }
+}
+
 impl<P: NetworkParams> AddressEncoder for BitcoinEncoder<P> {
     type Address = Address;
     type Error = EncodingError;
     type RecipientIdentifier = ScriptPubkey;
 
     fn encode_address(s: &ScriptPubkey) -> EncodingResult<Address> {
+        Self::params().encode_address(s)
+    }
+
+    /// Panics if the address is invalid. See `try_decode_address`
+    fn decode_address(addr: &Address) -> ScriptPubkey {
+        Self::try_decode_address(addr).unwrap()
+    }
+
+    fn string_to_address(string: &str) -> EncodingResult<Address> {
+        Self::params().string_to_address(string)
+    }
+}
+
+impl<P: NetworkParams> BitcoinEncoderMarker for BitcoinEncoder<P> {
+    fn try_decode_address(addr: &Address) -> EncodingResult<ScriptPubkey> {
+        Self::params().try_decode_address(addr)
+    }
+
+    fn encode_silent_payment_address(addr: &SilentPaymentAddress) -> EncodingResult<Address> {
+        Self::params().encode_silent_payment_address(addr)
+    }
+
+    fn decode_silent_payment_address(addr: &Address) -> EncodingResult<SilentPaymentAddress> {
+        Self::params().decode_silent_payment_address(addr)
+    }
+}
+
+/// An address encoder with network parameters chosen at runtime, e.g. for signet variants,
+/// regtest forks, or networks read from configuration. It offers the same methods as
+/// `AddressEncoder`, but takes `&self`.
+///
+/// The tx builder and `Network` types require a compile-time encoder. Use
+/// `pay_script_pubkey` to pay addresses decoded by a `DynamicEncoder`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct DynamicEncoder {
+    hrp: Cow<'static, str>,
+    pkh_version: u8,
+    sh_version: u8,
+    #[serde(default)]
+    sp_hrp: Option<Cow<'static, str>>,
+}
+
+impl DynamicEncoder {
+    /// Instantiate an encoder from a bech32 HRP and base58check version bytes
+    pub fn new<S>(hrp: S, pkh_version: u8, sh_version: u8) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        Self {
+            hrp: hrp.into(),
+            pkh_version,
+            sh_version,
+            sp_hrp: None,
+        }
+    }
+
+    /// Instantiate an encoder with the parameters of a compile-time network
+    pub fn from_params<P: NetworkParams>() -> Self {
+        let encoder = Self::new(P::HRP, P::PKH_VERSION, P::SH_VERSION);
+        match P::SP_HRP {
+            Some(sp_hrp) => encoder.with_sp_hrp(sp_hrp),
+            None => encoder,
+        }
+    }
+
+    /// Set the silent payment address HRP. Without it, silent payment addresses are not
+    /// supported
+    pub fn with_sp_hrp<S>(mut self, sp_hrp: S) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        self.sp_hrp = Some(sp_hrp.into());
+        self
+    }
+
+    /// The bech32 HRP
+    pub fn hrp(&self) -> &str {
+        &self.hrp
+    }
+
+    /// The legacy PKH base58check version byte
+    pub fn pkh_version(&self) -> u8 {
+        self.pkh_version
+    }
+
+    /// The legacy SH base58check version byte
+    pub fn sh_version(&self) -> u8 {
+        self.sh_version
+    }
+
+    /// The silent payment address HRP, if the network supports silent payments
+    pub fn sp_hrp(&self) -> Option<&str> {
+        self.sp_hrp.as_deref()
+    }
+
+    /// Encode a script pubkey as an address. See `AddressEncoder::encode_address`
+    pub fn encode_address(&self, s: &ScriptPubkey) -> EncodingResult<Address> {
         match s.standard_type() {
             ScriptType::Pkh(payload) => {
                 // s.items contains the op codes. we want only the pkh
                 Ok(Address::Pkh(encode_base58(
-                    P::PKH_VERSION,
+                    self.pkh_version,
                     payload.as_slice(),
                 )))
             }
             ScriptType::Sh(payload) => {
                 // s.items contains the op codes. we want only the sh
                 Ok(Address::Sh(encode_base58(
-                    P::SH_VERSION,
+                    self.sh_version,
                     payload.as_slice(),
                 )))
             }
-            ScriptType::Wsh(_) => Ok(Address::Wsh(encode_bech32(P::HRP, s.items())?)),
-            ScriptType::Wpkh(_) => Ok(Address::Wpkh(encode_bech32(P::HRP, s.items())?)),
+            ScriptType::Wsh(_) => Ok(Address::Wsh(encode_bech32(&self.hrp, s.items())?)),
+            ScriptType::Wpkh(_) => Ok(Address::Wpkh(encode_bech32(&self.hrp, s.items())?)),
+            ScriptType::Tr(_) => Ok(Address::Tr(encode_bech32(&self.hrp, s.items())?)),
             ScriptType::OpReturn(_) => Err(EncodingError::NullDataScript),
-            ScriptType::NonStandard => Err(EncodingError::UnknownScriptType),
+            ScriptType::Multisig { .. } | ScriptType::NonStandard => {
+                Err(EncodingError::UnknownScriptType)
+            }
         }
     }
 
-    fn decode_address(addr: &Address) -> ScriptPubkey {
+    /// Convert an address to its script pubkey. Errors if the address is invalid, is for another
+    /// network, or does not match its type. Silent payment addresses have no script pubkey, and
+    /// always error. See `BitcoinEncoderMarker::try_decode_address`
+    pub fn try_decode_address(&self, addr: &Address) -> EncodingResult<ScriptPubkey> {
         match &addr {
-            Address::Pkh(s) => decode_base58(P::PKH_VERSION, s).unwrap().into(),
-            Address::Sh(s) => decode_base58(P::SH_VERSION, s).unwrap().into(),
-            Address::Wpkh(s) | Address::Wsh(s) => decode_bech32(P::HRP, s).unwrap().into(),
+            Address::Pkh(s) => {
+                let hash = <[u8; 20]>::try_from(decode_base58(self.pkh_version, s)?.as_slice())
+                    .map_err(|_| EncodingError::InvalidSizeError)?;
+                Ok(ScriptPubkey::p2pkh_from_hash(&hash))
+            }
+            Address::Sh(s) => {
+                let hash = <[u8; 20]>::try_from(decode_base58(self.sh_version, s)?.as_slice())
+                    .map_err(|_| EncodingError::InvalidSizeError)?;
+                Ok(ScriptPubkey::p2sh_from_hash(&hash))
+            }
+            Address::Wpkh(s) | Address::Wsh(s) | Address::Tr(s) => {
+                let program = ScriptPubkey::from(decode_bech32(&self.hrp, s)?);
+                let matches = matches!(
+                    (addr, program.standard_type()),
+                    (Address::Wpkh(_), ScriptType::Wpkh(_))
+                        | (Address::Wsh(_), ScriptType::Wsh(_))
+                        | (Address::Tr(_), ScriptType::Tr(_))
+                );
+                if !matches {
+                    return Err(EncodingError::UnknownScriptType);
+                }
+                Ok(program)
+            }
+            Address::Sp(_) => Err(EncodingError::UnknownScriptType),
         }
     }
 
-    fn string_to_address(string: &str) -> EncodingResult<Address> {
+    /// Encode a silent payment address. Errors if the network does not support silent payments.
+    /// See `BitcoinEncoderMarker::encode_silent_payment_address`
+    pub fn encode_silent_payment_address(
+        &self,
+        addr: &SilentPaymentAddress,
+    ) -> EncodingResult<Address> {
+        let sp_hrp = self.sp_hrp().ok_or(EncodingError::UnknownScriptType)?;
+        Ok(Address::Sp(addr.encode(sp_hrp)?))
+    }
+
+    /// Decode a silent payment address to its keys. See
+    /// `BitcoinEncoderMarker::decode_silent_payment_address`
+    pub fn decode_silent_payment_address(
+        &self,
+        addr: &Address,
+    ) -> EncodingResult<SilentPaymentAddress> {
+        match (addr, self.sp_hrp()) {
+            (Address::Sp(s), Some(sp_hrp)) => SilentPaymentAddress::decode(sp_hrp, s),
+            _ => Err(EncodingError::UnknownScriptType),
+        }
+    }
+
+    /// Parse an address string, and determine its type. See `AddressEncoder::string_to_address`
+    pub fn string_to_address(&self, string: &str) -> EncodingResult<Address> {
         let s = string.to_owned();
-        if s.starts_with(P::HRP) {
-            let result = decode_bech32(P::HRP, &s)?;
-            match result.len() {
-                22 => Ok(Address::Wpkh(s)),
-                34 => Ok(Address::Wsh(s)),
+        if let Some(sp_hrp) = self.sp_hrp() {
+            if s.starts_with(&format!("{}1", sp_hrp)) {
+                SilentPaymentAddress::decode(sp_hrp, &s)?;
+                return Ok(Address::Sp(s));
+            }
+        }
+        if s.starts_with(self.hrp.as_ref()) {
+            let result = ScriptPubkey::from(decode_bech32(&self.hrp, &s)?);
+            // v0 programs are 20 or 32 bytes. v1 programs are 32-byte taproot output keys
+            match result.standard_type() {
+                ScriptType::Wpkh(_) => Ok(Address::Wpkh(s)),
+                ScriptType::Wsh(_) => Ok(Address::Wsh(s)),
+                ScriptType::Tr(_) => Ok(Address::Tr(s)),
                 _ => Err(EncodingError::UnknownScriptType),
             }
-        } else if decode_base58(P::PKH_VERSION, &s).is_ok() {
+        } else if decode_base58(self.pkh_version, &s).is_ok() {
             Ok(Address::Pkh(s))
-        } else if decode_base58(P::SH_VERSION, &s).is_ok() {
+        } else if decode_base58(self.sh_version, &s).is_ok() {
             Ok(Address::Sh(s))
         } else {
             Err(EncodingError::UnknownScriptType)
@@ -144,8 +330,6 @@ impl<P: NetworkParams> AddressEncoder for BitcoinEncoder<P> {
     }
 }
 
-impl<P: NetworkParams> BitcoinEncoderMarker for BitcoinEncoder<P> {}
-
 /// A param struct for Bitcoin Mainnet
 #[derive(Debug, Clone)]
 pub struct Main;
@@ -154,6 +338,7 @@ impl NetworkParams for Main {
     const HRP: &'static str = "bc";
     const PKH_VERSION: u8 = 0x00;
     const SH_VERSION: u8 = 0x05;
+    const SP_HRP: Option<&'static str> = Some("sp");
 }
 
 /// A param struct for Bitcoin Tesnet
@@ -164,6 +349,7 @@ impl NetworkParams for Test {
     const HRP: &'static str = "tb";
     const PKH_VERSION: u8 = 0x6f;
     const SH_VERSION: u8 = 0xc4;
+    const SP_HRP: Option<&'static str> = Some("tsp");
 }
 
 /// A param struct for Bitcoin Signet
@@ -174,6 +360,18 @@ impl NetworkParams for Sig {
     const HRP: &'static str = "sb";
     const PKH_VERSION: u8 = 0x7d;
     const SH_VERSION: u8 = 0x57;
+    const SP_HRP: Option<&'static str> = Some("tsp");
+}
+
+/// A param struct for Bitcoin Regtest. Legacy addresses use the testnet version bytes
+#[derive(Debug, Clone)]
+pub struct Reg;
+
+impl NetworkParams for Reg {
+    const HRP: &'static str = "bcrt";
+    const PKH_VERSION: u8 = 0x6f;
+    const SH_VERSION: u8 = 0xc4;
+    const SP_HRP: Option<&'static str> = Some("tsp");
 }
 
 /// An encoder for Bitcoin Mainnet
@@ -185,6 +383,9 @@ pub type TestnetEncoder = BitcoinEncoder<Test>;
 /// An encoder for Bitcoin Signet
 pub type SignetEncoder = BitcoinEncoder<Sig>;
 
+/// An encoder for Bitcoin Regtest
+pub type RegtestEncoder = BitcoinEncoder<Reg>;
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -202,6 +403,12 @@ mod test {
                     "bc1qwqdg6squsna38e46795at95yu9atm8azzmyvckulcc7kytlcckxswvvzej".to_owned(),
                 ),
             ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0".to_owned(),
+                Address::Tr(
+                    "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0".to_owned(),
+                ),
+            ),
             (
                 "1AqE7oGF1EUoJviX1uuYrwpRBdEBTuGhES".to_owned(),
                 Address::Pkh("1AqE7oGF1EUoJviX1uuYrwpRBdEBTuGhES".to_owned()),
@@ -260,10 +467,23 @@ mod test {
                 ),
                 Address::Wpkh("bc1qr0u2rqcak4zrks4yfuc2zgw3kctdqydt3wy5yh".to_owned()),
             ),
+            (
+                ScriptPubkey::p2tr(&[
+                    0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce,
+                    0x87, 0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2,
+                    0x81, 0x5b, 0x16, 0xf8, 0x17, 0x98,
+                ]),
+                Address::Tr(
+                    "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0".to_owned(),
+                ),
+            ),
         ];
         for case in cases.iter() {
             assert_eq!(MainnetEncoder::encode_address(&case.0).unwrap(), case.1);
         }
+        for (script, address) in cases.iter() {
+            assert_eq!(&MainnetEncoder::decode_address(address), script);
+        }
         let errors = [
             (ScriptPubkey::new(hex::decode("01201bf8a1831db5443b42a44f30a121d1b616d011ab15df62b588722a845864cc99").unwrap())), // wrong witness program version
             (ScriptPubkey::new(hex::decode("a914e88869b88866281ab166541ad8aafba8f8aba47a89").unwrap())), // wrong last byte
@@ -283,6 +503,101 @@ mod test {
         }
     }
 
+    #[test]
+    fn it_rejects_invalid_addresses() {
+        let cases = [
+            // wpkh program in a wsh address
+            Address::Wsh("bc1qr0u2rqcak4zrks4yfuc2zgw3kctdqydt3wy5yh".to_owned()),
+            // testnet address
+            Address::Wpkh("tb1qr0u2rqcak4zrks4yfuc2zgw3kctdqydtzdcz8h".to_owned()),
+            // sh address as pkh
+            Address::Pkh("3NtY7BrF3xrcb31JXXaYCKVcz1cH3Azo5y".to_owned()),
+            Address::Sh("hello".to_owned()),
+        ];
+        for case in cases.iter() {
+            assert!(MainnetEncoder::try_decode_address(case).is_err());
+        }
+    }
+
+    #[test]
+    fn it_encodes_with_runtime_params() {
+        let wpkh = ScriptPubkey::p2wpkh_from_hash(&[0x11; 20]);
+        let pkh = ScriptPubkey::p2pkh_from_hash(&[0x11; 20]);
+
+        let testnet = DynamicEncoder::new("tb", 0x6f, 0xc4).with_sp_hrp("tsp");
+        assert_eq!(testnet, DynamicEncoder::from_params::<Test>());
+        for script in [wpkh.clone(), pkh.clone()].iter() {
+            let addr = testnet.encode_address(script).unwrap();
+            assert_eq!(addr, TestnetEncoder::encode_address(script).unwrap());
+            assert_eq!(testnet.string_to_address(addr.as_ref()).unwrap(), addr);
+            assert_eq!(&testnet.try_decode_address(&addr).unwrap(), script);
+        }
+
+        // a network that is not known at compile time
+        let custom = DynamicEncoder::new("xyz".to_owned(), 0x30, 0x32);
+        assert_eq!(custom.hrp(), "xyz");
+        let addr = custom.encode_address(&wpkh).unwrap();
+        assert!(addr.as_ref().starts_with("xyz1"));
+        assert_eq!(custom.try_decode_address(&addr).unwrap(), wpkh);
+        let addr = custom.encode_address(&pkh).unwrap();
+        assert_eq!(custom.string_to_address(addr.as_ref()).unwrap(), addr);
+        assert!(MainnetEncoder::try_decode_address(&addr).is_err());
+
+        let mainnet = MainnetEncoder::encode_address(&pkh).unwrap();
+        assert!(custom.try_decode_address(&mainnet).is_err());
+        assert!(custom.string_to_address(mainnet.as_ref()).is_err());
+    }
+
+    #[test]
+    fn it_encodes_regtest_addresses() {
+        let wpkh = ScriptPubkey::p2wpkh_from_hash(&[0x11; 20]);
+        let addr = RegtestEncoder::encode_address(&wpkh).unwrap();
+        assert!(addr.as_ref().starts_with("bcrt1q"));
+        assert_eq!(
+            RegtestEncoder::string_to_address(addr.as_ref()).unwrap(),
+            addr
+        );
+        assert_eq!(RegtestEncoder::try_decode_address(&addr).unwrap(), wpkh);
+        assert!(MainnetEncoder::string_to_address(addr.as_ref()).is_err());
+        assert!(TestnetEncoder::try_decode_address(&addr).is_err());
+
+        // legacy addresses are shared with testnet
+        let pkh = ScriptPubkey::p2pkh_from_hash(&[0x11; 20]);
+        let addr = RegtestEncoder::encode_address(&pkh).unwrap();
+        assert_eq!(addr, TestnetEncoder::encode_address(&pkh).unwrap());
+        let sh = Address::Sh("2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc".to_owned());
+        assert_eq!(RegtestEncoder::string_to_address(sh.as_ref()).unwrap(), sh);
+    }
+
+    #[test]
+    fn it_encodes_silent_payment_addresses() {
+        let s = "sp1qqvkqkl8e2vj2qlg98x9jgqt5msxzhezym943tx4xclmmrengdqyezq56cgpnt6ecw6xjq547rkau8j8kz7zqw3vw28ntftfz78v3wkyftvjs8qwk";
+        let addr = MainnetEncoder::string_to_address(s).unwrap();
+        assert_eq!(addr, Address::Sp(s.to_owned()));
+        let recipient = MainnetEncoder::decode_silent_payment_address(&addr).unwrap();
+        assert_eq!(
+            MainnetEncoder::encode_silent_payment_address(&recipient).unwrap(),
+            addr
+        );
+        // silent payment addresses have no script pubkey
+        assert!(MainnetEncoder::try_decode_address(&addr).is_err());
+        assert!(TestnetEncoder::string_to_address(s).is_err());
+
+        let testnet = TestnetEncoder::encode_silent_payment_address(&recipient).unwrap();
+        assert!(testnet.as_ref().starts_with("tsp1q"));
+        assert_eq!(
+            SignetEncoder::decode_silent_payment_address(&testnet).unwrap(),
+            recipient
+        );
+        // runtime networks support silent payments if given an HRP
+        let custom = DynamicEncoder::new("xyz", 0x30, 0x32);
+        assert_eq!(custom.sp_hrp(), None);
+        assert!(custom.encode_silent_payment_address(&recipient).is_err());
+        let custom = custom.with_sp_hrp("xsp");
+        let addr = custom.encode_silent_payment_address(&recipient).unwrap();
+        assert_eq!(custom.string_to_address(addr.as_ref()).unwrap(), addr);
+    }
+
     #[test]
     fn it_allows_you_to_unwrap_strings_from_addresses() {
         let cases = [
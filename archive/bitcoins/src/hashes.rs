@@ -1,7 +1,13 @@
 //! This module holds `MarkedDigest` types used by Bitcoin transactions. Currently we represent
 //! only `TXID`s and `WTXID`s. In the future we may also represent sighash digests this way.
+//!
+//! It also holds the BIP340 tagged hash used by taproot.
 
-use coins_core::{hashes, impl_hex_serde, marked_digest};
+use coins_core::{
+    hashes::{self, Digest, MarkedDigestOutput, Sha256},
+    impl_hex_serde, marked_digest,
+    ser::SerError,
+};
 
 marked_digest!(
     /// A marked Hash256Digest representing transaction IDs
@@ -25,11 +31,102 @@ impl_hex_serde!(TXID);
 impl_hex_serde!(WTXID);
 impl_hex_serde!(BlockHash);
 
+/// Implement byte order accessors, and `Display` and `FromStr` in big-endian order, for a
+/// 32-byte marked digest.
+macro_rules! impl_be_display {
+    ($name:ident) => {
+        impl $name {
+            /// The digest in internal (little-endian) byte order, as it appears in serialized
+            /// transactions and blocks.
+            pub fn to_le_bytes(&self) -> [u8; 32] {
+                let mut bytes = [0u8; 32];
+                bytes.copy_from_slice(self.as_ref());
+                bytes
+            }
+
+            /// Instantiate from bytes in internal (little-endian) byte order
+            pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+                Self::from(bytes)
+            }
+
+            /// The digest in big-endian byte order, as displayed by explorers and RPC
+            pub fn to_be_bytes(&self) -> [u8; 32] {
+                let mut bytes = self.to_le_bytes();
+                bytes.reverse();
+                bytes
+            }
+
+            /// Instantiate from bytes in big-endian byte order
+            pub fn from_be_bytes(mut bytes: [u8; 32]) -> Self {
+                bytes.reverse();
+                Self::from(bytes)
+            }
+
+            /// The digest as big-endian hex, as displayed by explorers and RPC. Same as
+            /// `Display`.
+            pub fn to_be_hex(&self) -> String {
+                hex::encode(self.to_be_bytes())
+            }
+        }
+
+        /// Big-endian hex, as displayed by explorers and RPC. `serialize_hex` and serde use
+        /// internal (little-endian) byte order.
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(&self.to_be_hex())
+            }
+        }
+
+        /// Parses big-endian hex, as displayed by explorers and RPC
+        impl std::str::FromStr for $name {
+            type Err = SerError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                <Self as MarkedDigestOutput>::from_be_hex(s)
+            }
+        }
+    };
+}
+
+impl_be_display!(TXID);
+impl_be_display!(WTXID);
+impl_be_display!(BlockHash);
+
+/// The BIP340 tagged hash of `data`: `sha256(sha256(tag) || sha256(tag) || data)`. The tag
+/// separates hashes of different kinds of data, e.g. `"TapSighash"` or `"TapLeaf"`.
+pub fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use coins_core::ser::ByteFormat;
 
+    #[test]
+    fn it_displays_and_parses_big_endian_hex() {
+        // the genesis block
+        let be = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+        let hash: BlockHash = be.parse().unwrap();
+        assert_eq!(hash.to_string(), be);
+        assert_eq!(hash.to_be_hex(), be);
+        assert_eq!(hash.to_be_bytes()[..4], [0, 0, 0, 0]);
+        assert_eq!(hash.to_le_bytes()[28..], [0, 0, 0, 0]);
+        assert_eq!(hash.serialize_hex(), hex::encode(hash.to_le_bytes()));
+        assert_eq!(BlockHash::from_le_bytes(hash.to_le_bytes()), hash);
+        assert_eq!(BlockHash::from_be_bytes(hash.to_be_bytes()), hash);
+
+        let txid = TXID::from_be_bytes([1; 32]);
+        assert_eq!(format!("{}", txid), "01".repeat(32));
+        assert!("00".parse::<TXID>().is_err());
+        assert!("zz".repeat(32).parse::<WTXID>().is_err());
+    }
+
     #[test]
     fn it_serializes_and_derializes_hash256digests() {
         let cases = [(
@@ -1,24 +1,41 @@
 //! This crate provides a simple interface for interacting with Bitcoin mainnet,
-//! testnet, and signet.
+//! testnet, signet, and regtest.
 
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 #![warn(unused_extern_crates)]
 
+pub mod bip322;
 pub mod builder;
+pub mod coinselect;
+pub mod descriptor;
 pub mod enc;
 pub mod hashes;
+pub mod message;
+pub mod miniscript;
 pub mod nets;
+pub mod recovery;
+pub mod silent_payments;
 pub mod types;
 
 /// Common re-exports
 pub mod prelude;
 
 #[doc(hidden)]
-#[cfg(any(feature = "mainnet", feature = "testnet"))]
+#[cfg(any(
+    feature = "mainnet",
+    feature = "testnet",
+    feature = "signet",
+    feature = "regtest"
+))]
 pub mod defaults;
 
-#[cfg(any(feature = "mainnet", feature = "testnet"))]
+#[cfg(any(
+    feature = "mainnet",
+    feature = "testnet",
+    feature = "signet",
+    feature = "regtest"
+))]
 pub use defaults::network::{Encoder, Net};
 
 pub use nets::*;
@@ -0,0 +1,211 @@
+//! Bitcoin signed messages, as produced by `signmessage` and hardware
+//! wallets. Signatures are encoded in the 65-byte recoverable format of
+//! [BIP 137](https://github.com/bitcoin/bips/blob/master/bip-0137.mediawiki),
+//! whose header byte records the recovery ID and the type of address signed
+//! for.
+
+use std::convert::TryFrom;
+
+use coins_bip32::{
+    ecdsa::{recoverable, Signature, VerifyingKey},
+    prelude::RecoverableSignature,
+};
+use coins_core::{
+    hashes::{Digest, Hash160, Hash256, Hash256Digest, MarkedDigest},
+    ser,
+};
+use thiserror::Error;
+
+use crate::types::script::{Script, ScriptPubkey};
+
+/// The prefix committed to by signed messages.
+pub const MESSAGE_PREFIX: &[u8] = b"\x18Bitcoin Signed Message:\n";
+
+/// The type of address a message signature proves ownership of. BIP 137
+/// records this in the signature's header byte. Only compressed keys are
+/// supported.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MessageAddressType {
+    /// Pay to Pubkeyhash
+    Pkh,
+    /// Pay to Witness Pubkeyhash nested in Pay to Scripthash
+    ShWpkh,
+    /// Pay to Witness Pubkeyhash
+    Wpkh,
+}
+
+impl MessageAddressType {
+    /// The BIP 137 header byte, excluding the recovery ID.
+    pub const fn header_base(self) -> u8 {
+        match self {
+            MessageAddressType::Pkh => 31,
+            MessageAddressType::ShWpkh => 35,
+            MessageAddressType::Wpkh => 39,
+        }
+    }
+
+    /// The script pubkey of this address type for `key`.
+    pub fn script_pubkey(self, key: &VerifyingKey) -> ScriptPubkey {
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&Hash160::digest(key.to_bytes()));
+
+        let wpkh = ScriptPubkey::p2wpkh_from_hash(&hash);
+        match self {
+            MessageAddressType::Pkh => ScriptPubkey::p2pkh_from_hash(&hash),
+            MessageAddressType::ShWpkh => ScriptPubkey::p2sh(&Script::from(&wpkh)),
+            MessageAddressType::Wpkh => wpkh,
+        }
+    }
+}
+
+/// Message signing errors
+#[derive(Debug, Error)]
+pub enum MessageError {
+    /// Signature was not 65 bytes
+    #[error("Expected a 65-byte signature. Got {0} bytes")]
+    WrongLength(usize),
+
+    /// The header byte does not denote a compressed key type
+    #[error("Unsupported BIP 137 header byte {0}")]
+    UnsupportedHeader(u8),
+
+    /// The signature is malformed, or no key could be recovered
+    #[error(transparent)]
+    EcdsaError(#[from] coins_bip32::ecdsa::Error),
+}
+
+/// The digest signed by a message signature. This is the Hash256 of the
+/// prefix, the compact-int message length, and the message.
+pub fn message_hash(message: &[u8]) -> Hash256Digest {
+    let mut w = Hash256::default();
+    w.update(MESSAGE_PREFIX);
+    ser::write_compact_int(&mut w, message.len() as u64).expect("no error on hash writer");
+    w.update(message);
+    w.finalize_marked()
+}
+
+/// Encode a signature in the BIP 137 format, for an address of type
+/// `address_type`.
+pub fn encode_signature(sig: &RecoverableSignature, address_type: MessageAddressType) -> [u8; 65] {
+    let mut buf = [0u8; 65];
+    buf[0] = address_type.header_base() + u8::from(sig.recovery_id());
+    buf[1..].copy_from_slice(&sig.as_ref()[..64]);
+    buf
+}
+
+/// Decode a BIP 137 signature into its recoverable signature and address
+/// type. Recovery IDs 2 and 3, which denote an `r` value above the curve
+/// order, are not supported, and error.
+pub fn decode_signature(
+    sig: &[u8],
+) -> Result<(RecoverableSignature, MessageAddressType), MessageError> {
+    if sig.len() != 65 {
+        return Err(MessageError::WrongLength(sig.len()));
+    }
+    let address_type = match sig[0] {
+        31..=34 => MessageAddressType::Pkh,
+        35..=38 => MessageAddressType::ShWpkh,
+        39..=42 => MessageAddressType::Wpkh,
+        header => return Err(MessageError::UnsupportedHeader(header)),
+    };
+    let recovery_id = recoverable::Id::new(sig[0] - address_type.header_base())?;
+    let sig = RecoverableSignature::new(&Signature::try_from(&sig[1..])?, recovery_id)?;
+    Ok((sig, address_type))
+}
+
+/// Recover the key that signed `message`, and the type of address it signed
+/// for.
+pub fn recover_signer(
+    message: &[u8],
+    sig: &[u8],
+) -> Result<(VerifyingKey, MessageAddressType), MessageError> {
+    let (sig, address_type) = decode_signature(sig)?;
+    let digest = message_hash(message);
+    let key = sig.recover_verifying_key_from_digest_bytes(&digest.to_internal())?;
+    Ok((key, address_type))
+}
+
+/// Verify that `sig` is a signature over `message` by the owner of the
+/// address with `script_pubkey`. Returns false if the signature is
+/// well-formed, but was made by another key or for another address type.
+///
+/// Use an address encoder's `decode_address` to get the script pubkey of an
+/// address.
+pub fn verify_message(
+    script_pubkey: &ScriptPubkey,
+    message: &[u8],
+    sig: &[u8],
+) -> Result<bool, MessageError> {
+    let (key, address_type) = recover_signer(message, sig)?;
+    Ok(&address_type.script_pubkey(&key) == script_pubkey)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::enc::{Address, TestnetEncoder};
+    use coins_bip32::ecdsa::{signature::DigestSigner, SigningKey};
+    use coins_core::enc::AddressEncoder;
+
+    fn sign(key: &SigningKey, message: &[u8]) -> RecoverableSignature {
+        let mut w = Hash256::default();
+        w.update(MESSAGE_PREFIX);
+        ser::write_compact_int(&mut w, message.len() as u64).unwrap();
+        w.update(message);
+        key.sign_digest(w)
+    }
+
+    #[test]
+    fn it_hashes_messages() {
+        // `Hash256("\x18Bitcoin Signed Message:\n\x00")`
+        let mut w = Hash256::default();
+        w.update(b"\x18Bitcoin Signed Message:\n\x00");
+        assert_eq!(message_hash(b""), w.finalize_marked());
+
+        // Bitcoin Core `signmessage` vector, from `rpc_signmessage.py`
+        let address = Address::Pkh("mpLQjfK79b7CCV4VMJWEWAj5Mpx8Up5zxB".to_owned());
+        let script = TestnetEncoder::decode_address(&address);
+        let message = b"This is just a test message";
+        // base64 `INbVnW4e6PeRmsv2Qgu8NuopvrVjkcxob+sX8OcZG0SALhWybUjzMLPdAsXI46YZGb0KQTRii+wWIQzRpG/U+S0=`
+        let sig = hex::decode("20d6d59d6e1ee8f7919acbf6420bbc36ea29beb56391cc686feb17f0e7191b44802e15b26d48f330b3dd02c5c8e3a61919bd0a4134628bec16210cd1a46fd4f92d").unwrap();
+        assert!(verify_message(&script, message, &sig).unwrap());
+        assert!(!verify_message(&script, b"This is just a test message.", &sig).unwrap());
+    }
+
+    #[test]
+    fn it_signs_and_verifies_messages() {
+        let key = SigningKey::from_bytes(&[0x11; 32]).unwrap();
+        let message = b"This is an example of a signed message.";
+        let sig = sign(&key, message);
+
+        for address_type in [
+            MessageAddressType::Pkh,
+            MessageAddressType::ShWpkh,
+            MessageAddressType::Wpkh,
+        ]
+        .iter()
+        {
+            let encoded = encode_signature(&sig, *address_type);
+            let (recovered, ty) = recover_signer(message, &encoded).unwrap();
+            assert_eq!(&recovered, key.as_ref());
+            assert_eq!(ty, *address_type);
+
+            let script = address_type.script_pubkey(key.as_ref());
+            assert!(verify_message(&script, message, &encoded).unwrap());
+            assert!(!verify_message(&script, b"another message", &encoded).unwrap());
+        }
+
+        // an address of a different type is not proven
+        let encoded = encode_signature(&sig, MessageAddressType::Wpkh);
+        let p2pkh = ScriptPubkey::p2pkh(&key);
+        assert!(!verify_message(&p2pkh, message, &encoded).unwrap());
+
+        let mut bad_header = encoded;
+        bad_header[0] = 27;
+        assert!(recover_signer(message, &bad_header).is_err());
+        // recovery IDs 2 and 3 are not masked into 0 and 1
+        bad_header[0] = MessageAddressType::Wpkh.header_base() + 2;
+        assert!(recover_signer(message, &bad_header).is_err());
+        assert!(recover_signer(message, &encoded[..64]).is_err());
+    }
+}
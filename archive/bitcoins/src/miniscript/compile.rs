@@ -0,0 +1,221 @@
+use coins_core::hashes::{Digest, Hash160};
+
+use crate::{
+    miniscript::Fragment,
+    types::script::{push_data, push_int},
+};
+
+const OP_0: u8 = 0x00;
+const OP_1: u8 = 0x51;
+const OP_IF: u8 = 0x63;
+const OP_NOTIF: u8 = 0x64;
+const OP_ELSE: u8 = 0x67;
+const OP_ENDIF: u8 = 0x68;
+const OP_VERIFY: u8 = 0x69;
+const OP_TOALTSTACK: u8 = 0x6b;
+const OP_FROMALTSTACK: u8 = 0x6c;
+const OP_IFDUP: u8 = 0x73;
+const OP_DUP: u8 = 0x76;
+const OP_SWAP: u8 = 0x7c;
+const OP_SIZE: u8 = 0x82;
+const OP_EQUAL: u8 = 0x87;
+const OP_EQUALVERIFY: u8 = 0x88;
+const OP_0NOTEQUAL: u8 = 0x92;
+const OP_ADD: u8 = 0x93;
+const OP_BOOLAND: u8 = 0x9a;
+const OP_BOOLOR: u8 = 0x9b;
+const OP_NUMEQUAL: u8 = 0x9c;
+const OP_NUMEQUALVERIFY: u8 = 0x9d;
+const OP_RIPEMD160: u8 = 0xa6;
+const OP_SHA256: u8 = 0xa8;
+const OP_HASH160: u8 = 0xa9;
+const OP_HASH256: u8 = 0xaa;
+const OP_CHECKSIG: u8 = 0xac;
+const OP_CHECKSIGVERIFY: u8 = 0xad;
+const OP_CHECKMULTISIG: u8 = 0xae;
+const OP_CHECKMULTISIGVERIFY: u8 = 0xaf;
+const OP_CLTV: u8 = 0xb1;
+const OP_CSV: u8 = 0xb2;
+const OP_CHECKSIGADD: u8 = 0xba;
+
+fn hash_check(script: &mut Vec<u8>, op: u8, hash: &[u8]) {
+    script.extend_from_slice(&[OP_SIZE, 0x01, 0x20, OP_EQUALVERIFY, op]);
+    push_data(script, hash);
+    script.push(OP_EQUAL);
+}
+
+impl Fragment {
+    /// Compile the expression to Script
+    pub fn encode(&self) -> Vec<u8> {
+        let mut script = vec![];
+        self.encode_to(&mut script);
+        script
+    }
+
+    fn encode_to(&self, s: &mut Vec<u8>) {
+        match self {
+            Fragment::False => s.push(OP_0),
+            Fragment::True => s.push(OP_1),
+            Fragment::PkK(key) => push_data(s, key),
+            Fragment::PkH(key) => {
+                s.extend_from_slice(&[OP_DUP, OP_HASH160]);
+                push_data(s, &Hash160::digest(key));
+                s.push(OP_EQUALVERIFY);
+            }
+            Fragment::Older(n) => {
+                push_int(s, *n as u64);
+                s.push(OP_CSV);
+            }
+            Fragment::After(n) => {
+                push_int(s, *n as u64);
+                s.push(OP_CLTV);
+            }
+            Fragment::Sha256(h) => hash_check(s, OP_SHA256, h),
+            Fragment::Hash256(h) => hash_check(s, OP_HASH256, h),
+            Fragment::Ripemd160(h) => hash_check(s, OP_RIPEMD160, h),
+            Fragment::Hash160(h) => hash_check(s, OP_HASH160, h),
+            Fragment::AndOr(x, y, z) => {
+                x.encode_to(s);
+                s.push(OP_NOTIF);
+                z.encode_to(s);
+                s.push(OP_ELSE);
+                y.encode_to(s);
+                s.push(OP_ENDIF);
+            }
+            Fragment::AndV(x, y) => {
+                x.encode_to(s);
+                y.encode_to(s);
+            }
+            Fragment::AndB(x, y) => {
+                x.encode_to(s);
+                y.encode_to(s);
+                s.push(OP_BOOLAND);
+            }
+            Fragment::OrB(x, z) => {
+                x.encode_to(s);
+                z.encode_to(s);
+                s.push(OP_BOOLOR);
+            }
+            Fragment::OrC(x, z) => {
+                x.encode_to(s);
+                s.push(OP_NOTIF);
+                z.encode_to(s);
+                s.push(OP_ENDIF);
+            }
+            Fragment::OrD(x, z) => {
+                x.encode_to(s);
+                s.extend_from_slice(&[OP_IFDUP, OP_NOTIF]);
+                z.encode_to(s);
+                s.push(OP_ENDIF);
+            }
+            Fragment::OrI(x, z) => {
+                s.push(OP_IF);
+                x.encode_to(s);
+                s.push(OP_ELSE);
+                z.encode_to(s);
+                s.push(OP_ENDIF);
+            }
+            Fragment::Thresh(k, subs) => {
+                for (i, sub) in subs.iter().enumerate() {
+                    sub.encode_to(s);
+                    if i > 0 {
+                        s.push(OP_ADD);
+                    }
+                }
+                push_int(s, *k as u64);
+                s.push(OP_EQUAL);
+            }
+            Fragment::Multi(k, keys) => {
+                push_int(s, *k as u64);
+                for key in keys.iter() {
+                    push_data(s, key);
+                }
+                push_int(s, keys.len() as u64);
+                s.push(OP_CHECKMULTISIG);
+            }
+            Fragment::MultiA(k, keys) => {
+                for (i, key) in keys.iter().enumerate() {
+                    push_data(s, key);
+                    s.push(if i == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD });
+                }
+                push_int(s, *k as u64);
+                s.push(OP_NUMEQUAL);
+            }
+            Fragment::Alt(x) => {
+                s.push(OP_TOALTSTACK);
+                x.encode_to(s);
+                s.push(OP_FROMALTSTACK);
+            }
+            Fragment::Swap(x) => {
+                s.push(OP_SWAP);
+                x.encode_to(s);
+            }
+            Fragment::Check(x) => {
+                x.encode_to(s);
+                s.push(OP_CHECKSIG);
+            }
+            Fragment::DupIf(x) => {
+                s.extend_from_slice(&[OP_DUP, OP_IF]);
+                x.encode_to(s);
+                s.push(OP_ENDIF);
+            }
+            Fragment::Verify(x) => {
+                x.encode_to(s);
+                // fold the VERIFY into the last opcode where a VERIFY form exists
+                let verify_op = match x.last_opcode() {
+                    Some(OP_EQUAL) => Some(OP_EQUALVERIFY),
+                    Some(OP_CHECKSIG) => Some(OP_CHECKSIGVERIFY),
+                    Some(OP_CHECKMULTISIG) => Some(OP_CHECKMULTISIGVERIFY),
+                    Some(OP_NUMEQUAL) => Some(OP_NUMEQUALVERIFY),
+                    _ => None,
+                };
+                match verify_op {
+                    Some(op) => *s.last_mut().unwrap() = op,
+                    None => s.push(OP_VERIFY),
+                }
+            }
+            Fragment::NonZero(x) => {
+                s.extend_from_slice(&[OP_SIZE, OP_0NOTEQUAL, OP_IF]);
+                x.encode_to(s);
+                s.push(OP_ENDIF);
+            }
+            Fragment::ZeroNotEqual(x) => {
+                x.encode_to(s);
+                s.push(OP_0NOTEQUAL);
+            }
+        }
+    }
+
+    /// The final opcode of the compiled expression, if it does not end in a data push
+    fn last_opcode(&self) -> Option<u8> {
+        match self {
+            Fragment::PkK(_) => None,
+            Fragment::PkH(_) => Some(OP_EQUALVERIFY),
+            Fragment::False => Some(OP_0),
+            Fragment::True => Some(OP_1),
+            Fragment::Older(_) => Some(OP_CSV),
+            Fragment::After(_) => Some(OP_CLTV),
+            Fragment::Sha256(_)
+            | Fragment::Hash256(_)
+            | Fragment::Ripemd160(_)
+            | Fragment::Hash160(_)
+            | Fragment::Thresh(..) => Some(OP_EQUAL),
+            Fragment::AndOr(..)
+            | Fragment::OrC(..)
+            | Fragment::OrD(..)
+            | Fragment::OrI(..)
+            | Fragment::DupIf(_)
+            | Fragment::NonZero(_) => Some(OP_ENDIF),
+            Fragment::AndV(_, y) => y.last_opcode(),
+            Fragment::Swap(x) => x.last_opcode(),
+            Fragment::AndB(..) => Some(OP_BOOLAND),
+            Fragment::OrB(..) => Some(OP_BOOLOR),
+            Fragment::Multi(..) => Some(OP_CHECKMULTISIG),
+            Fragment::MultiA(..) => Some(OP_NUMEQUAL),
+            Fragment::Alt(_) => Some(OP_FROMALTSTACK),
+            Fragment::Check(_) => Some(OP_CHECKSIG),
+            Fragment::Verify(_) => None,
+            Fragment::ZeroNotEqual(_) => Some(OP_0NOTEQUAL),
+        }
+    }
+}
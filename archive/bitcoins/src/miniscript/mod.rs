@@ -0,0 +1,628 @@
+//! Miniscript: a structured representation of a subset of Bitcoin Script.
+//!
+//! This module parses the Miniscript string format, checks each expression against the
+//! correctness type system, compiles it to Script, and builds witnesses from the signatures,
+//! preimages and timelocks available to a `Satisfier`. Both P2WSH and Tapscript contexts are
+//! supported. Keys are hex: 33-byte compressed keys under `wsh`, 32-byte x-only keys under `tr`.
+//!
+//! The malleability analysis of the specification is not performed. Satisfactions are chosen by
+//! witness size alone.
+//!
+//! ```
+//! use bitcoins::miniscript::{Context, Miniscript};
+//!
+//! let ms = Miniscript::parse(
+//!     "or_d(pk(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798),and_v(v:pk(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5),older(144)))",
+//!     Context::Wsh,
+//! )
+//! .unwrap();
+//! let witness_script = ms.to_script();
+//! ```
+
+mod compile;
+mod satisfy;
+mod types;
+
+pub use satisfy::*;
+pub use types::*;
+
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::types::script::{Script, ScriptPubkey};
+
+/// The maximum nesting depth of an expression, counting wrappers. Parsing, type checking and
+/// compiling recurse once per level, so deeper expressions are rejected rather than risk
+/// overflowing the stack.
+pub const MAX_RECURSION_DEPTH: usize = 402;
+
+/// Errors while parsing, type-checking or satisfying Miniscript
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MiniscriptError {
+    /// The expression is not well-formed
+    #[error("Malformed expression: {0}")]
+    Malformed(String),
+
+    /// Unknown fragment or wrapper name
+    #[error("Unknown fragment: {0}")]
+    UnknownFragment(String),
+
+    /// A fragment received the wrong number of arguments
+    #[error("Fragment {0} expects {1} arguments")]
+    WrongArgCount(String, usize),
+
+    /// A key was not valid hex of the length the context requires
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    /// A hash was not valid hex of the length the fragment requires
+    #[error("Invalid hash: {0}")]
+    InvalidHash(String),
+
+    /// A timelock was outside of 1..2^31
+    #[error("Invalid timelock: {0}")]
+    InvalidTimelock(String),
+
+    /// A threshold was outside of 1..=n, or n exceeded the context's limit
+    #[error("Invalid threshold {k} of {n}")]
+    InvalidThreshold {
+        /// The threshold
+        k: usize,
+        /// The number of subexpressions or keys
+        n: usize,
+    },
+
+    /// The fragment is not available in this context
+    #[error("{0} is not allowed in {1:?} context")]
+    WrongContext(String, Context),
+
+    /// The expression does not type check
+    #[error("Type check failed: {0}")]
+    TypeCheck(String),
+
+    /// The satisfier does not hold enough signatures, preimages or timelocks
+    #[error("Could not satisfy the script with the available assets")]
+    Unsatisfiable,
+}
+
+/// Type alias for result with MiniscriptError
+pub type MiniscriptResult<T> = Result<T, MiniscriptError>;
+
+/// The script context a Miniscript is compiled for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Context {
+    /// A P2WSH witness script
+    Wsh,
+    /// A Tapscript leaf
+    Tap,
+}
+
+/// A node of a Miniscript expression. Sugared forms (`pk`, `pkh`, `t:`, `l:` and `u:`) are
+/// expanded while parsing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fragment {
+    /// `0`
+    False,
+    /// `1`
+    True,
+    /// `pk_k(key)`
+    PkK(Vec<u8>),
+    /// `pk_h(key)`
+    PkH(Vec<u8>),
+    /// `older(n)`
+    Older(u32),
+    /// `after(n)`
+    After(u32),
+    /// `sha256(h)`
+    Sha256(Vec<u8>),
+    /// `hash256(h)`
+    Hash256(Vec<u8>),
+    /// `ripemd160(h)`
+    Ripemd160(Vec<u8>),
+    /// `hash160(h)`
+    Hash160(Vec<u8>),
+    /// `andor(X,Y,Z)`
+    AndOr(Box<Fragment>, Box<Fragment>, Box<Fragment>),
+    /// `and_v(X,Y)`
+    AndV(Box<Fragment>, Box<Fragment>),
+    /// `and_b(X,Y)`
+    AndB(Box<Fragment>, Box<Fragment>),
+    /// `or_b(X,Z)`
+    OrB(Box<Fragment>, Box<Fragment>),
+    /// `or_c(X,Z)`
+    OrC(Box<Fragment>, Box<Fragment>),
+    /// `or_d(X,Z)`
+    OrD(Box<Fragment>, Box<Fragment>),
+    /// `or_i(X,Z)`
+    OrI(Box<Fragment>, Box<Fragment>),
+    /// `thresh(k,X1,...,Xn)`
+    Thresh(usize, Vec<Fragment>),
+    /// `multi(k,key1,...,keyn)`. P2WSH only
+    Multi(usize, Vec<Vec<u8>>),
+    /// `multi_a(k,key1,...,keyn)`. Tapscript only
+    MultiA(usize, Vec<Vec<u8>>),
+    /// `a:X`
+    Alt(Box<Fragment>),
+    /// `s:X`
+    Swap(Box<Fragment>),
+    /// `c:X`
+    Check(Box<Fragment>),
+    /// `d:X`
+    DupIf(Box<Fragment>),
+    /// `v:X`
+    Verify(Box<Fragment>),
+    /// `j:X`
+    NonZero(Box<Fragment>),
+    /// `n:X`
+    ZeroNotEqual(Box<Fragment>),
+}
+
+/// A parsed and type-checked Miniscript expression. The top-level expression is always of
+/// basic type B.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Miniscript {
+    root: Fragment,
+    ctx: Context,
+    ty: Type,
+}
+
+impl Miniscript {
+    /// Parse and type-check a Miniscript expression
+    pub fn parse(s: &str, ctx: Context) -> MiniscriptResult<Self> {
+        let tree = Tree::parse(s)?;
+        Self::from_fragment(tree.to_fragment(ctx, 0)?, ctx)
+    }
+
+    /// Type-check an expression
+    pub fn from_fragment(root: Fragment, ctx: Context) -> MiniscriptResult<Self> {
+        let ty = root.type_check(ctx)?;
+        if ty.base != Base::B {
+            return Err(MiniscriptError::TypeCheck(
+                "top-level expression must be of type B".to_owned(),
+            ));
+        }
+        Ok(Self { root, ctx, ty })
+    }
+
+    /// The root of the expression
+    pub fn root(&self) -> &Fragment {
+        &self.root
+    }
+
+    /// The script context
+    pub fn ctx(&self) -> Context {
+        self.ctx
+    }
+
+    /// The type of the expression
+    pub fn ty(&self) -> Type {
+        self.ty
+    }
+
+    /// Compile the expression to Script
+    pub fn to_script(&self) -> Script {
+        Script::new(self.root.encode())
+    }
+
+    /// The P2WSH output script committing to this expression. `None` for Tapscript, which must
+    /// be committed to as a leaf of a taproot tree
+    pub fn wsh_script_pubkey(&self) -> Option<ScriptPubkey> {
+        match self.ctx {
+            Context::Wsh => Some(ScriptPubkey::p2wsh(&self.to_script())),
+            Context::Tap => None,
+        }
+    }
+}
+
+impl fmt::Display for Miniscript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.root.fmt(f)
+    }
+}
+
+impl fmt::Display for Fragment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // collect the wrappers, then print the wrapped fragment after a single colon
+        let mut wrappers = String::new();
+        let mut node = self;
+        loop {
+            let (letter, inner) = match node {
+                Fragment::Alt(x) => ('a', x),
+                Fragment::Swap(x) => ('s', x),
+                Fragment::Check(x) => match x.as_ref() {
+                    Fragment::PkK(_) | Fragment::PkH(_) => break,
+                    _ => ('c', x),
+                },
+                Fragment::DupIf(x) => ('d', x),
+                Fragment::Verify(x) => ('v', x),
+                Fragment::NonZero(x) => ('j', x),
+                Fragment::ZeroNotEqual(x) => ('n', x),
+                _ => break,
+            };
+            wrappers.push(letter);
+            node = inner;
+        }
+        if !wrappers.is_empty() {
+            write!(f, "{}:", wrappers)?;
+        }
+
+        let keys = |keys: &[Vec<u8>]| -> String {
+            keys.iter().map(hex::encode).collect::<Vec<_>>().join(",")
+        };
+        match node {
+            Fragment::False => write!(f, "0"),
+            Fragment::True => write!(f, "1"),
+            Fragment::Check(x) => match x.as_ref() {
+                Fragment::PkK(k) => write!(f, "pk({})", hex::encode(k)),
+                Fragment::PkH(k) => write!(f, "pkh({})", hex::encode(k)),
+                _ => unreachable!("other checked fragments are printed as wrappers"),
+            },
+            Fragment::PkK(k) => write!(f, "pk_k({})", hex::encode(k)),
+            Fragment::PkH(k) => write!(f, "pk_h({})", hex::encode(k)),
+            Fragment::Older(n) => write!(f, "older({})", n),
+            Fragment::After(n) => write!(f, "after({})", n),
+            Fragment::Sha256(h) => write!(f, "sha256({})", hex::encode(h)),
+            Fragment::Hash256(h) => write!(f, "hash256({})", hex::encode(h)),
+            Fragment::Ripemd160(h) => write!(f, "ripemd160({})", hex::encode(h)),
+            Fragment::Hash160(h) => write!(f, "hash160({})", hex::encode(h)),
+            Fragment::AndOr(x, y, z) => write!(f, "andor({},{},{})", x, y, z),
+            Fragment::AndV(x, y) => write!(f, "and_v({},{})", x, y),
+            Fragment::AndB(x, y) => write!(f, "and_b({},{})", x, y),
+            Fragment::OrB(x, z) => write!(f, "or_b({},{})", x, z),
+            Fragment::OrC(x, z) => write!(f, "or_c({},{})", x, z),
+            Fragment::OrD(x, z) => write!(f, "or_d({},{})", x, z),
+            Fragment::OrI(x, z) => write!(f, "or_i({},{})", x, z),
+            Fragment::Thresh(k, subs) => {
+                write!(f, "thresh({}", k)?;
+                for sub in subs.iter() {
+                    write!(f, ",{}", sub)?;
+                }
+                write!(f, ")")
+            }
+            Fragment::Multi(k, ks) => write!(f, "multi({},{})", k, keys(ks)),
+            Fragment::MultiA(k, ks) => write!(f, "multi_a({},{})", k, keys(ks)),
+            _ => unreachable!("wrappers are collected above"),
+        }
+    }
+}
+
+/// An untyped expression tree: a name and its arguments
+struct Tree<'a> {
+    name: &'a str,
+    args: Vec<Tree<'a>>,
+}
+
+impl<'a> Tree<'a> {
+    fn parse(s: &'a str) -> MiniscriptResult<Self> {
+        let (tree, rest) = Self::parse_prefix(s, 0)?;
+        if !rest.is_empty() {
+            return Err(MiniscriptError::Malformed(format!(
+                "trailing characters: {}",
+                rest
+            )));
+        }
+        Ok(tree)
+    }
+
+    fn parse_prefix(s: &'a str, depth: usize) -> MiniscriptResult<(Self, &'a str)> {
+        if depth > MAX_RECURSION_DEPTH {
+            return Err(MiniscriptError::Malformed(format!(
+                "nested deeper than {}",
+                MAX_RECURSION_DEPTH
+            )));
+        }
+        let end = s.find(['(', ',', ')']).unwrap_or(s.len());
+        let name = &s[..end];
+        if name.is_empty() {
+            return Err(MiniscriptError::Malformed(format!(
+                "expected a fragment at: {}",
+                s
+            )));
+        }
+        let mut rest = &s[end..];
+        let mut args = vec![];
+        if rest.starts_with('(') {
+            rest = &rest[1..];
+            loop {
+                let (arg, r) = Self::parse_prefix(rest, depth + 1)?;
+                args.push(arg);
+                if let Some(r) = r.strip_prefix(',') {
+                    rest = r;
+                } else if let Some(r) = r.strip_prefix(')') {
+                    rest = r;
+                    break;
+                } else {
+                    return Err(MiniscriptError::Malformed(format!(
+                        "unclosed arguments of {}",
+                        name
+                    )));
+                }
+            }
+        }
+        Ok((Self { name, args }, rest))
+    }
+
+    fn expect_args(&self, name: &str, n: usize) -> MiniscriptResult<()> {
+        if self.args.len() != n {
+            return Err(MiniscriptError::WrongArgCount(name.to_owned(), n));
+        }
+        Ok(())
+    }
+
+    /// The name of an argument that should be a terminal, e.g. a key or a number
+    fn terminal(&self) -> MiniscriptResult<&'a str> {
+        if !self.args.is_empty() {
+            return Err(MiniscriptError::Malformed(format!(
+                "{} may not have arguments",
+                self.name
+            )));
+        }
+        Ok(self.name)
+    }
+
+    /// Convert the tree to a fragment. `depth` is the nesting depth of the tree, counting the
+    /// wrappers of its ancestors
+    fn to_fragment(&self, ctx: Context, depth: usize) -> MiniscriptResult<Fragment> {
+        let (wrappers, name) = match self.name.find(':') {
+            Some(idx) => (&self.name[..idx], &self.name[idx + 1..]),
+            None => ("", self.name),
+        };
+        // each wrapper nests the fragment one level deeper
+        let depth = depth + wrappers.len();
+        if depth > MAX_RECURSION_DEPTH {
+            return Err(MiniscriptError::Malformed(format!(
+                "nested deeper than {}",
+                MAX_RECURSION_DEPTH
+            )));
+        }
+
+        let subs = |n: usize| -> MiniscriptResult<Vec<Box<Fragment>>> {
+            self.expect_args(name, n)?;
+            self.args
+                .iter()
+                .map(|arg| arg.to_fragment(ctx, depth + 1).map(Box::new))
+                .collect()
+        };
+        let key = || -> MiniscriptResult<Vec<u8>> {
+            self.expect_args(name, 1)?;
+            parse_key(self.args[0].terminal()?, ctx)
+        };
+        let hash = |len: usize| -> MiniscriptResult<Vec<u8>> {
+            self.expect_args(name, 1)?;
+            let h = self.args[0].terminal()?;
+            match hex::decode(h) {
+                Ok(bytes) if bytes.len() == len => Ok(bytes),
+                _ => Err(MiniscriptError::InvalidHash(h.to_owned())),
+            }
+        };
+        let timelock = || -> MiniscriptResult<u32> {
+            self.expect_args(name, 1)?;
+            let n = self.args[0].terminal()?;
+            match n.parse::<u32>() {
+                Ok(v) if (1..0x8000_0000).contains(&v) => Ok(v),
+                _ => Err(MiniscriptError::InvalidTimelock(n.to_owned())),
+            }
+        };
+        let multi = |max: usize| -> MiniscriptResult<(usize, Vec<Vec<u8>>)> {
+            if self.args.len() < 2 {
+                return Err(MiniscriptError::Malformed(format!(
+                    "{} needs a threshold and keys",
+                    name
+                )));
+            }
+            let k = parse_threshold(self.args[0].terminal()?)?;
+            let keys = self.args[1..]
+                .iter()
+                .map(|arg| parse_key(arg.terminal()?, ctx))
+                .collect::<MiniscriptResult<Vec<_>>>()?;
+            check_threshold(k, keys.len(), max)?;
+            Ok((k, keys))
+        };
+
+        let mut fragment = match name {
+            "0" => {
+                self.expect_args(name, 0)?;
+                Fragment::False
+            }
+            "1" => {
+                self.expect_args(name, 0)?;
+                Fragment::True
+            }
+            "pk_k" => Fragment::PkK(key()?),
+            "pk_h" => Fragment::PkH(key()?),
+            "pk" => Fragment::Check(Box::new(Fragment::PkK(key()?))),
+            "pkh" => Fragment::Check(Box::new(Fragment::PkH(key()?))),
+            "older" => Fragment::Older(timelock()?),
+            "after" => Fragment::After(timelock()?),
+            "sha256" => Fragment::Sha256(hash(32)?),
+            "hash256" => Fragment::Hash256(hash(32)?),
+            "ripemd160" => Fragment::Ripemd160(hash(20)?),
+            "hash160" => Fragment::Hash160(hash(20)?),
+            "andor" => {
+                let mut s = subs(3)?.into_iter();
+                Fragment::AndOr(s.next().unwrap(), s.next().unwrap(), s.next().unwrap())
+            }
+            "and_v" | "and_b" | "or_b" | "or_c" | "or_d" | "or_i" => {
+                let mut s = subs(2)?.into_iter();
+                let (x, y) = (s.next().unwrap(), s.next().unwrap());
+                match name {
+                    "and_v" => Fragment::AndV(x, y),
+                    "and_b" => Fragment::AndB(x, y),
+                    "or_b" => Fragment::OrB(x, y),
+                    "or_c" => Fragment::OrC(x, y),
+                    "or_d" => Fragment::OrD(x, y),
+                    _ => Fragment::OrI(x, y),
+                }
+            }
+            "thresh" => {
+                if self.args.len() < 2 {
+                    return Err(MiniscriptError::Malformed(
+                        "thresh needs a threshold and subexpressions".to_owned(),
+                    ));
+                }
+                let k = parse_threshold(self.args[0].terminal()?)?;
+                let subs = self.args[1..]
+                    .iter()
+                    .map(|arg| arg.to_fragment(ctx, depth + 1))
+                    .collect::<MiniscriptResult<Vec<_>>>()?;
+                check_threshold(k, subs.len(), usize::MAX)?;
+                Fragment::Thresh(k, subs)
+            }
+            "multi" => {
+                if ctx != Context::Wsh {
+                    return Err(MiniscriptError::WrongContext(name.to_owned(), ctx));
+                }
+                let (k, keys) = multi(20)?;
+                Fragment::Multi(k, keys)
+            }
+            "multi_a" => {
+                if ctx != Context::Tap {
+                    return Err(MiniscriptError::WrongContext(name.to_owned(), ctx));
+                }
+                let (k, keys) = multi(999)?;
+                Fragment::MultiA(k, keys)
+            }
+            _ => return Err(MiniscriptError::UnknownFragment(name.to_owned())),
+        };
+
+        // the rightmost wrapper is applied first
+        for wrapper in wrappers.chars().rev() {
+            let x = Box::new(fragment);
+            fragment = match wrapper {
+                'a' => Fragment::Alt(x),
+                's' => Fragment::Swap(x),
+                'c' => Fragment::Check(x),
+                'd' => Fragment::DupIf(x),
+                'v' => Fragment::Verify(x),
+                'j' => Fragment::NonZero(x),
+                'n' => Fragment::ZeroNotEqual(x),
+                't' => Fragment::AndV(x, Box::new(Fragment::True)),
+                'l' => Fragment::OrI(Box::new(Fragment::False), x),
+                'u' => Fragment::OrI(x, Box::new(Fragment::False)),
+                _ => return Err(MiniscriptError::UnknownFragment(format!("{}:", wrapper))),
+            };
+        }
+        Ok(fragment)
+    }
+}
+
+fn parse_key(s: &str, ctx: Context) -> MiniscriptResult<Vec<u8>> {
+    let bytes = hex::decode(s).map_err(|_| MiniscriptError::InvalidKey(s.to_owned()))?;
+    let valid = match ctx {
+        Context::Wsh => bytes.len() == 33 && (bytes[0] == 0x02 || bytes[0] == 0x03),
+        Context::Tap => bytes.len() == 32,
+    };
+    if !valid {
+        return Err(MiniscriptError::InvalidKey(s.to_owned()));
+    }
+    Ok(bytes)
+}
+
+fn parse_threshold(s: &str) -> MiniscriptResult<usize> {
+    s.parse()
+        .map_err(|_| MiniscriptError::Malformed(format!("invalid threshold: {}", s)))
+}
+
+fn check_threshold(k: usize, n: usize, max: usize) -> MiniscriptResult<()> {
+    if k == 0 || k > n || n > max {
+        return Err(MiniscriptError::InvalidThreshold { k, n });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const K1: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const K2: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const K3: &str = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+
+    #[test]
+    fn it_parses_and_prints_expressions() {
+        let cases = [
+            format!("pk({})", K1),
+            format!("or_d(pk({}),and_v(v:pkh({}),older(144)))", K1, K2),
+            format!("thresh(2,pk({}),s:pk({}),s:or_i(0,n:older(1000)))", K1, K2),
+            format!("andor(pk({}),after(500000),multi(1,{},{}))", K1, K2, K3),
+            format!("and_b(pk({}),adv:older(1))", K1),
+        ];
+        for case in cases.iter() {
+            let ms = Miniscript::parse(case, Context::Wsh).unwrap();
+            assert_eq!(&ms.to_string(), case);
+        }
+
+        // sugar is expanded
+        let ms = Miniscript::parse(&format!("tv:pk({})", K1), Context::Wsh).unwrap();
+        assert_eq!(ms.to_string(), format!("and_v(v:pk({}),1)", K1));
+    }
+
+    #[test]
+    fn it_rejects_bad_expressions() {
+        let errs = [
+            (format!("pk({}", K1), Context::Wsh),
+            (format!("pk({})x", K1), Context::Wsh),
+            (format!("pk({})", &K1[2..]), Context::Wsh),
+            (format!("pk({})", K1), Context::Tap),
+            (format!("multi(1,{})", &K1[2..]), Context::Tap),
+            (format!("multi(3,{},{})", K1, K2), Context::Wsh),
+            ("older(0)".to_owned(), Context::Wsh),
+            ("sha256(00)".to_owned(), Context::Wsh),
+            ("foo(1)".to_owned(), Context::Wsh),
+            // v:X is not B
+            (format!("v:pk({})", K1), Context::Wsh),
+            // and_b needs a W on the right
+            (format!("and_b(pk({}),pk({}))", K1, K2), Context::Wsh),
+            // or_d needs a dissatisfiable, unit left side
+            (format!("or_d(older(1),pk({}))", K1), Context::Wsh),
+        ];
+        for (case, ctx) in errs.iter() {
+            assert!(Miniscript::parse(case, *ctx).is_err(), "{}", case);
+        }
+    }
+
+    #[test]
+    fn it_rejects_deep_nesting() {
+        let nested = |n: usize| format!("{}0{}", "and_v(v:1,".repeat(n), ")".repeat(n));
+        let ms = Miniscript::parse(&nested(100), Context::Wsh).unwrap();
+        assert_eq!(ms.to_string(), nested(100));
+
+        for n in [MAX_RECURSION_DEPTH + 1, 200_000].iter() {
+            assert!(matches!(
+                Miniscript::parse(&nested(*n), Context::Wsh),
+                Err(MiniscriptError::Malformed(_))
+            ));
+        }
+        let wrapped = format!("{}:0", "n".repeat(200_000));
+        assert!(matches!(
+            Miniscript::parse(&wrapped, Context::Wsh),
+            Err(MiniscriptError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn it_compiles_scripts() {
+        let ms =
+            Miniscript::parse(&format!("and_v(v:pk({}),pk({}))", K1, K2), Context::Wsh).unwrap();
+        let expected = format!("21{}ad21{}ac", K1, K2);
+        assert_eq!(hex::encode(ms.to_script().items()), expected);
+
+        let ms = Miniscript::parse(&format!("or_d(pk({}),older(144))", K1), Context::Wsh).unwrap();
+        let expected = format!("21{}ac73640290 00b268", K1).replace(' ', "");
+        assert_eq!(hex::encode(ms.to_script().items()), expected);
+        assert!(ms.wsh_script_pubkey().is_some());
+
+        let ms =
+            Miniscript::parse(&format!("multi(2,{},{},{})", K1, K2, K3), Context::Wsh).unwrap();
+        let expected = format!("5221{}21{}21{}53ae", K1, K2, K3);
+        assert_eq!(hex::encode(ms.to_script().items()), expected);
+
+        let x1 = &K1[2..];
+        let x2 = &K2[2..];
+        let ms = Miniscript::parse(&format!("multi_a(1,{},{})", x1, x2), Context::Tap).unwrap();
+        let expected = format!("20{}ac20{}ba519c", x1, x2);
+        assert_eq!(hex::encode(ms.to_script().items()), expected);
+        assert!(ms.wsh_script_pubkey().is_none());
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+
+use crate::{
+    miniscript::{Fragment, Miniscript, MiniscriptError, MiniscriptResult},
+    types::{
+        locktime::LockTime,
+        script::{Witness, WitnessStackItem},
+    },
+};
+
+/// Provides the signatures, preimages and timelock information needed to satisfy a Miniscript
+pub trait Satisfier {
+    /// A signature by the key, with its sighash flag appended
+    fn lookup_sig(&self, _key: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// The 32-byte preimage of a hash, as it appears in the expression
+    fn lookup_preimage(&self, _hash: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// True if the spending input's sequence number satisfies `older(n)`
+    fn check_older(&self, _n: u32) -> bool {
+        false
+    }
+
+    /// True if the spending transaction's locktime satisfies `after(n)`
+    fn check_after(&self, _n: u32) -> bool {
+        false
+    }
+}
+
+/// A simple `Satisfier` that holds its assets in memory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assets {
+    /// Signatures, keyed by public key
+    pub sigs: HashMap<Vec<u8>, Vec<u8>>,
+    /// Preimages, keyed by hash
+    pub preimages: HashMap<Vec<u8>, Vec<u8>>,
+    /// The sequence number of the spending input
+    pub sequence: u32,
+    /// The locktime of the spending transaction
+    pub locktime: u32,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Self {
+            sigs: Default::default(),
+            preimages: Default::default(),
+            sequence: 0xffff_ffff,
+            locktime: 0,
+        }
+    }
+}
+
+impl Satisfier for Assets {
+    fn lookup_sig(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.sigs.get(key).cloned()
+    }
+
+    fn lookup_preimage(&self, hash: &[u8]) -> Option<Vec<u8>> {
+        self.preimages.get(hash).cloned()
+    }
+
+    fn check_older(&self, n: u32) -> bool {
+        const DISABLE_FLAG: u32 = 1 << 31;
+        const TYPE_FLAG: u32 = 1 << 22;
+        const MASK: u32 = 0x0000_ffff;
+        if self.sequence & DISABLE_FLAG != 0 {
+            return false;
+        }
+        // heights and times can not be compared
+        if (self.sequence & TYPE_FLAG) != (n & TYPE_FLAG) {
+            return false;
+        }
+        self.sequence & MASK >= n & MASK
+    }
+
+    fn check_after(&self, n: u32) -> bool {
+        LockTime::from_consensus(n).is_satisfied_by(LockTime::from_consensus(self.locktime))
+    }
+}
+
+/// A witness stack under construction, bottom first
+type Stack = Vec<Vec<u8>>;
+
+fn size(stack: &Stack) -> usize {
+    stack.iter().map(|item| item.len() + 1).sum()
+}
+
+/// Concatenate two stacks. `top` is consumed first by the script
+fn cat(bottom: Option<Stack>, top: Option<Stack>) -> Option<Stack> {
+    let mut stack = bottom?;
+    stack.extend(top?);
+    Some(stack)
+}
+
+/// The smaller of two stacks
+fn min(a: Option<Stack>, b: Option<Stack>) -> Option<Stack> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if size(&b) < size(&a) { b } else { a }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn push(item: Vec<u8>) -> Option<Stack> {
+    Some(vec![item])
+}
+
+fn empty() -> Option<Stack> {
+    Some(vec![])
+}
+
+/// A satisfaction and dissatisfaction of an expression, if they exist
+struct Sat {
+    sat: Option<Stack>,
+    dsat: Option<Stack>,
+}
+
+impl Fragment {
+    fn satisfy_inner<S: Satisfier>(&self, s: &S) -> Sat {
+        let (sat, dsat) = match self {
+            Fragment::False => (None, empty()),
+            Fragment::True => (empty(), None),
+            Fragment::PkK(key) => (s.lookup_sig(key).map(|sig| vec![sig]), push(vec![])),
+            Fragment::PkH(key) => (
+                s.lookup_sig(key).map(|sig| vec![sig, key.clone()]),
+                Some(vec![vec![], key.clone()]),
+            ),
+            Fragment::Older(n) => (if s.check_older(*n) { empty() } else { None }, None),
+            Fragment::After(n) => (if s.check_after(*n) { empty() } else { None }, None),
+            Fragment::Sha256(h)
+            | Fragment::Hash256(h)
+            | Fragment::Ripemd160(h)
+            | Fragment::Hash160(h) => (
+                s.lookup_preimage(h)
+                    .filter(|p| p.len() == 32)
+                    .map(|p| vec![p]),
+                push(vec![0; 32]),
+            ),
+            Fragment::AndOr(x, y, z) => {
+                let (x, y, z) = (x.satisfy_inner(s), y.satisfy_inner(s), z.satisfy_inner(s));
+                (
+                    min(cat(y.sat, x.sat), cat(z.sat, x.dsat.clone())),
+                    cat(z.dsat, x.dsat),
+                )
+            }
+            Fragment::AndV(x, y) => {
+                let (x, y) = (x.satisfy_inner(s), y.satisfy_inner(s));
+                (cat(y.sat, x.sat), None)
+            }
+            Fragment::AndB(x, y) => {
+                let (x, y) = (x.satisfy_inner(s), y.satisfy_inner(s));
+                (cat(y.sat, x.sat), cat(y.dsat, x.dsat))
+            }
+            Fragment::OrB(x, z) => {
+                let (x, z) = (x.satisfy_inner(s), z.satisfy_inner(s));
+                (
+                    min(cat(z.dsat.clone(), x.sat), cat(z.sat, x.dsat.clone())),
+                    cat(z.dsat, x.dsat),
+                )
+            }
+            Fragment::OrC(x, z) => {
+                let (x, z) = (x.satisfy_inner(s), z.satisfy_inner(s));
+                (min(x.sat, cat(z.sat, x.dsat)), None)
+            }
+            Fragment::OrD(x, z) => {
+                let (x, z) = (x.satisfy_inner(s), z.satisfy_inner(s));
+                (min(x.sat, cat(z.sat, x.dsat.clone())), cat(z.dsat, x.dsat))
+            }
+            Fragment::OrI(x, z) => {
+                let (x, z) = (x.satisfy_inner(s), z.satisfy_inner(s));
+                (
+                    min(cat(x.sat, push(vec![1])), cat(z.sat, push(vec![]))),
+                    min(cat(x.dsat, push(vec![1])), cat(z.dsat, push(vec![]))),
+                )
+            }
+            Fragment::Thresh(k, subs) => {
+                let subs: Vec<Sat> = subs.iter().map(|sub| sub.satisfy_inner(s)).collect();
+                (thresh_sat(*k, &subs), thresh_dsat(&subs))
+            }
+            Fragment::Multi(k, keys) => {
+                let sigs: Vec<Vec<u8>> = keys
+                    .iter()
+                    .filter_map(|key| s.lookup_sig(key))
+                    .take(*k)
+                    .collect();
+                let sat = if sigs.len() == *k {
+                    let mut stack = vec![vec![]];
+                    stack.extend(sigs);
+                    Some(stack)
+                } else {
+                    None
+                };
+                (sat, Some(vec![vec![]; k + 1]))
+            }
+            Fragment::MultiA(k, keys) => {
+                // the first key's signature is on top of the stack
+                let mut found = 0;
+                let mut stack: Stack = keys
+                    .iter()
+                    .map(|key| match s.lookup_sig(key) {
+                        Some(sig) if found < *k => {
+                            found += 1;
+                            sig
+                        }
+                        _ => vec![],
+                    })
+                    .collect();
+                stack.reverse();
+                (
+                    if found == *k { Some(stack) } else { None },
+                    Some(vec![vec![]; keys.len()]),
+                )
+            }
+            Fragment::Alt(x)
+            | Fragment::Swap(x)
+            | Fragment::Check(x)
+            | Fragment::ZeroNotEqual(x) => {
+                let x = x.satisfy_inner(s);
+                (x.sat, x.dsat)
+            }
+            Fragment::DupIf(x) => (cat(x.satisfy_inner(s).sat, push(vec![1])), push(vec![])),
+            Fragment::Verify(x) => (x.satisfy_inner(s).sat, None),
+            Fragment::NonZero(x) => (x.satisfy_inner(s).sat, push(vec![])),
+        };
+        Sat { sat, dsat }
+    }
+}
+
+/// Satisfy exactly `k` subexpressions, and dissatisfy the rest, as cheaply as possible
+fn thresh_sat(k: usize, subs: &[Sat]) -> Option<Stack> {
+    // subexpressions that can not be dissatisfied must be satisfied
+    let mut chosen: Vec<bool> = subs.iter().map(|sub| sub.dsat.is_none()).collect();
+    if subs
+        .iter()
+        .any(|sub| sub.sat.is_none() && sub.dsat.is_none())
+    {
+        return None;
+    }
+    let forced = chosen.iter().filter(|c| **c).count();
+    if forced > k {
+        return None;
+    }
+
+    let mut candidates: Vec<(usize, isize)> = subs
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !chosen[*i])
+        .filter_map(|(i, sub)| {
+            let sat = size(sub.sat.as_ref()?) as isize;
+            let dsat = size(sub.dsat.as_ref()?) as isize;
+            Some((i, sat - dsat))
+        })
+        .collect();
+    if candidates.len() < k - forced {
+        return None;
+    }
+    candidates.sort_by_key(|(_, cost)| *cost);
+    for (i, _) in candidates.into_iter().take(k - forced) {
+        chosen[i] = true;
+    }
+
+    // X1 runs first, so its witness goes on top
+    let mut stack = vec![];
+    for (sub, chosen) in subs.iter().zip(chosen.iter()).rev() {
+        let witness = if *chosen { &sub.sat } else { &sub.dsat };
+        stack.extend(witness.clone()?);
+    }
+    Some(stack)
+}
+
+fn thresh_dsat(subs: &[Sat]) -> Option<Stack> {
+    let mut stack = vec![];
+    for sub in subs.iter().rev() {
+        stack.extend(sub.dsat.clone()?);
+    }
+    Some(stack)
+}
+
+impl Miniscript {
+    /// Build the smallest witness stack satisfying the expression from the assets available to
+    /// the satisfier. The stack does not include the script itself. Append the witness script
+    /// for P2WSH, or the script and control block for a Tapscript spend.
+    pub fn satisfy<S: Satisfier>(&self, satisfier: &S) -> MiniscriptResult<Witness> {
+        let stack = self
+            .root()
+            .satisfy_inner(satisfier)
+            .sat
+            .ok_or(MiniscriptError::Unsatisfiable)?;
+        Ok(stack.into_iter().map(WitnessStackItem::new).collect())
+    }
+
+    /// Build a complete P2WSH witness: the satisfaction followed by the witness script
+    pub fn satisfy_wsh<S: Satisfier>(&self, satisfier: &S) -> MiniscriptResult<Witness> {
+        let mut witness = self.satisfy(satisfier)?;
+        witness.push(WitnessStackItem::new(self.to_script().items().to_vec()));
+        Ok(witness)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::miniscript::Context;
+
+    const K1: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const K2: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const K3: &str = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+
+    fn sig(n: u8) -> Vec<u8> {
+        vec![0x30, n, 0x01]
+    }
+
+    fn items(witness: &Witness) -> Vec<Vec<u8>> {
+        witness.iter().map(|item| item.items().to_vec()).collect()
+    }
+
+    #[test]
+    fn it_satisfies_timelocked_recovery() {
+        let ms = Miniscript::parse(
+            &format!("or_d(pk({}),and_v(v:pk({}),older(144)))", K1, K2),
+            Context::Wsh,
+        )
+        .unwrap();
+        let k1 = hex::decode(K1).unwrap();
+        let k2 = hex::decode(K2).unwrap();
+
+        let mut assets = Assets::default();
+        assert_eq!(ms.satisfy(&assets), Err(MiniscriptError::Unsatisfiable));
+
+        // the primary key spends alone
+        assets.sigs.insert(k1, sig(1));
+        assert_eq!(items(&ms.satisfy(&assets).unwrap()), vec![sig(1)]);
+
+        // the recovery key needs the delay
+        assets.sigs.clear();
+        assets.sigs.insert(k2, sig(2));
+        assert!(ms.satisfy(&assets).is_err());
+        assets.sequence = 144;
+        assert_eq!(items(&ms.satisfy(&assets).unwrap()), vec![sig(2), vec![]]);
+
+        let witness = ms.satisfy_wsh(&assets).unwrap();
+        assert_eq!(witness.last().unwrap().items(), ms.to_script().items());
+    }
+
+    #[test]
+    fn it_satisfies_thresholds() {
+        // 2-of-3, where the third key only counts after a delay
+        let ms = Miniscript::parse(
+            &format!("thresh(2,pk({}),s:pk({}),sln:older(1000))", K1, K2),
+            Context::Wsh,
+        )
+        .unwrap();
+        let mut assets = Assets::default();
+        assets.sigs.insert(hex::decode(K2).unwrap(), sig(2));
+        assert!(ms.satisfy(&assets).is_err());
+
+        assets.sequence = 1000;
+        assert_eq!(
+            items(&ms.satisfy(&assets).unwrap()),
+            vec![vec![], sig(2), vec![]]
+        );
+
+        let ms =
+            Miniscript::parse(&format!("multi(2,{},{},{})", K1, K2, K3), Context::Wsh).unwrap();
+        assets.sigs.insert(hex::decode(K3).unwrap(), sig(3));
+        assert_eq!(
+            items(&ms.satisfy(&assets).unwrap()),
+            vec![vec![], sig(2), sig(3)]
+        );
+
+        let x1 = &K1[2..];
+        let x2 = &K2[2..];
+        let ms = Miniscript::parse(&format!("multi_a(1,{},{})", x1, x2), Context::Tap).unwrap();
+        let mut assets = Assets::default();
+        assets.sigs.insert(hex::decode(x2).unwrap(), sig(2));
+        assert_eq!(items(&ms.satisfy(&assets).unwrap()), vec![sig(2), vec![]]);
+    }
+
+    #[test]
+    fn it_checks_timelocks() {
+        let mut assets = Assets {
+            sequence: 10,
+            locktime: 600_000,
+            ..Default::default()
+        };
+        assert!(assets.check_older(10));
+        assert!(!assets.check_older(11));
+        assert!(!assets.check_older(10 | (1 << 22)));
+        assert!(assets.check_after(500_000));
+        assert!(!assets.check_after(500_000_001));
+        assets.sequence |= 1 << 31;
+        assert!(!assets.check_older(1));
+    }
+}
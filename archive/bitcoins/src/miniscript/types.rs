@@ -0,0 +1,310 @@
+use crate::miniscript::{Context, Fragment, MiniscriptError, MiniscriptResult};
+
+/// The basic type of a Miniscript expression
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Base {
+    /// Base: pushes a nonzero value on satisfaction, and an exact 0 on dissatisfaction
+    B,
+    /// Verify: continues on satisfaction, and cannot be dissatisfied
+    V,
+    /// Key: pushes a public key, to be checked against a signature
+    K,
+    /// Wrapped: takes its inputs from one below the top of the stack
+    W,
+}
+
+/// The correctness type of a Miniscript expression: its basic type and properties
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Type {
+    /// The basic type
+    pub base: Base,
+    /// Zero-arg: always consumes exactly 0 stack elements
+    pub z: bool,
+    /// One-arg: always consumes exactly 1 stack element
+    pub o: bool,
+    /// Nonzero: satisfactions never need a zero top stack element
+    pub n: bool,
+    /// Dissatisfiable: has an unconditional dissatisfaction
+    pub d: bool,
+    /// Unit: pushes exactly 1 on satisfaction
+    pub u: bool,
+}
+
+impl Type {
+    fn new(base: Base) -> Self {
+        Self {
+            base,
+            z: false,
+            o: false,
+            n: false,
+            d: false,
+            u: false,
+        }
+    }
+}
+
+fn require(cond: bool, fragment: &str, msg: &str) -> MiniscriptResult<()> {
+    if !cond {
+        return Err(MiniscriptError::TypeCheck(format!("{}: {}", fragment, msg)));
+    }
+    Ok(())
+}
+
+impl Fragment {
+    /// Check the expression against the correctness type system, and return its type
+    pub fn type_check(&self, ctx: Context) -> MiniscriptResult<Type> {
+        use Base::*;
+        let ty = match self {
+            Fragment::False => Type {
+                z: true,
+                u: true,
+                d: true,
+                ..Type::new(B)
+            },
+            Fragment::True => Type {
+                z: true,
+                u: true,
+                ..Type::new(B)
+            },
+            Fragment::PkK(_) => Type {
+                o: true,
+                n: true,
+                d: true,
+                u: true,
+                ..Type::new(K)
+            },
+            Fragment::PkH(_) => Type {
+                n: true,
+                d: true,
+                u: true,
+                ..Type::new(K)
+            },
+            Fragment::Older(_) | Fragment::After(_) => Type {
+                z: true,
+                ..Type::new(B)
+            },
+            Fragment::Sha256(_)
+            | Fragment::Hash256(_)
+            | Fragment::Ripemd160(_)
+            | Fragment::Hash160(_) => Type {
+                o: true,
+                n: true,
+                d: true,
+                u: true,
+                ..Type::new(B)
+            },
+            Fragment::Multi(..) => Type {
+                n: true,
+                d: true,
+                u: true,
+                ..Type::new(B)
+            },
+            Fragment::MultiA(..) => Type {
+                d: true,
+                u: true,
+                ..Type::new(B)
+            },
+            Fragment::AndOr(x, y, z) => {
+                let (x, y, z) = (x.type_check(ctx)?, y.type_check(ctx)?, z.type_check(ctx)?);
+                require(x.base == B && x.d && x.u, "andor", "X must be Bdu")?;
+                require(
+                    y.base == z.base && y.base != W,
+                    "andor",
+                    "Y and Z must both be B, K or V",
+                )?;
+                Type {
+                    base: y.base,
+                    z: x.z && y.z && z.z,
+                    o: (x.z && y.o && z.o) || (x.o && y.z && z.z),
+                    n: false,
+                    d: z.d,
+                    u: y.u && z.u,
+                }
+            }
+            Fragment::AndV(x, y) => {
+                let (x, y) = (x.type_check(ctx)?, y.type_check(ctx)?);
+                require(x.base == V, "and_v", "X must be V")?;
+                require(y.base != W, "and_v", "Y must be B, K or V")?;
+                Type {
+                    base: y.base,
+                    z: x.z && y.z,
+                    o: (x.z && y.o) || (x.o && y.z),
+                    n: x.n || (x.z && y.n),
+                    d: false,
+                    u: y.u,
+                }
+            }
+            Fragment::AndB(x, y) => {
+                let (x, y) = (x.type_check(ctx)?, y.type_check(ctx)?);
+                require(x.base == B, "and_b", "X must be B")?;
+                require(y.base == W, "and_b", "Y must be W")?;
+                Type {
+                    base: B,
+                    z: x.z && y.z,
+                    o: (x.z && y.o) || (x.o && y.z),
+                    n: x.n || (x.z && y.n),
+                    d: x.d && y.d,
+                    u: true,
+                }
+            }
+            Fragment::OrB(x, z) => {
+                let (x, z) = (x.type_check(ctx)?, z.type_check(ctx)?);
+                require(x.base == B && x.d, "or_b", "X must be Bd")?;
+                require(z.base == W && z.d, "or_b", "Z must be Wd")?;
+                Type {
+                    base: B,
+                    z: x.z && z.z,
+                    o: (x.z && z.o) || (x.o && z.z),
+                    n: false,
+                    d: true,
+                    u: true,
+                }
+            }
+            Fragment::OrC(x, z) => {
+                let (x, z) = (x.type_check(ctx)?, z.type_check(ctx)?);
+                require(x.base == B && x.d && x.u, "or_c", "X must be Bdu")?;
+                require(z.base == V, "or_c", "Z must be V")?;
+                Type {
+                    base: V,
+                    z: x.z && z.z,
+                    o: x.o && z.z,
+                    ..Type::new(V)
+                }
+            }
+            Fragment::OrD(x, z) => {
+                let (x, z) = (x.type_check(ctx)?, z.type_check(ctx)?);
+                require(x.base == B && x.d && x.u, "or_d", "X must be Bdu")?;
+                require(z.base == B, "or_d", "Z must be B")?;
+                Type {
+                    base: B,
+                    z: x.z && z.z,
+                    o: x.o && z.z,
+                    n: false,
+                    d: z.d,
+                    u: z.u,
+                }
+            }
+            Fragment::OrI(x, z) => {
+                let (x, z) = (x.type_check(ctx)?, z.type_check(ctx)?);
+                require(
+                    x.base == z.base && x.base != W,
+                    "or_i",
+                    "X and Z must both be B, K or V",
+                )?;
+                Type {
+                    base: x.base,
+                    z: false,
+                    o: x.z && z.z,
+                    n: false,
+                    d: x.d || z.d,
+                    u: x.u && z.u,
+                }
+            }
+            Fragment::Thresh(_, subs) => {
+                let mut z = true;
+                let mut args = 0;
+                for (i, sub) in subs.iter().enumerate() {
+                    let t = sub.type_check(ctx)?;
+                    let base = if i == 0 { B } else { W };
+                    require(
+                        t.base == base && t.d && t.u,
+                        "thresh",
+                        "X1 must be Bdu, and the rest Wdu",
+                    )?;
+                    z &= t.z;
+                    if !t.z {
+                        args += if t.o { 1 } else { 2 };
+                    }
+                }
+                Type {
+                    base: B,
+                    z,
+                    o: args == 1,
+                    n: false,
+                    d: true,
+                    u: true,
+                }
+            }
+            Fragment::Alt(x) => {
+                let x = x.type_check(ctx)?;
+                require(x.base == B, "a:", "X must be B")?;
+                Type {
+                    d: x.d,
+                    u: x.u,
+                    ..Type::new(W)
+                }
+            }
+            Fragment::Swap(x) => {
+                let x = x.type_check(ctx)?;
+                require(x.base == B && x.o, "s:", "X must be Bo")?;
+                Type {
+                    d: x.d,
+                    u: x.u,
+                    ..Type::new(W)
+                }
+            }
+            Fragment::Check(x) => {
+                let x = x.type_check(ctx)?;
+                require(x.base == K, "c:", "X must be K")?;
+                Type {
+                    base: B,
+                    z: false,
+                    o: x.o,
+                    n: x.n,
+                    d: x.d,
+                    u: true,
+                }
+            }
+            Fragment::DupIf(x) => {
+                let x = x.type_check(ctx)?;
+                require(x.base == V && x.z, "d:", "X must be Vz")?;
+                Type {
+                    base: B,
+                    z: false,
+                    o: true,
+                    n: true,
+                    d: true,
+                    // MINIMALIF makes the output a unit in tapscript
+                    u: ctx == Context::Tap,
+                }
+            }
+            Fragment::Verify(x) => {
+                let x = x.type_check(ctx)?;
+                require(x.base == B, "v:", "X must be B")?;
+                Type {
+                    base: V,
+                    z: x.z,
+                    o: x.o,
+                    n: x.n,
+                    d: false,
+                    u: false,
+                }
+            }
+            Fragment::NonZero(x) => {
+                let x = x.type_check(ctx)?;
+                require(x.base == B && x.n, "j:", "X must be Bn")?;
+                Type {
+                    base: B,
+                    z: false,
+                    o: x.o,
+                    n: true,
+                    d: true,
+                    u: x.u,
+                }
+            }
+            Fragment::ZeroNotEqual(x) => {
+                let x = x.type_check(ctx)?;
+                require(x.base == B, "n:", "X must be B")?;
+                Type {
+                    base: B,
+                    z: x.z,
+                    o: x.o,
+                    n: x.n,
+                    d: x.d,
+                    u: true,
+                }
+            }
+        };
+        Ok(ty)
+    }
+}
@@ -5,7 +5,7 @@
 //! This gives the user immediate access to the full bitcoin toolchain via a single import.
 //!
 //! ```
-//! use bitcoins::{BitcoinMainnet, enc::Address, types::Outpoint};
+//! use bitcoins::{BitcoinMainnet, enc::Address, types::{Amount, Outpoint}};
 //! use coins_core::{
 //!     nets::Network,
 //!     builder::TxBuilder,
@@ -17,8 +17,8 @@
 //! let b = BitcoinMainnet::tx_builder();
 //! b.version(2)
 //!  .spend(Outpoint::default(), 0xaabbccdd)
-//!  .pay(0x8888_8888_8888_8888, &address)
-//!  .pay(0x7777_7777_7777_7777, &Address::Sh("377mKFYsaJPsxYSB5aFfx8SW3RaN5BzZVh".to_owned()))
+//!  .pay(Amount::from_sat(0x8888_8888_8888_8888), &address)
+//!  .pay(Amount::from_sat(0x7777_7777_7777_7777), &Address::Sh("377mKFYsaJPsxYSB5aFfx8SW3RaN5BzZVh".to_owned()))
 //!  .build()
 //!  .unwrap()
 //!  .serialize_hex();
@@ -36,7 +36,10 @@ use coins_core::{
 
 use crate::{
     builder::BitcoinTxBuilder,
-    enc::encoder::{Address, BitcoinEncoderMarker, MainnetEncoder, SignetEncoder, TestnetEncoder},
+    enc::encoder::{
+        Address, BitcoinEncoderMarker, MainnetEncoder, RegtestEncoder, SignetEncoder,
+        TestnetEncoder,
+    },
     types::{
         BitcoinTransaction, BitcoinTx, BitcoinTxIn, ScriptPubkey, TxOut, WitnessTransaction,
         WitnessTx,
@@ -51,7 +54,7 @@ pub trait BitcoinNetwork: Network {
 }
 
 /// A newtype for Bitcoin networks, parameterized by an encoder. We change the encoder to
-/// differentiate between main, test, and signet.
+/// differentiate between main, test, signet, and regtest.
 #[derive(Debug)]
 pub struct Bitcoin<T: AddressEncoder>(PhantomData<fn(T) -> T>);
 
@@ -85,11 +88,14 @@ pub type BitcoinTestnet = Bitcoin<TestnetEncoder>;
 /// A fully-parameterized BitcoinSignet. This is the main interface for accessing the library.
 pub type BitcoinSignet = Bitcoin<SignetEncoder>;
 
+/// A fully-parameterized BitcoinRegtest, for local test networks.
+pub type BitcoinRegtest = Bitcoin<RegtestEncoder>;
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::types::txin::BitcoinOutpoint;
-    use coins_core::{builder::TxBuilder, ser::ByteFormat};
+    use crate::types::{amount::Amount, txin::BitcoinOutpoint};
+    use coins_core::{builder::TxBuilder, ser::ByteFormat, types::tx::Transaction};
 
     #[test]
     fn it_has_sensible_syntax() {
@@ -97,11 +103,11 @@ mod test {
             .version(2)
             .spend(BitcoinOutpoint::default(), 0xaabbccdd)
             .pay(
-                0x8888_8888_8888_8888,
+                Amount::from_sat(0x8888_8888_8888_8888),
                 &Address::Wpkh("bc1qvyyvsdcd0t9863stt7u9rf37wx443lzasg0usy".to_owned()),
             )
             .pay(
-                0x7777_7777_7777_7777,
+                Amount::from_sat(0x7777_7777_7777_7777),
                 &Address::Sh("377mKFYsaJPsxYSB5aFfx8SW3RaN5BzZVh".to_owned()),
             )
             .build()
@@ -111,6 +117,241 @@ mod test {
         // println!("{:?}", b);
     }
 
+    #[test]
+    fn it_builds_funded_txs() {
+        use crate::{
+            builder::BuilderError,
+            coinselect::CoinSelectError,
+            types::{FeeRate, SpendScript, Utxo},
+        };
+
+        let change = Address::Wpkh("bc1qvyyvsdcd0t9863stt7u9rf37wx443lzasg0usy".to_owned());
+        let utxo = Utxo::new(
+            BitcoinOutpoint::default(),
+            Amount::from_sat(100_000),
+            BitcoinMainnet::decode_address(&change),
+            SpendScript::None,
+        );
+        let builder = BitcoinMainnet::tx_builder()
+            .version(2)
+            .pay(Amount::from_sat(50_000), &change);
+
+        let tx = builder
+            .clone()
+            .fee_rate(FeeRate(2.0))
+            .change_address(&change)
+            .build_funded(std::slice::from_ref(&utxo))
+            .unwrap();
+        assert!(tx.is_witness());
+        assert_eq!(tx.inputs().len(), 1);
+        assert_eq!(tx.outputs().len(), 2);
+        // 562 WU at 2 sat/vB is 281 sat
+        assert_eq!(tx.outputs()[1].value.to_sat(), 100_000 - 50_000 - 281);
+
+        let tx = builder
+            .clone()
+            .op_return(&[0xaa; 80])
+            .unwrap()
+            .fee_rate(FeeRate(2.0))
+            .change_address(&change)
+            .build_funded(std::slice::from_ref(&utxo))
+            .unwrap();
+        assert_eq!(
+            tx.outputs()[1].extract_op_return_data(),
+            Some(vec![0xaa; 80])
+        );
+        match builder.clone().op_return(&[0xaa; 81]) {
+            Err(BuilderError::OpReturnTooLarge(81)) => {}
+            _ => panic!("expected oversized op_return"),
+        }
+
+        match builder.clone().change_address(&change).build_funded(&[]) {
+            Err(BuilderError::MissingFeeRate) => {}
+            _ => panic!("expected missing feerate"),
+        }
+        match builder
+            .pay(Amount::from_sat(60_000), &change)
+            .fee_rate(FeeRate(2.0))
+            .change_address(&change)
+            .build_funded(&[utxo])
+        {
+            Err(BuilderError::CoinSelectError(CoinSelectError::InsufficientFunds { .. })) => {}
+            _ => panic!("expected insufficient funds"),
+        }
+    }
+
+    #[test]
+    fn it_sets_rbf() {
+        use crate::types::{locktime::LockTime, tx::BitcoinTransaction, txin::RBF_SEQUENCE};
+
+        let address = Address::Wpkh("bc1qvyyvsdcd0t9863stt7u9rf37wx443lzasg0usy".to_owned());
+        let builder = BitcoinMainnet::tx_builder()
+            .version(2)
+            .spend(BitcoinOutpoint::default(), 0xffff_ffff)
+            .pay(Amount::from_sat(1000), &address);
+
+        let tx = builder.clone().build().unwrap();
+        assert!(!tx.signals_rbf());
+        assert_eq!(tx.lock_time(), LockTime::BlockHeight(0));
+
+        let locktime = LockTime::from_time(1_600_000_000).unwrap();
+        let tx = builder.clone().set_locktime(locktime).build().unwrap();
+        assert_eq!(tx.locktime(), 1_600_000_000);
+        assert_eq!(tx.lock_time(), locktime);
+
+        let tx = builder.clone().set_rbf(true).build().unwrap();
+        assert!(tx.signals_rbf());
+        assert_eq!(tx.inputs()[0].sequence, RBF_SEQUENCE);
+
+        let tx = builder
+            .clone()
+            .spend_replaceable(BitcoinOutpoint::new(Default::default(), 1))
+            .build_witness()
+            .unwrap();
+        assert!(tx.signals_rbf());
+        assert!(!tx.inputs()[0].signals_rbf());
+
+        // a relative timelock of 10 blocks always signals
+        let tx = builder
+            .spend(BitcoinOutpoint::new(Default::default(), 1), 10)
+            .spend_replaceable(BitcoinOutpoint::new(Default::default(), 2))
+            .set_rbf(false)
+            .build()
+            .unwrap();
+        let sequences: Vec<u32> = tx.inputs().iter().map(|i| i.sequence).collect();
+        assert_eq!(sequences, vec![0xffff_ffff, 10, 0xffff_fffe]);
+        assert!(tx.signals_rbf());
+    }
+
+    #[test]
+    fn it_sorts_bip69() {
+        use crate::{hashes::TXID, types::script::Witness};
+        use coins_core::hashes::MarkedDigestOutput;
+
+        // BIP69 compares txids in big-endian order, so `high` sorts after `low`
+        let mut low = TXID::default();
+        low.as_mut_slice()[0] = 0xff;
+        let mut high = TXID::default();
+        high.as_mut_slice()[31] = 0x01;
+
+        let address = Address::Wpkh("bc1qvyyvsdcd0t9863stt7u9rf37wx443lzasg0usy".to_owned());
+        let script = BitcoinMainnet::decode_address(&address);
+        let builder = BitcoinMainnet::tx_builder()
+            .version(2)
+            .spend(BitcoinOutpoint::new(high, 0), 0)
+            .spend(BitcoinOutpoint::new(low, 1), 1)
+            .spend(BitcoinOutpoint::new(low, 0), 2)
+            .extend_witnesses(vec![Witness::default(), vec![vec![1u8].into()]])
+            .pay(Amount::from_sat(2000), &address)
+            .pay_script_pubkey(Amount::from_sat(1000), script.clone())
+            .op_return(&[1])
+            .unwrap();
+
+        let tx = builder.clone().sort_bip69().build().unwrap();
+        let sequences: Vec<u32> = tx.inputs().iter().map(|i| i.sequence).collect();
+        assert_eq!(sequences, vec![2, 1, 0]);
+        assert_eq!(tx.witnesses()[1].len(), 1);
+        let values: Vec<u64> = tx.outputs().iter().map(|o| o.value.to_sat()).collect();
+        assert_eq!(values, vec![0, 1000, 2000]);
+
+        let tx = builder
+            .set_bip69(true)
+            .spend(BitcoinOutpoint::new(low, 2), 3)
+            .build()
+            .unwrap();
+        let sequences: Vec<u32> = tx.inputs().iter().map(|i| i.sequence).collect();
+        assert_eq!(sequences, vec![2, 1, 3, 0]);
+    }
+
+    #[test]
+    fn it_pays_many() {
+        use crate::builder::BuilderError;
+
+        let wpkh = Address::Wpkh("bc1qvyyvsdcd0t9863stt7u9rf37wx443lzasg0usy".to_owned());
+        let sh = Address::Sh("377mKFYsaJPsxYSB5aFfx8SW3RaN5BzZVh".to_owned());
+        let builder = BitcoinMainnet::tx_builder()
+            .version(2)
+            .spend(BitcoinOutpoint::default(), 0);
+
+        let tx = builder
+            .clone()
+            .pay_many(vec![
+                (Amount::from_sat(3000), wpkh.clone()),
+                (Amount::from_sat(1000), sh.clone()),
+                (Amount::from_sat(2000), wpkh.clone()),
+            ])
+            .unwrap()
+            .build()
+            .unwrap();
+        let values: Vec<u64> = tx.outputs().iter().map(|o| o.value.to_sat()).collect();
+        assert_eq!(values, vec![3000, 1000, 2000]);
+        assert_eq!(
+            BitcoinMainnet::encode_address(&tx.outputs()[1].script_pubkey).unwrap(),
+            sh
+        );
+
+        let bad = Address::Wsh(wpkh.as_string());
+        match builder.pay_many(vec![
+            (Amount::from_sat(3000), wpkh),
+            (Amount::from_sat(1000), sh),
+            (Amount::from_sat(2000), bad.clone()),
+        ]) {
+            Err(BuilderError::BadRecipient { index, address, .. }) => {
+                assert_eq!(index, 2);
+                assert_eq!(address, bad);
+            }
+            _ => panic!("expected bad recipient"),
+        }
+    }
+
+    #[test]
+    fn it_pays_silent_payments() {
+        use crate::{
+            hashes::TXID,
+            silent_payments::InputKey,
+            types::{script::ScriptPubkey, tx::TxError},
+        };
+        use coins_bip32::ecdsa::SigningKey;
+
+        let sp = BitcoinMainnet::string_to_address("sp1qqvkqkl8e2vj2qlg98x9jgqt5msxzhezym943tx4xclmmrengdqyezq56cgpnt6ecw6xjq547rkau8j8kz7zqw3vw28ntftfz78v3wkyftvjs8qwk").unwrap();
+        let wpkh = Address::Wpkh("bc1qvyyvsdcd0t9863stt7u9rf37wx443lzasg0usy".to_owned());
+        let builder = BitcoinMainnet::tx_builder()
+            .version(2)
+            .spend(BitcoinOutpoint::new(TXID::from([0x33; 32]), 0), 0)
+            .spend(BitcoinOutpoint::new(TXID::from([0x22; 32]), 5), 0)
+            .pay(Amount::from_sat(3000), &sp)
+            .pay_many(vec![
+                (Amount::from_sat(1000), wpkh),
+                (Amount::from_sat(2000), sp),
+            ])
+            .unwrap();
+        match builder.clone().build() {
+            Err(TxError::PendingSilentPayments) => {}
+            _ => panic!("expected pending silent payments"),
+        }
+
+        let keys = [
+            InputKey::Ecdsa(SigningKey::from_bytes(&[0x11; 32]).unwrap()),
+            InputKey::Taproot(SigningKey::from_bytes(&[0x23; 32]).unwrap()),
+        ];
+        let tx = builder
+            .derive_silent_payments(&keys)
+            .unwrap()
+            .build()
+            .unwrap();
+        let values: Vec<u64> = tx.outputs().iter().map(|o| o.value.to_sat()).collect();
+        assert_eq!(values, vec![1000, 3000, 2000]);
+        let output_keys = [
+            "56546cf746442d4888ceb768430ada2a1a06e104b60261266ff664ffa96ec6aa",
+            "2d2a6682a01c37f47cc549e04458153ad9251c625bf3a0245cb453c39cbed89e",
+        ];
+        for (output, key) in tx.outputs()[1..].iter().zip(output_keys.iter()) {
+            let mut xonly = [0u8; 32];
+            xonly.copy_from_slice(&hex::decode(key).unwrap());
+            assert_eq!(output.script_pubkey, ScriptPubkey::p2tr(&xonly));
+        }
+    }
+
     #[test]
     fn it_exposes_encoder_interface() {
         let addr_string = "bc1qvyyvsdcd0t9863stt7u9rf37wx443lzasg0usy".to_owned();
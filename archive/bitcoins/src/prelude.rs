@@ -7,8 +7,18 @@ pub use crate::{
 
 pub use coins_core::prelude::*;
 
-#[cfg(any(feature = "mainnet", feature = "testnet", feature = "signet"))]
+#[cfg(any(
+    feature = "mainnet",
+    feature = "testnet",
+    feature = "signet",
+    feature = "regtest"
+))]
 pub use crate::defaults::*;
 
-#[cfg(any(feature = "mainnet", feature = "testnet", feature = "signet"))]
+#[cfg(any(
+    feature = "mainnet",
+    feature = "testnet",
+    feature = "signet",
+    feature = "regtest"
+))]
 pub use crate::defaults::network::*;
@@ -0,0 +1,434 @@
+//! Taproot recovery wallets.
+//!
+//! A recovery wallet's output may be spent at any time by the owner, through the key path, or by
+//! a recovery key once the output is `delay` blocks old, through a single tapscript leaf:
+//! `and_v(v:pk(RECOVERY),older(DELAY))`. This is the usual shape of inheritance and recovery
+//! wallets.
+//!
+//! `RecoveryWallet` generates the wallet's output descriptor, derives its script pubkey, and signs
+//! spends through either path. Encode the script pubkey with a network's `AddressEncoder` to get
+//! the address. Signing works on transactions directly. PSBTs are not supported.
+
+use coins_bip32::ecdsa::SigningKey;
+use coins_core::types::tx::Transaction;
+use k256::{
+    elliptic_curve::{group::Group, sec1::ToEncodedPoint, PrimeField},
+    schnorr, ProjectivePoint, PublicKey, Scalar,
+};
+use thiserror::Error;
+
+use crate::{
+    descriptor::add_checksum,
+    hashes::tagged_hash,
+    miniscript::{Assets, Context, Miniscript, MiniscriptError, Satisfier},
+    types::{
+        script::{Script, ScriptPubkey, Witness, WitnessStackItem, TAPSCRIPT_LEAF_VERSION},
+        tx::TxError,
+        txout::TxOut,
+        witness::{TaprootSighashArgs, WitnessTx},
+    },
+};
+
+/// Recovery wallet errors
+#[derive(Debug, Error)]
+pub enum RecoveryError {
+    /// A key is not a valid x-only public key
+    #[error("Invalid x-only key: {0}")]
+    InvalidKey(String),
+
+    /// The delay is zero. Outputs would be spendable by the recovery key immediately
+    #[error("The recovery delay must be at least 1 block")]
+    InvalidDelay,
+
+    /// The taproot tweak is not a valid scalar. This happens with negligible probability
+    #[error("Invalid taproot tweak")]
+    InvalidTweak,
+
+    /// The signing key is not the wallet's key for the path being spent
+    #[error("The key is not the wallet's key for this path")]
+    WrongKey,
+
+    /// The input's sequence number, or the transaction's version, does not enable the relative
+    /// timelock, or the timelock is shorter than the delay
+    #[error("The input does not satisfy the recovery delay of {0} blocks")]
+    TimelockNotMet(u16),
+
+    /// Error building the recovery leaf or its witness
+    #[error(transparent)]
+    MiniscriptError(#[from] MiniscriptError),
+
+    /// Error calculating a sighash
+    #[error(transparent)]
+    TxError(#[from] TxError),
+
+    /// Error creating a signature
+    #[error(transparent)]
+    SignatureError(#[from] coins_bip32::ecdsa::Error),
+}
+
+/// The x-only output key and its parity of a taproot output with `internal_key` and a script
+/// tree with root `merkle_root`. `true` if the y coordinate is odd.
+fn taproot_output(
+    internal_key: &[u8; 32],
+    merkle_root: &[u8; 32],
+) -> Result<([u8; 32], bool), RecoveryError> {
+    let tweak = tap_tweak(internal_key, merkle_root)?;
+    let internal = PublicKey::from_sec1_bytes(&[&[0x02][..], &internal_key[..]].concat())
+        .map_err(|_| RecoveryError::InvalidKey(hex::encode(internal_key)))?;
+    let output = internal.to_projective() + ProjectivePoint::GENERATOR * tweak;
+    if bool::from(output.is_identity()) {
+        return Err(RecoveryError::InvalidTweak);
+    }
+    let encoded = output.to_affine().to_encoded_point(true);
+    let mut xonly = [0u8; 32];
+    xonly.copy_from_slice(&encoded.as_bytes()[1..]);
+    Ok((xonly, encoded.as_bytes()[0] == 0x03))
+}
+
+/// The BIP341 tweak of `internal_key` committing to `merkle_root`
+fn tap_tweak(internal_key: &[u8; 32], merkle_root: &[u8; 32]) -> Result<Scalar, RecoveryError> {
+    let tweak = tagged_hash("TapTweak", &[&internal_key[..], &merkle_root[..]].concat());
+    Option::from(Scalar::from_repr(tweak.into())).ok_or(RecoveryError::InvalidTweak)
+}
+
+/// A taproot output spendable by an internal key, or by a recovery key after a relative
+/// timelock
+#[derive(Clone, Debug)]
+pub struct RecoveryWallet {
+    internal_key: [u8; 32],
+    recovery_key: [u8; 32],
+    delay: u16,
+    leaf: Miniscript,
+}
+
+impl RecoveryWallet {
+    /// Instantiate a wallet from the x-only owner and recovery keys, and the number of blocks
+    /// an output must wait before the recovery key can spend it
+    pub fn new(
+        internal_key: [u8; 32],
+        recovery_key: [u8; 32],
+        delay: u16,
+    ) -> Result<Self, RecoveryError> {
+        for key in [internal_key, recovery_key].iter() {
+            schnorr::VerifyingKey::from_bytes(key)
+                .map_err(|_| RecoveryError::InvalidKey(hex::encode(key)))?;
+        }
+        if delay == 0 {
+            return Err(RecoveryError::InvalidDelay);
+        }
+        let leaf = Miniscript::parse(
+            &format!(
+                "and_v(v:pk({}),older({}))",
+                hex::encode(recovery_key),
+                delay
+            ),
+            Context::Tap,
+        )?;
+        Ok(Self {
+            internal_key,
+            recovery_key,
+            delay,
+            leaf,
+        })
+    }
+
+    /// The x-only key that spends through the key path
+    pub fn internal_key(&self) -> &[u8; 32] {
+        &self.internal_key
+    }
+
+    /// The x-only key that spends through the script path after the delay
+    pub fn recovery_key(&self) -> &[u8; 32] {
+        &self.recovery_key
+    }
+
+    /// The number of blocks an output must wait before the recovery key can spend it
+    pub fn delay(&self) -> u16 {
+        self.delay
+    }
+
+    /// The recovery leaf
+    pub fn leaf(&self) -> &Miniscript {
+        &self.leaf
+    }
+
+    /// The script of the recovery leaf
+    pub fn leaf_script(&self) -> Script {
+        self.leaf.to_script()
+    }
+
+    /// The output descriptor of the wallet, with its checksum. E.g.
+    /// `tr(KEY,and_v(v:pk(RECOVERY),older(DELAY)))#checksum`
+    pub fn descriptor(&self) -> String {
+        let desc = format!("tr({},{})", hex::encode(self.internal_key), self.leaf);
+        add_checksum(&desc).expect("hex keys and miniscript are valid descriptor characters")
+    }
+
+    /// The x-only output key and its parity. The script tree is the single recovery leaf, so
+    /// its merkle root is the leaf hash.
+    fn output_key(&self) -> Result<([u8; 32], bool), RecoveryError> {
+        taproot_output(&self.internal_key, &self.leaf_script().tapleaf_hash())
+    }
+
+    /// The P2TR script pubkey of the wallet's outputs
+    pub fn script_pubkey(&self) -> Result<ScriptPubkey, RecoveryError> {
+        let (output_key, _) = self.output_key()?;
+        Ok(ScriptPubkey::p2tr(&output_key))
+    }
+
+    /// The control block of the recovery leaf: the leaf version and output key parity, followed
+    /// by the internal key
+    pub fn control_block(&self) -> Result<Vec<u8>, RecoveryError> {
+        let (_, odd) = self.output_key()?;
+        let mut control_block = vec![TAPSCRIPT_LEAF_VERSION | odd as u8];
+        control_block.extend(&self.internal_key);
+        Ok(control_block)
+    }
+
+    /// Sign input `index` of `tx` through the key path, with the private key of the internal
+    /// key, and return its witness. `prevouts` are the outputs spent by each input, in input
+    /// order. Signs with SIGHASH_DEFAULT, without auxiliary randomness.
+    pub fn sign_key_path(
+        &self,
+        tx: &WitnessTx,
+        index: usize,
+        prevouts: &[TxOut],
+        key: &SigningKey,
+    ) -> Result<Witness, RecoveryError> {
+        let internal = schnorr::SigningKey::from_bytes(&key.to_bytes())?;
+        if internal.verifying_key().to_bytes().as_slice() != self.internal_key {
+            return Err(RecoveryError::WrongKey);
+        }
+        let tweak = tap_tweak(&self.internal_key, &self.leaf_script().tapleaf_hash())?;
+        let secret = Option::<Scalar>::from(Scalar::from_repr(internal.to_bytes()))
+            .expect("signing keys are valid scalars");
+        let output_key = schnorr::SigningKey::from_bytes(&(secret + tweak).to_bytes())?;
+
+        let sighash = tx.taproot_sighash(&TaprootSighashArgs {
+            index,
+            sighash_flag: None,
+            prevouts: prevouts.to_vec(),
+            annex: None,
+            leaf_hash: None,
+            codesep_pos: 0xffff_ffff,
+        })?;
+        let sig = output_key.try_sign_prehashed(&sighash, &[0u8; 32])?;
+        Ok(vec![WitnessStackItem::new(sig.as_bytes().to_vec())])
+    }
+
+    /// Sign input `index` of `tx` through the recovery leaf, with the private key of the
+    /// recovery key, and return its witness. `prevouts` are the outputs spent by each input, in
+    /// input order. Signs with SIGHASH_DEFAULT, without auxiliary randomness.
+    ///
+    /// Errors with `TimelockNotMet` unless the transaction's version is at least 2, and the
+    /// input's sequence number enables a relative timelock of at least `delay` blocks.
+    pub fn sign_recovery(
+        &self,
+        tx: &WitnessTx,
+        index: usize,
+        prevouts: &[TxOut],
+        key: &SigningKey,
+    ) -> Result<Witness, RecoveryError> {
+        let recovery = schnorr::SigningKey::from_bytes(&key.to_bytes())?;
+        if recovery.verifying_key().to_bytes().as_slice() != self.recovery_key {
+            return Err(RecoveryError::WrongKey);
+        }
+
+        let script = self.leaf_script();
+        let sighash = tx.taproot_sighash(&TaprootSighashArgs {
+            index,
+            sighash_flag: None,
+            prevouts: prevouts.to_vec(),
+            annex: None,
+            leaf_hash: Some(script.tapleaf_hash()),
+            codesep_pos: 0xffff_ffff,
+        })?;
+        let sig = recovery.try_sign_prehashed(&sighash, &[0u8; 32])?;
+
+        let mut assets = Assets {
+            sequence: tx.inputs()[index].sequence,
+            ..Default::default()
+        };
+        if tx.version() < 2 || !assets.check_older(self.delay as u32) {
+            return Err(RecoveryError::TimelockNotMet(self.delay));
+        }
+        assets
+            .sigs
+            .insert(self.recovery_key.to_vec(), sig.as_bytes().to_vec());
+
+        let mut witness = self.leaf.satisfy(&assets)?;
+        witness.push(WitnessStackItem::new(script.items().to_vec()));
+        witness.push(WitnessStackItem::new(self.control_block()?));
+        Ok(witness)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::{
+        descriptor::verify_checksum,
+        types::{
+            amount::Amount,
+            txin::{BitcoinOutpoint, BitcoinTxIn},
+            witness::WitnessTransaction,
+        },
+    };
+
+    fn key(byte: u8) -> SigningKey {
+        SigningKey::from_bytes(&[byte; 32]).unwrap()
+    }
+
+    fn xonly(key: &SigningKey) -> [u8; 32] {
+        let mut xonly = [0u8; 32];
+        xonly.copy_from_slice(&key.verifying_key().to_bytes()[1..]);
+        xonly
+    }
+
+    fn wallet() -> RecoveryWallet {
+        RecoveryWallet::new(xonly(&key(0x11)), xonly(&key(0x22)), 144).unwrap()
+    }
+
+    /// A transaction spending a wallet output with `sequence`, and the output it spends
+    fn spend(wallet: &RecoveryWallet, sequence: u32) -> (WitnessTx, Vec<TxOut>) {
+        let prevouts = vec![TxOut::new(
+            Amount::from_sat(50_000),
+            wallet.script_pubkey().unwrap(),
+        )];
+        let vin = vec![BitcoinTxIn::new(
+            BitcoinOutpoint::new(Default::default(), 0),
+            vec![],
+            sequence,
+        )];
+        let vout = vec![TxOut::new(
+            Amount::from_sat(49_000),
+            ScriptPubkey::p2tr(&[7; 32]),
+        )];
+        let tx = <WitnessTx as WitnessTransaction>::new(2, vin, vout, vec![vec![]], 0).unwrap();
+        (tx, prevouts)
+    }
+
+    #[test]
+    fn it_tweaks_output_keys() {
+        // BIP341 wallet test vector with a single leaf
+        let internal_key =
+            hex::decode("187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27")
+                .unwrap();
+        let mut internal = [0u8; 32];
+        internal.copy_from_slice(&internal_key);
+        let leaf = Script::from(
+            hex::decode("20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac")
+                .unwrap(),
+        );
+        let (output_key, odd) = taproot_output(&internal, &leaf.tapleaf_hash()).unwrap();
+        assert_eq!(
+            hex::encode(output_key),
+            "147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3"
+        );
+        assert!(odd);
+    }
+
+    #[test]
+    fn it_describes_the_wallet() {
+        let wallet = wallet();
+        let desc = wallet.descriptor();
+        let body = verify_checksum(&desc).unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "tr({},and_v(v:pk({}),older(144)))",
+                hex::encode(xonly(&key(0x11))),
+                hex::encode(xonly(&key(0x22)))
+            )
+        );
+
+        let (output_key, odd) = wallet.output_key().unwrap();
+        assert_eq!(
+            wallet.script_pubkey().unwrap(),
+            ScriptPubkey::p2tr(&output_key)
+        );
+        let control_block = wallet.control_block().unwrap();
+        assert_eq!(control_block[0], 0xc0 | odd as u8);
+        assert_eq!(&control_block[1..], &wallet.internal_key()[..]);
+
+        assert!(matches!(
+            RecoveryWallet::new(xonly(&key(0x11)), xonly(&key(0x22)), 0),
+            Err(RecoveryError::InvalidDelay)
+        ));
+        assert!(matches!(
+            RecoveryWallet::new([0xff; 32], xonly(&key(0x22)), 144),
+            Err(RecoveryError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn it_signs_the_key_path() {
+        let wallet = wallet();
+        let (tx, prevouts) = spend(&wallet, 0xffff_fffd);
+        let witness = wallet.sign_key_path(&tx, 0, &prevouts, &key(0x11)).unwrap();
+        assert_eq!(witness.len(), 1);
+
+        let sighash = tx
+            .taproot_sighash(&TaprootSighashArgs {
+                index: 0,
+                sighash_flag: None,
+                prevouts: prevouts.clone(),
+                annex: None,
+                leaf_hash: None,
+                codesep_pos: 0xffff_ffff,
+            })
+            .unwrap();
+        let (output_key, _) = wallet.output_key().unwrap();
+        let sig = schnorr::Signature::try_from(witness[0].items()).unwrap();
+        schnorr::VerifyingKey::from_bytes(&output_key)
+            .unwrap()
+            .verify_prehashed(&sighash, &sig)
+            .unwrap();
+
+        assert!(matches!(
+            wallet.sign_key_path(&tx, 0, &prevouts, &key(0x22)),
+            Err(RecoveryError::WrongKey)
+        ));
+    }
+
+    #[test]
+    fn it_signs_the_recovery_path() {
+        let wallet = wallet();
+        let (tx, prevouts) = spend(&wallet, 144);
+        let witness = wallet.sign_recovery(&tx, 0, &prevouts, &key(0x22)).unwrap();
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness[1].items(), wallet.leaf_script().items());
+        assert_eq!(witness[2].items(), &wallet.control_block().unwrap()[..]);
+
+        let sighash = tx
+            .taproot_sighash(&TaprootSighashArgs {
+                index: 0,
+                sighash_flag: None,
+                prevouts: prevouts.clone(),
+                annex: None,
+                leaf_hash: Some(wallet.leaf_script().tapleaf_hash()),
+                codesep_pos: 0xffff_ffff,
+            })
+            .unwrap();
+        let sig = schnorr::Signature::try_from(witness[0].items()).unwrap();
+        schnorr::VerifyingKey::from_bytes(wallet.recovery_key())
+            .unwrap()
+            .verify_prehashed(&sighash, &sig)
+            .unwrap();
+
+        assert!(matches!(
+            wallet.sign_recovery(&tx, 0, &prevouts, &key(0x11)),
+            Err(RecoveryError::WrongKey)
+        ));
+        // too early, or without a relative timelock
+        for sequence in [143, 0xffff_fffd, 144 | 1 << 22].iter() {
+            let (tx, prevouts) = spend(&wallet, *sequence);
+            assert!(matches!(
+                wallet.sign_recovery(&tx, 0, &prevouts, &key(0x22)),
+                Err(RecoveryError::TimelockNotMet(144))
+            ));
+        }
+    }
+}
@@ -0,0 +1,821 @@
+//! Silent payments, as specified in
+//! [BIP 352](https://github.com/bitcoin/bips/blob/master/bip-0352.mediawiki).
+//!
+//! A silent payment address holds a scan key and a spend key. The sender
+//! derives a new taproot output for each payment, from the private keys of
+//! its inputs and the recipient's keys. The outputs can not be linked to the
+//! address, or to each other. The recipient finds them by scanning
+//! transactions with its scan private key.
+//!
+//! The builder pays silent payment addresses with `pay`, and derives their
+//! outputs with `derive_silent_payments` once all inputs are added.
+//!
+//! Recipients may label addresses with `SilentPaymentAddress::with_label`, to
+//! tell payments to each address apart. Labeled outputs are found by passing
+//! the labels to `scan`. Label 0 is reserved for change.
+
+use std::collections::HashMap;
+
+use coins_bip32::ecdsa::{SigningKey, VerifyingKey};
+use coins_core::{
+    enc::{EncodingError, EncodingResult},
+    hashes::{Digest, Hash160, MarkedDigestOutput},
+    ser::ByteFormat,
+};
+use k256::{
+    elliptic_curve::{group::Group, sec1::ToEncodedPoint, PrimeField},
+    ProjectivePoint, Scalar,
+};
+use thiserror::Error;
+
+use crate::{
+    enc::bases::{decode_bech32m_with_max_len, encode_bech32m},
+    hashes::tagged_hash,
+    types::{
+        script::{witness_program, ScriptPubkey, ScriptType, Witness},
+        tx::BitcoinTransaction,
+        txin::{BitcoinOutpoint, BitcoinTxIn},
+        txout::TxOut,
+    },
+};
+
+/// The longest silent payment address. Longer than the BIP173 limit, as the
+/// address holds two keys.
+const MAX_ADDRESS_LEN: usize = 1023;
+
+/// The x coordinate of the BIP341 NUMS point. Taproot outputs with this
+/// internal key have no key path, and are not eligible inputs.
+const NUMS_H: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// Silent payment errors
+#[derive(Debug, Error)]
+pub enum SilentPaymentError {
+    /// The transaction has no inputs, so there is no outpoint to commit to
+    #[error("No inputs")]
+    NoInputs,
+
+    /// No input keys were provided, or they sum to zero
+    #[error("No input keys, or the input keys sum to zero")]
+    InvalidKeySum,
+
+    /// Scanning needs the prevout of every input
+    #[error("Expected one prevout per input. Got {0}.")]
+    WrongPrevoutCount(usize),
+
+    /// A hash is not a valid scalar. This happens with negligible probability
+    #[error("Hash is not a valid scalar")]
+    InvalidScalar,
+}
+
+/// A silent payment address. Holds the recipient's scan and spend keys.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SilentPaymentAddress {
+    scan: VerifyingKey,
+    spend: VerifyingKey,
+}
+
+impl SilentPaymentAddress {
+    /// Instantiate an address from its scan and spend keys
+    pub fn new(scan: VerifyingKey, spend: VerifyingKey) -> Self {
+        Self { scan, spend }
+    }
+
+    /// The key used to find payments. Its private key may be kept online.
+    pub fn scan_key(&self) -> &VerifyingKey {
+        &self.scan
+    }
+
+    /// The key that received outputs are tweaked from. Its private key is
+    /// needed to spend them.
+    pub fn spend_key(&self) -> &VerifyingKey {
+        &self.spend
+    }
+
+    /// The address with the label `label`, given the recipient's private scan
+    /// key. Its spend key is tweaked by the label, so payments to it are found
+    /// by scanning with the label. Label 0 is reserved for change.
+    pub fn with_label(
+        &self,
+        scan_key: &SigningKey,
+        label: u32,
+    ) -> Result<Self, SilentPaymentError> {
+        let tweak = label_tweak(scan_key, label)?;
+        let spend = ProjectivePoint::from(&self.spend) + ProjectivePoint::GENERATOR * tweak;
+        let spend = VerifyingKey::from_sec1_bytes(&serialize_point(&spend))
+            .map_err(|_| SilentPaymentError::InvalidScalar)?;
+        Ok(Self::new(self.scan, spend))
+    }
+
+    /// Encode the address to bech32m as version 0. `hrp` is "sp" on mainnet,
+    /// and "tsp" on test networks.
+    pub fn encode(&self, hrp: &str) -> EncodingResult<String> {
+        let mut payload = self.scan.to_bytes().to_vec();
+        payload.extend(self.spend.to_bytes());
+        encode_bech32m(hrp, 0, &payload)
+    }
+
+    /// Decode an address from a bech32m string. Caller specifies an expected
+    /// HRP. If a different HRP is found, returns `WrongHrp`. Later versions
+    /// are decoded by reading the keys, and ignoring the rest of the payload.
+    /// Version 31 is reserved for backwards incompatible changes.
+    pub fn decode(expected_hrp: &str, s: &str) -> EncodingResult<Self> {
+        let (version, payload) = decode_bech32m_with_max_len(expected_hrp, s, MAX_ADDRESS_LEN)?;
+        let valid_len = match version {
+            0 => payload.len() == 66,
+            1..=30 => payload.len() >= 66,
+            _ => return Err(EncodingError::SegwitVersionError(version)),
+        };
+        if !valid_len {
+            return Err(EncodingError::InvalidSizeError);
+        }
+        let key = |bytes: &[u8]| {
+            VerifyingKey::from_sec1_bytes(bytes)
+                .map_err(|_| EncodingError::from(bech32::Error::InvalidData(bytes[0])))
+        };
+        Ok(Self::new(key(&payload[..33])?, key(&payload[33..66])?))
+    }
+}
+
+/// The private key of a transaction input, used to derive silent payment
+/// outputs.
+#[derive(Clone)]
+pub enum InputKey {
+    /// The key of a P2PKH, P2WPKH, or P2SH-P2WPKH input
+    Ecdsa(SigningKey),
+    /// The key of a P2TR input spent with the key path. This is the private
+    /// key of the output key, i.e. after the taproot tweak.
+    Taproot(SigningKey),
+}
+
+impl InputKey {
+    /// The key as a scalar. Taproot keys are negated if needed, so that their
+    /// public key has an even y coordinate.
+    fn scalar(&self) -> Scalar {
+        let key = match self {
+            InputKey::Ecdsa(key) | InputKey::Taproot(key) => key,
+        };
+        let scalar = Option::<Scalar>::from(Scalar::from_repr(key.to_bytes()))
+            .expect("signing keys are valid scalars");
+        let odd = key.verifying_key().to_bytes()[0] == 0x03;
+        match self {
+            InputKey::Taproot(_) if odd => -scalar,
+            _ => scalar,
+        }
+    }
+}
+
+/// Interpret a hash as a scalar, erroring if it is not below the curve order
+fn hash_to_scalar(hash: [u8; 32]) -> Result<Scalar, SilentPaymentError> {
+    Option::from(Scalar::from_repr(hash.into())).ok_or(SilentPaymentError::InvalidScalar)
+}
+
+/// The compressed encoding of a point
+fn serialize_point(point: &ProjectivePoint) -> Vec<u8> {
+    point.to_affine().to_encoded_point(true).as_bytes().to_vec()
+}
+
+/// The hash committing to the inputs. `outpoints` must not be empty.
+fn input_hash(
+    outpoints: &[BitcoinOutpoint],
+    key_sum: &ProjectivePoint,
+) -> Result<Scalar, SilentPaymentError> {
+    let smallest = outpoints
+        .iter()
+        .map(|outpoint| outpoint.serialize_hex())
+        .min()
+        .ok_or(SilentPaymentError::NoInputs)?;
+    let mut data = hex::decode(smallest).expect("valid hex");
+    data.extend(serialize_point(key_sum));
+    hash_to_scalar(tagged_hash("BIP0352/Inputs", &data))
+}
+
+/// The x-only encoding of a point
+fn xonly(point: &ProjectivePoint) -> [u8; 32] {
+    let mut xonly = [0u8; 32];
+    xonly.copy_from_slice(&serialize_point(point)[1..]);
+    xonly
+}
+
+/// The tweak of the label `label`, added to the spend key of labeled addresses
+fn label_tweak(scan_key: &SigningKey, label: u32) -> Result<Scalar, SilentPaymentError> {
+    let mut data = scan_key.to_bytes().to_vec();
+    data.extend(&label.to_be_bytes());
+    hash_to_scalar(tagged_hash("BIP0352/Label", &data))
+}
+
+/// The tweak and output key of the `k`th output paying `spend` with the
+/// shared secret
+fn output_key(
+    shared_secret: &ProjectivePoint,
+    spend: &ProjectivePoint,
+    k: u32,
+) -> Result<(Scalar, ProjectivePoint), SilentPaymentError> {
+    let mut data = serialize_point(shared_secret);
+    data.extend(&k.to_be_bytes());
+    let tweak = hash_to_scalar(tagged_hash("BIP0352/SharedSecret", &data))?;
+    Ok((tweak, *spend + ProjectivePoint::GENERATOR * tweak))
+}
+
+/// Derive the x-only taproot output keys paying `recipients`, in order.
+/// `outpoints` are the outpoints of all inputs of the transaction. `keys` are
+/// the private keys of its inputs that are eligible, i.e. P2PKH, P2WPKH,
+/// P2SH-P2WPKH, and P2TR key path inputs. Paying the same address many times
+/// derives different outputs.
+///
+/// The outputs commit to the inputs. Adding, removing, or changing inputs
+/// after deriving the outputs leaves the recipient unable to find them.
+pub fn derive_outputs(
+    outpoints: &[BitcoinOutpoint],
+    keys: &[InputKey],
+    recipients: &[SilentPaymentAddress],
+) -> Result<Vec<[u8; 32]>, SilentPaymentError> {
+    let key_sum = keys
+        .iter()
+        .map(InputKey::scalar)
+        .fold(Scalar::ZERO, |sum, scalar| sum + scalar);
+    if bool::from(key_sum.is_zero()) {
+        return Err(SilentPaymentError::InvalidKeySum);
+    }
+    let secret = input_hash(outpoints, &(ProjectivePoint::GENERATOR * key_sum))? * key_sum;
+
+    // outputs to the same scan key share a secret, and increment k
+    let mut shared_secrets: HashMap<Vec<u8>, (ProjectivePoint, u32)> = HashMap::new();
+    recipients
+        .iter()
+        .map(|recipient| {
+            let (shared_secret, k) = shared_secrets
+                .entry(recipient.scan.to_bytes().to_vec())
+                .or_insert_with(|| (ProjectivePoint::from(&recipient.scan) * secret, 0));
+            let (_, key) = output_key(shared_secret, &ProjectivePoint::from(&recipient.spend), *k)?;
+            *k += 1;
+            Ok(xonly(&key))
+        })
+        .collect()
+}
+
+/// The public key an input contributes to the silent payment shared secret,
+/// or `None` if the input is not eligible. `witness` is the input's witness,
+/// and `prevout` the output it spends.
+///
+/// P2PKH, P2WPKH, and P2SH-P2WPKH inputs are eligible if they reveal a
+/// compressed key. P2TR inputs are eligible unless they are script path
+/// spends of an output with the NUMS internal key, which has no key path.
+pub fn input_public_key(
+    input: &BitcoinTxIn,
+    witness: &Witness,
+    prevout: &TxOut,
+) -> Option<VerifyingKey> {
+    let compressed = |key: &[u8]| key.len() == 33 && (key[0] == 0x02 || key[0] == 0x03);
+    let script_sig = input.script_sig.items();
+    let key = match prevout.standard_type() {
+        // The key need not be a push of its own in a malleated script sig.
+        // Like the reference implementation, take the last 33 bytes that
+        // hash to the pubkey hash.
+        ScriptType::Pkh(hash) => script_sig
+            .windows(33)
+            .rev()
+            .find(|key| Hash160::digest(key).as_slice() == hash.as_slice())?,
+        ScriptType::Sh(_) => {
+            let redeem_script = script_sig.get(1..)?;
+            match witness_program(redeem_script) {
+                Some((0, program)) if program.len() == 20 && script_sig[0] == 22 => {}
+                _ => return None,
+            }
+            witness.last()?.items()
+        }
+        ScriptType::Wpkh(_) => witness.last()?.items(),
+        ScriptType::Tr(output_key) => {
+            let mut items = witness.as_slice();
+            if items.len() > 1 && items.last()?.items().first() == Some(&0x50) {
+                items = &items[..items.len() - 1];
+            }
+            // a script path spend. The last item is the control block
+            if items.len() > 1 && items.last()?.items().get(1..33) == Some(&NUMS_H[..]) {
+                return None;
+            }
+            // x-only keys are lifted to the point with an even y coordinate
+            let key = [&[0x02][..], &output_key].concat();
+            return VerifyingKey::from_sec1_bytes(&key).ok();
+        }
+        _ => return None,
+    };
+    if !compressed(key) {
+        return None;
+    }
+    VerifyingKey::from_sec1_bytes(key).ok()
+}
+
+/// A silent payment output found by `scan`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReceivedOutput {
+    /// The index of the output in the transaction
+    pub index: usize,
+    /// The x-only taproot output key
+    pub output_key: [u8; 32],
+    /// The tweak added to the spend key, including the label tweak of labeled
+    /// outputs
+    pub tweak: [u8; 32],
+    /// The label of the address paid, or `None` if it is not labeled
+    pub label: Option<u32>,
+}
+
+impl ReceivedOutput {
+    /// The private key of the output key, for signing a key path spend. This
+    /// is the spend private key plus the tweak. Unlike most taproot outputs,
+    /// no BIP341 tweak is applied.
+    pub fn spending_key(&self, spend_key: &SigningKey) -> Result<SigningKey, SilentPaymentError> {
+        let spend = Option::<Scalar>::from(Scalar::from_repr(spend_key.to_bytes()))
+            .expect("signing keys are valid scalars");
+        let secret = spend + hash_to_scalar(self.tweak)?;
+        SigningKey::from_bytes(&secret.to_bytes()).map_err(|_| SilentPaymentError::InvalidScalar)
+    }
+}
+
+/// Find the outputs of `tx` paying the silent payment address with the
+/// private scan key `scan_key`, and the public spend key `spend_key`, or the
+/// addresses with the labels `labels`. `prevouts` are the outputs spent by
+/// each input, in input order.
+///
+/// Transactions spending witness versions above 1, or without eligible
+/// inputs, can not contain silent payments. No outputs are found in them.
+pub fn scan<T>(
+    tx: &T,
+    prevouts: &[TxOut],
+    scan_key: &SigningKey,
+    spend_key: &VerifyingKey,
+    labels: &[u32],
+) -> Result<Vec<ReceivedOutput>, SilentPaymentError>
+where
+    T: BitcoinTransaction + ?Sized,
+{
+    if prevouts.len() != tx.inputs().len() {
+        return Err(SilentPaymentError::WrongPrevoutCount(prevouts.len()));
+    }
+    let future_version = prevouts.iter().any(
+        |prevout| matches!(witness_program(prevout.script_pubkey.items()), Some((v, _)) if v > 1),
+    );
+    if future_version {
+        return Ok(vec![]);
+    }
+
+    let empty = Witness::default();
+    let key_sum = tx
+        .inputs()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, input)| {
+            let witness = tx.witnesses().get(i).unwrap_or(&empty);
+            input_public_key(input, witness, &prevouts[i])
+        })
+        .fold(ProjectivePoint::IDENTITY, |sum, key| {
+            sum + ProjectivePoint::from(&key)
+        });
+    if bool::from(key_sum.is_identity()) {
+        return Ok(vec![]);
+    }
+
+    let outpoints: Vec<_> = tx.inputs().iter().map(|input| input.outpoint).collect();
+    let scan = Option::<Scalar>::from(Scalar::from_repr(scan_key.to_bytes()))
+        .expect("signing keys are valid scalars");
+    let shared_secret = key_sum * (input_hash(&outpoints, &key_sum)? * scan);
+    let spend = ProjectivePoint::from(spend_key);
+    let labels = labels
+        .iter()
+        .map(|label| {
+            let tweak = label_tweak(scan_key, *label)?;
+            Ok((*label, tweak, ProjectivePoint::GENERATOR * tweak))
+        })
+        .collect::<Result<Vec<_>, SilentPaymentError>>()?;
+
+    let mut found: Vec<ReceivedOutput> = vec![];
+    loop {
+        let (tweak, key) = output_key(&shared_secret, &spend, found.len() as u32)?;
+        // the unlabeled output key, then the key tweaked by each label
+        let mut candidates =
+            std::iter::once((None, tweak, key)).chain(labels.iter().map(
+                |(label, label_tweak, point)| (Some(*label), tweak + label_tweak, key + point),
+            ));
+        let received = candidates.find_map(|(label, tweak, key)| {
+            let output_key = xonly(&key);
+            let index = tx
+                .outputs()
+                .iter()
+                .enumerate()
+                .position(|(index, output)| {
+                    output.script_pubkey == ScriptPubkey::p2tr(&output_key)
+                        && found.iter().all(|received| received.index != index)
+                })?;
+            Some(ReceivedOutput {
+                index,
+                output_key,
+                tweak: tweak.to_bytes().into(),
+                label,
+            })
+        });
+        match received {
+            Some(received) => found.push(received),
+            None => return Ok(found),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hashes::TXID,
+        types::{
+            amount::Amount,
+            script::WitnessStackItem,
+            witness::{WitnessTransaction, WitnessTx},
+        },
+    };
+
+    fn key(byte: u8) -> SigningKey {
+        SigningKey::from_bytes(&[byte; 32]).unwrap()
+    }
+
+    fn xonly(key: &SigningKey) -> [u8; 32] {
+        let mut xonly = [0u8; 32];
+        xonly.copy_from_slice(&key.verifying_key().to_bytes()[1..]);
+        xonly
+    }
+
+    fn address(scan: u8, spend: u8) -> SilentPaymentAddress {
+        SilentPaymentAddress::new(key(scan).verifying_key(), key(spend).verifying_key())
+    }
+
+    #[test]
+    fn it_encodes_and_decodes_addresses() {
+        let addr = address(0x44, 0x55);
+        let encoded = "sp1qqvkqkl8e2vj2qlg98x9jgqt5msxzhezym943tx4xclmmrengdqyezq56cgpnt6ecw6xjq547rkau8j8kz7zqw3vw28ntftfz78v3wkyftvjs8qwk";
+        assert_eq!(addr.encode("sp").unwrap(), encoded);
+        assert_eq!(SilentPaymentAddress::decode("sp", encoded).unwrap(), addr);
+        assert!(matches!(
+            SilentPaymentAddress::decode("tsp", encoded),
+            Err(EncodingError::WrongHrp { .. })
+        ));
+
+        let payload = [&addr.scan.to_bytes()[..], &addr.spend.to_bytes()[..]].concat();
+        // later versions ignore extra data. Version 31 is invalid
+        let v1 = encode_bech32m("tsp", 1, &[&payload[..], &[0xab; 5]].concat()).unwrap();
+        assert_eq!(SilentPaymentAddress::decode("tsp", &v1).unwrap(), addr);
+        let v31 = encode_bech32m("tsp", 31, &payload).unwrap();
+        assert!(SilentPaymentAddress::decode("tsp", &v31).is_err());
+        let short = encode_bech32m("sp", 0, &payload[..65]).unwrap();
+        assert!(SilentPaymentAddress::decode("sp", &short).is_err());
+    }
+
+    #[test]
+    fn it_derives_and_scans_outputs() {
+        let outpoints = [
+            BitcoinOutpoint::new(TXID::from([0x33; 32]), 0),
+            BitcoinOutpoint::new(TXID::from([0x22; 32]), 5),
+        ];
+        // the taproot key has an odd y coordinate, and is negated
+        let ecdsa_key = key(0x11);
+        let taproot_key = key(0x23);
+        let keys = [
+            InputKey::Ecdsa(ecdsa_key.clone()),
+            InputKey::Taproot(taproot_key.clone()),
+        ];
+        let recipients = [
+            address(0x44, 0x55),
+            address(0x66, 0x77),
+            address(0x44, 0x55),
+        ];
+        let outputs = derive_outputs(&outpoints, &keys, &recipients).unwrap();
+        let expected = [
+            "56546cf746442d4888ceb768430ada2a1a06e104b60261266ff664ffa96ec6aa",
+            "cd0e39c23e1a8d2a69ef04402e5f4aed2d2c9cc16403d7fe4a655a589d95dffd",
+            "2d2a6682a01c37f47cc549e04458153ad9251c625bf3a0245cb453c39cbed89e",
+        ];
+        for (output, expected) in outputs.iter().zip(expected.iter()) {
+            assert_eq!(hex::encode(output), *expected);
+        }
+
+        // the recipient finds its outputs in a transaction spending the inputs
+        let vin: Vec<_> = outpoints
+            .iter()
+            .map(|outpoint| BitcoinTxIn::new(*outpoint, vec![], 0xffff_fffd))
+            .collect();
+        let mut vout: Vec<_> = outputs
+            .iter()
+            .map(|output| TxOut::new(Amount::from_sat(10_000), ScriptPubkey::p2tr(output)))
+            .collect();
+        vout.insert(
+            0,
+            TxOut::new(Amount::from_sat(5_000), ScriptPubkey::p2wpkh(&key(0x99))),
+        );
+        let witnesses = vec![
+            vec![
+                WitnessStackItem::new(vec![0x30; 71]),
+                WitnessStackItem::new(ecdsa_key.verifying_key().to_bytes().to_vec()),
+            ],
+            vec![WitnessStackItem::new(vec![0x01; 64])],
+        ];
+        let tx = <WitnessTx as WitnessTransaction>::new(2, vin, vout, witnesses, 0).unwrap();
+        let prevouts = [
+            TxOut::new(Amount::from_sat(20_000), ScriptPubkey::p2wpkh(&ecdsa_key)),
+            TxOut::new(
+                Amount::from_sat(20_000),
+                ScriptPubkey::p2tr(&xonly(&taproot_key)),
+            ),
+        ];
+
+        let found = scan(&tx, &prevouts, &key(0x44), &key(0x55).verifying_key(), &[]).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].index, 1);
+        assert_eq!(found[1].index, 3);
+        assert_eq!(
+            hex::encode(found[0].tweak),
+            "c5d1f2d7ee052e3d205bf36ce84fb6ef7edf970293d201726d36262f3afcab78"
+        );
+        for received in found.iter() {
+            let spending_key = received.spending_key(&key(0x55)).unwrap();
+            assert_eq!(
+                &spending_key.verifying_key().to_bytes()[1..],
+                &received.output_key[..]
+            );
+        }
+        let found = scan(&tx, &prevouts, &key(0x66), &key(0x77).verifying_key(), &[]).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].index, 2);
+        assert!(
+            scan(&tx, &prevouts, &key(0x55), &key(0x44).verifying_key(), &[])
+                .unwrap()
+                .is_empty()
+        );
+
+        // spending a future witness version disables silent payments
+        let mut future = prevouts.clone();
+        future[0] = TxOut::new(
+            Amount::from_sat(20_000),
+            [&[0x52, 0x20][..], &[1; 32]].concat(),
+        );
+        assert!(
+            scan(&tx, &future, &key(0x44), &key(0x55).verifying_key(), &[])
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            derive_outputs(&outpoints, &[], &recipients),
+            Err(SilentPaymentError::InvalidKeySum)
+        ));
+    }
+
+    #[test]
+    fn it_extracts_input_public_keys() {
+        let signing_key = key(0x11);
+        let key = signing_key.verifying_key();
+        let key_bytes = key.to_bytes().to_vec();
+        let input = |script_sig: Vec<u8>| BitcoinTxIn::new(Default::default(), script_sig, 0);
+        let witness = vec![
+            WitnessStackItem::new(vec![0x30; 71]),
+            WitnessStackItem::new(key_bytes.clone()),
+        ];
+        let value = Amount::from_sat(10_000);
+
+        let p2pkh = TxOut::new(value, ScriptPubkey::p2pkh(&signing_key));
+        let script_sig = [&[71][..], &[0x30; 71], &[33], &key_bytes].concat();
+        assert_eq!(
+            input_public_key(&input(script_sig), &vec![], &p2pkh),
+            Some(key)
+        );
+        // malleated, with the key inside a longer push
+        let script_sig = [&[71][..], &[0x30; 71], &[35, 0x00], &key_bytes, &[0x75]].concat();
+        assert_eq!(
+            input_public_key(&input(script_sig), &vec![], &p2pkh),
+            Some(key)
+        );
+
+        let p2sh_p2wpkh = TxOut::new(value, ScriptPubkey::p2sh_p2wpkh(&signing_key));
+        let script_sig = [&[22][..], ScriptPubkey::p2wpkh(&signing_key).items()].concat();
+        assert_eq!(
+            input_public_key(&input(script_sig), &witness, &p2sh_p2wpkh),
+            Some(key)
+        );
+
+        let p2wpkh = TxOut::new(value, ScriptPubkey::p2wpkh(&signing_key));
+        assert_eq!(
+            input_public_key(&input(vec![]), &witness, &p2wpkh),
+            Some(key)
+        );
+
+        // script path spends of outputs with the NUMS internal key are not eligible
+        let p2tr = TxOut::new(value, ScriptPubkey::p2tr(&xonly(&signing_key)));
+        let key_path = vec![WitnessStackItem::new(vec![1; 64])];
+        assert!(input_public_key(&input(vec![]), &key_path, &p2tr).is_some());
+        let control_block = [&[0xc0][..], &NUMS_H].concat();
+        let script_path = vec![
+            WitnessStackItem::new(vec![0x51]),
+            WitnessStackItem::new(control_block),
+        ];
+        assert_eq!(input_public_key(&input(vec![]), &script_path, &p2tr), None);
+
+        let p2wsh = TxOut::new(value, ScriptPubkey::p2wsh(&vec![0x51].into()));
+        assert_eq!(input_public_key(&input(vec![]), &witness, &p2wsh), None);
+    }
+    /// A case of the BIP352 send and receive test vectors. Each input is a
+    /// txid, vout, private key, and whether it is a taproot key path spend.
+    /// All cases pay `VECTOR_ADDRESS` once.
+    struct Vector {
+        inputs: &'static [(&'static str, u32, &'static str, bool)],
+        output: &'static str,
+    }
+
+    const VECTOR_ADDRESS: &str = "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv";
+    const VECTOR_SCAN_KEY: &str =
+        "0f694e068028a717f8af6b9411f9a133dd3565258714cc226594b34db90c1f2c";
+    const VECTOR_SPEND_KEY: &str =
+        "9d6ad855ce3417ef84e836892e5a56392bfba05fa5d97ccea30e266f540e08b3";
+    const TXID_A: &str = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16";
+    const TXID_B: &str = "a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d";
+    const KEY_A: &str = "eadc78165ff1f8ea94ad7cfdc54990738a4c53f6e0507b42154201b8e5dff3b1";
+    const KEY_B: &str = "93f5ed907ad5b2bdbbdcb5d9116ebc0a4e1f92f910d5260237fa45a9408aad16";
+    const KEY_C: &str = "fc8716a97a48ba9a05a98ae47b5cd201a25a7fd5d8b73c203c5f7b6b6b3b6ad7";
+    const KEY_ODD: &str = "1d37787c2b7116ee983e9f9c13269df29091b391c04db94239e0d2bc2182c3bf";
+    const KEY_PKH: &str = "8d4751f6e8a3586880fb66c19ae277969bd5aa06f61c4ee2f1e2486efdf666d3";
+
+    const VECTORS: &[Vector] = &[
+        // simple send: two inputs
+        Vector {
+            inputs: &[(TXID_A, 0, KEY_A, false), (TXID_B, 0, KEY_B, false)],
+            output: "3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1",
+        },
+        // simple send: two inputs, order reversed
+        Vector {
+            inputs: &[(TXID_B, 0, KEY_B, false), (TXID_A, 0, KEY_A, false)],
+            output: "3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1",
+        },
+        // simple send: two inputs from the same transaction
+        Vector {
+            inputs: &[(TXID_A, 3, KEY_A, false), (TXID_A, 7, KEY_B, false)],
+            output: "79e71baa2ba3fc66396de3a04f168c7bf24d6870ec88ca877754790c1db357b6",
+        },
+        // outpoint ordering byte-lexicographically vs. vout integer
+        Vector {
+            inputs: &[(TXID_A, 1, KEY_A, false), (TXID_A, 256, KEY_B, false)],
+            output: "a85ef8701394b517a4b35217c4bd37ac01ebeed4b008f8d0879f9e09ba95319c",
+        },
+        // single recipient: multiple UTXOs from the same public key
+        Vector {
+            inputs: &[(TXID_A, 0, KEY_A, false), (TXID_B, 0, KEY_A, false)],
+            output: "548ae55c8eec1e736e8d3e520f011f1f42a56d166116ad210b3937599f87f566",
+        },
+        // single recipient: taproot only inputs with even y-values
+        Vector {
+            inputs: &[(TXID_A, 0, KEY_A, true), (TXID_B, 0, KEY_C, true)],
+            output: "de88bea8e7ffc9ce1af30d1132f910323c505185aec8eae361670421e749a1fb",
+        },
+        // single recipient: taproot input with even y-value and non-taproot input
+        Vector {
+            inputs: &[(TXID_A, 0, KEY_A, true), (TXID_B, 0, KEY_PKH, false)],
+            output: "30523cca96b2a9ae3c98beb5e60f7d190ec5bc79b2d11a0b2d4d09a608c448f0",
+        },
+        // single recipient: taproot input with odd y-value and non-taproot input
+        Vector {
+            inputs: &[(TXID_A, 0, KEY_ODD, true), (TXID_B, 0, KEY_PKH, false)],
+            output: "359358f59ee9e9eec3f00bdf4882570fd5c182e451aa2650b788544aff012a3a",
+        },
+    ];
+
+    fn hex_key(s: &str) -> SigningKey {
+        SigningKey::from_bytes(&hex::decode(s).unwrap()).unwrap()
+    }
+
+    /// The outpoint of a vector input. Txids are displayed in reverse byte
+    /// order
+    fn vector_outpoint(txid: &str, vout: u32) -> BitcoinOutpoint {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&hex::decode(txid).unwrap());
+        bytes.reverse();
+        BitcoinOutpoint::new(TXID::from(bytes), vout)
+    }
+
+    /// A transaction spending `inputs` to `outputs`, and its prevouts. The
+    /// vectors' signatures do not affect the keys found, so placeholders are
+    /// used. P2PKH inputs reveal their key in the script sig.
+    fn vector_tx(
+        inputs: &[(&str, u32, &str, bool)],
+        outputs: &[[u8; 32]],
+    ) -> (WitnessTx, Vec<TxOut>) {
+        let value = Amount::from_sat(10_000);
+        let mut vin = vec![];
+        let mut witnesses = vec![];
+        let mut prevouts = vec![];
+        for (txid, vout, private_key, taproot) in inputs.iter() {
+            let outpoint = vector_outpoint(txid, *vout);
+            let signing_key = hex_key(private_key);
+            if *taproot {
+                vin.push(BitcoinTxIn::new(outpoint, vec![], 0xffff_fffd));
+                witnesses.push(vec![WitnessStackItem::new(vec![0x01; 64])]);
+                prevouts.push(TxOut::new(value, ScriptPubkey::p2tr(&xonly(&signing_key))));
+            } else {
+                let key_bytes = signing_key.verifying_key().to_bytes();
+                let script_sig = [&[71][..], &[0x30; 71], &[33], &key_bytes[..]].concat();
+                vin.push(BitcoinTxIn::new(outpoint, script_sig, 0xffff_fffd));
+                witnesses.push(vec![]);
+                prevouts.push(TxOut::new(value, ScriptPubkey::p2pkh(&signing_key)));
+            }
+        }
+        let vout: Vec<_> = outputs
+            .iter()
+            .map(|output| TxOut::new(value, ScriptPubkey::p2tr(output)))
+            .collect();
+        let tx = <WitnessTx as WitnessTransaction>::new(2, vin, vout, witnesses, 0).unwrap();
+        (tx, prevouts)
+    }
+
+    fn vector_keys(inputs: &[(&str, u32, &str, bool)]) -> (Vec<BitcoinOutpoint>, Vec<InputKey>) {
+        let outpoints = inputs
+            .iter()
+            .map(|(txid, vout, _, _)| vector_outpoint(txid, *vout))
+            .collect();
+        let keys = inputs
+            .iter()
+            .map(|(_, _, private_key, taproot)| match taproot {
+                true => InputKey::Taproot(hex_key(private_key)),
+                false => InputKey::Ecdsa(hex_key(private_key)),
+            })
+            .collect();
+        (outpoints, keys)
+    }
+
+    #[test]
+    fn it_matches_bip352_vectors() {
+        let recipient = SilentPaymentAddress::decode("sp", VECTOR_ADDRESS).unwrap();
+        let scan_key = hex_key(VECTOR_SCAN_KEY);
+        let spend_key = hex_key(VECTOR_SPEND_KEY);
+        assert_eq!(
+            recipient,
+            SilentPaymentAddress::new(scan_key.verifying_key(), spend_key.verifying_key())
+        );
+
+        for vector in VECTORS.iter() {
+            let (outpoints, keys) = vector_keys(vector.inputs);
+            let outputs = derive_outputs(&outpoints, &keys, &[recipient]).unwrap();
+            assert_eq!(hex::encode(outputs[0]), vector.output);
+
+            let (tx, prevouts) = vector_tx(vector.inputs, &outputs);
+            let found = scan(&tx, &prevouts, &scan_key, recipient.spend_key(), &[]).unwrap();
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].output_key, outputs[0]);
+            assert_eq!(found[0].label, None);
+            let spending_key = found[0].spending_key(&spend_key).unwrap();
+            assert_eq!(
+                &spending_key.verifying_key().to_bytes()[1..],
+                &found[0].output_key[..]
+            );
+        }
+    }
+
+    #[test]
+    fn it_scans_for_labels() {
+        let recipient = SilentPaymentAddress::decode("sp", VECTOR_ADDRESS).unwrap();
+        let scan_key = hex_key(VECTOR_SCAN_KEY);
+        let spend_key = hex_key(VECTOR_SPEND_KEY);
+        let inputs = VECTORS[0].inputs;
+        let (outpoints, keys) = vector_keys(inputs);
+
+        // the labeled addresses of the BIP352 vectors
+        let labeled = [
+            (1, "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqaxww2fnhrx05cghth75n0qcj59e3e2anscr0q9wyknjxtxycg07y3pevyj"),
+            (3, "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqsg59z2rppn4qlkx0yz9sdltmjv3j8zgcqadjn4ug98m3t6plujsq9qvu5n"),
+        ];
+        for (label, encoded) in labeled.iter() {
+            let address = recipient.with_label(&scan_key, *label).unwrap();
+            assert_eq!(address.encode("sp").unwrap(), *encoded);
+
+            // an unlabeled payment, and one to the labeled address
+            let outputs = derive_outputs(&outpoints, &keys, &[recipient, address]).unwrap();
+            let (tx, prevouts) = vector_tx(inputs, &outputs);
+            let found = scan(
+                &tx,
+                &prevouts,
+                &scan_key,
+                recipient.spend_key(),
+                &[2, *label],
+            )
+            .unwrap();
+            assert_eq!(found.len(), 2);
+            assert_eq!(found[0].label, None);
+            assert_eq!(found[1].index, 1);
+            assert_eq!(found[1].label, Some(*label));
+            for received in found.iter() {
+                let spending_key = received.spending_key(&spend_key).unwrap();
+                assert_eq!(
+                    &spending_key.verifying_key().to_bytes()[1..],
+                    &received.output_key[..]
+                );
+            }
+
+            // labeled outputs are missed without the label
+            let found = scan(&tx, &prevouts, &scan_key, recipient.spend_key(), &[2]).unwrap();
+            assert_eq!(found.len(), 1);
+        }
+    }
+}
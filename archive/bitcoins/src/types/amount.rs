@@ -0,0 +1,323 @@
+//! Bitcoin amounts.
+//!
+//! `Amount` holds a number of satoshis, so that values can not be confused with other integers,
+//! and so that BTC strings are parsed without floating point error.
+
+use std::{
+    convert::TryFrom,
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign, Sub, SubAssign},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+/// The number of satoshis in 1 BTC
+pub const SATS_PER_BTC: u64 = 100_000_000;
+
+/// Errors in parsing amounts
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    /// The string is not a decimal number
+    #[error("Invalid BTC amount: {0:?}")]
+    InvalidFormat(String),
+
+    /// The string has more than 8 decimal places
+    #[error("BTC amounts have at most 8 decimal places. Got {0:?}")]
+    TooPrecise(String),
+
+    /// The amount is above `Amount::MAX_MONEY`
+    #[error("Amount is above the 21 million BTC limit")]
+    TooLarge,
+}
+
+/// An amount of bitcoin, in satoshis.
+///
+/// Any u64 may be held, as the null output used in legacy sighash has a value of `u64::MAX`.
+/// `is_valid_money` checks that the amount is no more than `MAX_MONEY`, and the checked
+/// arithmetic methods fail if their result is above it.
+///
+/// `Display` and `FromStr` use BTC strings, e.g. `"0.015"`. Serde uses the number of satoshis,
+/// and rejects amounts above `MAX_MONEY` when deserializing.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(into = "u64", try_from = "u64")]
+pub struct Amount(u64);
+
+impl Amount {
+    /// Zero satoshis
+    pub const ZERO: Amount = Amount(0);
+
+    /// One satoshi
+    pub const ONE_SAT: Amount = Amount(1);
+
+    /// The 21 million BTC limit. Consensus rejects outputs and transactions paying more.
+    pub const MAX_MONEY: Amount = Amount(21_000_000 * SATS_PER_BTC);
+
+    /// Instantiate from a number of satoshis
+    pub const fn from_sat(sats: u64) -> Self {
+        Self(sats)
+    }
+
+    /// The number of satoshis
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    /// True if the amount is no more than `MAX_MONEY`
+    pub fn is_valid_money(self) -> bool {
+        self <= Self::MAX_MONEY
+    }
+
+    /// Add two amounts. `None` if the sum is above `MAX_MONEY`.
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0
+            .checked_add(other.0)
+            .map(Amount)
+            .filter(|a| a.is_valid_money())
+    }
+
+    /// Subtract an amount. `None` if the difference is negative.
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    /// Sum amounts. `None` if any partial sum is above `MAX_MONEY`.
+    pub fn checked_sum<I>(amounts: I) -> Option<Amount>
+    where
+        I: IntoIterator<Item = Amount>,
+    {
+        amounts
+            .into_iter()
+            .try_fold(Amount::ZERO, |acc, a| acc.checked_add(a))
+    }
+
+    /// Subtract an amount, stopping at zero.
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    /// Parse a BTC string, such as `"0.015"`. At most 8 decimal places are allowed.
+    pub fn from_btc_str(s: &str) -> Result<Amount, AmountError> {
+        let invalid = || AmountError::InvalidFormat(s.to_owned());
+
+        let (whole, frac) = match s.find('.') {
+            Some(idx) => (&s[..idx], &s[idx + 1..]),
+            None => (s, ""),
+        };
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if (whole.is_empty() && frac.is_empty()) || !all_digits(whole) || !all_digits(frac) {
+            return Err(invalid());
+        }
+        if frac.len() > 8 {
+            return Err(AmountError::TooPrecise(s.to_owned()));
+        }
+
+        let whole: u64 = match whole {
+            "" => 0,
+            _ => whole.parse().map_err(|_| AmountError::TooLarge)?,
+        };
+        let frac: u64 = match frac {
+            "" => 0,
+            _ => format!("{:0<8}", frac).parse().map_err(|_| invalid())?,
+        };
+        let sats = whole
+            .checked_mul(SATS_PER_BTC)
+            .and_then(|w| w.checked_add(frac))
+            .map(Amount)
+            .ok_or(AmountError::TooLarge)?;
+        if !sats.is_valid_money() {
+            return Err(AmountError::TooLarge);
+        }
+        Ok(sats)
+    }
+
+    /// Format as a BTC string, without trailing zeros. E.g. `"0.015"` or `"21"`.
+    pub fn to_btc_string(self) -> String {
+        let whole = self.0 / SATS_PER_BTC;
+        let frac = self.0 % SATS_PER_BTC;
+        if frac == 0 {
+            return whole.to_string();
+        }
+        let frac = format!("{:08}", frac);
+        format!("{}.{}", whole, frac.trim_end_matches('0'))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_btc_string())
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_btc_str(s)
+    }
+}
+
+/// Fails if the amount is above `MAX_MONEY`. Use `from_sat` to hold any u64.
+impl TryFrom<u64> for Amount {
+    type Error = AmountError;
+
+    fn try_from(sats: u64) -> Result<Self, Self::Error> {
+        let amount = Amount(sats);
+        if !amount.is_valid_money() {
+            return Err(AmountError::TooLarge);
+        }
+        Ok(amount)
+    }
+}
+
+impl From<Amount> for u64 {
+    fn from(amount: Amount) -> u64 {
+        amount.0
+    }
+}
+
+/// Panics on overflow. Use `checked_add` for untrusted amounts.
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, other: Amount) -> Amount {
+        Amount(self.0.checked_add(other.0).expect("amount overflow"))
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, other: Amount) {
+        *self = *self + other;
+    }
+}
+
+/// Panics on underflow. Use `checked_sub` for untrusted amounts.
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, other: Amount) -> Amount {
+        Amount(self.0.checked_sub(other.0).expect("amount underflow"))
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, other: Amount) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Amount> for Amount {
+    fn sum<I: Iterator<Item = &'a Amount>>(iter: I) -> Amount {
+        iter.copied().sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_parses_and_formats_btc_strings() {
+        let cases = [
+            ("0.015", 1_500_000, "0.015"),
+            ("1", SATS_PER_BTC, "1"),
+            ("1.", SATS_PER_BTC, "1"),
+            (".5", 50_000_000, "0.5"),
+            ("0.00000001", 1, "0.00000001"),
+            (
+                "20999999.99999999",
+                21_000_000 * SATS_PER_BTC - 1,
+                "20999999.99999999",
+            ),
+            ("21000000", 21_000_000 * SATS_PER_BTC, "21000000"),
+            ("0", 0, "0"),
+        ];
+        for (s, sats, formatted) in cases.iter() {
+            let amount: Amount = s.parse().unwrap();
+            assert_eq!(amount.to_sat(), *sats);
+            assert_eq!(&amount.to_string(), formatted);
+        }
+    }
+
+    #[test]
+    fn it_rejects_bad_btc_strings() {
+        assert_eq!(
+            Amount::from_btc_str("0.000000001"),
+            Err(AmountError::TooPrecise("0.000000001".to_owned()))
+        );
+        assert_eq!(
+            Amount::from_btc_str("21000000.00000001"),
+            Err(AmountError::TooLarge)
+        );
+        assert_eq!(
+            Amount::from_btc_str("99999999999999999999"),
+            Err(AmountError::TooLarge)
+        );
+        for s in ["", ".", "-1", "1.5.0", "1e8", " 1", "0x10"].iter() {
+            assert_eq!(
+                Amount::from_btc_str(s),
+                Err(AmountError::InvalidFormat(s.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn it_checks_arithmetic() {
+        let a = Amount::from_sat(5_000);
+        let b = Amount::from_sat(3_000);
+        assert_eq!(a.checked_add(b), Some(Amount::from_sat(8_000)));
+        assert_eq!(a.checked_sub(b), Some(Amount::from_sat(2_000)));
+        assert_eq!(b.checked_sub(a), None);
+        assert_eq!(b.saturating_sub(a), Amount::ZERO);
+        assert_eq!(Amount::MAX_MONEY.checked_add(Amount::ONE_SAT), None);
+        assert_eq!(Amount::from_sat(u64::MAX).checked_add(a), None);
+        assert!(!Amount::from_sat(u64::MAX).is_valid_money());
+
+        assert_eq!(
+            Amount::checked_sum(vec![a, b, a]),
+            Some(Amount::from_sat(13_000))
+        );
+        assert_eq!(
+            Amount::checked_sum(vec![Amount::MAX_MONEY, a, Amount::ZERO]),
+            None
+        );
+        assert_eq!(vec![a, b].into_iter().sum::<Amount>(), a + b);
+    }
+
+    #[test]
+    fn it_rejects_invalid_money_when_deserializing() {
+        use serde::{
+            de::{value::Error, IntoDeserializer},
+            Deserialize,
+        };
+
+        let de =
+            |sats: u64| Amount::deserialize(IntoDeserializer::<Error>::into_deserializer(sats));
+        assert_eq!(de(5_000).unwrap(), Amount::from_sat(5_000));
+        assert_eq!(de(Amount::MAX_MONEY.to_sat()).unwrap(), Amount::MAX_MONEY);
+        assert!(de(Amount::MAX_MONEY.to_sat() + 1).is_err());
+        assert!(de(u64::MAX).is_err());
+
+        assert_eq!(Amount::try_from(3_000), Ok(Amount::from_sat(3_000)));
+        assert_eq!(Amount::try_from(u64::MAX), Err(AmountError::TooLarge));
+    }
+}
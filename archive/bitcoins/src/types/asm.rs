@@ -0,0 +1,328 @@
+//! Script assembly and disassembly.
+//!
+//! Scripts are rendered as space-separated opcode names and hex push data, using Bitcoin Core's
+//! opcode names. Direct pushes of 1 to 75 bytes are rendered as their data alone, while
+//! `OP_PUSHDATA1`, `OP_PUSHDATA2` and `OP_PUSHDATA4` are rendered with the opcode followed by
+//! the data, so that non-minimal pushes survive a round trip. An empty push is rendered as `0x`.
+//!
+//! `Script::from_asm(&script.to_asm())` returns the original script, unless the script ends in
+//! a truncated push. Truncated pushes are rendered as `[error]`, and can not be assembled.
+
+use thiserror::Error;
+
+use crate::types::script::{Instructions, Script};
+
+/// Errors in assembling scripts
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AsmError {
+    /// The token is neither an opcode name nor hex data
+    #[error("Unknown opcode or invalid hex: {0}")]
+    UnknownToken(String),
+
+    /// An `OP_PUSHDATA` opcode was not followed by hex data
+    #[error("{0} must be followed by hex data")]
+    MissingPushData(&'static str),
+
+    /// The data is too long for the `OP_PUSHDATA` opcode
+    #[error("{len} bytes is too long for {op}")]
+    PushTooLong {
+        /// The push opcode
+        op: &'static str,
+        /// The length of the data
+        len: usize,
+    },
+}
+
+/// Result type for script assembly
+pub type AsmResult<T> = Result<T, AsmError>;
+
+/// The name of an opcode. Direct pushes (`0x01` to `0x4b`) have no name. Undefined opcodes are
+/// named `OP_UNKNOWN_0x..`.
+fn opcode_name(op: u8) -> Option<String> {
+    let name = match op {
+        0x00 => "OP_0",
+        0x01..=0x4b => return None,
+        0x4c => "OP_PUSHDATA1",
+        0x4d => "OP_PUSHDATA2",
+        0x4e => "OP_PUSHDATA4",
+        0x4f => "OP_1NEGATE",
+        0x50 => "OP_RESERVED",
+        0x51..=0x60 => return Some(format!("OP_{}", op - 0x50)),
+        0x61 => "OP_NOP",
+        0x62 => "OP_VER",
+        0x63 => "OP_IF",
+        0x64 => "OP_NOTIF",
+        0x65 => "OP_VERIF",
+        0x66 => "OP_VERNOTIF",
+        0x67 => "OP_ELSE",
+        0x68 => "OP_ENDIF",
+        0x69 => "OP_VERIFY",
+        0x6a => "OP_RETURN",
+        0x6b => "OP_TOALTSTACK",
+        0x6c => "OP_FROMALTSTACK",
+        0x6d => "OP_2DROP",
+        0x6e => "OP_2DUP",
+        0x6f => "OP_3DUP",
+        0x70 => "OP_2OVER",
+        0x71 => "OP_2ROT",
+        0x72 => "OP_2SWAP",
+        0x73 => "OP_IFDUP",
+        0x74 => "OP_DEPTH",
+        0x75 => "OP_DROP",
+        0x76 => "OP_DUP",
+        0x77 => "OP_NIP",
+        0x78 => "OP_OVER",
+        0x79 => "OP_PICK",
+        0x7a => "OP_ROLL",
+        0x7b => "OP_ROT",
+        0x7c => "OP_SWAP",
+        0x7d => "OP_TUCK",
+        0x7e => "OP_CAT",
+        0x7f => "OP_SUBSTR",
+        0x80 => "OP_LEFT",
+        0x81 => "OP_RIGHT",
+        0x82 => "OP_SIZE",
+        0x83 => "OP_INVERT",
+        0x84 => "OP_AND",
+        0x85 => "OP_OR",
+        0x86 => "OP_XOR",
+        0x87 => "OP_EQUAL",
+        0x88 => "OP_EQUALVERIFY",
+        0x89 => "OP_RESERVED1",
+        0x8a => "OP_RESERVED2",
+        0x8b => "OP_1ADD",
+        0x8c => "OP_1SUB",
+        0x8d => "OP_2MUL",
+        0x8e => "OP_2DIV",
+        0x8f => "OP_NEGATE",
+        0x90 => "OP_ABS",
+        0x91 => "OP_NOT",
+        0x92 => "OP_0NOTEQUAL",
+        0x93 => "OP_ADD",
+        0x94 => "OP_SUB",
+        0x95 => "OP_MUL",
+        0x96 => "OP_DIV",
+        0x97 => "OP_MOD",
+        0x98 => "OP_LSHIFT",
+        0x99 => "OP_RSHIFT",
+        0x9a => "OP_BOOLAND",
+        0x9b => "OP_BOOLOR",
+        0x9c => "OP_NUMEQUAL",
+        0x9d => "OP_NUMEQUALVERIFY",
+        0x9e => "OP_NUMNOTEQUAL",
+        0x9f => "OP_LESSTHAN",
+        0xa0 => "OP_GREATERTHAN",
+        0xa1 => "OP_LESSTHANOREQUAL",
+        0xa2 => "OP_GREATERTHANOREQUAL",
+        0xa3 => "OP_MIN",
+        0xa4 => "OP_MAX",
+        0xa5 => "OP_WITHIN",
+        0xa6 => "OP_RIPEMD160",
+        0xa7 => "OP_SHA1",
+        0xa8 => "OP_SHA256",
+        0xa9 => "OP_HASH160",
+        0xaa => "OP_HASH256",
+        0xab => "OP_CODESEPARATOR",
+        0xac => "OP_CHECKSIG",
+        0xad => "OP_CHECKSIGVERIFY",
+        0xae => "OP_CHECKMULTISIG",
+        0xaf => "OP_CHECKMULTISIGVERIFY",
+        0xb0 => "OP_NOP1",
+        0xb1 => "OP_CHECKLOCKTIMEVERIFY",
+        0xb2 => "OP_CHECKSEQUENCEVERIFY",
+        0xb3..=0xb9 => return Some(format!("OP_NOP{}", op - 0xaf)),
+        0xba => "OP_CHECKSIGADD",
+        0xff => "OP_INVALIDOPCODE",
+        _ => return Some(format!("OP_UNKNOWN_{:#04x}", op)),
+    };
+    Some(name.to_owned())
+}
+
+/// The opcode with this name. Also accepts the aliases `OP_FALSE`, `OP_TRUE`, `OP_NOP2` and
+/// `OP_NOP3`
+fn opcode_by_name(name: &str) -> Option<u8> {
+    match name {
+        "OP_FALSE" => return Some(0x00),
+        "OP_TRUE" => return Some(0x51),
+        "OP_NOP2" => return Some(0xb1),
+        "OP_NOP3" => return Some(0xb2),
+        _ => {}
+    }
+    (0..=0xff).find(|op| opcode_name(*op).as_deref() == Some(name))
+}
+
+/// Parse a hex data token. `0x` prefixes are allowed
+fn parse_hex(token: &str) -> Option<Vec<u8>> {
+    hex::decode(token.strip_prefix("0x").unwrap_or(token)).ok()
+}
+
+impl Script {
+    /// Disassemble the script. See the module docs for the format.
+    pub fn to_asm(&self) -> String {
+        let script = self.items();
+        let mut tokens = vec![];
+        let mut consumed = 0;
+        for (op, data) in Instructions::new(script) {
+            let len_bytes = match op {
+                0x4c => 1,
+                0x4d => 2,
+                0x4e => 4,
+                _ => 0,
+            };
+            consumed += 1 + len_bytes + data.len();
+            if let Some(name) = opcode_name(op) {
+                tokens.push(name);
+            }
+            if (0x01..=0x4e).contains(&op) {
+                tokens.push(if data.is_empty() {
+                    "0x".to_owned()
+                } else {
+                    hex::encode(data)
+                });
+            }
+        }
+        if consumed != script.len() {
+            tokens.push("[error]".to_owned());
+        }
+        tokens.join(" ")
+    }
+
+    /// Assemble a script from opcode names and hex data. Hex data not preceded by an
+    /// `OP_PUSHDATA` opcode is pushed with the smallest push opcode that fits it.
+    pub fn from_asm(asm: &str) -> AsmResult<Script> {
+        let mut script = vec![];
+        let mut tokens = asm.split_whitespace();
+        while let Some(token) = tokens.next() {
+            if let Some(op) = opcode_by_name(token) {
+                script.push(op);
+                let (name, len_bytes) = match op {
+                    0x4c => ("OP_PUSHDATA1", 1),
+                    0x4d => ("OP_PUSHDATA2", 2),
+                    0x4e => ("OP_PUSHDATA4", 4),
+                    _ => continue,
+                };
+                let data = tokens
+                    .next()
+                    .and_then(parse_hex)
+                    .ok_or(AsmError::MissingPushData(name))?;
+                let len = (data.len() as u64).to_le_bytes();
+                if len[len_bytes..].iter().any(|b| *b != 0) {
+                    return Err(AsmError::PushTooLong {
+                        op: name,
+                        len: data.len(),
+                    });
+                }
+                script.extend_from_slice(&len[..len_bytes]);
+                script.extend_from_slice(&data);
+                continue;
+            }
+            let data = parse_hex(token).ok_or_else(|| AsmError::UnknownToken(token.to_owned()))?;
+            push_minimal(&mut script, &data)?;
+        }
+        Ok(script.into())
+    }
+}
+
+/// Push data with the smallest push opcode that fits it
+fn push_minimal(script: &mut Vec<u8>, data: &[u8]) -> AsmResult<()> {
+    let len = data.len();
+    match len {
+        0..=0x4b => script.push(len as u8),
+        0x4c..=0xff => script.extend_from_slice(&[0x4c, len as u8]),
+        0x100..=0xffff => {
+            script.push(0x4d);
+            script.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+        _ => {
+            if len > u32::MAX as usize {
+                return Err(AsmError::PushTooLong {
+                    op: "OP_PUSHDATA4",
+                    len,
+                });
+            }
+            script.push(0x4e);
+            script.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+    }
+    script.extend_from_slice(data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_disassembles_scripts() {
+        let cases = [
+            (
+                "76a914000000000000000000000000000000000000000088ac",
+                "OP_DUP OP_HASH160 0000000000000000000000000000000000000000 OP_EQUALVERIFY OP_CHECKSIG",
+            ),
+            ("0014deadbeefdeadbeefdeadbeefdeadbeefdeadbeef", "OP_0 deadbeefdeadbeefdeadbeefdeadbeefdeadbeef"),
+            ("6a4c03abcdef", "OP_RETURN OP_PUSHDATA1 abcdef"),
+            ("4d0000b1b2ba4f60", "OP_PUSHDATA2 0x OP_CHECKLOCKTIMEVERIFY OP_CHECKSEQUENCEVERIFY OP_CHECKSIGADD OP_1NEGATE OP_16"),
+            ("b3b9bbff", "OP_NOP4 OP_NOP10 OP_UNKNOWN_0xbb OP_INVALIDOPCODE"),
+            ("", ""),
+        ];
+        for (script, asm) in cases.iter() {
+            let script = Script::new(hex::decode(script).unwrap());
+            assert_eq!(&script.to_asm(), asm);
+            assert_eq!(Script::from_asm(asm).unwrap(), script);
+        }
+
+        // truncated pushes
+        let script = Script::new(hex::decode("76140000").unwrap());
+        assert_eq!(script.to_asm(), "OP_DUP [error]");
+        assert!(Script::from_asm(&script.to_asm()).is_err());
+    }
+
+    #[test]
+    fn it_round_trips_every_opcode() {
+        for op in 0..=0xffu8 {
+            let mut script = vec![op];
+            match op {
+                0x01..=0x4b => script.extend(std::iter::repeat_n(0xab, op as usize)),
+                0x4c..=0x4e => script.extend(std::iter::repeat_n(0, 1 << (op - 0x4c))),
+                _ => {}
+            }
+            let script = Script::new(script);
+            assert_eq!(Script::from_asm(&script.to_asm()).unwrap(), script);
+        }
+    }
+
+    #[test]
+    fn it_assembles_scripts() {
+        assert_eq!(
+            Script::from_asm("OP_TRUE OP_FALSE OP_NOP2 OP_NOP3 0x").unwrap(),
+            Script::new(vec![0x51, 0x00, 0xb1, 0xb2, 0x00])
+        );
+        let data = vec![0xaa; 300];
+        let script = Script::from_asm(&hex::encode(&data)).unwrap();
+        assert_eq!(&script.items()[..3], &[0x4d, 0x2c, 0x01]);
+        assert_eq!(
+            script.to_asm(),
+            format!("OP_PUSHDATA2 {}", hex::encode(&data))
+        );
+
+        assert_eq!(
+            Script::from_asm("OP_DUP OP_FOO"),
+            Err(AsmError::UnknownToken("OP_FOO".to_owned()))
+        );
+        assert_eq!(
+            Script::from_asm("abc"),
+            Err(AsmError::UnknownToken("abc".to_owned()))
+        );
+        assert_eq!(
+            Script::from_asm("OP_PUSHDATA1"),
+            Err(AsmError::MissingPushData("OP_PUSHDATA1"))
+        );
+        assert_eq!(
+            Script::from_asm(&format!("OP_PUSHDATA1 {}", hex::encode(&data))),
+            Err(AsmError::PushTooLong {
+                op: "OP_PUSHDATA1",
+                len: 300
+            })
+        );
+    }
+}
@@ -0,0 +1,552 @@
+//! Bitcoin block and block header types.
+use std::io::{Read, Write};
+
+use coins_core::{
+    hashes::*,
+    ser::{self, ByteFormat, SerError, SerResult},
+    types::tx::Transaction,
+};
+
+use thiserror::Error;
+
+use crate::{
+    hashes::{BlockHash, TXID},
+    types::tx::{BitcoinTx, TxError},
+};
+
+/// The most transactions that fit in a block, given the minimum transaction weight
+const MAX_BLOCK_TXNS: u32 = 4_000_000 / 240;
+
+fn hash_pair(left: &Hash256Digest, right: &Hash256Digest) -> Hash256Digest {
+    let mut ctx = Hash256::default();
+    ctx.write_all(left.as_slice())
+        .expect("no error on heap allocation");
+    ctx.write_all(right.as_slice())
+        .expect("no error on heap allocation");
+    ctx.finalize_marked()
+}
+
+/// Calculate the merkle root of a list of TXIDs. The last node of each odd-length level is
+/// paired with itself.
+pub fn merkle_root(txids: &[TXID]) -> Hash256Digest {
+    let mut level: Vec<Hash256Digest> = txids.iter().map(|t| t.to_internal().into()).collect();
+    if level.is_empty() {
+        return Hash256Digest::default();
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    level[0]
+}
+
+/// An 80-byte Bitcoin block header.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BlockHeader {
+    /// The block version
+    pub version: u32,
+    /// The hash of the previous block
+    pub parent: BlockHash,
+    /// The root of the merkle tree of the block's TXIDs
+    pub merkle_root: Hash256Digest,
+    /// The block timestamp
+    pub timestamp: u32,
+    /// The compact-encoded difficulty target
+    pub bits: u32,
+    /// The nonce
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    /// Calculate the block hash
+    pub fn hash(&self) -> BlockHash {
+        let mut ctx = Hash256::default();
+        self.write_to(&mut ctx)
+            .expect("no error on heap allocation");
+        ctx.finalize_marked()
+    }
+}
+
+impl ByteFormat for BlockHeader {
+    type Error = SerError;
+
+    fn serialized_length(&self) -> usize {
+        80
+    }
+
+    fn read_from<R>(reader: &mut R) -> SerResult<Self>
+    where
+        R: Read,
+        Self: std::marker::Sized,
+    {
+        Ok(Self {
+            version: ser::read_u32_le(reader)?,
+            parent: BlockHash::read_from(reader)?,
+            merkle_root: Hash256Digest::read_from(reader)?,
+            timestamp: ser::read_u32_le(reader)?,
+            bits: ser::read_u32_le(reader)?,
+            nonce: ser::read_u32_le(reader)?,
+        })
+    }
+
+    fn write_to<W>(&self, writer: &mut W) -> SerResult<usize>
+    where
+        W: Write,
+    {
+        let mut len = ser::write_u32_le(writer, self.version)?;
+        len += self.parent.write_to(writer)?;
+        len += self.merkle_root.write_to(writer)?;
+        len += ser::write_u32_le(writer, self.timestamp)?;
+        len += ser::write_u32_le(writer, self.bits)?;
+        len += ser::write_u32_le(writer, self.nonce)?;
+        Ok(len)
+    }
+}
+
+/// A Bitcoin block. A header, followed by a length-prefixed list of transactions.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Block {
+    /// The block header
+    pub header: BlockHeader,
+    /// The transactions in the block, starting with the coinbase
+    pub txns: Vec<BitcoinTx>,
+}
+
+impl Block {
+    /// Instantiate a new block
+    pub fn new(header: BlockHeader, txns: Vec<BitcoinTx>) -> Self {
+        Self { header, txns }
+    }
+
+    /// Calculate the block hash
+    pub fn hash(&self) -> BlockHash {
+        self.header.hash()
+    }
+
+    /// The TXIDs of the block's transactions, in block order
+    pub fn txids(&self) -> Vec<TXID> {
+        self.txns.iter().map(Transaction::txid).collect()
+    }
+
+    /// Find a transaction in the block by its TXID
+    pub fn find_tx(&self, txid: TXID) -> Option<&BitcoinTx> {
+        self.txns.iter().find(|tx| tx.txid() == txid)
+    }
+
+    /// Calculate the merkle root of the block's TXIDs
+    pub fn compute_merkle_root(&self) -> Hash256Digest {
+        merkle_root(&self.txids())
+    }
+
+    /// True if the header's merkle root commits to the block's transactions
+    pub fn check_merkle_root(&self) -> bool {
+        self.compute_merkle_root() == self.header.merkle_root
+    }
+}
+
+impl ByteFormat for Block {
+    type Error = TxError;
+
+    fn serialized_length(&self) -> usize {
+        let mut len = self.header.serialized_length();
+        len += ser::prefix_byte_len(self.txns.len() as u64) as usize;
+        len += self
+            .txns
+            .iter()
+            .map(ByteFormat::serialized_length)
+            .sum::<usize>();
+        len
+    }
+
+    fn read_from<R>(reader: &mut R) -> Result<Self, Self::Error>
+    where
+        R: Read,
+        Self: std::marker::Sized,
+    {
+        let header = BlockHeader::read_from(reader)?;
+        let txns = ser::read_prefix_vec(reader)?;
+        Ok(Self { header, txns })
+    }
+
+    fn write_to<W>(&self, writer: &mut W) -> Result<usize, Self::Error>
+    where
+        W: Write,
+    {
+        let mut len = self.header.write_to(writer)?;
+        len += ser::write_prefix_vec(writer, &self.txns)?;
+        Ok(len)
+    }
+}
+
+/// Errors in validating a `MerkleBlock`
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MerkleBlockError {
+    /// The merkle block claims the block has no transactions
+    #[error("Merkle block has no transactions")]
+    NoTransactions,
+
+    /// The merkle block claims more transactions than fit in a block
+    #[error("Merkle block claims {0} transactions")]
+    TooManyTransactions(u32),
+
+    /// The partial merkle tree ran out of flag bits or hashes
+    #[error("Partial merkle tree is truncated")]
+    Truncated,
+
+    /// The partial merkle tree has flag bits or hashes left over after traversal
+    #[error("Partial merkle tree has unused flag bits or hashes")]
+    UnusedData,
+
+    /// A node has identical children. This allows forged trees, as in CVE-2012-2459
+    #[error("Partial merkle tree has a node with identical children")]
+    DuplicateHashes,
+
+    /// The partial merkle tree does not commit to the header's merkle root
+    #[error("Partial merkle tree root does not match the header")]
+    BadMerkleRoot,
+}
+
+/// Type alias for result with MerkleBlockError
+pub type MerkleBlockResult<T> = Result<T, MerkleBlockError>;
+
+/// A BIP37 merkle block. A block header, and a partial merkle tree proving that some
+/// transactions are in the block. Sent by peers in response to filtered block requests.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MerkleBlock {
+    /// The block header
+    pub header: BlockHeader,
+    /// The number of transactions in the block
+    pub total_txns: u32,
+    /// The hashes of the partial merkle tree, in depth-first order
+    pub hashes: Vec<Hash256Digest>,
+    /// The flag bits of the partial merkle tree, in depth-first order, packed least significant
+    /// bit first
+    pub flags: Vec<u8>,
+}
+
+/// Walks a partial merkle tree. Tracks how many flag bits and hashes have been used
+struct PartialTree<'a> {
+    total: u32,
+    hashes: &'a [Hash256Digest],
+    flags: &'a [u8],
+    bits_used: usize,
+    hashes_used: usize,
+}
+
+impl PartialTree<'_> {
+    fn width(total: u32, height: u32) -> u32 {
+        (total + (1 << height) - 1) >> height
+    }
+
+    fn next_bit(&mut self) -> MerkleBlockResult<bool> {
+        let byte = self
+            .flags
+            .get(self.bits_used / 8)
+            .ok_or(MerkleBlockError::Truncated)?;
+        let bit = byte >> (self.bits_used % 8) & 1 == 1;
+        self.bits_used += 1;
+        Ok(bit)
+    }
+
+    fn next_hash(&mut self) -> MerkleBlockResult<Hash256Digest> {
+        let hash = self
+            .hashes
+            .get(self.hashes_used)
+            .ok_or(MerkleBlockError::Truncated)?;
+        self.hashes_used += 1;
+        Ok(*hash)
+    }
+
+    fn extract(
+        &mut self,
+        height: u32,
+        pos: u32,
+        matches: &mut Vec<(u32, TXID)>,
+    ) -> MerkleBlockResult<Hash256Digest> {
+        let parent_of_match = self.next_bit()?;
+        if height == 0 || !parent_of_match {
+            let hash = self.next_hash()?;
+            if height == 0 && parent_of_match {
+                matches.push((pos, TXID::from(hash.to_internal())));
+            }
+            return Ok(hash);
+        }
+        let left = self.extract(height - 1, pos * 2, matches)?;
+        let right = if pos * 2 + 1 < Self::width(self.total, height - 1) {
+            let right = self.extract(height - 1, pos * 2 + 1, matches)?;
+            if right == left {
+                return Err(MerkleBlockError::DuplicateHashes);
+            }
+            right
+        } else {
+            left
+        };
+        Ok(hash_pair(&left, &right))
+    }
+}
+
+fn tree_height(total: u32) -> u32 {
+    let mut height = 0;
+    while PartialTree::width(total, height) > 1 {
+        height += 1;
+    }
+    height
+}
+
+impl MerkleBlock {
+    /// Build a merkle block proving the inclusion of each transaction in `txids` for which
+    /// `matches` returns true. `txids` must be all of the block's TXIDs, in block order.
+    pub fn from_txids<F>(header: BlockHeader, txids: &[TXID], matches: F) -> Self
+    where
+        F: Fn(&TXID) -> bool,
+    {
+        let leaves: Vec<Hash256Digest> = txids.iter().map(|t| t.to_internal().into()).collect();
+        let matched: Vec<bool> = txids.iter().map(matches).collect();
+        let total = txids.len() as u32;
+
+        fn calc_hash(leaves: &[Hash256Digest], total: u32, height: u32, pos: u32) -> Hash256Digest {
+            if height == 0 {
+                return leaves[pos as usize];
+            }
+            let left = calc_hash(leaves, total, height - 1, pos * 2);
+            let right = if pos * 2 + 1 < PartialTree::width(total, height - 1) {
+                calc_hash(leaves, total, height - 1, pos * 2 + 1)
+            } else {
+                left
+            };
+            hash_pair(&left, &right)
+        }
+
+        fn build(
+            leaves: &[Hash256Digest],
+            matched: &[bool],
+            height: u32,
+            pos: u32,
+            bits: &mut Vec<bool>,
+            hashes: &mut Vec<Hash256Digest>,
+        ) {
+            let total = leaves.len() as u32;
+            let start = (pos << height) as usize;
+            let end = std::cmp::min(((pos + 1) << height) as usize, leaves.len());
+            let parent_of_match = matched[start..end].iter().any(|m| *m);
+            bits.push(parent_of_match);
+            if height == 0 || !parent_of_match {
+                hashes.push(calc_hash(leaves, total, height, pos));
+            } else {
+                build(leaves, matched, height - 1, pos * 2, bits, hashes);
+                if pos * 2 + 1 < PartialTree::width(total, height - 1) {
+                    build(leaves, matched, height - 1, pos * 2 + 1, bits, hashes);
+                }
+            }
+        }
+
+        let mut bits = vec![];
+        let mut hashes = vec![];
+        if total > 0 {
+            build(
+                &leaves,
+                &matched,
+                tree_height(total),
+                0,
+                &mut bits,
+                &mut hashes,
+            );
+        }
+        let mut flags = vec![0u8; bits.len().div_ceil(8)];
+        for (i, bit) in bits.iter().enumerate() {
+            flags[i / 8] |= (*bit as u8) << (i % 8);
+        }
+
+        Self {
+            header,
+            total_txns: total,
+            hashes,
+            flags,
+        }
+    }
+
+    /// Build a merkle block proving the inclusion of each transaction in `block` for which
+    /// `matches` returns true
+    pub fn from_block<F>(block: &Block, matches: F) -> Self
+    where
+        F: Fn(&TXID) -> bool,
+    {
+        Self::from_txids(block.header, &block.txids(), matches)
+    }
+
+    /// Validate the partial merkle tree against the header's merkle root, and return the
+    /// matched transactions as `(index in block, TXID)` pairs.
+    pub fn extract_matches(&self) -> MerkleBlockResult<Vec<(u32, TXID)>> {
+        if self.total_txns == 0 {
+            return Err(MerkleBlockError::NoTransactions);
+        }
+        if self.total_txns > MAX_BLOCK_TXNS {
+            return Err(MerkleBlockError::TooManyTransactions(self.total_txns));
+        }
+        // each hash needs at least one flag bit
+        if self.hashes.len() > self.total_txns as usize || self.flags.len() * 8 < self.hashes.len()
+        {
+            return Err(MerkleBlockError::Truncated);
+        }
+
+        let mut tree = PartialTree {
+            total: self.total_txns,
+            hashes: &self.hashes,
+            flags: &self.flags,
+            bits_used: 0,
+            hashes_used: 0,
+        };
+        let mut matches = vec![];
+        let root = tree.extract(tree_height(self.total_txns), 0, &mut matches)?;
+
+        // unused bits are only allowed as padding in the last byte
+        if tree.bits_used.div_ceil(8) != self.flags.len() || tree.hashes_used != self.hashes.len() {
+            return Err(MerkleBlockError::UnusedData);
+        }
+        if root != self.header.merkle_root {
+            return Err(MerkleBlockError::BadMerkleRoot);
+        }
+        Ok(matches)
+    }
+}
+
+impl ByteFormat for MerkleBlock {
+    type Error = SerError;
+
+    fn serialized_length(&self) -> usize {
+        let mut len = self.header.serialized_length();
+        len += 4; // total txns
+        len += ser::prefix_byte_len(self.hashes.len() as u64) as usize;
+        len += self.hashes.len() * 32;
+        len += ser::prefix_byte_len(self.flags.len() as u64) as usize;
+        len += self.flags.len();
+        len
+    }
+
+    fn read_from<R>(reader: &mut R) -> SerResult<Self>
+    where
+        R: Read,
+        Self: std::marker::Sized,
+    {
+        let header = BlockHeader::read_from(reader)?;
+        let total_txns = ser::read_u32_le(reader)?;
+        let hashes = ser::read_prefix_vec(reader)?;
+        let flag_len = ser::read_compact_int(reader)?;
+        // a byte of flags per transaction is more than enough. Guards the allocation
+        if flag_len > total_txns as u64 + 1 {
+            return Err(SerError::ComponentError(format!(
+                "{} flag bytes for {} transactions",
+                flag_len, total_txns
+            )));
+        }
+        let mut flags = vec![0u8; flag_len as usize];
+        reader.read_exact(&mut flags)?;
+        Ok(Self {
+            header,
+            total_txns,
+            hashes,
+            flags,
+        })
+    }
+
+    fn write_to<W>(&self, writer: &mut W) -> SerResult<usize>
+    where
+        W: Write,
+    {
+        let mut len = self.header.write_to(writer)?;
+        len += ser::write_u32_le(writer, self.total_txns)?;
+        len += ser::write_prefix_vec(writer, &self.hashes)?;
+        len += ser::write_compact_int(writer, self.flags.len() as u64)?;
+        writer.write_all(&self.flags)?;
+        len += self.flags.len();
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_serializes_and_deserializes_blocks() {
+        // mainnet genesis
+        let hex = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c0101000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+        let block = Block::deserialize_hex(hex).unwrap();
+
+        assert_eq!(block.serialized_length(), hex.len() / 2);
+        assert_eq!(block.serialize_hex(), hex);
+        assert_eq!(block.txns.len(), 1);
+        assert_eq!(
+            block.hash(),
+            BlockHash::from_be_hex(
+                "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+            )
+            .unwrap()
+        );
+        assert!(block.check_merkle_root());
+        assert!(block.find_tx(block.txids()[0]).is_some());
+        assert!(block.find_tx(TXID::default()).is_none());
+    }
+
+    fn txid(n: u8) -> TXID {
+        let mut txid = TXID::default();
+        txid.as_mut_slice()[0] = n;
+        txid
+    }
+
+    #[test]
+    fn it_builds_and_extracts_merkle_blocks() {
+        for total in 1..=9u8 {
+            let txids: Vec<TXID> = (0..total).map(txid).collect();
+            let header = BlockHeader {
+                merkle_root: merkle_root(&txids),
+                ..Default::default()
+            };
+            let mb = MerkleBlock::from_txids(header, &txids, |t| t.as_slice()[0] % 3 == 1);
+            let expected: Vec<(u32, TXID)> = (0..total)
+                .filter(|n| n % 3 == 1)
+                .map(|n| (n as u32, txid(n)))
+                .collect();
+            assert_eq!(mb.extract_matches().unwrap(), expected);
+
+            let hex = mb.serialize_hex();
+            assert_eq!(hex.len() / 2, mb.serialized_length());
+            assert_eq!(MerkleBlock::deserialize_hex(&hex).unwrap(), mb);
+        }
+    }
+
+    #[test]
+    fn it_rejects_bad_merkle_blocks() {
+        let txids: Vec<TXID> = (0..5).map(txid).collect();
+        let header = BlockHeader {
+            merkle_root: merkle_root(&txids),
+            ..Default::default()
+        };
+        let mb = MerkleBlock::from_txids(header, &txids, |t| *t == txid(4));
+        assert_eq!(mb.extract_matches().unwrap(), vec![(4, txid(4))]);
+
+        let mut bad = mb.clone();
+        bad.hashes[0] = Hash256Digest::default();
+        assert_eq!(bad.extract_matches(), Err(MerkleBlockError::BadMerkleRoot));
+
+        let mut bad = mb.clone();
+        bad.hashes.pop();
+        assert_eq!(bad.extract_matches(), Err(MerkleBlockError::Truncated));
+
+        let mut bad = mb.clone();
+        bad.flags.push(0);
+        assert_eq!(bad.extract_matches(), Err(MerkleBlockError::UnusedData));
+
+        let mut bad = mb;
+        bad.total_txns = 0;
+        assert_eq!(bad.extract_matches(), Err(MerkleBlockError::NoTransactions));
+
+        // duplicating the last transaction produces the same root, but is rejected
+        let mut dup = txids.clone();
+        dup.push(txid(4));
+        let mb = MerkleBlock::from_txids(header, &dup, |t| *t == txid(4));
+        assert_eq!(mb.extract_matches(), Err(MerkleBlockError::DuplicateHashes));
+    }
+}
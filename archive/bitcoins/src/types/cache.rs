@@ -0,0 +1,259 @@
+//! Sighash caching for signing many inputs of one transaction.
+
+use coins_core::hashes::{Digest, DigestOutput, Hash256, Hash256Digest};
+
+use crate::{
+    hashes::tagged_hash,
+    types::{
+        tx::{Sighash, TxError, TxResult},
+        txout::TxOut,
+        witness::{TaprootInputHashes, TaprootSighashArgs, WitnessSighashArgs, WitnessTx},
+    },
+};
+
+/// Calculates sighashes for many inputs of one `WitnessTx`, reusing the hashes of its inputs and
+/// outputs.
+///
+/// Every BIP143 and BIP341 sighash commits to hashes of all inputs and outputs, unless signed
+/// with ANYONECANPAY, NONE, or SINGLE. Calculating them for each input makes signing all inputs
+/// quadratic in their number. The cache calculates each of them at most once, so signing all
+/// inputs is linear.
+///
+/// The cache borrows the transaction, so it can not be modified while cached hashes exist. The
+/// taproot input hashes commit to the prevouts of the first taproot sighash calculated. Later
+/// taproot sighashes with different prevouts error with `PrevoutMismatch`.
+#[derive(Clone, Debug)]
+pub struct SighashCache<'a> {
+    tx: &'a WitnessTx,
+    hash_prevouts: Option<Hash256Digest>,
+    hash_sequence: Option<Hash256Digest>,
+    hash_outputs: Option<Hash256Digest>,
+    // the prevouts the hashes commit to, and the hashes
+    taproot_inputs: Option<(Vec<TxOut>, TaprootInputHashes)>,
+    taproot_outputs: Option<[u8; 32]>,
+}
+
+impl<'a> SighashCache<'a> {
+    /// Instantiate an empty cache for a transaction
+    pub fn new(tx: &'a WitnessTx) -> Self {
+        Self {
+            tx,
+            hash_prevouts: None,
+            hash_sequence: None,
+            hash_outputs: None,
+            taproot_inputs: None,
+            taproot_outputs: None,
+        }
+    }
+
+    /// The cached transaction
+    pub fn tx(&self) -> &'a WitnessTx {
+        self.tx
+    }
+
+    /// Writes the BIP143 sighash preimage to the provided `writer`. See
+    /// `WitnessTransaction::write_witness_sighash_preimage`.
+    pub fn write_witness_sighash_preimage<W: std::io::Write>(
+        &mut self,
+        writer: &mut W,
+        args: &WitnessSighashArgs,
+    ) -> TxResult<()> {
+        self.tx.check_witness_sighash_args(args)?;
+        let flag = args.sighash_flag;
+        let tx = self.tx;
+
+        // Only the full hashes are cached. The others are empty, or hash a single output
+        let hash_prevouts = match flag {
+            Sighash::All | Sighash::Single => {
+                *cached(&mut self.hash_prevouts, || tx.hash_prevouts(flag))?
+            }
+            _ => tx.hash_prevouts(flag)?,
+        };
+        let hash_sequence = match flag {
+            Sighash::All => *cached(&mut self.hash_sequence, || tx.hash_sequence(flag))?,
+            _ => tx.hash_sequence(flag)?,
+        };
+        let hash_outputs = match flag {
+            Sighash::All | Sighash::AllAcp => {
+                *cached(&mut self.hash_outputs, || tx.hash_outputs(args.index, flag))?
+            }
+            _ => tx.hash_outputs(args.index, flag)?,
+        };
+
+        tx.write_witness_sighash_preimage_with(
+            writer,
+            args,
+            &hash_prevouts,
+            &hash_sequence,
+            &hash_outputs,
+        )
+    }
+
+    /// Calculates the BIP143 sighash. See `WitnessTransaction::witness_sighash`.
+    pub fn witness_sighash(
+        &mut self,
+        args: &WitnessSighashArgs,
+    ) -> TxResult<DigestOutput<Hash256>> {
+        let mut w = Hash256::default();
+        self.write_witness_sighash_preimage(&mut w, args)?;
+        Ok(w.finalize())
+    }
+
+    /// Writes the BIP341 sighash preimage to the provided `writer`. See
+    /// `WitnessTx::write_taproot_sighash_preimage`.
+    pub fn write_taproot_sighash_preimage<W: std::io::Write>(
+        &mut self,
+        writer: &mut W,
+        args: &TaprootSighashArgs,
+    ) -> TxResult<()> {
+        self.tx.check_taproot_sighash_args(args)?;
+        let hash_type = args.sighash_flag.map_or(0, Sighash::to_u8);
+        let tx = self.tx;
+
+        let inputs = if hash_type & 0x80 == 0x80 {
+            None
+        } else {
+            let prevouts: &[TxOut] = &args.prevouts;
+            let (cached_prevouts, hashes) = cached(&mut self.taproot_inputs, || {
+                Ok((prevouts.to_vec(), tx.taproot_input_hashes(prevouts)?))
+            })?;
+            if cached_prevouts.as_slice() != prevouts {
+                return Err(TxError::PrevoutMismatch);
+            }
+            Some(hashes)
+        };
+        let outputs = match hash_type & 0x03 {
+            0x00 | 0x01 => Some(*cached(&mut self.taproot_outputs, || {
+                tx.taproot_outputs_hash()
+            })?),
+            _ => None,
+        };
+
+        tx.write_taproot_sighash_preimage_with(writer, args, inputs, outputs.as_ref())
+    }
+
+    /// Calculates the BIP341 sighash. See `WitnessTx::taproot_sighash`.
+    pub fn taproot_sighash(&mut self, args: &TaprootSighashArgs) -> TxResult<[u8; 32]> {
+        let mut preimage = vec![];
+        self.write_taproot_sighash_preimage(&mut preimage, args)?;
+        Ok(tagged_hash("TapSighash", &preimage))
+    }
+}
+
+/// Get the cached value, or calculate and cache it
+fn cached<T, F>(slot: &mut Option<T>, calculate: F) -> TxResult<&T>
+where
+    F: FnOnce() -> TxResult<T>,
+{
+    if slot.is_none() {
+        *slot = Some(calculate()?);
+    }
+    Ok(slot.as_ref().expect("just set"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hashes::TXID,
+        types::{Amount, BitcoinOutpoint, BitcoinTxIn, Script, ScriptPubkey, WitnessTransaction},
+    };
+
+    #[test]
+    fn it_matches_uncached_sighashes() {
+        let vin: Vec<BitcoinTxIn> = (0..5u8)
+            .map(|i| {
+                BitcoinTxIn::new(
+                    BitcoinOutpoint::new(TXID::from([i; 32]), i as u32),
+                    vec![],
+                    0xffff_fffd,
+                )
+            })
+            .collect();
+        let vout: Vec<TxOut> = (0..3u8)
+            .map(|i| {
+                TxOut::new(
+                    Amount::from_sat(10_000),
+                    ScriptPubkey::p2wpkh_from_hash(&[i; 20]),
+                )
+            })
+            .collect();
+        let prevouts: Vec<TxOut> = (0..5u8)
+            .map(|i| TxOut::new(Amount::from_sat(20_000), ScriptPubkey::p2tr(&[i; 32])))
+            .collect();
+        let tx = <WitnessTx as WitnessTransaction>::new(2, vin, vout, vec![], 0).unwrap();
+        let mut cache = SighashCache::new(&tx);
+
+        let flags = [
+            Sighash::All,
+            Sighash::AllAcp,
+            Sighash::Single,
+            Sighash::SingleAcp,
+        ];
+        for index in 0..3 {
+            for flag in flags.iter() {
+                let args = WitnessSighashArgs {
+                    index,
+                    sighash_flag: *flag,
+                    prevout_script: Script::from(&ScriptPubkey::p2pkh_from_hash(&[9; 20])),
+                    prevout_value: Amount::from_sat(20_000),
+                };
+                assert_eq!(
+                    cache.witness_sighash(&args).unwrap(),
+                    tx.witness_sighash(&args).unwrap()
+                );
+            }
+        }
+
+        let flags = [
+            None,
+            Some(Sighash::All),
+            Some(Sighash::None),
+            Some(Sighash::NoneAcp),
+            Some(Sighash::Single),
+            Some(Sighash::AllAcp),
+        ];
+        for index in 0..3 {
+            for flag in flags.iter() {
+                let args = TaprootSighashArgs {
+                    index,
+                    sighash_flag: *flag,
+                    prevouts: prevouts.clone(),
+                    annex: None,
+                    leaf_hash: None,
+                    codesep_pos: 0xffff_ffff,
+                };
+                assert_eq!(
+                    cache.taproot_sighash(&args).unwrap(),
+                    tx.taproot_sighash(&args).unwrap()
+                );
+            }
+        }
+
+        // args are still checked
+        let args = WitnessSighashArgs {
+            index: 4,
+            sighash_flag: Sighash::Single,
+            prevout_script: vec![].into(),
+            prevout_value: Amount::from_sat(20_000),
+        };
+        assert!(cache.witness_sighash(&args).is_err());
+
+        // the cached input hashes commit to the prevouts
+        let mut other = prevouts.clone();
+        other[4] = TxOut::new(Amount::from_sat(30_000), ScriptPubkey::p2tr(&[4; 32]));
+        let args = TaprootSighashArgs {
+            index: 0,
+            sighash_flag: None,
+            prevouts: other,
+            annex: None,
+            leaf_hash: None,
+            codesep_pos: 0xffff_ffff,
+        };
+        assert!(tx.taproot_sighash(&args).is_ok());
+        assert!(matches!(
+            cache.taproot_sighash(&args),
+            Err(TxError::PrevoutMismatch)
+        ));
+    }
+}
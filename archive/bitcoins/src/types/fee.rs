@@ -0,0 +1,141 @@
+//! Feerates and fee arithmetic.
+//!
+//! Feerates are held in sat/vbyte. A vbyte is 4 weight units, so 1 sat/vbyte is 250 sat/kWU.
+
+use crate::types::tx::BitcoinTransaction;
+
+/// The number of weight units in a vbyte
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+
+/// How to round a fee to a whole number of satoshis
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round up. Never pays less than the feerate. Used by default.
+    #[default]
+    Up,
+    /// Round down
+    Down,
+    /// Round to the nearest satoshi, and half a satoshi up
+    Nearest,
+}
+
+impl Rounding {
+    fn apply(self, fee: f64) -> u64 {
+        match self {
+            Rounding::Up => fee.ceil() as u64,
+            Rounding::Down => fee.floor() as u64,
+            Rounding::Nearest => fee.round() as u64,
+        }
+    }
+}
+
+/// A feerate, in sat/vbyte.
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub struct FeeRate(pub f64);
+
+impl FeeRate {
+    /// Instantiate from a feerate in sat/vbyte
+    pub fn from_sat_per_vbyte(sat_per_vbyte: f64) -> Self {
+        Self(sat_per_vbyte)
+    }
+
+    /// Instantiate from a feerate in sat per 1000 weight units
+    pub fn from_sat_per_kwu(sat_per_kwu: f64) -> Self {
+        Self(sat_per_kwu * WITNESS_SCALE_FACTOR as f64 / 1000.0)
+    }
+
+    /// Instantiate from a feerate in BTC/kvbyte, as returned by Bitcoin Core.
+    pub fn from_btc_per_kvb(btc_per_kvb: f64) -> Self {
+        Self(btc_per_kvb * 100_000_000.0 / 1000.0)
+    }
+
+    /// The feerate paid by a fee of `fee` satoshis for `vsize` vbytes. Zero if `vsize` is 0.
+    pub fn from_fee_and_vsize(fee: u64, vsize: usize) -> Self {
+        if vsize == 0 {
+            return Self::default();
+        }
+        Self(fee as f64 / vsize as f64)
+    }
+
+    /// The feerate paid by a fee of `fee` satoshis for `weight` weight units. Zero if `weight`
+    /// is 0.
+    pub fn from_fee_and_weight(fee: u64, weight: usize) -> Self {
+        if weight == 0 {
+            return Self::default();
+        }
+        Self(fee as f64 * WITNESS_SCALE_FACTOR as f64 / weight as f64)
+    }
+
+    /// The feerate in sat/vbyte.
+    pub fn sat_per_vbyte(&self) -> f64 {
+        self.0
+    }
+
+    /// The feerate in sat per 1000 weight units.
+    pub fn sat_per_kwu(&self) -> f64 {
+        self.0 * 1000.0 / WITNESS_SCALE_FACTOR as f64
+    }
+
+    /// The fee in satoshis for a transaction of `vsize` vbytes at this feerate, rounded up.
+    pub fn fee_for_vsize(&self, vsize: usize) -> u64 {
+        self.fee_for_vsize_rounded(vsize, Rounding::Up)
+    }
+
+    /// The fee in satoshis for a transaction of `vsize` vbytes at this feerate.
+    pub fn fee_for_vsize_rounded(&self, vsize: usize, rounding: Rounding) -> u64 {
+        rounding.apply(self.0 * vsize as f64)
+    }
+
+    /// The fee in satoshis for `weight` weight units at this feerate, rounded up. May be lower
+    /// than `fee_for_vsize`, which rounds the weight up to a whole vbyte first.
+    pub fn fee_for_weight(&self, weight: usize) -> u64 {
+        self.fee_for_weight_rounded(weight, Rounding::Up)
+    }
+
+    /// The fee in satoshis for `weight` weight units at this feerate.
+    pub fn fee_for_weight_rounded(&self, weight: usize, rounding: Rounding) -> u64 {
+        rounding.apply(self.0 * weight as f64 / WITNESS_SCALE_FACTOR as f64)
+    }
+
+    /// The fee in satoshis for a transaction at this feerate, rounded up. Uses the vsize of the
+    /// transaction as it is, so it should already carry its final signatures or placeholders of
+    /// the same size.
+    pub fn fee_for_tx<T: BitcoinTransaction>(&self, tx: &T) -> u64 {
+        self.fee_for_vsize(tx.vsize())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_converts_feerates() {
+        let rate = FeeRate::from_btc_per_kvb(0.0002);
+        assert!((rate.sat_per_vbyte() - 20.0).abs() < 1e-9);
+        assert!((rate.sat_per_kwu() - 5000.0).abs() < 1e-9);
+        assert_eq!(FeeRate::from_sat_per_kwu(253.0), FeeRate(1.012));
+        assert_eq!(FeeRate::from_sat_per_vbyte(2.5), FeeRate(2.5));
+
+        assert_eq!(FeeRate::from_fee_and_vsize(1410, 141), FeeRate(10.0));
+        assert_eq!(FeeRate::from_fee_and_weight(1410, 564), FeeRate(10.0));
+        assert_eq!(FeeRate::from_fee_and_weight(1410, 0), FeeRate(0.0));
+    }
+
+    #[test]
+    fn it_calculates_fees() {
+        let rate = FeeRate(1.5);
+        assert_eq!(rate.fee_for_vsize(3), 5);
+        assert_eq!(rate.fee_for_vsize_rounded(3, Rounding::Down), 4);
+        assert_eq!(rate.fee_for_vsize_rounded(3, Rounding::Nearest), 5);
+
+        // 561 WU is 140.25 vbytes, and rounds up to a vsize of 141
+        let rate = FeeRate(10.0);
+        assert_eq!(rate.fee_for_vsize(141), 1410);
+        assert_eq!(rate.fee_for_weight(561), 1403);
+        assert_eq!(rate.fee_for_weight_rounded(561, Rounding::Down), 1402);
+        assert_eq!(rate.fee_for_weight_rounded(561, Rounding::Nearest), 1403);
+    }
+}
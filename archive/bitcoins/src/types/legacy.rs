@@ -10,7 +10,8 @@ use coins_core::{
 use crate::{
     hashes::TXID,
     types::{
-        script::{Script, ScriptSig, Witness},
+        limits::{read_len, LimitedReader, ReadLimits},
+        script::{remove_codeseparators, Script, ScriptSig, Witness},
         tx::*,
         txin::{BitcoinTxIn, Vin},
         txout::{TxOut, Vout},
@@ -37,6 +38,13 @@ use crate::{
 /// signed. If there is no output at that index, (because, e.g. the input vector is longer than
 /// the output vector) it behaves insecurely, and we do not implement that protocol bug.
 ///
+/// SIGHASH_NONE commits to ALL inputs and NO outputs, and the sequence numbers of the other
+/// inputs are not committed. It is unsupported by `legacy_sighash`.
+///
+/// `LegacyTx::consensus_sighash` implements both of these protocol quirks, as well as
+/// `OP_CODESEPARATOR` removal from the script code. It is intended for validating existing
+/// signatures. It should not be used to create new ones.
+///
 /// SIGHASH_SINGLE + ANYONECANPAY commits to ONE input and ONE output. It indicates that anyone
 /// may add additional value to the transaction, and route value to any other location. The
 /// signed input and output must be included in the fully-formed transaction at the same index in
@@ -129,6 +137,102 @@ impl LegacyTx {
         copy_tx.vin = vin;
     }
 
+    /// Modifies copy_tx according to legacy SIGHASH_NONE semantics.
+    ///
+    /// For Legacy sighash documentation, see here:
+    ///
+    /// - https://en.bitcoin.it/wiki/OP_CHECKSIG#Hashtype_SIGHASH_NONE
+    fn legacy_sighash_none(copy_tx: &mut Self, index: usize) {
+        copy_tx.vout.clear();
+        for (i, txin) in copy_tx.vin.iter_mut().enumerate() {
+            if i != index {
+                txin.sequence = 0;
+            }
+        }
+    }
+
+    /// Writes the legacy sighash preimage as consensus computes it. Unlike
+    /// `write_sighash_preimage`, this supports SIGHASH_NONE, and removes `OP_CODESEPARATOR`s
+    /// from `prevout_script`. The script must still begin after the last executed
+    /// `OP_CODESEPARATOR`.
+    ///
+    /// SIGHASH_SINGLE without a matching output has no preimage, and errors with
+    /// `SighashSingleBug`. Use `consensus_sighash` to get its digest.
+    ///
+    /// # Warning
+    ///
+    /// This is for checking existing signatures. Do not sign with SIGHASH_NONE, or with
+    /// SIGHASH_SINGLE without a matching output. Signatures found in the script code are not
+    /// removed, as the interpreter does before calculating the sighash.
+    pub fn write_consensus_sighash_preimage<W: Write>(
+        &self,
+        writer: &mut W,
+        args: &LegacySighashArgs,
+    ) -> TxResult<()> {
+        let script_code = Script::from(remove_codeseparators(args.prevout_script.items()));
+        let mut copy_tx: Self = self.legacy_sighash_prep(args.index, &script_code);
+        match args.sighash_flag {
+            Sighash::None | Sighash::NoneAcp => Self::legacy_sighash_none(&mut copy_tx, args.index),
+            Sighash::Single | Sighash::SingleAcp => {
+                if args.index >= self.outputs().len() {
+                    return Err(TxError::SighashSingleBug);
+                }
+                Self::legacy_sighash_single(&mut copy_tx, args.index);
+            }
+            _ => {}
+        }
+
+        if args.sighash_flag as u8 & 0x80 == 0x80 {
+            Self::legacy_sighash_anyone_can_pay(&mut copy_tx, args.index);
+        }
+
+        copy_tx.write_to(writer)?;
+        coins_core::ser::write_u32_le(writer, args.sighash_flag as u32)?;
+
+        Ok(())
+    }
+
+    /// Calculates the legacy sighash as consensus does. See `write_consensus_sighash_preimage`.
+    /// SIGHASH_SINGLE without a matching output produces the digest `1`, i.e. `0x01` followed by
+    /// 31 zero bytes. A signature over this digest authorizes ANY transaction spending the
+    /// output.
+    pub fn consensus_sighash(&self, args: &LegacySighashArgs) -> TxResult<Hash256Digest> {
+        match args.sighash_flag {
+            Sighash::Single | Sighash::SingleAcp if args.index >= self.outputs().len() => {
+                let mut one = Hash256Digest::default();
+                one.as_mut_slice()[0] = 1;
+                Ok(one)
+            }
+            _ => {
+                let mut w = Hash256::default();
+                self.write_consensus_sighash_preimage(&mut w, args)?;
+                Ok(w.finalize_marked())
+            }
+        }
+    }
+
+    /// Deserialize a transaction, enforcing `limits` on its size, and on the lengths and counts
+    /// it contains.
+    pub fn read_from_with_limits<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Self> {
+        let mut reader = LimitedReader::new(reader, limits.max_tx_size);
+        let result = Self::read_limited(&mut reader, limits);
+        reader.check(result, limits.max_tx_size)
+    }
+
+    /// Read the transaction without enforcing its size limit
+    pub(crate) fn read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Self> {
+        let version = coins_core::ser::read_u32_le(reader)?;
+        let vin = read_vin(reader, limits)?;
+        let vout = read_vout(reader, limits)?;
+        let locktime = coins_core::ser::read_u32_le(reader)?;
+        Ok(Self {
+            version,
+            vin,
+            vout,
+            locktime,
+        })
+    }
+
     /// Modifies copy_tx according to legacy SIGHASH_ANYONECANPAY semantics.
     ///
     /// For Legacy sighash documentation, see here:
@@ -139,6 +243,22 @@ impl LegacyTx {
     }
 }
 
+/// Read a length-prefixed vin, enforcing `limits`
+pub(crate) fn read_vin<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Vin> {
+    let count = read_len(reader, "input count", limits.max_inputs)?;
+    (0..count)
+        .map(|_| BitcoinTxIn::read_with_limits(reader, limits))
+        .collect()
+}
+
+/// Read a length-prefixed vout, enforcing `limits`
+pub(crate) fn read_vout<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Vout> {
+    let count = read_len(reader, "output count", limits.max_outputs)?;
+    (0..count)
+        .map(|_| TxOut::read_with_limits(reader, limits))
+        .collect()
+}
+
 impl Transaction for LegacyTx {
     type TxError = TxError;
     type TxIn = BitcoinTxIn;
@@ -260,16 +380,7 @@ impl ByteFormat for LegacyTx {
         R: Read,
         Self: std::marker::Sized,
     {
-        let version = coins_core::ser::read_u32_le(reader)?;
-        let vin = ser::read_prefix_vec(reader)?;
-        let vout = ser::read_prefix_vec(reader)?;
-        let locktime = coins_core::ser::read_u32_le(reader)?;
-        Ok(Self {
-            version,
-            vin,
-            vout,
-            locktime,
-        })
+        Self::read_from_with_limits(reader, &ReadLimits::default())
     }
 
     fn write_to<W>(&self, writer: &mut W) -> Result<usize, Self::Error>
@@ -0,0 +1,228 @@
+//! Limits on deserializing untrusted data.
+//!
+//! Length prefixes are read before the data they describe. Without limits, a malicious prefix
+//! can claim gigabytes of data. Transactions are read with `ReadLimits::default()`, which accepts
+//! any consensus-valid transaction. Services parsing untrusted data may tighten the limits with
+//! `read_from_with_limits`.
+
+use std::io::{Error as IOError, ErrorKind, Read};
+
+use coins_core::ser::{self, SerError};
+
+use crate::types::tx::{TxError, TxResult};
+
+/// The largest serialized transaction consensus allows. A transaction's size can not exceed its
+/// weight, and a block's weight is at most 4 million.
+pub const MAX_TX_SIZE: usize = 4_000_000;
+
+/// The largest non-witness transaction size consensus allows
+const MAX_TX_BASE_SIZE: usize = MAX_TX_SIZE / 4;
+
+/// Caps applied while deserializing transactions. Exceeding one errors with
+/// `TxError::LimitExceeded`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ReadLimits {
+    /// The maximum serialized transaction size, in bytes
+    pub max_tx_size: usize,
+    /// The maximum number of inputs
+    pub max_inputs: usize,
+    /// The maximum number of outputs
+    pub max_outputs: usize,
+    /// The maximum length of a script sig, script pubkey, or witness stack item
+    pub max_script_len: usize,
+    /// The maximum number of stack items in each input's witness
+    pub max_witness_items: usize,
+}
+
+impl Default for ReadLimits {
+    /// The loosest limits a consensus-valid transaction can need.
+    fn default() -> Self {
+        Self {
+            max_tx_size: MAX_TX_SIZE,
+            // inputs are at least 41 bytes, and outputs at least 9
+            max_inputs: MAX_TX_BASE_SIZE / 41,
+            max_outputs: MAX_TX_BASE_SIZE / 9,
+            max_script_len: MAX_TX_SIZE,
+            max_witness_items: MAX_TX_SIZE,
+        }
+    }
+}
+
+/// Read a length prefix, erroring if it is above `limit`
+pub(crate) fn read_len<R: Read>(
+    reader: &mut R,
+    what: &'static str,
+    limit: usize,
+) -> TxResult<usize> {
+    let len = ser::read_compact_int(reader)?;
+    if len > limit as u64 {
+        return Err(TxError::LimitExceeded { what, limit });
+    }
+    Ok(len as usize)
+}
+
+/// Read length-prefixed bytes, erroring if the length is above `limit`. Memory is allocated as
+/// the bytes are read, rather than up front.
+pub(crate) fn read_bytes<R: Read>(
+    reader: &mut R,
+    what: &'static str,
+    limit: usize,
+) -> TxResult<Vec<u8>> {
+    let len = read_len(reader, what, limit)?;
+    let mut bytes = vec![];
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(IOError::from(ErrorKind::UnexpectedEof).into());
+    }
+    Ok(bytes)
+}
+
+/// Convert a `TxError` for component types that return a `SerError`
+pub(crate) fn into_ser_error(e: TxError) -> SerError {
+    match e {
+        TxError::SerError(e) => e,
+        TxError::IoError(e) => SerError::IoError(e),
+        e => SerError::ComponentError(e.to_string()),
+    }
+}
+
+/// A reader that errors after `remaining` bytes
+pub(crate) struct LimitedReader<R> {
+    inner: R,
+    remaining: usize,
+    exceeded: bool,
+}
+
+impl<R: Read> LimitedReader<R> {
+    pub(crate) fn new(inner: R, limit: usize) -> Self {
+        Self {
+            inner,
+            remaining: limit,
+            exceeded: false,
+        }
+    }
+
+    /// Replace an error caused by the limit with `LimitExceeded`
+    pub(crate) fn check<T>(&self, result: TxResult<T>, limit: usize) -> TxResult<T> {
+        match result {
+            Err(_) if self.exceeded => Err(TxError::LimitExceeded {
+                what: "transaction size",
+                limit,
+            }),
+            result => result,
+        }
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            self.exceeded = true;
+            return Err(IOError::other("size limit exceeded"));
+        }
+        let max = std::cmp::min(buf.len(), self.remaining);
+        let read = self.inner.read(&mut buf[..max])?;
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_reads_bounded_bytes() {
+        let data = hex::decode("03aabbcc").unwrap();
+        assert_eq!(
+            read_bytes(&mut data.as_slice(), "script", 3).unwrap(),
+            vec![0xaa, 0xbb, 0xcc]
+        );
+        assert!(matches!(
+            read_bytes(&mut data.as_slice(), "script", 2),
+            Err(TxError::LimitExceeded {
+                what: "script",
+                limit: 2
+            })
+        ));
+
+        // a huge prefix with little data fails without allocating
+        let data = hex::decode("ffffffffffffffff7faabb").unwrap();
+        assert!(matches!(
+            read_bytes(&mut data.as_slice(), "script", usize::MAX),
+            Err(TxError::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn it_enforces_limits_on_txns() {
+        use crate::types::tx::BitcoinTx;
+
+        // 1 input, 2 outputs, and an empty witness
+        let tx_hex = "01000000000101813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac0019430600";
+        let tx = hex::decode(tx_hex).unwrap();
+        let read =
+            |limits: ReadLimits| BitcoinTx::read_from_with_limits(&mut tx.as_slice(), &limits);
+        assert!(read(ReadLimits::default()).is_ok());
+
+        let limit_exceeded = |limits, expected: &str| match read(limits) {
+            Err(TxError::LimitExceeded { what, .. }) => assert_eq!(what, expected),
+            other => panic!("expected {} limit. Got {:?}", expected, other),
+        };
+        let default = ReadLimits::default();
+        limit_exceeded(
+            ReadLimits {
+                max_tx_size: tx.len() - 1,
+                ..default
+            },
+            "transaction size",
+        );
+        limit_exceeded(
+            ReadLimits {
+                max_outputs: 1,
+                ..default
+            },
+            "output count",
+        );
+        limit_exceeded(
+            ReadLimits {
+                max_script_len: 0x6a,
+                ..default
+            },
+            "script sig length",
+        );
+        assert!(read(ReadLimits {
+            max_tx_size: tx.len(),
+            max_inputs: 1,
+            max_outputs: 2,
+            max_script_len: 0x6b,
+            max_witness_items: 0,
+        })
+        .is_ok());
+
+        // a huge input count fails without reading them all
+        let mut bad = tx[..6].to_vec();
+        bad.extend(hex::decode("feffffffff").unwrap());
+        bad.extend(&tx[7..]);
+        assert!(matches!(
+            BitcoinTx::read_from_with_limits(&mut bad.as_slice(), &default),
+            Err(TxError::LimitExceeded {
+                what: "input count",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn it_limits_reads() {
+        let data = [1u8; 10];
+        let mut reader = LimitedReader::new(&data[..], 4);
+        let mut buf = [0u8; 3];
+        reader.read_exact(&mut buf).unwrap();
+        assert!(reader.read_exact(&mut buf).is_err());
+        assert!(reader.exceeded);
+    }
+}
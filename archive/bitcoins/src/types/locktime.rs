@@ -0,0 +1,151 @@
+//! Typed absolute locktimes.
+//!
+//! The nLocktime field and `OP_CHECKLOCKTIMEVERIFY` interpret values below 500,000,000 as block
+//! heights, and other values as unix timestamps. `LockTime` keeps the two apart, so that a
+//! timestamp can not be passed where a height is expected.
+
+use thiserror::Error;
+
+/// Locktimes below this value are block heights. Others are unix timestamps.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Errors in constructing locktimes
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum LockTimeError {
+    /// The value is at or above the threshold, so would be read as a timestamp
+    #[error(
+        "{0} is not a block height. Heights must be below {}",
+        LOCKTIME_THRESHOLD
+    )]
+    InvalidHeight(u32),
+
+    /// The value is below the threshold, so would be read as a block height
+    #[error(
+        "{0} is not a unix timestamp. Timestamps must be at least {}",
+        LOCKTIME_THRESHOLD
+    )]
+    InvalidTime(u32),
+}
+
+/// An absolute locktime, as used in the nLocktime field and by `OP_CHECKLOCKTIMEVERIFY`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum LockTime {
+    /// A block height
+    BlockHeight(u32),
+    /// A unix timestamp, compared against the median time past of the previous 11 blocks
+    UnixTime(u32),
+}
+
+impl Default for LockTime {
+    fn default() -> Self {
+        LockTime::BlockHeight(0)
+    }
+}
+
+impl LockTime {
+    /// Instantiate a block height locktime. Errors if `height` would be read as a timestamp
+    pub fn from_height(height: u32) -> Result<Self, LockTimeError> {
+        if height >= LOCKTIME_THRESHOLD {
+            return Err(LockTimeError::InvalidHeight(height));
+        }
+        Ok(LockTime::BlockHeight(height))
+    }
+
+    /// Instantiate a timestamp locktime. Errors if `time` would be read as a block height
+    pub fn from_time(time: u32) -> Result<Self, LockTimeError> {
+        if time < LOCKTIME_THRESHOLD {
+            return Err(LockTimeError::InvalidTime(time));
+        }
+        Ok(LockTime::UnixTime(time))
+    }
+
+    /// Interpret a raw nLocktime value
+    pub fn from_consensus(locktime: u32) -> Self {
+        if locktime < LOCKTIME_THRESHOLD {
+            LockTime::BlockHeight(locktime)
+        } else {
+            LockTime::UnixTime(locktime)
+        }
+    }
+
+    /// The raw nLocktime value
+    pub fn to_consensus_u32(self) -> u32 {
+        match self {
+            LockTime::BlockHeight(n) | LockTime::UnixTime(n) => n,
+        }
+    }
+
+    /// True if both locktimes are heights, or both are timestamps
+    pub fn is_same_unit(self, other: LockTime) -> bool {
+        matches!(
+            (self, other),
+            (LockTime::BlockHeight(_), LockTime::BlockHeight(_))
+                | (LockTime::UnixTime(_), LockTime::UnixTime(_))
+        )
+    }
+
+    /// True if a transaction with locktime `tx_locktime` satisfies this locktime, as checked by
+    /// `OP_CHECKLOCKTIMEVERIFY`. Locktimes of different units never satisfy each other.
+    pub fn is_satisfied_by(self, tx_locktime: LockTime) -> bool {
+        self.is_same_unit(tx_locktime) && self.to_consensus_u32() <= tx_locktime.to_consensus_u32()
+    }
+}
+
+impl From<LockTime> for u32 {
+    fn from(locktime: LockTime) -> u32 {
+        locktime.to_consensus_u32()
+    }
+}
+
+impl std::fmt::Display for LockTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockTime::BlockHeight(n) => write!(f, "height {}", n),
+            LockTime::UnixTime(n) => write!(f, "time {}", n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_validates_locktimes() {
+        assert_eq!(
+            LockTime::from_height(700_000),
+            Ok(LockTime::BlockHeight(700_000))
+        );
+        assert_eq!(
+            LockTime::from_height(1_600_000_000),
+            Err(LockTimeError::InvalidHeight(1_600_000_000))
+        );
+        assert_eq!(
+            LockTime::from_time(1_600_000_000),
+            Ok(LockTime::UnixTime(1_600_000_000))
+        );
+        assert_eq!(
+            LockTime::from_time(700_000),
+            Err(LockTimeError::InvalidTime(700_000))
+        );
+        assert_eq!(
+            LockTime::from_consensus(LOCKTIME_THRESHOLD - 1),
+            LockTime::BlockHeight(LOCKTIME_THRESHOLD - 1)
+        );
+        assert_eq!(
+            LockTime::from_consensus(LOCKTIME_THRESHOLD),
+            LockTime::UnixTime(LOCKTIME_THRESHOLD)
+        );
+    }
+
+    #[test]
+    fn it_compares_locktimes() {
+        let height = LockTime::BlockHeight(700_000);
+        let time = LockTime::UnixTime(1_600_000_000);
+        assert!(height.is_satisfied_by(LockTime::BlockHeight(700_000)));
+        assert!(!height.is_satisfied_by(LockTime::BlockHeight(699_999)));
+        assert!(!height.is_satisfied_by(time));
+        assert!(!time.is_satisfied_by(height));
+        assert!(time.is_satisfied_by(LockTime::UnixTime(1_700_000_000)));
+    }
+}
@@ -1,8 +1,17 @@
-//! Holds Bitcoin specific types, including scripts, witnesses, inputs, outputs, and transactions.
+//! Holds Bitcoin specific types, including scripts, witnesses, inputs, outputs, transactions,
+//! and blocks.
 //! Extends the `Transaction` trait to maintain a type distinction between Legacy and Witness
 //! transactions (and allow conversion from one to the other).
 
+pub mod amount;
+pub mod asm;
+pub mod block;
+pub mod cache;
+pub mod fee;
 pub mod legacy;
+pub mod limits;
+pub mod locktime;
+pub mod policy;
 pub mod script;
 pub mod tx;
 pub mod txin;
@@ -10,7 +19,15 @@ pub mod txout;
 pub mod utxo;
 pub mod witness;
 
+pub use amount::*;
+pub use asm::*;
+pub use block::*;
+pub use cache::*;
+pub use fee::*;
 pub use legacy::*;
+pub use limits::*;
+pub use locktime::*;
+pub use policy::*;
 pub use script::*;
 pub use tx::*;
 pub use txin::*;
@@ -0,0 +1,293 @@
+//! Bitcoin Core's default relay policy.
+//!
+//! Nodes running the default policy do not relay or mine non-standard transactions, even when
+//! they are valid. `BitcoinTransaction::check_standardness` checks the rules that need only the
+//! transaction itself. Sigop limits and rules on the spent scripts need the prevouts, and are not
+//! checked.
+
+use thiserror::Error;
+
+use crate::types::{
+    fee::FeeRate,
+    script::{is_push_only, witness_program, ScriptType},
+    tx::BitcoinTransaction,
+};
+
+use coins_core::ser::ByteFormat;
+
+/// The feerate used to decide whether an output is dust, as set by Bitcoin Core's
+/// `-dustrelayfee`
+pub const DUST_RELAY_FEE: FeeRate = FeeRate(3.0);
+
+/// The largest standard transaction weight
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+
+/// The smallest standard transaction size, without witnesses. Smaller transactions may be
+/// confused with merkle tree nodes
+pub const MIN_STANDARD_TX_NONWITNESS_SIZE: usize = 65;
+
+/// The largest standard script sig
+pub const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1650;
+
+/// The largest standard transaction version
+pub const MAX_STANDARD_VERSION: u32 = 3;
+
+/// The largest standard OP_RETURN script pubkey. The OP_RETURN, a push opcode, and
+/// `MAX_OP_RETURN_DATA` bytes
+pub const MAX_OP_RETURN_RELAY: usize = 83;
+
+/// Reasons a transaction is not standard
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum StandardnessError {
+    /// The version is 0 or above `MAX_STANDARD_VERSION`
+    #[error("Non-standard version: {0}")]
+    Version(u32),
+
+    /// The weight is above `MAX_STANDARD_TX_WEIGHT`
+    #[error("Transaction weight {0} is above the standard limit")]
+    TooLarge(usize),
+
+    /// The size without witnesses is below `MIN_STANDARD_TX_NONWITNESS_SIZE`
+    #[error("Transaction size {0} is below the standard minimum")]
+    TooSmall(usize),
+
+    /// An input's script sig is larger than `MAX_STANDARD_SCRIPTSIG_SIZE`
+    #[error("Script sig of input {index} is {size} bytes. Above the standard limit")]
+    ScriptSigTooLarge {
+        /// The input index
+        index: usize,
+        /// The script sig size
+        size: usize,
+    },
+
+    /// An input's script sig contains non-push opcodes
+    #[error("Script sig of input {0} is not push-only")]
+    ScriptSigNotPushOnly(usize),
+
+    /// An output's script pubkey is not a standard type
+    #[error("Script pubkey of output {0} is not standard")]
+    NonStandardScriptPubkey(usize),
+
+    /// An OP_RETURN output is larger than `MAX_OP_RETURN_RELAY`
+    #[error("OP_RETURN output {index} is {size} bytes. Above the standard limit")]
+    OpReturnTooLarge {
+        /// The output index
+        index: usize,
+        /// The script pubkey size
+        size: usize,
+    },
+
+    /// More than one output is an OP_RETURN
+    #[error("Multiple OP_RETURN outputs")]
+    MultipleOpReturns,
+
+    /// An output is dust at `DUST_RELAY_FEE`
+    #[error("Output {0} is dust")]
+    Dust(usize),
+}
+
+/// True if the script pubkey is a standard spendable type. Bare multisig is limited to 3 keys,
+/// and witness programs of unknown versions are standard.
+fn is_standard_script_pubkey(script: &[u8], script_type: &ScriptType) -> bool {
+    match script_type {
+        ScriptType::Pkh(_)
+        | ScriptType::Sh(_)
+        | ScriptType::Wpkh(_)
+        | ScriptType::Wsh(_)
+        | ScriptType::Tr(_) => true,
+        ScriptType::Multisig { n, .. } => *n <= 3,
+        _ => matches!(witness_program(script), Some((version, _)) if version != 0),
+    }
+}
+
+/// Check a transaction against Bitcoin Core's `IsStandardTx`. Returns the first failing rule.
+pub(crate) fn check_standardness<T>(tx: &T) -> Result<(), StandardnessError>
+where
+    T: BitcoinTransaction + ?Sized,
+{
+    if tx.version() == 0 || tx.version() > MAX_STANDARD_VERSION {
+        return Err(StandardnessError::Version(tx.version()));
+    }
+
+    let weight = tx.weight();
+    if weight > MAX_STANDARD_TX_WEIGHT {
+        return Err(StandardnessError::TooLarge(weight));
+    }
+
+    let size = tx.as_legacy().serialized_length();
+    if size < MIN_STANDARD_TX_NONWITNESS_SIZE {
+        return Err(StandardnessError::TooSmall(size));
+    }
+
+    for (index, input) in tx.inputs().iter().enumerate() {
+        let size = input.script_sig.len();
+        if size > MAX_STANDARD_SCRIPTSIG_SIZE {
+            return Err(StandardnessError::ScriptSigTooLarge { index, size });
+        }
+        if !is_push_only(input.script_sig.items()) {
+            return Err(StandardnessError::ScriptSigNotPushOnly(index));
+        }
+    }
+
+    let mut op_returns = 0;
+    for (index, output) in tx.outputs().iter().enumerate() {
+        let script = output.script_pubkey.items();
+        if script.first() == Some(&0x6a) && is_push_only(&script[1..]) {
+            if script.len() > MAX_OP_RETURN_RELAY {
+                return Err(StandardnessError::OpReturnTooLarge {
+                    index,
+                    size: script.len(),
+                });
+            }
+            op_returns += 1;
+            continue;
+        }
+        if !is_standard_script_pubkey(script, &output.standard_type()) {
+            return Err(StandardnessError::NonStandardScriptPubkey(index));
+        }
+        if output.is_dust(DUST_RELAY_FEE) {
+            return Err(StandardnessError::Dust(index));
+        }
+    }
+
+    if op_returns > 1 {
+        return Err(StandardnessError::MultipleOpReturns);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{
+        amount::Amount, legacy::LegacyTx, script::ScriptPubkey, txin::BitcoinTxIn, txout::TxOut,
+    };
+    use coins_core::types::tx::Transaction;
+
+    fn tx(version: u32, script_sig: Vec<u8>, outputs: Vec<TxOut>) -> LegacyTx {
+        let input = BitcoinTxIn::new(Default::default(), script_sig, 0xffff_fffd);
+        LegacyTx::new(version, vec![input], outputs, 0).unwrap()
+    }
+
+    fn p2wpkh(sats: u64) -> TxOut {
+        TxOut::new(
+            Amount::from_sat(sats),
+            ScriptPubkey::p2wpkh_from_hash(&[1; 20]),
+        )
+    }
+
+    #[test]
+    fn it_accepts_standard_txns() {
+        let outputs = vec![
+            p2wpkh(10_000),
+            TxOut::new(Amount::from_sat(330), ScriptPubkey::p2tr(&[2; 32])),
+            TxOut::op_return(&[3; 80]),
+            // pay to anchor
+            TxOut::new(Amount::from_sat(240), vec![0x51, 0x02, 0x4e, 0x73]),
+        ];
+        assert_eq!(
+            tx(2, vec![0x01, 0xaa], outputs).check_standardness(),
+            Ok(())
+        );
+
+        let multisig = ScriptPubkey::new(
+            [
+                &[0x51, 0x21][..],
+                &[2; 33],
+                &[0x21],
+                &[3; 33],
+                &[0x21],
+                &[2; 33],
+                &[0x53, 0xae],
+            ]
+            .concat(),
+        );
+        let outputs = vec![TxOut::new(Amount::from_sat(10_000), multisig)];
+        assert_eq!(tx(3, vec![], outputs).check_standardness(), Ok(()));
+    }
+
+    #[test]
+    fn it_rejects_non_standard_txns() {
+        let cases = [
+            (
+                tx(0, vec![], vec![p2wpkh(10_000)]),
+                StandardnessError::Version(0),
+            ),
+            (
+                tx(4, vec![], vec![p2wpkh(10_000)]),
+                StandardnessError::Version(4),
+            ),
+            (
+                tx(2, vec![], vec![TxOut::new(Amount::ZERO, vec![0x6a])]),
+                StandardnessError::TooSmall(61),
+            ),
+            (
+                tx(2, vec![0x4d, 0x72, 0x06], vec![p2wpkh(10_000)]),
+                StandardnessError::ScriptSigNotPushOnly(0),
+            ),
+            (
+                tx(2, vec![0x51, 0x75], vec![p2wpkh(10_000)]),
+                StandardnessError::ScriptSigNotPushOnly(0),
+            ),
+            (
+                tx(2, vec![0x51; 1651], vec![p2wpkh(10_000)]),
+                StandardnessError::ScriptSigTooLarge {
+                    index: 0,
+                    size: 1651,
+                },
+            ),
+            (
+                tx(2, vec![], vec![p2wpkh(10_000), p2wpkh(293)]),
+                StandardnessError::Dust(1),
+            ),
+            (
+                tx(
+                    2,
+                    vec![],
+                    vec![TxOut::new(Amount::from_sat(10_000), vec![0x51; 5])],
+                ),
+                StandardnessError::NonStandardScriptPubkey(0),
+            ),
+            (
+                // witness v0 programs must be 20 or 32 bytes
+                tx(
+                    2,
+                    vec![],
+                    vec![TxOut::new(
+                        Amount::from_sat(10_000),
+                        [&[0, 24][..], &[1; 24]].concat(),
+                    )],
+                ),
+                StandardnessError::NonStandardScriptPubkey(0),
+            ),
+            (
+                tx(
+                    2,
+                    vec![],
+                    vec![TxOut::new(
+                        Amount::ZERO,
+                        [&[0x6a, 0x4c, 81][..], &[1; 81]].concat(),
+                    )],
+                ),
+                StandardnessError::OpReturnTooLarge { index: 0, size: 84 },
+            ),
+            (
+                tx(
+                    2,
+                    vec![],
+                    vec![TxOut::op_return(&[1; 20]), TxOut::op_return(&[2; 20])],
+                ),
+                StandardnessError::MultipleOpReturns,
+            ),
+        ];
+        for (tx, err) in cases.iter() {
+            assert_eq!(tx.check_standardness().as_ref(), Err(err));
+        }
+
+        let outputs = vec![p2wpkh(10_000); 12_000];
+        assert!(matches!(
+            tx(2, vec![], outputs).check_standardness(),
+            Err(StandardnessError::TooLarge(_))
+        ));
+    }
+}
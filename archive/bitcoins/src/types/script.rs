@@ -1,8 +1,8 @@
 //! Simple types for Bitcoin Script Witness stack datastructures, each of which are treated as
 //! opaque, wrapped `Vec<u8>` instance.
 //!
-//! We do not handle assembly, disassembly, or Script execution in `bitcoins`. Scripts are treated
-//! as opaque bytes vectors with no semantics.
+//! We do not handle Script execution in `bitcoins`. Scripts are treated as opaque bytes vectors
+//! with no semantics. Assembly and disassembly are in the `asm` module.
 //!
 //! Scripts can be freely converted between eachother using `From` and `Into`. This merely rewraps
 //! the underlying `Vec<u8>` in the new type.
@@ -23,10 +23,13 @@
 use coins_core::{
     hashes::{Digest, Hash160, Hash160Digest, Hash256Digest, MarkedDigestOutput, Sha256},
     impl_hex_serde, impl_script_conversion,
+    ser::ByteFormat,
     types::tx::RecipientIdentifier,
     wrap_prefixed_byte_vector,
 };
 
+use crate::{hashes::tagged_hash, types::locktime::LockTime};
+
 /// A wrapped script.
 pub trait BitcoinScript {}
 
@@ -97,10 +100,13 @@ impl ScriptPubkey {
     where
         K: AsRef<coins_bip32::ecdsa::VerifyingKey>,
     {
-        let digest = Hash160::digest(&key.as_ref().to_bytes());
+        Self::p2pkh_from_hash(&key_hash(key))
+    }
 
+    /// Instantiate a standard p2pkh script pubkey from a pubkey hash.
+    pub fn p2pkh_from_hash(hash: &[u8; 20]) -> Self {
         let mut v: Vec<u8> = vec![0x76, 0xa9, 0x14]; // DUP, HASH160, PUSH_20
-        v.extend(&digest);
+        v.extend(hash);
         v.extend(&[0x88, 0xac]); // EQUALVERIFY, CHECKSIG
         v.into()
     }
@@ -110,27 +116,113 @@ impl ScriptPubkey {
     where
         K: AsRef<coins_bip32::ecdsa::VerifyingKey>,
     {
-        let digest = Hash160::digest(&key.as_ref().to_bytes());
+        Self::p2wpkh_from_hash(&key_hash(key))
+    }
 
+    /// Instantiate a standard p2wpkh script pubkey from a pubkey hash.
+    pub fn p2wpkh_from_hash(hash: &[u8; 20]) -> Self {
         let mut v: Vec<u8> = vec![0x00, 0x14]; // OP_0, PUSH_20
-        v.extend(&digest);
+        v.extend(hash);
         v.into()
     }
 
+    /// Instantiate a p2sh-wrapped p2wpkh script pubkey from a pubkey. The redeem script is the
+    /// p2wpkh script pubkey of the key.
+    pub fn p2sh_p2wpkh<K>(key: &K) -> Self
+    where
+        K: AsRef<coins_bip32::ecdsa::VerifyingKey>,
+    {
+        Self::p2sh(&Script::from(&Self::p2wpkh(key)))
+    }
+
     /// Instantiate a standard p2sh script pubkey from a script.
     pub fn p2sh(script: &Script) -> Self {
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&Hash160::digest(script.as_ref()));
+        Self::p2sh_from_hash(&hash)
+    }
+
+    /// Instantiate a standard p2sh script pubkey from a script hash.
+    pub fn p2sh_from_hash(hash: &[u8; 20]) -> Self {
         let mut v: Vec<u8> = vec![0xa9, 0x14]; // HASH160, PUSH_20
-        v.extend(Hash160::digest(script.as_ref()).as_slice());
+        v.extend(hash);
         v.extend(&[0x87]); // EQUAL
         v.into()
     }
 
+    /// Instantiate a bare `OP_m <keys> OP_n OP_CHECKMULTISIG` script pubkey, with keys in the
+    /// order given. Returns `None` unless `1 <= m <= n <= 16`. The same script is used as the
+    /// redeem script or witness script of p2sh and p2wsh multisig.
+    pub fn p2ms<K>(m: usize, keys: &[K]) -> Option<Self>
+    where
+        K: AsRef<coins_bip32::ecdsa::VerifyingKey>,
+    {
+        if m == 0 || m > keys.len() || keys.len() > 16 {
+            return None;
+        }
+        let mut v = vec![];
+        push_int(&mut v, m as u64);
+        for key in keys {
+            push_data(&mut v, &key.as_ref().to_bytes());
+        }
+        push_int(&mut v, keys.len() as u64);
+        v.push(0xae); // CHECKMULTISIG
+        Some(v.into())
+    }
+
     /// Instantiate a standard p2wsh script pubkey from a script.
     pub fn p2wsh(script: &Script) -> Self {
         let mut v: Vec<u8> = vec![0x00, 0x20]; // OP_0, PUSH_32
         v.extend(Sha256::digest(script.as_ref()));
         v.into()
     }
+
+    /// Instantiate a standard p2tr script pubkey from an x-only taproot output key. The key
+    /// must already be tweaked with the script tree commitment, if any, as specified in BIP341.
+    pub fn p2tr(xonly_key: &[u8; 32]) -> Self {
+        let mut v: Vec<u8> = vec![0x51, 0x20]; // OP_1, PUSH_32
+        v.extend(xonly_key);
+        v.into()
+    }
+}
+
+/// The BIP342 tapscript leaf version
+pub const TAPSCRIPT_LEAF_VERSION: u8 = 0xc0;
+
+impl Script {
+    /// The BIP341 leaf hash of the script as a tapscript leaf. Script path spends commit to it
+    /// in the taproot sighash.
+    pub fn tapleaf_hash(&self) -> [u8; 32] {
+        let mut data = vec![TAPSCRIPT_LEAF_VERSION];
+        self.write_to(&mut data).expect("no error writing to vec");
+        tagged_hash("TapLeaf", &data)
+    }
+
+    /// Classify the script as though it were a script pubkey. Useful for redeem scripts and
+    /// witness scripts, e.g. to find a P2SH-wrapped witness program or a multisig script.
+    pub fn standard_type(&self) -> ScriptType {
+        ScriptPubkey::from(self.items().to_vec()).standard_type()
+    }
+
+    /// Prefix `script` with `<locktime> OP_CHECKLOCKTIMEVERIFY OP_DROP`, so that it can not be
+    /// spent by a transaction with an earlier locktime, or a locktime of the other unit.
+    pub fn cltv(locktime: LockTime, script: &Script) -> Script {
+        let mut v = vec![];
+        push_int(&mut v, locktime.to_consensus_u32() as u64);
+        v.extend(&[0xb1, 0x75]); // CHECKLOCKTIMEVERIFY, DROP
+        v.extend(script.items());
+        v.into()
+    }
+}
+
+/// The hash160 of a compressed pubkey
+fn key_hash<K>(key: &K) -> [u8; 20]
+where
+    K: AsRef<coins_bip32::ecdsa::VerifyingKey>,
+{
+    let mut hash = [0u8; 20];
+    hash.copy_from_slice(&Hash160::digest(key.as_ref().to_bytes()));
+    hash
 }
 
 /// Standard script types, and a non-standard type for all other scripts.
@@ -144,24 +236,63 @@ pub enum ScriptType {
     Wpkh(Hash160Digest),
     /// Pay to Witness Scripthash.
     Wsh(Hash256Digest),
+    /// Pay to Taproot. Holds the x-only output key.
+    Tr([u8; 32]),
+    /// Bare `m`-of-`n` CHECKMULTISIG. Use `multisig_keys` to extract the keys.
+    Multisig {
+        /// The number of signatures required
+        m: u8,
+        /// The number of keys
+        n: u8,
+    },
     /// OP_RETURN
     OpReturn(Vec<u8>),
     /// Nonstandard or unknown `Script` type. May be a newer witness version.
     NonStandard,
 }
 
+/// The largest OP_RETURN payload relayed by default, as set by Bitcoin Core's `-datacarriersize`
+pub const MAX_OP_RETURN_DATA: usize = 80;
+
+/// The largest script allowed by consensus. Larger script pubkeys are unspendable
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
 impl ScriptPubkey {
+    /// Instantiate an OP_RETURN script pushing `data`. Uses OP_PUSHDATA1 for payloads over 75
+    /// bytes. None if the payload is larger than `MAX_OP_RETURN_DATA`.
+    pub fn op_return(data: &[u8]) -> Option<Self> {
+        if data.len() > MAX_OP_RETURN_DATA {
+            return None;
+        }
+        let mut payload = vec![0x6a];
+        if data.len() > 75 {
+            payload.push(0x4c);
+        }
+        payload.push(data.len() as u8);
+        payload.extend(data);
+        Some(payload.into())
+    }
+
     /// Extract the op return payload. None if not an op return. Does not extract OP_RETURN blobs
-    /// larger than 75 bytes.
+    /// larger than `MAX_OP_RETURN_DATA`, or made of more than one push.
     pub fn extract_op_return_data(&self) -> Option<Vec<u8>> {
         // check before indexing to avoid potential panic on malformed input
-        if self.len() < 2 {
+        if self.len() < 2 || self[0] != 0x6a {
             return None;
         }
 
-        if self[0] == 0x6a && self[1] <= 75 && self[1] as usize == (self.len() - 2) {
+        if self[1] <= 75 && self[1] as usize == (self.len() - 2) {
             return Some(self.0[2..].to_vec());
         }
+        // OP_PUSHDATA1 is only standard for payloads that a direct push can not hold
+        if self.len() > 2
+            && self[1] == 0x4c
+            && self[2] > 75
+            && self[2] as usize <= MAX_OP_RETURN_DATA
+            && self[2] as usize == (self.len() - 3)
+        {
+            return Some(self.0[3..].to_vec());
+        }
         None
     }
 
@@ -173,29 +304,23 @@ impl ScriptPubkey {
 
         let items = &self.0;
         match self.0.len() {
-            0x19 => {
-                // PKH;
-                if items[0..3] == [0x76, 0xa9, 0x14] && items[0x17..] == [0x88, 0xac] {
-                    let mut buf = Hash160Digest::default();
-                    buf.as_mut_slice().copy_from_slice(&items[3..23]);
-                    return ScriptType::Pkh(buf);
-                }
+            // PKH
+            0x19 if items[0..3] == [0x76, 0xa9, 0x14] && items[0x17..] == [0x88, 0xac] => {
+                let mut buf = Hash160Digest::default();
+                buf.as_mut_slice().copy_from_slice(&items[3..23]);
+                return ScriptType::Pkh(buf);
             }
-            0x17 => {
-                // SH
-                if items[0..2] == [0xa9, 0x14] && items[0x16..] == [0x87] {
-                    let mut buf = Hash160Digest::default();
-                    buf.as_mut_slice().copy_from_slice(&items[2..22]);
-                    return ScriptType::Sh(buf);
-                }
+            // SH
+            0x17 if items[0..2] == [0xa9, 0x14] && items[0x16..] == [0x87] => {
+                let mut buf = Hash160Digest::default();
+                buf.as_mut_slice().copy_from_slice(&items[2..22]);
+                return ScriptType::Sh(buf);
             }
-            0x16 => {
-                // WPKH
-                if items[0..2] == [0x00, 0x14] {
-                    let mut buf = Hash160Digest::default();
-                    buf.as_mut_slice().copy_from_slice(&items[2..22]);
-                    return ScriptType::Wpkh(buf);
-                }
+            // WPKH
+            0x16 if items[0..2] == [0x00, 0x14] => {
+                let mut buf = Hash160Digest::default();
+                buf.as_mut_slice().copy_from_slice(&items[2..22]);
+                return ScriptType::Wpkh(buf);
             }
             0x22 => {
                 if items[0..2] == [0x00, 0x20] {
@@ -203,14 +328,226 @@ impl ScriptPubkey {
                     buf.as_mut_slice().copy_from_slice(&items[2..34]);
                     return ScriptType::Wsh(buf);
                 }
+                // TR
+                if items[0..2] == [0x51, 0x20] {
+                    let mut buf = [0u8; 32];
+                    buf.copy_from_slice(&items[2..34]);
+                    return ScriptType::Tr(buf);
+                }
             }
-            _ => return ScriptType::NonStandard,
+            _ => {}
+        }
+        if let Some((m, keys)) = multisig_keys(items) {
+            return ScriptType::Multisig {
+                m: m as u8,
+                n: keys.len() as u8,
+            };
         }
-        // fallthrough
         ScriptType::NonStandard
     }
 }
 
+impl ScriptType {
+    /// True if the script pubkey is spent with a witness. P2SH-wrapped witness programs are
+    /// classified as `Sh`, and their redeem script must be checked separately.
+    pub fn is_witness(&self) -> bool {
+        matches!(
+            self,
+            ScriptType::Wpkh(_) | ScriptType::Wsh(_) | ScriptType::Tr(_)
+        )
+    }
+}
+
+/// Iterates over the opcodes of a script, yielding each opcode and the data it pushes. Ends at
+/// the first truncated push.
+pub(crate) struct Instructions<'a> {
+    script: &'a [u8],
+    truncated: bool,
+}
+
+impl<'a> Instructions<'a> {
+    pub(crate) fn new(script: &'a [u8]) -> Self {
+        Self {
+            script,
+            truncated: false,
+        }
+    }
+
+    /// True if iteration ended at a truncated push, rather than at the end of the script
+    pub(crate) fn truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&op, rest) = self.script.split_first()?;
+        let (len_bytes, len) = match op {
+            0x01..=0x4b => (0, op as usize),
+            0x4c => (1, *rest.first()? as usize),
+            0x4d if rest.len() >= 2 => (2, u16::from_le_bytes([rest[0], rest[1]]) as usize),
+            0x4e if rest.len() >= 4 => (
+                4,
+                u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize,
+            ),
+            0x4d | 0x4e => {
+                self.script = &[];
+                self.truncated = true;
+                return None;
+            }
+            _ => (0, 0),
+        };
+        if rest.len() < len_bytes + len {
+            self.script = &[];
+            self.truncated = true;
+            return None;
+        }
+        let data = &rest[len_bytes..len_bytes + len];
+        self.script = &rest[len_bytes + len..];
+        Some((op, data))
+    }
+}
+
+/// Remove the `OP_CODESEPARATOR`s from a script, as legacy sighash does to the script code.
+/// Pushed data, and anything after a truncated push, is kept as it is.
+pub fn remove_codeseparators(script: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(script.len());
+    let mut pos = 0;
+    for (op, data) in Instructions::new(script) {
+        let len = match op {
+            0x4c => 2,
+            0x4d => 3,
+            0x4e => 5,
+            _ => 1,
+        } + data.len();
+        if op != 0xab {
+            result.extend_from_slice(&script[pos..pos + len]);
+        }
+        pos += len;
+    }
+    result.extend_from_slice(&script[pos..]);
+    result
+}
+
+/// Count the signature operations in a script. `CHECKMULTISIG` counts as 20, unless `accurate`
+/// is set and it is preceded by a key count of `OP_1` to `OP_16`. Accurate counting applies to
+/// P2SH redeem scripts and witness scripts.
+pub fn sigop_count(script: &[u8], accurate: bool) -> usize {
+    let mut count = 0;
+    let mut last_op = 0xff;
+    for (op, _) in Instructions::new(script) {
+        match op {
+            // CHECKSIG, CHECKSIGVERIFY
+            0xac | 0xad => count += 1,
+            // CHECKMULTISIG, CHECKMULTISIGVERIFY
+            0xae | 0xaf => {
+                count += if accurate && (0x51..=0x60).contains(&last_op) {
+                    (last_op - 0x50) as usize
+                } else {
+                    20
+                }
+            }
+            _ => {}
+        }
+        last_op = op;
+    }
+    count
+}
+
+/// True if the script contains only push opcodes, up to and including `OP_16`, and has no
+/// truncated pushes. Standard script sigs must be push-only.
+pub fn is_push_only(script: &[u8]) -> bool {
+    let mut instructions = Instructions::new(script);
+    instructions.by_ref().all(|(op, _)| op <= 0x60) && !instructions.truncated()
+}
+
+/// The version and program of a BIP141 witness program: a version opcode from `OP_0` to
+/// `OP_16`, followed by a single push of 2 to 40 bytes.
+pub fn witness_program(script: &[u8]) -> Option<(u8, &[u8])> {
+    if script.len() < 4 || script.len() > 42 || script[1] as usize != script.len() - 2 {
+        return None;
+    }
+    match script[0] {
+        0x00 => Some((0, &script[2..])),
+        0x51..=0x60 => Some((script[0] - 0x50, &script[2..])),
+        _ => None,
+    }
+}
+
+/// The threshold and public keys of a bare `OP_m <keys> OP_n OP_CHECKMULTISIG` script. Keys
+/// must be 33-byte compressed or 65-byte uncompressed keys, and `m` and `n` must be between 1
+/// and 16.
+pub fn multisig_keys(script: &[u8]) -> Option<(usize, Vec<&[u8]>)> {
+    let ops: Vec<(u8, &[u8])> = Instructions::new(script).collect();
+    // truncated pushes end the iterator early, so also check the script is fully consumed. All
+    // valid instructions here are direct pushes or single opcodes
+    let consumed: usize = ops.iter().map(|(_, data)| 1 + data.len()).sum();
+    if ops.len() < 4 || consumed != script.len() {
+        return None;
+    }
+    let small_int = |op: u8| match op {
+        0x51..=0x60 => Some((op - 0x50) as usize),
+        _ => None,
+    };
+    let m = small_int(ops[0].0)?;
+    let n = small_int(ops[ops.len() - 2].0)?;
+    let keys: Vec<&[u8]> = ops[1..ops.len() - 2]
+        .iter()
+        .map(|(op, key)| match (op, key.len(), key.first()) {
+            (0x21, 33, Some(0x02)) | (0x21, 33, Some(0x03)) | (0x41, 65, Some(0x04)) => Some(*key),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    if ops[ops.len() - 1].0 != 0xae || keys.len() != n || m > n {
+        return None;
+    }
+    Some((m, keys))
+}
+
+/// Push data onto a script. Uses OP_PUSHDATA1 for data over 75 bytes. Data must not be longer
+/// than 255 bytes.
+pub(crate) fn push_data(script: &mut Vec<u8>, data: &[u8]) {
+    if data.len() > 75 {
+        script.push(0x4c); // PUSHDATA1
+    }
+    script.push(data.len() as u8);
+    script.extend_from_slice(data);
+}
+
+/// Push a non-negative number, minimally encoded
+pub(crate) fn push_int(script: &mut Vec<u8>, n: u64) {
+    match n {
+        0 => script.push(0x00),
+        1..=16 => script.push(0x50 + n as u8),
+        _ => {
+            let mut num = vec![];
+            let mut n = n;
+            while n > 0 {
+                num.push((n & 0xff) as u8);
+                n >>= 8;
+            }
+            // the high bit is the sign bit
+            if num.last().unwrap() & 0x80 != 0 {
+                num.push(0);
+            }
+            push_data(script, &num);
+        }
+    }
+}
+
+/// The data pushed by the last opcode of a script, if it is a push. Used to find the redeem
+/// script of a P2SH script sig.
+pub(crate) fn last_push(script: &[u8]) -> Option<&[u8]> {
+    let (op, data) = Instructions::new(script).last()?;
+    if op <= 0x4e {
+        Some(data)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -295,6 +632,95 @@ mod test {
         ScriptSig::from(&spk);
     }
 
+    #[test]
+    fn it_builds_cltv_scripts() {
+        let inner = Script::new(vec![0x51]);
+        let locked = Script::cltv(LockTime::BlockHeight(700_000), &inner);
+        assert_eq!(hex::encode(locked.items()), "0360ae0ab17551");
+        let locked = Script::cltv(LockTime::UnixTime(1_600_000_000), &inner);
+        assert_eq!(hex::encode(locked.items()), "0400105e5fb17551");
+    }
+
+    #[test]
+    fn it_builds_standard_scripts_from_keys() {
+        use coins_bip32::ecdsa::SigningKey;
+
+        let keys: Vec<SigningKey> = (1..=3u8)
+            .map(|i| SigningKey::from_bytes(&[i; 32]).unwrap())
+            .collect();
+        let hash = key_hash(&keys[0]);
+
+        let wpkh = ScriptPubkey::p2wpkh(&keys[0]);
+        assert_eq!(wpkh.standard_type(), ScriptType::Wpkh(hash.into()));
+        assert_eq!(
+            ScriptPubkey::p2pkh(&keys[0]).standard_type(),
+            ScriptType::Pkh(hash.into())
+        );
+        let sh_wpkh = ScriptPubkey::p2sh_p2wpkh(&keys[0]);
+        assert_eq!(sh_wpkh, ScriptPubkey::p2sh(&Script::from(&wpkh)));
+        assert!(matches!(sh_wpkh.standard_type(), ScriptType::Sh(_)));
+
+        let multisig = ScriptPubkey::p2ms(2, &keys).unwrap();
+        assert_eq!(
+            multisig.standard_type(),
+            ScriptType::Multisig { m: 2, n: 3 }
+        );
+        let (m, found) = multisig_keys(multisig.items()).unwrap();
+        assert_eq!(m, 2);
+        for (key, found) in keys.iter().zip(found.iter()) {
+            let key: &coins_bip32::ecdsa::VerifyingKey = key.as_ref();
+            assert_eq!(&key.to_bytes()[..], *found);
+        }
+        assert_eq!(ScriptPubkey::p2ms(0, &keys), None);
+        assert_eq!(ScriptPubkey::p2ms(4, &keys), None);
+    }
+
+    #[test]
+    fn it_removes_codeseparators() {
+        let cases = [
+            ("ab76abac", "76ac"),
+            // pushed data is kept
+            ("01ab4c01abab", "01ab4c01ab"),
+            // a truncated push is kept as it is
+            ("ab4c05abab", "4c05abab"),
+        ];
+        for (script, expected) in cases.iter() {
+            let script = hex::decode(script).unwrap();
+            assert_eq!(hex::encode(remove_codeseparators(&script)), *expected);
+        }
+    }
+
+    #[test]
+    fn it_checks_push_only_scripts() {
+        assert!(is_push_only(&[]));
+        assert!(is_push_only(&hex::decode("004c020102510060").unwrap()));
+        // OP_DUP
+        assert!(!is_push_only(&[0x51, 0x76]));
+        // truncated pushes
+        assert!(!is_push_only(&[0x02, 0x01]));
+        assert!(!is_push_only(&[0x4d, 0x01]));
+    }
+
+    #[test]
+    fn it_counts_sigops() {
+        // 2-of-3 multisig
+        let multisig = hex::decode("52210375e00eb72e29da82b89367947f29ef34afb75e8654f6ea368e0acdfd92976b7c2103a1b26313f430c4b15bb1fdce663207659d8cac749a0e53d70eff01874496feff2103c96d495bfdd5ba4145e3e046fee45e84a8a48ad05bd8dbb395c011a32cf9f88053ae").unwrap();
+        assert_eq!(sigop_count(&multisig, true), 3);
+        assert_eq!(sigop_count(&multisig, false), 20);
+
+        let p2pkh = hex::decode("76a9140e5c3c8d420c7f11e88d76f7b860d471e6517a4488ac").unwrap();
+        assert_eq!(sigop_count(&p2pkh, false), 1);
+
+        // pushed data is not counted, and truncated pushes end the script
+        assert_eq!(sigop_count(&[0x01, 0xac], false), 0);
+        assert_eq!(sigop_count(&[0xac, 0x4c, 0x05, 0xac], false), 1);
+
+        let mut script_sig = vec![0x00, 0x4c, multisig.len() as u8];
+        script_sig.extend(&multisig);
+        assert_eq!(last_push(&script_sig), Some(&multisig[..]));
+        assert_eq!(last_push(&[0xac]), None);
+    }
+
     #[test]
     fn it_determines_script_pubkey_types_accurately() {
         let cases = [
@@ -306,6 +732,7 @@ mod test {
             (ScriptPubkey::new(hex::decode("77a9140e5c3c8d420c7f11e88d76f7b860d471e6517a4488ac").unwrap()), ScriptType::NonStandard), // wrong first byte
             (ScriptPubkey::new(hex::decode("00201bf8a1831db5443b42a44f30a121d1b616d011ab15df62b588722a845864cc99").unwrap()), ScriptType::Wsh([27, 248, 161, 131, 29, 181, 68, 59, 66, 164, 79, 48, 161, 33, 209, 182, 22, 208, 17, 171, 21, 223, 98, 181, 136, 114, 42, 132, 88, 100, 204, 153].into())),
             (ScriptPubkey::new(hex::decode("01201bf8a1831db5443b42a44f30a121d1b616d011ab15df62b588722a845864cc99").unwrap()), ScriptType::NonStandard), // wrong witness program version
+            (ScriptPubkey::new(hex::decode("51201bf8a1831db5443b42a44f30a121d1b616d011ab15df62b588722a845864cc99").unwrap()), ScriptType::Tr([27, 248, 161, 131, 29, 181, 68, 59, 66, 164, 79, 48, 161, 33, 209, 182, 22, 208, 17, 171, 21, 223, 98, 181, 136, 114, 42, 132, 88, 100, 204, 153])),
             (ScriptPubkey::new(hex::decode("00141bf8a1831db5443b42a44f30a121d1b616d011ab").unwrap()), ScriptType::Wpkh([27, 248, 161, 131, 29, 181, 68, 59, 66, 164, 79, 48, 161, 33, 209, 182, 22, 208, 17, 171].into())),
             (ScriptPubkey::new(hex::decode("01141bf8a1831db5443b42a44f30a121d1b616d011ab").unwrap()), ScriptType::NonStandard), // wrong witness program version
             (ScriptPubkey::new(hex::decode("0011223344").unwrap()), ScriptType::NonStandard), // junk
@@ -318,4 +745,34 @@ mod test {
             assert_eq!(script.standard_type(), *t);
         }
     }
+
+    #[test]
+    fn it_extracts_multisig_keys_and_witness_programs() {
+        let multisig = hex::decode("52210375e00eb72e29da82b89367947f29ef34afb75e8654f6ea368e0acdfd92976b7c2103a1b26313f430c4b15bb1fdce663207659d8cac749a0e53d70eff01874496feff2103c96d495bfdd5ba4145e3e046fee45e84a8a48ad05bd8dbb395c011a32cf9f88053ae").unwrap();
+        let script = Script::new(multisig.clone());
+        assert_eq!(script.standard_type(), ScriptType::Multisig { m: 2, n: 3 });
+        let (m, keys) = multisig_keys(&multisig).unwrap();
+        assert_eq!(m, 2);
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[1], &multisig[36..69]);
+
+        // m > n, bad key prefix, and truncated
+        let mut bad = multisig.clone();
+        bad[0] = 0x54;
+        assert_eq!(multisig_keys(&bad), None);
+        let mut bad = multisig.clone();
+        bad[2] = 0x05;
+        assert_eq!(multisig_keys(&bad), None);
+        assert_eq!(multisig_keys(&multisig[..multisig.len() - 2]), None);
+
+        let wsh = ScriptPubkey::p2wsh(&script);
+        assert_eq!(witness_program(wsh.items()), Some((0, &wsh.items()[2..])));
+        assert!(wsh.standard_type().is_witness());
+        assert_eq!(
+            witness_program(&[0x60, 0x02, 0xaa, 0xbb]),
+            Some((16, &[0xaa, 0xbb][..]))
+        );
+        assert_eq!(witness_program(&[0x61, 0x02, 0xaa, 0xbb]), None);
+        assert_eq!(witness_program(&multisig), None);
+    }
 }
@@ -12,7 +12,10 @@ use crate::{
     hashes::TXID,
     types::{
         legacy::*,
-        script::Witness,
+        limits::{LimitedReader, ReadLimits},
+        locktime::LockTime,
+        policy::{self, StandardnessError},
+        script::{last_push, sigop_count, witness_program, ScriptType, Witness},
         txin::{BitcoinOutpoint, BitcoinTxIn},
         txout::TxOut,
         witness::*,
@@ -64,6 +67,31 @@ impl BitcoinTx {
     //     }
     // }
 
+    /// Deserialize a transaction, enforcing `limits` on its size, and on the lengths and counts
+    /// it contains.
+    pub fn read_from_with_limits<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Self> {
+        let mut reader = LimitedReader::new(reader, limits.max_tx_size);
+        let result = Self::read_limited(&mut reader, limits);
+        reader.check(result, limits.max_tx_size)
+    }
+
+    fn read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Self> {
+        // Read the first 6 bytes, look for the witness tag, then chain them back on the front
+        // of the reader
+        let mut tag = [0u8; 6];
+        reader.read_exact(&mut tag)?;
+        let mut chain = tag.chain(reader);
+        if tag[4..=5] == [0, 1] {
+            Ok(BitcoinTx::Witness(WitnessTx::read_limited(
+                &mut chain, limits,
+            )?))
+        } else {
+            Ok(BitcoinTx::Legacy(LegacyTx::read_limited(
+                &mut chain, limits,
+            )?))
+        }
+    }
+
     /// True if the wrapped tx is a witness transaction. False otherwise
     pub fn is_witness(&self) -> bool {
         matches!(self, BitcoinTx::Witness(_))
@@ -91,16 +119,7 @@ impl ByteFormat for BitcoinTx {
         R: Read,
         Self: std::marker::Sized,
     {
-        // Read the first 6 bytes, look for the witness tag, then chain them back on the front
-        // of the reader
-        let mut tag = [0u8; 6];
-        reader.read_exact(&mut tag)?;
-        let mut chain = tag.chain(reader);
-        if tag[4..=5] == [0, 1] {
-            Ok(BitcoinTx::Witness(WitnessTx::read_from(&mut chain)?))
-        } else {
-            Ok(BitcoinTx::Legacy(LegacyTx::read_from(&mut chain)?))
-        }
+        Self::read_from_with_limits(reader, &ReadLimits::default())
     }
 
     fn write_to<W>(&self, writer: &mut W) -> Result<usize, <Self as ByteFormat>::Error>
@@ -222,6 +241,27 @@ pub enum TxError {
     #[error("Sighash args must match the wrapped tx type")]
     WrongSighashArgs,
 
+    /// Taproot sighash needs the prevout of every input
+    #[error("Expected one prevout per input. Got {}.", .0)]
+    WrongPrevoutCount(usize),
+
+    /// A `SighashCache` was passed different prevouts than its cached hashes commit to
+    #[error("Prevouts differ from those of earlier cached sighashes")]
+    PrevoutMismatch,
+
+    /// Taproot annexes must start with 0x50
+    #[error("Annex must start with 0x50")]
+    InvalidAnnex,
+
+    /// A length or count read while deserializing is above its `ReadLimits` cap
+    #[error("{what} exceeds the limit of {limit}")]
+    LimitExceeded {
+        /// What was being read
+        what: &'static str,
+        /// The limit
+        limit: usize,
+    },
+
     /// No outputs in vout
     #[error("Vout may not be empty")]
     EmptyVout,
@@ -229,6 +269,10 @@ pub enum TxError {
     /// No inputs in vin
     #[error("Vin may not be empty")]
     EmptyVin,
+
+    /// The builder pays silent payment addresses, but their outputs have not been derived
+    #[error("Silent payment outputs not derived. Use `derive_silent_payments`")]
+    PendingSilentPayments,
 }
 
 /// Type alias for result with TxError
@@ -262,6 +306,87 @@ pub trait BitcoinTransaction:
     /// For witness txns, this will ALWAYS be the same length as the input vector.
     fn witnesses(&self) -> &[Witness];
 
+    /// The BIP141 weight of the transaction: 3 times its size without witnesses, plus its full
+    /// size. A witness tx with no witness data is sized as a legacy tx.
+    fn weight(&self) -> usize {
+        let base = self.as_legacy().serialized_length();
+        let total = if self.witnesses().iter().all(|w| w.is_empty()) {
+            base
+        } else {
+            self.serialized_length()
+        };
+        base * 3 + total
+    }
+
+    /// The virtual size of the transaction, in vbytes. Its weight divided by 4, rounded up.
+    fn vsize(&self) -> usize {
+        self.weight().div_ceil(4)
+    }
+
+    /// The BIP141 signature operation cost of the transaction. `prevouts` are the outputs spent
+    /// by each input, in input order. P2SH and witness sigops are only counted for inputs with a
+    /// prevout.
+    fn sigops(&self, prevouts: &[TxOut]) -> usize {
+        let legacy: usize = self
+            .inputs()
+            .iter()
+            .map(|input| sigop_count(input.script_sig.items(), false))
+            .chain(
+                self.outputs()
+                    .iter()
+                    .map(|output| sigop_count(output.script_pubkey.items(), false)),
+            )
+            .sum();
+        let mut cost = legacy * 4;
+
+        for (i, (input, prevout)) in self.inputs().iter().zip(prevouts.iter()).enumerate() {
+            let mut program = prevout.script_pubkey.items();
+            if let ScriptType::Sh(_) = prevout.script_pubkey.standard_type() {
+                match last_push(input.script_sig.items()) {
+                    Some(redeem_script) => {
+                        cost += sigop_count(redeem_script, true) * 4;
+                        program = redeem_script;
+                    }
+                    None => continue,
+                }
+            }
+
+            // witness v0 programs. Sigops in other versions are not counted
+            let program = match witness_program(program) {
+                Some((0, program)) => program,
+                _ => continue,
+            };
+            match program.len() {
+                20 => cost += 1,
+                32 => {
+                    if let Some(script) = self.witnesses().get(i).and_then(|w| w.last()) {
+                        cost += sigop_count(script.items(), true);
+                    }
+                }
+                _ => {}
+            }
+        }
+        cost
+    }
+
+    /// The typed locktime of the transaction
+    fn lock_time(&self) -> LockTime {
+        LockTime::from_consensus(self.locktime())
+    }
+
+    /// True if the transaction signals BIP125 replaceability. I.e. any of its inputs has a
+    /// sequence number below `0xffff_fffe`. Does not account for replaceability inherited from
+    /// unconfirmed ancestors.
+    fn signals_rbf(&self) -> bool {
+        self.inputs().iter().any(BitcoinTxIn::signals_rbf)
+    }
+
+    /// Check the transaction against Bitcoin Core's default relay policy. Returns the first
+    /// rule it breaks. Rules that need the spent outputs, such as sigop limits, are not checked.
+    fn check_standardness(&self) -> Result<(), StandardnessError> {
+        policy::check_standardness(self)
+    }
+
     /// Get a reference to the output by
     fn txout_from_outpoint(&self, outpoint: &BitcoinOutpoint) -> Option<&TxOut> {
         if outpoint.txid == self.txid() && (outpoint.idx as usize) < self.outputs().len() {
@@ -438,7 +563,7 @@ mod tests {
             index: 0,
             sighash_flag: Sighash::All,
             prevout_script,
-            prevout_value: 120000,
+            prevout_value: Amount::from_sat(120000),
         };
 
         assert_eq!(tx.sighash(&args).unwrap(), all);
@@ -494,7 +619,7 @@ mod tests {
             index: 1,
             sighash_flag: Sighash::All,
             prevout_script,
-            prevout_value: 120000,
+            prevout_value: Amount::from_sat(120000),
         };
 
         assert_eq!(tx.sighash(&args).unwrap(), all);
@@ -582,6 +707,31 @@ mod tests {
         assert_eq!(tx.wtxid(), wtxid);
     }
 
+    #[test]
+    fn it_calculates_weight_and_sigops() {
+        // from mainnet: 3c7fb4af9b7bd2ba6f155318e0bc8a50432d4732ab6e36293ef45b304567b46a
+        // spends a 2-of-3 multisig p2wsh output
+        let tx_hex = "01000000000101b77bebb3ac480e99c0d95a4c812137b116e65e2f3b3a66a36d0e252928d460180100000000ffffffff03982457000000000017a91417b8e0f150215cc70bf2fb58070041d655b162dd8740e133000000000017a9142535e444f7d55f0500c1f86609d6cfc289576b698747abfb0100000000220020701a8d401c84fb13e6baf169d59684e17abd9fa216c8cc5b9fc63d622ff8c58d040047304402205c6a889efa26955bef7ce2b08792e63e25eac9859080f0d83912b0ea833d7eb402205f859f4640f1600db5012b467ec05bb4ae1779640c1b5fadc8908960740e52b30147304402201c239ea25cfeadfa9493a1b0d136d70f50f821385972b7188c4329c2bf2d23a302201ee790e4b6794af6567f85a226a387d5b0222c3dc90d2fc558d09e08062b8271016952210375e00eb72e29da82b89367947f29ef34afb75e8654f6ea368e0acdfd92976b7c2103a1b26313f430c4b15bb1fdce663207659d8cac749a0e53d70eff01874496feff2103c96d495bfdd5ba4145e3e046fee45e84a8a48ad05bd8dbb395c011a32cf9f88053ae00000000";
+        let tx = BitcoinTx::deserialize_hex(tx_hex).unwrap();
+        assert_eq!(tx.weight(), 886);
+        assert_eq!(tx.vsize(), 222);
+
+        let prevout = TxOut::new(
+            Amount::ZERO,
+            ScriptPubkey::new(
+                hex::decode("0020701a8d401c84fb13e6baf169d59684e17abd9fa216c8cc5b9fc63d622ff8c58d")
+                    .unwrap(),
+            ),
+        );
+        assert_eq!(tx.sigops(&[prevout]), 3);
+        assert_eq!(tx.sigops(&[]), 0);
+
+        // legacy txns weigh 4 times their size
+        let legacy = tx.clone().into_legacy();
+        assert_eq!(legacy.weight(), 158 * 4);
+        assert_eq!(legacy.vsize(), 158);
+    }
+
     #[test]
     fn it_rejects_sighash_none() {
         let tx_hex = "02000000000102ee9242c89e79ab2aa537408839329895392b97505b3496d5543d6d2f531b94d20000000000fdffffffee9242c89e79ab2aa537408839329895392b97505b3496d5543d6d2f531b94d20000000000fdffffff0273d301000000000017a914bba5acbec4e6e3374a0345bf3609fa7cfea825f18773d301000000000017a914bba5acbec4e6e3374a0345bf3609fa7cfea825f1870000cafd0700";
@@ -591,7 +741,7 @@ mod tests {
             index: 0,
             sighash_flag: Sighash::None,
             prevout_script: vec![].into(),
-            prevout_value: 120000,
+            prevout_value: Amount::from_sat(120000),
         };
 
         match tx.sighash(&args) {
@@ -609,7 +759,7 @@ mod tests {
             index: 1,
             sighash_flag: Sighash::Single,
             prevout_script: vec![].into(),
-            prevout_value: 120000,
+            prevout_value: Amount::from_sat(120000),
         };
 
         match tx.sighash(&args) {
@@ -618,6 +768,98 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_calculates_consensus_legacy_sighashes() {
+        let p2pkh = |h| ScriptPubkey::p2pkh_from_hash(&[h; 20]);
+        let vin = vec![
+            BitcoinTxIn::new(
+                BitcoinOutpoint::new(TXID::from([1u8; 32]), 0),
+                vec![],
+                0xffff_fffd,
+            ),
+            BitcoinTxIn::new(
+                BitcoinOutpoint::new(TXID::from([2u8; 32]), 1),
+                vec![],
+                0xffff_ffff,
+            ),
+        ];
+        let vout = vec![
+            TxOut::new(Amount::from_sat(10_000), p2pkh(3)),
+            TxOut::new(Amount::from_sat(20_000), p2pkh(4)),
+        ];
+        let tx = LegacyTx::new(1, vin, vout, 0).unwrap();
+
+        // OP_CODESEPARATORs are removed, except inside pushes
+        let prevout_script = Script::new(
+            [
+                &[0x01, 0xab, 0x76, 0xab, 0xa9, 0x14][..],
+                &[5; 20],
+                &[0x88, 0xab, 0xac],
+            ]
+            .concat(),
+        );
+        let cases = [
+            (
+                1,
+                Sighash::None,
+                "265a225e463a2ad0c1a8abe0df2d960c9a9c64c5ae2c67f1b37696fe4f804366",
+            ),
+            (
+                0,
+                Sighash::NoneAcp,
+                "67776d8b4a9e42736226b06bb6fa5e2e12094343470890356cdd8e4a80b4dc5e",
+            ),
+            (
+                1,
+                Sighash::Single,
+                "06377193acf0cb9300fbbb4ef481b05736cfcdf65d6b1defe0ad234d9835167b",
+            ),
+            (
+                0,
+                Sighash::All,
+                "3adc3e11258575c61a2928ea37b2e458d1b7de7fdddd323ba8b653edc68fa501",
+            ),
+        ];
+        for (index, sighash_flag, digest) in cases.iter() {
+            let args = LegacySighashArgs {
+                index: *index,
+                sighash_flag: *sighash_flag,
+                prevout_script: prevout_script.clone(),
+            };
+            assert_eq!(
+                tx.consensus_sighash(&args).unwrap().serialize_hex(),
+                *digest
+            );
+        }
+
+        // without OP_CODESEPARATORs, the result matches `legacy_sighash`
+        let args = LegacySighashArgs {
+            index: 1,
+            sighash_flag: Sighash::SingleAcp,
+            prevout_script: Script::from(&p2pkh(5)),
+        };
+        assert_eq!(
+            tx.consensus_sighash(&args).unwrap().as_slice(),
+            tx.sighash(&args).unwrap().as_slice()
+        );
+
+        // SIGHASH_SINGLE without a matching output signs the digest 1
+        let tx = LegacyTx::new(1, tx.inputs().to_vec(), vec![tx.outputs()[0].clone()], 0).unwrap();
+        let args = LegacySighashArgs {
+            index: 1,
+            sighash_flag: Sighash::Single,
+            prevout_script: prevout_script.clone(),
+        };
+        assert_eq!(
+            tx.consensus_sighash(&args).unwrap().serialize_hex(),
+            format!("01{}", "00".repeat(31))
+        );
+        assert!(matches!(
+            tx.write_consensus_sighash_preimage(&mut vec![], &args),
+            Err(TxError::SighashSingleBug)
+        ));
+    }
+
     #[test]
     fn it_calculates_legacy_sighash_of_witness_txns() {
         // pulled from riemann-py helpers
@@ -8,7 +8,18 @@ use coins_core::{
     types::tx::{Input, TxoIdentifier},
 };
 
-use crate::{hashes::TXID, types::script::ScriptSig};
+use crate::{
+    hashes::TXID,
+    types::{
+        limits::{into_ser_error, read_bytes, ReadLimits},
+        script::ScriptSig,
+        tx::TxResult,
+    },
+};
+
+/// The highest sequence number that signals BIP125 replaceability. Used by default for
+/// replaceable inputs, as it does not enable a relative timelock.
+pub const RBF_SEQUENCE: u32 = 0xffff_fffd;
 /// An Outpoint. This is a unique identifier for a UTXO, and is composed of a transaction ID (in
 /// Bitcoin-style LE format), and the index of the output being spent within that transactions
 /// output vectour (vout).
@@ -151,6 +162,24 @@ where
     pub fn unsigned(&self) -> TxInput<M> {
         Self::new(self.outpoint, vec![], self.sequence)
     }
+
+    /// Deserialize an input, enforcing the script length limit
+    pub(crate) fn read_with_limits<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Self>
+    where
+        M: ByteFormat,
+    {
+        Ok(TxInput {
+            outpoint: Outpoint::read_from(reader)?,
+            script_sig: read_bytes(reader, "script sig length", limits.max_script_len)?.into(),
+            sequence: coins_core::ser::read_u32_le(reader)?,
+        })
+    }
+
+    /// True if the input signals BIP125 replaceability. I.e. its sequence number is below
+    /// `0xffff_fffe`.
+    pub fn signals_rbf(&self) -> bool {
+        self.sequence <= RBF_SEQUENCE
+    }
 }
 
 impl<M> ByteFormat for TxInput<M>
@@ -171,11 +200,7 @@ where
         T: Read,
         Self: std::marker::Sized,
     {
-        Ok(TxInput {
-            outpoint: Outpoint::read_from(reader)?,
-            script_sig: ScriptSig::read_from(reader)?,
-            sequence: coins_core::ser::read_u32_le(reader)?,
-        })
+        Self::read_with_limits(reader, &ReadLimits::default()).map_err(into_ser_error)
     }
 
     fn write_to<T>(&self, writer: &mut T) -> SerResult<usize>
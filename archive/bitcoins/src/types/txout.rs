@@ -7,7 +7,13 @@ use coins_core::{
     types::tx::Output,
 };
 
-use crate::types::script::{ScriptPubkey, ScriptType};
+use crate::types::{
+    amount::Amount,
+    fee::FeeRate,
+    limits::{into_ser_error, read_bytes, ReadLimits},
+    script::{witness_program, ScriptPubkey, ScriptType, MAX_OP_RETURN_DATA, MAX_SCRIPT_SIZE},
+    tx::TxResult,
+};
 
 /// An Output. This describes a new UTXO to be created. The value is encoded as an LE u64. The
 /// script pubkey encodes the spending constraints.
@@ -17,14 +23,14 @@ use crate::types::script::{ScriptPubkey, ScriptType};
 /// sighash calculations.
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct TxOut {
-    /// The value of the output in satoshis
-    pub value: u64,
+    /// The value of the output
+    pub value: Amount,
     /// The `ScriptPubkey` which locks the UTXO.
     pub script_pubkey: ScriptPubkey,
 }
 
 impl Output for TxOut {
-    type Value = u64;
+    type Value = Amount;
     type RecipientIdentifier = ScriptPubkey;
 }
 
@@ -36,7 +42,7 @@ impl Default for TxOut {
 
 impl TxOut {
     /// Instantiate a new TxOut.
-    pub fn new<T>(value: u64, script_pubkey: T) -> Self
+    pub fn new<T>(value: Amount, script_pubkey: T) -> Self
     where
         T: Into<ScriptPubkey>,
     {
@@ -49,22 +55,50 @@ impl TxOut {
     /// Instantiate the null TxOut, which is used in Legacy Sighash.
     pub fn null() -> Self {
         TxOut {
-            value: 0xffff_ffff_ffff_ffff,
+            value: Amount::from_sat(0xffff_ffff_ffff_ffff),
             script_pubkey: ScriptPubkey::null(),
         }
     }
 
-    /// Instantiate an OP_RETURN output with some data. Discards all but the first 75 bytes.
+    /// Instantiate an OP_RETURN output with some data. Discards all but the first
+    /// `MAX_OP_RETURN_DATA` bytes.
     pub fn op_return(data: &[u8]) -> Self {
-        let mut data = data.to_vec();
-        data.truncate(75);
-
-        let mut payload = vec![0x6a, data.len() as u8];
-        payload.extend(data);
+        let data = &data[..std::cmp::min(data.len(), MAX_OP_RETURN_DATA)];
         TxOut {
-            value: 0,
-            script_pubkey: ScriptPubkey::from(payload),
+            value: Amount::ZERO,
+            script_pubkey: ScriptPubkey::op_return(data).expect("length checked"),
+        }
+    }
+
+    /// The smallest value this output can have without being dust at `fee_rate`, following
+    /// Bitcoin Core's `GetDustThreshold`. An output is dust if spending it would cost more than
+    /// it is worth. Unspendable outputs are never dust.
+    pub fn dust_threshold(&self, fee_rate: FeeRate) -> Amount {
+        let script = self.script_pubkey.items();
+        if script.first() == Some(&0x6a) || script.len() > MAX_SCRIPT_SIZE {
+            return Amount::ZERO;
         }
+        // outpoint, script sig length, and sequence, plus a signature and key. Witness data is
+        // discounted
+        let spend_size = if witness_program(script).is_some() {
+            32 + 4 + 1 + (107 / 4) + 4
+        } else {
+            32 + 4 + 1 + 107 + 4
+        };
+        Amount::from_sat(fee_rate.fee_for_vsize(self.serialized_length() + spend_size))
+    }
+
+    /// True if the output is worth less than the fee to spend it at `fee_rate`. Bitcoin Core
+    /// relays no dust outputs, at a feerate of `DUST_RELAY_FEE`.
+    pub fn is_dust(&self, fee_rate: FeeRate) -> bool {
+        self.value < self.dust_threshold(fee_rate)
+    }
+
+    /// Deserialize an output, enforcing the script length limit
+    pub(crate) fn read_with_limits<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Self> {
+        let value = Amount::from_sat(coins_core::ser::read_u64_le(reader)?);
+        let script_pubkey = read_bytes(reader, "script pubkey length", limits.max_script_len)?;
+        Ok(TxOut::new(value, script_pubkey))
     }
 
     /// Inspect the TxOut's script pubkey to determine its type.
@@ -92,18 +126,14 @@ impl ByteFormat for TxOut {
         R: Read,
         Self: std::marker::Sized,
     {
-        let value = coins_core::ser::read_u64_le(reader)?;
-        Ok(TxOut {
-            value,
-            script_pubkey: ScriptPubkey::read_from(reader)?,
-        })
+        Self::read_with_limits(reader, &ReadLimits::default()).map_err(into_ser_error)
     }
 
     fn write_to<W>(&self, writer: &mut W) -> SerResult<usize>
     where
         W: Write,
     {
-        let mut len = coins_core::ser::write_u64_le(writer, self.value)?;
+        let mut len = coins_core::ser::write_u64_le(writer, self.value.to_sat())?;
         len += self.script_pubkey.write_to(writer)?;
         Ok(len)
     }
@@ -118,10 +148,51 @@ mod test {
     use super::*;
     use coins_core::ser::ByteFormat;
 
+    #[test]
+    fn it_calculates_dust_thresholds() {
+        use crate::types::policy::DUST_RELAY_FEE;
+
+        let cases = [
+            (ScriptPubkey::p2pkh_from_hash(&[1; 20]), 546),
+            (ScriptPubkey::p2sh_from_hash(&[1; 20]), 540),
+            (ScriptPubkey::p2wpkh_from_hash(&[1; 20]), 294),
+            (ScriptPubkey::p2tr(&[1; 32]), 330),
+            (ScriptPubkey::op_return(&[1; 80]).unwrap(), 0),
+        ];
+        for (script_pubkey, threshold) in cases.iter() {
+            let output = TxOut::new(Amount::from_sat(*threshold), script_pubkey.clone());
+            assert_eq!(output.dust_threshold(DUST_RELAY_FEE).to_sat(), *threshold);
+            assert!(!output.is_dust(DUST_RELAY_FEE));
+            if *threshold > 0 {
+                let output = TxOut::new(Amount::from_sat(threshold - 1), script_pubkey.clone());
+                assert!(output.is_dust(DUST_RELAY_FEE));
+            }
+        }
+        // the threshold scales with the feerate
+        let output = TxOut::new(Amount::ZERO, ScriptPubkey::p2wpkh_from_hash(&[1; 20]));
+        assert_eq!(output.dust_threshold(FeeRate(1.0)).to_sat(), 98);
+    }
+
+    #[test]
+    fn it_builds_op_return_outputs() {
+        let short = TxOut::op_return(&[0xaa; 75]);
+        assert_eq!(short.script_pubkey.len(), 77);
+        assert_eq!(short.extract_op_return_data(), Some(vec![0xaa; 75]));
+
+        let long = TxOut::op_return(&[0xbb; 100]);
+        assert_eq!(&long.script_pubkey.items()[..3], &[0x6a, 0x4c, 80]);
+        assert_eq!(long.standard_type(), ScriptType::OpReturn(vec![0xbb; 80]));
+
+        assert_eq!(ScriptPubkey::op_return(&[0; 81]), None);
+        // non-minimal pushes are not standard
+        let non_minimal = ScriptPubkey::new(vec![0x6a, 0x4c, 0x01, 0x00]);
+        assert_eq!(non_minimal.extract_op_return_data(), None);
+    }
+
     #[test]
     fn it_serializes_and_derializes_outputs() {
         let cases = [
-            (TxOut::new(0, vec![]), "000000000000000000", 9),
+            (TxOut::new(Amount::ZERO, vec![]), "000000000000000000", 9),
             (TxOut::null(), "ffffffffffffffff00", 9),
         ];
         for case in cases.iter() {
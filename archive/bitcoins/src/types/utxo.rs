@@ -9,8 +9,8 @@
 //! This functionality does NOT currently support nested witness-via-p2sh prevouts. If you' like
 //! to use those, you'll need a processing step in your tx signer.
 use crate::types::{
-    BitcoinOutpoint, BitcoinTransaction, LegacySighashArgs, Script, ScriptPubkey, ScriptType,
-    Sighash, TxOut, WitnessSighashArgs,
+    Amount, BitcoinOutpoint, BitcoinTransaction, LegacySighashArgs, Script, ScriptPubkey,
+    ScriptType, Sighash, TxOut, WitnessSighashArgs,
 };
 use coins_core::hashes::{Digest, Hash160, MarkedDigest, MarkedDigestOutput, Sha256};
 use serde::{Deserialize, Serialize};
@@ -43,7 +43,7 @@ pub struct Utxo {
     /// UTXO outpoint
     pub outpoint: BitcoinOutpoint,
     /// UTXO value
-    pub value: u64,
+    pub value: Amount,
     /// The prevout script pubkey
     pub script_pubkey: ScriptPubkey,
     /// The prevout redeem script or witness script hashed into the script pubkey (if any)
@@ -55,7 +55,7 @@ impl Utxo {
     /// script_pubkey does not require a spend script, the spend_script will be discarded.
     pub fn new(
         outpoint: BitcoinOutpoint,
-        value: u64,
+        value: Amount,
         script_pubkey: ScriptPubkey,
         spend_script: SpendScript,
     ) -> Utxo {
@@ -115,12 +115,10 @@ impl Utxo {
                 let spk = self.script_pubkey();
                 match spk.standard_type() {
                     ScriptType::Pkh(_) => Some(spk.into()),
-                    // TODO: break this out into a function
                     ScriptType::Wpkh(payload) => {
-                        let mut v = vec![0x76, 0xa9, 0x14];
-                        v.extend(payload.as_slice());
-                        v.extend(&[0x88, 0xac]);
-                        Some(v.into())
+                        let mut hash = [0u8; 20];
+                        hash.copy_from_slice(payload.as_slice());
+                        Some(Script::from(&ScriptPubkey::p2pkh_from_hash(&hash)))
                     }
                     _ => None, // Should be unreachable
                 }
@@ -3,16 +3,20 @@
 use std::io::{Read, Write};
 
 use coins_core::{
-    hashes::{Digest, DigestOutput, Hash256, Hash256Digest, MarkedDigest, MarkedDigestOutput},
+    hashes::{
+        Digest, DigestOutput, Hash256, Hash256Digest, MarkedDigest, MarkedDigestOutput, Sha256,
+    },
     ser::{self, ByteFormat},
     types::tx::Transaction,
 };
 
 use crate::{
-    hashes::{TXID, WTXID},
+    hashes::{tagged_hash, TXID, WTXID},
     types::{
+        amount::Amount,
         legacy::*,
-        script::{Script, Witness},
+        limits::{read_bytes, read_len, LimitedReader, ReadLimits},
+        script::{Script, Witness, WitnessStackItem},
         tx::*,
         txin::BitcoinTxIn,
         txout::TxOut,
@@ -132,7 +136,53 @@ pub struct WitnessSighashArgs {
     /// currently being executed.
     pub prevout_script: Script,
     /// The value of the prevout.
-    pub prevout_value: u64,
+    pub prevout_value: Amount,
+}
+
+/// Arguments required to serialize the transaction to create the BIP341 (taproot) sighash
+/// digest. Used in `taproot_sighash`.
+///
+/// Unlike BIP143, the taproot sighash commits to the amounts and script pubkeys of all spent
+/// outputs, so `prevouts` must hold the output spent by each input, in input order. With
+/// ANYONECANPAY only the signed input's prevout is committed to.
+///
+/// All sighash modes are supported, including SIGHASH_NONE. `sighash_flag: None` is
+/// SIGHASH_DEFAULT, which signs the same data as SIGHASH_ALL.
+///
+/// For BIP341 sighash documentation, see here:
+///
+/// - https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki#common-signature-message
+///
+/// # Note
+///
+/// After signing the digest with any explicit sighash flag, you MUST append the sighash
+/// indicator byte to the resulting signature. SIGHASH_DEFAULT signatures are 64 bytes, with no
+/// indicator.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TaprootSighashArgs {
+    /// The index of the input we'd like to sign
+    pub index: usize,
+    /// The sighash mode to use. `None` for SIGHASH_DEFAULT.
+    pub sighash_flag: Option<Sighash>,
+    /// The outputs spent by each input, in input order
+    pub prevouts: Vec<TxOut>,
+    /// The annex of the signed input, if any. Includes its 0x50 prefix.
+    pub annex: Option<Vec<u8>>,
+    /// For script path spends, the `tapleaf_hash` of the executing script. `None` for key path
+    /// spends.
+    pub leaf_hash: Option<[u8; 32]>,
+    /// For script path spends, the opcode position of the last executed `OP_CODESEPARATOR`, or
+    /// `0xffff_ffff` if none has been executed. Ignored for key path spends.
+    pub codesep_pos: u32,
+}
+
+/// The BIP341 hashes committing to all inputs and their prevouts
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct TaprootInputHashes {
+    pub(crate) prevouts: [u8; 32],
+    pub(crate) amounts: [u8; 32],
+    pub(crate) script_pubkeys: [u8; 32],
+    pub(crate) sequences: [u8; 32],
 }
 
 /// A witness transaction. Any transaction that contains 1 or more witnesses.
@@ -149,8 +199,8 @@ impl WitnessTx {
     ///
     /// - https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
     ///
-    /// TODO: memoize
-    fn hash_prevouts(&self, sighash_flag: Sighash) -> TxResult<Hash256Digest> {
+    /// `SighashCache` reuses the result across inputs.
+    pub(crate) fn hash_prevouts(&self, sighash_flag: Sighash) -> TxResult<Hash256Digest> {
         if sighash_flag as u8 & 0x80 == 0x80 {
             Ok(Hash256Digest::default())
         } else {
@@ -168,8 +218,8 @@ impl WitnessTx {
     ///
     /// - https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
     ///
-    /// TODO: memoize
-    fn hash_sequence(&self, sighash_flag: Sighash) -> TxResult<Hash256Digest> {
+    /// `SighashCache` reuses the result across inputs.
+    pub(crate) fn hash_sequence(&self, sighash_flag: Sighash) -> TxResult<Hash256Digest> {
         if sighash_flag == Sighash::Single || sighash_flag as u8 & 0x80 == 0x80 {
             Ok(Hash256Digest::default())
         } else {
@@ -187,8 +237,12 @@ impl WitnessTx {
     ///
     /// - https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
     ///
-    /// TODO: memoize
-    fn hash_outputs(&self, index: usize, sighash_flag: Sighash) -> TxResult<Hash256Digest> {
+    /// `SighashCache` reuses the result across inputs.
+    pub(crate) fn hash_outputs(
+        &self,
+        index: usize,
+        sighash_flag: Sighash,
+    ) -> TxResult<Hash256Digest> {
         match sighash_flag {
             Sighash::All | Sighash::AllAcp => {
                 let mut w = Hash256::default();
@@ -206,6 +260,237 @@ impl WitnessTx {
         }
     }
 
+    /// Checks that the args can produce a BIP143 sighash for this transaction
+    pub(crate) fn check_witness_sighash_args(&self, args: &WitnessSighashArgs) -> TxResult<()> {
+        if args.sighash_flag == Sighash::None || args.sighash_flag == Sighash::NoneAcp {
+            return Err(TxError::NoneUnsupported);
+        }
+
+        if (args.sighash_flag == Sighash::Single || args.sighash_flag == Sighash::SingleAcp)
+            && args.index >= self.outputs().len()
+        {
+            return Err(TxError::SighashSingleBug);
+        }
+        Ok(())
+    }
+
+    /// Writes the BIP143 sighash preimage using precalculated hashes. The args must already be
+    /// checked.
+    pub(crate) fn write_witness_sighash_preimage_with<W: Write>(
+        &self,
+        writer: &mut W,
+        args: &WitnessSighashArgs,
+        hash_prevouts: &Hash256Digest,
+        hash_sequence: &Hash256Digest,
+        hash_outputs: &Hash256Digest,
+    ) -> TxResult<()> {
+        let input = &self.legacy_tx.vin[args.index];
+
+        ser::write_u32_le(writer, self.legacy_tx.version)?;
+        hash_prevouts.write_to(writer)?;
+        hash_sequence.write_to(writer)?;
+        input.outpoint.write_to(writer)?;
+        args.prevout_script.write_to(writer)?;
+        ser::write_u64_le(writer, args.prevout_value.to_sat())?;
+        ser::write_u32_le(writer, input.sequence)?;
+        hash_outputs.write_to(writer)?;
+        ser::write_u32_le(writer, self.legacy_tx.locktime)?;
+        ser::write_u32_le(writer, args.sighash_flag as u32)?;
+        Ok(())
+    }
+
+    /// Checks that the args can produce a BIP341 sighash for this transaction
+    pub(crate) fn check_taproot_sighash_args(&self, args: &TaprootSighashArgs) -> TxResult<()> {
+        if args.prevouts.len() != self.legacy_tx.vin.len() {
+            return Err(TxError::WrongPrevoutCount(args.prevouts.len()));
+        }
+        let output_type = args.sighash_flag.map_or(0, Sighash::to_u8) & 0x03;
+        if output_type == Sighash::Single as u8 && args.index >= self.legacy_tx.vout.len() {
+            return Err(TxError::SighashSingleBug);
+        }
+        if let Some(annex) = &args.annex {
+            if annex.first() != Some(&0x50) {
+                return Err(TxError::InvalidAnnex);
+            }
+        }
+        Ok(())
+    }
+
+    /// Calculates the BIP341 hashes of the prevouts, amounts, script pubkeys and sequences of
+    /// all inputs. `prevouts` must hold one output per input.
+    pub(crate) fn taproot_input_hashes(&self, prevouts: &[TxOut]) -> TxResult<TaprootInputHashes> {
+        let mut outpoints = vec![];
+        let mut amounts = vec![];
+        let mut script_pubkeys = vec![];
+        let mut sequences = vec![];
+        for (input, prevout) in self.legacy_tx.vin.iter().zip(prevouts.iter()) {
+            input.outpoint.write_to(&mut outpoints)?;
+            ser::write_u64_le(&mut amounts, prevout.value.to_sat())?;
+            prevout.script_pubkey.write_to(&mut script_pubkeys)?;
+            ser::write_u32_le(&mut sequences, input.sequence)?;
+        }
+        Ok(TaprootInputHashes {
+            prevouts: Sha256::digest(&outpoints).into(),
+            amounts: Sha256::digest(&amounts).into(),
+            script_pubkeys: Sha256::digest(&script_pubkeys).into(),
+            sequences: Sha256::digest(&sequences).into(),
+        })
+    }
+
+    /// Calculates the BIP341 hash of all outputs
+    pub(crate) fn taproot_outputs_hash(&self) -> TxResult<[u8; 32]> {
+        let mut outputs = vec![];
+        for output in self.legacy_tx.vout.iter() {
+            output.write_to(&mut outputs)?;
+        }
+        Ok(Sha256::digest(&outputs).into())
+    }
+
+    /// Writes the BIP341 sighash preimage using precalculated hashes. `inputs` must be provided
+    /// unless signing with ANYONECANPAY, and `outputs` must be provided when signing with
+    /// SIGHASH_DEFAULT or SIGHASH_ALL. The args must already be checked.
+    pub(crate) fn write_taproot_sighash_preimage_with<W: Write>(
+        &self,
+        writer: &mut W,
+        args: &TaprootSighashArgs,
+        inputs: Option<&TaprootInputHashes>,
+        outputs: Option<&[u8; 32]>,
+    ) -> TxResult<()> {
+        let vin = &self.legacy_tx.vin;
+        let vout = &self.legacy_tx.vout;
+        let hash_type = args.sighash_flag.map_or(0, Sighash::to_u8);
+        let anyone_can_pay = hash_type & 0x80 == 0x80;
+        let output_type = hash_type & 0x03;
+
+        // epoch
+        writer.write_all(&[0x00, hash_type])?;
+        ser::write_u32_le(writer, self.legacy_tx.version)?;
+        ser::write_u32_le(writer, self.legacy_tx.locktime)?;
+
+        if !anyone_can_pay {
+            let inputs = inputs.expect("input hashes are provided without ANYONECANPAY");
+            writer.write_all(&inputs.prevouts)?;
+            writer.write_all(&inputs.amounts)?;
+            writer.write_all(&inputs.script_pubkeys)?;
+            writer.write_all(&inputs.sequences)?;
+        }
+
+        // SIGHASH_DEFAULT and SIGHASH_ALL
+        if output_type != Sighash::None as u8 && output_type != Sighash::Single as u8 {
+            writer.write_all(outputs.expect("outputs hash is provided with SIGHASH_ALL"))?;
+        }
+
+        let ext_flag = if args.leaf_hash.is_some() { 1 } else { 0 };
+        let spend_type = ext_flag * 2 + args.annex.is_some() as u8;
+        writer.write_all(&[spend_type])?;
+
+        if anyone_can_pay {
+            let input = &vin[args.index];
+            let prevout = &args.prevouts[args.index];
+            input.outpoint.write_to(writer)?;
+            ser::write_u64_le(writer, prevout.value.to_sat())?;
+            prevout.script_pubkey.write_to(writer)?;
+            ser::write_u32_le(writer, input.sequence)?;
+        } else {
+            ser::write_u32_le(writer, args.index as u32)?;
+        }
+
+        if let Some(annex) = &args.annex {
+            let mut buf = vec![];
+            ser::write_compact_int(&mut buf, annex.len() as u64)?;
+            buf.extend(annex);
+            writer.write_all(&Sha256::digest(&buf))?;
+        }
+
+        if output_type == Sighash::Single as u8 {
+            let mut buf = vec![];
+            vout[args.index].write_to(&mut buf)?;
+            writer.write_all(&Sha256::digest(&buf))?;
+        }
+
+        if let Some(leaf_hash) = &args.leaf_hash {
+            writer.write_all(leaf_hash)?;
+            // key version
+            writer.write_all(&[0x00])?;
+            ser::write_u32_le(writer, args.codesep_pos)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the BIP341 sighash preimage to the provided `writer`, starting with the sighash
+    /// epoch byte. See the `TaprootSighashArgs` documentation for more in-depth discussion of
+    /// sighash.
+    pub fn write_taproot_sighash_preimage<W: Write>(
+        &self,
+        writer: &mut W,
+        args: &TaprootSighashArgs,
+    ) -> TxResult<()> {
+        self.check_taproot_sighash_args(args)?;
+        let hash_type = args.sighash_flag.map_or(0, Sighash::to_u8);
+        let inputs = if hash_type & 0x80 == 0x80 {
+            None
+        } else {
+            Some(self.taproot_input_hashes(&args.prevouts)?)
+        };
+        let outputs = match hash_type & 0x03 {
+            0x00 | 0x01 => Some(self.taproot_outputs_hash()?),
+            _ => None,
+        };
+        self.write_taproot_sighash_preimage_with(writer, args, inputs.as_ref(), outputs.as_ref())
+    }
+
+    /// Calculates the BIP341 sighash given the sighash args. See the `TaprootSighashArgs`
+    /// documentation for more in-depth discussion of sighash.
+    pub fn taproot_sighash(&self, args: &TaprootSighashArgs) -> TxResult<[u8; 32]> {
+        let mut preimage = vec![];
+        self.write_taproot_sighash_preimage(&mut preimage, args)?;
+        Ok(tagged_hash("TapSighash", &preimage))
+    }
+
+    /// Deserialize a transaction, enforcing `limits` on its size, and on the lengths and counts
+    /// it contains.
+    pub fn read_from_with_limits<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Self> {
+        let mut reader = LimitedReader::new(reader, limits.max_tx_size);
+        let result = Self::read_limited(&mut reader, limits);
+        reader.check(result, limits.max_tx_size)
+    }
+
+    /// Read the transaction without enforcing its size limit
+    pub(crate) fn read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> TxResult<Self> {
+        let version = ser::read_u32_le(reader)?;
+        let mut flag = [0u8; 2];
+        reader.read_exact(&mut flag)?;
+        if flag != [0u8, 1u8] {
+            return Err(TxError::BadWitnessFlag(flag));
+        };
+        let vin = read_vin(reader, limits)?;
+        let vout = read_vout(reader, limits)?;
+        let mut witnesses = vec![];
+        for _ in vin.iter() {
+            let items = read_len(reader, "witness item count", limits.max_witness_items)?;
+            let witness = (0..items)
+                .map(|_| {
+                    read_bytes(reader, "witness item length", limits.max_script_len)
+                        .map(WitnessStackItem::new)
+                })
+                .collect::<TxResult<Witness>>()?;
+            witnesses.push(witness);
+        }
+        let locktime = ser::read_u32_le(reader)?;
+
+        let legacy_tx = LegacyTx {
+            version,
+            vin,
+            vout,
+            locktime,
+        };
+
+        Ok(Self {
+            legacy_tx,
+            witnesses,
+        })
+    }
+
     /// Consumes a `LegacyTx` and instantiates a new `WitnessTx` with empty witnesses
     pub fn from_legacy(legacy_tx: LegacyTx) -> Self {
         let witnesses = (0..legacy_tx.inputs().len())
@@ -348,30 +633,14 @@ impl WitnessTransaction for WitnessTx {
     where
         W: Write,
     {
-        if args.sighash_flag == Sighash::None || args.sighash_flag == Sighash::NoneAcp {
-            return Err(TxError::NoneUnsupported);
-        }
-
-        if (args.sighash_flag == Sighash::Single || args.sighash_flag == Sighash::SingleAcp)
-            && args.index >= self.outputs().len()
-        {
-            return Err(TxError::SighashSingleBug);
-        }
-
-        let input = &self.legacy_tx.vin[args.index];
-
-        ser::write_u32_le(writer, self.legacy_tx.version)?;
-        self.hash_prevouts(args.sighash_flag)?.write_to(writer)?;
-        self.hash_sequence(args.sighash_flag)?.write_to(writer)?;
-        input.outpoint.write_to(writer)?;
-        args.prevout_script.write_to(writer)?;
-        ser::write_u64_le(writer, args.prevout_value)?;
-        ser::write_u32_le(writer, input.sequence)?;
-        self.hash_outputs(args.index, args.sighash_flag)?
-            .write_to(writer)?;
-        ser::write_u32_le(writer, self.legacy_tx.locktime)?;
-        ser::write_u32_le(writer, args.sighash_flag as u32)?;
-        Ok(())
+        self.check_witness_sighash_args(args)?;
+        self.write_witness_sighash_preimage_with(
+            writer,
+            args,
+            &self.hash_prevouts(args.sighash_flag)?,
+            &self.hash_sequence(args.sighash_flag)?,
+            &self.hash_outputs(args.index, args.sighash_flag)?,
+        )
     }
 }
 
@@ -408,31 +677,7 @@ impl ByteFormat for WitnessTx {
         R: Read,
         Self: std::marker::Sized,
     {
-        let version = ser::read_u32_le(reader)?;
-        let mut flag = [0u8; 2];
-        reader.read_exact(&mut flag)?;
-        if flag != [0u8, 1u8] {
-            return Err(TxError::BadWitnessFlag(flag));
-        };
-        let vin = ser::read_prefix_vec(reader)?;
-        let vout = ser::read_prefix_vec(reader)?;
-        let mut witnesses = vec![];
-        for _ in vin.iter() {
-            witnesses.push(ser::read_prefix_vec(reader)?);
-        }
-        let locktime = ser::read_u32_le(reader)?;
-
-        let legacy_tx = LegacyTx {
-            version,
-            vin,
-            vout,
-            locktime,
-        };
-
-        Ok(Self {
-            legacy_tx,
-            witnesses,
-        })
+        Self::read_from_with_limits(reader, &ReadLimits::default())
     }
 
     fn write_to<W>(&self, writer: &mut W) -> Result<usize, Self::Error>
@@ -455,7 +700,9 @@ impl ByteFormat for WitnessTx {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::types::{BitcoinTxIn, TxOut, Witness, WitnessStackItem};
+    use crate::types::{
+        Amount, BitcoinOutpoint, BitcoinTxIn, ScriptPubkey, TxOut, Witness, WitnessStackItem,
+    };
 
     #[test]
     fn it_should_ensure_correct_amount_of_witnesses_addition() {
@@ -481,4 +728,261 @@ mod test {
         assert_eq!(tx.witnesses.len(), expected_size);
         assert_eq!(expected_witness, tx.witnesses[0]);
     }
+
+    #[test]
+    fn it_calculates_taproot_sighashes() {
+        let vin = vec![
+            BitcoinTxIn::new(
+                BitcoinOutpoint::new(TXID::from([1u8; 32]), 0),
+                vec![],
+                0xffff_fffd,
+            ),
+            BitcoinTxIn::new(
+                BitcoinOutpoint::new(TXID::from([2u8; 32]), 1),
+                vec![],
+                0xffff_ffff,
+            ),
+        ];
+        let vout = vec![
+            TxOut::new(
+                Amount::from_sat(10_000),
+                ScriptPubkey::p2wpkh_from_hash(&[3; 20]),
+            ),
+            TxOut::new(Amount::from_sat(20_000), ScriptPubkey::p2tr(&[4; 32])),
+        ];
+        let prevouts = vec![
+            TxOut::new(Amount::from_sat(50_000), ScriptPubkey::p2tr(&[5; 32])),
+            TxOut::new(Amount::from_sat(60_000), ScriptPubkey::p2tr(&[6; 32])),
+        ];
+        let tx = <WitnessTx as WitnessTransaction>::new(2, vin, vout, vec![], 0).unwrap();
+
+        let leaf_hash = Script::new(vec![0x51]).tapleaf_hash();
+        assert_eq!(
+            hex::encode(leaf_hash),
+            "a85b2107f791b26a84e7586c28cec7cb61202ed3d01944d832500f363782d675"
+        );
+
+        let args = |index, sighash_flag, annex: Option<Vec<u8>>, leaf_hash| TaprootSighashArgs {
+            index,
+            sighash_flag,
+            prevouts: prevouts.clone(),
+            annex,
+            leaf_hash,
+            codesep_pos: 0xffff_ffff,
+        };
+        // preimage lengths follow BIP341, plus the epoch byte
+        let cases = [
+            (
+                args(0, None, None, None),
+                175,
+                "442b387004cc5fadbeb1d60ea4ebde093894b140c46c670be9a6a4b2b57f4424",
+            ),
+            (
+                args(1, Some(Sighash::All), None, None),
+                175,
+                "4a7cffe44481fa635644ab0e0f8191c5669da0ee2ff0cefe8579ab5277fe0de6",
+            ),
+            (
+                args(1, Some(Sighash::SingleAcp), Some(vec![0x50, 0x01]), None),
+                158,
+                "83ac981b9094bb7778cf20e69fc351f0900882508ca5ad7f378566b67fb0e89c",
+            ),
+            (
+                args(0, Some(Sighash::None), None, Some(leaf_hash)),
+                180,
+                "a306e5c838b8dd19d49c5fc682180a5ddd323b7b63522b2992ae090d346aebc2",
+            ),
+        ];
+        for (args, len, sighash) in cases.iter() {
+            let mut preimage = vec![];
+            tx.write_taproot_sighash_preimage(&mut preimage, args)
+                .unwrap();
+            assert_eq!(preimage.len(), *len);
+            assert_eq!(hex::encode(tx.taproot_sighash(args).unwrap()), *sighash);
+        }
+
+        let mut bad = args(0, None, None, None);
+        bad.prevouts.pop();
+        assert!(matches!(
+            tx.taproot_sighash(&bad),
+            Err(TxError::WrongPrevoutCount(1))
+        ));
+        let bad = args(0, None, Some(vec![0x51]), None);
+        assert!(matches!(
+            tx.taproot_sighash(&bad),
+            Err(TxError::InvalidAnnex)
+        ));
+        let mut tx = tx;
+        tx.legacy_tx.vout.pop();
+        let bad = args(1, Some(Sighash::Single), None, None);
+        assert!(matches!(
+            tx.taproot_sighash(&bad),
+            Err(TxError::SighashSingleBug)
+        ));
+    }
+
+    // The keyPathSpending vectors from BIP341
+    // https://github.com/bitcoin/bips/blob/master/bip-0341/wallet-test-vectors.json
+    #[test]
+    fn it_matches_bip341_key_path_vectors() {
+        let raw_tx = concat!(
+            "02000000097de20cbff686da83a54981d2b9bab3586f4ca7e48f57f5b55963115f3b334e9c010000",
+            "000000000000d7b7cab57b1393ace2d064f4d4a2cb8af6def61273e127517d44759b6dafdd990000",
+            "000000fffffffff8e1f583384333689228c5d28eac13366be082dc57441760d957275419a4184200",
+            "00000000fffffffff0689180aa63b30cb162a73c6d2a38b7eeda2a83ece74310fda0843ad604853b",
+            "0100000000feffffffaa5202bdf6d8ccd2ee0f0202afbbb7461d9264a25e5bfd3c5a52ee1239e0ba",
+            "6c0000000000feffffff956149bdc66faa968eb2be2d2faa29718acbfe3941215893a2a3446d32ac",
+            "d050000000000000000000e664b9773b88c09c32cb70a2a3e4da0ced63b7ba3b22f848531bbb1d5d",
+            "5f4c94010000000000000000e9aa6b8e6c9de67619e6a3924ae25696bb7b694bb677a632a74ef7ea",
+            "dfd4eabf0000000000ffffffffa778eb6a263dc090464cd125c466b5a99667720b1c110468831d05",
+            "8aa1b82af10100000000ffffffff0200ca9a3b000000001976a91406afd46bcdfd22ef94ac122aa1",
+            "1f241244a37ecc88ac807840cb0000000020ac9a87f5594be208f8532db38cff670c450ed2fea8fc",
+            "defcc9a663f78bab962b0065cd1d",
+        );
+        let utxos = [
+            (
+                "512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343",
+                420000000,
+            ),
+            (
+                "5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3",
+                462000000,
+            ),
+            (
+                "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac",
+                294000000,
+            ),
+            (
+                "5120e4d810fd50586274face62b8a807eb9719cef49c04177cc6b76a9a4251d5450e",
+                504000000,
+            ),
+            (
+                "512091b64d5324723a985170e4dc5a0f84c041804f2cd12660fa5dec09fc21783605",
+                630000000,
+            ),
+            ("00147dd65592d0ab2fe0d0257d571abf032cd9db93dc", 378000000),
+            (
+                "512075169f4001aa68f15bbed28b218df1d0a62cbbcf1188c6665110c293c907b831",
+                672000000,
+            ),
+            (
+                "5120712447206d7a5238acc7ff53fbe94a3b64539ad291c7cdbc490b7577e4b17df5",
+                546000000,
+            ),
+            (
+                "512077e30a5522dd9f894c3f8b8bd4c4b2cf82ca7da8a3ea6a239655c39c050ab220",
+                588000000,
+            ),
+        ];
+        // input index, hash type, sighash preimage, sighash
+        let cases = [
+            (
+                0,
+                0x03,
+                concat!(
+                    "0003020000000065cd1de3b33bb4ef3a52ad1fffb555c0d82828eb22737036eaeb02a235d82b909c",
+                    "4c3f58a6964a4f5f8f0b642ded0a8a553be7622a719da71d1f5befcefcdee8e0fde623ad0f61ad2b",
+                    "ca5ba6a7693f50fce988e17c3780bf2b1e720cfbb38fbdd52e2118959c7221ab5ce9e26c3cd67b22",
+                    "c24f8baa54bac281d8e6b05e400e6c3a957e0000000000d0418f0e9a36245b9a50ec87f8bf5be5bc",
+                    "ae434337b87139c3a5b1f56e33cba0",
+                ),
+                "2514a6272f85cfa0f45eb907fcb0d121b808ed37c6ea160a5a9046ed5526d555",
+            ),
+            (
+                1,
+                0x83,
+                concat!(
+                    "0083020000000065cd1d00d7b7cab57b1393ace2d064f4d4a2cb8af6def61273e127517d44759b6d",
+                    "afdd9900000000808f891b00000000225120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60",
+                    "371ee77557b6620f3ea3ffffffffffcef8fb4ca7efc5433f591ecfc57391811ce1e186a3793024de",
+                    "f5c884cba51d",
+                ),
+                "325a644af47e8a5a2591cda0ab0723978537318f10e6a63d4eed783b96a71a4d",
+            ),
+            (
+                3,
+                0x01,
+                concat!(
+                    "0001020000000065cd1de3b33bb4ef3a52ad1fffb555c0d82828eb22737036eaeb02a235d82b909c",
+                    "4c3f58a6964a4f5f8f0b642ded0a8a553be7622a719da71d1f5befcefcdee8e0fde623ad0f61ad2b",
+                    "ca5ba6a7693f50fce988e17c3780bf2b1e720cfbb38fbdd52e2118959c7221ab5ce9e26c3cd67b22",
+                    "c24f8baa54bac281d8e6b05e400e6c3a957ea2e6dab7c1f0dcd297c8d61647fd17d821541ea69c3c",
+                    "c37dcbad7f90d4eb4bc50003000000",
+                ),
+                "bf013ea93474aa67815b1b6cc441d23b64fa310911d991e713cd34c7f5d46669",
+            ),
+            (
+                4,
+                0x00,
+                concat!(
+                    "0000020000000065cd1de3b33bb4ef3a52ad1fffb555c0d82828eb22737036eaeb02a235d82b909c",
+                    "4c3f58a6964a4f5f8f0b642ded0a8a553be7622a719da71d1f5befcefcdee8e0fde623ad0f61ad2b",
+                    "ca5ba6a7693f50fce988e17c3780bf2b1e720cfbb38fbdd52e2118959c7221ab5ce9e26c3cd67b22",
+                    "c24f8baa54bac281d8e6b05e400e6c3a957ea2e6dab7c1f0dcd297c8d61647fd17d821541ea69c3c",
+                    "c37dcbad7f90d4eb4bc50004000000",
+                ),
+                "4f900a0bae3f1446fd48490c2958b5a023228f01661cda3496a11da502a7f7ef",
+            ),
+            (
+                6,
+                0x02,
+                concat!(
+                    "0002020000000065cd1de3b33bb4ef3a52ad1fffb555c0d82828eb22737036eaeb02a235d82b909c",
+                    "4c3f58a6964a4f5f8f0b642ded0a8a553be7622a719da71d1f5befcefcdee8e0fde623ad0f61ad2b",
+                    "ca5ba6a7693f50fce988e17c3780bf2b1e720cfbb38fbdd52e2118959c7221ab5ce9e26c3cd67b22",
+                    "c24f8baa54bac281d8e6b05e400e6c3a957e0006000000",
+                ),
+                "15f25c298eb5cdc7eb1d638dd2d45c97c4c59dcaec6679cfc16ad84f30876b85",
+            ),
+            (
+                7,
+                0x82,
+                concat!(
+                    "0082020000000065cd1d00e9aa6b8e6c9de67619e6a3924ae25696bb7b694bb677a632a74ef7eadf",
+                    "d4eabf00000000804c8b2000000000225120712447206d7a5238acc7ff53fbe94a3b64539ad291c7",
+                    "cdbc490b7577e4b17df5ffffffff",
+                ),
+                "cd292de50313804dabe4685e83f923d2969577191a3e1d2882220dca88cbeb10",
+            ),
+            (
+                8,
+                0x81,
+                concat!(
+                    "0081020000000065cd1da2e6dab7c1f0dcd297c8d61647fd17d821541ea69c3cc37dcbad7f90d4eb",
+                    "4bc500a778eb6a263dc090464cd125c466b5a99667720b1c110468831d058aa1b82af10100000000",
+                    "2b0c230000000022512077e30a5522dd9f894c3f8b8bd4c4b2cf82ca7da8a3ea6a239655c39c050a",
+                    "b220ffffffff",
+                ),
+                "cccb739eca6c13a8a89e6e5cd317ffe55669bbda23f2fd37b0f18755e008edd2",
+            ),
+        ];
+
+        let tx = WitnessTx::from_legacy(LegacyTx::deserialize_hex(raw_tx).unwrap());
+        let prevouts: Vec<_> = utxos
+            .iter()
+            .map(|(script_pubkey, value)| {
+                TxOut::new(
+                    Amount::from_sat(*value),
+                    ScriptPubkey::new(hex::decode(script_pubkey).unwrap()),
+                )
+            })
+            .collect();
+        for (index, hash_type, preimage, sighash) in cases.iter() {
+            let args = TaprootSighashArgs {
+                index: *index,
+                sighash_flag: match hash_type {
+                    0 => None,
+                    flag => Some(Sighash::from_u8(*flag).unwrap()),
+                },
+                prevouts: prevouts.clone(),
+                annex: None,
+                leaf_hash: None,
+                codesep_pos: 0xffff_ffff,
+            };
+            let mut buf = vec![];
+            tx.write_taproot_sighash_preimage(&mut buf, &args).unwrap();
+            assert_eq!(hex::encode(buf), *preimage);
+            assert_eq!(hex::encode(tx.taproot_sighash(&args).unwrap()), *sighash);
+        }
+    }
 }
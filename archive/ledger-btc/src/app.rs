@@ -1,14 +1,17 @@
-use crate::{utils::*, LedgerBTCError};
+use crate::{network::*, utils::*, LedgerBTCError};
 use bitcoins::{
+    enc::Address,
     prelude::Transaction,
-    types::{BitcoinTxIn, Utxo, WitnessTx},
+    types::{BitcoinTx, BitcoinTxIn, ScriptPubkey, Sighash, Utxo, WitnessTx},
 };
-use coins_bip32::{path::DerivationPath, prelude::*};
+use coins_bip32::{path::DerivationPath, prelude::*, BIP32_HARDEN};
+use coins_core::enc::{AddressEncoder, EncodingError};
 use coins_ledger::{
     common::{APDUAnswer, APDUCommand},
     transports::{Ledger, LedgerAsync},
 };
 use futures::lock::Mutex;
+use std::marker::PhantomData;
 
 /// Info required to sign an input on the ledger, including the KeyDerivation and the prevout
 #[derive(Clone, Debug)]
@@ -19,6 +22,13 @@ pub struct SigningInfo {
     pub prevout: Utxo,
     /// A reference to a key derivation if this input should be signed
     pub deriv: Option<KeyDerivation>,
+    /// The transaction that created the prevout. Signing legacy (P2PKH and P2SH) inputs
+    /// requires this for EVERY input of the transaction, so that the device can verify the
+    /// amounts being spent.
+    pub prev_tx: Option<BitcoinTx>,
+    /// The sighash flag to sign with. Usually `Sighash::All`. The device may reject flags it
+    /// does not support.
+    pub sighash: Sighash,
 }
 
 /// A Signature and the index of the input if signs.
@@ -30,28 +40,83 @@ pub struct SigInfo {
     pub sig: Signature,
     /// The derivation of the key that signed it
     pub deriv: KeyDerivation,
+    /// The sighash flag the signature commits to
+    pub sighash: Sighash,
 }
 
-/// A Ledger BTC App.
-pub struct LedgerBTC {
+/// The address formats the device can derive and display.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AddressFormat {
+    /// Legacy P2PKH
+    Legacy = 0x00,
+    /// P2SH-wrapped P2WPKH
+    P2shWpkh = 0x01,
+    /// Native segwit P2WPKH
+    Bech32 = 0x02,
+}
+
+/// A Ledger app for a bitcoin-like network. Parameterized by a `LedgerNetwork`.
+pub struct LedgerApp<N: LedgerNetwork> {
     transport: Mutex<Ledger>,
+    network: PhantomData<fn(N) -> N>,
 }
 
+/// A Ledger BTC App.
+pub type LedgerBTC = LedgerApp<BitcoinMain>;
+
+/// A Ledger Bitcoin Test App.
+pub type LedgerBTCTest = LedgerApp<BitcoinTest>;
+
+/// A Ledger Litecoin App.
+pub type LedgerLTC = LedgerApp<LitecoinMain>;
+
 // Lifecycle
-impl LedgerBTC {
+impl<N: LedgerNetwork> LedgerApp<N> {
     /// Instantiate the application by acquiring a lock on the ledger device.
-    pub async fn init() -> Result<LedgerBTC, LedgerBTCError> {
-        Ok(LedgerBTC {
+    pub async fn init() -> Result<Self, LedgerBTCError> {
+        Ok(Self {
             transport: Mutex::new(Ledger::init().await?),
+            network: PhantomData,
         })
     }
 
+    /// Check that the app for this network is running on the device.
+    pub async fn check_app(&self) -> Result<(), LedgerBTCError> {
+        let transport = self.transport.lock().await;
+        transport.require_app(N::APP_NAMES).await?;
+        Ok(())
+    }
+
     /// Consume self and drop the ledger mutex
     pub fn close(self) {}
 }
 
+// Network
+impl<N: LedgerNetwork> LedgerApp<N> {
+    /// The BIP44-style account path for this network, `m/purpose'/coin_type'/account'`.
+    pub fn account_path(purpose: u32, account: u32) -> DerivationPath {
+        vec![
+            purpose + BIP32_HARDEN,
+            N::COIN_TYPE + BIP32_HARDEN,
+            account + BIP32_HARDEN,
+        ]
+        .into()
+    }
+
+    /// Encode an xpub with this network's version bytes.
+    pub fn encode_xpub<K: AsRef<XPub>>(xpub: &K) -> Result<String, LedgerBTCError> {
+        Ok(N::XKeyEncoder::xpub_to_base58(xpub)?)
+    }
+
+    /// Encode a script pubkey as an address on this network.
+    pub fn encode_address(script_pubkey: &ScriptPubkey) -> Result<Address, EncodingError> {
+        N::Encoder::encode_address(script_pubkey)
+    }
+}
+
 // XPubs
-impl LedgerBTC {
+impl<N: LedgerNetwork> LedgerApp<N> {
     /// Get information about the public key at a certain derivation
     async fn get_key_info(
         &self,
@@ -63,14 +128,8 @@ impl LedgerBTC {
             return Err(LedgerBTCError::DerivationTooLong);
         }
 
-        let data = derivation_path_to_apdu_data(deriv);
-        let command = APDUCommand {
-            ins: Commands::GetWalletPublicKey as u8,
-            p1: 0x00,
-            p2: 0x02, // always native segwit address
-            data,
-            response_len: None,
-        };
+        // always native segwit address
+        let command = get_wallet_public_key(deriv, AddressFormat::Bech32, false);
 
         let answer = transport.exchange(&command).await?;
         let data = answer
@@ -99,7 +158,7 @@ impl LedgerBTC {
                         parent: fingerprint_of(&parent.pubkey),
                         index: *deriv.last().unwrap(),
                         chain_code: child.chain_code,
-                        hint: Hint::SegWit,
+                        hint: hint_for(deriv),
                     },
                 ),
                 KeyDerivation {
@@ -117,7 +176,7 @@ impl LedgerBTC {
                         parent: KeyFingerprint([0u8; 4]),
                         index: 0,
                         chain_code: child.chain_code,
-                        hint: Hint::SegWit,
+                        hint: hint_for(deriv),
                     },
                 ),
                 KeyDerivation {
@@ -132,10 +191,41 @@ impl LedgerBTC {
     pub async fn get_master_xpub<'a>(&self) -> Result<DerivedXPub, LedgerBTCError> {
         self.get_xpub(&Default::default()).await
     }
+
+    /// Get the fingerprint of the master key, as used in PSBT key derivations and descriptor key
+    /// origins. The v1 app protocol has no dedicated command, so this is derived from the master
+    /// pubkey.
+    pub async fn get_master_fingerprint(&self) -> Result<KeyFingerprint, LedgerBTCError> {
+        let transport = self.transport.lock().await;
+        let master = self.get_key_info(&transport, &Default::default()).await?;
+        Ok(fingerprint_of(&master.pubkey))
+    }
+
+    /// Get the address at a certain derivation. If `display` is true, the device shows the
+    /// address, and this resolves only after the user confirms it on the device screen.
+    pub async fn get_address(
+        &self,
+        deriv: &DerivationPath,
+        format: AddressFormat,
+        display: bool,
+    ) -> Result<String, LedgerBTCError> {
+        if deriv.len() > 10 {
+            return Err(LedgerBTCError::DerivationTooLong);
+        }
+
+        let transport = self.transport.lock().await;
+        let command = get_wallet_public_key(deriv, format, display);
+        let answer = transport.exchange(&command).await?;
+        let data = answer
+            .data()
+            .ok_or(LedgerBTCError::UnexpectedNullResponse)?;
+
+        parse_address_response(data)
+    }
 }
 
 // Signing
-impl LedgerBTC {
+impl<N: LedgerNetwork> LedgerApp<N> {
     // Exchange packets to get a signature response from the device.
     async fn signature_exchange(
         &self,
@@ -145,13 +235,14 @@ impl LedgerBTC {
         utxo: &Utxo,
         txin: &BitcoinTxIn,
         deriv: &DerivationPath,
+        sighash: Sighash,
     ) -> Result<APDUAnswer, LedgerBTCError> {
         let mut packets = vec![modify_tx_start_packet(first_packet)];
         packets.extend(packetize_input_for_signing(utxo, txin));
         for packet in packets.iter() {
             transport.exchange(packet).await?;
         }
-        let last_packet = transaction_final_packet(locktime, deriv);
+        let last_packet = transaction_final_packet(locktime, deriv, sighash);
         Ok(transport.exchange(&last_packet).await?)
     }
 
@@ -164,10 +255,19 @@ impl LedgerBTC {
         utxo: &Utxo,
         txin: &BitcoinTxIn,
         deriv: &DerivationPath,
+        sighash: Sighash,
     ) -> Result<Signature, LedgerBTCError> {
         parse_sig(
             &self
-                .signature_exchange(transport, first_packet, locktime, utxo, txin, deriv)
+                .signature_exchange(
+                    transport,
+                    first_packet,
+                    locktime,
+                    utxo,
+                    txin,
+                    deriv,
+                    sighash,
+                )
                 .await?,
         )
     }
@@ -194,48 +294,160 @@ impl LedgerBTC {
 
         // Lock the transport and start making packets for exchange
         let transport = self.transport.lock().await;
-        let first_packet = packetize_version_and_vin_length(tx.version(), tx.inputs().len() as u64);
-        let mut packets = vec![first_packet.clone()];
-
-        // Packetize each inputs
-        packets.extend(
-            signing_info
-                .iter()
-                .map(|s| &s.prevout)
-                .zip(tx.inputs())
-                .flat_map(|(u, i)| packetize_input(u, i))
-                .collect::<Vec<_>>(),
-        );
-
-        // Packetize all outputs
-        packets.extend(packetize_vout(tx.outputs()));
-        // Exchange all packets
-        for packet in packets.iter() {
-            transport.exchange(packet).await?;
+        let mut sigs = vec![];
+
+        let sign_witness = signing_info
+            .iter()
+            .any(|s| s.deriv.is_some() && !is_legacy(&s.prevout));
+        if sign_witness {
+            let first_packet =
+                packetize_version_and_vin_length(tx.version(), tx.inputs().len() as u64);
+            let mut packets = vec![first_packet.clone()];
+
+            // Packetize each inputs
+            packets.extend(
+                signing_info
+                    .iter()
+                    .map(|s| &s.prevout)
+                    .zip(tx.inputs())
+                    .flat_map(|(u, i)| packetize_input(u, i))
+                    .collect::<Vec<_>>(),
+            );
+
+            // Packetize all outputs
+            packets.extend(packetize_vout(tx.outputs()));
+            // Exchange all packets
+            for packet in packets.iter() {
+                transport.exchange(packet).await?;
+            }
+
+            // For each witness input that we can sign, we call `get_sig`
+            for (i, info) in signing_info.iter().enumerate() {
+                if is_legacy(&info.prevout) {
+                    continue;
+                }
+                if let Some(deriv) = &info.deriv {
+                    let sig = self
+                        .get_sig(
+                            &transport,
+                            &first_packet,
+                            tx.locktime(),
+                            &info.prevout,
+                            &tx.inputs()[i],
+                            &deriv.path,
+                            info.sighash,
+                        )
+                        .await?;
+                    sigs.push(SigInfo {
+                        input_idx: info.input_idx,
+                        sig,
+                        deriv: deriv.clone(),
+                        sighash: info.sighash,
+                    });
+                }
+            }
         }
 
-        let mut sigs = vec![];
+        let sign_legacy = signing_info
+            .iter()
+            .any(|s| s.deriv.is_some() && is_legacy(&s.prevout));
+        if sign_legacy {
+            sigs.extend(self.get_legacy_sigs(&transport, tx, signing_info).await?);
+        }
+        Ok(sigs)
+    }
+
+    // Get a trusted input for each input of the tx.
+    async fn get_trusted_inputs(
+        &self,
+        transport: &Ledger,
+        tx: &WitnessTx,
+        signing_info: &[SigningInfo],
+    ) -> Result<Vec<Vec<u8>>, LedgerBTCError> {
+        let mut trusted_inputs = vec![];
+        for (i, info) in signing_info.iter().enumerate() {
+            let prev_tx = info
+                .prev_tx
+                .as_ref()
+                .ok_or(LedgerBTCError::MissingPrevTx(i))?;
+
+            let mut answer = None;
+            for packet in packetize_trusted_input(tx.inputs()[i].outpoint.idx, prev_tx).iter() {
+                answer = Some(transport.exchange(packet).await?);
+            }
+            let trusted_input = answer
+                .as_ref()
+                .and_then(|a| a.data())
+                .ok_or(LedgerBTCError::UnexpectedNullResponse)?;
+            trusted_inputs.push(trusted_input.to_vec());
+        }
+        Ok(trusted_inputs)
+    }
+
+    // Sign the legacy inputs. The legacy sighash commits to the whole tx, so the tx is hashed
+    // again for each input.
+    async fn get_legacy_sigs(
+        &self,
+        transport: &Ledger,
+        tx: &WitnessTx,
+        signing_info: &[SigningInfo],
+    ) -> Result<Vec<SigInfo>, LedgerBTCError> {
+        let trusted_inputs = self.get_trusted_inputs(transport, tx, signing_info).await?;
 
-        // For each input that we can sign, we call `get_sig`
+        let mut sigs = vec![];
         for (i, info) in signing_info.iter().enumerate() {
-            if let Some(deriv) = &info.deriv {
-                let sig = self
-                    .get_sig(
-                        &transport,
-                        &first_packet,
-                        tx.locktime(),
-                        &info.prevout,
-                        &tx.inputs()[i],
-                        &deriv.path,
-                    )
-                    .await?;
-                sigs.push(SigInfo {
-                    input_idx: info.input_idx,
-                    sig,
-                    deriv: deriv.clone(),
-                });
+            if !is_legacy(&info.prevout) {
+                continue;
+            }
+            let (deriv, script) = match (&info.deriv, info.prevout.signing_script()) {
+                (Some(deriv), Some(script)) => (deriv, script),
+                _ => continue,
+            };
+
+            let mut packets =
+                packetize_legacy_inputs(tx, &trusted_inputs, i, &script, sigs.is_empty());
+            packets.extend(packetize_vout(tx.outputs()));
+            for packet in packets.iter() {
+                transport.exchange(packet).await?;
             }
+
+            let last_packet = transaction_final_packet(tx.locktime(), &deriv.path, info.sighash);
+            let sig = parse_sig(&transport.exchange(&last_packet).await?)?;
+            sigs.push(SigInfo {
+                input_idx: info.input_idx,
+                sig,
+                deriv: deriv.clone(),
+                sighash: info.sighash,
+            });
         }
         Ok(sigs)
     }
 }
+
+// Messages
+impl<N: LedgerNetwork> LedgerApp<N> {
+    /// Sign a message with the key at `deriv`. The device shows the message
+    /// hash, and asks the user to approve it.
+    ///
+    /// Use `bitcoins::message::encode_signature` to encode the signature in
+    /// the BIP 137 format, for the address type to be proven.
+    pub async fn sign_message(
+        &self,
+        deriv: &DerivationPath,
+        message: &[u8],
+    ) -> Result<RecoverableSignature, LedgerBTCError> {
+        if deriv.len() > 10 {
+            return Err(LedgerBTCError::DerivationTooLong);
+        }
+        if message.len() > u16::MAX as usize {
+            return Err(LedgerBTCError::MessageTooLong);
+        }
+
+        let transport = self.transport.lock().await;
+        for packet in packetize_message(deriv, message).iter() {
+            transport.exchange(packet).await?;
+        }
+        let answer = transport.exchange(&sign_message_packet()).await?;
+        parse_message_sig(&answer)
+    }
+}
@@ -17,7 +17,11 @@ pub(crate) mod utils;
 /// Core BTC APP.
 pub mod app;
 
-pub use app::{LedgerBTC, SigningInfo};
+/// Network parameters for the supported device apps.
+pub mod network;
+
+pub use app::{AddressFormat, LedgerApp, LedgerBTC, LedgerBTCTest, LedgerLTC, SigningInfo};
+pub use network::LedgerNetwork;
 
 use thiserror::Error;
 
@@ -45,4 +49,16 @@ pub enum LedgerBTCError {
         "Received the wrong number of prevouts/key derivtions while signing. Need 1 per witness."
     )]
     SigningInfoLengthMismatch,
+
+    /// Signing legacy inputs requires the previous tx of every input
+    #[error("Signing legacy inputs requires the previous transaction of every input. Missing for input {0}.")]
+    MissingPrevTx(usize),
+
+    /// The device response could not be parsed
+    #[error("Received a malformed response from device.")]
+    MalformedResponse,
+
+    /// `sign_message` received a message longer than the device accepts
+    #[error("Message is too long. Messages may be at most 65535 bytes.")]
+    MessageTooLong,
 }
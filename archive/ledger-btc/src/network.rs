@@ -0,0 +1,83 @@
+use bitcoins::enc::{self, BitcoinEncoderMarker};
+use coins_bip32::enc::{self as xenc, XKeyEncoder};
+
+/// LedgerNetwork holds the parameters of a bitcoin-like network, and the device app that speaks
+/// it. The device protocol is the same across these apps, so the network only determines which
+/// app to expect, the BIP44 coin type, and how keys and addresses are encoded.
+pub trait LedgerNetwork {
+    /// The names of the device apps for this network. E.g. `["Bitcoin"]` for mainnet.
+    const APP_NAMES: &'static [&'static str];
+    /// The BIP44 coin type. 0 for Bitcoin mainnet.
+    const COIN_TYPE: u32;
+    /// The address encoder. E.g. `bitcoins::enc::MainnetEncoder`.
+    type Encoder: BitcoinEncoderMarker;
+    /// The xpub encoder. E.g. `coins_bip32::enc::MainnetEncoder`.
+    type XKeyEncoder: XKeyEncoder;
+}
+
+/// Bitcoin Mainnet, and the Bitcoin app.
+#[derive(Debug, Clone)]
+pub struct BitcoinMain;
+
+impl LedgerNetwork for BitcoinMain {
+    const APP_NAMES: &'static [&'static str] = &["Bitcoin"];
+    const COIN_TYPE: u32 = 0;
+    type Encoder = enc::MainnetEncoder;
+    type XKeyEncoder = xenc::MainnetEncoder;
+}
+
+/// Bitcoin Testnet, and the Bitcoin Test app.
+#[derive(Debug, Clone)]
+pub struct BitcoinTest;
+
+impl LedgerNetwork for BitcoinTest {
+    const APP_NAMES: &'static [&'static str] = &["Bitcoin Test"];
+    const COIN_TYPE: u32 = 1;
+    type Encoder = enc::TestnetEncoder;
+    type XKeyEncoder = xenc::TestnetEncoder;
+}
+
+/// Litecoin xkey version bytes. `Ltub`/`Mtub` for BIP32 and BIP49 keys. BIP84 keys use the
+/// `zpub` bytes, as Litecoin Core does.
+#[derive(Debug, Clone)]
+pub struct LtcXKeyParams;
+
+impl xenc::NetworkParams for LtcXKeyParams {
+    const PRIV_VERSION: u32 = 0x019d_9cfe;
+    const BIP49_PRIV_VERSION: u32 = 0x01b2_6792;
+    const BIP84_PRIV_VERSION: u32 = 0x04b2_430c;
+    const PUB_VERSION: u32 = 0x019d_a462;
+    const BIP49_PUB_VERSION: u32 = 0x01b2_6ef6;
+    const BIP84_PUB_VERSION: u32 = 0x04b2_4746;
+}
+
+/// Litecoin Mainnet, and the Litecoin app.
+#[derive(Debug, Clone)]
+pub struct LitecoinMain;
+
+impl LedgerNetwork for LitecoinMain {
+    const APP_NAMES: &'static [&'static str] = &["Litecoin"];
+    const COIN_TYPE: u32 = 2;
+    type Encoder = litecoins::LitecoinMainEncoder;
+    type XKeyEncoder = xenc::BitcoinEncoder<LtcXKeyParams>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LedgerBTC, LedgerLTC};
+    use coins_bip32::BIP32_HARDEN;
+
+    #[test]
+    fn it_parameterizes_paths_and_keys() {
+        let path = LedgerLTC::account_path(44, 0);
+        assert_eq!(
+            path.iter().copied().collect::<Vec<_>>(),
+            vec![44 + BIP32_HARDEN, 2 + BIP32_HARDEN, BIP32_HARDEN]
+        );
+
+        let xpub = xenc::MainnetEncoder::xpub_from_base58("xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8").unwrap();
+        assert!(LedgerBTC::encode_xpub(&xpub).unwrap().starts_with("xpub"));
+        assert!(LedgerLTC::encode_xpub(&xpub).unwrap().starts_with("Ltub"));
+    }
+}
@@ -1,20 +1,25 @@
 use bitcoins::{
-    prelude::ByteFormat,
-    types::{BitcoinTxIn, ScriptType, SpendScript, TxOut, Utxo},
+    prelude::{ByteFormat, Transaction},
+    types::{
+        witness_program, BitcoinTx, BitcoinTxIn, Script, ScriptPubkey, ScriptType, Sighash,
+        SpendScript, TxOut, Utxo, WitnessTx,
+    },
 };
-use coins_bip32::{path::DerivationPath, prelude::*};
+use coins_bip32::{ecdsa, path::DerivationPath, prelude::*, BIP32_HARDEN};
 use coins_core::ser;
 use coins_ledger::common::{APDUAnswer, APDUCommand, APDUData};
 
-use crate::LedgerBTCError;
+use crate::{app::AddressFormat, LedgerBTCError};
 
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub(crate) enum Commands {
     GetWalletPublicKey = 0x40,
+    GetTrustedInput = 0x42,
     UntrustedHashTxInputStart = 0x44,
     UntrustedHashSign = 0x48,
     UntrustedHashTxInputFinalizeFull = 0x4a,
+    SignMessage = 0x4e,
 }
 
 pub(crate) struct InternalKeyInfo {
@@ -36,6 +41,42 @@ pub(crate) fn parse_pubkey_response(deriv: &DerivationPath, data: &[u8]) -> Inte
     }
 }
 
+// The response is the pubkey, the address and the chain code. The pubkey and address are length
+// prefixed.
+pub(crate) fn parse_address_response(data: &[u8]) -> Result<String, LedgerBTCError> {
+    let pk_len = *data.first().ok_or(LedgerBTCError::MalformedResponse)? as usize;
+    let addr_len = *data
+        .get(1 + pk_len)
+        .ok_or(LedgerBTCError::MalformedResponse)? as usize;
+    let addr = data
+        .get(2 + pk_len..2 + pk_len + addr_len)
+        .ok_or(LedgerBTCError::MalformedResponse)?;
+    String::from_utf8(addr.to_vec()).map_err(|_| LedgerBTCError::MalformedResponse)
+}
+
+pub(crate) fn get_wallet_public_key(
+    deriv: &DerivationPath,
+    format: AddressFormat,
+    display: bool,
+) -> APDUCommand {
+    APDUCommand {
+        ins: Commands::GetWalletPublicKey as u8,
+        p1: if display { 0x01 } else { 0x00 },
+        p2: format as u8,
+        data: derivation_path_to_apdu_data(deriv),
+        response_len: None,
+    }
+}
+
+// Infer the xkey encoding hint from the BIP44-style purpose of the path. Defaults to SegWit.
+pub(crate) fn hint_for(deriv: &DerivationPath) -> Hint {
+    match deriv.iter().next() {
+        Some(&p) if p == 44 + BIP32_HARDEN => Hint::Legacy,
+        Some(&p) if p == 49 + BIP32_HARDEN => Hint::Compatibility,
+        _ => Hint::SegWit,
+    }
+}
+
 // Convert a derivation path to its apdu data format
 pub(crate) fn derivation_path_to_apdu_data(deriv: &DerivationPath) -> APDUData {
     let mut buf = vec![deriv.len() as u8];
@@ -75,6 +116,140 @@ pub(crate) fn untrusted_hash_sign(chunk: &[u8]) -> APDUCommand {
     }
 }
 
+pub(crate) fn get_trusted_input(chunk: &[u8], first: bool) -> APDUCommand {
+    APDUCommand {
+        ins: Commands::GetTrustedInput as u8,
+        p1: if first { 0x00 } else { 0x80 },
+        p2: 0x00,
+        data: APDUData::from(chunk),
+        response_len: None,
+    }
+}
+
+pub(crate) fn untrusted_hash_legacy_input_start(
+    chunk: &[u8],
+    first: bool,
+    new_tx: bool,
+) -> APDUCommand {
+    APDUCommand {
+        ins: Commands::UntrustedHashTxInputStart as u8,
+        p1: if first { 0x00 } else { 0x80 },
+        p2: if new_tx { 0x00 } else { 0x80 },
+        data: APDUData::from(chunk),
+        response_len: None,
+    }
+}
+
+// Split a script into blocks, appending the suffix (e.g. the sequence) to the last block. An
+// empty script produces a single block holding the suffix.
+pub(crate) fn script_blocks(script: &[u8], suffix: &[u8]) -> Vec<Vec<u8>> {
+    let mut blocks: Vec<Vec<u8>> = script.chunks(50).map(|c| c.to_vec()).collect();
+    match blocks.last_mut() {
+        Some(last) => last.extend(suffix),
+        None => blocks.push(suffix.to_vec()),
+    }
+    blocks
+}
+
+// Legacy and P2SH prevouts are signed with the legacy sighash, and require trusted inputs. P2SH
+// prevouts whose redeem script is a witness program are signed like native witness prevouts.
+pub(crate) fn is_legacy(utxo: &Utxo) -> bool {
+    match utxo.standard_type() {
+        ScriptType::Pkh(_) => true,
+        ScriptType::Sh(_) => match utxo.spend_script() {
+            SpendScript::Known(script) => witness_program(script.items()).is_none(),
+            _ => true,
+        },
+        _ => false,
+    }
+}
+
+// The script code of a witness prevout. For P2SH-wrapped P2WPKH this is the P2PKH script of the
+// key hash, rather than the redeem script.
+fn witness_script_code(utxo: &Utxo) -> Option<Script> {
+    let script = utxo.signing_script()?;
+    match witness_program(script.items()) {
+        Some((0, program)) if program.len() == 20 => {
+            let mut hash = [0u8; 20];
+            hash.copy_from_slice(program);
+            Some(Script::from(&ScriptPubkey::p2pkh_from_hash(&hash)))
+        }
+        _ => Some(script),
+    }
+}
+
+// Packetize a previous transaction, so that the device can compute the trusted input for its
+// output at `index`. The trusted input is the answer to the last packet.
+pub(crate) fn packetize_trusted_input(index: u32, prev_tx: &BitcoinTx) -> Vec<APDUCommand> {
+    let mut buf = index.to_be_bytes().to_vec();
+    buf.extend(&prev_tx.version().to_le_bytes());
+    ser::write_compact_int(&mut buf, prev_tx.inputs().len() as u64).unwrap();
+    let mut packets = vec![get_trusted_input(&buf, true)];
+
+    for input in prev_tx.inputs() {
+        let mut buf = vec![];
+        input.outpoint.write_to(&mut buf).unwrap();
+        ser::write_compact_int(&mut buf, input.script_sig.len() as u64).unwrap();
+        packets.push(get_trusted_input(&buf, false));
+        packets.extend(
+            script_blocks(input.script_sig.items(), &input.sequence.to_le_bytes())
+                .iter()
+                .map(|block| get_trusted_input(block, false)),
+        );
+    }
+
+    let mut buf = vec![];
+    ser::write_compact_int(&mut buf, prev_tx.outputs().len() as u64).unwrap();
+    packets.push(get_trusted_input(&buf, false));
+    for output in prev_tx.outputs() {
+        let mut buf = output.value.to_sat().to_le_bytes().to_vec();
+        ser::write_compact_int(&mut buf, output.script_pubkey.len() as u64).unwrap();
+        packets.push(get_trusted_input(&buf, false));
+        packets.extend(
+            script_blocks(output.script_pubkey.items(), &[])
+                .iter()
+                .map(|block| get_trusted_input(block, false)),
+        );
+    }
+
+    packets.push(get_trusted_input(&prev_tx.locktime().to_le_bytes(), false));
+    packets
+}
+
+// Packetize the inputs of a tx for the legacy sighash of the input at `signing_idx`. Every input
+// is identified by its trusted input. Only the signed input carries a script.
+pub(crate) fn packetize_legacy_inputs(
+    tx: &WitnessTx,
+    trusted_inputs: &[Vec<u8>],
+    signing_idx: usize,
+    signing_script: &Script,
+    new_tx: bool,
+) -> Vec<APDUCommand> {
+    let mut buf = vec![];
+    buf.extend(&tx.version().to_le_bytes());
+    ser::write_compact_int(&mut buf, tx.inputs().len() as u64).unwrap();
+    let mut packets = vec![untrusted_hash_legacy_input_start(&buf, true, new_tx)];
+
+    for (i, (txin, trusted)) in tx.inputs().iter().zip(trusted_inputs).enumerate() {
+        let script = if i == signing_idx {
+            signing_script.items()
+        } else {
+            &[]
+        };
+
+        let mut buf = vec![0x01, trusted.len() as u8];
+        buf.extend(trusted);
+        ser::write_compact_int(&mut buf, script.len() as u64).unwrap();
+        packets.push(untrusted_hash_legacy_input_start(&buf, false, new_tx));
+        packets.extend(
+            script_blocks(script, &txin.sequence.to_le_bytes())
+                .iter()
+                .map(|block| untrusted_hash_legacy_input_start(block, false, new_tx)),
+        );
+    }
+    packets
+}
+
 pub(crate) fn packetize_version_and_vin_length(version: u32, vin_len: u64) -> APDUCommand {
     let mut chunk = vec![];
     chunk.extend(&version.to_le_bytes());
@@ -85,7 +260,7 @@ pub(crate) fn packetize_version_and_vin_length(version: u32, vin_len: u64) -> AP
 pub(crate) fn packetize_input(utxo: &Utxo, txin: &BitcoinTxIn) -> Vec<APDUCommand> {
     let mut buf = vec![0x02];
     txin.outpoint.write_to(&mut buf).unwrap();
-    buf.extend(&utxo.value.to_le_bytes());
+    buf.extend(&utxo.value.to_sat().to_le_bytes());
     buf.push(0x00);
 
     let first = untrusted_hash_tx_input_start(&buf, false);
@@ -97,8 +272,8 @@ pub(crate) fn packetize_input(utxo: &Utxo, txin: &BitcoinTxIn) -> Vec<APDUComman
 pub(crate) fn packetize_input_for_signing(utxo: &Utxo, txin: &BitcoinTxIn) -> Vec<APDUCommand> {
     let mut buf = vec![0x02];
     txin.outpoint.write_to(&mut buf).unwrap();
-    buf.extend(&utxo.value.to_le_bytes());
-    buf.extend(utxo.signing_script().unwrap()); // should have been preflighted by `should_sign`
+    buf.extend(&utxo.value.to_sat().to_le_bytes());
+    buf.extend(witness_script_code(utxo).unwrap()); // should have been preflighted by `should_sign`
 
     buf.chunks(50)
         .map(|d| untrusted_hash_tx_input_start(d, false))
@@ -124,12 +299,16 @@ pub(crate) fn packetize_vout(outputs: &[TxOut]) -> Vec<APDUCommand> {
     packets
 }
 
-pub(crate) fn transaction_final_packet(lock_time: u32, path: &DerivationPath) -> APDUCommand {
+pub(crate) fn transaction_final_packet(
+    lock_time: u32,
+    path: &DerivationPath,
+    sighash: Sighash,
+) -> APDUCommand {
     let mut buf = vec![];
     buf.extend(derivation_path_to_apdu_data(path).data());
     buf.push(0x00); // deprecated
     buf.extend(&lock_time.to_le_bytes());
-    buf.push(0x01); // SIGHASH_ALL
+    buf.push(sighash.to_u8());
     untrusted_hash_sign(&buf)
 }
 
@@ -156,6 +335,59 @@ pub(crate) fn parse_sig(answer: &APDUAnswer) -> Result<Signature, LedgerBTCError
     Ok(Signature::from_der(&sig[..sig.len() - 1]).map_err(Bip32Error::from)?)
 }
 
+// Packetize a message for signing. The first packet carries the derivation
+// and the message length.
+pub(crate) fn packetize_message(path: &DerivationPath, message: &[u8]) -> Vec<APDUCommand> {
+    let mut first = derivation_path_to_apdu_data(path).data();
+    first.extend(&(message.len() as u16).to_be_bytes());
+    let first_len = std::cmp::min(message.len(), 50 - first.len());
+    first.extend(&message[..first_len]);
+
+    let mut packets = vec![APDUCommand {
+        ins: Commands::SignMessage as u8,
+        p1: 0x00,
+        p2: 0x01,
+        data: first.into(),
+        response_len: None,
+    }];
+    packets.extend(message[first_len..].chunks(50).map(|chunk| APDUCommand {
+        ins: Commands::SignMessage as u8,
+        p1: 0x80,
+        p2: 0x01,
+        data: APDUData::from(chunk),
+        response_len: None,
+    }));
+    packets
+}
+
+pub(crate) fn sign_message_packet() -> APDUCommand {
+    APDUCommand {
+        ins: Commands::SignMessage as u8,
+        p1: 0x80,
+        p2: 0x00,
+        data: APDUData::from(vec![0x00]), // no user validation code
+        response_len: None,
+    }
+}
+
+// The answer is a DER signature, with the recovery id in the low bit of the
+// first byte
+pub(crate) fn parse_message_sig(
+    answer: &APDUAnswer,
+) -> Result<RecoverableSignature, LedgerBTCError> {
+    let mut sig = answer
+        .data()
+        .ok_or(LedgerBTCError::UnexpectedNullResponse)?
+        .to_vec();
+    // the device sets the low bit of the DER sequence tag to the parity of y
+    let first = sig.first_mut().ok_or(LedgerBTCError::MalformedResponse)?;
+    let recovery_id = *first & 0x01;
+    *first &= 0xfe;
+    let sig = Signature::from_der(&sig).map_err(Bip32Error::from)?;
+    let recovery_id = ecdsa::recoverable::Id::new(recovery_id).map_err(Bip32Error::from)?;
+    Ok(RecoverableSignature::new(&sig, recovery_id).map_err(Bip32Error::from)?)
+}
+
 pub(crate) fn should_sign(xpub: &DerivedXPub, signing_info: &[crate::app::SigningInfo]) -> bool {
     signing_info
         .iter()
@@ -172,3 +404,203 @@ pub(crate) fn should_sign(xpub: &DerivedXPub, signing_info: &[crate::app::Signin
                 .is_possible_ancestor_of(s.deriv.as_ref().unwrap())
         })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoins::types::{Amount, BitcoinOutpoint, BitcoinTransaction, LegacyTx};
+    use coins_bip32::ecdsa::{signature::DigestSigner, SigningKey};
+    use coins_core::hashes::{Digest, Hash256};
+
+    // A tx with one input, with a 3-byte script sig, and one P2PKH output of 1000 sats.
+    const PREV_TX: &str = "010000000111111111111111111111111111111111111111111111111111111111111111110000000003515151ffffffff01e8030000000000001976a914222222222222222222222222222222222222222288ac00000000";
+    // A tx spending two outpoints, with one P2PKH output of 1000 sats.
+    const TX: &str = "010000000211111111111111111111111111111111111111111111111111111111111111110000000000ffffffff22222222222222222222222222222222222222222222222222222222222222220100000000feffffff01e8030000000000001976a914222222222222222222222222222222222222222288ac00000000";
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // Render a command as `ins p1 p2 data`, all in hex.
+    fn show(command: &APDUCommand) -> String {
+        let data: String = command
+            .data
+            .clone()
+            .data()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        format!(
+            "{:02x} {:02x} {:02x} {}",
+            command.ins, command.p1, command.p2, data
+        )
+    }
+
+    fn answer(data: &[u8]) -> APDUAnswer {
+        let mut answer = data.to_vec();
+        answer.extend_from_slice(&[0x90, 0x00]);
+        APDUAnswer::from_answer(answer).unwrap()
+    }
+
+    fn utxo(script_pubkey: &str, spend_script: Option<&str>) -> Utxo {
+        let mut utxo = Utxo::new(
+            BitcoinOutpoint::default(),
+            Amount::from_sat(1000),
+            ScriptPubkey::new(unhex(script_pubkey)),
+            SpendScript::Missing,
+        );
+        if let Some(script) = spend_script {
+            assert!(utxo.set_spend_script(Script::new(unhex(script))));
+        }
+        utxo
+    }
+
+    #[test]
+    fn it_classifies_legacy_prevouts() {
+        let wpkh = "00142222222222222222222222222222222222222222";
+        let p2pkh = "76a914222222222222222222222222222222222222222288ac";
+        // the P2SH of `wpkh` and of `p2pkh`
+        let sh_wpkh = "a914bd5be2e29c2eafe9b1d60da5aa8f2354a5e7568887";
+        let sh_p2pkh = "a91471e4257991a732cb49c89c2c0112f6e32949118487";
+
+        assert!(is_legacy(&utxo(p2pkh, None)));
+        assert!(!is_legacy(&utxo(wpkh, None)));
+        assert!(is_legacy(&utxo(sh_p2pkh, Some(p2pkh))));
+        assert!(!is_legacy(&utxo(sh_wpkh, Some(wpkh))));
+        // without the redeem script, a P2SH prevout can not be signed as a witness prevout
+        assert!(is_legacy(&utxo(sh_wpkh, None)));
+
+        // wrapped P2WPKH is signed with the P2PKH script code
+        assert_eq!(
+            witness_script_code(&utxo(sh_wpkh, Some(wpkh))),
+            Some(Script::new(unhex(p2pkh)))
+        );
+    }
+
+    #[test]
+    fn it_splits_scripts_into_blocks() {
+        assert_eq!(script_blocks(&[], &[0xff; 4]), vec![vec![0xff; 4]]);
+        assert_eq!(script_blocks(&[0x51; 3], &[]), vec![vec![0x51; 3]]);
+
+        let blocks = script_blocks(&[0x51; 60], &[0xff; 4]);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0], vec![0x51; 50]);
+        assert_eq!(blocks[1], [vec![0x51; 10], vec![0xff; 4]].concat());
+    }
+
+    #[test]
+    fn it_packetizes_trusted_inputs() {
+        let prev_tx = BitcoinTx::deserialize_hex(PREV_TX).unwrap();
+        let packets: Vec<_> = packetize_trusted_input(1, &prev_tx)
+            .iter()
+            .map(show)
+            .collect();
+        assert_eq!(
+            packets,
+            vec![
+                // output index, version and vin length
+                "42 00 00 000000010100000001",
+                // outpoint and script sig length
+                "42 80 00 11111111111111111111111111111111111111111111111111111111111111110000000003",
+                // script sig and sequence
+                "42 80 00 515151ffffffff",
+                // vout length
+                "42 80 00 01",
+                // value and script pubkey length
+                "42 80 00 e80300000000000019",
+                // script pubkey
+                "42 80 00 76a914222222222222222222222222222222222222222288ac",
+                // locktime
+                "42 80 00 00000000",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_packetizes_legacy_inputs() {
+        let tx = LegacyTx::deserialize_hex(TX).unwrap().into_witness();
+        let trusted = vec![vec![0xaa; 4], vec![0xbb; 4]];
+        let script = Script::new(vec![0x51; 3]);
+
+        let packets: Vec<_> = packetize_legacy_inputs(&tx, &trusted, 1, &script, true)
+            .iter()
+            .map(show)
+            .collect();
+        assert_eq!(
+            packets,
+            vec![
+                // version and vin length
+                "44 00 00 0100000002",
+                // trusted input, and an empty script for the input not being signed
+                "44 80 00 0104aaaaaaaa00",
+                "44 80 00 ffffffff",
+                // trusted input and script of the signed input
+                "44 80 00 0104bbbbbbbb03",
+                "44 80 00 515151feffffff",
+            ]
+        );
+
+        // later signatures continue the same tx
+        let packets = packetize_legacy_inputs(&tx, &trusted, 0, &script, false);
+        assert!(packets.iter().all(|p| p.p2 == 0x80));
+        assert_eq!(show(&packets[1]), "44 80 80 0104aaaaaaaa03");
+        assert_eq!(show(&packets[2]), "44 80 80 515151ffffffff");
+    }
+
+    #[test]
+    fn it_parses_address_responses() {
+        let address = b"1BoatSLRHtKNngkdXEeobR76b53LETtpyT";
+        let mut data = vec![65];
+        data.extend_from_slice(&[0x04; 65]);
+        data.push(address.len() as u8);
+        data.extend_from_slice(address);
+        data.extend_from_slice(&[0x33; 32]);
+        assert_eq!(
+            parse_address_response(&data).unwrap(),
+            "1BoatSLRHtKNngkdXEeobR76b53LETtpyT"
+        );
+
+        for truncated in [&[][..], &data[..66], &data[..80]].iter() {
+            match parse_address_response(truncated) {
+                Err(LedgerBTCError::MalformedResponse) => {}
+                _ => panic!("expected malformed response"),
+            }
+        }
+    }
+
+    #[test]
+    fn it_packetizes_messages() {
+        let path: DerivationPath = "m/44'/0'/0'".parse().unwrap();
+        let packets: Vec<_> = packetize_message(&path, &[0x61; 60])
+            .iter()
+            .map(show)
+            .collect();
+
+        // the first packet carries the path and the message length, and fills up to 50 bytes
+        let first = format!("4e 00 01 038000002c8000000080000000003c{}", "61".repeat(35));
+        let second = format!("4e 80 01 {}", "61".repeat(25));
+        assert_eq!(packets, vec![first, second]);
+        assert_eq!(show(&sign_message_packet()), "4e 80 00 00");
+    }
+
+    #[test]
+    fn it_parses_message_signatures() {
+        let key = SigningKey::from_bytes(&[0x11; 32]).unwrap();
+        let mut digest = Hash256::default();
+        digest.update(b"message");
+        let sig: RecoverableSignature = key.sign_digest(digest);
+
+        // the device returns the DER signature, with the parity in the tag
+        let mut der = ecdsa::Signature::from(sig).to_der().as_bytes().to_vec();
+        der[0] |= u8::from(sig.recovery_id());
+        assert_eq!(parse_message_sig(&answer(&der)).unwrap(), sig);
+
+        match parse_message_sig(&answer(&[])) {
+            Err(LedgerBTCError::MalformedResponse) => {}
+            _ => panic!("expected malformed response"),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! A provider for the Trezor Blockbook REST API.
+//!
+//! Blockbook indexes addresses and xpubs, so in addition to the `BtcProvider` methods it offers
+//! balance and UTXO queries for a whole xpub, which the Esplora API lacks. See
+//! `BlockbookProvider::xpub_balance` and `BlockbookProvider::get_utxos_by_xpub`.
+
+/// Blockbook API response types
+pub mod types;
+
+use async_trait::async_trait;
+use futures_util::future::try_join_all;
+use std::time::Duration;
+
+use bitcoins::prelude::*;
+use coins_core::hashes::MarkedDigestOutput;
+
+use crate::{
+    provider::{BtcProvider, PollingBtcProvider, ProviderError},
+    reqwest_utils::post_str,
+    types::RawHeader,
+};
+use types::*;
+
+#[cfg(feature = "mainnet")]
+static TREZOR: &str = "https://btc1.trezor.io";
+
+#[cfg(feature = "testnet")]
+static TREZOR: &str = "https://tbtc1.trezor.io";
+
+/// A Provider that uses the Blockbook API
+#[derive(Debug)]
+pub struct BlockbookProvider {
+    interval: Duration,
+    api_root: String,
+    client: reqwest::Client,
+}
+
+impl Default for BlockbookProvider {
+    fn default() -> Self {
+        Self::with_api_root(TREZOR)
+    }
+}
+
+impl BlockbookProvider {
+    /// Instantiate the API pointing at a specific URL, e.g. a self-hosted Blockbook instance
+    pub fn with_api_root(api_root: &str) -> Self {
+        Self {
+            interval: crate::DEFAULT_POLL_INTERVAL,
+            api_root: api_root.trim_end_matches('/').to_owned(),
+            client: Default::default(),
+        }
+    }
+
+    /// Fetch the details of a transaction. Returns `None` if the transaction is unknown
+    pub async fn get_tx_details(&self, txid: TXID) -> Result<Option<BlockbookTx>, ProviderError> {
+        Ok(Some(blockbook_if_found!(
+            BlockbookTx::fetch_by_txid(&self.client, &self.api_root, txid).await
+        )))
+    }
+
+    /// Fetch the balance of an address
+    pub async fn address_balance(
+        &self,
+        address: &Address,
+    ) -> Result<BlockbookBalance, ProviderError> {
+        Ok(BlockbookBalance::fetch(
+            &self.client,
+            &self.api_root,
+            "address",
+            &address.as_string(),
+        )
+        .await?)
+    }
+
+    /// Fetch the combined balance of all addresses derived from an xpub. Blockbook also accepts
+    /// output descriptors in place of the xpub
+    pub async fn xpub_balance(&self, xpub: &str) -> Result<BlockbookBalance, ProviderError> {
+        Ok(BlockbookBalance::fetch(&self.client, &self.api_root, "xpub", xpub).await?)
+    }
+
+    /// Fetch the UTXOs of all addresses derived from an xpub. Blockbook also accepts output
+    /// descriptors in place of the xpub
+    pub async fn get_utxos_by_xpub(&self, xpub: &str) -> Result<Vec<Utxo>, ProviderError> {
+        BlockbookUtxo::fetch(&self.client, &self.api_root, xpub)
+            .await?
+            .into_iter()
+            .map(|utxo| utxo.into_utxo(None))
+            .collect()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl BtcProvider for BlockbookProvider {
+    async fn tip_hash(&self) -> Result<BlockHash, ProviderError> {
+        let status = BlockbookStatus::fetch(&self.client, &self.api_root).await?;
+        Ok(BlockHash::from_be_hex(&status.backend.best_block_hash)?)
+    }
+
+    async fn tip_height(&self) -> Result<usize, ProviderError> {
+        let status = BlockbookStatus::fetch(&self.client, &self.api_root).await?;
+        Ok(status.backend.blocks)
+    }
+
+    async fn in_best_chain(&self, digest: BlockHash) -> Result<bool, ProviderError> {
+        let block = BlockbookBlock::fetch_by_digest(&self.client, &self.api_root, digest).await?;
+        Ok(block.confirmations > 0)
+    }
+
+    async fn get_digest_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<BlockHash>, ProviderError> {
+        let tip = self.tip_height().await?;
+        let end = std::cmp::min(start + headers, tip + 1);
+        let indices = try_join_all(
+            (start..end)
+                .map(|height| BlockIndex::fetch_by_height(&self.client, &self.api_root, height)),
+        )
+        .await?;
+        indices
+            .iter()
+            .map(|index| Ok(BlockHash::from_be_hex(&index.block_hash)?))
+            .collect()
+    }
+
+    async fn get_raw_header_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<RawHeader>, ProviderError> {
+        let digests = self.get_digest_range(start, headers).await?;
+        let blocks =
+            try_join_all(digests.into_iter().map(|digest| {
+                BlockbookBlock::fetch_by_digest(&self.client, &self.api_root, digest)
+            }))
+            .await?;
+        blocks.iter().map(BlockbookBlock::serialize).collect()
+    }
+
+    async fn get_raw_header(&self, digest: BlockHash) -> Result<Option<RawHeader>, ProviderError> {
+        let block = blockbook_if_found!(
+            BlockbookBlock::fetch_by_digest(&self.client, &self.api_root, digest).await
+        );
+        Ok(Some(block.serialize()?))
+    }
+
+    async fn get_height_of(&self, digest: BlockHash) -> Result<Option<usize>, ProviderError> {
+        let block = blockbook_if_found!(
+            BlockbookBlock::fetch_by_digest(&self.client, &self.api_root, digest).await
+        );
+        Ok(Some(block.height))
+    }
+
+    async fn get_confirmed_height(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        Ok(self
+            .get_tx_details(txid)
+            .await?
+            .and_then(|tx| tx.confirmed_height()))
+    }
+
+    async fn get_confs(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        Ok(self.get_tx_details(txid).await?.map(|tx| tx.confirmations))
+    }
+
+    async fn get_tx(&self, txid: TXID) -> Result<Option<BitcoinTx>, ProviderError> {
+        match self.get_tx_details(txid).await? {
+            Some(details) => Ok(Some(details.tx()?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError> {
+        let url = format!("{}/api/v2/sendtx/", self.api_root);
+        let body = post_str(&self.client, &url, &tx.serialize_hex()).await?;
+        let response: SendTxResponse = serde_json::from_str(&body)?;
+        match (response.result, response.error) {
+            (Some(txid), _) => Ok(TXID::from_be_hex(&txid)?),
+            (None, Some(error)) => Err(ProviderError::Rejected(match error {
+                serde_json::Value::String(message) => message,
+                other => other.to_string(),
+            })),
+            (None, None) => Err(ProviderError::Rejected(body)),
+        }
+    }
+
+    async fn get_outspend(&self, outpoint: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError> {
+        let tx = match self.get_tx_details(outpoint.txid).await? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+        let vout = match tx.vout.iter().find(|vout| vout.n == outpoint.idx) {
+            Some(vout) => vout,
+            None => return Ok(None),
+        };
+        match (vout.spent, &vout.spent_tx_id) {
+            (false, _) => Ok(None),
+            (true, Some(txid)) => Ok(Some(TXID::from_be_hex(txid)?)),
+            (true, None) => Err(ProviderError::Unsupported(
+                "Blockbook instance does not report spending txids".to_owned(),
+            )),
+        }
+    }
+
+    async fn get_utxos_by_address(&self, address: &Address) -> Result<Vec<Utxo>, ProviderError> {
+        BlockbookUtxo::fetch(&self.client, &self.api_root, &address.as_string())
+            .await?
+            .into_iter()
+            .map(|utxo| utxo.into_utxo(Some(address)))
+            .collect()
+    }
+
+    async fn get_merkle(
+        &self,
+        _txid: TXID,
+    ) -> Result<Option<(usize, Vec<Hash256Digest>)>, ProviderError> {
+        Err(ProviderError::Unsupported(
+            "Blockbook does not serve merkle proofs".to_owned(),
+        ))
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl PollingBtcProvider for BlockbookProvider {
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn set_interval(&mut self, interval: usize) {
+        self.interval = Duration::from_secs(interval as u64);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_reassembles_headers() {
+        // block 1, as served by Blockbook
+        let block: BlockbookBlock = serde_json::from_str(
+            r#"{
+                "page": 1, "totalPages": 1, "itemsOnPage": 1,
+                "hash": "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048",
+                "previousBlockHash": "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+                "nextBlockHash": "000000006a625f06636b8bb6ac7b960a8d03705d1ace08b1a19da3fdcc99ddbd",
+                "height": 1, "confirmations": 800000, "size": 215, "time": 1231469665,
+                "version": 1,
+                "merkleRoot": "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098",
+                "nonce": "2573394689", "bits": "1d00ffff", "difficulty": "1", "txCount": 1
+            }"#,
+        )
+        .unwrap();
+        let header = block.serialize().unwrap();
+        assert_eq!(
+            header.digest(),
+            BlockHash::from_be_hex(&block.hash).unwrap()
+        );
+        let mut tampered = block.clone();
+        tampered.nonce = "0".to_owned();
+        assert!(tampered.serialize().is_err());
+
+        let balance: BlockbookBalance = serde_json::from_str(
+            r#"{"address": "xpub...", "balance": "1500", "totalReceived": "2000",
+                "totalSent": "500", "unconfirmedBalance": "-100", "unconfirmedTxs": 1,
+                "txs": 3, "usedTokens": 2}"#,
+        )
+        .unwrap();
+        assert_eq!(balance.balance, 1500);
+        assert_eq!(balance.unconfirmed_balance, -100);
+        assert_eq!(balance.used_tokens, Some(2));
+    }
+}
@@ -0,0 +1,267 @@
+use std::convert::TryInto;
+
+use bitcoins::prelude::*;
+use coins_core::hashes::MarkedDigestOutput;
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    provider::ProviderError,
+    reqwest_utils::{self, FetchError},
+    types::RawHeader,
+};
+
+// Blockbook serializes amounts as decimal strings of satoshis
+fn amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+fn parse_error(message: &str) -> ProviderError {
+    ProviderError::custom(true, message.to_owned().into())
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BackendStatus {
+    pub blocks: usize,
+    pub best_block_hash: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct BlockbookStatus {
+    pub backend: BackendStatus,
+}
+
+impl BlockbookStatus {
+    pub(crate) async fn fetch(
+        client: &reqwest::Client,
+        api_root: &str,
+    ) -> Result<Self, FetchError> {
+        let url = format!("{}/api/v2", api_root);
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockIndex {
+    pub block_hash: String,
+}
+
+impl BlockIndex {
+    pub(crate) async fn fetch_by_height(
+        client: &reqwest::Client,
+        api_root: &str,
+        height: usize,
+    ) -> Result<Self, FetchError> {
+        let url = format!("{}/api/v2/block-index/{}", api_root, height);
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+}
+
+/// A block, without its transactions
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockbookBlock {
+    pub hash: String,
+    #[serde(default)]
+    pub previous_block_hash: Option<String>,
+    pub height: usize,
+    pub confirmations: i64,
+    pub version: u32,
+    pub merkle_root: String,
+    pub time: u32,
+    pub nonce: String,
+    pub bits: String,
+}
+
+impl BlockbookBlock {
+    pub(crate) async fn fetch_by_digest(
+        client: &reqwest::Client,
+        api_root: &str,
+        digest: BlockHash,
+    ) -> Result<Self, FetchError> {
+        // the first page is the smallest, as the header fields are included in every page
+        let url = format!(
+            "{}/api/v2/block/{}?pageSize=1",
+            api_root,
+            digest.to_be_hex()
+        );
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+
+    /// Reassemble the raw header from its fields, and check it against the block hash
+    pub(crate) fn serialize(&self) -> Result<RawHeader, ProviderError> {
+        let parent = match &self.previous_block_hash {
+            Some(hash) => BlockHash::from_be_hex(hash)?,
+            None => BlockHash::default(),
+        };
+        let merkle_root = Hash256Digest::from_be_hex(&self.merkle_root)?;
+        let bits =
+            u32::from_str_radix(&self.bits, 16).map_err(|_| parse_error("Malformed bits"))?;
+        let nonce: u32 = self
+            .nonce
+            .parse()
+            .map_err(|_| parse_error("Malformed nonce"))?;
+
+        let mut buf = Vec::with_capacity(80);
+        buf.extend_from_slice(&self.version.to_le_bytes());
+        buf.extend_from_slice(parent.as_slice());
+        buf.extend_from_slice(merkle_root.as_slice());
+        buf.extend_from_slice(&self.time.to_le_bytes());
+        buf.extend_from_slice(&bits.to_le_bytes());
+        buf.extend_from_slice(&nonce.to_le_bytes());
+        let buf: [u8; 80] = buf.as_slice().try_into().expect("header is 80 bytes");
+        let header: RawHeader = buf.into();
+        if header.digest() != BlockHash::from_be_hex(&self.hash)? {
+            return Err(parse_error("Reassembled header does not match block hash"));
+        }
+        Ok(header)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockbookVout {
+    pub n: u32,
+    #[serde(default)]
+    pub spent: bool,
+    #[serde(default)]
+    pub spent_tx_id: Option<String>,
+}
+
+/// Transaction details, as reported by Blockbook
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockbookTx {
+    /// The BE txid
+    pub txid: String,
+    /// The BE hash of the confirming block. Absent for unconfirmed transactions
+    #[serde(default)]
+    pub block_hash: Option<String>,
+    /// The height of the confirming block. -1 for unconfirmed transactions
+    pub block_height: i64,
+    /// The number of confirmations
+    pub confirmations: usize,
+    /// The total output value, in satoshis
+    #[serde(deserialize_with = "amount")]
+    pub value: i64,
+    /// The fee, in satoshis
+    #[serde(deserialize_with = "amount")]
+    pub fees: i64,
+    /// The serialized transaction, in hex
+    pub hex: String,
+    #[serde(default)]
+    pub(crate) vout: Vec<BlockbookVout>,
+}
+
+impl BlockbookTx {
+    pub(crate) async fn fetch_by_txid(
+        client: &reqwest::Client,
+        api_root: &str,
+        txid: TXID,
+    ) -> Result<Self, FetchError> {
+        let url = format!("{}/api/v2/tx/{}", api_root, txid.to_be_hex());
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+
+    /// The confirmed height, if any
+    pub fn confirmed_height(&self) -> Option<usize> {
+        if self.block_height < 0 {
+            None
+        } else {
+            Some(self.block_height as usize)
+        }
+    }
+
+    /// Deserialize the transaction
+    pub fn tx(&self) -> Result<BitcoinTx, ProviderError> {
+        BitcoinTx::deserialize_hex(&self.hex).map_err(|_| parse_error("Invalid transaction hex"))
+    }
+}
+
+/// The balance of an address or xpub, in satoshis
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockbookBalance {
+    /// The confirmed balance
+    #[serde(deserialize_with = "amount")]
+    pub balance: i64,
+    /// The total received
+    #[serde(deserialize_with = "amount")]
+    pub total_received: i64,
+    /// The total sent
+    #[serde(deserialize_with = "amount")]
+    pub total_sent: i64,
+    /// The net change in balance from unconfirmed transactions. May be negative
+    #[serde(deserialize_with = "amount")]
+    pub unconfirmed_balance: i64,
+    /// The number of unconfirmed transactions
+    pub unconfirmed_txs: usize,
+    /// The number of confirmed transactions
+    pub txs: usize,
+    /// For xpubs, the number of derived addresses that have been used
+    #[serde(default)]
+    pub used_tokens: Option<usize>,
+}
+
+impl BlockbookBalance {
+    pub(crate) async fn fetch(
+        client: &reqwest::Client,
+        api_root: &str,
+        kind: &str,
+        descriptor: &str,
+    ) -> Result<Self, FetchError> {
+        let url = format!("{}/api/v2/{}/{}?details=basic", api_root, kind, descriptor);
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct BlockbookUtxo {
+    /// The BE txid
+    pub txid: String,
+    pub vout: u32,
+    #[serde(deserialize_with = "amount")]
+    pub value: i64,
+    /// The address holding the UTXO. Included in xpub queries
+    #[serde(default)]
+    pub address: Option<String>,
+}
+
+impl BlockbookUtxo {
+    /// Fetch the UTXOs of an address or xpub
+    pub(crate) async fn fetch(
+        client: &reqwest::Client,
+        api_root: &str,
+        descriptor: &str,
+    ) -> Result<Vec<Self>, FetchError> {
+        let url = format!("{}/api/v2/utxo/{}", api_root, descriptor);
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+
+    /// Convert to a `Utxo`. `address` is required if the UTXO does not name its address
+    pub(crate) fn into_utxo(self, address: Option<&Address>) -> Result<Utxo, ProviderError> {
+        let address = match (self.address, address) {
+            (Some(a), _) => bitcoins::Net::string_to_address(&a)?,
+            (None, Some(a)) => a.clone(),
+            (None, None) => return Err(parse_error("UTXO has no address")),
+        };
+        let script_pubkey = bitcoins::Net::decode_address(&address);
+        let spend_script = SpendScript::from_script_pubkey(&script_pubkey);
+        Ok(Utxo::new(
+            BitcoinOutpoint::new(TXID::from_be_hex(&self.txid)?, self.vout),
+            Amount::from_sat(self.value as u64),
+            script_pubkey,
+            spend_script,
+        ))
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct SendTxResponse {
+    #[serde(default)]
+    pub result: Option<String>,
+    #[serde(default)]
+    pub error: Option<serde_json::Value>,
+}
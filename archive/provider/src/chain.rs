@@ -1,4 +1,5 @@
 use std::{
+    collections::VecDeque,
     pin::Pin,
     task::{Context, Poll},
     time::Duration,
@@ -10,7 +11,21 @@ use pin_project::pin_project;
 
 use bitcoins::prelude::*;
 
-use crate::{provider::BtcProvider, utils::new_interval, ProviderFut, DEFAULT_POLL_INTERVAL};
+use crate::{
+    provider::{BtcProvider, ProviderError},
+    spv::{Checkpoint, SpvError},
+    store::ChainStore,
+    types::RawHeader,
+    utils::new_interval,
+    ProviderFut, DEFAULT_POLL_INTERVAL,
+};
+
+/// The default number of recent blocks tracked by `ChainEvents`. Reorgs deeper than this are
+/// reported as this deep.
+pub const DEFAULT_REORG_DEPTH: usize = 100;
+
+/// The number of headers requested at a time by `sync_headers`
+pub const DEFAULT_SYNC_BATCH: usize = 500;
 
 /// Polls the API for the chain tip. Updates every time the tip changes
 #[pin_project(project = TipsProj)]
@@ -41,6 +56,16 @@ impl<'a> Tips<'a> {
         self.interval = Box::new(new_interval(duration.into()));
         self
     }
+
+    /// Poll after each tick of a stream instead of at a fixed interval, e.g. to poll on push
+    /// updates from `esplora::ws::PushTicks`
+    pub fn ticks<S>(mut self, ticks: S) -> Self
+    where
+        S: Stream<Item = ()> + Send + Unpin + 'static,
+    {
+        self.interval = Box::new(ticks);
+        self
+    }
 }
 
 impl<'a> futures_core::Stream for Tips<'a> {
@@ -85,3 +110,410 @@ impl<'a> futures_core::Stream for Tips<'a> {
         Poll::Pending
     }
 }
+
+/// An event on the chain, emitted by `ChainEvents`
+#[derive(Clone, Debug)]
+pub enum ChainEvent {
+    /// A block was connected to the best chain
+    Block {
+        /// The block height
+        height: usize,
+        /// The block hash
+        hash: BlockHash,
+        /// The block header
+        header: RawHeader,
+    },
+    /// The best chain was reorganized. `depth` blocks, ending at `old_tip`, are no longer in the
+    /// best chain. The `Block` events of the new branch, ending at `new_tip`, follow this event.
+    Reorg {
+        /// The number of blocks disconnected
+        depth: usize,
+        /// The tip before the reorg
+        old_tip: BlockHash,
+        /// The tip after the reorg
+        new_tip: BlockHash,
+    },
+}
+
+// A recently seen block, by height and hash
+type BranchEntry = (usize, BlockHash);
+
+// The result of one poll of the chain: the new events, and the updated branch
+type Advance = (Vec<ChainEvent>, Vec<BranchEntry>);
+
+// Compare the tracked branch to the best chain. Disconnect blocks that left the best chain,
+// and connect the blocks of the new branch, up to the current tip
+async fn advance(
+    provider: &dyn BtcProvider,
+    mut branch: Vec<BranchEntry>,
+    max_depth: usize,
+) -> Result<Advance, ProviderError> {
+    let new_tip = provider.tip_hash().await?;
+    let old_tip = match branch.last() {
+        Some(&(_, hash)) if hash == new_tip => return Ok((vec![], branch)),
+        Some(&(_, hash)) => Some(hash),
+        None => None,
+    };
+    let tip_height = match provider.get_height_of(new_tip).await? {
+        Some(height) => height,
+        // the tip changed under us. Try again at the next poll
+        None => return Ok((vec![], branch)),
+    };
+
+    let mut events = vec![];
+    // On the first poll, only the tip is emitted
+    let mut start = tip_height;
+    if let Some(old_tip) = old_tip {
+        let mut depth = 0;
+        while let Some(&(height, hash)) = branch.last() {
+            start = height + 1;
+            if provider.in_best_chain(hash).await? {
+                break;
+            }
+            branch.pop();
+            start = height;
+            depth += 1;
+        }
+        if depth > 0 {
+            events.push(ChainEvent::Reorg {
+                depth,
+                old_tip,
+                new_tip,
+            });
+        }
+    }
+
+    let count = (tip_height + 1).saturating_sub(start);
+    let hashes = provider.get_digest_range(start, count).await?;
+    let headers = provider.get_raw_header_range(start, count).await?;
+    for (height, (hash, header)) in (start..).zip(hashes.into_iter().zip(headers)) {
+        branch.push((height, hash));
+        events.push(ChainEvent::Block {
+            height,
+            hash,
+            header,
+        });
+    }
+
+    if branch.len() > max_depth {
+        branch.drain(..branch.len() - max_depth);
+    }
+    Ok((events, branch))
+}
+
+// Write the headers of connected blocks to a store. Each block becomes the highest stored
+// header, so headers of blocks disconnected by a reorg are removed
+fn persist(store: &mut dyn ChainStore, events: &[ChainEvent]) -> Result<(), ProviderError> {
+    for event in events.iter() {
+        if let ChainEvent::Block { height, header, .. } = event {
+            store.truncate(height.saturating_sub(1))?;
+            store.put_header(*height, *header)?;
+        }
+    }
+    Ok(())
+}
+
+/// Polls the API for the chain tip, and emits a `ChainEvent` for each block connected to the
+/// best chain, and for each reorg. After a reorg, the blocks of the new branch are replayed, so
+/// that consumers can roll back and recount confirmations.
+///
+/// The first event is a `Block` event for the tip at the time of the first poll.
+#[pin_project(project = ChainEventsProj)]
+#[must_use = "streams do nothing unless polled"]
+pub struct ChainEvents<'a> {
+    max_depth: usize,
+    interval: Box<dyn Stream<Item = ()> + Send + Unpin>,
+    provider: &'a dyn BtcProvider,
+    fut_opt: Option<ProviderFut<'a, Advance>>,
+    branch: Vec<BranchEntry>,
+    queue: VecDeque<ChainEvent>,
+    store: Option<Box<dyn ChainStore + 'a>>,
+}
+
+impl<'a> ChainEvents<'a> {
+    /// Instantiate a new ChainEvents.
+    pub fn new(provider: &'a dyn BtcProvider) -> Self {
+        let fut = Box::pin(advance(provider, vec![], DEFAULT_REORG_DEPTH));
+        Self {
+            max_depth: DEFAULT_REORG_DEPTH,
+            interval: Box::new(new_interval(DEFAULT_POLL_INTERVAL)),
+            provider,
+            fut_opt: Some(fut),
+            branch: vec![],
+            queue: VecDeque::new(),
+            store: None,
+        }
+    }
+
+    /// Sets the number of recent blocks to track. Reorgs deeper than this are reported as this
+    /// deep, and the new branch is replayed from the oldest tracked height.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = std::cmp::max(max_depth, 1);
+        self
+    }
+
+    /// Sets the polling interval
+    pub fn interval<T: Into<Duration>>(mut self, duration: T) -> Self {
+        self.interval = Box::new(new_interval(duration.into()));
+        self
+    }
+
+    /// Poll after each tick of a stream instead of at a fixed interval, e.g. to poll on push
+    /// updates from `esplora::ws::PushTicks`
+    pub fn ticks<S>(mut self, ticks: S) -> Self
+    where
+        S: Stream<Item = ()> + Send + Unpin + 'static,
+    {
+        self.interval = Box::new(ticks);
+        self
+    }
+
+    /// Write the headers of connected blocks to a `ChainStore`, and resume from the headers
+    /// stored by a previous run. The first poll then emits a `Block` event for each block
+    /// connected since the highest stored header, preceded by a `Reorg` event if that header
+    /// left the best chain. Store errors are ignored.
+    pub fn store<S: ChainStore + 'a>(mut self, store: S) -> Self {
+        self.branch = match store.header_height() {
+            Ok(Some(height)) => (height.saturating_sub(self.max_depth - 1)..=height)
+                .filter_map(|h| match store.get_header(h) {
+                    Ok(Some(header)) => Some((h, header.digest())),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        self.fut_opt = Some(Box::pin(advance(
+            self.provider,
+            self.branch.clone(),
+            self.max_depth,
+        )));
+        self.store = Some(Box::new(store));
+        self
+    }
+}
+
+impl<'a> futures_core::Stream for ChainEvents<'a> {
+    type Item = ChainEvent;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        let ChainEventsProj {
+            max_depth,
+            interval,
+            provider,
+            fut_opt,
+            branch,
+            queue,
+            store,
+        } = self.project();
+
+        if let Some(event) = queue.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+
+        if let Some(fut) = fut_opt {
+            let result = futures_util::ready!(fut.as_mut().poll(ctx));
+            *fut_opt = None;
+
+            // Errors will fail through to being retried at the interval
+            if let Ok((events, new_branch)) = result {
+                if let Some(store) = store {
+                    let _ = persist(store.as_mut(), &events);
+                }
+                *branch = new_branch;
+                queue.extend(events);
+                if let Some(event) = queue.pop_front() {
+                    return Poll::Ready(Some(event));
+                }
+            }
+        }
+
+        // if the interval has elapsed, reset the fut
+        let fut = unpause!(
+            ctx,
+            interval,
+            advance(*provider, branch.clone(), *max_depth)
+        );
+        *fut_opt = Some(fut);
+        Poll::Pending
+    }
+}
+
+/// The outcome of a `sync_headers` call
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HeaderSync {
+    /// The height of the highest stored header
+    pub height: usize,
+    /// The hash of the highest stored header
+    pub hash: BlockHash,
+    /// The number of headers written to the store, including any later removed by a reorg
+    pub added: usize,
+    /// The number of stored headers removed because they left the best chain
+    pub disconnected: usize,
+}
+
+// The hash of the local chain at a height. The checkpoint's header need not be stored
+fn local_hash<S: ChainStore + ?Sized>(
+    store: &S,
+    checkpoint: &Checkpoint,
+    height: usize,
+) -> Result<Option<BlockHash>, ProviderError> {
+    if height == checkpoint.height {
+        return Ok(Some(checkpoint.hash));
+    }
+    Ok(store.get_header(height)?.map(|header| header.digest()))
+}
+
+// Walk back from `height` to the highest stored header that is in the provider's best chain,
+// and remove the headers above it. Returns the fork height
+async fn rewind<S: ChainStore + ?Sized>(
+    provider: &dyn BtcProvider,
+    store: &mut S,
+    checkpoint: &Checkpoint,
+    mut height: usize,
+) -> Result<usize, ProviderError> {
+    loop {
+        let local = local_hash(store, checkpoint, height)?;
+        let remote = provider.get_digest_range(height, 1).await?.pop();
+        if local.is_some() && local == remote {
+            break;
+        }
+        if height == checkpoint.height {
+            return Err(SpvError::CheckpointMismatch(height).into());
+        }
+        height -= 1;
+    }
+    store.truncate(height)?;
+    Ok(height)
+}
+
+/// Download headers from the highest stored header, or from a checkpoint if the store has none
+/// above it, to the provider's current tip. Headers are fetched in batches of
+/// `DEFAULT_SYNC_BATCH`, checked to link to their parents, and written to the store after each
+/// batch, so an interrupted sync resumes where it stopped.
+///
+/// If the stored headers at the tail are no longer in the provider's best chain, they are
+/// removed, and syncing resumes from the fork point. Fails with `SpvError::CheckpointMismatch`
+/// if the checkpoint itself is not in the provider's best chain.
+///
+/// This checks linkage only. Use `spv::HeaderChain` to validate proof of work.
+pub async fn sync_headers<S: ChainStore + ?Sized>(
+    provider: &dyn BtcProvider,
+    store: &mut S,
+    from_checkpoint: Checkpoint,
+) -> Result<HeaderSync, ProviderError> {
+    let checkpoint = from_checkpoint;
+    let resume_height = match store.header_height()? {
+        Some(height) if height > checkpoint.height => height,
+        _ => checkpoint.height,
+    };
+    let tip = provider.tip_height().await?;
+    // The provider is behind us. Don't discard headers it doesn't know yet
+    if tip < resume_height {
+        let hash = local_hash(store, &checkpoint, resume_height)?
+            .ok_or_else(|| ProviderError::custom(true, "Missing header in chain store".into()))?;
+        return Ok(HeaderSync {
+            height: resume_height,
+            hash,
+            added: 0,
+            disconnected: 0,
+        });
+    }
+
+    let mut height = rewind(provider, store, &checkpoint, resume_height).await?;
+    let mut disconnected = resume_height - height;
+    let mut hash = local_hash(store, &checkpoint, height)?.expect("checked by rewind");
+    let mut added = 0;
+
+    while height < tip {
+        let count = std::cmp::min(DEFAULT_SYNC_BATCH, tip - height);
+        let headers = provider.get_raw_header_range(height + 1, count).await?;
+        if headers.is_empty() {
+            break;
+        }
+
+        // Keep the linked prefix of the batch. A broken link means the chain reorganized
+        // under us, so we rewind before continuing
+        let mut linked = 0;
+        for header in headers.iter() {
+            if header.parent() != hash {
+                break;
+            }
+            store.put_header(height + 1, *header)?;
+            hash = header.digest();
+            height += 1;
+            linked += 1;
+        }
+        added += linked;
+
+        if linked < headers.len() {
+            let fork = rewind(provider, store, &checkpoint, height).await?;
+            // The provider's headers don't link to its own digests
+            if linked == 0 && fork == height {
+                return Err(SpvError::WrongParent(height + 1).into());
+            }
+            disconnected += height - fork;
+            height = fork;
+            hash = local_hash(store, &checkpoint, height)?.expect("checked by rewind");
+        }
+    }
+
+    Ok(HeaderSync {
+        height,
+        hash,
+        added,
+        disconnected,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{store::MemoryChainStore, test_utils::MockProvider};
+
+    #[test]
+    fn it_syncs_headers_through_reorgs() {
+        let mut rt = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let provider = MockProvider::default();
+        provider.extend(0, 10, 0);
+        let checkpoint = Checkpoint {
+            height: 2,
+            hash: provider.hash_at(2),
+            ..Checkpoint::mainnet_genesis()
+        };
+        let mut store = MemoryChainStore::default();
+
+        let sync = rt
+            .block_on(sync_headers(&provider, &mut store, checkpoint))
+            .unwrap();
+        assert_eq!((sync.height, sync.added, sync.disconnected), (9, 7, 0));
+        assert_eq!(sync.hash, provider.hash_at(9));
+        assert_eq!(store.get_header(2).unwrap(), None);
+
+        // resumes from the highest stored header
+        provider.extend(10, 5, 0);
+        let sync = rt
+            .block_on(sync_headers(&provider, &mut store, checkpoint))
+            .unwrap();
+        assert_eq!((sync.height, sync.added, sync.disconnected), (14, 5, 0));
+
+        // replaces headers that left the best chain
+        provider.extend(12, 5, 1);
+        let sync = rt
+            .block_on(sync_headers(&provider, &mut store, checkpoint))
+            .unwrap();
+        assert_eq!((sync.height, sync.added, sync.disconnected), (16, 5, 3));
+        assert_eq!(
+            store.get_header(13).unwrap().map(|h| h.digest()),
+            Some(provider.hash_at(13))
+        );
+
+        // the checkpoint must be in the best chain
+        provider.extend(1, 20, 2);
+        assert!(rt
+            .block_on(sync_headers(&provider, &mut store, checkpoint))
+            .is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::stream::Stream;
+use pin_project::pin_project;
+
+use crate::{
+    esplora::{types::EsploraTx, EsploraProvider},
+    provider::ProviderError,
+    types::DetailedTx,
+    ProviderFut,
+};
+
+/// Enumerates the transactions of an address, newest first, following the Esplora API's
+/// pagination. Mempool transactions come first. Esplora reports at most 50 of them.
+///
+/// Each page holds the full transactions, so no per-transaction requests are made. The stream
+/// ends after the first error.
+#[pin_project(project = AddressTxsProj)]
+#[must_use = "streams do nothing unless polled"]
+pub struct AddressTxs<'a> {
+    provider: &'a EsploraProvider,
+    address: String,
+    buffer: VecDeque<EsploraTx>,
+    last_seen: Option<String>,
+    fut_opt: Option<ProviderFut<'a, Vec<EsploraTx>>>,
+    done: bool,
+}
+
+impl<'a> AddressTxs<'a> {
+    pub(crate) fn new(provider: &'a EsploraProvider, address: String) -> Self {
+        Self {
+            provider,
+            address,
+            buffer: Default::default(),
+            last_seen: None,
+            fut_opt: None,
+            done: false,
+        }
+    }
+}
+
+fn fetch_page(
+    provider: &EsploraProvider,
+    address: String,
+    last_seen: Option<String>,
+) -> ProviderFut<'_, Vec<EsploraTx>> {
+    Box::pin(async move {
+        instrument!("esplora", "address_txs", {
+            let client = provider.client().await;
+            Ok(EsploraTx::fetch_page_by_address(
+                client,
+                &provider.api_root,
+                &address,
+                last_seen.as_deref(),
+            )
+            .await?)
+        })
+    })
+}
+
+impl<'a> Stream for AddressTxs<'a> {
+    type Item = Result<DetailedTx, ProviderError>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        let AddressTxsProj {
+            provider,
+            address,
+            buffer,
+            last_seen,
+            fut_opt,
+            done,
+        } = self.project();
+
+        loop {
+            if let Some(tx) = buffer.pop_front() {
+                return Poll::Ready(Some(tx.to_detailed()));
+            }
+            if *done {
+                return Poll::Ready(None);
+            }
+
+            let fut = fut_opt
+                .get_or_insert_with(|| fetch_page(provider, address.clone(), last_seen.clone()));
+            let result = futures_util::ready!(fut.as_mut().poll(ctx));
+            *fut_opt = None;
+
+            match result {
+                Ok(page) => {
+                    // Only confirmed txs are paginated. A page without any is the last
+                    match page.iter().rev().find(|tx| tx.status.confirmed) {
+                        Some(tx) => *last_seen = Some(tx.txid.clone()),
+                        None => *done = true,
+                    }
+                    buffer.extend(page);
+                }
+                Err(e) => {
+                    *done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoins::prelude::*;
+
+    use super::*;
+
+    fn render(tx: &BitcoinTx, confirmed: bool) -> String {
+        let witnesses: Vec<Witness> = match tx {
+            BitcoinTx::Witness(tx) => tx.witnesses().to_vec(),
+            BitcoinTx::Legacy(_) => vec![],
+        };
+        let vin: Vec<String> = tx
+            .inputs()
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                let witness: Vec<String> = witnesses
+                    .get(i)
+                    .map(|w| {
+                        w.iter()
+                            .map(|item| format!("\"{}\"", hex::encode(item.items())))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                format!(
+                    r#"{{"txid":"{}","vout":{},"scriptsig":"{}","witness":[{}],"sequence":{}}}"#,
+                    input.outpoint.txid.to_be_hex(),
+                    input.outpoint.idx,
+                    hex::encode(input.script_sig.items()),
+                    witness.join(","),
+                    input.sequence
+                )
+            })
+            .collect();
+        let vout: Vec<String> = tx
+            .outputs()
+            .iter()
+            .map(|output| {
+                format!(
+                    r#"{{"scriptpubkey":"{}","value":{}}}"#,
+                    hex::encode(output.script_pubkey.items()),
+                    output.value.to_sat()
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"txid":"{}","version":{},"locktime":{},"vin":[{}],"vout":[{}],"status":{{"confirmed":{}}}}}"#,
+            tx.txid().to_be_hex(),
+            tx.version(),
+            tx.locktime(),
+            vin.join(","),
+            vout.join(","),
+            confirmed
+        )
+    }
+
+    #[test]
+    fn it_reassembles_paged_txs() {
+        let outpoint = BitcoinOutpoint::new(TXID::deserialize_hex(&"07".repeat(32)).unwrap(), 1);
+        let script = ScriptPubkey::new(vec![0x00, 0x14, 0x11]);
+        let legacy: BitcoinTx = LegacyTx::new(
+            2,
+            vec![BitcoinTxIn::new(
+                outpoint,
+                ScriptSig::new(vec![0x51]),
+                0xffff_fffd,
+            )],
+            vec![TxOut::new(Amount::from_sat(5000), script.clone())],
+            600_000,
+        )
+        .unwrap()
+        .into();
+        let witness: BitcoinTx = <WitnessTx as WitnessTransaction>::new(
+            2,
+            vec![BitcoinTxIn::new(outpoint, ScriptSig::null(), 0xffff_ffff)],
+            vec![TxOut::new(Amount::from_sat(4000), script)],
+            vec![vec![
+                WitnessStackItem::new(vec![0x30, 0x01]),
+                WitnessStackItem::new(vec![0x02]),
+            ]],
+            0,
+        )
+        .unwrap()
+        .into();
+
+        let page: Vec<EsploraTx> = serde_json::from_str(&format!(
+            "[{},{}]",
+            render(&witness, false),
+            render(&legacy, true)
+        ))
+        .unwrap();
+        assert_eq!(page[0].to_tx().unwrap(), witness);
+        assert_eq!(page[1].to_tx().unwrap(), legacy);
+        assert_eq!(page[0].to_detailed().unwrap().inputs.len(), 1);
+
+        // a tx that does not match its txid is rejected
+        let mut tampered = page[1].clone();
+        tampered.vout[0].value += 1;
+        assert!(tampered.to_tx().is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! A provider for the mempool.space REST API.
+//!
+//! mempool.space serves the Esplora API, so `MempoolSpaceProvider` delegates to an
+//! `EsploraProvider`. It adds typed access to the mempool.space fee endpoints: recommended fee
+//! tiers, and the projected blocks of the mempool.
+
+use async_trait::async_trait;
+use std::time::Duration;
+
+use bitcoins::prelude::*;
+
+use crate::{
+    esplora::{history::AddressTxs, EsploraProvider},
+    provider::{BtcProvider, PollingBtcProvider, ProviderError},
+    reqwest_utils::ez_fetch_json,
+    types::{DetailedTx, FeeHistogram, FeeRate, MempoolEntry, RawHeader, SpendingInput},
+};
+
+#[cfg(feature = "mainnet")]
+static MEMPOOL_SPACE: &str = "https://mempool.space/api";
+
+#[cfg(feature = "testnet")]
+static MEMPOOL_SPACE: &str = "https://mempool.space/testnet/api";
+
+/// The fee tiers recommended by mempool.space
+#[derive(serde::Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendedFees {
+    /// Expected to confirm in the next block
+    pub fastest_fee: FeeRate,
+    /// Expected to confirm within 3 blocks
+    pub half_hour_fee: FeeRate,
+    /// Expected to confirm within 6 blocks
+    pub hour_fee: FeeRate,
+    /// Expected to confirm eventually
+    pub economy_fee: FeeRate,
+    /// The minimum feerate accepted by the mempool
+    pub minimum_fee: FeeRate,
+}
+
+impl RecommendedFees {
+    /// The tier for a confirmation target
+    pub fn for_target(&self, target_blocks: usize) -> FeeRate {
+        match target_blocks {
+            0..=1 => self.fastest_fee,
+            2..=3 => self.half_hour_fee,
+            4..=6 => self.hour_fee,
+            _ => self.economy_fee,
+        }
+    }
+}
+
+/// A block that mempool.space projects will be mined from the current mempool. Feerates are
+/// in sat/vbyte.
+#[derive(serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectedBlock {
+    /// The block size, in bytes
+    pub block_size: usize,
+    /// The block vsize
+    #[serde(rename = "blockVSize")]
+    pub block_vsize: f64,
+    /// The number of transactions
+    pub n_tx: usize,
+    /// The total fees, in satoshis
+    pub total_fees: u64,
+    /// The median feerate
+    pub median_fee: FeeRate,
+    /// Feerate percentiles, from the minimum to the maximum feerate in the block
+    pub fee_range: Vec<FeeRate>,
+}
+
+impl ProjectedBlock {
+    /// The lowest feerate included in the block
+    pub fn min_fee(&self) -> Option<FeeRate> {
+        self.fee_range.first().copied()
+    }
+}
+
+/// A provider that uses the mempool.space API
+#[derive(Debug)]
+pub struct MempoolSpaceProvider {
+    esplora: EsploraProvider,
+}
+
+impl Default for MempoolSpaceProvider {
+    fn default() -> Self {
+        Self::with_api_root(MEMPOOL_SPACE)
+    }
+}
+
+impl MempoolSpaceProvider {
+    /// Instantiate the API pointing at a specific URL
+    pub fn with_api_root(api_root: &str) -> Self {
+        Self {
+            esplora: EsploraProvider::with_api_root(api_root),
+        }
+    }
+
+    /// Limit requests to `per_second` requests per second, with bursts of up to `burst`
+    /// requests. See `EsploraProvider::rate_limit`.
+    pub fn rate_limit(mut self, per_second: f64, burst: usize) -> Self {
+        self.esplora = self.esplora.rate_limit(per_second, burst);
+        self
+    }
+
+    /// Make at most `concurrency` concurrent requests when fetching ranges or batches. See
+    /// `EsploraProvider::concurrency`.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.esplora = self.esplora.concurrency(concurrency);
+        self
+    }
+
+    /// Stream the transactions of an address, newest first. See
+    /// `EsploraProvider::address_txs`.
+    pub fn address_txs(&self, address: &Address) -> AddressTxs<'_> {
+        self.esplora.address_txs(address)
+    }
+
+    /// Return a reference to the underlying Esplora provider
+    pub fn esplora(&self) -> &EsploraProvider {
+        &self.esplora
+    }
+
+    /// Fetch the recommended fee tiers
+    pub async fn recommended_fees(&self) -> Result<RecommendedFees, ProviderError> {
+        let url = format!("{}/v1/fees/recommended", self.esplora.api_root);
+        Ok(ez_fetch_json(self.esplora.client().await, &url).await?)
+    }
+
+    /// Fetch the projected blocks of the mempool, from the next block onwards
+    pub async fn projected_blocks(&self) -> Result<Vec<ProjectedBlock>, ProviderError> {
+        let url = format!("{}/v1/fees/mempool-blocks", self.esplora.api_root);
+        Ok(ez_fetch_json(self.esplora.client().await, &url).await?)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl BtcProvider for MempoolSpaceProvider {
+    async fn tip_hash(&self) -> Result<BlockHash, ProviderError> {
+        self.esplora.tip_hash().await
+    }
+
+    async fn tip_height(&self) -> Result<usize, ProviderError> {
+        self.esplora.tip_height().await
+    }
+
+    async fn in_best_chain(&self, digest: BlockHash) -> Result<bool, ProviderError> {
+        self.esplora.in_best_chain(digest).await
+    }
+
+    async fn get_digest_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<BlockHash>, ProviderError> {
+        self.esplora.get_digest_range(start, headers).await
+    }
+
+    async fn get_raw_header_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<RawHeader>, ProviderError> {
+        self.esplora.get_raw_header_range(start, headers).await
+    }
+
+    async fn get_raw_header(&self, digest: BlockHash) -> Result<Option<RawHeader>, ProviderError> {
+        self.esplora.get_raw_header(digest).await
+    }
+
+    async fn get_height_of(&self, digest: BlockHash) -> Result<Option<usize>, ProviderError> {
+        self.esplora.get_height_of(digest).await
+    }
+
+    async fn get_confirmed_height(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.esplora.get_confirmed_height(txid).await
+    }
+
+    async fn get_confs(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.esplora.get_confs(txid).await
+    }
+
+    async fn get_tx(&self, txid: TXID) -> Result<Option<BitcoinTx>, ProviderError> {
+        self.esplora.get_tx(txid).await
+    }
+
+    async fn get_tx_detailed(&self, txid: TXID) -> Result<Option<DetailedTx>, ProviderError> {
+        self.esplora.get_tx_detailed(txid).await
+    }
+
+    async fn get_block_full(&self, digest: BlockHash) -> Result<Option<Block>, ProviderError> {
+        self.esplora.get_block_full(digest).await
+    }
+
+    async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError> {
+        self.esplora.broadcast(tx).await
+    }
+
+    async fn get_fee_histogram(&self) -> Result<FeeHistogram, ProviderError> {
+        self.esplora.get_fee_histogram().await
+    }
+
+    /// Selects a recommended fee tier for the target
+    async fn estimate_fee(&self, target_blocks: usize) -> Result<FeeRate, ProviderError> {
+        Ok(self.recommended_fees().await?.for_target(target_blocks))
+    }
+
+    async fn get_mempool_entry(&self, txid: TXID) -> Result<Option<MempoolEntry>, ProviderError> {
+        self.esplora.get_mempool_entry(txid).await
+    }
+
+    async fn get_outspend(&self, outpoint: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError> {
+        self.esplora.get_outspend(outpoint).await
+    }
+
+    async fn get_spending_input(
+        &self,
+        outpoint: BitcoinOutpoint,
+    ) -> Result<Option<SpendingInput>, ProviderError> {
+        self.esplora.get_spending_input(outpoint).await
+    }
+
+    async fn get_utxos_by_address(&self, address: &Address) -> Result<Vec<Utxo>, ProviderError> {
+        self.esplora.get_utxos_by_address(address).await
+    }
+
+    async fn get_merkle(
+        &self,
+        txid: TXID,
+    ) -> Result<Option<(usize, Vec<Hash256Digest>)>, ProviderError> {
+        self.esplora.get_merkle(txid).await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl PollingBtcProvider for MempoolSpaceProvider {
+    fn interval(&self) -> Duration {
+        self.esplora.interval()
+    }
+
+    fn set_interval(&mut self, interval: usize) {
+        self.esplora.set_interval(interval)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_parses_fee_endpoints() {
+        let fees: RecommendedFees = serde_json::from_str(
+            r#"{"fastestFee":25,"halfHourFee":20,"hourFee":15,"economyFee":5,"minimumFee":1}"#,
+        )
+        .unwrap();
+        assert_eq!(fees.for_target(1), FeeRate(25.0));
+        assert_eq!(fees.for_target(3), FeeRate(20.0));
+        assert_eq!(fees.for_target(6), FeeRate(15.0));
+        assert_eq!(fees.for_target(144), FeeRate(5.0));
+
+        let blocks: Vec<ProjectedBlock> = serde_json::from_str(
+            r#"[{"blockSize":1589231,"blockVSize":997924.5,"nTx":2866,"totalFees":21514729,
+                "medianFee":12.3,"feeRange":[10.1,10.9,11.5,12.3,15.2,22.0,301.4]}]"#,
+        )
+        .unwrap();
+        assert_eq!(blocks[0].n_tx, 2866);
+        assert_eq!(blocks[0].median_fee, FeeRate(12.3));
+        assert_eq!(blocks[0].min_fee(), Some(FeeRate(10.1)));
+    }
+}
@@ -1,18 +1,30 @@
 mod types;
 
+/// Push updates from the mempool.space WebSocket API
+pub mod ws;
+
+/// The mempool.space REST API
+pub mod mempool;
+
+/// Paginated address histories
+pub mod history;
+
+use history::AddressTxs;
 use types::*;
 
 use crate::reqwest_utils::*;
 
 use async_trait::async_trait;
-use std::time::Duration;
+use std::{collections::HashSet, time::Duration};
 
 use bitcoins::prelude::*;
 use coins_core::hashes::MarkedDigestOutput;
 
 use crate::{
     provider::{BtcProvider, PollingBtcProvider, ProviderError},
-    types::RawHeader,
+    types::{DetailedTx, FeeHistogram, FeeRate, MempoolEntry, RawHeader, SpendingInput},
+    utils::{try_join_bounded, RateLimiter},
+    zeroconf::{DEFAULT_ANCESTOR_DEPTH, RBF_SEQUENCE_THRESHOLD},
 };
 
 #[cfg(feature = "mainnet")]
@@ -21,12 +33,20 @@ static BLOCKSTREAM: &str = "https://blockstream.info/api";
 #[cfg(feature = "testnet")]
 static BLOCKSTREAM: &str = "https://blockstream.info/testnet/api";
 
+/// The default maximum number of concurrent requests made by a single range or batch fetch
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+// The number of blocks in a page of the `/blocks/` endpoint
+const BLOCKS_PAGE_SIZE: usize = 10;
+
 /// A Provider that uses the Esplora API and caches some responses
 #[derive(Debug)]
 pub struct EsploraProvider {
     interval: std::time::Duration,
     api_root: String,
     client: reqwest::Client,
+    limiter: Option<RateLimiter>,
+    concurrency: usize,
 }
 
 impl Default for EsploraProvider {
@@ -42,168 +62,422 @@ impl EsploraProvider {
             interval: crate::DEFAULT_POLL_INTERVAL,
             api_root: api_root.to_owned(),
             client: Default::default(),
+            limiter: None,
+            concurrency: DEFAULT_CONCURRENCY,
         }
     }
+
+    /// Limit requests to `per_second` requests per second, with bursts of up to `burst`
+    /// requests. Public instances ban clients that make too many requests, e.g. during header
+    /// range fetches or address scans.
+    pub fn rate_limit(mut self, per_second: f64, burst: usize) -> Self {
+        self.limiter = Some(RateLimiter::new(per_second, burst));
+        self
+    }
+
+    /// Make at most `concurrency` concurrent requests when fetching header ranges, digest
+    /// ranges, or batches of merkle proofs. Defaults to `DEFAULT_CONCURRENCY`. Combine with
+    /// `rate_limit` when using a public instance.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = std::cmp::max(concurrency, 1);
+        self
+    }
+
+    /// Get the merkle proofs for a batch of transactions, in the order of the txids. A proof is
+    /// `None` if its tx is not confirmed. Requests are made concurrently, see `concurrency`.
+    pub async fn get_merkles(
+        &self,
+        txids: &[TXID],
+    ) -> Result<Vec<Option<(usize, Vec<Hash256Digest>)>>, ProviderError> {
+        let proofs = try_join_bounded(
+            txids.iter().map(|txid| self.fetch_merkle(*txid)),
+            self.concurrency,
+        )
+        .await?;
+        Ok(proofs
+            .into_iter()
+            .map(|proof| proof.map(MerkleProof::into_branch))
+            .collect())
+    }
+
+    /// Stream the transactions of an address, newest first, with their prevouts and confirming
+    /// blocks. This follows the API's pagination, so long histories are enumerated in full
+    /// rather than truncated at the first page.
+    pub fn address_txs(&self, address: &Address) -> AddressTxs<'_> {
+        AddressTxs::new(self, address.as_string())
+    }
+
+    // Wait for the rate limiter, if any, and return the client
+    async fn client(&self) -> &reqwest::Client {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+        &self.client
+    }
+}
+
+impl EsploraProvider {
+    // Fetch a tx by its BE txid
+    async fn fetch_tx(&self, txid_be: &str) -> Result<EsploraTx, FetchError> {
+        let url = format!("{}/tx/{}", self.api_root, txid_be);
+        ez_fetch_json(self.client().await, &url).await
+    }
+
+    // Fetch the blocks from `start` to `start + headers - 1`, in ascending order, using the
+    // paginated `/blocks/` endpoint. Blocks past the chain tip are omitted
+    async fn fetch_block_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<EsploraBlock>, ProviderError> {
+        if headers == 0 {
+            return Ok(vec![]);
+        }
+        let tip = self.tip_height().await?;
+        if start > tip {
+            return Ok(vec![]);
+        }
+
+        // Pages descend from their starting height, so we work backwards from the end. Some
+        // instances serve larger pages, so pages may overlap
+        let mut heights = vec![];
+        let mut height = std::cmp::min(start + headers - 1, tip);
+        loop {
+            heights.push(height);
+            if height < start + BLOCKS_PAGE_SIZE {
+                break;
+            }
+            height -= BLOCKS_PAGE_SIZE;
+        }
+
+        let pages = try_join_bounded(
+            heights.into_iter().map(|height| async move {
+                EsploraBlock::fetch_from_height(self.client().await, &self.api_root, height).await
+            }),
+            self.concurrency,
+        )
+        .await?;
+        let mut blocks: Vec<EsploraBlock> = pages
+            .into_iter()
+            .flatten()
+            .filter(|block| block.height >= start)
+            .collect();
+        blocks.sort_by_key(|block| block.height);
+        blocks.dedup_by_key(|block| block.height);
+        Ok(blocks)
+    }
+
+    // Fetch a merkle proof. `None` if the tx is unknown or unconfirmed
+    async fn fetch_merkle(&self, txid: TXID) -> Result<Option<MerkleProof>, FetchError> {
+        match MerkleProof::fetch_by_txid(self.client().await, &self.api_root, txid).await {
+            Ok(proof) => Ok(Some(proof)),
+            Err(FetchError::SerdeError(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Walk unconfirmed ancestors. Updates the ancestor stats and replaceability of the entry
+    async fn walk_ancestors(
+        &self,
+        tx: &EsploraTx,
+        entry: &mut MempoolEntry,
+    ) -> Result<(), ProviderError> {
+        let mut seen = HashSet::new();
+        let mut frontier: Vec<String> = tx.vin.iter().map(|i| i.txid.clone()).collect();
+
+        for _ in 0..DEFAULT_ANCESTOR_DEPTH {
+            let mut next = vec![];
+            for txid in frontier.into_iter() {
+                if !seen.insert(txid.clone()) {
+                    continue;
+                }
+                let ancestor = self.fetch_tx(&txid).await?;
+                if ancestor.status.confirmed {
+                    continue;
+                }
+                entry.ancestor_count += 1;
+                entry.ancestor_fees += ancestor.fee;
+                entry.ancestor_vsize += ancestor.weight.div_ceil(4);
+                entry.bip125_replaceable |= signals_rbf(&ancestor);
+                next.extend(ancestor.vin.into_iter().map(|i| i.txid));
+            }
+            frontier = next;
+        }
+        Ok(())
+    }
+
+    // Walk unconfirmed descendants. Updates the descendant stats of the entry
+    async fn walk_descendants(
+        &self,
+        tx: &EsploraTx,
+        entry: &mut MempoolEntry,
+    ) -> Result<(), ProviderError> {
+        let mut seen = HashSet::new();
+        let mut frontier = vec![tx.txid.clone()];
+
+        for _ in 0..DEFAULT_ANCESTOR_DEPTH {
+            let mut next = vec![];
+            for txid in frontier.into_iter() {
+                let spenders =
+                    EsploraSpender::fetch_by_txid(self.client().await, &self.api_root, &txid)
+                        .await?;
+                for spender in spenders.iter().filter_map(|s| s.unconfirmed_txid()) {
+                    if !seen.insert(spender.to_owned()) {
+                        continue;
+                    }
+                    let descendant = self.fetch_tx(spender).await?;
+                    entry.descendant_count += 1;
+                    entry.descendant_fees += descendant.fee;
+                    entry.descendant_vsize += descendant.weight.div_ceil(4);
+                    next.push(spender.to_owned());
+                }
+            }
+            frontier = next;
+        }
+        Ok(())
+    }
+}
+
+fn signals_rbf(tx: &EsploraTx) -> bool {
+    tx.vin.iter().any(|i| i.sequence < RBF_SEQUENCE_THRESHOLD)
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl BtcProvider for EsploraProvider {
     async fn tip_hash(&self) -> Result<BlockHash, ProviderError> {
-        let url = format!("{}/blocks/tip/hash", self.api_root);
-        let response = ez_fetch_string(&self.client, &url).await?;
-        Ok(BlockHash::from_be_hex(&response)?)
+        instrument!("esplora", "tip_hash", {
+            let url = format!("{}/blocks/tip/hash", self.api_root);
+            let response = ez_fetch_string(self.client().await, &url).await?;
+            Ok(BlockHash::from_be_hex(&response)?)
+        })
     }
 
     async fn tip_height(&self) -> Result<usize, ProviderError> {
-        let url = format!("{}/blocks/tip/height", self.api_root);
-        let response = ez_fetch_string(&self.client, &url).await?;
-        Ok(response.parse().unwrap())
+        instrument!("esplora", "tip_height", {
+            let url = format!("{}/blocks/tip/height", self.api_root);
+            let response = ez_fetch_string(self.client().await, &url).await?;
+            Ok(response.parse().unwrap())
+        })
     }
 
     async fn in_best_chain(&self, digest: BlockHash) -> Result<bool, ProviderError> {
-        Ok(
-            BlockStatus::fetch_by_digest(&self.client, &self.api_root, digest)
-                .await?
-                .in_best_chain,
-        )
+        instrument!("esplora", "in_best_chain", {
+            Ok(
+                BlockStatus::fetch_by_digest(self.client().await, &self.api_root, digest)
+                    .await?
+                    .in_best_chain,
+            )
+        })
     }
 
-    // TODO: rewrite to not make O(2 * n) requests using the /blocks/ endpoint
     async fn get_raw_header_range(
         &self,
         start: usize,
         headers: usize,
     ) -> Result<Vec<RawHeader>, ProviderError> {
-        let digests = self.get_digest_range(start, headers).await?;
-        let mut h = vec![];
-        for digest in digests.into_iter() {
-            if let Some(header) = self.get_raw_header(digest).await? {
-                h.push(header);
-            } else {
-                break;
-            }
-        }
-        Ok(h)
+        instrument!("esplora", "get_raw_header_range", {
+            let blocks = self.fetch_block_range(start, headers).await?;
+            Ok(blocks.iter().map(EsploraBlock::serialize).collect())
+        })
     }
 
-    // TODO: rewrite to not make O(n) requests using the /blocks/ endpoint
     async fn get_digest_range(
         &self,
         start: usize,
         headers: usize,
     ) -> Result<Vec<BlockHash>, ProviderError> {
-        let mut h = vec![];
-        for i in 0..headers {
-            let url = format!("{}/block-height/{}", self.api_root, start + i);
-            h.push(BlockHash::from_be_hex(
-                &ez_fetch_string(&self.client, &url).await?,
-            )?);
-        }
-
-        Ok(h)
+        instrument!("esplora", "get_digest_range", {
+            self.fetch_block_range(start, headers)
+                .await?
+                .iter()
+                .map(|block| BlockHash::from_be_hex(&block.id).map_err(Into::into))
+                .collect()
+        })
     }
 
     async fn get_raw_header(&self, digest: BlockHash) -> Result<Option<RawHeader>, ProviderError> {
-        let header = esplora_if_found!(
-            EsploraBlock::fetch_by_digest(&self.client, &self.api_root, digest).await
-        );
-        Ok(Some(header.serialize()))
+        instrument!("esplora", "get_raw_header", {
+            let header = esplora_if_found!(
+                EsploraBlock::fetch_by_digest(self.client().await, &self.api_root, digest).await
+            );
+            Ok(Some(header.serialize()))
+        })
     }
 
     async fn get_height_of(&self, digest: BlockHash) -> Result<Option<usize>, ProviderError> {
-        let block = esplora_if_found!(
-            EsploraBlock::fetch_by_digest(&self.client, &self.api_root, digest).await
-        );
-        Ok(Some(block.height))
+        instrument!("esplora", "get_height_of", {
+            let block = esplora_if_found!(
+                EsploraBlock::fetch_by_digest(self.client().await, &self.api_root, digest).await
+            );
+            Ok(Some(block.height))
+        })
     }
 
     async fn get_confirmed_height(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
-        let tx = esplora_if_found!(
-            EsploraTxStatus::fetch_by_txid(&self.client, &self.api_root, txid).await
-        );
-        Ok(Some(tx.block_height))
+        instrument!("esplora", "get_confirmed_height", {
+            let tx = esplora_if_found!(
+                EsploraTxStatus::fetch_by_txid(self.client().await, &self.api_root, txid).await
+            );
+            Ok(Some(tx.block_height))
+        })
     }
 
     async fn get_confs(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
-        let tx =
-            esplora_if_found!(EsploraTx::fetch_by_txid(&self.client, &self.api_root, txid).await);
+        instrument!("esplora", "get_confs", {
+            let tx = esplora_if_found!(
+                EsploraTx::fetch_by_txid(self.client().await, &self.api_root, txid).await
+            );
 
-        if !tx.status.confirmed {
-            return Ok(Some(0));
-        }
-        let digest =
-            BlockHash::from_be_hex(&tx.status.block_hash).expect("No bad hex in API response");
-        if !self.in_best_chain(digest).await? {
-            return Ok(Some(0));
-        }
-        let height = self.tip_height().await?;
-        Ok(Some(height - tx.status.block_height + 1))
+            if !tx.status.confirmed {
+                return Ok(Some(0));
+            }
+            let digest =
+                BlockHash::from_be_hex(&tx.status.block_hash).expect("No bad hex in API response");
+            if !self.in_best_chain(digest).await? {
+                return Ok(Some(0));
+            }
+            let height = self.tip_height().await?;
+            Ok(Some(height - tx.status.block_height + 1))
+        })
     }
 
     async fn get_tx(&self, txid: TXID) -> Result<Option<BitcoinTx>, ProviderError> {
-        let tx_hex = fetch_tx_hex_by_id(&self.client, &self.api_root, txid).await?;
-        if let Ok(tx) = BitcoinTx::deserialize_hex(&tx_hex) {
-            Ok(Some(tx))
-        } else {
-            Ok(None)
-        }
+        instrument!("esplora", "get_tx", {
+            let tx_hex = fetch_tx_hex_by_id(self.client().await, &self.api_root, txid).await?;
+            if let Ok(tx) = BitcoinTx::deserialize_hex(&tx_hex) {
+                Ok(Some(tx))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    async fn get_block_full(&self, digest: BlockHash) -> Result<Option<Block>, ProviderError> {
+        instrument!("esplora", "get_block_full", {
+            let url = format!("{}/block/{}/raw", self.api_root, digest.to_be_hex());
+            let raw = ez_fetch_blob(self.client().await, &url).await?;
+            // Unknown blocks produce an error string, which does not parse as a block
+            if let Ok(block) = Block::read_from(&mut raw.as_ref()) {
+                Ok(Some(block))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    async fn get_tx_detailed(&self, txid: TXID) -> Result<Option<DetailedTx>, ProviderError> {
+        instrument!("esplora", "get_tx_detailed", {
+            let details = esplora_if_found!(
+                EsploraTx::fetch_by_txid(self.client().await, &self.api_root, txid).await
+            );
+            let tx_hex = fetch_tx_hex_by_id(self.client().await, &self.api_root, txid).await?;
+            let tx = BitcoinTx::deserialize_hex(&tx_hex)
+                .map_err(|e| ProviderError::custom(true, Box::new(e)))?;
+            Ok(Some(DetailedTx::new(
+                tx,
+                details.block_hash()?,
+                details.prevouts()?,
+            )))
+        })
     }
 
     async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError> {
-        let url = format!("{}/tx", self.api_root);
-        let mut buf = vec![];
-        tx.write_to(&mut buf).unwrap();
-        let response = post_bytes_as_hex(&self.client, &url, &buf).await?;
-        Ok(TXID::deserialize_hex(&response)?)
+        instrument!("esplora", "broadcast", {
+            let url = format!("{}/tx", self.api_root);
+            let mut buf = vec![];
+            tx.write_to(&mut buf).unwrap();
+            let response = post_bytes_as_hex(self.client().await, &url, &buf).await?;
+            // Esplora responds with the BE txid, or with the error message
+            TXID::from_be_hex(&response).map_err(|_| ProviderError::Rejected(response))
+        })
+    }
+
+    async fn get_fee_histogram(&self) -> Result<FeeHistogram, ProviderError> {
+        instrument!("esplora", "get_fee_histogram", {
+            let mempool = EsploraMempool::fetch(self.client().await, &self.api_root).await?;
+            Ok(FeeHistogram(mempool.fee_histogram))
+        })
+    }
+
+    async fn estimate_fee(&self, target_blocks: usize) -> Result<FeeRate, ProviderError> {
+        instrument!("esplora", "estimate_fee", {
+            let estimates = EsploraFeeEstimates::fetch(self.client().await, &self.api_root).await?;
+            estimates.for_target(target_blocks).ok_or_else(|| {
+                ProviderError::Unsupported("No fee estimates available from API".to_owned())
+            })
+        })
+    }
+
+    /// Esplora has no mempool entry endpoint. The entry is assembled by walking the unconfirmed
+    /// ancestors and descendants of the tx, which requires several requests per relative.
+    async fn get_mempool_entry(&self, txid: TXID) -> Result<Option<MempoolEntry>, ProviderError> {
+        instrument!("esplora", "get_mempool_entry", {
+            let tx = esplora_if_found!(self.fetch_tx(&txid.to_be_hex()).await);
+            if tx.status.confirmed {
+                return Ok(None);
+            }
+
+            let vsize = tx.weight.div_ceil(4);
+            let mut entry = MempoolEntry {
+                fee: tx.fee,
+                weight: tx.weight,
+                ancestor_count: 1,
+                ancestor_fees: tx.fee,
+                ancestor_vsize: vsize,
+                descendant_count: 1,
+                descendant_fees: tx.fee,
+                descendant_vsize: vsize,
+                bip125_replaceable: signals_rbf(&tx),
+            };
+            self.walk_ancestors(&tx, &mut entry).await?;
+            self.walk_descendants(&tx, &mut entry).await?;
+            Ok(Some(entry))
+        })
     }
 
     async fn get_outspend(&self, outpoint: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError> {
-        let outspend_opt =
-            Outspend::fetch_by_outpoint(&self.client, &self.api_root, &outpoint).await?;
-
-        match outspend_opt {
-            Some(outspend) => {
-                if outspend.spent {
-                    let txid = Default::default();
-                    Ok(Some(txid))
-                } else {
-                    Ok(None)
-                }
+        instrument!("esplora", "get_outspend", {
+            Ok(self
+                .get_spending_input(outpoint)
+                .await?
+                .map(|input| input.txid))
+        })
+    }
+
+    async fn get_spending_input(
+        &self,
+        outpoint: BitcoinOutpoint,
+    ) -> Result<Option<SpendingInput>, ProviderError> {
+        instrument!("esplora", "get_spending_input", {
+            match Outspend::fetch_by_outpoint(self.client().await, &self.api_root, &outpoint)
+                .await?
+            {
+                Some(outspend) => outspend.spending_input(),
+                None => Ok(None),
             }
-            None => Ok(None),
-        }
+        })
     }
 
     async fn get_utxos_by_address(&self, address: &Address) -> Result<Vec<Utxo>, ProviderError> {
-        let res: Result<Vec<_>, _> =
-            EsploraUtxo::fetch_by_address(&self.client, &self.api_root, address)
-                .await?
-                .into_iter()
-                .map(|e| e.into_utxo(address))
-                .collect();
-        Ok(res?)
+        instrument!("esplora", "get_utxos_by_address", {
+            let res: Result<Vec<_>, _> =
+                EsploraUtxo::fetch_by_address(self.client().await, &self.api_root, address)
+                    .await?
+                    .into_iter()
+                    .map(|e| e.into_utxo(address))
+                    .collect();
+            res
+        })
     }
 
     async fn get_merkle(
         &self,
         txid: TXID,
     ) -> Result<Option<(usize, Vec<Hash256Digest>)>, ProviderError> {
-        let proof_res = MerkleProof::fetch_by_txid(&self.client, &self.api_root, txid).await;
-        match proof_res {
-            Ok(proof) => {
-                let ids = proof
-                    .merkle
-                    .iter()
-                    .map(|s| {
-                        Hash256Digest::from_be_hex(s).expect("No malformed txids in api response")
-                    })
-                    .collect();
-                Ok(Some((proof.pos, ids)))
-            }
-            Err(FetchError::SerdeError(_)) => Ok(None),
-            Err(e) => Err(e.into()),
-        }
+        instrument!("esplora", "get_merkle", {
+            Ok(self.fetch_merkle(txid).await?.map(MerkleProof::into_branch))
+        })
     }
 }
 
@@ -1,7 +1,11 @@
 use bitcoins::prelude::*;
 
 use crate::esplora::*;
-use crate::{provider::ProviderError, reqwest_utils};
+use crate::{
+    provider::ProviderError,
+    reqwest_utils,
+    types::{DetailedOutput, DetailedTx},
+};
 
 #[allow(dead_code)]
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -17,9 +21,19 @@ impl MerkleProof {
         api_root: &str,
         txid: TXID,
     ) -> Result<Self, FetchError> {
-        let url = format!("{}/tx/{}/MerkleProof", api_root, txid.to_be_hex());
+        let url = format!("{}/tx/{}/merkle-proof", api_root, txid.to_be_hex());
         reqwest_utils::ez_fetch_json(client, &url).await
     }
+
+    /// The position of the tx in its block, and its merkle branch
+    pub(crate) fn into_branch(self) -> (usize, Vec<Hash256Digest>) {
+        let ids = self
+            .merkle
+            .iter()
+            .map(|s| Hash256Digest::from_be_hex(s).expect("No malformed txids in api response"))
+            .collect();
+        (self.pos, ids)
+    }
 }
 
 #[allow(dead_code)]
@@ -61,11 +75,64 @@ impl EsploraTxStatus {
     }
 }
 
+#[allow(dead_code)]
+#[derive(serde::Deserialize, Clone, Debug)]
+pub(crate) struct EsploraVin {
+    /// The BE txid of the prevout
+    pub txid: String,
+    #[serde(default)]
+    pub vout: u32,
+    /// The hex script sig
+    #[serde(default)]
+    pub scriptsig: String,
+    /// The hex witness stack items. Absent for legacy inputs
+    #[serde(default)]
+    pub witness: Vec<String>,
+    pub sequence: u32,
+    #[serde(default)]
+    pub is_coinbase: bool,
+    /// The output spent. Absent for coinbase inputs
+    #[serde(default)]
+    pub prevout: Option<EsploraTxOut>,
+}
+
+#[allow(dead_code)]
+#[derive(serde::Deserialize, Clone, Debug)]
+pub(crate) struct EsploraTxOut {
+    /// The hex script pubkey
+    pub scriptpubkey: String,
+    /// The address, if the script is standard
+    #[serde(default)]
+    pub scriptpubkey_address: Option<String>,
+    pub value: u64,
+}
+
+impl EsploraTxOut {
+    pub(crate) fn to_detailed(&self) -> Result<DetailedOutput, ProviderError> {
+        Ok(DetailedOutput::new(
+            self.value,
+            ScriptPubkey::new(decode_hex(&self.scriptpubkey)?),
+        ))
+    }
+}
+
 #[allow(dead_code)]
 #[derive(serde::Deserialize, Clone, Debug)]
 pub(crate) struct EsploraTx {
     pub status: EsploraTxStatus,
     pub txid: String,
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub locktime: u32,
+    #[serde(default)]
+    pub vin: Vec<EsploraVin>,
+    #[serde(default)]
+    pub vout: Vec<EsploraTxOut>,
+    #[serde(default)]
+    pub weight: usize,
+    #[serde(default)]
+    pub fee: u64,
 }
 
 impl EsploraTx {
@@ -77,6 +144,117 @@ impl EsploraTx {
         let url = format!("{}/tx/{}", api_root, txid.to_be_hex());
         reqwest_utils::ez_fetch_json(client, &url).await
     }
+
+    /// Fetch a page of an address's transactions, newest first. The first page holds its
+    /// mempool transactions, and its most recent confirmed transactions. Later pages hold the
+    /// confirmed transactions that follow the BE txid `last_seen`
+    pub(crate) async fn fetch_page_by_address(
+        client: &reqwest::Client,
+        api_root: &str,
+        address: &str,
+        last_seen: Option<&str>,
+    ) -> Result<Vec<Self>, FetchError> {
+        let url = match last_seen {
+            Some(txid_be) => format!("{}/address/{}/txs/chain/{}", api_root, address, txid_be),
+            None => format!("{}/address/{}/txs", api_root, address),
+        };
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+
+    /// The hash of the confirming block, if any
+    pub(crate) fn block_hash(&self) -> Result<Option<BlockHash>, ProviderError> {
+        if self.status.confirmed {
+            Ok(Some(BlockHash::from_be_hex(&self.status.block_hash)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The outputs spent by the inputs, in input order
+    pub(crate) fn prevouts(&self) -> Result<Vec<Option<DetailedOutput>>, ProviderError> {
+        self.vin
+            .iter()
+            .map(|vin| {
+                vin.prevout
+                    .as_ref()
+                    .map(EsploraTxOut::to_detailed)
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// Reassemble the transaction from its fields. Fails if the result does not match the
+    /// reported txid
+    pub(crate) fn to_tx(&self) -> Result<BitcoinTx, ProviderError> {
+        let vin = self
+            .vin
+            .iter()
+            .map(|vin| {
+                Ok(BitcoinTxIn::new(
+                    BitcoinOutpoint::new(TXID::from_be_hex(&vin.txid)?, vin.vout),
+                    ScriptSig::new(decode_hex(&vin.scriptsig)?),
+                    vin.sequence,
+                ))
+            })
+            .collect::<Result<Vec<_>, ProviderError>>()?;
+        let vout = self
+            .vout
+            .iter()
+            .map(|vout| {
+                Ok(TxOut::new(
+                    Amount::from_sat(vout.value),
+                    ScriptPubkey::new(decode_hex(&vout.scriptpubkey)?),
+                ))
+            })
+            .collect::<Result<Vec<_>, ProviderError>>()?;
+
+        let tx: BitcoinTx = if self.vin.iter().any(|vin| !vin.witness.is_empty()) {
+            let witnesses = self
+                .vin
+                .iter()
+                .map(|vin| {
+                    vin.witness
+                        .iter()
+                        .map(|item| Ok(WitnessStackItem::new(decode_hex(item)?)))
+                        .collect::<Result<Witness, ProviderError>>()
+                })
+                .collect::<Result<Vec<_>, ProviderError>>()?;
+            <WitnessTx as WitnessTransaction>::new(
+                self.version,
+                vin,
+                vout,
+                witnesses,
+                self.locktime,
+            )
+            .map_err(|e| ProviderError::custom(true, Box::new(e)))?
+            .into()
+        } else {
+            LegacyTx::new(self.version, vin, vout, self.locktime)
+                .map_err(|e| ProviderError::custom(true, Box::new(e)))?
+                .into()
+        };
+
+        if tx.txid() != TXID::from_be_hex(&self.txid)? {
+            return Err(ProviderError::custom(
+                true,
+                "Reassembled tx does not match its txid".into(),
+            ));
+        }
+        Ok(tx)
+    }
+
+    /// Reassemble the transaction, with its prevouts and confirming block
+    pub(crate) fn to_detailed(&self) -> Result<DetailedTx, ProviderError> {
+        Ok(DetailedTx::new(
+            self.to_tx()?,
+            self.block_hash()?,
+            self.prevouts()?,
+        ))
+    }
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, ProviderError> {
+    hex::decode(s).map_err(|e| ProviderError::custom(true, Box::new(e)))
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -108,7 +286,7 @@ impl EsploraUtxo {
         let spend_script = SpendScript::from_script_pubkey(&script_pubkey);
         Ok(Utxo::new(
             outpoint,
-            self.value as u64,
+            Amount::from_sat(self.value as u64),
             script_pubkey,
             spend_script,
         ))
@@ -120,17 +298,27 @@ impl EsploraUtxo {
 pub(crate) struct Outspend {
     /// Whether the output has been spent
     pub spent: bool,
-    /// The TXID that spend it
-    #[serde(default = "String::new")]
+    /// The BE txid of the tx that spends it
+    #[serde(rename = "txid", default = "String::new")]
     pub txid_be: String,
     /// The index of the spending input in that transaction's Vin
-    #[serde(default = "usize::max_value")]
-    pub vin: usize,
+    pub vin: Option<usize>,
     /// The status of the spending TX
-    pub status: EsploraTxStatus,
+    pub status: Option<EsploraTxStatus>,
 }
 
 impl Outspend {
+    /// The spending input. `None` if the response is missing the input index
+    pub(crate) fn spending_input(&self) -> Result<Option<SpendingInput>, ProviderError> {
+        match self.vin {
+            Some(vin) => Ok(Some(SpendingInput {
+                txid: TXID::from_be_hex(&self.txid_be)?,
+                vin,
+            })),
+            None => Ok(None),
+        }
+    }
+
     /// Fetch an Outspend by an outpoint referencing it
     pub(crate) async fn fetch_by_outpoint(
         client: &reqwest::Client,
@@ -152,7 +340,7 @@ impl Outspend {
     ) -> Result<Option<Outspend>, FetchError> {
         let url = format!("{}/tx/{}/outspend/{}", api_root, txid_be_hex, idx);
         let o: Outspend = reqwest_utils::ez_fetch_json(client, &url).await?;
-        if o.txid_be.is_empty() {
+        if !o.spent || o.txid_be.is_empty() {
             Ok(None)
         } else {
             Ok(Some(o))
@@ -160,6 +348,87 @@ impl Outspend {
     }
 }
 
+#[derive(serde::Deserialize, Clone, Debug)]
+pub(crate) struct EsploraSpender {
+    pub spent: bool,
+    /// The BE txid of the spending tx
+    #[serde(default = "String::new")]
+    pub txid: String,
+    pub status: Option<EsploraTxStatus>,
+}
+
+impl EsploraSpender {
+    /// Fetch the spenders of all outputs of a tx
+    pub(crate) async fn fetch_by_txid(
+        client: &reqwest::Client,
+        api_root: &str,
+        txid: &str,
+    ) -> Result<Vec<EsploraSpender>, FetchError> {
+        let url = format!("{}/tx/{}/outspends", api_root, txid);
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+
+    /// The BE txid of the spender, if it is unconfirmed
+    pub(crate) fn unconfirmed_txid(&self) -> Option<&str> {
+        let confirmed = self.status.as_ref().is_some_and(|s| s.confirmed);
+        if self.spent && !confirmed {
+            Some(&self.txid)
+        } else {
+            None
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(serde::Deserialize, Clone, Debug)]
+pub(crate) struct EsploraMempool {
+    pub count: usize,
+    pub vsize: usize,
+    pub total_fee: u64,
+    pub fee_histogram: Vec<(f64, usize)>,
+}
+
+impl EsploraMempool {
+    pub(crate) async fn fetch(
+        client: &reqwest::Client,
+        api_root: &str,
+    ) -> Result<Self, FetchError> {
+        let url = format!("{}/mempool", api_root);
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+}
+
+/// Feerate estimates in sat/vbyte, keyed by confirmation target in blocks
+#[derive(serde::Deserialize, Clone, Debug)]
+pub(crate) struct EsploraFeeEstimates(pub std::collections::HashMap<String, f64>);
+
+impl EsploraFeeEstimates {
+    pub(crate) async fn fetch(
+        client: &reqwest::Client,
+        api_root: &str,
+    ) -> Result<Self, FetchError> {
+        let url = format!("{}/fee-estimates", api_root);
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+
+    /// The estimate for the longest target within `target_blocks`. If there is none, the
+    /// estimate for the shortest target.
+    pub(crate) fn for_target(&self, target_blocks: usize) -> Option<FeeRate> {
+        let mut targets: Vec<(usize, f64)> = self
+            .0
+            .iter()
+            .filter_map(|(k, v)| k.parse().ok().map(|k| (k, *v)))
+            .collect();
+        targets.sort_by_key(|(k, _)| *k);
+        targets
+            .iter()
+            .rev()
+            .find(|(k, _)| *k <= target_blocks)
+            .or_else(|| targets.first())
+            .map(|(_, rate)| FeeRate(*rate))
+    }
+}
+
 #[allow(dead_code)]
 #[derive(serde::Deserialize, Clone, Debug)]
 pub(crate) struct EsploraBlock {
@@ -206,12 +475,77 @@ impl EsploraBlock {
         reqwest_utils::ez_fetch_json(client, &url).await
     }
 
-    // pub(crate) async fn fetch_from_height(
-    //     client: &reqwest::Client,
-    //     api_root: &str,
-    //     height: usize,
-    // ) -> Result<[Self; 10], FetchError> {
-    //     let url = format!("{}/block/{}", api_root, height);
-    //     Ok(reqwest_utils::ez_fetch_json(client, &url).await?)
-    // }
+    // Fetch up to 10 blocks, in descending order, starting at `height`
+    pub(crate) async fn fetch_from_height(
+        client: &reqwest::Client,
+        api_root: &str,
+        height: usize,
+    ) -> Result<Vec<Self>, FetchError> {
+        let url = format!("{}/blocks/{}", api_root, height);
+        reqwest_utils::ez_fetch_json(client, &url).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_selects_fee_estimates() {
+        let estimates: EsploraFeeEstimates =
+            serde_json::from_str(r#"{"1":87.8,"2":60.1,"6":20.5,"144":1.0}"#).unwrap();
+        assert_eq!(estimates.for_target(0), Some(FeeRate(87.8)));
+        assert_eq!(estimates.for_target(1), Some(FeeRate(87.8)));
+        assert_eq!(estimates.for_target(5), Some(FeeRate(60.1)));
+        assert_eq!(estimates.for_target(6), Some(FeeRate(20.5)));
+        assert_eq!(estimates.for_target(1000), Some(FeeRate(1.0)));
+        assert_eq!(EsploraFeeEstimates(Default::default()).for_target(6), None);
+    }
+
+    #[test]
+    fn it_parses_outspends() {
+        let txid = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16";
+        let outspend: Outspend = serde_json::from_str(&format!(
+            r#"{{"spent":true,"txid":"{}","vin":1,"status":{{"confirmed":false}}}}"#,
+            txid
+        ))
+        .unwrap();
+        assert_eq!(
+            outspend.spending_input().unwrap(),
+            Some(SpendingInput {
+                txid: TXID::from_be_hex(txid).unwrap(),
+                vin: 1
+            })
+        );
+
+        let unspent: Outspend = serde_json::from_str(r#"{"spent":false}"#).unwrap();
+        assert!(!unspent.spent);
+        assert!(unspent.txid_be.is_empty());
+    }
+
+    #[test]
+    fn it_serializes_block_headers() {
+        let blocks: Vec<EsploraBlock> = serde_json::from_str(
+            r#"[{
+                "id": "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048",
+                "height": 1,
+                "version": 1,
+                "timestamp": 1231469665,
+                "tx_count": 1,
+                "size": 215,
+                "weight": 536,
+                "merkle_root": "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098",
+                "previousblockhash": "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+                "mediantime": 1231469665,
+                "nonce": 2573394689,
+                "bits": 486604799,
+                "difficulty": 1
+            }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            blocks[0].serialize().serialize_hex(),
+            "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299"
+        );
+    }
 }
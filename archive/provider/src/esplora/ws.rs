@@ -0,0 +1,566 @@
+//! Push updates from the mempool.space WebSocket API.
+//!
+//! With the `ws` feature, `MempoolSocket` connects to e.g. `MEMPOOL_WS_URL`, sends
+//! `Subscription` messages, and streams the resulting `MempoolEvent`s, including the
+//! transactions of a tracked address. Its `into_ticks` method turns it into `PushTicks`.
+//!
+//! Without the feature, this module is agnostic to the WebSocket client. Connect with the client
+//! of your choice, send the `Subscription` messages, and pass the stream of incoming text frames
+//! to `PushTicks`. The ticks can then drive the existing `Tips`, `PendingTx` and
+//! `PollingWatcher` streams via their `ticks` method. When the socket drops, `PushTicks` falls
+//! back to polling at an interval.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::stream::Stream;
+use futures_util::stream::StreamExt;
+
+use bitcoins::{enc::Address, prelude::*};
+
+use super::types::EsploraTx;
+use crate::{
+    provider::ProviderError, types::DetailedTx, utils::new_interval, DEFAULT_POLL_INTERVAL,
+};
+
+#[cfg(feature = "ws")]
+use async_tungstenite::{
+    tungstenite::{self, Message},
+    WebSocketStream,
+};
+#[cfg(feature = "ws")]
+use futures_util::{
+    io::{AsyncRead, AsyncWrite},
+    sink::SinkExt,
+};
+#[cfg(feature = "ws")]
+use std::collections::VecDeque;
+
+/// The mempool.space mainnet WebSocket endpoint
+pub const MEMPOOL_WS_URL: &str = "wss://mempool.space/api/v1/ws";
+
+/// A subscription to mempool.space push updates.
+#[derive(Debug, Clone)]
+pub enum Subscription {
+    /// New blocks
+    Blocks,
+    /// Transactions involving an address
+    Address(Address),
+    /// Confirmation of a transaction
+    Tx(TXID),
+}
+
+impl Subscription {
+    /// The message to send on the socket to subscribe.
+    pub fn to_message(&self) -> String {
+        match self {
+            Subscription::Blocks => r#"{"action":"want","data":["blocks"]}"#.to_owned(),
+            Subscription::Address(address) => {
+                format!(r#"{{"track-address":"{}"}}"#, address.as_string())
+            }
+            Subscription::Tx(txid) => format!(r#"{{"track-tx":"{}"}}"#, txid.to_be_hex()),
+        }
+    }
+}
+
+/// The address and transaction tracked by a connection. mempool.space tracks at most one of
+/// each per connection, and its messages refer to them implicitly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tracked {
+    /// The address subscribed to with `Subscription::Address`
+    pub address: Option<Address>,
+    /// The transaction subscribed to with `Subscription::Tx`
+    pub tx: Option<TXID>,
+}
+
+impl Tracked {
+    /// Record a subscription. Tracking an address or transaction replaces the one tracked before.
+    pub fn track(&mut self, subscription: &Subscription) {
+        match subscription {
+            Subscription::Blocks => {}
+            Subscription::Address(address) => self.address = Some(address.clone()),
+            Subscription::Tx(txid) => self.tx = Some(*txid),
+        }
+    }
+}
+
+/// An event pushed by mempool.space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MempoolEvent {
+    /// A new block
+    Block {
+        /// The block hash
+        hash: BlockHash,
+        /// The block height
+        height: usize,
+    },
+    /// Transactions involving the tracked address entered the mempool
+    AddressMempool {
+        /// The tracked address
+        address: Address,
+        /// The transactions, with their prevouts
+        txs: Vec<DetailedTx>,
+    },
+    /// Transactions involving the tracked address confirmed in a new block
+    AddressConfirmed {
+        /// The tracked address
+        address: Address,
+        /// The transactions, with their prevouts and confirming block
+        txs: Vec<DetailedTx>,
+    },
+    /// The tracked transaction confirmed
+    TxConfirmed(TXID),
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WsBlock {
+    id: String,
+    height: usize,
+}
+
+// Older mempool.space backends send `true` instead of the txid.
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+enum WsTxConfirmed {
+    Txid(String),
+    Flag(bool),
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct WsMessage {
+    block: Option<WsBlock>,
+    #[serde(rename = "address-transactions", default)]
+    address_transactions: Vec<EsploraTx>,
+    #[serde(rename = "block-transactions", default)]
+    block_transactions: Vec<EsploraTx>,
+    #[serde(rename = "txConfirmed")]
+    tx_confirmed: Option<WsTxConfirmed>,
+}
+
+impl WsMessage {
+    fn has_events(&self) -> bool {
+        self.block.is_some()
+            || !self.address_transactions.is_empty()
+            || !self.block_transactions.is_empty()
+            || self.tx_confirmed.is_some()
+    }
+}
+
+fn untracked(what: &str) -> ProviderError {
+    ProviderError::custom(
+        true,
+        format!("Received {} without tracking any", what).into(),
+    )
+}
+
+fn address_txs(
+    tracked: &Tracked,
+    txs: &[EsploraTx],
+) -> Result<Option<(Address, Vec<DetailedTx>)>, ProviderError> {
+    if txs.is_empty() {
+        return Ok(None);
+    }
+    let address = tracked
+        .address
+        .clone()
+        .ok_or_else(|| untracked("address transactions"))?;
+    let txs = txs
+        .iter()
+        .map(EsploraTx::to_detailed)
+        .collect::<Result<_, _>>()?;
+    Ok(Some((address, txs)))
+}
+
+/// Parse the events in a message from the socket. Messages that carry no events we know of
+/// (e.g. mempool stats) produce an empty vector. Address and transaction events refer to the
+/// `tracked` address and transaction, and error if there are none.
+pub fn parse_message(message: &str, tracked: &Tracked) -> Result<Vec<MempoolEvent>, ProviderError> {
+    let message: WsMessage = serde_json::from_str(message)?;
+    let mut events = vec![];
+
+    if let Some(block) = message.block {
+        events.push(MempoolEvent::Block {
+            hash: BlockHash::from_be_hex(&block.id)?,
+            height: block.height,
+        });
+    }
+
+    if let Some((address, txs)) = address_txs(tracked, &message.address_transactions)? {
+        events.push(MempoolEvent::AddressMempool { address, txs });
+    }
+    if let Some((address, txs)) = address_txs(tracked, &message.block_transactions)? {
+        events.push(MempoolEvent::AddressConfirmed { address, txs });
+    }
+
+    match message.tx_confirmed {
+        Some(WsTxConfirmed::Txid(txid)) => {
+            events.push(MempoolEvent::TxConfirmed(TXID::from_be_hex(&txid)?))
+        }
+        Some(WsTxConfirmed::Flag(true)) => {
+            let txid = tracked.tx.ok_or_else(|| untracked("a confirmation"))?;
+            events.push(MempoolEvent::TxConfirmed(txid))
+        }
+        Some(WsTxConfirmed::Flag(false)) | None => {}
+    }
+    Ok(events)
+}
+
+/// A stream of ticks, one per socket message carrying a `MempoolEvent`. When the socket stream
+/// ends, e.g. because the connection dropped, it falls back to ticking at the polling interval.
+#[must_use = "streams do nothing unless polled"]
+pub struct PushTicks<S> {
+    socket: Option<S>,
+    fallback: Box<dyn Stream<Item = ()> + Send + Unpin>,
+}
+
+impl<S> PushTicks<S>
+where
+    S: Stream<Item = String> + Send + Unpin,
+{
+    /// Instantiate from a stream of incoming text messages.
+    pub fn new(socket: S) -> Self {
+        Self {
+            socket: Some(socket),
+            fallback: Box::new(new_interval(DEFAULT_POLL_INTERVAL)),
+        }
+    }
+
+    /// Sets the polling interval to fall back to when the socket drops
+    pub fn interval<T: Into<Duration>>(mut self, duration: T) -> Self {
+        self.fallback = Box::new(new_interval(duration.into()));
+        self
+    }
+
+    /// True if the socket has dropped, and ticks are produced by polling
+    pub fn is_polling(&self) -> bool {
+        self.socket.is_none()
+    }
+}
+
+impl<S> Stream for PushTicks<S>
+where
+    S: Stream<Item = String> + Send + Unpin,
+{
+    type Item = ();
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        while let Some(socket) = self.socket.as_mut() {
+            match futures_util::ready!(socket.poll_next_unpin(ctx)) {
+                // Unparsable and eventless messages are skipped
+                Some(message) => {
+                    if matches!(
+                        serde_json::from_str::<WsMessage>(&message),
+                        Ok(message) if message.has_events()
+                    ) {
+                        return Poll::Ready(Some(()));
+                    }
+                }
+                None => self.socket = None,
+            }
+        }
+        self.fallback.poll_next_unpin(ctx)
+    }
+}
+
+#[cfg(feature = "ws")]
+impl From<tungstenite::Error> for ProviderError {
+    fn from(e: tungstenite::Error) -> Self {
+        ProviderError::custom(false, Box::new(e))
+    }
+}
+
+/// A connection to the mempool.space WebSocket API. Streams the `MempoolEvent`s of its
+/// subscriptions, and ends when the connection closes.
+#[cfg(feature = "ws")]
+#[must_use = "streams do nothing unless polled"]
+pub struct MempoolSocket<S> {
+    ws: WebSocketStream<S>,
+    tracked: Tracked,
+    pending: VecDeque<MempoolEvent>,
+}
+
+#[cfg(feature = "ws")]
+impl MempoolSocket<async_tungstenite::async_std::ConnectStream> {
+    /// Connect to a mempool.space WebSocket endpoint, e.g. `MEMPOOL_WS_URL`
+    pub async fn connect(url: &str) -> Result<Self, ProviderError> {
+        let (ws, _) = async_tungstenite::async_std::connect_async(url).await?;
+        Ok(Self::new(ws))
+    }
+}
+
+#[cfg(feature = "ws")]
+impl<S> MempoolSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Instantiate from an established WebSocket connection
+    pub fn new(ws: WebSocketStream<S>) -> Self {
+        Self {
+            ws,
+            tracked: Default::default(),
+            pending: Default::default(),
+        }
+    }
+
+    /// Send a subscription. Tracking an address or transaction replaces the one tracked before.
+    pub async fn subscribe(&mut self, subscription: &Subscription) -> Result<(), ProviderError> {
+        self.ws
+            .send(Message::Text(subscription.to_message()))
+            .await?;
+        self.tracked.track(subscription);
+        Ok(())
+    }
+
+    /// The address and transaction tracked by this connection
+    pub fn tracked(&self) -> &Tracked {
+        &self.tracked
+    }
+
+    /// Convert to a stream of ticks, e.g. to drive `Tips` or `PendingTx`. The ticks fall back to
+    /// polling when the connection drops.
+    pub fn into_ticks(self) -> PushTicks<Pin<Box<dyn Stream<Item = String> + Send>>>
+    where
+        S: Send + 'static,
+    {
+        let messages = self
+            .ws
+            .take_while(|message| futures_util::future::ready(message.is_ok()))
+            .filter_map(|message| {
+                futures_util::future::ready(match message {
+                    Ok(Message::Text(text)) => Some(text),
+                    _ => None,
+                })
+            });
+        PushTicks::new(Box::pin(messages))
+    }
+}
+
+#[cfg(feature = "ws")]
+impl<S> Stream for MempoolSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<MempoolEvent, ProviderError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            match futures_util::ready!(self.ws.poll_next_unpin(ctx)) {
+                Some(Ok(Message::Text(text))) => {
+                    let events = parse_message(&text, &self.tracked);
+                    match events {
+                        Ok(events) => self.pending.extend(events),
+                        Err(e) => return Poll::Ready(Some(Err(e))),
+                    }
+                }
+                // pings are answered by the socket
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::{stream, FutureExt};
+
+    const HASH: &str = "00000000000000000002a7c4c1e48d76c5a37902165a270156b7a8d72728a054";
+    const TXID_BE: &str = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16";
+
+    fn tx() -> BitcoinTx {
+        LegacyTx::new(
+            2,
+            vec![BitcoinTxIn::new(
+                BitcoinOutpoint::new(TXID::from_be_hex(TXID_BE).unwrap(), 1),
+                ScriptSig::new(vec![0x51]),
+                0xffff_fffd,
+            )],
+            vec![TxOut::new(
+                Amount::from_sat(5000),
+                ScriptPubkey::new(vec![0x00, 0x14, 0x11]),
+            )],
+            0,
+        )
+        .unwrap()
+        .into()
+    }
+
+    // `tx` in the esplora format, with its prevout
+    fn render_tx() -> String {
+        let tx = tx();
+        format!(
+            r#"{{"txid":"{}","version":2,"locktime":0,"vin":[{{"txid":"{}","vout":1,"scriptsig":"51","witness":[],"sequence":{},"prevout":{{"scriptpubkey":"001411","value":6000}}}}],"vout":[{{"scriptpubkey":"001411","value":5000}}],"status":{{"confirmed":false}}}}"#,
+            tx.txid().to_be_hex(),
+            TXID_BE,
+            0xffff_fffdu32,
+        )
+    }
+
+    fn tracked() -> Tracked {
+        let mut tracked = Tracked::default();
+        tracked.track(&Subscription::Address(Address::Wpkh(
+            "bc1q2y2y5kdqy6yz9fqv0qgk2sdzpjuslz2gp4vw8x".to_owned(),
+        )));
+        tracked.track(&Subscription::Tx(TXID::from_be_hex(TXID_BE).unwrap()));
+        tracked
+    }
+
+    #[test]
+    fn it_parses_messages() {
+        let tracked = tracked();
+        let txid = TXID::from_be_hex(TXID_BE).unwrap();
+
+        let events = parse_message(
+            &format!(
+                r#"{{"block":{{"id":"{}","height":700000}},"txConfirmed":"{}"}}"#,
+                HASH, TXID_BE
+            ),
+            &tracked,
+        )
+        .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                MempoolEvent::Block {
+                    hash: BlockHash::from_be_hex(HASH).unwrap(),
+                    height: 700000
+                },
+                MempoolEvent::TxConfirmed(txid),
+            ]
+        );
+        assert_eq!(
+            parse_message(r#"{"txConfirmed":true}"#, &tracked).unwrap(),
+            vec![MempoolEvent::TxConfirmed(txid)]
+        );
+
+        let events = parse_message(
+            &format!(r#"{{"address-transactions":[{}]}}"#, render_tx()),
+            &tracked,
+        )
+        .unwrap();
+        match &events[..] {
+            [MempoolEvent::AddressMempool { address, txs }] => {
+                assert_eq!(Some(address), tracked.address.as_ref());
+                assert_eq!(txs[0].tx, tx());
+                assert_eq!(txs[0].inputs[0].prevout.as_ref().unwrap().value, 6000);
+            }
+            _ => panic!("expected address transactions"),
+        }
+
+        // address and tx events without a tracked address or tx
+        assert!(parse_message(
+            &format!(r#"{{"block-transactions":[{}]}}"#, render_tx()),
+            &Tracked::default()
+        )
+        .is_err());
+        assert!(parse_message(r#"{"txConfirmed":true}"#, &Tracked::default()).is_err());
+
+        assert!(parse_message(r#"{"mempoolInfo":{"size":1}}"#, &tracked)
+            .unwrap()
+            .is_empty());
+        assert!(parse_message("pong", &tracked).is_err());
+    }
+
+    #[test]
+    fn it_falls_back_to_polling() {
+        let messages = vec![
+            r#"{"mempoolInfo":{"size":1}}"#.to_owned(),
+            format!(r#"{{"txConfirmed":"{}"}}"#, TXID_BE),
+        ];
+        let mut ticks = PushTicks::new(stream::iter(messages)).interval(Duration::from_secs(60));
+
+        assert_eq!(ticks.next().now_or_never(), Some(Some(())));
+        assert!(!ticks.is_polling());
+        assert_eq!(ticks.next().now_or_never(), None);
+        assert!(ticks.is_polling());
+    }
+
+    #[cfg(feature = "ws")]
+    #[test]
+    fn it_streams_events_from_the_socket() {
+        use async_std::net::{TcpListener, TcpStream};
+
+        async_std::task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            let tracked = tracked();
+
+            let server = async_std::task::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = async_tungstenite::accept_async(stream).await.unwrap();
+                let mut subscriptions = vec![];
+                for _ in 0..2 {
+                    match ws.next().await.unwrap().unwrap() {
+                        Message::Text(text) => subscriptions.push(text),
+                        message => panic!("unexpected message {:?}", message),
+                    }
+                }
+                for message in [
+                    r#"{"mempoolInfo":{"size":1}}"#.to_owned(),
+                    format!(r#"{{"address-transactions":[{}]}}"#, render_tx()),
+                    format!(
+                        r#"{{"block":{{"id":"{}","height":700000}},"txConfirmed":true}}"#,
+                        HASH
+                    ),
+                ]
+                .iter()
+                {
+                    ws.send(Message::Text(message.clone())).await.unwrap();
+                }
+                ws.close(None).await.unwrap();
+                subscriptions
+            });
+
+            let stream = TcpStream::connect(&url[5..]).await.unwrap();
+            let (ws, _) = async_tungstenite::client_async(url.as_str(), stream)
+                .await
+                .unwrap();
+            let mut socket = MempoolSocket::new(ws);
+            socket
+                .subscribe(&Subscription::Address(tracked.address.clone().unwrap()))
+                .await
+                .unwrap();
+            socket
+                .subscribe(&Subscription::Tx(tracked.tx.unwrap()))
+                .await
+                .unwrap();
+            assert_eq!(socket.tracked(), &tracked);
+
+            let events: Vec<_> = socket.map(Result::unwrap).collect().await;
+            assert_eq!(
+                server.await,
+                vec![
+                    r#"{"track-address":"bc1q2y2y5kdqy6yz9fqv0qgk2sdzpjuslz2gp4vw8x"}"#.to_owned(),
+                    format!(r#"{{"track-tx":"{}"}}"#, TXID_BE),
+                ]
+            );
+
+            assert_eq!(events.len(), 3);
+            match &events[0] {
+                MempoolEvent::AddressMempool { address, txs } => {
+                    assert_eq!(Some(address), tracked.address.as_ref());
+                    assert_eq!(txs[0].tx, tx());
+                }
+                event => panic!("unexpected event {:?}", event),
+            }
+            assert_eq!(
+                events[1..],
+                [
+                    MempoolEvent::Block {
+                        hash: BlockHash::from_be_hex(HASH).unwrap(),
+                        height: 700000
+                    },
+                    MempoolEvent::TxConfirmed(tracked.tx.unwrap()),
+                ]
+            );
+        });
+    }
+}
@@ -0,0 +1,157 @@
+//! Composable provider middleware.
+//!
+//! A `Layer` wraps a provider in another provider that adds some behavior, e.g. retries,
+//! caching, rate limiting, or metrics. Because layers are generic over the wrapped provider,
+//! they compose with any `BtcProvider`, including third-party providers and third-party layers.
+//!
+//! `ProviderBuilder` stacks layers, and applies them to a provider:
+//!
+//! ```no_run
+//! # #[cfg(feature = "esplora")]
+//! # {
+//! use bitcoins_provider::{esplora::EsploraProvider, layer::ProviderBuilder, retry::RetryLayer};
+//!
+//! // requests pass through the metrics first, then the retries, then the cache
+//! let provider = ProviderBuilder::new()
+//!     .metrics()
+//!     .layer(RetryLayer::default().max_retries(5))
+//!     .cache()
+//!     .rate_limit(10.0, 20)
+//!     .provider(EsploraProvider::default());
+//! # }
+//! ```
+
+use crate::{
+    metrics::MetricsLayer, provider::BtcProvider, provider::CachingLayer, ratelimit::RateLimitLayer,
+};
+
+/// Wraps a provider in middleware
+pub trait Layer<P: BtcProvider> {
+    /// The wrapped provider
+    type Provider: BtcProvider;
+
+    /// Wrap a provider
+    fn layer(&self, inner: P) -> Self::Provider;
+}
+
+/// A layer that does nothing
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Identity;
+
+impl<P: BtcProvider> Layer<P> for Identity {
+    type Provider = P;
+
+    fn layer(&self, inner: P) -> P {
+        inner
+    }
+}
+
+/// Two layers. `inner` wraps the provider, and `outer` wraps the result
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Stack<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+impl<Inner, Outer> Stack<Inner, Outer> {
+    /// Instantiate a stack from two layers
+    pub fn new(inner: Inner, outer: Outer) -> Self {
+        Self { inner, outer }
+    }
+}
+
+impl<P, Inner, Outer> Layer<P> for Stack<Inner, Outer>
+where
+    P: BtcProvider,
+    Inner: Layer<P>,
+    Outer: Layer<Inner::Provider>,
+{
+    type Provider = Outer::Provider;
+
+    fn layer(&self, inner: P) -> Self::Provider {
+        self.outer.layer(self.inner.layer(inner))
+    }
+}
+
+/// Stacks layers, and applies them to providers.
+///
+/// Layers added first are outermost, i.e. they see each request first, and each response last.
+#[derive(Clone, Debug, Default)]
+pub struct ProviderBuilder<L = Identity> {
+    layer: L,
+}
+
+impl ProviderBuilder {
+    /// Instantiate a builder with no layers
+    pub fn new() -> Self {
+        Self { layer: Identity }
+    }
+}
+
+impl<L> ProviderBuilder<L> {
+    /// Add a layer. It wraps all layers added after it
+    pub fn layer<T>(self, layer: T) -> ProviderBuilder<Stack<T, L>> {
+        ProviderBuilder {
+            layer: Stack::new(layer, self.layer),
+        }
+    }
+
+    /// Add a `CachingProvider` layer
+    pub fn cache(self) -> ProviderBuilder<Stack<CachingLayer, L>> {
+        self.layer(CachingLayer)
+    }
+
+    /// Add a `RateLimitedProvider` layer, allowing `per_second` requests per second, with bursts
+    /// of up to `burst` requests
+    pub fn rate_limit(
+        self,
+        per_second: f64,
+        burst: usize,
+    ) -> ProviderBuilder<Stack<RateLimitLayer, L>> {
+        self.layer(RateLimitLayer::new(per_second, burst))
+    }
+
+    /// Add a `MetricsProvider` layer
+    pub fn metrics(self) -> ProviderBuilder<Stack<MetricsLayer, L>> {
+        self.layer(MetricsLayer::default())
+    }
+
+    /// Return the stacked layers
+    pub fn into_inner(self) -> L {
+        self.layer
+    }
+
+    /// Wrap a provider in the stacked layers
+    pub fn provider<P>(&self, provider: P) -> L::Provider
+    where
+        P: BtcProvider,
+        L: Layer<P>,
+    {
+        self.layer.layer(provider)
+    }
+}
+
+#[cfg(all(test, feature = "esplora"))]
+mod test {
+    use super::*;
+    use crate::{
+        esplora::EsploraProvider, metrics::MetricsProvider, provider::CachingProvider,
+        ratelimit::RateLimitedProvider, retry::RetryLayer, retry::RetryingProvider,
+    };
+
+    #[test]
+    fn it_stacks_layers_outermost_first() {
+        let provider: MetricsProvider<
+            RetryingProvider<CachingProvider<RateLimitedProvider<EsploraProvider>>>,
+        > = ProviderBuilder::new()
+            .metrics()
+            .layer(RetryLayer::default().max_retries(5))
+            .cache()
+            .rate_limit(10.0, 20)
+            .provider(EsploraProvider::default());
+        assert!(provider.metrics().is_empty());
+
+        let provider: EsploraProvider = ProviderBuilder::new().provider(EsploraProvider::default());
+        let _: &dyn BtcProvider = &provider;
+    }
+}
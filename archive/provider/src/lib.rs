@@ -20,17 +20,54 @@ pub mod watcher;
 /// Chain watcher
 pub mod chain;
 
+/// Retrying provider
+pub mod retry;
+
+/// Failover and quorum provider
+pub mod multi;
+
+/// Composable provider middleware
+pub mod layer;
+
+/// Rate limiting provider
+pub mod ratelimit;
+
+/// Request metrics provider
+pub mod metrics;
+
+/// Header chain validation
+pub mod spv;
+
+/// Persistent chain storage
+pub mod store;
+
+/// UTXO tracking for watched scripts
+pub mod tracker;
+
+/// Address subscriptions
+pub mod subscription;
+
+/// Zero-confirmation payment risk scoring
+pub mod zeroconf;
+
 #[doc(hidden)]
-#[cfg(any(feature = "rpc", feature = "esplora"))]
+#[cfg(any(feature = "rpc", feature = "esplora", feature = "blockbook"))]
 pub mod reqwest_utils;
 
 /// Utils
 pub mod utils;
 
+#[cfg(test)]
+pub(crate) mod test_utils;
+
 /// EsploraProvider
 #[cfg(feature = "esplora")]
 pub mod esplora;
 
+/// BlockbookProvider
+#[cfg(feature = "blockbook")]
+pub mod blockbook;
+
 /// Local (or remote) node RPC
 #[cfg(feature = "rpc")]
 pub mod rpc;
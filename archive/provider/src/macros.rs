@@ -44,3 +44,36 @@ macro_rules! esplora_if_found {
         result.unwrap()
     }};
 }
+
+// Blockbook responds to unknown objects with an error object, which fails to parse as the
+// expected type.
+#[cfg(feature = "blockbook")]
+macro_rules! blockbook_if_found {
+    ($func:expr) => {{
+        let result = $func.map_err(Into::<crate::provider::ProviderError>::into);
+        match result {
+            Ok(value) => value,
+            Err(e) if e.from_parsing() => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    }};
+}
+
+// Emits request metrics via the `metrics` facade when the `metrics` feature is enabled. The
+// body runs in an async block, so `?` and `return` exit the block, not the enclosing function
+#[cfg(feature = "metrics")]
+macro_rules! instrument {
+    ($provider:expr, $method:expr, $body:block) => {{
+        let start = instant::Instant::now();
+        let result: Result<_, ProviderError> = async $body.await;
+        crate::metrics::emit($provider, $method, start.elapsed(), result.as_ref().err());
+        result
+    }};
+}
+
+#[cfg(not(feature = "metrics"))]
+macro_rules! instrument {
+    ($provider:expr, $method:expr, $body:block) => {
+        $body
+    };
+}
@@ -0,0 +1,494 @@
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bitcoins::{
+    enc::Address,
+    hashes::{BlockHash, TXID},
+    types::*,
+};
+use coins_core::prelude::*;
+use instant::Instant;
+
+use crate::{
+    layer::Layer,
+    provider::{BtcProvider, ErrorCategory, PollingBtcProvider, ProviderError},
+    types::{DetailedTx, FeeHistogram, FeeRate, MempoolEntry, RawHeader, SpendingInput},
+};
+
+/// A callback that observes each request: the method name, its latency, and its error, if any.
+/// Useful for logging, or for forwarding to a metrics backend
+pub type Observer = Arc<dyn Fn(&str, Duration, Option<&ProviderError>) + Send + Sync>;
+
+/// Request counters for a single method
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MethodMetrics {
+    /// The number of requests
+    pub requests: u64,
+    /// The number of requests that returned an error
+    pub errors: u64,
+    /// The number of errors in each category
+    pub error_categories: HashMap<ErrorCategory, u64>,
+    /// The total time spent on requests
+    pub latency: Duration,
+}
+
+impl MethodMetrics {
+    /// The mean time spent on a request
+    pub fn mean_latency(&self) -> Duration {
+        if self.requests == 0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(self.latency.as_secs_f64() / self.requests as f64)
+        }
+    }
+}
+
+/// Records request counts, errors, and latency, per method, and notifies an optional
+/// `Observer` of each request. Used by `MetricsProvider`
+#[derive(Default)]
+pub(crate) struct Recorder {
+    metrics: Mutex<HashMap<String, MethodMetrics>>,
+    observer: Option<Observer>,
+}
+
+impl std::fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("metrics", &self.metrics)
+            .field("observer", &self.observer.is_some())
+            .finish()
+    }
+}
+
+impl Recorder {
+    /// Instantiate a recorder that notifies an observer
+    pub(crate) fn with_observer(observer: Option<Observer>) -> Self {
+        Self {
+            metrics: Default::default(),
+            observer,
+        }
+    }
+
+    /// Notify an observer of each request, replacing any previous observer
+    pub(crate) fn set_observer(&mut self, observer: Observer) {
+        self.observer = Some(observer);
+    }
+
+    /// Return a snapshot of the metrics, keyed by method name. Methods that have not been called
+    /// are absent
+    pub(crate) fn snapshot(&self) -> HashMap<String, MethodMetrics> {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    /// Clear the metrics
+    pub(crate) fn reset(&self) {
+        self.metrics.lock().unwrap().clear()
+    }
+
+    /// Run a request, and record its outcome
+    pub(crate) async fn record<R, Fut>(&self, method: &str, fut: Fut) -> Result<R, ProviderError>
+    where
+        Fut: Future<Output = Result<R, ProviderError>>,
+    {
+        let start = Instant::now();
+        let result = fut.await;
+        let elapsed = start.elapsed();
+        {
+            let mut metrics = self.metrics.lock().unwrap();
+            let entry = metrics.entry(method.to_owned()).or_default();
+            entry.requests += 1;
+            entry.latency += elapsed;
+            if let Err(e) = &result {
+                entry.errors += 1;
+                *entry.error_categories.entry(e.category()).or_default() += 1;
+            }
+        }
+        if let Some(observer) = &self.observer {
+            observer(method, elapsed, result.as_ref().err());
+        }
+        result
+    }
+}
+
+/// Emit a request's count, latency and error category via the `metrics` facade. The built-in
+/// providers call this for each request when the `metrics` feature is enabled. Metrics are
+/// labeled with the provider (e.g. `esplora` or `rpc`) and the method:
+///
+/// - `bitcoins_provider_requests_total`, a counter
+/// - `bitcoins_provider_request_duration_seconds`, a histogram
+/// - `bitcoins_provider_errors_total`, a counter, additionally labeled with the error category
+#[cfg(feature = "metrics")]
+pub(crate) fn emit(
+    provider: &'static str,
+    method: &str,
+    elapsed: Duration,
+    error: Option<&ProviderError>,
+) {
+    let method = method.to_owned();
+    ::metrics::counter!(
+        "bitcoins_provider_requests_total",
+        "provider" => provider,
+        "method" => method.clone()
+    )
+    .increment(1);
+    ::metrics::histogram!(
+        "bitcoins_provider_request_duration_seconds",
+        "provider" => provider,
+        "method" => method.clone()
+    )
+    .record(elapsed.as_secs_f64());
+    if let Some(e) = error {
+        ::metrics::counter!(
+            "bitcoins_provider_errors_total",
+            "provider" => provider,
+            "method" => method,
+            "category" => e.category().as_str()
+        )
+        .increment(1);
+    }
+}
+
+/// A provider that records request counts, errors, and latency, per method. An optional
+/// `Observer` is notified of each request.
+pub struct MetricsProvider<T: BtcProvider> {
+    provider: T,
+    recorder: Recorder,
+}
+
+impl<T: BtcProvider> From<T> for MetricsProvider<T> {
+    fn from(provider: T) -> Self {
+        Self {
+            provider,
+            recorder: Default::default(),
+        }
+    }
+}
+
+impl<T> Default for MetricsProvider<T>
+where
+    T: BtcProvider + Default,
+{
+    fn default() -> Self {
+        T::default().into()
+    }
+}
+
+impl<T: BtcProvider> MetricsProvider<T> {
+    /// Notify an observer of each request
+    pub fn observer<F>(mut self, observer: F) -> Self
+    where
+        F: Fn(&str, Duration, Option<&ProviderError>) + Send + Sync + 'static,
+    {
+        self.recorder.set_observer(Arc::new(observer));
+        self
+    }
+
+    /// Return a snapshot of the metrics, keyed by method name. Methods that have not been called
+    /// are absent
+    pub fn metrics(&self) -> HashMap<String, MethodMetrics> {
+        self.recorder.snapshot()
+    }
+
+    /// Clear the metrics
+    pub fn reset(&self) {
+        self.recorder.reset()
+    }
+
+    /// Return a reference to the wrapped provider
+    pub fn inner(&self) -> &T {
+        &self.provider
+    }
+
+    async fn record<R, Fut>(&self, method: &str, fut: Fut) -> Result<R, ProviderError>
+    where
+        Fut: Future<Output = Result<R, ProviderError>>,
+    {
+        self.recorder.record(method, fut).await
+    }
+}
+
+/// A layer that wraps providers in a `MetricsProvider`. Each wrapped provider records its own
+/// metrics, and shares the observer
+#[derive(Clone, Default)]
+pub struct MetricsLayer {
+    observer: Option<Observer>,
+}
+
+impl MetricsLayer {
+    /// Notify an observer of each request
+    pub fn observer<F>(mut self, observer: F) -> Self
+    where
+        F: Fn(&str, Duration, Option<&ProviderError>) + Send + Sync + 'static,
+    {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+}
+
+impl<T: BtcProvider> Layer<T> for MetricsLayer {
+    type Provider = MetricsProvider<T>;
+
+    fn layer(&self, inner: T) -> Self::Provider {
+        MetricsProvider {
+            provider: inner,
+            recorder: Recorder::with_observer(self.observer.clone()),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T> BtcProvider for MetricsProvider<T>
+where
+    T: BtcProvider,
+{
+    async fn tip_hash(&self) -> Result<BlockHash, ProviderError> {
+        self.record("tip_hash", self.provider.tip_hash()).await
+    }
+
+    async fn tip_height(&self) -> Result<usize, ProviderError> {
+        self.record("tip_height", self.provider.tip_height()).await
+    }
+
+    async fn in_best_chain(&self, digest: BlockHash) -> Result<bool, ProviderError> {
+        self.record("in_best_chain", self.provider.in_best_chain(digest))
+            .await
+    }
+
+    async fn get_digest_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<BlockHash>, ProviderError> {
+        self.record(
+            "get_digest_range",
+            self.provider.get_digest_range(start, headers),
+        )
+        .await
+    }
+
+    async fn get_raw_header_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<RawHeader>, ProviderError> {
+        self.record(
+            "get_raw_header_range",
+            self.provider.get_raw_header_range(start, headers),
+        )
+        .await
+    }
+
+    async fn get_raw_header(&self, digest: BlockHash) -> Result<Option<RawHeader>, ProviderError> {
+        self.record("get_raw_header", self.provider.get_raw_header(digest))
+            .await
+    }
+
+    async fn get_height_of(&self, digest: BlockHash) -> Result<Option<usize>, ProviderError> {
+        self.record("get_height_of", self.provider.get_height_of(digest))
+            .await
+    }
+
+    async fn get_confirmed_height(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.record(
+            "get_confirmed_height",
+            self.provider.get_confirmed_height(txid),
+        )
+        .await
+    }
+
+    async fn get_confs(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.record("get_confs", self.provider.get_confs(txid))
+            .await
+    }
+
+    async fn get_tx(&self, txid: TXID) -> Result<Option<BitcoinTx>, ProviderError> {
+        self.record("get_tx", self.provider.get_tx(txid)).await
+    }
+
+    async fn get_tx_detailed(&self, txid: TXID) -> Result<Option<DetailedTx>, ProviderError> {
+        self.record("get_tx_detailed", self.provider.get_tx_detailed(txid))
+            .await
+    }
+
+    async fn get_block_full(&self, digest: BlockHash) -> Result<Option<Block>, ProviderError> {
+        self.record("get_block_full", self.provider.get_block_full(digest))
+            .await
+    }
+
+    async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError> {
+        self.record("broadcast", self.provider.broadcast(tx)).await
+    }
+
+    async fn get_fee_histogram(&self) -> Result<FeeHistogram, ProviderError> {
+        self.record("get_fee_histogram", self.provider.get_fee_histogram())
+            .await
+    }
+
+    async fn estimate_fee(&self, target_blocks: usize) -> Result<FeeRate, ProviderError> {
+        self.record("estimate_fee", self.provider.estimate_fee(target_blocks))
+            .await
+    }
+
+    async fn get_mempool_entry(&self, txid: TXID) -> Result<Option<MempoolEntry>, ProviderError> {
+        self.record("get_mempool_entry", self.provider.get_mempool_entry(txid))
+            .await
+    }
+
+    async fn get_outspend(&self, outpoint: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError> {
+        self.record("get_outspend", self.provider.get_outspend(outpoint))
+            .await
+    }
+
+    async fn get_spending_input(
+        &self,
+        outpoint: BitcoinOutpoint,
+    ) -> Result<Option<SpendingInput>, ProviderError> {
+        self.record(
+            "get_spending_input",
+            self.provider.get_spending_input(outpoint),
+        )
+        .await
+    }
+
+    async fn get_utxos_by_address(&self, address: &Address) -> Result<Vec<Utxo>, ProviderError> {
+        self.record(
+            "get_utxos_by_address",
+            self.provider.get_utxos_by_address(address),
+        )
+        .await
+    }
+
+    async fn get_merkle(
+        &self,
+        txid: TXID,
+    ) -> Result<Option<(usize, Vec<Hash256Digest>)>, ProviderError> {
+        self.record("get_merkle", self.provider.get_merkle(txid))
+            .await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T> PollingBtcProvider for MetricsProvider<T>
+where
+    T: PollingBtcProvider,
+{
+    fn interval(&self) -> Duration {
+        self.provider.interval()
+    }
+    fn set_interval(&mut self, interval: usize) {
+        self.provider.set_interval(interval)
+    }
+}
+
+#[cfg(all(test, feature = "esplora"))]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn it_records_requests() {
+        let mut rt = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let observed = Arc::new(AtomicUsize::new(0));
+        let counter = observed.clone();
+        let provider = MetricsProvider::from(crate::esplora::EsploraProvider::default()).observer(
+            move |_, _, _| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            },
+        );
+
+        rt.block_on(async {
+            let _ = provider.record("tip_height", async { Ok(1) }).await;
+            let _ = provider
+                .record("tip_height", async {
+                    Err::<usize, _>(ProviderError::Unsupported("nope".to_owned()))
+                })
+                .await;
+            let _ = provider.record("get_tx", async { Ok(()) }).await;
+        });
+
+        let metrics = provider.metrics();
+        assert_eq!(metrics["tip_height"].requests, 2);
+        assert_eq!(metrics["tip_height"].errors, 1);
+        assert_eq!(
+            metrics["tip_height"].error_categories[&ErrorCategory::Unsupported],
+            1
+        );
+        assert_eq!(metrics["get_tx"].requests, 1);
+        assert_eq!(metrics["get_tx"].errors, 0);
+        assert_eq!(observed.load(Ordering::SeqCst), 3);
+
+        provider.reset();
+        assert!(provider.metrics().is_empty());
+    }
+
+    #[test]
+    fn it_averages_latency() {
+        let metrics = MethodMetrics {
+            requests: 3,
+            latency: Duration::from_millis(100),
+            ..Default::default()
+        };
+        assert_eq!(metrics.mean_latency().as_micros(), 33_333);
+        assert_eq!(
+            MethodMetrics::default().mean_latency(),
+            Duration::from_secs(0)
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn it_emits_facade_metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        ::metrics::with_local_recorder(&recorder, || {
+            let mut rt = tokio::runtime::Builder::new()
+                .basic_scheduler()
+                .build()
+                .unwrap();
+            rt.block_on(async {
+                let _: Result<usize, _> = instrument!("esplora", "tip_height", { Ok(1) });
+                let _: Result<usize, _> = instrument!("esplora", "tip_height", {
+                    Err(ProviderError::Unsupported("nope".to_owned()))
+                });
+            });
+        });
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        let find = |name: &str| {
+            snapshot
+                .iter()
+                .find(|(key, _, _, _)| key.key().name() == name)
+                .map(|(key, _, _, value)| (key.key(), value))
+                .unwrap()
+        };
+
+        let (key, requests) = find("bitcoins_provider_requests_total");
+        assert_eq!(requests, &DebugValue::Counter(2));
+        assert!(key
+            .labels()
+            .any(|label| label.key() == "method" && label.value() == "tip_height"));
+
+        match find("bitcoins_provider_request_duration_seconds").1 {
+            DebugValue::Histogram(latencies) => assert_eq!(latencies.len(), 2),
+            _ => panic!("expected a histogram"),
+        }
+
+        let (key, errors) = find("bitcoins_provider_errors_total");
+        assert_eq!(errors, &DebugValue::Counter(1));
+        assert!(key
+            .labels()
+            .any(|label| label.key() == "category" && label.value() == "unsupported"));
+    }
+}
@@ -0,0 +1,348 @@
+use async_trait::async_trait;
+use futures_util::{future::join_all, FutureExt};
+use std::{future::Future, time::Duration};
+
+use bitcoins::{
+    enc::Address,
+    hashes::{BlockHash, TXID},
+    types::*,
+};
+use coins_core::prelude::*;
+
+use crate::{
+    provider::{BtcProvider, PollingBtcProvider, ProviderError},
+    types::{DetailedTx, FeeHistogram, FeeRate, MempoolEntry, RawHeader, SpendingInput},
+};
+
+// Broadcast to a single provider, treating an already known transaction as a success
+async fn broadcast_one(provider: &dyn BtcProvider, tx: BitcoinTx) -> Result<TXID, ProviderError> {
+    let txid = tx.txid();
+    match provider.broadcast(tx).await {
+        Err(e) if e.is_already_known() => Ok(txid),
+        result => result,
+    }
+}
+
+/// Broadcast a transaction through several providers concurrently, improving propagation.
+/// Returns the result of each provider, in order. Providers that already know the transaction
+/// report success.
+pub async fn broadcast_all(
+    providers: &[Box<dyn BtcProvider>],
+    tx: BitcoinTx,
+) -> Vec<Result<TXID, ProviderError>> {
+    join_all(
+        providers
+            .iter()
+            .map(|provider| broadcast_one(provider.as_ref(), tx.clone())),
+    )
+    .await
+}
+
+/// How a `MultiProvider` combines its providers.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MultiStrategy {
+    /// Query providers in order. On error, try the next one.
+    Failover,
+    /// Query all providers for the chain tip and its height, and require this many matching
+    /// answers. Other requests fail over. This detects a backend lying about the chain.
+    ///
+    /// Under either strategy, transactions are broadcast through all providers. See
+    /// `broadcast_all`.
+    Quorum(usize),
+}
+
+/// A provider that wraps several providers, so that requests survive one flaky backend, or
+/// detect a lying one. See `MultiStrategy`.
+pub struct MultiProvider {
+    providers: Vec<Box<dyn BtcProvider>>,
+    strategy: MultiStrategy,
+    interval: Duration,
+}
+
+impl MultiProvider {
+    /// Instantiate a failover provider. Providers are queried in order.
+    pub fn new(providers: Vec<Box<dyn BtcProvider>>) -> Self {
+        Self {
+            providers,
+            strategy: MultiStrategy::Failover,
+            interval: crate::DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets the strategy. Fails if a quorum is 0, or larger than the number of providers, as it
+    /// could never be reached.
+    pub fn strategy(mut self, strategy: MultiStrategy) -> Result<Self, ProviderError> {
+        if let MultiStrategy::Quorum(needed) = strategy {
+            if needed == 0 || needed > self.providers.len() {
+                return Err(ProviderError::Unsupported(format!(
+                    "Quorum of {} with {} providers",
+                    needed,
+                    self.providers.len()
+                )));
+            }
+        }
+        self.strategy = strategy;
+        Ok(self)
+    }
+
+    /// Return a reference to the wrapped providers
+    pub fn providers(&self) -> &[Box<dyn BtcProvider>] {
+        &self.providers
+    }
+
+    // Return the first successful response. If all providers fail, return the last error.
+    async fn failover<'a, R, F, Fut>(&'a self, f: F) -> Result<R, ProviderError>
+    where
+        F: Fn(&'a dyn BtcProvider) -> Fut,
+        Fut: Future<Output = Result<R, ProviderError>>,
+    {
+        let (last, rest) = match self.providers.split_last() {
+            Some(split) => split,
+            None => {
+                return Err(ProviderError::Unsupported(
+                    "MultiProvider has no providers".to_owned(),
+                ))
+            }
+        };
+        for provider in rest.iter() {
+            if let Ok(r) = f(provider.as_ref()).await {
+                return Ok(r);
+            }
+        }
+        f(last.as_ref()).await
+    }
+
+    // Query all providers, and return the first response given by at least `needed` of them
+    async fn quorum<'a, R, F, Fut>(&'a self, needed: usize, f: F) -> Result<R, ProviderError>
+    where
+        R: PartialEq,
+        F: Fn(&'a dyn BtcProvider) -> Fut,
+        Fut: Future<Output = Result<R, ProviderError>>,
+    {
+        let responses: Vec<R> =
+            join_all(self.providers.iter().map(|p| f(p.as_ref()).map(Result::ok)))
+                .await
+                .into_iter()
+                .flatten()
+                .collect();
+
+        let position = (0..responses.len())
+            .find(|&i| responses.iter().filter(|r| **r == responses[i]).count() >= needed);
+        match position {
+            Some(i) => Ok(responses.into_iter().nth(i).unwrap()),
+            None => Err(ProviderError::NoQuorum {
+                needed,
+                responses: responses.len(),
+            }),
+        }
+    }
+
+    async fn agreed<'a, R, F, Fut>(&'a self, f: F) -> Result<R, ProviderError>
+    where
+        R: PartialEq,
+        F: Fn(&'a dyn BtcProvider) -> Fut,
+        Fut: Future<Output = Result<R, ProviderError>>,
+    {
+        match self.strategy {
+            MultiStrategy::Failover => self.failover(f).await,
+            MultiStrategy::Quorum(needed) => self.quorum(needed, f).await,
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl BtcProvider for MultiProvider {
+    async fn tip_hash(&self) -> Result<BlockHash, ProviderError> {
+        self.agreed(|p| p.tip_hash()).await
+    }
+
+    async fn tip_height(&self) -> Result<usize, ProviderError> {
+        self.agreed(|p| p.tip_height()).await
+    }
+
+    async fn in_best_chain(&self, digest: BlockHash) -> Result<bool, ProviderError> {
+        self.failover(|p| p.in_best_chain(digest)).await
+    }
+
+    async fn get_digest_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<BlockHash>, ProviderError> {
+        self.failover(|p| p.get_digest_range(start, headers)).await
+    }
+
+    async fn get_raw_header_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<RawHeader>, ProviderError> {
+        self.failover(|p| p.get_raw_header_range(start, headers))
+            .await
+    }
+
+    async fn get_raw_header(&self, digest: BlockHash) -> Result<Option<RawHeader>, ProviderError> {
+        self.failover(|p| p.get_raw_header(digest)).await
+    }
+
+    async fn get_height_of(&self, digest: BlockHash) -> Result<Option<usize>, ProviderError> {
+        self.failover(|p| p.get_height_of(digest)).await
+    }
+
+    async fn get_confirmed_height(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.failover(|p| p.get_confirmed_height(txid)).await
+    }
+
+    async fn get_confs(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.failover(|p| p.get_confs(txid)).await
+    }
+
+    async fn get_tx(&self, txid: TXID) -> Result<Option<BitcoinTx>, ProviderError> {
+        self.failover(|p| p.get_tx(txid)).await
+    }
+
+    async fn get_tx_detailed(&self, txid: TXID) -> Result<Option<DetailedTx>, ProviderError> {
+        self.failover(|p| p.get_tx_detailed(txid)).await
+    }
+
+    async fn get_block_full(&self, digest: BlockHash) -> Result<Option<Block>, ProviderError> {
+        self.failover(|p| p.get_block_full(digest)).await
+    }
+
+    async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError> {
+        // Errors are stringified, as `ProviderError` is not `Send`
+        let results = join_all(self.providers.iter().map(|provider| {
+            broadcast_one(provider.as_ref(), tx.clone()).map(|r| r.map_err(|e| e.to_string()))
+        }))
+        .await;
+
+        let mut errors = vec![];
+        for result in results.into_iter() {
+            match result {
+                Ok(txid) => return Ok(txid),
+                Err(e) => errors.push(e),
+            }
+        }
+        Err(ProviderError::BroadcastFailed(errors))
+    }
+
+    async fn get_fee_histogram(&self) -> Result<FeeHistogram, ProviderError> {
+        self.failover(|p| p.get_fee_histogram()).await
+    }
+
+    async fn estimate_fee(&self, target_blocks: usize) -> Result<FeeRate, ProviderError> {
+        self.failover(|p| p.estimate_fee(target_blocks)).await
+    }
+
+    async fn get_mempool_entry(&self, txid: TXID) -> Result<Option<MempoolEntry>, ProviderError> {
+        self.failover(|p| p.get_mempool_entry(txid)).await
+    }
+
+    async fn get_outspend(&self, outpoint: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError> {
+        self.failover(|p| p.get_outspend(outpoint)).await
+    }
+
+    async fn get_spending_input(
+        &self,
+        outpoint: BitcoinOutpoint,
+    ) -> Result<Option<SpendingInput>, ProviderError> {
+        self.failover(|p| p.get_spending_input(outpoint)).await
+    }
+
+    async fn get_utxos_by_address(&self, address: &Address) -> Result<Vec<Utxo>, ProviderError> {
+        self.failover(|p| p.get_utxos_by_address(address)).await
+    }
+
+    async fn get_merkle(
+        &self,
+        txid: TXID,
+    ) -> Result<Option<(usize, Vec<Hash256Digest>)>, ProviderError> {
+        self.failover(|p| p.get_merkle(txid)).await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl PollingBtcProvider for MultiProvider {
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn set_interval(&mut self, interval: usize) {
+        self.interval = Duration::from_secs(interval as u64);
+    }
+}
+
+#[cfg(all(test, feature = "esplora"))]
+mod test {
+    use super::*;
+    use crate::esplora::EsploraProvider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn it_fails_over_and_counts_votes() {
+        let mut rt = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let providers: Vec<Box<dyn BtcProvider>> = (0..4)
+            .map(|_| Box::new(EsploraProvider::default()) as Box<dyn BtcProvider>)
+            .collect();
+        let multi = MultiProvider::new(providers);
+
+        // The nth call is answered with the nth response. Calls are made in provider order
+        let answers = [Err(()), Ok(2usize), Ok(3), Ok(2)];
+        let calls = AtomicUsize::new(0);
+        let answer = |_: &dyn BtcProvider| {
+            let i = calls.fetch_add(1, Ordering::SeqCst);
+            let answer = answers[i].map_err(|_| ProviderError::custom(false, "down".into()));
+            async move { answer }
+        };
+
+        assert_eq!(rt.block_on(multi.failover(answer)).unwrap(), 2);
+        calls.store(0, Ordering::SeqCst);
+        assert_eq!(rt.block_on(multi.quorum(2, answer)).unwrap(), 2);
+        calls.store(0, Ordering::SeqCst);
+        match rt.block_on(multi.quorum(3, answer)) {
+            Err(ProviderError::NoQuorum { needed, responses }) => {
+                assert_eq!(needed, 3);
+                assert_eq!(responses, 3);
+            }
+            _ => panic!("expected no quorum"),
+        }
+    }
+
+    #[test]
+    fn it_rejects_unreachable_quorums() {
+        let providers = || -> Vec<Box<dyn BtcProvider>> {
+            (0..3)
+                .map(|_| Box::new(EsploraProvider::default()) as Box<dyn BtcProvider>)
+                .collect()
+        };
+        for &needed in [0, 4].iter() {
+            assert!(MultiProvider::new(providers())
+                .strategy(MultiStrategy::Quorum(needed))
+                .is_err());
+        }
+        let multi = MultiProvider::new(providers())
+            .strategy(MultiStrategy::Quorum(3))
+            .unwrap();
+        assert_eq!(multi.strategy, MultiStrategy::Quorum(3));
+        assert!(MultiProvider::new(vec![])
+            .strategy(MultiStrategy::Failover)
+            .is_ok());
+    }
+
+    #[test]
+    fn it_treats_known_txns_as_broadcast() {
+        let known = ProviderError::Rejected(
+            r#"sendrawtransaction RPC error: {"code":-26,"message":"txn-already-in-mempool"}"#
+                .to_owned(),
+        );
+        assert!(known.is_already_known());
+        let invalid = ProviderError::Rejected("bad-txns-inputs-missingorspent".to_owned());
+        assert!(!invalid.is_already_known());
+        assert!(!ProviderError::Unsupported("nope".to_owned()).is_already_known());
+    }
+}
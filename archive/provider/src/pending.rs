@@ -5,13 +5,19 @@ use std::{
 };
 
 use futures_core::stream::Stream;
-use futures_util::stream::StreamExt;
+use futures_timer::Delay;
+use futures_util::{
+    future::{select, Either},
+    pin_mut,
+    stream::StreamExt,
+};
 use pin_project::pin_project;
+use thiserror::Error;
 
 use bitcoins::prelude::*;
 
 use crate::{
-    provider::BtcProvider,
+    provider::{BtcProvider, ProviderError},
     utils::{new_interval, StreamLast},
     ProviderFut, DEFAULT_POLL_INTERVAL,
 };
@@ -20,12 +26,45 @@ enum PendingTxStates<'a> {
     Broadcasting(ProviderFut<'a, TXID>),
     Paused,
     WaitingConfFut(ProviderFut<'a, Option<usize>>),
+    // Checking whether a conflicting tx spends our first input
+    CheckingReplacement(ProviderFut<'a, Option<TXID>>),
+    Rebroadcasting(ProviderFut<'a, TXID>),
     // Stream has failed and should not be polled again
     Dropped,
     // Stream has completed, and should not be polled again
     Completed,
 }
 
+/// When a `PendingTx` re-submits a transaction that the provider no longer knows, e.g. because
+/// it expired from the mempool
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RebroadcastPolicy {
+    /// The number of consecutive polls that must not find the tx before it is re-submitted
+    pub after_polls: usize,
+    /// The maximum number of re-submissions. After this, the tx is considered evicted
+    pub max_attempts: usize,
+}
+
+impl Default for RebroadcastPolicy {
+    fn default() -> Self {
+        Self {
+            after_polls: 3,
+            max_attempts: 5,
+        }
+    }
+}
+
+/// How a `PendingTx` stream ended
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PendingTxOutcome {
+    /// The tx received the wanted confirmations
+    Confirmed,
+    /// The provider no longer knows the tx, and it was not (or could no longer be) re-submitted
+    Evicted,
+    /// A conflicting tx with this ID spends one of the tx's inputs
+    Replaced(TXID),
+}
+
 /// A pending transaction. Periodically polls the API to see if it has been confirmed.
 ///
 /// If the transaction is confirmed, the stream will yield the number of confirmations it has
@@ -40,6 +79,10 @@ enum PendingTxStates<'a> {
 /// `>= self.confs_wanted` confirmations, the stream will finish.
 ///
 /// To get a future yielding a single event when the stream ends, use `StreamLast::last()`
+///
+/// If the provider stops reporting the tx, the stream yields the tx as an error, and ends. Set
+/// a `RebroadcastPolicy` with `rebroadcast` to re-submit it instead. After the stream ends,
+/// `outcome` reports whether the tx was confirmed, evicted or replaced.
 #[pin_project(project = PendingTxProj)]
 #[must_use = "streams do nothing unless polled"]
 pub struct PendingTx<'a> {
@@ -50,6 +93,10 @@ pub struct PendingTx<'a> {
     state: PendingTxStates<'a>,
     interval: Box<dyn Stream<Item = ()> + Send + Unpin>,
     provider: &'a dyn BtcProvider,
+    policy: Option<RebroadcastPolicy>,
+    unseen: usize,
+    rebroadcasts: usize,
+    outcome: Option<PendingTxOutcome>,
 }
 
 impl<'a> PendingTx<'a> {
@@ -65,9 +112,29 @@ impl<'a> PendingTx<'a> {
             state: PendingTxStates::Broadcasting(fut),
             interval: Box::new(new_interval(DEFAULT_POLL_INTERVAL)),
             provider,
+            policy: None,
+            unseen: 0,
+            rebroadcasts: 0,
+            outcome: None,
         }
     }
 
+    /// Re-submit the tx according to a policy if the provider stops reporting it
+    pub fn rebroadcast(mut self, policy: RebroadcastPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// How the stream ended. `None` while the stream is running
+    pub fn outcome(&self) -> Option<PendingTxOutcome> {
+        self.outcome
+    }
+
+    /// The number of times the tx has been re-submitted
+    pub fn rebroadcasts(&self) -> usize {
+        self.rebroadcasts
+    }
+
     /// Sets the number of confs_wanted before being notified of the spend
     pub fn confirmations(mut self, confs: usize) -> Self {
         self.confs_wanted = confs;
@@ -79,6 +146,16 @@ impl<'a> PendingTx<'a> {
         self.interval = Box::new(new_interval(duration.into()));
         self
     }
+
+    /// Poll after each tick of a stream instead of at a fixed interval, e.g. to poll on push
+    /// updates from `esplora::ws::PushTicks`
+    pub fn ticks<S>(mut self, ticks: S) -> Self
+    where
+        S: Stream<Item = ()> + Send + Unpin + 'static,
+    {
+        self.interval = Box::new(ticks);
+        self
+    }
 }
 
 impl StreamLast for PendingTx<'_> {}
@@ -95,6 +172,10 @@ impl<'a> futures_core::stream::Stream for PendingTx<'a> {
             state,
             interval,
             provider,
+            policy,
+            unseen,
+            rebroadcasts,
+            outcome,
         } = self.project();
 
         match state {
@@ -113,6 +194,7 @@ impl<'a> futures_core::stream::Stream for PendingTx<'a> {
             PendingTxStates::WaitingConfFut(fut) => {
                 match futures_util::ready!(fut.as_mut().poll(ctx)) {
                     Ok(Some(confs)) => {
+                        *unseen = 0;
                         *confs_have = confs;
                         // If we're not at our limit
                         if confs > *confs_have && confs < *confs_wanted {
@@ -123,6 +205,7 @@ impl<'a> futures_core::stream::Stream for PendingTx<'a> {
                         // If we have enough confs, ready now
                         if confs >= *confs_wanted {
                             *state = PendingTxStates::Completed;
+                            *outcome = Some(PendingTxOutcome::Confirmed);
                             ctx.waker().wake_by_ref();
                             return Poll::Ready(Some(Ok((confs, *txid))));
                         }
@@ -130,9 +213,25 @@ impl<'a> futures_core::stream::Stream for PendingTx<'a> {
                         *state = PendingTxStates::Paused;
                     }
                     Ok(None) => {
-                        *state = PendingTxStates::Dropped;
+                        let policy = match policy {
+                            Some(policy) => policy,
+                            None => {
+                                *state = PendingTxStates::Dropped;
+                                *outcome = Some(PendingTxOutcome::Evicted);
+                                ctx.waker().wake_by_ref();
+                                return Poll::Ready(Some(Err(tx.clone())));
+                            }
+                        };
+                        *unseen += 1;
+                        *state = if *unseen < policy.after_polls {
+                            PendingTxStates::Paused
+                        } else {
+                            let outpoint = tx.inputs()[0].outpoint;
+                            PendingTxStates::CheckingReplacement(Box::pin(
+                                provider.get_outspend(outpoint),
+                            ))
+                        };
                         ctx.waker().wake_by_ref();
-                        return Poll::Ready(Some(Err(tx.clone())));
                     }
                     Err(e) => {
                         if !e.from_parsing() {
@@ -146,6 +245,39 @@ impl<'a> futures_core::stream::Stream for PendingTx<'a> {
                     }
                 }
             }
+            PendingTxStates::CheckingReplacement(fut) => {
+                let spender = futures_util::ready!(fut.as_mut().poll(ctx)).unwrap_or(None);
+                *unseen = 0;
+                match spender {
+                    Some(spender) if spender != *txid => {
+                        *state = PendingTxStates::Dropped;
+                        *outcome = Some(PendingTxOutcome::Replaced(spender));
+                        ctx.waker().wake_by_ref();
+                        return Poll::Ready(Some(Err(tx.clone())));
+                    }
+                    // the provider knows the tx after all
+                    Some(_) => *state = PendingTxStates::Paused,
+                    None if *rebroadcasts < policy.map(|p| p.max_attempts).unwrap_or(0) => {
+                        *rebroadcasts += 1;
+                        let fut = Box::pin(provider.broadcast(tx.clone()));
+                        *state = PendingTxStates::Rebroadcasting(fut);
+                    }
+                    None => {
+                        *state = PendingTxStates::Dropped;
+                        *outcome = Some(PendingTxOutcome::Evicted);
+                        ctx.waker().wake_by_ref();
+                        return Poll::Ready(Some(Err(tx.clone())));
+                    }
+                }
+                ctx.waker().wake_by_ref();
+            }
+            PendingTxStates::Rebroadcasting(fut) => {
+                // Failures are retried until the attempts run out. A conflicting tx is detected
+                // at the next replacement check
+                let _ = futures_util::ready!(fut.as_mut().poll(ctx));
+                *state = PendingTxStates::Paused;
+                ctx.waker().wake_by_ref();
+            }
             PendingTxStates::Dropped => {
                 return Poll::Ready(None);
             }
@@ -156,3 +288,169 @@ impl<'a> futures_core::stream::Stream for PendingTx<'a> {
         Poll::Pending
     }
 }
+
+/// The block that confirmed a transaction. Returned by `wait_for_confs`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Confirmed {
+    /// The height of the confirming block
+    pub height: usize,
+    /// The hash of the confirming block
+    pub hash: BlockHash,
+    /// The number of confirmations when the wait ended
+    pub confirmations: usize,
+}
+
+/// Errors produced while waiting for confirmations
+#[derive(Debug, Error)]
+pub enum ConfirmationError {
+    /// The timeout elapsed before the transaction had enough confirmations
+    #[error("Timed out waiting for confirmations")]
+    Timeout,
+
+    /// The block that confirmed the transaction left the best chain
+    #[error("Confirming block at height {height} left the best chain")]
+    Reorg {
+        /// The height of the confirming block
+        height: usize,
+        /// The hash of the confirming block
+        hash: BlockHash,
+    },
+
+    /// The transaction is not known to the provider, e.g. because it was evicted from the
+    /// mempool
+    #[error("Transaction not found")]
+    NotFound,
+
+    /// Bubbled up from the provider
+    #[error(transparent)]
+    ProviderError(#[from] ProviderError),
+}
+
+// Check a transaction's confirmations once. Updates the confirming block, and returns it once
+// there are enough confirmations
+async fn check_confs(
+    provider: &dyn BtcProvider,
+    txid: TXID,
+    confirmations: usize,
+    confirmed_in: &mut Option<(usize, BlockHash)>,
+) -> Result<Option<Confirmed>, ConfirmationError> {
+    let confs = provider
+        .get_confs(txid)
+        .await?
+        .ok_or(ConfirmationError::NotFound)?;
+    let height = if confs == 0 {
+        None
+    } else {
+        provider.get_confirmed_height(txid).await?
+    };
+    let block = match height {
+        Some(height) => {
+            let digests = provider.get_digest_range(height, 1).await?;
+            digests.first().map(|hash| (height, *hash))
+        }
+        None => None,
+    };
+
+    if let Some((height, hash)) = *confirmed_in {
+        if block.map(|(_, h)| h) != Some(hash) {
+            return Err(ConfirmationError::Reorg { height, hash });
+        }
+    }
+    *confirmed_in = block;
+
+    match block {
+        Some((height, hash)) if confs >= confirmations => Ok(Some(Confirmed {
+            height,
+            hash,
+            confirmations: confs,
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// Wait until a transaction has `confirmations` confirmations, polling the provider at an
+/// interval, or until `timeout` elapses. Resolves to the confirming block. At least 1
+/// confirmation is always awaited.
+///
+/// Fails with `ConfirmationError::Reorg` if the confirming block leaves the best chain while
+/// waiting, and with `ConfirmationError::NotFound` if the provider does not know the
+/// transaction. Other provider errors are retried at the next poll, unless they were caused by
+/// parsing the response.
+pub async fn wait_for_confs(
+    provider: &dyn BtcProvider,
+    txid: TXID,
+    confirmations: usize,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<Confirmed, ConfirmationError> {
+    let confirmations = std::cmp::max(confirmations, 1);
+    let poll = async {
+        let mut ticks = new_interval(interval);
+        let mut confirmed_in = None;
+        loop {
+            match check_confs(provider, txid, confirmations, &mut confirmed_in).await {
+                Ok(Some(confirmed)) => return Ok(confirmed),
+                Ok(None) => {}
+                Err(ConfirmationError::ProviderError(e)) if e.should_retry() => {}
+                Err(e) => return Err(e),
+            }
+            ticks.next().await;
+        }
+    };
+    let deadline = Delay::new(timeout);
+    pin_mut!(poll);
+
+    match select(poll, deadline).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(ConfirmationError::Timeout),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::MockProvider;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn it_rebroadcasts_until_evicted_or_replaced() {
+        let mut rt = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let tx = bitcoins::Net::tx_builder()
+            .spend(BitcoinOutpoint::default(), 0xffff_fffd)
+            .pay_script_pubkey(Amount::from_sat(1000), ScriptPubkey::default())
+            .build()
+            .unwrap();
+        let policy = RebroadcastPolicy {
+            after_polls: 2,
+            max_attempts: 2,
+        };
+
+        let provider = MockProvider::default();
+        let mut pending = PendingTx::new(tx.clone(), &provider)
+            .interval(Duration::from_millis(1))
+            .rebroadcast(policy);
+        let events: Vec<_> = rt.block_on(pending.by_ref().collect());
+        assert_eq!(events, vec![Ok((0, tx.txid())), Err(tx.clone())]);
+        assert_eq!(pending.outcome(), Some(PendingTxOutcome::Evicted));
+        assert_eq!(pending.rebroadcasts(), 2);
+        assert_eq!(provider.broadcasts.load(Ordering::SeqCst), 3);
+
+        let replacement = TXID::from([7u8; 32]);
+        let provider = MockProvider {
+            spender: Some(replacement),
+            ..Default::default()
+        };
+        let mut pending = PendingTx::new(tx.clone(), &provider)
+            .interval(Duration::from_millis(1))
+            .rebroadcast(policy);
+        rt.block_on(pending.by_ref().collect::<Vec<_>>());
+        assert_eq!(
+            pending.outcome(),
+            Some(PendingTxOutcome::Replaced(replacement))
+        );
+        assert_eq!(pending.rebroadcasts(), 0);
+    }
+}
@@ -1,9 +1,24 @@
+#[cfg(feature = "blockbook")]
+pub use crate::blockbook::BlockbookProvider;
 #[cfg(feature = "esplora")]
-pub use crate::esplora::EsploraProvider;
+pub use crate::esplora::{mempool::MempoolSpaceProvider, EsploraProvider};
+pub use crate::layer::{Layer, ProviderBuilder};
+pub use crate::metrics::MetricsProvider;
+pub use crate::multi::{broadcast_all, MultiProvider, MultiStrategy};
 pub use crate::provider::*;
+pub use crate::ratelimit::RateLimitedProvider;
+pub use crate::retry::{RetryLayer, RetryingProvider};
 #[cfg(feature = "rpc")]
 pub use crate::rpc::BitcoinRpc;
 
-pub use crate::types::RawHeader;
+pub use crate::spv::{Checkpoint, HeaderChain, SpvError};
+pub use crate::store::{ChainStore, FileChainStore, MemoryChainStore};
+pub use crate::subscription::{AddressEvent, AddressSubscription};
+pub use crate::tracker::{MemoryStore, UtxoEvent, UtxoStore, UtxoTracker};
+pub use crate::types::{
+    DetailedInput, DetailedOutput, DetailedTx, FeeHistogram, FeeRate, MempoolEntry, RawHeader,
+    SpendingInput,
+};
+pub use crate::zeroconf::{ZeroConfAnalyzer, ZeroConfReport, ZeroConfRisk};
 
 pub use bitcoins::prelude::{BlockHash, Hash256Digest};
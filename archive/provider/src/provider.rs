@@ -1,5 +1,8 @@
 use async_trait::async_trait;
-use std::time::Duration;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::Duration,
+};
 use thiserror::Error;
 
 use bitcoins::{
@@ -12,14 +15,22 @@ use futures_util::lock::Mutex;
 use lru::LruCache;
 
 use crate::{
-    chain::Tips, pending::PendingTx, types::RawHeader, watcher::PollingWatcher, DEFAULT_CACHE_SIZE,
+    chain::{ChainEvents, Tips},
+    layer::Layer,
+    pending::{wait_for_confs, ConfirmationError, Confirmed, PendingTx},
+    subscription::AddressSubscription,
+    types::{
+        DetailedOutput, DetailedTx, FeeHistogram, FeeRate, MempoolEntry, RawHeader, SpendingInput,
+    },
+    watcher::{MultiWatcher, PollingWatcher},
+    DEFAULT_CACHE_SIZE,
 };
 
 /// Errors thrown by providers
 #[derive(Debug, Error)]
 pub enum ProviderError {
     /// Serde issue
-    #[cfg(any(feature = "rpc", feature = "esplora"))]
+    #[cfg(any(feature = "rpc", feature = "esplora", feature = "blockbook"))]
     #[error(transparent)]
     SerdeJsonError(#[from] serde_json::Error),
 
@@ -31,6 +42,14 @@ pub enum ProviderError {
     #[error(transparent)]
     CoinsSerError(#[from] coins_core::ser::SerError),
 
+    /// Bubbled up from a `ChainStore`
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    /// Header validation error
+    #[error(transparent)]
+    SpvError(#[from] crate::spv::SpvError),
+
     /// Unsupported action. Provider should give a string describing the action and reason
     #[error("Unsupported action: {0}")]
     Unsupported(String),
@@ -40,6 +59,29 @@ pub enum ProviderError {
     #[error("RPC Error Response: {0}")]
     RpcErrorResponse(crate::rpc::common::ErrorResponse),
 
+    /// A `scantxoutset` is already running on the node. Contains its progress in percent.
+    #[cfg(feature = "rpc")]
+    #[error("A UTXO set scan is already in progress: {0}%")]
+    ScanInProgress(f64),
+
+    /// The backend rejected the request, e.g. because a transaction is invalid. Contains the
+    /// backend's message
+    #[error("Rejected by backend: {0}")]
+    Rejected(String),
+
+    /// A broadcast failed at every endpoint. Contains each endpoint's error message
+    #[error("Broadcast failed at all endpoints: {0:?}")]
+    BroadcastFailed(Vec<String>),
+
+    /// The providers of a `MultiProvider` did not return enough matching responses
+    #[error("No quorum: needed {needed} matching responses, got {responses} responses")]
+    NoQuorum {
+        /// The number of matching responses needed
+        needed: usize,
+        /// The number of successful responses
+        responses: usize,
+    },
+
     /// Custom provider error. Indicates whether the request should be retried
     #[error("Proivder error {e}")]
     Custom {
@@ -50,18 +92,101 @@ pub enum ProviderError {
     },
 }
 
+/// A coarse classification of `ProviderError`s, for monitoring
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The backend could not be reached, or the connection failed
+    Network,
+    /// The response could not be parsed. Often indicates that an object was not found
+    Parsing,
+    /// The node returned an RPC error response
+    Rpc,
+    /// The backend rejected the request, e.g. an invalid transaction
+    Rejected,
+    /// The provider does not support the request
+    Unsupported,
+    /// Any other error
+    Other,
+}
+
+impl ErrorCategory {
+    /// The category's name, e.g. for use as a metric label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Network => "network",
+            ErrorCategory::Parsing => "parsing",
+            ErrorCategory::Rpc => "rpc",
+            ErrorCategory::Rejected => "rejected",
+            ErrorCategory::Unsupported => "unsupported",
+            ErrorCategory::Other => "other",
+        }
+    }
+}
+
 impl ProviderError {
     /// Shortcut for instantiating a custom error
     pub fn custom(from_parsing: bool, e: Box<dyn std::error::Error>) -> Self {
         Self::Custom { from_parsing, e }
     }
+
+    /// Classify the error, for monitoring
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            _ if self.from_parsing() => ErrorCategory::Parsing,
+            _ if self.should_retry() => ErrorCategory::Network,
+            #[cfg(feature = "rpc")]
+            ProviderError::RpcErrorResponse(_) | ProviderError::ScanInProgress(_) => {
+                ErrorCategory::Rpc
+            }
+            ProviderError::Rejected(_) | ProviderError::BroadcastFailed(_) => {
+                ErrorCategory::Rejected
+            }
+            ProviderError::Unsupported(_) => ErrorCategory::Unsupported,
+            _ => ErrorCategory::Other,
+        }
+    }
+    /// Returns true if the request may succeed if retried. This is the case for custom errors
+    /// not caused by local parsing, e.g. network errors. Parsing errors, unsupported actions,
+    /// and RPC error responses will fail again.
+    pub fn should_retry(&self) -> bool {
+        matches!(
+            self,
+            ProviderError::Custom {
+                from_parsing: false,
+                e: _,
+            }
+        )
+    }
+
+    /// Returns true if a broadcast failed because the backend already knows the transaction,
+    /// i.e. it is already in the mempool or the chain. Broadcasters may treat this as success.
+    pub fn is_already_known(&self) -> bool {
+        const ALREADY_KNOWN: [&str; 3] = [
+            "txn-already-in-mempool",
+            "txn-already-known",
+            "already in block chain",
+        ];
+        let message = match self {
+            // RPC_VERIFY_ALREADY_IN_CHAIN
+            #[cfg(feature = "rpc")]
+            ProviderError::RpcErrorResponse(e) if e.code == -27 => return true,
+            #[cfg(feature = "rpc")]
+            ProviderError::RpcErrorResponse(e) => &e.message,
+            ProviderError::Rejected(message) => message,
+            _ => return false,
+        };
+        ALREADY_KNOWN
+            .iter()
+            .any(|pattern| message.to_lowercase().contains(pattern))
+    }
+
     /// Returns true if the request failed due to a local parsing error.
     ///
     /// ## Note:
     ///
     /// This usually indicates that a requested object was not found. It is common for Bitcoin
     /// APIs to violate JSON RPC conventions, and return raw strings in this case.
-    #[cfg(any(feature = "rpc", feature = "esplora"))]
+    #[cfg(any(feature = "rpc", feature = "esplora", feature = "blockbook"))]
     pub fn from_parsing(&self) -> bool {
         matches!(
             self,
@@ -80,7 +205,7 @@ impl ProviderError {
     ///
     /// This usually indicates that a requested object was not found. It is common for Bitcoin
     /// APIs to violate JSON RPC conventions, and return raw strings in this case.
-    #[cfg(not(any(feature = "rpc", feature = "esplora")))]
+    #[cfg(not(any(feature = "rpc", feature = "esplora", feature = "blockbook")))]
     pub fn from_parsing(&self) -> bool {
         match self {
             ProviderError::Custom {
@@ -165,9 +290,66 @@ pub trait BtcProvider: Sync + Send {
     /// `Ok(None)`
     async fn get_tx(&self, txid: TXID) -> Result<Option<BitcoinTx>, ProviderError>;
 
+    /// Fetch a transaction, with the outputs spent by its inputs, their values and addresses.
+    /// If the tx is not found, the result will be `Ok(None)`.
+    ///
+    /// By default, this fetches the transaction of each prevout. Providers that report prevouts
+    /// directly should override it.
+    async fn get_tx_detailed(&self, txid: TXID) -> Result<Option<DetailedTx>, ProviderError> {
+        let tx = match self.get_tx(txid).await? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+        let block_hash = self.get_confirming_digests(txid, 1).await?.pop();
+        let outpoints: Vec<BitcoinOutpoint> =
+            tx.inputs().iter().map(|input| input.outpoint).collect();
+        let prevouts = fetch_prevouts(self, &outpoints).await?;
+        Ok(Some(DetailedTx::new(tx, block_hash, prevouts)))
+    }
+
+    /// Fetch a block, with all of its transactions, by its digest. This allows filtering and
+    /// matching transactions locally, instead of fetching them one at a time. If the block is
+    /// not found, the result will be `Ok(None)`.
+    ///
+    /// Note: some providers may not implement this functionality.
+    async fn get_block_full(&self, _digest: BlockHash) -> Result<Option<Block>, ProviderError> {
+        Err(ProviderError::Unsupported(
+            "get_block_full not supported by this provider".to_owned(),
+        ))
+    }
+
     /// Broadcast a transaction to the network. Resolves to a TXID when broadcast.
     async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError>;
 
+    /// Fetch the feerate distribution of the remote source's mempool.
+    ///
+    /// Note: some providers may not implement this functionality.
+    async fn get_fee_histogram(&self) -> Result<FeeHistogram, ProviderError> {
+        Err(ProviderError::Unsupported(
+            "get_fee_histogram not supported by this provider".to_owned(),
+        ))
+    }
+
+    /// Estimate the feerate needed for a transaction to confirm within `target_blocks` blocks.
+    ///
+    /// Note: some providers may not implement this functionality.
+    async fn estimate_fee(&self, _target_blocks: usize) -> Result<FeeRate, ProviderError> {
+        Err(ProviderError::Unsupported(
+            "estimate_fee not supported by this provider".to_owned(),
+        ))
+    }
+
+    /// Fetch the mempool entry of a transaction, including its ancestor and descendant fees and
+    /// its BIP125 replaceability. If the transaction is not in the mempool, the result will be
+    /// `Ok(None)`.
+    ///
+    /// Note: some providers may not implement this functionality.
+    async fn get_mempool_entry(&self, _txid: TXID) -> Result<Option<MempoolEntry>, ProviderError> {
+        Err(ProviderError::Unsupported(
+            "get_mempool_entry not supported by this provider".to_owned(),
+        ))
+    }
+
     // -- SPEND UTILS -- //
 
     /// Fetch the ID of a transaction that spends an outpoint. If no TX known to the remote source
@@ -176,6 +358,31 @@ pub trait BtcProvider: Sync + Send {
     /// Note: some providers may not implement this functionality.
     async fn get_outspend(&self, outpoint: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError>;
 
+    /// Fetch the input that spends an outpoint, i.e. the ID of the spending transaction and the
+    /// index of the input in it. If no TX known to the remote source spends that outpoint, the
+    /// result will be `Ok(None)`.
+    ///
+    /// By default, this fetches the spending transaction to find the input. Providers that
+    /// report the index directly should override it.
+    async fn get_spending_input(
+        &self,
+        outpoint: BitcoinOutpoint,
+    ) -> Result<Option<SpendingInput>, ProviderError> {
+        let txid = match self.get_outspend(outpoint).await? {
+            Some(txid) => txid,
+            None => return Ok(None),
+        };
+        let tx = match self.get_tx(txid).await? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+        Ok(tx
+            .inputs()
+            .iter()
+            .position(|input| input.outpoint == outpoint)
+            .map(|vin| SpendingInput { txid, vin }))
+    }
+
     /// Fetch the UTXOs belonging to an address from the remote API
     ///
     /// ## Note: some providers may not implement this functionality.
@@ -201,6 +408,28 @@ pub trait BtcProvider: Sync + Send {
         txid: TXID,
     ) -> Result<Option<(usize, Vec<Hash256Digest>)>, ProviderError>;
 
+    /// Fetch the merkle proof of a confirmed transaction, and verify it against the header of
+    /// the confirming block. Returns `None` if the transaction is not confirmed.
+    ///
+    /// Note: this trusts the provider's header. Validate headers with `spv::HeaderChain` to
+    /// detect a provider feeding you a fake chain.
+    async fn prove_and_verify(&self, txid: TXID) -> Result<Option<bool>, ProviderError> {
+        let (index, proof) = match self.get_merkle(txid).await? {
+            Some(merkle) => merkle,
+            None => return Ok(None),
+        };
+        let header = match self.get_confirming_headers(txid, 1).await?.pop() {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        Ok(Some(crate::utils::verify_merkle(
+            txid,
+            index,
+            &proof,
+            header.merkle_root(),
+        )))
+    }
+
     /// TODO: make less brittle
     async fn get_confirming_digests(
         &self,
@@ -234,6 +463,32 @@ pub trait BtcProvider: Sync + Send {
     }
 }
 
+/// Fetch the outputs spent by a list of outpoints, by fetching their transactions. Each
+/// transaction is fetched once. Prevouts are `None` for the null outpoint of a coinbase input,
+/// or if the transaction is unknown
+pub(crate) async fn fetch_prevouts<P: BtcProvider + ?Sized>(
+    provider: &P,
+    outpoints: &[BitcoinOutpoint],
+) -> Result<Vec<Option<DetailedOutput>>, ProviderError> {
+    let mut txns: HashMap<TXID, Option<BitcoinTx>> = HashMap::new();
+    let mut prevouts = vec![];
+    for outpoint in outpoints.iter() {
+        if *outpoint == BitcoinOutpoint::null() {
+            prevouts.push(None);
+            continue;
+        }
+        if let Entry::Vacant(entry) = txns.entry(outpoint.txid) {
+            entry.insert(provider.get_tx(outpoint.txid).await?);
+        }
+        let prevout = txns[&outpoint.txid]
+            .as_ref()
+            .and_then(|tx| tx.outputs().get(outpoint.idx as usize))
+            .map(|output| DetailedOutput::new(output.value.to_sat(), output.script_pubkey.clone()));
+        prevouts.push(prevout);
+    }
+    Ok(prevouts)
+}
+
 /// An extension trait that adds polling watchers for a provider
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
@@ -270,6 +525,21 @@ pub trait PollingBtcProvider: BtcProvider {
         )
     }
 
+    /// Wait until a transaction has `confirmations` confirmations, or until `timeout` elapses.
+    /// Resolves to the confirming block, or fails with `ConfirmationError::Reorg` if that block
+    /// leaves the best chain while waiting. See `pending::wait_for_confs`.
+    async fn wait_for_confs(
+        &self,
+        txid: TXID,
+        confirmations: usize,
+        timeout: Duration,
+    ) -> Result<Confirmed, ConfirmationError>
+    where
+        Self: Sized,
+    {
+        wait_for_confs(self, txid, confirmations, timeout, self.interval()).await
+    }
+
     /// Watch the chain tip. Get notified of the new `BlockHash` every time it changes.
     ///
     /// Note: A new hash does not necessarily mean the chain height has increased. Reorgs may
@@ -281,6 +551,15 @@ pub trait PollingBtcProvider: BtcProvider {
         Tips::new(limit, self).interval(self.interval())
     }
 
+    /// Watch the chain. Get notified of each block connected to the best chain, and of each
+    /// reorg. After a reorg, the blocks of the new branch are replayed.
+    fn chain_events(&self) -> ChainEvents<'_>
+    where
+        Self: Sized,
+    {
+        ChainEvents::new(self).interval(self.interval())
+    }
+
     /// Watch an outpoint, waiting for a tx to spend it. This returns a `PollingWatcher` future.
     /// The observation will not start until that future is scheduled to run.
     ///
@@ -293,6 +572,28 @@ pub trait PollingBtcProvider: BtcProvider {
             .confirmations(confirmations)
             .interval(self.interval())
     }
+
+    /// Watch a set of outpoints with a single stream, waiting for txs to spend them. Each poll
+    /// queries every watched outpoint. This returns a `MultiWatcher` stream.
+    fn watch_all<I>(&self, outpoints: I, confirmations: usize) -> MultiWatcher<'_>
+    where
+        Self: Sized,
+        I: IntoIterator<Item = BitcoinOutpoint>,
+    {
+        MultiWatcher::new(outpoints, self)
+            .confirmations(confirmations)
+            .interval(self.interval())
+    }
+
+    /// Subscribe to an address. Get notified each time it receives funds, or its funds are
+    /// spent. This returns an `AddressSubscription` stream, which polls at the provider's
+    /// interval. Pass it push notifications via `ticks` or `push` to react immediately.
+    fn subscribe_address(&self, address: &Address) -> AddressSubscription<'_>
+    where
+        Self: Sized,
+    {
+        AddressSubscription::new(address, self).interval(self.interval())
+    }
 }
 
 /// A provider that caches API responses whose values will never change.
@@ -343,6 +644,23 @@ impl<T: BtcProvider> CachingProvider<T> {
     pub async fn has_height(&self, digest: BlockHash) -> bool {
         self.height_cache.lock().await.contains(&digest)
     }
+
+    /// Return a reference to the wrapped provider
+    pub fn inner(&self) -> &T {
+        &self.provider
+    }
+}
+
+/// A layer that wraps providers in a `CachingProvider`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CachingLayer;
+
+impl<T: BtcProvider> Layer<T> for CachingLayer {
+    type Provider = CachingProvider<T>;
+
+    fn layer(&self, inner: T) -> Self::Provider {
+        inner.into()
+    }
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -433,10 +751,37 @@ where
         self.provider.broadcast(tx).await
     }
 
+    async fn get_fee_histogram(&self) -> Result<FeeHistogram, ProviderError> {
+        self.provider.get_fee_histogram().await
+    }
+
+    async fn estimate_fee(&self, target_blocks: usize) -> Result<FeeRate, ProviderError> {
+        self.provider.estimate_fee(target_blocks).await
+    }
+
+    async fn get_mempool_entry(&self, txid: TXID) -> Result<Option<MempoolEntry>, ProviderError> {
+        self.provider.get_mempool_entry(txid).await
+    }
+
     async fn get_outspend(&self, outpoint: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError> {
         self.provider.get_outspend(outpoint).await
     }
 
+    async fn get_spending_input(
+        &self,
+        outpoint: BitcoinOutpoint,
+    ) -> Result<Option<SpendingInput>, ProviderError> {
+        self.provider.get_spending_input(outpoint).await
+    }
+
+    async fn get_tx_detailed(&self, txid: TXID) -> Result<Option<DetailedTx>, ProviderError> {
+        self.provider.get_tx_detailed(txid).await
+    }
+
+    async fn get_block_full(&self, digest: BlockHash) -> Result<Option<Block>, ProviderError> {
+        self.provider.get_block_full(digest).await
+    }
+
     async fn get_utxos_by_address(&self, address: &Address) -> Result<Vec<Utxo>, ProviderError> {
         self.provider.get_utxos_by_address(address).await
     }
@@ -0,0 +1,186 @@
+use async_trait::async_trait;
+use std::time::Duration;
+
+use bitcoins::{
+    enc::Address,
+    hashes::{BlockHash, TXID},
+    types::*,
+};
+use coins_core::prelude::*;
+
+use crate::{
+    layer::Layer,
+    provider::{BtcProvider, PollingBtcProvider, ProviderError},
+    types::{DetailedTx, FeeHistogram, FeeRate, MempoolEntry, RawHeader, SpendingInput},
+    utils::RateLimiter,
+};
+
+/// A provider that limits the rate of requests to the wrapped provider. Requests over the limit
+/// wait until the limiter allows them.
+pub struct RateLimitedProvider<T: BtcProvider> {
+    provider: T,
+    limiter: RateLimiter,
+}
+
+impl<T: BtcProvider> RateLimitedProvider<T> {
+    /// Wrap a provider, allowing `per_second` requests per second, with bursts of up to `burst`
+    /// requests
+    pub fn new(provider: T, per_second: f64, burst: usize) -> Self {
+        Self {
+            provider,
+            limiter: RateLimiter::new(per_second, burst),
+        }
+    }
+
+    /// Return a reference to the wrapped provider
+    pub fn inner(&self) -> &T {
+        &self.provider
+    }
+
+    // Wait for the limiter, then return the wrapped provider
+    async fn limited(&self) -> &T {
+        self.limiter.acquire().await;
+        &self.provider
+    }
+}
+
+/// A layer that wraps providers in a `RateLimitedProvider`. Each wrapped provider gets its own
+/// limiter
+#[derive(Copy, Clone, Debug)]
+pub struct RateLimitLayer {
+    per_second: f64,
+    burst: usize,
+}
+
+impl RateLimitLayer {
+    /// Allow `per_second` requests per second, with bursts of up to `burst` requests
+    pub fn new(per_second: f64, burst: usize) -> Self {
+        Self { per_second, burst }
+    }
+}
+
+impl<T: BtcProvider> Layer<T> for RateLimitLayer {
+    type Provider = RateLimitedProvider<T>;
+
+    fn layer(&self, inner: T) -> Self::Provider {
+        RateLimitedProvider::new(inner, self.per_second, self.burst)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T> BtcProvider for RateLimitedProvider<T>
+where
+    T: BtcProvider,
+{
+    async fn tip_hash(&self) -> Result<BlockHash, ProviderError> {
+        self.limited().await.tip_hash().await
+    }
+
+    async fn tip_height(&self) -> Result<usize, ProviderError> {
+        self.limited().await.tip_height().await
+    }
+
+    async fn in_best_chain(&self, digest: BlockHash) -> Result<bool, ProviderError> {
+        self.limited().await.in_best_chain(digest).await
+    }
+
+    async fn get_digest_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<BlockHash>, ProviderError> {
+        self.limited().await.get_digest_range(start, headers).await
+    }
+
+    async fn get_raw_header_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<RawHeader>, ProviderError> {
+        self.limited()
+            .await
+            .get_raw_header_range(start, headers)
+            .await
+    }
+
+    async fn get_raw_header(&self, digest: BlockHash) -> Result<Option<RawHeader>, ProviderError> {
+        self.limited().await.get_raw_header(digest).await
+    }
+
+    async fn get_height_of(&self, digest: BlockHash) -> Result<Option<usize>, ProviderError> {
+        self.limited().await.get_height_of(digest).await
+    }
+
+    async fn get_confirmed_height(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.limited().await.get_confirmed_height(txid).await
+    }
+
+    async fn get_confs(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.limited().await.get_confs(txid).await
+    }
+
+    async fn get_tx(&self, txid: TXID) -> Result<Option<BitcoinTx>, ProviderError> {
+        self.limited().await.get_tx(txid).await
+    }
+
+    async fn get_tx_detailed(&self, txid: TXID) -> Result<Option<DetailedTx>, ProviderError> {
+        self.limited().await.get_tx_detailed(txid).await
+    }
+
+    async fn get_block_full(&self, digest: BlockHash) -> Result<Option<Block>, ProviderError> {
+        self.limited().await.get_block_full(digest).await
+    }
+
+    async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError> {
+        self.limited().await.broadcast(tx).await
+    }
+
+    async fn get_fee_histogram(&self) -> Result<FeeHistogram, ProviderError> {
+        self.limited().await.get_fee_histogram().await
+    }
+
+    async fn estimate_fee(&self, target_blocks: usize) -> Result<FeeRate, ProviderError> {
+        self.limited().await.estimate_fee(target_blocks).await
+    }
+
+    async fn get_mempool_entry(&self, txid: TXID) -> Result<Option<MempoolEntry>, ProviderError> {
+        self.limited().await.get_mempool_entry(txid).await
+    }
+
+    async fn get_outspend(&self, outpoint: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError> {
+        self.limited().await.get_outspend(outpoint).await
+    }
+
+    async fn get_spending_input(
+        &self,
+        outpoint: BitcoinOutpoint,
+    ) -> Result<Option<SpendingInput>, ProviderError> {
+        self.limited().await.get_spending_input(outpoint).await
+    }
+
+    async fn get_utxos_by_address(&self, address: &Address) -> Result<Vec<Utxo>, ProviderError> {
+        self.limited().await.get_utxos_by_address(address).await
+    }
+
+    async fn get_merkle(
+        &self,
+        txid: TXID,
+    ) -> Result<Option<(usize, Vec<Hash256Digest>)>, ProviderError> {
+        self.limited().await.get_merkle(txid).await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T> PollingBtcProvider for RateLimitedProvider<T>
+where
+    T: PollingBtcProvider,
+{
+    fn interval(&self) -> Duration {
+        self.provider.interval()
+    }
+    fn set_interval(&mut self, interval: usize) {
+        self.provider.set_interval(interval)
+    }
+}
@@ -0,0 +1,381 @@
+use async_trait::async_trait;
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use bitcoins::{
+    enc::Address,
+    hashes::{BlockHash, TXID},
+    types::*,
+};
+use coins_core::prelude::*;
+use futures_timer::Delay;
+
+use crate::{
+    layer::Layer,
+    provider::{BtcProvider, PollingBtcProvider, ProviderError},
+    types::{DetailedTx, FeeHistogram, FeeRate, MempoolEntry, RawHeader, SpendingInput},
+};
+
+/// The default number of times a single request is retried
+pub const DEFAULT_MAX_RETRIES: usize = 3;
+
+/// The default delay before the first retry. Doubles with each retry
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(250);
+
+/// The default maximum delay between retries
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// The default retry budget
+pub const DEFAULT_RETRY_BUDGET: usize = 20;
+
+/// A provider that retries requests that fail with retryable errors (see
+/// `ProviderError::should_retry`), with exponential backoff and jitter.
+///
+/// Retries are limited per request, and by a budget shared by all requests. Each retry spends
+/// from the budget, and each successful request refills it by one, up to its capacity. This
+/// prevents retry storms against a backend that is down.
+pub struct RetryingProvider<T: BtcProvider> {
+    provider: T,
+    max_retries: usize,
+    base_delay: Duration,
+    max_delay: Duration,
+    capacity: usize,
+    budget: AtomicUsize,
+}
+
+impl<T: BtcProvider> From<T> for RetryingProvider<T> {
+    fn from(provider: T) -> Self {
+        Self {
+            provider,
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            capacity: DEFAULT_RETRY_BUDGET,
+            budget: AtomicUsize::new(DEFAULT_RETRY_BUDGET),
+        }
+    }
+}
+
+impl<T> Default for RetryingProvider<T>
+where
+    T: BtcProvider + Default,
+{
+    fn default() -> Self {
+        T::default().into()
+    }
+}
+
+impl<T: BtcProvider> RetryingProvider<T> {
+    /// Sets the number of times a single request is retried
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the delay before the first retry, and the maximum delay between retries
+    pub fn delays<D: Into<Duration>>(mut self, base_delay: D, max_delay: D) -> Self {
+        self.base_delay = base_delay.into();
+        self.max_delay = max_delay.into();
+        self
+    }
+
+    /// Sets the capacity of the retry budget, and refills it
+    pub fn budget(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self.budget = AtomicUsize::new(capacity);
+        self
+    }
+
+    /// The number of retries remaining in the budget
+    pub fn remaining_budget(&self) -> usize {
+        self.budget.load(Ordering::SeqCst)
+    }
+
+    /// Return a reference to the wrapped provider
+    pub fn inner(&self) -> &T {
+        &self.provider
+    }
+
+    // Spend one retry from the budget. False if the budget is exhausted
+    fn withdraw(&self) -> bool {
+        self.budget
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |b| b.checked_sub(1))
+            .is_ok()
+    }
+
+    // Refill the budget by one, up to its capacity
+    fn deposit(&self) {
+        let capacity = self.capacity;
+        let _ = self
+            .budget
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |b| {
+                if b < capacity {
+                    Some(b + 1)
+                } else {
+                    None
+                }
+            });
+    }
+
+    // The delay before retry number `attempt`. Uses "equal jitter", i.e. a random delay
+    // between half of the backoff, and the full backoff.
+    fn backoff(&self, attempt: usize) -> Duration {
+        let exp = 2u32.saturating_pow(attempt as u32);
+        let backoff = std::cmp::min(
+            self.base_delay.checked_mul(exp).unwrap_or(self.max_delay),
+            self.max_delay,
+        );
+        let half = backoff / 2;
+        let jitter = RandomState::new().build_hasher().finish() % (half.as_millis() as u64 + 1);
+        half + Duration::from_millis(jitter)
+    }
+
+    async fn retry<R, F, Fut>(&self, f: F) -> Result<R, ProviderError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<R, ProviderError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match f().await {
+                Err(e) if e.should_retry() && attempt < self.max_retries && self.withdraw() => {}
+                result => {
+                    if result.is_ok() {
+                        self.deposit();
+                    }
+                    return result;
+                }
+            }
+            Delay::new(self.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// A layer that wraps providers in a `RetryingProvider`. Each wrapped provider gets its own
+/// retry budget
+#[derive(Copy, Clone, Debug)]
+pub struct RetryLayer {
+    max_retries: usize,
+    base_delay: Duration,
+    max_delay: Duration,
+    capacity: usize,
+}
+
+impl Default for RetryLayer {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            capacity: DEFAULT_RETRY_BUDGET,
+        }
+    }
+}
+
+impl RetryLayer {
+    /// Sets the number of times a single request is retried
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the delay before the first retry, and the maximum delay between retries
+    pub fn delays<D: Into<Duration>>(mut self, base_delay: D, max_delay: D) -> Self {
+        self.base_delay = base_delay.into();
+        self.max_delay = max_delay.into();
+        self
+    }
+
+    /// Sets the capacity of the retry budget
+    pub fn budget(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+}
+
+impl<T: BtcProvider> Layer<T> for RetryLayer {
+    type Provider = RetryingProvider<T>;
+
+    fn layer(&self, inner: T) -> Self::Provider {
+        RetryingProvider::from(inner)
+            .max_retries(self.max_retries)
+            .delays(self.base_delay, self.max_delay)
+            .budget(self.capacity)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T> BtcProvider for RetryingProvider<T>
+where
+    T: BtcProvider,
+{
+    async fn tip_hash(&self) -> Result<BlockHash, ProviderError> {
+        self.retry(|| self.provider.tip_hash()).await
+    }
+
+    async fn tip_height(&self) -> Result<usize, ProviderError> {
+        self.retry(|| self.provider.tip_height()).await
+    }
+
+    async fn in_best_chain(&self, digest: BlockHash) -> Result<bool, ProviderError> {
+        self.retry(|| self.provider.in_best_chain(digest)).await
+    }
+
+    async fn get_digest_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<BlockHash>, ProviderError> {
+        self.retry(|| self.provider.get_digest_range(start, headers))
+            .await
+    }
+
+    async fn get_raw_header_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<RawHeader>, ProviderError> {
+        self.retry(|| self.provider.get_raw_header_range(start, headers))
+            .await
+    }
+
+    async fn get_raw_header(&self, digest: BlockHash) -> Result<Option<RawHeader>, ProviderError> {
+        self.retry(|| self.provider.get_raw_header(digest)).await
+    }
+
+    async fn get_height_of(&self, digest: BlockHash) -> Result<Option<usize>, ProviderError> {
+        self.retry(|| self.provider.get_height_of(digest)).await
+    }
+
+    async fn get_confirmed_height(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.retry(|| self.provider.get_confirmed_height(txid))
+            .await
+    }
+
+    async fn get_confs(&self, txid: TXID) -> Result<Option<usize>, ProviderError> {
+        self.retry(|| self.provider.get_confs(txid)).await
+    }
+
+    async fn get_tx(&self, txid: TXID) -> Result<Option<BitcoinTx>, ProviderError> {
+        self.retry(|| self.provider.get_tx(txid)).await
+    }
+
+    async fn get_tx_detailed(&self, txid: TXID) -> Result<Option<DetailedTx>, ProviderError> {
+        self.retry(|| self.provider.get_tx_detailed(txid)).await
+    }
+
+    async fn get_block_full(&self, digest: BlockHash) -> Result<Option<Block>, ProviderError> {
+        self.retry(|| self.provider.get_block_full(digest)).await
+    }
+
+    async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError> {
+        self.retry(|| self.provider.broadcast(tx.clone())).await
+    }
+
+    async fn get_fee_histogram(&self) -> Result<FeeHistogram, ProviderError> {
+        self.retry(|| self.provider.get_fee_histogram()).await
+    }
+
+    async fn estimate_fee(&self, target_blocks: usize) -> Result<FeeRate, ProviderError> {
+        self.retry(|| self.provider.estimate_fee(target_blocks))
+            .await
+    }
+
+    async fn get_mempool_entry(&self, txid: TXID) -> Result<Option<MempoolEntry>, ProviderError> {
+        self.retry(|| self.provider.get_mempool_entry(txid)).await
+    }
+
+    async fn get_outspend(&self, outpoint: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError> {
+        self.retry(|| self.provider.get_outspend(outpoint)).await
+    }
+
+    async fn get_spending_input(
+        &self,
+        outpoint: BitcoinOutpoint,
+    ) -> Result<Option<SpendingInput>, ProviderError> {
+        self.retry(|| self.provider.get_spending_input(outpoint))
+            .await
+    }
+
+    async fn get_utxos_by_address(&self, address: &Address) -> Result<Vec<Utxo>, ProviderError> {
+        self.retry(|| self.provider.get_utxos_by_address(address))
+            .await
+    }
+
+    async fn get_merkle(
+        &self,
+        txid: TXID,
+    ) -> Result<Option<(usize, Vec<Hash256Digest>)>, ProviderError> {
+        self.retry(|| self.provider.get_merkle(txid)).await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T> PollingBtcProvider for RetryingProvider<T>
+where
+    T: PollingBtcProvider,
+{
+    fn interval(&self) -> Duration {
+        self.provider.interval()
+    }
+    fn set_interval(&mut self, interval: usize) {
+        self.provider.set_interval(interval)
+    }
+}
+
+#[cfg(all(test, feature = "esplora"))]
+mod test {
+    use super::*;
+
+    fn network_error() -> ProviderError {
+        ProviderError::custom(false, "connection reset".into())
+    }
+
+    #[test]
+    fn it_retries_with_a_budget() {
+        let mut rt = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let provider = RetryingProvider::from(crate::esplora::EsploraProvider::default())
+            .max_retries(2)
+            .delays(Duration::from_millis(1), Duration::from_millis(2))
+            .budget(3);
+
+        let calls = AtomicUsize::new(0);
+        let failing = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(network_error())
+        };
+
+        // retried up to `max_retries` times
+        assert!(rt.block_on(provider.retry(failing)).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(provider.remaining_budget(), 1);
+
+        // the budget limits further retries
+        assert!(rt.block_on(provider.retry(failing)).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+        assert_eq!(provider.remaining_budget(), 0);
+
+        // successes refill the budget
+        assert!(rt.block_on(provider.retry(|| async { Ok(()) })).is_ok());
+        assert_eq!(provider.remaining_budget(), 1);
+
+        // errors that should not be retried are returned immediately
+        let unsupported = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(ProviderError::Unsupported("nope".to_owned()))
+        };
+        assert!(rt.block_on(provider.retry(unsupported)).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+    }
+}
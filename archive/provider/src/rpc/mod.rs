@@ -9,7 +9,12 @@ pub mod rpc_types;
 
 use async_trait::async_trait;
 use bitcoins::prelude::*;
-use futures_util::lock::Mutex;
+use futures_timer::Delay;
+use futures_util::{
+    future::{select, Either},
+    lock::Mutex,
+    pin_mut,
+};
 use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -17,17 +22,25 @@ use std::time::Duration;
 use crate::{
     provider::*,
     rpc::{common::*, http::HttpTransport, rpc_types::*},
-    types::RawHeader,
+    types::{DetailedTx, FeeRate, MempoolEntry, RawHeader},
 };
 
 static ERR_NOT_FOUND: i64 = -1;
 
+// RPC_METHOD_NOT_FOUND. Returned for disabled or unknown commands
+static ERR_METHOD_NOT_FOUND: i64 = -32601;
+
+// RPC_INVALID_ADDRESS_OR_KEY. Returned by `getmempoolentry` for txns not in the mempool
+static ERR_INVALID_ADDRESS_OR_KEY: i64 = -5;
+
 /// A Bitcoin RPC connection
 #[derive(Debug)]
 pub struct BitcoinRpc<T: JsonRpcTransport> {
     transport: T,
     interval: Duration,
     scan_guard: Mutex<()>,
+    scan_supported: Mutex<Option<bool>>,
+    validate_broadcast: bool,
 }
 
 impl<T: JsonRpcTransport> Default for BitcoinRpc<T> {
@@ -36,6 +49,8 @@ impl<T: JsonRpcTransport> Default for BitcoinRpc<T> {
             transport: Default::default(),
             interval: crate::DEFAULT_POLL_INTERVAL,
             scan_guard: Mutex::new(()),
+            scan_supported: Mutex::new(None),
+            validate_broadcast: false,
         }
     }
 }
@@ -66,15 +81,22 @@ impl BitcoinRpc<HttpTransport> {
 }
 
 impl<T: JsonRpcTransport> BitcoinRpc<T> {
+    /// Check transactions with `testmempoolaccept` before broadcasting them. Policy failures
+    /// (e.g. dust, or a low fee) are returned as `ProviderError::Rejected` without the
+    /// transaction being sent.
+    pub fn validate_before_broadcast(mut self, validate: bool) -> Self {
+        self.validate_broadcast = validate;
+        self
+    }
+
     async fn request<P: Serialize + Send + Sync, R: for<'a> Deserialize<'a>>(
         &self,
         method: &str,
         params: P,
     ) -> Result<R, ProviderError> {
-        self.transport
-            .request(method, params)
-            .await
-            .map_err(Into::into)
+        instrument!("rpc", method, {
+            self.transport.request(method, params).await
+        })
     }
 
     /// Get the digest of the best block
@@ -97,6 +119,12 @@ impl<T: JsonRpcTransport> BitcoinRpc<T> {
         self.request("getblock", vec![block.to_be_hex()]).await
     }
 
+    /// Get a block by its digest, as hex. Fails for blocks that the node has pruned
+    pub async fn get_raw_block(&self, block: BlockHash) -> Result<String, ProviderError> {
+        self.request("getblock", GetBlockParams(block.to_be_hex(), 0))
+            .await
+    }
+
     /// Get a TX by its txid
     pub async fn get_raw_transaction(
         &self,
@@ -106,24 +134,174 @@ impl<T: JsonRpcTransport> BitcoinRpc<T> {
             .await
     }
 
+    /// Get a transaction with verbosity 2, which reports the outputs spent by its inputs. Nodes
+    /// before Bitcoin Core 25.0 treat this as verbosity 1, and omit them. The node must have
+    /// `-txindex` enabled for confirmed transactions outside its wallet
+    pub async fn get_raw_transaction_verbose(
+        &self,
+        txid: TXID,
+    ) -> Result<GetRawTransactionResponse, ProviderError> {
+        self.request("getrawtransaction", GetRawTxParams(txid.to_be_hex(), 2))
+            .await
+    }
+
     /// Send a raw transaction to the network
     pub async fn send_raw_transaction(&self, tx: BitcoinTx) -> Result<String, ProviderError> {
         self.request("sendrawtransaction", vec![tx.serialize_hex()])
             .await
     }
 
+    /// Get statistics of a block, by height or hash. Pass the names of the `fields` to compute,
+    /// or an empty slice for all of them. Fails for blocks that the node has pruned.
+    pub async fn get_block_stats<B: Into<BlockStatsTarget>>(
+        &self,
+        block: B,
+        fields: &[&str],
+    ) -> Result<GetBlockStatsResponse, ProviderError> {
+        let fields = fields.iter().map(|f| (*f).to_owned()).collect();
+        self.request("getblockstats", GetBlockStatsParams(block.into(), fields))
+            .await
+    }
+
+    /// Check whether transactions would be accepted to the mempool, without broadcasting them.
+    /// Results are in the order of the transactions.
+    pub async fn test_mempool_accept(
+        &self,
+        txs: &[BitcoinTx],
+    ) -> Result<Vec<TestMempoolAcceptResult>, ProviderError> {
+        let hexes: Vec<String> = txs.iter().map(|tx| tx.serialize_hex()).collect();
+        self.request("testmempoolaccept", vec![hexes]).await
+    }
+
+    /// Ask the node's wallet to fund a PSBT spending `inputs` and paying `outputs`, in satoshis.
+    /// If `inputs` is empty, the wallet selects the inputs. It adds a change output if needed.
+    /// The PSBT includes the BIP32 derivation paths of the wallet's keys, so that an external
+    /// signer can sign it.
+    ///
+    /// The PSBT is returned base64 encoded, alongside the fee and the change output index.
+    pub async fn wallet_create_funded_psbt(
+        &self,
+        inputs: &[PsbtInputParam],
+        outputs: &[(Address, u64)],
+        options: FundPsbtOptions,
+    ) -> Result<WalletCreateFundedPsbtResponse, ProviderError> {
+        let outputs = outputs
+            .iter()
+            .map(|(address, value)| {
+                let mut output = std::collections::BTreeMap::new();
+                output.insert(address.as_string(), to_btc(*value));
+                output
+            })
+            .collect();
+        self.request(
+            "walletcreatefundedpsbt",
+            WalletCreateFundedPsbtParams(inputs.to_vec(), outputs, 0, options, true),
+        )
+        .await
+    }
+
+    /// Estimate the feerate needed to confirm within `target_blocks` blocks
+    pub async fn estimate_smart_fee(
+        &self,
+        target_blocks: usize,
+    ) -> Result<EstimateSmartFeeResponse, ProviderError> {
+        self.request("estimatesmartfee", vec![target_blocks]).await
+    }
+
+    /// Get the mempool entry of a transaction
+    pub async fn rpc_get_mempool_entry(
+        &self,
+        txid: TXID,
+    ) -> Result<GetMempoolEntryResponse, ProviderError> {
+        self.request("getmempoolentry", vec![txid.to_be_hex()])
+            .await
+    }
+
+    /// Check whether the node supports `scantxoutset`. It may be missing on old nodes, or
+    /// disabled by an RPC whitelist. The result is cached.
+    pub async fn supports_scan_tx_out_set(&self) -> Result<bool, ProviderError> {
+        let mut cached = self.scan_supported.lock().await;
+        if let Some(supported) = *cached {
+            return Ok(supported);
+        }
+
+        // `help` returns "help: unknown command" rather than an error for missing commands
+        let supported = match self
+            .request::<_, String>("help", vec!["scantxoutset"])
+            .await
+        {
+            Ok(help) => help.starts_with("scantxoutset"),
+            Err(ProviderError::RpcErrorResponse(e)) if e.code == ERR_METHOD_NOT_FOUND => false,
+            Err(e) => return Err(e),
+        };
+        *cached = Some(supported);
+        Ok(supported)
+    }
+
+    /// Get the progress of the txout scan in progress, if any
+    pub async fn scan_tx_out_set_status(&self) -> Result<Option<ScanTxOutStatus>, ProviderError> {
+        self.request("scantxoutset", vec!["status"]).await
+    }
+
+    /// Abort the txout scan in progress. Returns true if a scan was aborted
+    pub async fn scan_tx_out_set_abort(&self) -> Result<bool, ProviderError> {
+        self.request("scantxoutset", vec!["abort"]).await
+    }
+
     /// Start a txout scan. This may take some time, and will be interrupted by future requests.
-    /// So we acquire a lock for it
+    /// So we acquire a lock for it. If a scan is already running, this errors with its progress
+    /// rather than waiting for it.
     pub async fn scan_tx_out_set_for_address_start(
         &self,
         addr: &Address,
     ) -> Result<ScanTxOutResponse, ProviderError> {
-        let _lock = self.scan_guard.lock().await;
-        self.request(
+        self.scan_tx_out_set_with_progress(addr, |_| {}).await
+    }
+
+    /// Start a txout scan, and report its progress in percent to `on_progress` once per polling
+    /// interval until it completes. Errors if the node does not support scans, or if a scan is
+    /// already running.
+    pub async fn scan_tx_out_set_with_progress<F>(
+        &self,
+        addr: &Address,
+        on_progress: F,
+    ) -> Result<ScanTxOutResponse, ProviderError>
+    where
+        F: Fn(f64) + Send + Sync,
+    {
+        if !self.supports_scan_tx_out_set().await? {
+            return Err(ProviderError::Unsupported(
+                "scantxoutset not supported by this node".to_owned(),
+            ));
+        }
+
+        let _lock = match self.scan_guard.try_lock() {
+            Some(lock) => lock,
+            None => {
+                let progress = self.scan_tx_out_set_status().await?.map(|s| s.progress);
+                return Err(ProviderError::ScanInProgress(progress.unwrap_or(0.0)));
+            }
+        };
+
+        let scan = self.request(
             "scantxoutset",
             ScanTxOutParams("start".to_owned(), vec![addr.to_descriptor()]),
-        )
-        .await
+        );
+        let progress = async {
+            loop {
+                Delay::new(self.interval).await;
+                if let Ok(Some(status)) = self.scan_tx_out_set_status().await {
+                    on_progress(status.progress);
+                }
+            }
+        };
+        pin_mut!(scan);
+        pin_mut!(progress);
+
+        match select(scan, progress).await {
+            Either::Left((resp, _)) => resp,
+            Either::Right(_) => unreachable!("progress polling never completes"),
+        }
     }
 }
 
@@ -219,10 +397,65 @@ impl<T: JsonRpcTransport + Send + Sync> BtcProvider for BitcoinRpc<T> {
         ))
     }
 
+    async fn get_block_full(&self, digest: BlockHash) -> Result<Option<Block>, ProviderError> {
+        let raw = rpc_if_found!(self.get_raw_block(digest).await);
+        let block = Block::deserialize_hex(&raw).expect("No invalid block from RPC");
+        Ok(Some(block))
+    }
+
+    async fn get_tx_detailed(&self, txid: TXID) -> Result<Option<DetailedTx>, ProviderError> {
+        let response = rpc_if_found!(self.get_raw_transaction_verbose(txid).await);
+        let tx = BitcoinTx::deserialize_hex(&response.hex).expect("No invalid tx from RPC");
+        let block_hash = if response.blockhash.is_empty() {
+            None
+        } else {
+            Some(BlockHash::from_be_hex(&response.blockhash)?)
+        };
+        let prevouts = match response.prevouts() {
+            Some(prevouts) => prevouts,
+            None => {
+                let outpoints: Vec<BitcoinOutpoint> =
+                    tx.inputs().iter().map(|input| input.outpoint).collect();
+                fetch_prevouts(self, &outpoints).await?
+            }
+        };
+        Ok(Some(DetailedTx::new(tx, block_hash, prevouts)))
+    }
+
     async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError> {
+        if self.validate_broadcast {
+            let result = self.test_mempool_accept(std::slice::from_ref(&tx)).await?;
+            if let Some(rejected) = result.into_iter().find(|r| !r.allowed) {
+                return Err(ProviderError::Rejected(
+                    rejected
+                        .reject_reason
+                        .unwrap_or_else(|| "rejected by testmempoolaccept".to_owned()),
+                ));
+            }
+        }
         Ok(TXID::from_be_hex(&self.send_raw_transaction(tx).await?)?)
     }
 
+    async fn estimate_fee(&self, target_blocks: usize) -> Result<FeeRate, ProviderError> {
+        let resp = self.estimate_smart_fee(target_blocks).await?;
+        resp.feerate.map(FeeRate::from_btc_per_kvb).ok_or_else(|| {
+            ProviderError::Unsupported(format!(
+                "estimatesmartfee returned no estimate: {}",
+                resp.errors.join(", ")
+            ))
+        })
+    }
+
+    async fn get_mempool_entry(&self, txid: TXID) -> Result<Option<MempoolEntry>, ProviderError> {
+        match self.rpc_get_mempool_entry(txid).await {
+            Ok(entry) => Ok(Some(entry.into())),
+            Err(ProviderError::RpcErrorResponse(e)) if e.code == ERR_INVALID_ADDRESS_OR_KEY => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Unsupported
     async fn get_outspend(
         &self,
@@ -233,7 +466,8 @@ impl<T: JsonRpcTransport + Send + Sync> BtcProvider for BitcoinRpc<T> {
         ))
     }
 
-    /// TODO: preflight to make sure scantxoutset is supported
+    /// Errors if the node does not support `scantxoutset`, or if a scan is already running.
+    /// See `scan_tx_out_set_with_progress` to monitor long scans.
     async fn get_utxos_by_address(&self, address: &Address) -> Result<Vec<Utxo>, ProviderError> {
         let resp = self.scan_tx_out_set_for_address_start(address).await?;
         Ok(resp.unspents.into_iter().map(Into::<Utxo>::into).collect())
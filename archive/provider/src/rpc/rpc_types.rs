@@ -1,9 +1,41 @@
 use bitcoins::prelude::*;
 
+use crate::types::{DetailedOutput, MempoolEntry};
+
 /// The params for getrawtransaction
 #[derive(serde::Serialize, Debug)]
 pub struct GetRawTxParams(pub String, pub usize);
 
+/// The params for getblock: the BE hex block hash, and the verbosity
+#[derive(serde::Serialize, Debug)]
+pub struct GetBlockParams(pub String, pub usize);
+
+/// The block to get stats for, by height or hash
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum BlockStatsTarget {
+    /// The block height
+    Height(usize),
+    /// The BE hex block hash
+    Hash(String),
+}
+
+impl From<usize> for BlockStatsTarget {
+    fn from(height: usize) -> Self {
+        BlockStatsTarget::Height(height)
+    }
+}
+
+impl From<BlockHash> for BlockStatsTarget {
+    fn from(hash: BlockHash) -> Self {
+        BlockStatsTarget::Hash(hash.to_be_hex())
+    }
+}
+
+/// The params for getblockstats
+#[derive(serde::Serialize, Debug)]
+pub struct GetBlockStatsParams(pub BlockStatsTarget, pub Vec<String>);
+
 /// Either a list of IDs or a list of detailed objects
 #[derive(serde::Deserialize, Debug)]
 #[serde(untagged)]
@@ -29,7 +61,72 @@ pub struct GetBlockResponse {
     pub tx: GetBlockTxList,
 }
 
-/// Response for the `gettransaction` command
+/// A script pubkey in a verbose transaction
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RpcScriptPubkey {
+    /// The hex script
+    pub hex: String,
+    /// The address encoded by the script, if it is standard
+    #[serde(default)]
+    pub address: Option<String>,
+    /// The script type, e.g. "witness_v0_keyhash"
+    #[serde(rename = "type")]
+    pub script_type: String,
+}
+
+impl RpcScriptPubkey {
+    /// The script pubkey
+    pub fn script_pubkey(&self) -> ScriptPubkey {
+        ScriptPubkey::new(hex::decode(&self.hex).expect("valid API response"))
+    }
+}
+
+/// The output spent by an input in a verbose transaction. Only reported at verbosity 2
+#[allow(non_snake_case)]
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RpcPrevout {
+    /// Whether the output was created by a coinbase transaction
+    pub generated: bool,
+    /// The height of the block containing the output
+    pub height: usize,
+    /// The output value in BTC
+    pub value: f64,
+    /// The output script
+    pub scriptPubKey: RpcScriptPubkey,
+}
+
+/// An input in a verbose transaction
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RpcVin {
+    /// The BE txid of the spent output. Absent for coinbase inputs
+    #[serde(default)]
+    pub txid: Option<String>,
+    /// The index of the spent output. Absent for coinbase inputs
+    #[serde(default)]
+    pub vout: Option<u32>,
+    /// The hex coinbase script. Present only for coinbase inputs
+    #[serde(default)]
+    pub coinbase: Option<String>,
+    /// The sequence number
+    pub sequence: u32,
+    /// The output spent by this input. Present only at verbosity 2, on nodes that support it
+    #[serde(default)]
+    pub prevout: Option<RpcPrevout>,
+}
+
+/// An output in a verbose transaction
+#[allow(non_snake_case)]
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RpcVout {
+    /// The output value in BTC
+    pub value: f64,
+    /// The index of the output
+    pub n: u32,
+    /// The output script
+    pub scriptPubKey: RpcScriptPubkey,
+}
+
+/// Response for the `getrawtransaction` command
 ///
 /// https://bitcoincore.org/en/doc/0.20.0/rpc/rawtransactions/getrawtransaction/
 #[derive(serde::Deserialize, Debug)]
@@ -43,6 +140,39 @@ pub struct GetRawTransactionResponse {
     pub blockhash: String,
     /// The number of confirmations the tx has received. -1 for unconfirmed
     pub confirmations: isize,
+    /// The inputs
+    #[serde(default)]
+    pub vin: Vec<RpcVin>,
+    /// The outputs
+    #[serde(default)]
+    pub vout: Vec<RpcVout>,
+    /// The fee in BTC. Present only at verbosity 2, when the node has the prevouts
+    #[serde(default)]
+    pub fee: Option<f64>,
+}
+
+impl GetRawTransactionResponse {
+    /// The outputs spent by the inputs, in input order. Prevouts of coinbase inputs are `None`.
+    /// `None` if the node did not report the prevouts, i.e. at verbosity 1, or on nodes that
+    /// do not support verbosity 2
+    pub fn prevouts(&self) -> Option<Vec<Option<DetailedOutput>>> {
+        self.vin
+            .iter()
+            .map(|vin| match (&vin.coinbase, &vin.prevout) {
+                (Some(_), _) => Some(None),
+                (None, Some(prevout)) => Some(Some(DetailedOutput::new(
+                    to_sats(prevout.value),
+                    prevout.scriptPubKey.script_pubkey(),
+                ))),
+                (None, None) => None,
+            })
+            .collect()
+    }
+
+    /// The fee in satoshis, if reported
+    pub fn fee(&self) -> Option<u64> {
+        self.fee.map(to_sats)
+    }
 }
 
 /// The ScanTxOut paramaters
@@ -75,7 +205,7 @@ impl From<RpcUtxo> for Utxo {
                 txid: TXID::from_be_hex(&src.txid).expect("valid API respopnse"),
                 idx: src.vout,
             },
-            src.amount,
+            Amount::from_sat(src.amount),
             script_pubkey,
             spend_script,
         )
@@ -94,3 +224,360 @@ pub struct ScanTxOutResponse {
     /// The unspent txns
     pub unspents: Vec<RpcUtxo>,
 }
+
+/// The response for `scantxoutset` command with the "status" action. `None` if no scan is in
+/// progress
+///
+/// https://bitcoincore.org/en/doc/0.20.0/rpc/blockchain/scantxoutset/
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+pub struct ScanTxOutStatus {
+    /// The scan progress, in percent
+    pub progress: f64,
+}
+
+/// The response for the `estimatesmartfee` command
+///
+/// https://bitcoincore.org/en/doc/0.20.0/rpc/util/estimatesmartfee/
+#[derive(serde::Deserialize, Debug)]
+pub struct EstimateSmartFeeResponse {
+    /// The estimated feerate in BTC/kvB. Absent if no estimate is available
+    pub feerate: Option<f64>,
+    /// Errors encountered during processing
+    #[serde(default)]
+    pub errors: Vec<String>,
+    /// The block target for which the estimate is valid
+    pub blocks: usize,
+}
+
+/// The fees in a `GetMempoolEntryResponse`, in BTC
+#[derive(serde::Deserialize, Debug)]
+pub struct MempoolEntryFees {
+    /// The transaction fee
+    pub base: f64,
+    /// The fees of the transaction and its in-mempool ancestors
+    pub ancestor: f64,
+    /// The fees of the transaction and its in-mempool descendants
+    pub descendant: f64,
+}
+
+/// The response for the `getmempoolentry` command
+///
+/// https://bitcoincore.org/en/doc/0.20.0/rpc/blockchain/getmempoolentry/
+#[derive(serde::Deserialize, Debug)]
+pub struct GetMempoolEntryResponse {
+    /// The transaction weight
+    pub weight: usize,
+    /// The number of in-mempool ancestors, including this one
+    pub ancestorcount: usize,
+    /// The vsize of in-mempool ancestors, including this one
+    pub ancestorsize: usize,
+    /// The number of in-mempool descendants, including this one
+    pub descendantcount: usize,
+    /// The vsize of in-mempool descendants, including this one
+    pub descendantsize: usize,
+    /// The fees
+    pub fees: MempoolEntryFees,
+    /// Whether this transaction could be replaced due to BIP125
+    #[serde(rename = "bip125-replaceable")]
+    pub bip125_replaceable: bool,
+}
+
+/// The response for the `getblockstats` command. Amounts are in satoshis, and feerates in
+/// sat/vbyte. Stats that were not requested are `None`.
+///
+/// https://bitcoincore.org/en/doc/0.20.0/rpc/blockchain/getblockstats/
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct GetBlockStatsResponse {
+    /// The BE hex block hash
+    pub blockhash: Option<String>,
+    /// The block height
+    pub height: Option<usize>,
+    /// The block time
+    pub time: Option<u64>,
+    /// The block median time past
+    pub mediantime: Option<u64>,
+    /// The number of transactions, including the coinbase
+    pub txs: Option<usize>,
+    /// The number of inputs, excluding the coinbase
+    pub ins: Option<usize>,
+    /// The number of outputs
+    pub outs: Option<usize>,
+    /// The block subsidy
+    pub subsidy: Option<u64>,
+    /// The total fees
+    pub totalfee: Option<u64>,
+    /// The total output value, excluding the coinbase
+    pub total_out: Option<u64>,
+    /// The total size of all non-coinbase transactions
+    pub total_size: Option<usize>,
+    /// The total weight of all non-coinbase transactions
+    pub total_weight: Option<usize>,
+    /// The number of segwit transactions
+    pub swtxs: Option<usize>,
+    /// The total size of all segwit transactions
+    pub swtotal_size: Option<usize>,
+    /// The total weight of all segwit transactions
+    pub swtotal_weight: Option<usize>,
+    /// The average fee
+    pub avgfee: Option<u64>,
+    /// The median fee
+    pub medianfee: Option<u64>,
+    /// The minimum fee
+    pub minfee: Option<u64>,
+    /// The maximum fee
+    pub maxfee: Option<u64>,
+    /// The average feerate
+    pub avgfeerate: Option<u64>,
+    /// The minimum feerate
+    pub minfeerate: Option<u64>,
+    /// The maximum feerate
+    pub maxfeerate: Option<u64>,
+    /// The 10th, 25th, 50th, 75th, and 90th feerate percentiles, weighted by size
+    pub feerate_percentiles: Option<[u64; 5]>,
+    /// The average transaction size
+    pub avgtxsize: Option<usize>,
+    /// The median transaction size
+    pub mediantxsize: Option<usize>,
+    /// The minimum transaction size
+    pub mintxsize: Option<usize>,
+    /// The maximum transaction size
+    pub maxtxsize: Option<usize>,
+    /// The change in the number of UTXOs
+    pub utxo_increase: Option<i64>,
+    /// The change in the size of the UTXO set
+    pub utxo_size_inc: Option<i64>,
+}
+
+/// Options for the `walletcreatefundedpsbt` command. Unset options use the node's defaults
+///
+/// https://bitcoincore.org/en/doc/0.21.0/rpc/wallet/walletcreatefundedpsbt/
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct FundPsbtOptions {
+    /// The address to send change to. Defaults to a new wallet address
+    #[serde(rename = "changeAddress", skip_serializing_if = "Option::is_none")]
+    pub change_address: Option<String>,
+    /// The index of the change output. Defaults to a random index
+    #[serde(rename = "changePosition", skip_serializing_if = "Option::is_none")]
+    pub change_position: Option<usize>,
+    /// Whether to lock the selected UTXOs, so that other wallet txns don't spend them
+    #[serde(rename = "lockUnspents", skip_serializing_if = "Option::is_none")]
+    pub lock_unspents: Option<bool>,
+    /// The feerate in sat/vbyte. Requires Bitcoin Core 0.21 or later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_rate: Option<f64>,
+    /// The confirmation target in blocks, used to estimate the feerate if `fee_rate` is unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conf_target: Option<usize>,
+    /// The indices of the outputs to deduct the fee from
+    #[serde(
+        rename = "subtractFeeFromOutputs",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub subtract_fee_from_outputs: Option<Vec<usize>>,
+    /// Whether to signal BIP125 replaceability
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaceable: Option<bool>,
+}
+
+/// An input in the params for `walletcreatefundedpsbt`
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PsbtInputParam {
+    /// The BE txid
+    pub txid: String,
+    /// The output index
+    pub vout: u32,
+}
+
+/// The params for `walletcreatefundedpsbt`: the inputs, the outputs as address to BTC amount
+/// maps, the locktime, the options, and whether to include BIP32 derivation paths
+#[derive(serde::Serialize, Debug)]
+pub struct WalletCreateFundedPsbtParams(
+    pub Vec<PsbtInputParam>,
+    pub Vec<std::collections::BTreeMap<String, f64>>,
+    pub u32,
+    pub FundPsbtOptions,
+    pub bool,
+);
+
+/// The response for the `walletcreatefundedpsbt` command
+///
+/// https://bitcoincore.org/en/doc/0.21.0/rpc/wallet/walletcreatefundedpsbt/
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct WalletCreateFundedPsbtResponse {
+    /// The funded PSBT, base64 encoded
+    pub psbt: String,
+    /// The fee in BTC
+    #[serde(rename = "fee")]
+    pub fee_btc: f64,
+    /// The index of the change output. -1 if there is no change output
+    pub changepos: i64,
+}
+
+impl WalletCreateFundedPsbtResponse {
+    /// The fee in satoshis
+    pub fn fee(&self) -> u64 {
+        to_sats(self.fee_btc)
+    }
+
+    /// The index of the change output, if any
+    pub fn change_index(&self) -> Option<usize> {
+        if self.changepos < 0 {
+            None
+        } else {
+            Some(self.changepos as usize)
+        }
+    }
+}
+
+/// The fees in a `TestMempoolAcceptResult`, in BTC
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct MempoolAcceptFees {
+    /// The transaction fee
+    pub base: f64,
+}
+
+/// An entry in the response for the `testmempoolaccept` command
+///
+/// https://bitcoincore.org/en/doc/0.20.0/rpc/rawtransactions/testmempoolaccept/
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct TestMempoolAcceptResult {
+    /// The BE txid
+    pub txid: String,
+    /// Whether the transaction would be accepted to the mempool
+    pub allowed: bool,
+    /// The reason for rejection, if not allowed. E.g. "dust" or "min relay fee not met"
+    #[serde(rename = "reject-reason")]
+    pub reject_reason: Option<String>,
+    /// The transaction vsize, if allowed
+    pub vsize: Option<usize>,
+    /// The fees, if allowed
+    pub fees: Option<MempoolAcceptFees>,
+}
+
+impl TestMempoolAcceptResult {
+    /// The transaction fee in satoshis, if allowed
+    pub fn fee(&self) -> Option<u64> {
+        self.fees.as_ref().map(|fees| to_sats(fees.base))
+    }
+}
+
+// Convert a BTC amount to satoshis
+fn to_sats(btc: f64) -> u64 {
+    (btc * 100_000_000.0).round() as u64
+}
+
+// Convert a satoshi amount to BTC
+pub(crate) fn to_btc(sats: u64) -> f64 {
+    sats as f64 / 100_000_000.0
+}
+
+impl From<GetMempoolEntryResponse> for MempoolEntry {
+    fn from(src: GetMempoolEntryResponse) -> MempoolEntry {
+        MempoolEntry {
+            fee: to_sats(src.fees.base),
+            weight: src.weight,
+            ancestor_count: src.ancestorcount,
+            ancestor_fees: to_sats(src.fees.ancestor),
+            ancestor_vsize: src.ancestorsize,
+            descendant_count: src.descendantcount,
+            descendant_fees: to_sats(src.fees.descendant),
+            descendant_vsize: src.descendantsize,
+            bip125_replaceable: src.bip125_replaceable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_serializes_funded_psbt_requests() {
+        let mut output = std::collections::BTreeMap::new();
+        output.insert(
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4".to_owned(),
+            to_btc(12_345),
+        );
+        let options = FundPsbtOptions {
+            fee_rate: Some(2.5),
+            replaceable: Some(true),
+            ..Default::default()
+        };
+        let params = WalletCreateFundedPsbtParams(vec![], vec![output], 0, options, true);
+        assert_eq!(
+            serde_json::to_string(&params).unwrap(),
+            r#"[[],[{"bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4":0.00012345}],0,{"fee_rate":2.5,"replaceable":true},true]"#
+        );
+
+        let response: WalletCreateFundedPsbtResponse =
+            serde_json::from_str(r#"{"psbt": "cHNidP8=", "fee": 0.0000141, "changepos": -1}"#)
+                .unwrap();
+        assert_eq!(response.fee(), 1410);
+        assert_eq!(response.change_index(), None);
+    }
+
+    #[test]
+    fn it_deserializes_verbose_transactions() {
+        let response: GetRawTransactionResponse = serde_json::from_str(
+            r#"{
+                "txid": "aa", "hash": "aa", "hex": "00", "confirmations": 3, "blockhash": "bb",
+                "fee": 0.0000141,
+                "vin": [{
+                    "txid": "cc", "vout": 1, "sequence": 4294967293,
+                    "scriptSig": {"asm": "", "hex": ""},
+                    "prevout": {
+                        "generated": false, "height": 700000, "value": 0.0001,
+                        "scriptPubKey": {
+                            "asm": "", "hex": "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                            "address": "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                            "type": "witness_v0_keyhash"
+                        }
+                    }
+                }],
+                "vout": [{
+                    "value": 0.0000859, "n": 0,
+                    "scriptPubKey": {"asm": "", "hex": "6a00", "type": "nulldata"}
+                }]
+            }"#,
+        )
+        .unwrap();
+        let prevouts = response.prevouts().unwrap();
+        let prevout = prevouts[0].as_ref().unwrap();
+        assert_eq!(prevout.value, 10_000);
+        assert_eq!(
+            prevout.address.as_ref().unwrap().as_string(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        assert_eq!(response.fee(), Some(1410));
+        assert_eq!(response.vout[0].scriptPubKey.script_type, "nulldata");
+
+        // verbosity 1 has no prevouts
+        let response: GetRawTransactionResponse = serde_json::from_str(
+            r#"{"txid": "aa", "hex": "00", "confirmations": 3,
+                "vin": [{"txid": "cc", "vout": 1, "sequence": 0}], "vout": []}"#,
+        )
+        .unwrap();
+        assert!(response.prevouts().is_none());
+    }
+
+    #[test]
+    fn it_serializes_block_stats_requests() {
+        let params = GetBlockStatsParams(
+            BlockStatsTarget::from(1000),
+            vec!["subsidy".to_owned(), "feerate_percentiles".to_owned()],
+        );
+        assert_eq!(
+            serde_json::to_string(&params).unwrap(),
+            r#"[1000,["subsidy","feerate_percentiles"]]"#
+        );
+
+        let stats: GetBlockStatsResponse = serde_json::from_str(
+            r#"{"subsidy":625000000,"feerate_percentiles":[1,2,5,10,20],"height":650000}"#,
+        )
+        .unwrap();
+        assert_eq!(stats.subsidy, Some(625_000_000));
+        assert_eq!(stats.feerate_percentiles, Some([1, 2, 5, 10, 20]));
+        assert_eq!(stats.totalfee, None);
+    }
+}
@@ -0,0 +1,543 @@
+//! Header chain validation.
+//!
+//! A `HeaderChain` starts from a trusted `Checkpoint`, and accepts headers only if they extend
+//! its tip with a valid parent link, the expected difficulty target, and sufficient proof of
+//! work. It tracks the cumulative work of the chain, so that applications can compare chains
+//! served by different providers, and detect a provider feeding them a fake chain.
+//!
+//! Testnet's minimum-difficulty blocks are not supported.
+
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use thiserror::Error;
+
+use bitcoins::hashes::BlockHash;
+use coins_core::hashes::MarkedDigestOutput;
+
+use crate::{
+    provider::{BtcProvider, ProviderError},
+    store::ChainStore,
+    types::RawHeader,
+};
+
+/// The number of blocks between difficulty retargets
+pub const RETARGET_INTERVAL: usize = 2016;
+
+/// The expected duration of a retarget period, in seconds
+pub const TARGET_TIMESPAN: u32 = 14 * 24 * 60 * 60;
+
+/// Errors produced while validating headers
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SpvError {
+    /// The header does not build on the current tip
+    #[error("Header at height {0} does not build on the chain tip")]
+    WrongParent(usize),
+
+    /// The header's difficulty target is not the one required by the chain
+    #[error("Header at height {height} has target {actual:#x}, expected {expected:#x}")]
+    WrongTarget {
+        /// The header height
+        height: usize,
+        /// The expected compact target
+        expected: u32,
+        /// The header's compact target
+        actual: u32,
+    },
+
+    /// The header's compact target is negative or overflows
+    #[error("Header at height {0} has an invalid target")]
+    InvalidTarget(usize),
+
+    /// The header hash does not meet its target
+    #[error("Header at height {0} has insufficient proof of work")]
+    InsufficientWork(usize),
+
+    /// The header hash does not match a pinned checkpoint
+    #[error("Header at height {0} does not match the checkpoint")]
+    CheckpointMismatch(usize),
+}
+
+/// Consensus parameters for header validation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpvParams {
+    /// The easiest allowed target, in compact form
+    pub pow_limit: u32,
+    /// Whether the target is retargeted every `RETARGET_INTERVAL` blocks. False on regtest
+    pub retargeting: bool,
+}
+
+/// Bitcoin mainnet parameters
+pub const MAINNET_PARAMS: SpvParams = SpvParams {
+    pow_limit: 0x1d00_ffff,
+    retargeting: true,
+};
+
+/// Bitcoin regtest parameters
+pub const REGTEST_PARAMS: SpvParams = SpvParams {
+    pow_limit: 0x207f_ffff,
+    retargeting: false,
+};
+
+/// A validated block, and the state needed to validate its descendants. Persist the tip of a
+/// `HeaderChain` as a checkpoint to resume validation later.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// The block height
+    pub height: usize,
+    /// The block hash
+    pub hash: BlockHash,
+    /// The block's compact target
+    pub bits: u32,
+    /// The block timestamp
+    pub timestamp: u32,
+    /// The timestamp of the first block of the block's retarget period
+    pub period_start: u32,
+    /// The cumulative work of the chain up to and including the block. Saturates at
+    /// `u128::MAX`
+    pub work: u128,
+}
+
+impl Checkpoint {
+    /// The Bitcoin mainnet genesis block
+    pub fn mainnet_genesis() -> Self {
+        Self {
+            height: 0,
+            hash: BlockHash::from_be_hex(
+                "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            )
+            .expect("valid hex"),
+            bits: 0x1d00_ffff,
+            timestamp: 1_231_006_505,
+            period_start: 1_231_006_505,
+            work: 0x1_0001_0001,
+        }
+    }
+}
+
+/// A chain of validated headers. See the module docs.
+#[derive(Clone, Debug)]
+pub struct HeaderChain {
+    params: SpvParams,
+    tip: Checkpoint,
+    pinned: BTreeMap<usize, BlockHash>,
+}
+
+impl HeaderChain {
+    /// Instantiate a chain starting from a trusted checkpoint
+    pub fn new(params: SpvParams, checkpoint: Checkpoint) -> Self {
+        Self {
+            params,
+            tip: checkpoint,
+            pinned: Default::default(),
+        }
+    }
+
+    /// Instantiate a chain from the tip stored in a `ChainStore`, or from a trusted checkpoint if
+    /// the store has no tip
+    pub fn resume<S: ChainStore + ?Sized>(
+        params: SpvParams,
+        checkpoint: Checkpoint,
+        store: &S,
+    ) -> Result<Self, ProviderError> {
+        Ok(Self::new(params, store.tip()?.unwrap_or(checkpoint)))
+    }
+
+    /// Pin a known block hash. Headers at that height must match it
+    pub fn pin(mut self, height: usize, hash: BlockHash) -> Self {
+        self.pinned.insert(height, hash);
+        self
+    }
+
+    /// The chain tip. Persist it to resume validation later
+    pub fn tip(&self) -> Checkpoint {
+        self.tip
+    }
+
+    /// The height of the chain tip
+    pub fn height(&self) -> usize {
+        self.tip.height
+    }
+
+    /// The cumulative work of the chain
+    pub fn work(&self) -> u128 {
+        self.tip.work
+    }
+
+    /// Validate headers, and extend the chain with them. The headers must be in order, and the
+    /// first must build on the tip. If any header is invalid, the chain is not modified
+    pub fn extend(&mut self, headers: &[RawHeader]) -> Result<(), SpvError> {
+        let mut tip = self.tip;
+        for header in headers.iter() {
+            tip = self.validate(&tip, header)?;
+        }
+        self.tip = tip;
+        Ok(())
+    }
+
+    /// Fetch up to `headers` headers after the tip from a provider, validate them, and extend
+    /// the chain with them. Returns the number of headers added.
+    pub async fn sync(
+        &mut self,
+        provider: &dyn BtcProvider,
+        headers: usize,
+    ) -> Result<usize, ProviderError> {
+        let headers = provider
+            .get_raw_header_range(self.height() + 1, headers)
+            .await?;
+        self.extend(&headers)?;
+        Ok(headers.len())
+    }
+
+    /// Like `sync`, and then write the new headers and the new tip to a `ChainStore`. The tip
+    /// is written last, so that a chain resumed after an interrupted write never refers to
+    /// missing headers.
+    pub async fn sync_to_store<S: ChainStore + ?Sized>(
+        &mut self,
+        provider: &dyn BtcProvider,
+        headers: usize,
+        store: &mut S,
+    ) -> Result<usize, ProviderError> {
+        let start = self.height() + 1;
+        let headers = provider.get_raw_header_range(start, headers).await?;
+        self.extend(&headers)?;
+        for (height, header) in (start..).zip(headers.iter()) {
+            store.put_header(height, *header)?;
+        }
+        store.set_tip(self.tip)?;
+        Ok(headers.len())
+    }
+
+    // Validate a header against its parent, and produce the new tip
+    fn validate(&self, parent: &Checkpoint, header: &RawHeader) -> Result<Checkpoint, SpvError> {
+        let height = parent.height + 1;
+        if header.parent() != parent.hash {
+            return Err(SpvError::WrongParent(height));
+        }
+
+        let retarget = self.params.retargeting && height.is_multiple_of(RETARGET_INTERVAL);
+        let expected = if retarget {
+            next_bits(
+                parent.bits,
+                parent.period_start,
+                parent.timestamp,
+                self.params.pow_limit,
+            )
+        } else {
+            parent.bits
+        };
+        if header.bits() != expected {
+            return Err(SpvError::WrongTarget {
+                height,
+                expected,
+                actual: header.bits(),
+            });
+        }
+
+        let target = U256::from_compact(expected).ok_or(SpvError::InvalidTarget(height))?;
+        let hash = header.digest();
+        if U256::from_le_bytes(hash.as_slice()) > target {
+            return Err(SpvError::InsufficientWork(height));
+        }
+
+        if let Some(pinned) = self.pinned.get(&height) {
+            if *pinned != hash {
+                return Err(SpvError::CheckpointMismatch(height));
+            }
+        }
+
+        Ok(Checkpoint {
+            height,
+            hash,
+            bits: expected,
+            timestamp: header.timestamp(),
+            period_start: if retarget {
+                header.timestamp()
+            } else {
+                parent.period_start
+            },
+            work: parent.work.saturating_add(block_work(&target)),
+        })
+    }
+}
+
+/// Calculate the compact target of the first block of a retarget period, from the compact
+/// target of the previous period, and the timestamps of its first and last blocks.
+pub fn next_bits(bits: u32, period_start: u32, period_end: u32, pow_limit: u32) -> u32 {
+    let timespan = period_end.saturating_sub(period_start);
+    let timespan = timespan.clamp(TARGET_TIMESPAN / 4, TARGET_TIMESPAN * 4);
+
+    let limit = U256::from_compact(pow_limit).expect("valid pow limit");
+    let target = U256::from_compact(bits)
+        .unwrap_or(limit)
+        .mul_u64(timespan as u64)
+        .div_u64(TARGET_TIMESPAN as u64);
+    std::cmp::min(target, limit).to_compact()
+}
+
+// The expected number of hashes needed to meet a target, i.e. 2**256 / (target + 1)
+fn block_work(target: &U256) -> u128 {
+    // 2**256 / (target + 1) == (~target / (target + 1)) + 1
+    let work = target.not().div(&target.add_one()).add_one();
+    work.to_u128_saturating()
+}
+
+// A minimal unsigned 256-bit integer, as little-endian limbs
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct U256([u64; 4]);
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl U256 {
+    const ZERO: U256 = U256([0; 4]);
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, byte) in bytes.iter().take(32).enumerate() {
+            limbs[i / 8] |= (*byte as u64) << (8 * (i % 8));
+        }
+        U256(limbs)
+    }
+
+    // The number of significant bits
+    fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + 64 - self.0[i].leading_zeros();
+            }
+        }
+        0
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        self.0[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn shl(&self, shift: u32) -> Self {
+        let mut limbs = [0u64; 4];
+        let (words, bits) = ((shift / 64) as usize, shift % 64);
+        for i in (words..4).rev() {
+            limbs[i] = self.0[i - words] << bits;
+            if bits > 0 && i > words {
+                limbs[i] |= self.0[i - words - 1] >> (64 - bits);
+            }
+        }
+        U256(limbs)
+    }
+
+    fn shr(&self, shift: u32) -> Self {
+        let mut limbs = [0u64; 4];
+        let (words, bits) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in limbs
+            .iter_mut()
+            .take(4usize.saturating_sub(words))
+            .enumerate()
+        {
+            *limb = self.0[i + words] >> bits;
+            if bits > 0 && i + words < 3 {
+                *limb |= self.0[i + words + 1] << (64 - bits);
+            }
+        }
+        U256(limbs)
+    }
+
+    fn not(&self) -> Self {
+        U256([!self.0[0], !self.0[1], !self.0[2], !self.0[3]])
+    }
+
+    // Wrapping increment
+    fn add_one(&self) -> Self {
+        let mut limbs = self.0;
+        for limb in limbs.iter_mut() {
+            let (sum, carry) = limb.overflowing_add(1);
+            *limb = sum;
+            if !carry {
+                break;
+            }
+        }
+        U256(limbs)
+    }
+
+    // Wrapping subtraction
+    fn sub(&self, other: &Self) -> Self {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = b1 || b2;
+        }
+        U256(limbs)
+    }
+
+    // Wrapping multiplication
+    fn mul_u64(&self, rhs: u64) -> Self {
+        let mut limbs = [0u64; 4];
+        let mut carry = 0u128;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let product = self.0[i] as u128 * rhs as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        U256(limbs)
+    }
+
+    fn div_u64(&self, rhs: u64) -> Self {
+        let mut limbs = [0u64; 4];
+        let mut rem = 0u128;
+        for i in (0..4).rev() {
+            let dividend = (rem << 64) | self.0[i] as u128;
+            limbs[i] = (dividend / rhs as u128) as u64;
+            rem = dividend % rhs as u128;
+        }
+        U256(limbs)
+    }
+
+    // Binary long division
+    fn div(&self, rhs: &Self) -> Self {
+        let mut quotient = U256::ZERO;
+        let mut rem = U256::ZERO;
+        for i in (0..256).rev() {
+            rem = rem.shl(1);
+            rem.0[0] |= self.bit(i) as u64;
+            if rem >= *rhs {
+                rem = rem.sub(rhs);
+                quotient.0[i / 64] |= 1 << (i % 64);
+            }
+        }
+        quotient
+    }
+
+    fn to_u128_saturating(self) -> u128 {
+        if self.0[2] != 0 || self.0[3] != 0 {
+            u128::MAX
+        } else {
+            (self.0[1] as u128) << 64 | self.0[0] as u128
+        }
+    }
+
+    // Decode a compact target, as in Bitcoin Core's `SetCompact`. `None` if negative or
+    // overflowing
+    fn from_compact(bits: u32) -> Option<Self> {
+        let size = bits >> 24;
+        let word = bits & 0x007f_ffff;
+        let negative = word != 0 && bits & 0x0080_0000 != 0;
+        let overflow =
+            word != 0 && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32));
+        if negative || overflow {
+            return None;
+        }
+
+        let word = U256([word as u64, 0, 0, 0]);
+        if size <= 3 {
+            Some(word.shr(8 * (3 - size)))
+        } else {
+            Some(word.shl(8 * (size - 3)))
+        }
+    }
+
+    // Encode a compact target, as in Bitcoin Core's `GetCompact`
+    fn to_compact(self) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut word = if size <= 3 {
+            (self.0[0] << (8 * (3 - size))) as u32
+        } else {
+            self.shr(8 * (size - 3)).0[0] as u32
+        };
+        // The sign bit is set. Shift the word right, and increase the size
+        if word & 0x0080_0000 != 0 {
+            word >>= 8;
+            size += 1;
+        }
+        word | size << 24
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use coins_core::ser::ByteFormat;
+
+    #[test]
+    fn it_converts_compact_targets() {
+        for bits in [
+            0x1d00_ffff,
+            0x1c05_a3f4,
+            0x1b04_864c,
+            0x207f_ffff,
+            0x1703_a30c,
+        ]
+        .iter()
+        {
+            assert_eq!(U256::from_compact(*bits).unwrap().to_compact(), *bits);
+        }
+        assert_eq!(U256::from_compact(0x0480_0000), Some(U256::ZERO));
+        assert_eq!(U256::from_compact(0x0492_3456), None);
+        assert_eq!(U256::from_compact(0xff12_3456), None);
+    }
+
+    #[test]
+    fn it_retargets() {
+        // Cases from Bitcoin Core's pow_tests.cpp
+        assert_eq!(
+            next_bits(0x1d00_ffff, 1_261_130_161, 1_262_152_739, 0x1d00_ffff),
+            0x1d00_d86a
+        );
+        assert_eq!(
+            next_bits(0x1d00_ffff, 1_231_006_505, 1_233_061_996, 0x1d00_ffff),
+            0x1d00_ffff
+        );
+        assert_eq!(
+            next_bits(0x1c05_a3f4, 1_279_008_237, 1_279_297_671, 0x1d00_ffff),
+            0x1c01_68fd
+        );
+        assert_eq!(
+            next_bits(0x1c38_7f6f, 1_263_163_443, 1_269_211_443, 0x1d00_ffff),
+            0x1d00_e1fd
+        );
+    }
+
+    #[test]
+    fn it_validates_headers() {
+        let block_1 = RawHeader::deserialize_hex("010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299").unwrap();
+        let block_2 = RawHeader::deserialize_hex("010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d08d2bd61").unwrap();
+
+        let mut chain = HeaderChain::new(MAINNET_PARAMS, Checkpoint::mainnet_genesis());
+        assert_eq!(
+            chain.clone().extend(&[block_2]),
+            Err(SpvError::WrongParent(1))
+        );
+
+        // a header with a bad nonce does not meet its target
+        let mut bad_nonce = block_1;
+        bad_nonce.as_mut()[79] ^= 1;
+        assert_eq!(
+            chain.extend(&[block_1, block_2, bad_nonce]),
+            Err(SpvError::WrongParent(3))
+        );
+        assert_eq!(chain.height(), 0);
+        assert_eq!(
+            chain.clone().extend(&[bad_nonce]),
+            Err(SpvError::InsufficientWork(1))
+        );
+
+        // pinned checkpoints must match
+        let pinned = chain.clone().pin(1, block_2.digest()).extend(&[block_1]);
+        assert_eq!(pinned, Err(SpvError::CheckpointMismatch(1)));
+
+        chain.extend(&[block_1, block_2]).unwrap();
+        assert_eq!(chain.height(), 2);
+        assert_eq!(chain.tip().hash, block_2.digest());
+        assert_eq!(chain.work(), 0x3_0003_0003);
+    }
+}
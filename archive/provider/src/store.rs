@@ -0,0 +1,283 @@
+//! Persistent chain storage.
+//!
+//! A `ChainStore` holds headers by height, the tip of a validated `HeaderChain`, and an index
+//! of watched scripts. `HeaderChain::resume` and `ChainEvents::store` use it to pick up where
+//! the previous process left off, instead of syncing from scratch on every launch.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use bitcoins::{hashes::BlockHash, types::ScriptPubkey};
+use coins_core::{hashes::MarkedDigestOutput, ser::ByteFormat};
+
+use crate::{provider::ProviderError, spv::Checkpoint, types::RawHeader};
+
+/// Persists headers, the validated tip, and watched scripts between runs
+pub trait ChainStore: Send {
+    /// Store a header at a height, replacing any header stored there
+    fn put_header(&mut self, height: usize, header: RawHeader) -> Result<(), ProviderError>;
+
+    /// Get the header at a height, if it is stored
+    fn get_header(&self, height: usize) -> Result<Option<RawHeader>, ProviderError>;
+
+    /// The height of the highest stored header, if any
+    fn header_height(&self) -> Result<Option<usize>, ProviderError>;
+
+    /// Remove all headers above a height, e.g. after a reorg
+    fn truncate(&mut self, height: usize) -> Result<(), ProviderError>;
+
+    /// The stored tip of a validated header chain, if any
+    fn tip(&self) -> Result<Option<Checkpoint>, ProviderError>;
+
+    /// Store the tip of a validated header chain, replacing any previously stored
+    fn set_tip(&mut self, tip: Checkpoint) -> Result<(), ProviderError>;
+
+    /// The watched scripts
+    fn scripts(&self) -> Result<Vec<ScriptPubkey>, ProviderError>;
+
+    /// Add a script to the watched scripts. Does nothing if it is already watched
+    fn watch_script(&mut self, script: ScriptPubkey) -> Result<(), ProviderError>;
+}
+
+/// A `ChainStore` that keeps everything in memory
+#[derive(Clone, Debug, Default)]
+pub struct MemoryChainStore {
+    headers: BTreeMap<usize, RawHeader>,
+    tip: Option<Checkpoint>,
+    scripts: Vec<ScriptPubkey>,
+}
+
+impl ChainStore for MemoryChainStore {
+    fn put_header(&mut self, height: usize, header: RawHeader) -> Result<(), ProviderError> {
+        self.headers.insert(height, header);
+        Ok(())
+    }
+
+    fn get_header(&self, height: usize) -> Result<Option<RawHeader>, ProviderError> {
+        Ok(self.headers.get(&height).copied())
+    }
+
+    fn header_height(&self) -> Result<Option<usize>, ProviderError> {
+        Ok(self.headers.keys().next_back().copied())
+    }
+
+    fn truncate(&mut self, height: usize) -> Result<(), ProviderError> {
+        self.headers.split_off(&(height + 1));
+        Ok(())
+    }
+
+    fn tip(&self) -> Result<Option<Checkpoint>, ProviderError> {
+        Ok(self.tip)
+    }
+
+    fn set_tip(&mut self, tip: Checkpoint) -> Result<(), ProviderError> {
+        self.tip = Some(tip);
+        Ok(())
+    }
+
+    fn scripts(&self) -> Result<Vec<ScriptPubkey>, ProviderError> {
+        Ok(self.scripts.clone())
+    }
+
+    fn watch_script(&mut self, script: ScriptPubkey) -> Result<(), ProviderError> {
+        if !self.scripts.contains(&script) {
+            self.scripts.push(script);
+        }
+        Ok(())
+    }
+}
+
+/// A `ChainStore` backed by files in a directory.
+///
+/// Headers are stored as 80-byte records in the `headers` file, at an offset determined by
+/// their height. Heights below the first stored header are left as holes, so a store that
+/// starts from a recent checkpoint stays small on filesystems with sparse file support. The tip
+/// and the watched scripts are stored as text in the `tip` and `scripts` files.
+#[derive(Clone, Debug)]
+pub struct FileChainStore {
+    dir: PathBuf,
+}
+
+impl FileChainStore {
+    /// Open a store in a directory, creating the directory if necessary
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, ProviderError> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_owned(),
+        })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn headers(&self) -> Result<File, ProviderError> {
+        Ok(OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.path("headers"))?)
+    }
+
+    // Replace a file's contents, via a rename so that a crash can't leave it half-written
+    fn replace(&self, name: &str, contents: &str) -> Result<(), ProviderError> {
+        let tmp = self.path(&format!("{}.tmp", name));
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, self.path(name))?;
+        Ok(())
+    }
+
+    // Read a file, treating a missing file as empty
+    fn read(&self, name: &str) -> Result<String, ProviderError> {
+        match fs::read_to_string(self.path(name)) {
+            Ok(contents) => Ok(contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn corrupt(name: &str) -> ProviderError {
+    ProviderError::custom(true, format!("Corrupt chain store file: {}", name).into())
+}
+
+impl ChainStore for FileChainStore {
+    fn put_header(&mut self, height: usize, header: RawHeader) -> Result<(), ProviderError> {
+        let mut file = self.headers()?;
+        file.seek(SeekFrom::Start(height as u64 * 80))?;
+        file.write_all(header.as_ref())?;
+        Ok(())
+    }
+
+    fn get_header(&self, height: usize) -> Result<Option<RawHeader>, ProviderError> {
+        let mut file = self.headers()?;
+        if file.metadata()?.len() < (height as u64 + 1) * 80 {
+            return Ok(None);
+        }
+        let mut buf = [0u8; 80];
+        file.seek(SeekFrom::Start(height as u64 * 80))?;
+        file.read_exact(&mut buf)?;
+        // holes read as zeroes
+        if buf.iter().all(|b| *b == 0) {
+            return Ok(None);
+        }
+        Ok(Some(buf.into()))
+    }
+
+    fn header_height(&self) -> Result<Option<usize>, ProviderError> {
+        let records = self.headers()?.metadata()?.len() / 80;
+        Ok(records.checked_sub(1).map(|height| height as usize))
+    }
+
+    fn truncate(&mut self, height: usize) -> Result<(), ProviderError> {
+        let file = self.headers()?;
+        let len = (height as u64 + 1) * 80;
+        if file.metadata()?.len() > len {
+            file.set_len(len)?;
+        }
+        Ok(())
+    }
+
+    fn tip(&self) -> Result<Option<Checkpoint>, ProviderError> {
+        let contents = self.read("tip")?;
+        let fields: Vec<&str> = contents.split_whitespace().collect();
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        if fields.len() != 6 {
+            return Err(corrupt("tip"));
+        }
+        Ok(Some(Checkpoint {
+            height: fields[0].parse().map_err(|_| corrupt("tip"))?,
+            hash: BlockHash::from_be_hex(fields[1]).map_err(|_| corrupt("tip"))?,
+            bits: u32::from_str_radix(fields[2], 16).map_err(|_| corrupt("tip"))?,
+            timestamp: fields[3].parse().map_err(|_| corrupt("tip"))?,
+            period_start: fields[4].parse().map_err(|_| corrupt("tip"))?,
+            work: u128::from_str_radix(fields[5], 16).map_err(|_| corrupt("tip"))?,
+        }))
+    }
+
+    fn set_tip(&mut self, tip: Checkpoint) -> Result<(), ProviderError> {
+        self.replace(
+            "tip",
+            &format!(
+                "{} {} {:08x} {} {} {:x}\n",
+                tip.height,
+                tip.hash.to_be_hex(),
+                tip.bits,
+                tip.timestamp,
+                tip.period_start,
+                tip.work
+            ),
+        )
+    }
+
+    fn scripts(&self) -> Result<Vec<ScriptPubkey>, ProviderError> {
+        self.read("scripts")?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| ScriptPubkey::deserialize_hex(line).map_err(|_| corrupt("scripts")))
+            .collect()
+    }
+
+    fn watch_script(&mut self, script: ScriptPubkey) -> Result<(), ProviderError> {
+        let mut scripts = self.scripts()?;
+        if scripts.contains(&script) {
+            return Ok(());
+        }
+        scripts.push(script);
+        let contents: String = scripts
+            .iter()
+            .map(|script| format!("{}\n", script.serialize_hex()))
+            .collect();
+        self.replace("scripts", &contents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spv::{HeaderChain, MAINNET_PARAMS};
+
+    fn exercise<S: ChainStore>(store: &mut S) {
+        let block_1 = RawHeader::deserialize_hex("010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299").unwrap();
+        let block_2 = RawHeader::deserialize_hex("010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d08d2bd61").unwrap();
+
+        assert_eq!(store.header_height().unwrap(), None);
+        assert_eq!(store.tip().unwrap(), None);
+        store.put_header(1, block_1).unwrap();
+        store.put_header(2, block_2).unwrap();
+        assert_eq!(store.get_header(0).unwrap(), None);
+        assert_eq!(store.get_header(2).unwrap(), Some(block_2));
+        assert_eq!(store.header_height().unwrap(), Some(2));
+        store.truncate(1).unwrap();
+        assert_eq!(store.get_header(2).unwrap(), None);
+        assert_eq!(store.header_height().unwrap(), Some(1));
+
+        let mut chain =
+            HeaderChain::resume(MAINNET_PARAMS, Checkpoint::mainnet_genesis(), store).unwrap();
+        chain.extend(&[block_1, block_2]).unwrap();
+        store.set_tip(chain.tip()).unwrap();
+        let resumed =
+            HeaderChain::resume(MAINNET_PARAMS, Checkpoint::mainnet_genesis(), store).unwrap();
+        assert_eq!(resumed.tip(), chain.tip());
+
+        store.watch_script(ScriptPubkey::null()).unwrap();
+        store.watch_script(ScriptPubkey::null()).unwrap();
+        assert_eq!(store.scripts().unwrap(), vec![ScriptPubkey::null()]);
+    }
+
+    #[test]
+    fn it_stores_chain_state() {
+        exercise(&mut MemoryChainStore::default());
+
+        let dir = std::env::temp_dir().join(format!("chain-store-{}", std::process::id()));
+        exercise(&mut FileChainStore::open(&dir).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+//! Address subscriptions.
+//!
+//! `PollingBtcProvider::subscribe_address` reports funds received and spent by an address as a
+//! single stream, whatever the backend. By default it diffs the address's unspent set at the
+//! provider's polling interval. Backends with push updates drive the same diff on each pushed
+//! message instead, via `ticks` (or `push`, for the mempool.space WebSocket API).
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::stream::Stream;
+use futures_util::stream::StreamExt;
+
+use bitcoins::{enc::Address, prelude::*};
+
+use crate::{
+    provider::BtcProvider,
+    tracker::{UtxoEvent, UtxoTracker},
+};
+
+#[cfg(target_arch = "wasm32")]
+type EventStream<'a> = Pin<Box<dyn Stream<Item = AddressEvent> + 'a>>;
+
+#[cfg(not(target_arch = "wasm32"))]
+type EventStream<'a> = Pin<Box<dyn Stream<Item = AddressEvent> + Send + 'a>>;
+
+/// An event on a subscribed address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AddressEvent {
+    /// The address received funds in a new UTXO
+    Received(Utxo),
+    /// A UTXO of the address was spent, or left the chain in a reorg
+    Spent(Utxo),
+}
+
+impl From<UtxoEvent> for AddressEvent {
+    fn from(event: UtxoEvent) -> Self {
+        match event {
+            UtxoEvent::Credit(utxo) => AddressEvent::Received(utxo),
+            UtxoEvent::Debit(utxo) => AddressEvent::Spent(utxo),
+        }
+    }
+}
+
+/// A stream of `AddressEvent`s for a single address. The first poll reports the address's
+/// existing UTXOs as `Received`. The subscription does not start until polled, so it may be
+/// configured with `interval`, `ticks` or `push` first.
+#[must_use = "streams do nothing unless polled"]
+pub struct AddressSubscription<'a> {
+    address: Address,
+    tracker: Option<UtxoTracker<'a>>,
+    events: Option<EventStream<'a>>,
+}
+
+impl<'a> AddressSubscription<'a> {
+    /// Subscribe to an address, polling at the default interval
+    pub fn new(address: &Address, provider: &'a dyn BtcProvider) -> Self {
+        Self {
+            address: address.clone(),
+            tracker: Some(UtxoTracker::new(provider).watch_address(address)),
+            events: None,
+        }
+    }
+
+    /// The subscribed address
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Sets the polling interval. Has no effect once the subscription has started
+    pub fn interval<T: Into<Duration>>(mut self, duration: T) -> Self {
+        self.tracker = self.tracker.map(|tracker| tracker.interval(duration));
+        self
+    }
+
+    /// Check the address after each tick of a stream instead of at a fixed interval, e.g. on
+    /// each notification from a push backend. Has no effect once the subscription has started
+    pub fn ticks<T>(mut self, ticks: T) -> Self
+    where
+        T: Stream<Item = ()> + Send + Unpin + 'static,
+    {
+        self.tracker = self.tracker.map(|tracker| tracker.ticks(ticks));
+        self
+    }
+
+    /// Check the address on each event pushed by the mempool.space WebSocket API, falling back
+    /// to polling if the socket drops. `messages` is the stream of incoming text frames of a
+    /// socket that has sent the `subscription` message.
+    #[cfg(feature = "esplora")]
+    pub fn push<S>(self, messages: S) -> Self
+    where
+        S: Stream<Item = String> + Send + Unpin + 'static,
+    {
+        self.ticks(crate::esplora::ws::PushTicks::new(messages))
+    }
+
+    /// The WebSocket subscription to send before passing the socket to `push`
+    #[cfg(feature = "esplora")]
+    pub fn subscription(&self) -> crate::esplora::ws::Subscription {
+        crate::esplora::ws::Subscription::Address(self.address.clone())
+    }
+}
+
+impl<'a> Stream for AddressSubscription<'a> {
+    type Item = AddressEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        if self.events.is_none() {
+            if let Some(tracker) = self.tracker.take() {
+                self.events = Some(Box::pin(tracker.events().map(Into::into)));
+            }
+        }
+        match self.events.as_mut() {
+            Some(events) => events.as_mut().poll_next(ctx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "esplora"))]
+mod test {
+    use super::*;
+    use crate::esplora::EsploraProvider;
+
+    #[test]
+    fn it_maps_utxo_events() {
+        let utxo = Utxo::new(
+            BitcoinOutpoint::default(),
+            Amount::from_sat(1000),
+            ScriptPubkey::null(),
+            SpendScript::None,
+        );
+        assert_eq!(
+            AddressEvent::from(UtxoEvent::Credit(utxo.clone())),
+            AddressEvent::Received(utxo.clone())
+        );
+        assert_eq!(
+            AddressEvent::from(UtxoEvent::Debit(utxo.clone())),
+            AddressEvent::Spent(utxo)
+        );
+
+        let provider = EsploraProvider::default();
+        let address = Address::Wpkh("bc1qza7dfgl2q83cf68fqkkdd754qx546h4u9vd9tg".to_owned());
+        let subscription = AddressSubscription::new(&address, &provider);
+        assert_eq!(subscription.address(), &address);
+        assert_eq!(
+            subscription.subscription().to_message(),
+            r#"{"track-address":"bc1qza7dfgl2q83cf68fqkkdd754qx546h4u9vd9tg"}"#
+        );
+    }
+}
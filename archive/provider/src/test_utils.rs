@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+use bitcoins::prelude::*;
+use coins_core::hashes::MarkedDigestOutput;
+
+use crate::{
+    provider::{BtcProvider, ProviderError},
+    types::RawHeader,
+};
+
+fn unsupported<T>(method: &str) -> Result<T, ProviderError> {
+    Err(ProviderError::Unsupported(format!(
+        "MockProvider does not implement {}",
+        method
+    )))
+}
+
+// A provider serving a chain of headers that link, but have no proof of work. It accepts and
+// counts broadcasts, never confirms txs, and reports `spender` as the spender of any outpoint.
+// Requests it does not serve return `ProviderError::Unsupported`
+#[derive(Default)]
+pub(crate) struct MockProvider {
+    pub(crate) headers: Mutex<Vec<RawHeader>>,
+    pub(crate) broadcasts: AtomicUsize,
+    pub(crate) spender: Option<TXID>,
+}
+
+impl MockProvider {
+    // Replace the headers from `from` onwards with `count` new headers. Headers with different
+    // nonces have different digests, so a new nonce makes a fork
+    pub(crate) fn extend(&self, from: usize, count: usize, nonce: u8) {
+        let mut headers = self.headers.lock().unwrap();
+        headers.truncate(from);
+        for _ in 0..count {
+            let mut buf = [0u8; 80];
+            if let Some(parent) = headers.last() {
+                buf[4..36].copy_from_slice(parent.digest().as_slice());
+            }
+            buf[76] = nonce;
+            headers.push(buf.into());
+        }
+    }
+
+    pub(crate) fn hash_at(&self, height: usize) -> BlockHash {
+        self.headers.lock().unwrap()[height].digest()
+    }
+}
+
+#[async_trait]
+impl BtcProvider for MockProvider {
+    async fn tip_hash(&self) -> Result<BlockHash, ProviderError> {
+        match self.headers.lock().unwrap().last() {
+            Some(header) => Ok(header.digest()),
+            None => unsupported("tip_hash without headers"),
+        }
+    }
+
+    async fn tip_height(&self) -> Result<usize, ProviderError> {
+        match self.headers.lock().unwrap().len() {
+            0 => unsupported("tip_height without headers"),
+            len => Ok(len - 1),
+        }
+    }
+
+    async fn in_best_chain(&self, digest: BlockHash) -> Result<bool, ProviderError> {
+        Ok(self.get_height_of(digest).await?.is_some())
+    }
+
+    async fn get_digest_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<BlockHash>, ProviderError> {
+        let range = self.get_raw_header_range(start, headers).await?;
+        Ok(range.iter().map(RawHeader::digest).collect())
+    }
+
+    async fn get_raw_header_range(
+        &self,
+        start: usize,
+        headers: usize,
+    ) -> Result<Vec<RawHeader>, ProviderError> {
+        let chain = self.headers.lock().unwrap();
+        Ok(chain.iter().skip(start).take(headers).copied().collect())
+    }
+
+    async fn get_raw_header(&self, digest: BlockHash) -> Result<Option<RawHeader>, ProviderError> {
+        let chain = self.headers.lock().unwrap();
+        Ok(chain.iter().find(|h| h.digest() == digest).copied())
+    }
+
+    async fn get_height_of(&self, digest: BlockHash) -> Result<Option<usize>, ProviderError> {
+        let chain = self.headers.lock().unwrap();
+        Ok(chain.iter().position(|h| h.digest() == digest))
+    }
+
+    async fn get_confirmed_height(&self, _: TXID) -> Result<Option<usize>, ProviderError> {
+        unsupported("get_confirmed_height")
+    }
+
+    async fn get_confs(&self, _: TXID) -> Result<Option<usize>, ProviderError> {
+        Ok(None)
+    }
+
+    async fn get_tx(&self, _: TXID) -> Result<Option<BitcoinTx>, ProviderError> {
+        unsupported("get_tx")
+    }
+
+    async fn broadcast(&self, tx: BitcoinTx) -> Result<TXID, ProviderError> {
+        self.broadcasts.fetch_add(1, Ordering::SeqCst);
+        Ok(tx.txid())
+    }
+
+    async fn get_outspend(&self, _: BitcoinOutpoint) -> Result<Option<TXID>, ProviderError> {
+        Ok(self.spender)
+    }
+
+    async fn get_utxos_by_address(&self, _: &Address) -> Result<Vec<Utxo>, ProviderError> {
+        unsupported("get_utxos_by_address")
+    }
+
+    async fn get_merkle(
+        &self,
+        _: TXID,
+    ) -> Result<Option<(usize, Vec<Hash256Digest>)>, ProviderError> {
+        unsupported("get_merkle")
+    }
+}
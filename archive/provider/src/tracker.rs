@@ -0,0 +1,247 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    time::Duration,
+};
+
+use futures_core::stream::Stream;
+use futures_util::stream::{self, StreamExt};
+
+use bitcoins::{enc::Address, prelude::*};
+use coins_core::enc::AddressEncoder;
+
+use crate::{
+    provider::{BtcProvider, ProviderError},
+    utils::new_interval,
+    DEFAULT_POLL_INTERVAL,
+};
+
+/// A change to the tracked unspent set
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UtxoEvent {
+    /// A new UTXO paying a watched script
+    Credit(Utxo),
+    /// A tracked UTXO was spent, or left the chain in a reorg
+    Debit(Utxo),
+}
+
+/// Persists the unspent set of a `UtxoTracker` between runs
+pub trait UtxoStore: Send {
+    /// Load the tracked UTXOs. An empty store returns an empty vector
+    fn load(&self) -> Result<Vec<Utxo>, ProviderError>;
+
+    /// Persist the tracked UTXOs, replacing any previously stored
+    fn save(&mut self, utxos: &[Utxo]) -> Result<(), ProviderError>;
+}
+
+/// A `UtxoStore` that keeps the unspent set in memory
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore(Vec<Utxo>);
+
+impl UtxoStore for MemoryStore {
+    fn load(&self) -> Result<Vec<Utxo>, ProviderError> {
+        Ok(self.0.clone())
+    }
+
+    fn save(&mut self, utxos: &[Utxo]) -> Result<(), ProviderError> {
+        self.0 = utxos.to_vec();
+        Ok(())
+    }
+}
+
+/// Maintains the unspent set of a set of watched scripts. Each `sync` fetches the UTXOs of the
+/// watched scripts, and reports credits and debits relative to the previous sync. The unspent
+/// set is persisted to the `UtxoStore` whenever it changes.
+///
+/// `events` turns the tracker into a stream that syncs at the polling interval, or on each tick
+/// of a push stream (see `ticks`).
+#[must_use = "trackers do nothing unless synced"]
+pub struct UtxoTracker<'a, S: UtxoStore = MemoryStore> {
+    provider: &'a dyn BtcProvider,
+    scripts: Vec<ScriptPubkey>,
+    utxos: HashMap<BitcoinOutpoint, Utxo>,
+    store: S,
+    interval: Box<dyn Stream<Item = ()> + Send + Unpin>,
+}
+
+impl<'a> UtxoTracker<'a> {
+    /// Instantiate a tracker that keeps its state in memory
+    pub fn new(provider: &'a dyn BtcProvider) -> Self {
+        Self::with_store(provider, MemoryStore::default()).expect("memory store load is infallible")
+    }
+}
+
+impl<'a, S: UtxoStore> UtxoTracker<'a, S> {
+    /// Instantiate a tracker, and load its unspent set from a store
+    pub fn with_store(provider: &'a dyn BtcProvider, store: S) -> Result<Self, ProviderError> {
+        let utxos = store
+            .load()?
+            .into_iter()
+            .map(|utxo| (utxo.outpoint, utxo))
+            .collect();
+        Ok(Self {
+            provider,
+            scripts: vec![],
+            utxos,
+            store,
+            interval: Box::new(new_interval(DEFAULT_POLL_INTERVAL)),
+        })
+    }
+
+    /// Watch a script pubkey
+    pub fn watch(mut self, script: ScriptPubkey) -> Self {
+        if !self.scripts.contains(&script) {
+            self.scripts.push(script);
+        }
+        self
+    }
+
+    /// Watch an address
+    pub fn watch_address(self, address: &Address) -> Self {
+        self.watch(crate::Encoder::decode_address(address))
+    }
+
+    /// Sets the polling interval
+    pub fn interval<T: Into<Duration>>(mut self, duration: T) -> Self {
+        self.interval = Box::new(new_interval(duration.into()));
+        self
+    }
+
+    /// Sync after each tick of a stream instead of at a fixed interval, e.g. to sync on push
+    /// updates from `esplora::ws::PushTicks`
+    pub fn ticks<T>(mut self, ticks: T) -> Self
+    where
+        T: Stream<Item = ()> + Send + Unpin + 'static,
+    {
+        self.interval = Box::new(ticks);
+        self
+    }
+
+    /// The watched script pubkeys
+    pub fn scripts(&self) -> &[ScriptPubkey] {
+        &self.scripts
+    }
+
+    /// The tracked UTXOs
+    pub fn utxos(&self) -> impl Iterator<Item = &Utxo> {
+        self.utxos.values()
+    }
+
+    /// The total value of the tracked UTXOs. `None` if it is above `MAX_MONEY`, which only
+    /// UTXOs with invalid values reported by the provider can cause
+    pub fn balance(&self) -> Option<Amount> {
+        Amount::checked_sum(self.utxos.values().map(|utxo| utxo.value))
+    }
+
+    /// Return a reference to the store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Fetch the UTXOs of all watched scripts, update the unspent set, and return the changes.
+    /// Nothing is updated if any fetch fails.
+    pub async fn sync(&mut self) -> Result<Vec<UtxoEvent>, ProviderError> {
+        let mut current = vec![];
+        for script in self.scripts.iter() {
+            current.extend(self.provider.get_utxos_by_script(script).await?);
+        }
+        let events = self.apply(current);
+        if !events.is_empty() {
+            let utxos: Vec<_> = self.utxos.values().cloned().collect();
+            self.store.save(&utxos)?;
+        }
+        Ok(events)
+    }
+
+    // Replace the unspent set, and return the differences. Debits are reported first
+    fn apply(&mut self, current: Vec<Utxo>) -> Vec<UtxoEvent> {
+        let outpoints: HashSet<_> = current.iter().map(|utxo| utxo.outpoint).collect();
+
+        let spent: Vec<BitcoinOutpoint> = self
+            .utxos
+            .keys()
+            .filter(|outpoint| !outpoints.contains(outpoint))
+            .copied()
+            .collect();
+        let mut events: Vec<_> = spent
+            .iter()
+            .filter_map(|outpoint| self.utxos.remove(outpoint))
+            .map(UtxoEvent::Debit)
+            .collect();
+
+        for utxo in current.into_iter() {
+            if let Entry::Vacant(entry) = self.utxos.entry(utxo.outpoint) {
+                entry.insert(utxo.clone());
+                events.push(UtxoEvent::Credit(utxo));
+            }
+        }
+        events
+    }
+
+    /// Convert the tracker into a stream of `UtxoEvent`s. It syncs immediately, then at the
+    /// polling interval. Sync errors are retried at the next interval.
+    pub fn events(self) -> impl Stream<Item = UtxoEvent> + 'a
+    where
+        S: 'a,
+    {
+        stream::unfold((self, true), |(mut tracker, first)| async move {
+            if !first {
+                tracker.interval.next().await?;
+            }
+            loop {
+                if let Ok(events) = tracker.sync().await {
+                    if !events.is_empty() {
+                        return Some((events, (tracker, false)));
+                    }
+                }
+                tracker.interval.next().await?;
+            }
+        })
+        .flat_map(stream::iter)
+    }
+}
+
+#[cfg(all(test, feature = "esplora"))]
+mod test {
+    use super::*;
+    use crate::esplora::EsploraProvider;
+
+    fn utxo(idx: u32, value: u64) -> Utxo {
+        Utxo::new(
+            BitcoinOutpoint {
+                txid: Default::default(),
+                idx,
+            },
+            Amount::from_sat(value),
+            ScriptPubkey::null(),
+            SpendScript::None,
+        )
+    }
+
+    #[test]
+    fn it_tracks_credits_and_debits() {
+        let provider = EsploraProvider::default();
+        let mut tracker = UtxoTracker::new(&provider);
+
+        let events = tracker.apply(vec![utxo(0, 1000), utxo(1, 2000)]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(tracker.balance(), Some(Amount::from_sat(3000)));
+
+        let events = tracker.apply(vec![utxo(1, 2000), utxo(2, 500)]);
+        assert_eq!(
+            events,
+            vec![
+                UtxoEvent::Debit(utxo(0, 1000)),
+                UtxoEvent::Credit(utxo(2, 500))
+            ]
+        );
+        assert_eq!(tracker.balance(), Some(Amount::from_sat(2500)));
+        assert!(tracker.apply(vec![utxo(1, 2000), utxo(2, 500)]).is_empty());
+
+        // state is restored from the store
+        let mut store = MemoryStore::default();
+        let utxos: Vec<_> = tracker.utxos().cloned().collect();
+        store.save(&utxos).unwrap();
+        let restored = UtxoTracker::with_store(&provider, store).unwrap();
+        assert_eq!(restored.balance(), Some(Amount::from_sat(2500)));
+    }
+}
@@ -1,7 +1,19 @@
-use coins_core::ser::{ByteFormat, SerError};
+use bitcoins::{
+    enc::Address,
+    hashes::{BlockHash, TXID},
+    types::{BitcoinOutpoint, BitcoinTx, ScriptPubkey},
+};
+use coins_core::{
+    enc::AddressEncoder,
+    hashes::{Hash256, Hash256Digest, MarkedDigest, MarkedDigestOutput},
+    ser::{ByteFormat, SerError},
+    types::Transaction,
+};
+
+pub use bitcoins::types::FeeRate;
 
 /// A minimal type representing a raw Bitcoin header.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct RawHeader([u8; 80]);
 
 impl Default for RawHeader {
@@ -28,6 +40,43 @@ impl AsMut<[u8; 80]> for RawHeader {
     }
 }
 
+impl RawHeader {
+    /// The block hash
+    pub fn digest(&self) -> BlockHash {
+        Hash256::digest_marked(&self.0[..])
+    }
+
+    /// The hash of the parent block
+    pub fn parent(&self) -> BlockHash {
+        let mut parent = [0u8; 32];
+        parent.copy_from_slice(&self.0[4..36]);
+        parent.into()
+    }
+
+    /// The merkle root of the block's transactions
+    pub fn merkle_root(&self) -> Hash256Digest {
+        let mut root = Hash256Digest::default();
+        root.as_mut_slice().copy_from_slice(&self.0[36..68]);
+        root
+    }
+
+    /// The block timestamp
+    pub fn timestamp(&self) -> u32 {
+        self.read_u32(68)
+    }
+
+    /// The difficulty target, in compact form
+    pub fn bits(&self) -> u32 {
+        self.read_u32(72)
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(&self.0[offset..offset + 4]);
+        u32::from_le_bytes(buf)
+    }
+}
+
 impl ByteFormat for RawHeader {
     type Error = SerError;
 
@@ -53,3 +102,277 @@ impl ByteFormat for RawHeader {
         Ok(80)
     }
 }
+
+/// A snapshot of the mempool's feerate distribution, in the format used by Esplora and
+/// Electrum. Each entry is a feerate (in sat/vbyte) and the total vsize of mempool transactions
+/// paying at least that feerate, but less than the previous entry's feerate. Entries are
+/// ordered from highest to lowest feerate. The first entry has no upper bound.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeeHistogram(pub Vec<(f64, usize)>);
+
+impl FeeHistogram {
+    /// The total vsize of all transactions described by the histogram.
+    pub fn total_vsize(&self) -> usize {
+        self.0.iter().map(|(_, vsize)| vsize).sum()
+    }
+
+    /// The fraction of mempool vsize known to pay a lower feerate than `feerate`. Returns a
+    /// number between 0 and 1, or `None` if the histogram is empty.
+    ///
+    /// Only buckets whose upper bound is at or below `feerate` are counted, so this is a lower
+    /// bound on the true percentile.
+    pub fn percentile_of(&self, feerate: f64) -> Option<f64> {
+        let total = self.total_vsize();
+        if total == 0 {
+            return None;
+        }
+        let upper_bounds = std::iter::once(f64::INFINITY).chain(self.0.iter().map(|(r, _)| *r));
+        let below: usize = self
+            .0
+            .iter()
+            .zip(upper_bounds)
+            .filter(|(_, upper)| *upper <= feerate)
+            .map(|((_, vsize), _)| vsize)
+            .sum();
+        Some(below as f64 / total as f64)
+    }
+}
+
+/// A transaction's mempool entry. Ancestor and descendant statistics include the transaction
+/// itself, as in Bitcoin Core.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MempoolEntry {
+    /// The transaction fee, in satoshis
+    pub fee: u64,
+    /// The transaction weight
+    pub weight: usize,
+    /// The number of unconfirmed ancestors
+    pub ancestor_count: usize,
+    /// The total fees of unconfirmed ancestors, in satoshis
+    pub ancestor_fees: u64,
+    /// The total vsize of unconfirmed ancestors
+    pub ancestor_vsize: usize,
+    /// The number of descendants in the mempool
+    pub descendant_count: usize,
+    /// The total fees of descendants in the mempool, in satoshis
+    pub descendant_fees: u64,
+    /// The total vsize of descendants in the mempool
+    pub descendant_vsize: usize,
+    /// Whether the transaction is BIP125 replaceable, either because it signals, or because an
+    /// unconfirmed ancestor does
+    pub bip125_replaceable: bool,
+}
+
+impl MempoolEntry {
+    /// The transaction vsize
+    pub fn vsize(&self) -> usize {
+        self.weight.div_ceil(4)
+    }
+
+    /// The transaction feerate
+    pub fn feerate(&self) -> FeeRate {
+        FeeRate::from_fee_and_vsize(self.fee, self.vsize())
+    }
+
+    /// The feerate of the transaction and its unconfirmed ancestors. This is the feerate a miner
+    /// will consider when mining the package.
+    pub fn ancestor_feerate(&self) -> FeeRate {
+        FeeRate::from_fee_and_vsize(self.ancestor_fees, self.ancestor_vsize)
+    }
+}
+
+/// The input that spends an outpoint
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpendingInput {
+    /// The ID of the spending transaction
+    pub txid: TXID,
+    /// The index of the spending input in that transaction's vin
+    pub vin: usize,
+}
+
+/// A transaction output, with the address it pays to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetailedOutput {
+    /// The value in satoshis
+    pub value: u64,
+    /// The output script
+    pub script_pubkey: ScriptPubkey,
+    /// The address encoded by the script. `None` for non-standard scripts
+    pub address: Option<Address>,
+}
+
+impl DetailedOutput {
+    /// Instantiate an output, deriving its address from its script
+    pub fn new(value: u64, script_pubkey: ScriptPubkey) -> Self {
+        let address = crate::Encoder::encode_address(&script_pubkey).ok();
+        Self {
+            value,
+            script_pubkey,
+            address,
+        }
+    }
+}
+
+/// A transaction input, with the output it spends
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetailedInput {
+    /// The outpoint spent by the input
+    pub outpoint: BitcoinOutpoint,
+    /// The input's sequence number
+    pub sequence: u32,
+    /// The output spent by the input. `None` for coinbase inputs, or if the provider could not
+    /// find the output
+    pub prevout: Option<DetailedOutput>,
+}
+
+/// A transaction, with the outputs spent by its inputs. Allows fee and counterparty analysis
+/// without further lookups
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetailedTx {
+    /// The transaction
+    pub tx: BitcoinTx,
+    /// The hash of the confirming block. `None` if unconfirmed
+    pub block_hash: Option<BlockHash>,
+    /// The inputs, in order
+    pub inputs: Vec<DetailedInput>,
+    /// The outputs, in order
+    pub outputs: Vec<DetailedOutput>,
+}
+
+impl DetailedTx {
+    /// Assemble a detailed transaction from a transaction and its prevouts, in input order
+    pub fn new(
+        tx: BitcoinTx,
+        block_hash: Option<BlockHash>,
+        prevouts: Vec<Option<DetailedOutput>>,
+    ) -> Self {
+        let inputs = tx
+            .inputs()
+            .iter()
+            .zip(prevouts.into_iter().chain(std::iter::repeat(None)))
+            .map(|(input, prevout)| DetailedInput {
+                outpoint: input.outpoint,
+                sequence: input.sequence,
+                prevout,
+            })
+            .collect();
+        let outputs = tx
+            .outputs()
+            .iter()
+            .map(|output| DetailedOutput::new(output.value.to_sat(), output.script_pubkey.clone()))
+            .collect();
+        Self {
+            tx,
+            block_hash,
+            inputs,
+            outputs,
+        }
+    }
+
+    /// The transaction ID
+    pub fn txid(&self) -> TXID {
+        self.tx.txid()
+    }
+
+    /// True if this is a coinbase transaction
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1 && self.inputs[0].outpoint == BitcoinOutpoint::null()
+    }
+
+    /// The total value of the outputs
+    pub fn output_value(&self) -> u64 {
+        self.outputs.iter().map(|output| output.value).sum()
+    }
+
+    /// The total value of the inputs. `None` if any prevout is unknown, or for coinbase
+    /// transactions
+    pub fn input_value(&self) -> Option<u64> {
+        if self.is_coinbase() {
+            return None;
+        }
+        self.inputs
+            .iter()
+            .map(|input| input.prevout.as_ref().map(|prevout| prevout.value))
+            .sum()
+    }
+
+    /// The fee paid. `None` if any prevout is unknown, or for coinbase transactions
+    pub fn fee(&self) -> Option<u64> {
+        self.input_value()?.checked_sub(self.output_value())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_calculates_detailed_tx_fees() {
+        use bitcoins::prelude::*;
+
+        let prevout = BitcoinOutpoint::new(TXID::from([1u8; 32]), 0);
+        // a p2wpkh script
+        let mut spk = vec![0x00, 0x14];
+        spk.extend_from_slice(&[0x11; 20]);
+        let spk = ScriptPubkey::new(spk);
+        let tx = bitcoins::Net::tx_builder()
+            .spend(prevout, 0xffff_fffd)
+            .pay_script_pubkey(Amount::from_sat(9_000), spk.clone())
+            .build()
+            .unwrap();
+
+        let unknown = DetailedTx::new(tx.clone(), None, vec![None]);
+        assert_eq!(unknown.fee(), None);
+
+        let detailed = DetailedTx::new(
+            tx,
+            None,
+            vec![Some(DetailedOutput::new(10_000, spk.clone()))],
+        );
+        assert!(!detailed.is_coinbase());
+        assert_eq!(detailed.inputs[0].outpoint, prevout);
+        assert_eq!(
+            detailed.outputs[0].address,
+            detailed.inputs[0].prevout.as_ref().unwrap().address
+        );
+        assert!(detailed.outputs[0].address.is_some());
+        assert_eq!(detailed.output_value(), 9_000);
+        assert_eq!(detailed.fee(), Some(1_000));
+    }
+
+    #[test]
+    fn it_calculates_mempool_entry_feerates() {
+        let entry = MempoolEntry {
+            fee: 1410,
+            weight: 561,
+            ancestor_count: 2,
+            ancestor_fees: 1610,
+            ancestor_vsize: 241,
+            ..Default::default()
+        };
+        assert_eq!(entry.vsize(), 141);
+        assert_eq!(entry.feerate(), FeeRate(10.0));
+        assert!((entry.ancestor_feerate().sat_per_vbyte() - 6.68).abs() < 0.01);
+    }
+
+    #[test]
+    fn it_converts_feerates() {
+        let rate = FeeRate::from_btc_per_kvb(0.0002);
+        assert!((rate.sat_per_vbyte() - 20.0).abs() < 1e-9);
+        assert_eq!(rate.fee_for_vsize(141), 2820);
+        assert_eq!(FeeRate(1.5).fee_for_vsize(3), 5);
+    }
+
+    #[test]
+    fn it_calculates_feerate_percentiles() {
+        let histogram = FeeHistogram(vec![(50.0, 100), (20.0, 300), (5.0, 600)]);
+        assert_eq!(histogram.total_vsize(), 1000);
+        assert_eq!(histogram.percentile_of(1.0), Some(0.0));
+        assert_eq!(histogram.percentile_of(10.0), Some(0.0));
+        assert_eq!(histogram.percentile_of(20.0), Some(0.6));
+        assert_eq!(histogram.percentile_of(49.0), Some(0.6));
+        assert_eq!(histogram.percentile_of(50.0), Some(0.9));
+        assert_eq!(histogram.percentile_of(1000.0), Some(0.9));
+        assert_eq!(FeeHistogram::default().percentile_of(10.0), None);
+    }
+}
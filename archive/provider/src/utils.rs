@@ -10,10 +10,11 @@ use pin_project::pin_project;
 use futures_core::Stream;
 use futures_timer::Delay;
 use futures_util::{
-    stream::{self, StreamExt},
+    stream::{self, FuturesUnordered, StreamExt},
     FutureExt,
 };
-use std::time::Duration;
+use instant::Instant;
+use std::{sync::Mutex, time::Duration};
 
 use bitcoins::prelude::TXID;
 use coins_core::prelude::{Hash256, Hash256Digest, MarkedDigest, MarkedDigestOutput};
@@ -79,6 +80,79 @@ where
     }
 }
 
+/// Run futures with at most `limit` in flight at once, and collect their outputs in the order
+/// the futures were yielded. Resolves to the first error, dropping the futures still in flight.
+pub(crate) async fn try_join_bounded<I, Fut, T, E>(futs: I, limit: usize) -> Result<Vec<T>, E>
+where
+    I: IntoIterator<Item = Fut>,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let limit = std::cmp::max(limit, 1);
+    let mut pending = futs.into_iter().enumerate();
+    let mut in_flight = FuturesUnordered::new();
+    let mut outputs = vec![];
+
+    loop {
+        while in_flight.len() < limit {
+            match pending.next() {
+                Some((i, fut)) => in_flight.push(fut.map(move |res| (i, res))),
+                None => break,
+            }
+        }
+        match in_flight.next().await {
+            Some((i, Ok(output))) => outputs.push((i, output)),
+            Some((_, Err(e))) => return Err(e),
+            None => break,
+        }
+    }
+
+    outputs.sort_by_key(|(i, _)| *i);
+    Ok(outputs.into_iter().map(|(_, output)| output).collect())
+}
+
+/// A token bucket rate limiter. Allows bursts of up to `burst` requests, and refills at `rate`
+/// requests per second. Callers that find the bucket empty reserve the next token, and wait for
+/// it, so concurrent callers are served in order.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    // The tokens available, and the time they were counted
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    /// Instantiate a rate limiter with a full bucket
+    pub fn new(rate: f64, burst: usize) -> Self {
+        let burst = std::cmp::max(burst, 1) as f64;
+        Self {
+            rate,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Take a token, waiting until one is available
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(state.1).as_secs_f64();
+            // Reserve a token. A negative balance is repaid by waiting
+            let tokens = (state.0 + elapsed * self.rate).min(self.burst) - 1.0;
+            *state = (tokens, now);
+            if tokens < 0.0 {
+                Duration::from_secs_f64(-tokens / self.rate)
+            } else {
+                Duration::from_secs(0)
+            }
+        };
+        if wait > Duration::from_secs(0) {
+            Delay::new(wait).await;
+        }
+    }
+}
+
 /// Create a full merkle tree from a txid list.
 pub fn create_tree(leaves: &[TXID]) -> Vec<TXID> {
     let mut size = leaves.len();
@@ -148,6 +222,39 @@ pub fn merkle_from_txid_list(txid: TXID, block: &[TXID]) -> Option<(usize, Vec<H
     }
 }
 
+/// Verify a merkle proof, as produced by `merkle_from_txid_list` or `BtcProvider::get_merkle`,
+/// against the merkle root of a block header. `index` is the position of the tx in the block.
+pub fn verify_merkle(
+    txid: TXID,
+    index: usize,
+    proof: &[Hash256Digest],
+    merkle_root: Hash256Digest,
+) -> bool {
+    // The index must not have more bits than the proof has levels
+    if proof.len() < std::mem::size_of::<usize>() * 8 && index >> proof.len() != 0 {
+        return false;
+    }
+
+    let mut idx = index;
+    let mut current = Hash256Digest::default();
+    current.as_mut_slice().copy_from_slice(txid.as_slice());
+    for sibling in proof.iter() {
+        let (left, right) = if idx & 1 == 0 {
+            (&current, sibling)
+        } else {
+            (sibling, &current)
+        };
+        let mut ctx = Hash256::default();
+        ctx.write_all(left.as_slice())
+            .expect("no error on heap allocation");
+        ctx.write_all(right.as_slice())
+            .expect("no error on heap allocation");
+        current = ctx.finalize_marked();
+        idx >>= 1;
+    }
+    current == merkle_root
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -205,4 +312,81 @@ mod tests {
             assert_eq!(result, case.1);
         }
     }
+
+    #[test]
+    fn should_join_bounded() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut rt = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let task = |i: u64| {
+            let running = &running;
+            let peak = &peak;
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                // later tasks finish first
+                Delay::new(Duration::from_millis(20u64.saturating_sub(2 * i))).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                if i == 99 {
+                    Err(i)
+                } else {
+                    Ok(i)
+                }
+            }
+        };
+
+        let outputs = rt.block_on(try_join_bounded((0..8).map(task), 3));
+        assert_eq!(outputs, Ok((0..8).collect::<Vec<_>>()));
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+
+        let failed = rt.block_on(try_join_bounded(vec![task(0), task(99)], 2));
+        assert_eq!(failed, Err(99));
+    }
+
+    #[test]
+    fn should_rate_limit() {
+        let mut rt = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let limiter = RateLimiter::new(100.0, 5);
+        let start = Instant::now();
+        rt.block_on(async {
+            for _ in 0..10 {
+                limiter.acquire().await;
+            }
+        });
+        // 5 requests from the burst, then 5 more at 10ms each
+        assert!(start.elapsed() >= Duration::from_millis(45));
+    }
+
+    #[test]
+    fn should_verify_merkle() {
+        let leaves: Vec<_> = (0..5u8).map(|i| TXID::from([i; 32])).collect();
+        let tree = create_tree(&leaves);
+        let mut root = Hash256Digest::default();
+        root.as_mut_slice()
+            .copy_from_slice(tree.last().unwrap().as_slice());
+
+        for (index, txid) in leaves.iter().enumerate() {
+            let (i, proof) = merkle_from_txid_list(*txid, &leaves).unwrap();
+            assert_eq!(i, index);
+            assert!(verify_merkle(*txid, index, &proof, root));
+            assert!(!verify_merkle(*txid, index + 8, &proof, root));
+        }
+        let (_, proof) = merkle_from_txid_list(leaves[0], &leaves).unwrap();
+        assert!(!verify_merkle(leaves[0], 1, &proof, root));
+        assert!(!verify_merkle(leaves[1], 0, &proof, root));
+        assert!(verify_merkle(
+            leaves[0],
+            0,
+            &[],
+            leaves[0].to_internal().into()
+        ));
+    }
 }
@@ -1,17 +1,18 @@
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     pin::Pin,
     task::{Context, Poll},
     time::Duration,
 };
 
 use futures_core::stream::Stream;
-use futures_util::stream::StreamExt;
+use futures_util::{future::join_all, stream::StreamExt, FutureExt};
 use pin_project::pin_project;
 
 use bitcoins::prelude::*;
 
 use crate::{
-    provider::BtcProvider,
+    provider::{BtcProvider, ProviderError},
     utils::{new_interval, StreamLast},
     ProviderFut, DEFAULT_POLL_INTERVAL,
 };
@@ -71,6 +72,16 @@ impl<'a> PollingWatcher<'a> {
         self.interval = Box::new(new_interval(duration.into()));
         self
     }
+
+    /// Poll after each tick of a stream instead of at a fixed interval, e.g. to poll on push
+    /// updates from `esplora::ws::PushTicks`
+    pub fn ticks<S>(mut self, ticks: S) -> Self
+    where
+        S: Stream<Item = ()> + Send + Unpin + 'static,
+    {
+        self.interval = Box::new(ticks);
+        self
+    }
 }
 
 impl StreamLast for PollingWatcher<'_> {}
@@ -155,3 +166,253 @@ impl<'a> futures_core::stream::Stream for PollingWatcher<'a> {
         Poll::Pending
     }
 }
+
+/// An event from a `MultiWatcher`: an outpoint, the txid of the tx spending it, and the number
+/// of confirmations of that tx. The txid is `None` if the previously reported spend left the
+/// mempool.
+pub type SpendEvent = (BitcoinOutpoint, Option<TXID>, usize);
+
+// The spending tx and its confirmations, if the outpoint is spent
+type SpendStatus = Option<(TXID, usize)>;
+
+// Query the status of a set of outpoints. Unspent outpoints are checked for a spend, and the
+// confirmations of each known spending tx are fetched once, even if it spends several of the
+// outpoints. Requests run concurrently. Outpoints whose requests failed are omitted
+async fn observe(
+    provider: &dyn BtcProvider,
+    watched: Vec<(BitcoinOutpoint, Option<TXID>)>,
+) -> Result<Vec<(BitcoinOutpoint, SpendStatus)>, ProviderError> {
+    let spends: Vec<(BitcoinOutpoint, Option<Option<TXID>>)> =
+        join_all(watched.into_iter().map(|(outpoint, known)| async move {
+            match known {
+                Some(txid) => (outpoint, Some(Some(txid))),
+                None => (outpoint, provider.get_outspend(outpoint).await.ok()),
+            }
+        }))
+        .await;
+
+    let txids: HashSet<TXID> = spends
+        .iter()
+        .filter_map(|(_, spend)| spend.flatten())
+        .collect();
+    let confs: HashMap<TXID, Option<usize>> = join_all(
+        txids
+            .into_iter()
+            .map(|txid| provider.get_confs(txid).map(move |r| (txid, r.ok()))),
+    )
+    .await
+    .into_iter()
+    .filter_map(|(txid, confs)| confs.map(|c| (txid, c)))
+    .collect();
+
+    Ok(spends
+        .into_iter()
+        .filter_map(|(outpoint, spend)| match spend? {
+            None => Some((outpoint, None)),
+            Some(txid) => match confs.get(&txid)? {
+                Some(c) => Some((outpoint, Some((txid, *c)))),
+                // the spending tx is unknown, i.e. it left the mempool
+                None => Some((outpoint, None)),
+            },
+        })
+        .collect())
+}
+
+/// A stream that monitors a set of outpoints. Each poll queries the status of every watched
+/// outpoint at once, and yields a `SpendEvent` when an outpoint is spent, and each time the
+/// confirmations of its spending tx increase. An outpoint is no longer watched once its
+/// spending tx has `confirmations` confirmations, and the stream ends when no outpoints are
+/// watched.
+#[pin_project(project = MultiWatcherProj)]
+#[must_use = "streams do nothing unless polled"]
+pub struct MultiWatcher<'a> {
+    confirmations: usize,
+    interval: Box<dyn Stream<Item = ()> + Send + Unpin>,
+    provider: &'a dyn BtcProvider,
+    fut_opt: Option<ProviderFut<'a, Vec<(BitcoinOutpoint, SpendStatus)>>>,
+    started: bool,
+    watched: HashMap<BitcoinOutpoint, SpendStatus>,
+    queue: VecDeque<SpendEvent>,
+}
+
+impl<'a> MultiWatcher<'a> {
+    /// Creates a new watcher for a set of outpoints
+    pub fn new<I>(outpoints: I, provider: &'a dyn BtcProvider) -> Self
+    where
+        I: IntoIterator<Item = BitcoinOutpoint>,
+    {
+        Self {
+            confirmations: 0,
+            interval: Box::new(new_interval(DEFAULT_POLL_INTERVAL)),
+            provider,
+            fut_opt: None,
+            started: false,
+            watched: outpoints.into_iter().map(|o| (o, None)).collect(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Watch another outpoint
+    pub fn watch(mut self, outpoint: BitcoinOutpoint) -> Self {
+        self.watched.entry(outpoint).or_insert(None);
+        self
+    }
+
+    /// Sets the number of confirmations after which an outpoint is no longer watched
+    pub fn confirmations(mut self, confs: usize) -> Self {
+        self.confirmations = confs;
+        self
+    }
+
+    /// Sets the polling interval
+    pub fn interval<T: Into<Duration>>(mut self, duration: T) -> Self {
+        self.interval = Box::new(new_interval(duration.into()));
+        self
+    }
+
+    /// Poll after each tick of a stream instead of at a fixed interval, e.g. to poll on push
+    /// updates from `esplora::ws::PushTicks`
+    pub fn ticks<S>(mut self, ticks: S) -> Self
+    where
+        S: Stream<Item = ()> + Send + Unpin + 'static,
+    {
+        self.interval = Box::new(ticks);
+        self
+    }
+
+    /// The outpoints still being watched
+    pub fn outpoints(&self) -> impl Iterator<Item = &BitcoinOutpoint> {
+        self.watched.keys()
+    }
+
+    // Compare observations to the watched set, and return the changes. Outpoints whose spend
+    // has enough confirmations are removed
+    fn apply(
+        watched: &mut HashMap<BitcoinOutpoint, SpendStatus>,
+        confirmations: usize,
+        observations: Vec<(BitcoinOutpoint, SpendStatus)>,
+    ) -> Vec<SpendEvent> {
+        let mut events = vec![];
+        for (outpoint, status) in observations.into_iter() {
+            let previous = match watched.get(&outpoint) {
+                Some(previous) => *previous,
+                None => continue,
+            };
+            match (previous, status) {
+                (None, None) => {}
+                (Some(_), None) => events.push((outpoint, None, 0)),
+                (Some((old, old_confs)), Some((txid, confs)))
+                    if old == txid && confs <= old_confs => {}
+                (_, Some((txid, confs))) => events.push((outpoint, Some(txid), confs)),
+            }
+            match status {
+                Some((_, confs)) if confs >= confirmations => {
+                    watched.remove(&outpoint);
+                }
+                _ => {
+                    watched.insert(outpoint, status);
+                }
+            }
+        }
+        events
+    }
+}
+
+impl<'a> futures_core::Stream for MultiWatcher<'a> {
+    type Item = SpendEvent;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        let MultiWatcherProj {
+            confirmations,
+            interval,
+            provider,
+            fut_opt,
+            started,
+            watched,
+            queue,
+        } = self.project();
+
+        if let Some(event) = queue.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+
+        if let Some(fut) = fut_opt {
+            let result = futures_util::ready!(fut.as_mut().poll(ctx));
+            *fut_opt = None;
+
+            if let Ok(observations) = result {
+                queue.extend(Self::apply(watched, *confirmations, observations));
+                if let Some(event) = queue.pop_front() {
+                    return Poll::Ready(Some(event));
+                }
+            }
+        }
+
+        if watched.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        let snapshot: Vec<_> = watched
+            .iter()
+            .map(|(outpoint, status)| (*outpoint, status.map(|(txid, _)| txid)))
+            .collect();
+
+        // The first query runs immediately. Later queries wait for the interval
+        if !*started {
+            *started = true;
+            *fut_opt = Some(Box::pin(observe(*provider, snapshot)));
+            ctx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let fut = unpause!(ctx, interval, observe(*provider, snapshot));
+        *fut_opt = Some(fut);
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn outpoint(idx: u32) -> BitcoinOutpoint {
+        BitcoinOutpoint {
+            txid: Default::default(),
+            idx,
+        }
+    }
+
+    #[test]
+    fn it_reports_spends_and_confirmations() {
+        let spender =
+            TXID::from_be_hex("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b")
+                .unwrap();
+        let mut watched: HashMap<_, _> = (0..3).map(|i| (outpoint(i), None)).collect();
+
+        // one outpoint spent, one unspent, one failed to query
+        let events = MultiWatcher::apply(
+            &mut watched,
+            2,
+            vec![(outpoint(0), Some((spender, 0))), (outpoint(1), None)],
+        );
+        assert_eq!(events, vec![(outpoint(0), Some(spender), 0)]);
+
+        // no change, then a confirmation
+        let observation = vec![(outpoint(0), Some((spender, 0)))];
+        assert!(MultiWatcher::apply(&mut watched, 2, observation).is_empty());
+        let observation = vec![(outpoint(0), Some((spender, 1)))];
+        let events = MultiWatcher::apply(&mut watched, 2, observation);
+        assert_eq!(events, vec![(outpoint(0), Some(spender), 1)]);
+
+        // the spend leaves the mempool
+        let events = MultiWatcher::apply(&mut watched, 2, vec![(outpoint(0), None)]);
+        assert_eq!(events, vec![(outpoint(0), None, 0)]);
+
+        // enough confirmations stop the watch
+        let observation = vec![(outpoint(2), Some((spender, 2)))];
+        let events = MultiWatcher::apply(&mut watched, 2, observation);
+        assert_eq!(events, vec![(outpoint(2), Some(spender), 2)]);
+        assert_eq!(watched.len(), 2);
+        assert!(!watched.contains_key(&outpoint(2)));
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::{HashMap, HashSet};
+
+use bitcoins::prelude::*;
+
+use crate::provider::{BtcProvider, ProviderError};
+
+/// The default number of unconfirmed ancestor generations inspected by a `ZeroConfAnalyzer`
+pub const DEFAULT_ANCESTOR_DEPTH: usize = 25;
+
+/// Inputs with a sequence number below this value signal BIP125 replaceability
+pub(crate) const RBF_SEQUENCE_THRESHOLD: u32 = 0xffff_fffe;
+
+/// Returns true if any input of the transaction signals BIP125 replaceability.
+pub fn signals_rbf<T: BitcoinTransaction>(tx: &T) -> bool {
+    tx.signals_rbf()
+}
+
+/// Coarse risk categories for an unconfirmed payment.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ZeroConfRisk {
+    /// The payment shows no double-spend indicators
+    Low,
+    /// The payment may be replaced or may take a long time to confirm
+    Medium,
+    /// The payment is easily replaced, or a conflicting spend has been observed
+    High,
+}
+
+/// The double-spend indicators gathered for an unconfirmed transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZeroConfReport {
+    /// The txid of the analyzed transaction
+    pub txid: TXID,
+    /// The number of confirmations the transaction had when analyzed
+    pub confirmations: usize,
+    /// Whether the transaction itself signals replaceability
+    pub signals_rbf: bool,
+    /// Unconfirmed ancestors that signal replaceability. Replacing any of these invalidates
+    /// the analyzed transaction.
+    pub rbf_ancestors: Vec<TXID>,
+    /// All unconfirmed ancestors found within the search depth
+    pub unconfirmed_ancestors: Vec<TXID>,
+    /// The transaction fee in satoshis. `None` if any prevout could not be fetched
+    pub fee: Option<u64>,
+    /// The virtual size of the transaction
+    pub vsize: usize,
+    /// The fraction of the mempool known to pay a lower feerate. `None` if the provider does
+    /// not expose its mempool, or if the fee is unknown
+    pub feerate_percentile: Option<f64>,
+    /// Transactions observed spending the same outpoints as the analyzed transaction
+    pub conflicts: Vec<TXID>,
+}
+
+impl ZeroConfReport {
+    /// The feerate of the transaction in sat/vbyte, if the fee is known.
+    pub fn feerate(&self) -> Option<f64> {
+        self.fee
+            .map(|fee| FeeRate::from_fee_and_vsize(fee, self.vsize).sat_per_vbyte())
+    }
+
+    /// A double-spend risk score between 0 and 100. Confirmed transactions score 0, and
+    /// transactions with an observed conflicting spend score 100.
+    pub fn score(&self) -> u8 {
+        if self.confirmations > 0 {
+            return 0;
+        }
+        if !self.conflicts.is_empty() {
+            return 100;
+        }
+
+        let mut score = 0;
+        if self.signals_rbf {
+            score += 40;
+        }
+        if !self.rbf_ancestors.is_empty() {
+            score += 25;
+        }
+        score += std::cmp::min(5 * self.unconfirmed_ancestors.len(), 20);
+        score += match (self.fee, self.feerate_percentile) {
+            (None, _) => 10,
+            (Some(_), Some(p)) if p < 0.25 => 20,
+            (Some(_), Some(p)) if p < 0.5 => 10,
+            _ => 0,
+        };
+        std::cmp::min(score, 99) as u8
+    }
+
+    /// The risk category corresponding to the score.
+    pub fn risk(&self) -> ZeroConfRisk {
+        match self.score() {
+            0..=24 => ZeroConfRisk::Low,
+            25..=59 => ZeroConfRisk::Medium,
+            _ => ZeroConfRisk::High,
+        }
+    }
+}
+
+/// Scores the double-spend risk of unconfirmed incoming payments. The analyzer inspects the
+/// transaction's RBF signaling, its unconfirmed ancestry, its feerate relative to the mempool,
+/// and any conflicting spends known to the provider.
+///
+/// Mempool and outspend data are optional. If the provider reports them as `Unsupported`, the
+/// corresponding fields of the report are left empty.
+pub struct ZeroConfAnalyzer<'a> {
+    provider: &'a dyn BtcProvider,
+    max_depth: usize,
+}
+
+impl<'a> ZeroConfAnalyzer<'a> {
+    /// Instantiate a new analyzer using the provider
+    pub fn new(provider: &'a dyn BtcProvider) -> Self {
+        Self {
+            provider,
+            max_depth: DEFAULT_ANCESTOR_DEPTH,
+        }
+    }
+
+    /// Sets the number of unconfirmed ancestor generations to inspect
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Analyze a transaction. Resolves to `None` if the transaction is unknown to the provider.
+    pub async fn analyze(&self, txid: TXID) -> Result<Option<ZeroConfReport>, ProviderError> {
+        let tx = match self.provider.get_tx(txid).await? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+        let confirmations = self.provider.get_confs(txid).await?.unwrap_or(0);
+
+        let mut report = ZeroConfReport {
+            txid,
+            confirmations,
+            signals_rbf: signals_rbf(&tx),
+            rbf_ancestors: vec![],
+            unconfirmed_ancestors: vec![],
+            fee: None,
+            vsize: tx.vsize(),
+            feerate_percentile: None,
+            conflicts: vec![],
+        };
+        if confirmations > 0 {
+            return Ok(Some(report));
+        }
+
+        let mut parents = HashMap::new();
+        for input in tx.inputs() {
+            let parent_id = input.outpoint.txid;
+            if let Some(parent) = self.provider.get_tx(parent_id).await? {
+                parents.insert(parent_id, parent);
+            }
+        }
+
+        report.fee = self.fee(&tx, &parents);
+        self.walk_ancestors(&tx, parents, &mut report).await?;
+        self.find_conflicts(&tx, &mut report).await?;
+
+        if let Some(feerate) = report.feerate() {
+            report.feerate_percentile = match self.provider.get_fee_histogram().await {
+                Ok(histogram) => histogram.percentile_of(feerate),
+                Err(ProviderError::Unsupported(_)) => None,
+                Err(e) => return Err(e),
+            };
+        }
+
+        Ok(Some(report))
+    }
+
+    fn fee(&self, tx: &BitcoinTx, parents: &HashMap<TXID, BitcoinTx>) -> Option<u64> {
+        let mut value_in = Amount::ZERO;
+        for input in tx.inputs() {
+            let parent = parents.get(&input.outpoint.txid)?;
+            let prevout = parent.outputs().get(input.outpoint.idx as usize)?;
+            value_in = value_in.checked_add(prevout.value)?;
+        }
+        let value_out = Amount::checked_sum(tx.outputs().iter().map(|o| o.value))?;
+        value_in.checked_sub(value_out).map(Amount::to_sat)
+    }
+
+    async fn walk_ancestors(
+        &self,
+        tx: &BitcoinTx,
+        mut known: HashMap<TXID, BitcoinTx>,
+        report: &mut ZeroConfReport,
+    ) -> Result<(), ProviderError> {
+        let mut seen = HashSet::new();
+        let mut frontier: Vec<TXID> = tx.inputs().iter().map(|i| i.outpoint.txid).collect();
+
+        for _ in 0..self.max_depth {
+            let mut next = vec![];
+            for ancestor_id in frontier.into_iter() {
+                if !seen.insert(ancestor_id) {
+                    continue;
+                }
+                if self.provider.get_confs(ancestor_id).await? != Some(0) {
+                    continue;
+                }
+                let ancestor = match known.remove(&ancestor_id) {
+                    Some(ancestor) => ancestor,
+                    None => match self.provider.get_tx(ancestor_id).await? {
+                        Some(ancestor) => ancestor,
+                        None => continue,
+                    },
+                };
+
+                report.unconfirmed_ancestors.push(ancestor_id);
+                if signals_rbf(&ancestor) {
+                    report.rbf_ancestors.push(ancestor_id);
+                }
+                next.extend(ancestor.inputs().iter().map(|i| i.outpoint.txid));
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        Ok(())
+    }
+
+    async fn find_conflicts(
+        &self,
+        tx: &BitcoinTx,
+        report: &mut ZeroConfReport,
+    ) -> Result<(), ProviderError> {
+        for input in tx.inputs() {
+            match self.provider.get_outspend(input.outpoint).await {
+                // A null txid means the provider knows the outpoint is spent, but not by whom
+                Ok(Some(spender)) if spender != report.txid && spender != TXID::default() => {
+                    report.conflicts.push(spender)
+                }
+                Ok(_) => {}
+                Err(ProviderError::Unsupported(_)) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report() -> ZeroConfReport {
+        ZeroConfReport {
+            txid: TXID::default(),
+            confirmations: 0,
+            signals_rbf: false,
+            rbf_ancestors: vec![],
+            unconfirmed_ancestors: vec![],
+            fee: Some(2000),
+            vsize: 200,
+            feerate_percentile: Some(0.8),
+            conflicts: vec![],
+        }
+    }
+
+    #[test]
+    fn it_detects_rbf_signaling() {
+        let tx = bitcoins::Net::tx_builder()
+            .version(2)
+            .spend(BitcoinOutpoint::default(), 0xffff_fffe)
+            .pay_script_pubkey(Amount::from_sat(1000), ScriptPubkey::default())
+            .build()
+            .unwrap();
+        assert!(!signals_rbf(&tx));
+
+        let tx = bitcoins::Net::tx_builder()
+            .version(2)
+            .spend(BitcoinOutpoint::default(), 0xffff_fffe)
+            .spend(BitcoinOutpoint::default(), 0xffff_fffd)
+            .pay_script_pubkey(Amount::from_sat(1000), ScriptPubkey::default())
+            .build()
+            .unwrap();
+        assert!(signals_rbf(&tx));
+    }
+
+    #[test]
+    fn it_scores_reports() {
+        let mut r = report();
+        assert_eq!(r.feerate(), Some(10.0));
+        assert_eq!(r.score(), 0);
+        assert_eq!(r.risk(), ZeroConfRisk::Low);
+
+        r.feerate_percentile = Some(0.1);
+        r.unconfirmed_ancestors = vec![TXID::default(); 2];
+        assert_eq!(r.score(), 30);
+        assert_eq!(r.risk(), ZeroConfRisk::Medium);
+
+        r.signals_rbf = true;
+        r.rbf_ancestors = vec![TXID::default()];
+        assert_eq!(r.score(), 95);
+        assert_eq!(r.risk(), ZeroConfRisk::High);
+
+        r.conflicts = vec![TXID::default()];
+        assert_eq!(r.score(), 100);
+
+        r.confirmations = 1;
+        assert_eq!(r.score(), 0);
+        assert_eq!(r.risk(), ZeroConfRisk::Low);
+    }
+
+    #[test]
+    fn it_penalizes_unknown_fees() {
+        let mut r = report();
+        r.fee = None;
+        r.feerate_percentile = None;
+        assert_eq!(r.feerate(), None);
+        assert_eq!(r.score(), 10);
+    }
+}
@@ -0,0 +1,75 @@
+use std::ops::Range;
+
+use rayon::prelude::*;
+
+use crate::{
+    xkeys::{Parent, XPub},
+    Bip32Error,
+};
+
+impl XPub {
+    /// Derive the non-hardened children at each index in `indices`, spreading the work across
+    /// the rayon thread pool. Children are returned in index order.
+    ///
+    /// Useful for wallet rescans and address pre-generation, where tens of thousands of keys
+    /// are derived from a single account key.
+    pub fn derive_children_par(&self, indices: Range<u32>) -> Result<Vec<XPub>, Bip32Error> {
+        self.derive_children_par_with(indices, |_, child| child)
+    }
+
+    /// Derive the non-hardened children at each index in `indices` in parallel, and apply `f`
+    /// to each. `f` receives the child index and the derived key, and may e.g. encode the key
+    /// as an address. Results are returned in index order.
+    ///
+    /// Fails with `HardenedDerivationFailed` if the range contains hardened indices.
+    pub fn derive_children_par_with<T, F>(
+        &self,
+        indices: Range<u32>,
+        f: F,
+    ) -> Result<Vec<T>, Bip32Error>
+    where
+        T: Send,
+        F: Fn(u32, XPub) -> T + Sync + Send,
+    {
+        indices
+            .into_par_iter()
+            .map(|index| self.derive_child(index).map(|child| f(index, child)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{prelude::*, BIP32_HARDEN};
+
+    fn root() -> XPub {
+        let seed: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        XPriv::root_from_seed(&seed, Some(Hint::Legacy))
+            .unwrap()
+            .verify_key()
+    }
+
+    #[test]
+    fn it_matches_sequential_derivation() {
+        let xpub = root().derive_child(0).unwrap();
+        let children = xpub.derive_children_par(0..200).unwrap();
+        assert_eq!(children.len(), 200);
+        for (i, child) in children.iter().enumerate() {
+            assert_eq!(child, &xpub.derive_child(i as u32).unwrap());
+        }
+
+        let hashes = xpub
+            .derive_children_par_with(10..20, |i, child| (i, child.pubkey_hash160()))
+            .unwrap();
+        assert_eq!(hashes[3].0, 13);
+        assert_eq!(hashes[3].1, children[13].pubkey_hash160());
+    }
+
+    #[test]
+    fn it_rejects_hardened_indices() {
+        match root().derive_children_par(BIP32_HARDEN - 2..BIP32_HARDEN + 2) {
+            Err(Bip32Error::HardenedDerivationFailed) => {}
+            _ => panic!("expected hardened derivation failure"),
+        }
+    }
+}
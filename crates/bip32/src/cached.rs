@@ -0,0 +1,264 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use crate::{
+    path::DerivationPath,
+    xkeys::{Parent, XPub},
+    Bip32Error,
+};
+
+/// The default number of derived keys held by a `CachedXPub`.
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+/// Entries are stamped with the tick of their last use. `order` maps ticks back to entries, so
+/// the least recently used entry is the first in `order`.
+#[derive(Debug, Default)]
+struct DerivationCache {
+    entries: HashMap<Vec<u32>, (XPub, u64)>,
+    order: BTreeMap<u64, Vec<u32>>,
+    tick: u64,
+}
+
+impl DerivationCache {
+    /// Get a cached key, and mark it as most recently used.
+    fn get(&mut self, suffix: &[u32]) -> Option<XPub> {
+        let (xpub, used) = self.entries.get_mut(suffix)?;
+        let key = self.order.remove(used).expect("entries and order agree");
+        self.tick += 1;
+        *used = self.tick;
+        self.order.insert(self.tick, key);
+        Some(*xpub)
+    }
+
+    /// Find the longest cached prefix of `suffix`. Returns the prefix length and the key.
+    fn longest_prefix(&mut self, suffix: &[u32]) -> Option<(usize, XPub)> {
+        (1..=suffix.len())
+            .rev()
+            .find_map(|len| self.get(&suffix[..len]).map(|xpub| (len, xpub)))
+    }
+
+    fn insert(&mut self, suffix: Vec<u32>, xpub: XPub, capacity: usize) {
+        if capacity == 0 || self.get(&suffix).is_some() {
+            return;
+        }
+        while self.entries.len() >= capacity {
+            let oldest = match self.order.keys().next() {
+                Some(tick) => *tick,
+                None => break,
+            };
+            if let Some(key) = self.order.remove(&oldest) {
+                self.entries.remove(&key);
+            }
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, suffix.clone());
+        self.entries.insert(suffix, (xpub, self.tick));
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// An `XPub` that memoizes its derived descendants.
+///
+/// Descendants are keyed by their path relative to the wrapped key. Every intermediate key
+/// along a derivation is cached as well, so deriving `0/5` after `0/4` only performs the final
+/// step. The cache holds at most `capacity` keys, and evicts the least recently used entries
+/// first.
+///
+/// The cache uses interior mutability, so a `CachedXPub` may be shared between threads. The lock
+/// is not held while deriving keys.
+#[derive(Debug)]
+pub struct CachedXPub {
+    xpub: XPub,
+    capacity: usize,
+    cache: Mutex<DerivationCache>,
+}
+
+impl Clone for CachedXPub {
+    fn clone(&self) -> Self {
+        Self::with_capacity(self.xpub, self.capacity)
+    }
+}
+
+impl AsRef<XPub> for CachedXPub {
+    fn as_ref(&self) -> &XPub {
+        &self.xpub
+    }
+}
+
+impl From<XPub> for CachedXPub {
+    fn from(xpub: XPub) -> Self {
+        Self::new(xpub)
+    }
+}
+
+impl CachedXPub {
+    /// Wrap an `XPub` in a cache holding up to `DEFAULT_CACHE_CAPACITY` derived keys.
+    pub fn new(xpub: XPub) -> Self {
+        Self::with_capacity(xpub, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Wrap an `XPub` in a cache holding up to `capacity` derived keys. A capacity of 0
+    /// disables caching.
+    pub fn with_capacity(xpub: XPub, capacity: usize) -> Self {
+        Self {
+            xpub,
+            capacity,
+            cache: Default::default(),
+        }
+    }
+
+    /// Return the wrapped `XPub`.
+    pub const fn xpub(&self) -> &XPub {
+        &self.xpub
+    }
+
+    /// The maximum number of derived keys held in the cache.
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of derived keys currently held in the cache.
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+
+    /// Returns `true` if the cache holds no derived keys.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop all cached keys.
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear()
+    }
+
+    /// Derive the child at `index`, consulting the cache first.
+    pub fn derive_child(&self, index: u32) -> Result<XPub, Bip32Error> {
+        self.derive_path([index].as_ref())
+    }
+
+    /// Derive a descendant by its path relative to the wrapped key, consulting the cache first.
+    /// Accepts an iterator producing u32, or a string.
+    pub fn derive_path<E, P>(&self, p: P) -> Result<XPub, Bip32Error>
+    where
+        E: Into<Bip32Error>,
+        P: TryInto<DerivationPath, Error = E>,
+    {
+        let path: DerivationPath = p.try_into().map_err(Into::into)?;
+        let suffix: Vec<u32> = path.iter().copied().collect();
+
+        if suffix.is_empty() {
+            return Ok(self.xpub);
+        }
+
+        let (start, mut current) = self
+            .cache
+            .lock()
+            .unwrap()
+            .longest_prefix(&suffix)
+            .unwrap_or((0, self.xpub));
+
+        let mut derived = Vec::with_capacity(suffix.len() - start);
+        let result = (start..suffix.len()).try_for_each(|depth| {
+            current = current.derive_child(suffix[depth])?;
+            derived.push((suffix[..=depth].to_vec(), current));
+            Ok(())
+        });
+
+        // cache the keys derived before any failure
+        let mut cache = self.cache.lock().unwrap();
+        for (prefix, xpub) in derived {
+            cache.insert(prefix, xpub, self.capacity);
+        }
+        result.map(|_| current)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{prelude::*, BIP32_HARDEN};
+
+    fn root() -> XPub {
+        let seed: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        XPriv::root_from_seed(&seed, Some(Hint::Legacy))
+            .unwrap()
+            .verify_key()
+    }
+
+    #[test]
+    fn it_matches_uncached_derivation() {
+        let xpub = root();
+        let cached = CachedXPub::new(xpub);
+
+        for path in ["m/0/1", "m/0/2", "m/1/0/7", "m/0/1"].iter() {
+            assert_eq!(
+                cached.derive_path(*path).unwrap(),
+                xpub.derive_path(*path).unwrap()
+            );
+        }
+        assert_eq!(
+            cached.derive_child(3).unwrap(),
+            xpub.derive_child(3).unwrap()
+        );
+        // m/0, m/0/1, m/0/2, m/1, m/1/0, m/1/0/7, m/3
+        assert_eq!(cached.len(), 7);
+    }
+
+    #[test]
+    fn it_evicts_oldest_entries() {
+        let cached = CachedXPub::with_capacity(root(), 3);
+        for i in 0..5 {
+            cached.derive_child(i).unwrap();
+        }
+        assert_eq!(cached.len(), 3);
+
+        let mut cache = cached.cache.lock().unwrap();
+        assert!(cache.get(&[0]).is_none());
+        assert!(cache.get(&[1]).is_none());
+        assert!(cache.get(&[4]).is_some());
+    }
+
+    #[test]
+    fn it_evicts_least_recently_used_entries() {
+        let cached = CachedXPub::with_capacity(root(), 3);
+        for i in 0..3 {
+            cached.derive_child(i).unwrap();
+        }
+        // a hit refreshes 0, so 1 is evicted instead
+        cached.derive_child(0).unwrap();
+        cached.derive_child(3).unwrap();
+
+        let mut cache = cached.cache.lock().unwrap();
+        assert!(cache.get(&[0]).is_some());
+        assert!(cache.get(&[1]).is_none());
+        assert!(cache.get(&[2]).is_some());
+        assert!(cache.get(&[3]).is_some());
+    }
+
+    #[test]
+    fn it_does_not_cache_failures() {
+        let cached = CachedXPub::new(root());
+        match cached.derive_path([0, BIP32_HARDEN].as_ref()) {
+            Err(Bip32Error::HardenedDerivationFailed) => {}
+            _ => panic!("expected hardened derivation failure"),
+        }
+        assert_eq!(cached.len(), 1);
+
+        cached.clear();
+        assert!(cached.is_empty());
+    }
+
+    #[test]
+    fn it_can_be_disabled() {
+        let cached = CachedXPub::with_capacity(root(), 0);
+        cached.derive_path("m/0/1").unwrap();
+        assert!(cached.is_empty());
+    }
+}
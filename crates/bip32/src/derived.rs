@@ -3,7 +3,7 @@ use k256::ecdsa;
 use coins_core::prelude::{Hash160, Hash160Digest, MarkedDigest, MarkedDigestOutput};
 
 use crate::{
-    path::{DerivationPath, KeyDerivation},
+    path::{DerivationPath, KeyDerivation, KeySource},
     primitives::{Hint, KeyFingerprint, XKeyInfo},
     xkeys::{Parent, XPriv, XPub, SEED},
     Bip32Error,
@@ -15,6 +15,11 @@ pub trait DerivedKey {
     /// Return this key's derivation
     fn derivation(&self) -> &KeyDerivation;
 
+    /// Return this key's origin, suitable for descriptor key prefixes and PSBT derivation fields
+    fn key_source(&self) -> KeySource {
+        self.derivation().clone()
+    }
+
     /// `true` if the keys share a root fingerprint, `false` otherwise. Note that on key
     /// fingerprints, which may collide accidentally, or be intentionally collided.
     fn same_root<K: DerivedKey>(&self, other: &K) -> bool {
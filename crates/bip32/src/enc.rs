@@ -8,6 +8,14 @@ use crate::{
     Bip32Error,
 };
 
+mod custom;
+pub use custom::{CustomEncoder, VersionBytes};
+
+#[cfg(feature = "encryption")]
+mod encrypted;
+#[cfg(feature = "encryption")]
+pub use encrypted::EncryptedXPriv;
+
 /// Decode a bytevector from a base58 check string
 pub fn decode_b58_check(s: &str) -> Result<Vec<u8>, Bip32Error> {
     let data: Vec<u8> = bs58::decode(s).into_vec()?;
@@ -0,0 +1,262 @@
+use crate::{
+    enc::{decode_b58_check, encode_b58_check, MainnetEncoder, NetworkParams, XKeyEncoder},
+    primitives::Hint,
+    xkeys::{XPriv, XPub},
+    Bip32Error,
+};
+
+/// A pair of xpriv and xpub version bytes, e.g. `xprv`/`xpub` or `zprv`/`zpub`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionBytes {
+    /// The xpriv version bytes
+    pub xpriv: u32,
+    /// The xpub version bytes
+    pub xpub: u32,
+}
+
+impl VersionBytes {
+    /// Instantiate a version byte pair
+    pub const fn new(xpriv: u32, xpub: u32) -> Self {
+        Self { xpriv, xpub }
+    }
+}
+
+/// An xkey encoder with version bytes chosen at runtime.
+///
+/// `MainnetEncoder` and `TestnetEncoder` have their version bytes compiled in. A
+/// `CustomEncoder` instead holds one `VersionBytes` pair per `Hint`, so altcoin or vendor
+/// prefixes can be used without defining new `NetworkParams`. Keys are written with the pair
+/// registered for their hint, and read keys receive the hint whose pair matched.
+///
+/// ```
+/// use coins_bip32::{Bip32Error, enc::{CustomEncoder, VersionBytes}, prelude::*};
+/// # fn main() -> Result<(), Bip32Error> {
+/// # let seed = [0u8; 16];
+/// # let xpriv = XPriv::root_from_seed(&seed, Some(Hint::Legacy))?;
+/// // Litecoin Ltpv/Ltub
+/// let encoder = CustomEncoder::new(VersionBytes::new(0x019d_9cfe, 0x019d_a462));
+/// let ltub = encoder.xpub_to_base58(&xpriv.verify_key())?;
+/// assert!(ltub.starts_with("Ltub"));
+/// assert_eq!(encoder.xpub_from_base58(&ltub)?, xpriv.verify_key());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustomEncoder {
+    legacy: Option<VersionBytes>,
+    compatibility: Option<VersionBytes>,
+    segwit: Option<VersionBytes>,
+}
+
+impl CustomEncoder {
+    /// Instantiate an encoder with version bytes for `Hint::Legacy` keys only.
+    pub const fn new(legacy: VersionBytes) -> Self {
+        Self {
+            legacy: Some(legacy),
+            compatibility: None,
+            segwit: None,
+        }
+    }
+
+    /// Instantiate an encoder with the version bytes of a compiled-in `NetworkParams`.
+    pub const fn from_params<P: NetworkParams>() -> Self {
+        Self {
+            legacy: Some(VersionBytes::new(P::PRIV_VERSION, P::PUB_VERSION)),
+            compatibility: Some(VersionBytes::new(
+                P::BIP49_PRIV_VERSION,
+                P::BIP49_PUB_VERSION,
+            )),
+            segwit: Some(VersionBytes::new(
+                P::BIP84_PRIV_VERSION,
+                P::BIP84_PUB_VERSION,
+            )),
+        }
+    }
+
+    /// Register the version bytes used for keys with `hint`, replacing any existing pair.
+    pub const fn with_version(mut self, hint: Hint, version: VersionBytes) -> Self {
+        match hint {
+            Hint::Legacy => self.legacy = Some(version),
+            Hint::Compatibility => self.compatibility = Some(version),
+            Hint::SegWit => self.segwit = Some(version),
+        }
+        self
+    }
+
+    /// Return the version bytes registered for `hint`, if any.
+    pub const fn version(&self, hint: Hint) -> Option<VersionBytes> {
+        match hint {
+            Hint::Legacy => self.legacy,
+            Hint::Compatibility => self.compatibility,
+            Hint::SegWit => self.segwit,
+        }
+    }
+
+    fn registered(&self) -> impl Iterator<Item = (Hint, VersionBytes)> {
+        [
+            (Hint::Legacy, self.legacy),
+            (Hint::Compatibility, self.compatibility),
+            (Hint::SegWit, self.segwit),
+        ]
+        .into_iter()
+        .filter_map(|(hint, version)| version.map(|v| (hint, v)))
+    }
+
+    fn version_for(&self, hint: Hint) -> Result<VersionBytes, Bip32Error> {
+        self.version(hint)
+            .ok_or(Bip32Error::UnregisteredVersionBytes(hint))
+    }
+
+    /// Serialize the xpub to `std::io::Write`
+    pub fn write_xpub<W, K>(&self, writer: &mut W, key: &K) -> Result<usize, Bip32Error>
+    where
+        W: std::io::Write,
+        K: AsRef<XPub>,
+    {
+        let key = key.as_ref();
+        let version = self.version_for(key.xkey_info.hint)?.xpub;
+        let mut written = writer.write(&version.to_be_bytes())?;
+        written += MainnetEncoder::write_key_details(writer, key)?;
+        written += writer.write(key.key.to_sec1_bytes().as_ref())?;
+        Ok(written)
+    }
+
+    /// Serialize the xpriv to `std::io::Write`
+    pub fn write_xpriv<W, K>(&self, writer: &mut W, key: &K) -> Result<usize, Bip32Error>
+    where
+        W: std::io::Write,
+        K: AsRef<XPriv>,
+    {
+        let key = key.as_ref();
+        let version = self.version_for(key.xkey_info.hint)?.xpriv;
+        let mut written = writer.write(&version.to_be_bytes())?;
+        written += MainnetEncoder::write_key_details(writer, key)?;
+        written += writer.write(&[0])?;
+        written += writer.write(key.key.to_bytes().as_ref())?;
+        Ok(written)
+    }
+
+    /// Attempt to instantiate an `XPriv` from a `std::io::Read`
+    pub fn read_xpriv<R>(&self, reader: &mut R) -> Result<XPriv, Bip32Error>
+    where
+        R: std::io::Read,
+    {
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        let version_bytes = u32::from_be_bytes(buf);
+
+        let hint = self
+            .registered()
+            .find(|(_, v)| v.xpriv == version_bytes)
+            .map(|(hint, _)| hint)
+            .ok_or(Bip32Error::BadXPrivVersionBytes(buf))?;
+        MainnetEncoder::read_xpriv_body(reader, hint)
+    }
+
+    /// Attempt to instantiate an `XPub` from a `std::io::Read`
+    pub fn read_xpub<R>(&self, reader: &mut R) -> Result<XPub, Bip32Error>
+    where
+        R: std::io::Read,
+    {
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        let version_bytes = u32::from_be_bytes(buf);
+
+        let hint = self
+            .registered()
+            .find(|(_, v)| v.xpub == version_bytes)
+            .map(|(hint, _)| hint)
+            .ok_or(Bip32Error::BadXPubVersionBytes(buf))?;
+        MainnetEncoder::read_xpub_body(reader, hint)
+    }
+
+    /// Serialize an XPriv to base58
+    pub fn xpriv_to_base58<K>(&self, k: &K) -> Result<String, Bip32Error>
+    where
+        K: AsRef<XPriv>,
+    {
+        let mut v: Vec<u8> = vec![];
+        self.write_xpriv(&mut v, k)?;
+        Ok(encode_b58_check(&v))
+    }
+
+    /// Serialize an XPub to base58
+    pub fn xpub_to_base58<K>(&self, k: &K) -> Result<String, Bip32Error>
+    where
+        K: AsRef<XPub>,
+    {
+        let mut v: Vec<u8> = vec![];
+        self.write_xpub(&mut v, k)?;
+        Ok(encode_b58_check(&v))
+    }
+
+    /// Attempt to read an XPriv from a b58check string.
+    pub fn xpriv_from_base58(&self, s: &str) -> Result<XPriv, Bip32Error> {
+        let data = decode_b58_check(s)?;
+        self.read_xpriv(&mut &data[..])
+    }
+
+    /// Attempt to read an XPub from a b58check string.
+    pub fn xpub_from_base58(&self, s: &str) -> Result<XPub, Bip32Error> {
+        let data = decode_b58_check(s)?;
+        self.read_xpub(&mut &data[..])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::enc::Main;
+
+    #[test]
+    fn it_matches_compiled_in_encoders() {
+        let encoder = CustomEncoder::from_params::<Main>();
+        let xpriv_str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+        let mut xpriv = MainnetEncoder::xpriv_from_base58(xpriv_str).unwrap();
+
+        for hint in [Hint::Legacy, Hint::Compatibility, Hint::SegWit].iter() {
+            xpriv.xkey_info.hint = *hint;
+            let xpub = xpriv.verify_key();
+
+            let expected = MainnetEncoder::xpriv_to_base58(&xpriv).unwrap();
+            assert_eq!(encoder.xpriv_to_base58(&xpriv).unwrap(), expected);
+            let read = encoder.xpriv_from_base58(&expected).unwrap();
+            assert_eq!(read, xpriv);
+            assert_eq!(read.xkey_info.hint, *hint);
+
+            let expected = MainnetEncoder::xpub_to_base58(&xpub).unwrap();
+            assert_eq!(encoder.xpub_to_base58(&xpub).unwrap(), expected);
+            let read = encoder.xpub_from_base58(&expected).unwrap();
+            assert_eq!(read, xpub);
+            assert_eq!(read.xkey_info.hint, *hint);
+        }
+    }
+
+    #[test]
+    fn it_uses_registered_versions() {
+        let xpriv_str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+        let xpriv = MainnetEncoder::xpriv_from_base58(xpriv_str).unwrap();
+
+        // Litecoin Ltpv/Ltub
+        let ltc = CustomEncoder::new(VersionBytes::new(0x019d_9cfe, 0x019d_a462));
+        let ltpv = ltc.xpriv_to_base58(&xpriv).unwrap();
+        assert!(ltpv.starts_with("Ltpv"));
+        assert_eq!(ltc.xpriv_from_base58(&ltpv).unwrap(), xpriv);
+        assert!(ltc
+            .xpub_to_base58(&xpriv.verify_key())
+            .unwrap()
+            .starts_with("Ltub"));
+
+        match ltc.xpriv_from_base58(xpriv_str) {
+            Err(Bip32Error::BadXPrivVersionBytes(_)) => {}
+            _ => panic!("expected bad version bytes"),
+        }
+
+        let mut segwit = xpriv;
+        segwit.xkey_info.hint = Hint::SegWit;
+        match ltc.xpriv_to_base58(&segwit) {
+            Err(Bip32Error::UnregisteredVersionBytes(Hint::SegWit)) => {}
+            _ => panic!("expected unregistered hint"),
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use coins_core::ser::ByteFormat;
+use std::io::{Read, Write};
+use zeroize::Zeroizing;
+
+use crate::{
+    enc::{MainnetEncoder, XKeyEncoder},
+    primitives::Hint,
+    xkeys::XPriv,
+    Bip32Error,
+};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+// hint, depth, parent, index, chain code, padding, key
+const PLAINTEXT_LEN: usize = 1 + 1 + 4 + 4 + 32 + 1 + 32;
+// version, log_n, r, p, salt, nonce
+const HEADER_LEN: usize = 1 + 1 + 4 + 4 + SALT_LEN + NONCE_LEN;
+
+/// An `XPriv` encrypted under a passphrase, suitable for persisting at rest.
+///
+/// The passphrase is stretched with scrypt, and the key is sealed with XChaCha20Poly1305. The
+/// serialized container is versioned, and carries the scrypt parameters, salt, and nonce
+/// alongside the ciphertext. The whole header is authenticated, so tampering with the
+/// parameters is detected on `unseal`.
+///
+/// ```
+/// # use coins_bip32::{enc::EncryptedXPriv, prelude::*};
+/// # fn main() -> Result<(), Bip32Error> {
+/// # let seed = [0u8; 16];
+/// let xpriv = XPriv::root_from_seed(&seed, None)?;
+/// // Low-cost parameters for the example. Prefer `EncryptedXPriv::seal`.
+/// let sealed = EncryptedXPriv::seal_with_params(&xpriv, "hunter2", 10, 8, 1)?;
+/// assert_eq!(sealed.unseal("hunter2")?, xpriv);
+/// assert!(sealed.unseal("hunter3").is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedXPriv {
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+impl EncryptedXPriv {
+    /// The current container format version.
+    pub const VERSION: u8 = 1;
+    /// The default scrypt cost parameter, as log2(N).
+    pub const DEFAULT_LOG_N: u8 = 17;
+    /// The default scrypt block size parameter.
+    pub const DEFAULT_R: u32 = 8;
+    /// The default scrypt parallelization parameter.
+    pub const DEFAULT_P: u32 = 1;
+    /// The largest scrypt cost parameter accepted when deserializing. Together with `MAX_R`
+    /// and `MAX_P`, this bounds the work and memory an untrusted container can demand: scrypt
+    /// needs 128·r·N bytes, at most 1 GiB, and its work grows with N·r·p.
+    pub const MAX_LOG_N: u8 = 20;
+    /// The largest scrypt block size parameter accepted when deserializing.
+    pub const MAX_R: u32 = 8;
+    /// The largest scrypt parallelization parameter accepted when deserializing.
+    pub const MAX_P: u32 = 4;
+
+    /// Encrypt an `XPriv` under `passphrase` using the default scrypt parameters.
+    pub fn seal(xpriv: &XPriv, passphrase: &str) -> Result<Self, Bip32Error> {
+        Self::seal_with_params(
+            xpriv,
+            passphrase,
+            Self::DEFAULT_LOG_N,
+            Self::DEFAULT_R,
+            Self::DEFAULT_P,
+        )
+    }
+
+    /// Encrypt an `XPriv` under `passphrase` using custom scrypt parameters. Errors with
+    /// `InvalidScryptParams` if the parameters exceed `MAX_LOG_N`, `MAX_R` or `MAX_P`, as the
+    /// container could not be deserialized.
+    pub fn seal_with_params(
+        xpriv: &XPriv,
+        passphrase: &str,
+        log_n: u8,
+        r: u32,
+        p: u32,
+    ) -> Result<Self, Bip32Error> {
+        Self::check_params(log_n, r, p)?;
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let mut sealed = Self {
+            log_n,
+            r,
+            p,
+            salt,
+            nonce: nonce.into(),
+            ciphertext: vec![],
+        };
+
+        let mut plaintext = Zeroizing::new(Vec::with_capacity(PLAINTEXT_LEN));
+        plaintext.push(hint_to_byte(xpriv.xkey_info.hint));
+        MainnetEncoder::write_key_details(&mut *plaintext, xpriv)?;
+        plaintext.push(0);
+        plaintext.extend_from_slice(xpriv.key.to_bytes().as_ref());
+
+        let cipher = sealed.cipher(passphrase)?;
+        sealed.ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: &sealed.header(),
+                },
+            )
+            .map_err(|_| Bip32Error::EncryptionFailed)?;
+        Ok(sealed)
+    }
+
+    /// Reject scrypt parameters above the limits accepted when deserializing.
+    const fn check_params(log_n: u8, r: u32, p: u32) -> Result<(), Bip32Error> {
+        if log_n > Self::MAX_LOG_N || r > Self::MAX_R || p > Self::MAX_P {
+            return Err(Bip32Error::InvalidScryptParams);
+        }
+        Ok(())
+    }
+
+    /// Decrypt the `XPriv` with `passphrase`.
+    ///
+    /// A wrong passphrase and a corrupted container are indistinguishable, and both return
+    /// `DecryptionFailed`. The key stretching runs in full, and the authentication tag is
+    /// checked in constant time, so the failure leaks no timing information about the
+    /// passphrase.
+    pub fn unseal(&self, passphrase: &str) -> Result<XPriv, Bip32Error> {
+        let cipher = self.cipher(passphrase)?;
+        let plaintext = Zeroizing::new(
+            cipher
+                .decrypt(
+                    XNonce::from_slice(&self.nonce),
+                    Payload {
+                        msg: &self.ciphertext,
+                        aad: &self.header(),
+                    },
+                )
+                .map_err(|_| Bip32Error::DecryptionFailed)?,
+        );
+
+        let mut reader = &plaintext[..];
+        let mut hint = [0u8];
+        reader.read_exact(&mut hint)?;
+        MainnetEncoder::read_xpriv_body(&mut reader, byte_to_hint(hint[0])?)
+    }
+
+    /// The scrypt parameters as `(log_n, r, p)`.
+    pub const fn params(&self) -> (u8, u32, u32) {
+        (self.log_n, self.r, self.p)
+    }
+
+    fn header(&self) -> [u8; HEADER_LEN] {
+        let mut header = [0u8; HEADER_LEN];
+        header[0] = Self::VERSION;
+        header[1] = self.log_n;
+        header[2..6].copy_from_slice(&self.r.to_be_bytes());
+        header[6..10].copy_from_slice(&self.p.to_be_bytes());
+        header[10..10 + SALT_LEN].copy_from_slice(&self.salt);
+        header[10 + SALT_LEN..].copy_from_slice(&self.nonce);
+        header
+    }
+
+    fn cipher(&self, passphrase: &str) -> Result<XChaCha20Poly1305, Bip32Error> {
+        let params = scrypt::Params::new(self.log_n, self.r, self.p, 32)
+            .map_err(|_| Bip32Error::InvalidScryptParams)?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        scrypt::scrypt(passphrase.as_bytes(), &self.salt, &params, &mut key[..])
+            .map_err(|_| Bip32Error::InvalidScryptParams)?;
+        Ok(XChaCha20Poly1305::new(Key::from_slice(&key[..])))
+    }
+}
+
+const fn hint_to_byte(hint: Hint) -> u8 {
+    match hint {
+        Hint::Legacy => 0,
+        Hint::Compatibility => 1,
+        Hint::SegWit => 2,
+    }
+}
+
+const fn byte_to_hint(byte: u8) -> Result<Hint, Bip32Error> {
+    match byte {
+        0 => Ok(Hint::Legacy),
+        1 => Ok(Hint::Compatibility),
+        2 => Ok(Hint::SegWit),
+        _ => Err(Bip32Error::DecryptionFailed),
+    }
+}
+
+impl ByteFormat for EncryptedXPriv {
+    type Error = Bip32Error;
+
+    fn serialized_length(&self) -> usize {
+        HEADER_LEN + self.ciphertext.len()
+    }
+
+    fn read_from<R>(reader: &mut R) -> Result<Self, Self::Error>
+    where
+        R: Read,
+        Self: std::marker::Sized,
+    {
+        let mut header = [0u8; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        if header[0] != Self::VERSION {
+            return Err(Bip32Error::UnknownEncryptionVersion(header[0]));
+        }
+
+        let log_n = header[1];
+        let mut r = [0u8; 4];
+        r.copy_from_slice(&header[2..6]);
+        let r = u32::from_be_bytes(r);
+        let mut p = [0u8; 4];
+        p.copy_from_slice(&header[6..10]);
+        let p = u32::from_be_bytes(p);
+        Self::check_params(log_n, r, p)?;
+
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&header[10..10 + SALT_LEN]);
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&header[10 + SALT_LEN..]);
+
+        let mut ciphertext = vec![0u8; PLAINTEXT_LEN + TAG_LEN];
+        reader.read_exact(&mut ciphertext)?;
+
+        Ok(Self {
+            log_n,
+            r,
+            p,
+            salt,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    fn write_to<W>(&self, writer: &mut W) -> Result<usize, Self::Error>
+    where
+        W: Write,
+    {
+        let mut written = writer.write(&self.header())?;
+        written += writer.write(&self.ciphertext)?;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+
+    fn root() -> XPriv {
+        let seed: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        XPriv::root_from_seed(&seed, Some(Hint::SegWit)).unwrap()
+    }
+
+    #[test]
+    fn it_seals_and_unseals() {
+        let xpriv = root().derive_path("m/84h/0h/0h").unwrap();
+        let sealed = EncryptedXPriv::seal_with_params(&xpriv, "passphrase", 8, 8, 1).unwrap();
+
+        let hex = sealed.serialize_hex();
+        assert_eq!(hex.len(), 2 * (HEADER_LEN + PLAINTEXT_LEN + TAG_LEN));
+        let deserialized = EncryptedXPriv::deserialize_hex(&hex).unwrap();
+        assert_eq!(deserialized, sealed);
+
+        let unsealed = deserialized.unseal("passphrase").unwrap();
+        assert_eq!(unsealed, xpriv);
+        assert_eq!(unsealed.xkey_info.hint, Hint::SegWit);
+        assert_eq!(unsealed.xkey_info.index, xpriv.xkey_info.index);
+        assert_eq!(unsealed.xkey_info.depth, 3);
+    }
+
+    #[test]
+    fn it_rejects_wrong_passphrases_and_tampering() {
+        let sealed = EncryptedXPriv::seal_with_params(&root(), "passphrase", 8, 8, 1).unwrap();
+        match sealed.unseal("passphrasf") {
+            Err(Bip32Error::DecryptionFailed) => {}
+            _ => panic!("expected decryption failure"),
+        }
+
+        let mut tampered = sealed.clone();
+        tampered.salt[0] ^= 1;
+        match tampered.unseal("passphrase") {
+            Err(Bip32Error::DecryptionFailed) => {}
+            _ => panic!("expected decryption failure"),
+        }
+
+        let mut bytes = sealed.serialize_hex();
+        bytes.replace_range(0..2, "02");
+        match EncryptedXPriv::deserialize_hex(&bytes) {
+            Err(Bip32Error::UnknownEncryptionVersion(2)) => {}
+            _ => panic!("expected unknown version"),
+        }
+    }
+
+    #[test]
+    fn it_rejects_params_it_can_not_deserialize() {
+        let limits = [
+            (EncryptedXPriv::MAX_LOG_N + 1, 8, 1),
+            (8, EncryptedXPriv::MAX_R + 1, 1),
+            (8, 8, EncryptedXPriv::MAX_P + 1),
+        ];
+        for (log_n, r, p) in limits.iter() {
+            match EncryptedXPriv::seal_with_params(&root(), "passphrase", *log_n, *r, *p) {
+                Err(Bip32Error::InvalidScryptParams) => {}
+                _ => panic!("expected invalid scrypt params"),
+            }
+        }
+    }
+}
@@ -70,6 +70,16 @@ pub mod xkeys;
 /// Provides keys that are coupled with their derivation path
 pub mod derived;
 
+/// Signature serialization and canonicalization
+pub mod sig;
+
+/// Memoizing wrappers for repeated key derivation
+pub mod cached;
+
+/// Parallel batch derivation, backed by rayon
+#[cfg(feature = "rayon")]
+pub mod batch;
+
 #[doc(hidden)]
 #[cfg(any(feature = "mainnet", feature = "testnet"))]
 pub mod defaults;
@@ -151,6 +161,38 @@ pub enum Bip32Error {
     #[error("Attempted to deserialize a DER signature to a recoverable signature. Use deserialize_vrs instead")]
     NoRecoveryId,
 
+    /// Signature encoding was not strict DER
+    #[error("Signature is not strict DER")]
+    NonStrictDer,
+
+    /// Signature was not in low-S form
+    #[error("Signature s value is not low")]
+    HighS,
+
+    /// Parsing a key origin string failed
+    #[error("Malformatted key source: {0}")]
+    MalformattedKeySource(String),
+
+    /// A custom encoder has no version bytes for the key's hint
+    #[error("No version bytes registered for {0:?} keys")]
+    UnregisteredVersionBytes(primitives::Hint),
+
+    /// Encrypting an xpriv failed
+    #[error("Encryption failed")]
+    EncryptionFailed,
+
+    /// Decrypting an xpriv failed, due to a wrong passphrase or a corrupted container
+    #[error("Decryption failed. Wrong passphrase or corrupted data")]
+    DecryptionFailed,
+
+    /// The scrypt parameters of an encrypted xpriv were out of range
+    #[error("Invalid scrypt parameters")]
+    InvalidScryptParams,
+
+    /// Unrecognized version when deserializing an encrypted xpriv
+    #[error("Unknown encrypted xpriv version: {0}")]
+    UnknownEncryptionVersion(u8),
+
     /// Attempted to deserialize a very long path
     #[error("Invalid Bip32 Path.")]
     InvalidBip32Path,
@@ -112,3 +112,27 @@ macro_rules! params {
         }
     }
 }
+
+/// Parse a derivation path string at compile time into a `[u32; N]`. Malformatted paths are
+/// rejected with a compile error instead of a runtime parse error.
+///
+/// ```
+/// use coins_bip32::{derivation_path, path::DerivationPath};
+///
+/// const ACCOUNT: [u32; 3] = derivation_path!("m/84'/0'/0'");
+/// let path: DerivationPath = derivation_path!("m/84'/0'/0'/0/5").into();
+/// assert_eq!(path, "m/84h/0h/0h/0/5".parse().unwrap());
+/// assert!(path.starts_with(&ACCOUNT.into()));
+/// ```
+///
+/// ```compile_fail
+/// let path = coins_bip32::derivation_path!("m/84'/0'/x");
+/// ```
+#[macro_export]
+macro_rules! derivation_path {
+    ($path:literal) => {{
+        const PATH: [u32; $crate::path::const_path_len($path)] =
+            $crate::path::const_parse_path($path);
+        PATH
+    }};
+}
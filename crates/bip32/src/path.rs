@@ -38,6 +38,83 @@ pub const fn harden_index(index: u32) -> u32 {
     index + BIP32_HARDEN
 }
 
+#[doc(hidden)]
+// Used by `derivation_path!`. Returns the offset of the first index, skipping an `m` root.
+pub const fn const_path_start(path: &str) -> usize {
+    let bytes = path.as_bytes();
+    if bytes.is_empty() || bytes[0] != b'm' {
+        0
+    } else if bytes.len() == 1 {
+        1
+    } else if bytes[1] == b'/' {
+        2
+    } else {
+        panic!("Malformatted derivation path")
+    }
+}
+
+#[doc(hidden)]
+// Used by `derivation_path!`. Counts the indices in a derivation path string.
+pub const fn const_path_len(path: &str) -> usize {
+    let bytes = path.as_bytes();
+    let mut i = const_path_start(path);
+    if i == bytes.len() {
+        return 0;
+    }
+    let mut count = 1;
+    while i < bytes.len() {
+        if bytes[i] == b'/' {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+#[doc(hidden)]
+// Used by `derivation_path!`. Parses a derivation path string, panicking on malformatted
+// input. When evaluated in a const context, the panic is a compile error.
+pub const fn const_parse_path<const N: usize>(path: &str) -> [u32; N] {
+    let bytes = path.as_bytes();
+    let mut out = [0u32; N];
+    let mut i = const_path_start(path);
+    let mut n = 0;
+    while n < N {
+        let mut index: u64 = 0;
+        let mut digits = 0;
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            index = index * 10 + (bytes[i] - b'0') as u64;
+            if index > u32::MAX as u64 {
+                panic!("Derivation index out of range");
+            }
+            digits += 1;
+            i += 1;
+        }
+        if digits == 0 {
+            panic!("Malformatted index during derivation");
+        }
+
+        if i < bytes.len() && (bytes[i] == b'\'' || bytes[i] == b'h') {
+            if index >= BIP32_HARDEN as u64 {
+                panic!("Hardened derivation index out of range");
+            }
+            index += BIP32_HARDEN as u64;
+            i += 1;
+        }
+
+        if i < bytes.len() {
+            if bytes[i] != b'/' {
+                panic!("Malformatted index during derivation");
+            }
+            i += 1;
+        }
+
+        out[n] = index as u32;
+        n += 1;
+    }
+    out
+}
+
 /// A Bip32 derivation path
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct DerivationPath(Vec<u32>);
@@ -164,6 +241,12 @@ impl From<&[u32]> for DerivationPath {
     }
 }
 
+impl<const N: usize> From<[u32; N]> for DerivationPath {
+    fn from(v: [u32; N]) -> Self {
+        Self(Vec::from(v))
+    }
+}
+
 impl TryFrom<u32> for DerivationPath {
     type Error = Bip32Error;
 
@@ -247,6 +330,57 @@ impl KeyDerivation {
     }
 }
 
+/// The origin of a key: the fingerprint of the root key it descends from, and the path from that
+/// root. Its string form is the key origin prefix used in output descriptors, e.g.
+/// `[d34db33f/84'/0'/0']`. Its binary form is the value of the PSBT BIP32 derivation fields.
+pub type KeySource = KeyDerivation;
+
+impl std::fmt::Display for KeyDerivation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let root: String = self.root.0.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(f, "[{}]", self.path.custom_string(&root, '/', '\''))
+    }
+}
+
+impl FromStr for KeyDerivation {
+    type Err = Bip32Error;
+
+    /// Parse a key origin. The surrounding brackets are optional.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformatted = || Bip32Error::MalformattedKeySource(s.to_owned());
+
+        let inner = match (s.strip_prefix('['), s.strip_suffix(']')) {
+            (Some(_), Some(_)) => &s[1..s.len() - 1],
+            (None, None) => s,
+            _ => return Err(malformatted()),
+        };
+        let (root, path) = inner.split_at(inner.find('/').unwrap_or(inner.len()));
+
+        if root.len() != 8 || !root.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(malformatted());
+        }
+        let root = u32::from_str_radix(root, 16).map_err(|_| malformatted())?;
+
+        let path = if path.is_empty() {
+            DerivationPath::default()
+        } else {
+            path[1..].parse().map_err(|_| malformatted())?
+        };
+        Ok(Self {
+            root: root.to_be_bytes().into(),
+            path,
+        })
+    }
+}
+
+impl TryFrom<&str> for KeyDerivation {
+    type Error = Bip32Error;
+
+    fn try_from(v: &str) -> Result<Self, Self::Error> {
+        v.parse()
+    }
+}
+
 impl ByteFormat for KeyDerivation {
     type Error = Bip32Error;
 
@@ -254,34 +388,31 @@ impl ByteFormat for KeyDerivation {
         4 + 4 * self.path.len()
     }
 
-    fn read_from<T>(_reader: &mut T) -> Result<Self, Self::Error>
+    /// Reads until the end of the reader, as the encoding is not length-prefixed. Callers
+    /// should pass a reader limited to the encoded key source, e.g. a PSBT value slice.
+    fn read_from<T>(reader: &mut T) -> Result<Self, Self::Error>
     where
         T: Read,
         Self: std::marker::Sized,
     {
-        unimplemented!()
-        // if limit == 0 {
-        //     return Err(SerError::RequiresLimit.into());
-        // }
-
-        // if limit > 255 {
-        //     return Err(Bip32Error::InvalidBip32Path);
-        // }
-
-        // let mut finger = [0u8; 4];
-        // reader.read_exact(&mut finger)?;
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf)?;
 
-        // let mut path = vec![];
-        // for _ in 0..limit {
-        //     let mut buf = [0u8; 4];
-        //     reader.read_exact(&mut buf)?;
-        //     path.push(u32::from_le_bytes(buf));
-        // }
+        // fingerprint + up to 255 path elements
+        if buf.len() < 4 || buf.len() % 4 != 0 || buf.len() > 4 + 4 * 255 {
+            return Err(Bip32Error::InvalidBip32Path);
+        }
 
-        // Ok(KeyDerivation {
-        //     root: finger.into(),
-        //     path: path.into(),
-        // })
+        let mut root = [0u8; 4];
+        root.copy_from_slice(&buf[..4]);
+        let path = buf[4..]
+            .chunks(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        Ok(Self {
+            root: root.into(),
+            path,
+        })
     }
 
     fn write_to<T>(&self, writer: &mut T) -> Result<usize, Self::Error>
@@ -353,6 +484,38 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn it_parses_paths_at_compile_time() {
+        const PATH: [u32; 5] = derivation_path!("m/84'/0'/0'/0/5");
+        assert_eq!(
+            DerivationPath::from(PATH),
+            "m/84'/0'/0'/0/5".parse().unwrap()
+        );
+
+        let cases = ["m", "0", "m/0h/1/2h/2/1000000000", "2147483647h/4294967295"];
+        for case in cases.iter() {
+            let expected: DerivationPath = case.parse().unwrap();
+            let mut parsed = [0u32; 5];
+            let len = const_path_len(case);
+            assert_eq!(len, expected.len());
+            match len {
+                0 => {}
+                1 => parsed[..1].copy_from_slice(&const_parse_path::<1>(case)),
+                2 => parsed[..2].copy_from_slice(&const_parse_path::<2>(case)),
+                5 => parsed.copy_from_slice(&const_parse_path::<5>(case)),
+                _ => unreachable!(),
+            }
+            assert_eq!(DerivationPath::from(&parsed[..len]), expected);
+        }
+        assert!(derivation_path!("m").is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_rejects_malformatted_paths() {
+        const_parse_path::<2>("m/0/x");
+    }
+
     #[test]
     fn it_removes_prefixes_from_derivations() {
         // express each row in a separate instantiation syntax :)
@@ -406,4 +569,75 @@ pub mod test {
             assert_eq!(&case.0.derivation_string(), case.1);
         }
     }
+
+    #[test]
+    fn it_parses_and_formats_key_sources() {
+        let cases = [
+            (
+                "[d34db33f/84'/0'/0']",
+                KeySource {
+                    root: [0xd3, 0x4d, 0xb3, 0x3f].into(),
+                    path: vec![84 + BIP32_HARDEN, BIP32_HARDEN, BIP32_HARDEN].into(),
+                },
+            ),
+            (
+                "[00000000/1/2h]",
+                KeySource {
+                    root: [0; 4].into(),
+                    path: vec![1, 2 + BIP32_HARDEN].into(),
+                },
+            ),
+            (
+                "[deadbeef]",
+                KeySource {
+                    root: [0xde, 0xad, 0xbe, 0xef].into(),
+                    path: vec![].into(),
+                },
+            ),
+            (
+                "DEADBEEF/0",
+                KeySource {
+                    root: [0xde, 0xad, 0xbe, 0xef].into(),
+                    path: vec![0].into(),
+                },
+            ),
+        ];
+        for case in cases.iter() {
+            let parsed: KeySource = case.0.parse().unwrap();
+            assert_eq!(parsed, case.1);
+            assert_eq!(parsed.to_string().parse::<KeySource>().unwrap(), case.1);
+        }
+        assert_eq!(cases[0].1.to_string(), cases[0].0);
+        assert_eq!(cases[1].1.to_string(), "[00000000/1/2']");
+
+        let errors = [
+            "",
+            "[]",
+            "[d34db33f",
+            "d34db33f]",
+            "[d34db33/0]",
+            "[+34db33f/0]",
+            "[d34db33f/x]",
+            "[d34db33f/]",
+        ];
+        for case in errors.iter() {
+            match case.parse::<KeySource>() {
+                Err(Bip32Error::MalformattedKeySource(e)) => assert_eq!(&e, case),
+                _ => panic!("expected an error for {}", case),
+            }
+        }
+    }
+
+    #[test]
+    fn it_serializes_key_sources() {
+        let source: KeySource = "[d34db33f/84'/0'/0/5]".parse().unwrap();
+        let hex = "d34db33f54000080000000800000000005000000";
+        assert_eq!(source.serialized_length(), 20);
+        assert_eq!(source.serialize_hex(), hex);
+        assert_eq!(KeySource::deserialize_hex(hex).unwrap(), source);
+
+        for bad in ["", "d34db3", "d34db33f0000"].iter() {
+            assert!(KeySource::deserialize_hex(bad).is_err());
+        }
+    }
 }
@@ -1,7 +1,9 @@
+pub use crate::cached::CachedXPub;
 pub use crate::derived::{DerivedKey, DerivedPubkey, DerivedXPriv, DerivedXPub};
 pub use crate::enc::{MainnetEncoder, TestnetEncoder, XKeyEncoder};
-pub use crate::path::KeyDerivation;
+pub use crate::path::{KeyDerivation, KeySource};
 pub use crate::primitives::*;
+pub use crate::sig::SigSerialize;
 pub use crate::xkeys::{Parent, XPriv, XPub};
 pub use crate::Bip32Error;
 
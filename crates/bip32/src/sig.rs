@@ -0,0 +1,152 @@
+use k256::ecdsa::Signature;
+
+use crate::Bip32Error;
+
+/// Check whether `sig` is a strict DER encoding of an ECDSA signature, as required by BIP66.
+/// `sig` must not include a trailing sighash flag byte.
+///
+/// Strict DER rejects encodings that generic DER parsers often accept, e.g. excess zero
+/// padding on `r` or `s`, negative integers, and incorrect length bytes.
+pub fn is_strict_der(sig: &[u8]) -> bool {
+    // 0x30 [total-length] 0x02 [R-length] [R] 0x02 [S-length] [S]
+    let len = sig.len();
+    if !(8..=72).contains(&len) {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != len - 2 {
+        return false;
+    }
+
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= len {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 6 != len {
+        return false;
+    }
+
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+
+    true
+}
+
+/// Serialization and canonicalization of ECDSA signatures for use in Bitcoin scripts.
+pub trait SigSerialize: Sized {
+    /// Serialize the signature as DER. The output is always strict DER.
+    fn to_der_vec(&self) -> Vec<u8>;
+
+    /// Parse a DER signature, accepting any encoding the underlying DER parser accepts.
+    fn from_der_lax(der: &[u8]) -> Result<Self, Bip32Error>;
+
+    /// Parse a DER signature, rejecting encodings that are not strict DER per BIP66.
+    fn from_der_strict(der: &[u8]) -> Result<Self, Bip32Error>;
+
+    /// True if the `s` value is in the lower half of the curve order, per BIP62.
+    fn is_low_s(&self) -> bool;
+
+    /// Return the low-S form of this signature. Signatures with a high `s` are malleated to
+    /// the equivalent low-S signature. Others are returned unchanged.
+    fn to_low_s(&self) -> Self;
+
+    /// Parse a DER signature that is strict DER and low-S, as standardness rules require.
+    fn from_der_canonical(der: &[u8]) -> Result<Self, Bip32Error> {
+        let sig = Self::from_der_strict(der)?;
+        if !sig.is_low_s() {
+            return Err(Bip32Error::HighS);
+        }
+        Ok(sig)
+    }
+}
+
+impl SigSerialize for Signature {
+    fn to_der_vec(&self) -> Vec<u8> {
+        self.to_der().as_bytes().to_vec()
+    }
+
+    fn from_der_lax(der: &[u8]) -> Result<Self, Bip32Error> {
+        Ok(Signature::from_der(der)?)
+    }
+
+    fn from_der_strict(der: &[u8]) -> Result<Self, Bip32Error> {
+        if !is_strict_der(der) {
+            return Err(Bip32Error::NonStrictDer);
+        }
+        Self::from_der_lax(der)
+    }
+
+    fn is_low_s(&self) -> bool {
+        self.normalize_s().is_none()
+    }
+
+    fn to_low_s(&self) -> Self {
+        self.normalize_s().unwrap_or(*self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DER: &str = "304402200cc613393c11889ed1384388c9213b7778cfa0c7c2b6fcc080f0296fc8ac87d202205788d8994d61ce901d1ee22c5210994c235f17ddb3c31e0fc0ec9730ecf084ce";
+
+    #[test]
+    fn it_normalizes_s() {
+        let sig = Signature::from_der_strict(&hex::decode(DER).unwrap()).unwrap();
+        assert!(sig.is_low_s());
+        assert_eq!(sig.to_low_s(), sig);
+
+        let (r, s) = sig.split_scalars();
+        let high = Signature::from_scalars(r.to_bytes(), (-s).to_bytes()).unwrap();
+        assert!(!high.is_low_s());
+        assert_eq!(high.to_low_s(), sig);
+
+        match Signature::from_der_canonical(&high.to_der_vec()) {
+            Err(Bip32Error::HighS) => {}
+            _ => panic!("expected high-S rejection"),
+        }
+        assert_eq!(
+            Signature::from_der_canonical(&sig.to_der_vec()).unwrap(),
+            sig
+        );
+    }
+
+    #[test]
+    fn it_checks_strict_der() {
+        let der = hex::decode(DER).unwrap();
+        assert!(is_strict_der(&der));
+
+        let cases = [
+            // excess zero padding on r
+            "30450221000cc613393c11889ed1384388c9213b7778cfa0c7c2b6fcc080f0296fc8ac87d202205788d8994d61ce901d1ee22c5210994c235f17ddb3c31e0fc0ec9730ecf084ce",
+            // wrong total length
+            "304502200cc613393c11889ed1384388c9213b7778cfa0c7c2b6fcc080f0296fc8ac87d202205788d8994d61ce901d1ee22c5210994c235f17ddb3c31e0fc0ec9730ecf084ce",
+            // negative s
+            "304402200cc613393c11889ed1384388c9213b7778cfa0c7c2b6fcc080f0296fc8ac87d20220d788d8994d61ce901d1ee22c5210994c235f17ddb3c31e0fc0ec9730ecf084ce",
+            // trailing sighash byte
+            "304402200cc613393c11889ed1384388c9213b7778cfa0c7c2b6fcc080f0296fc8ac87d202205788d8994d61ce901d1ee22c5210994c235f17ddb3c31e0fc0ec9730ecf084ce01",
+            // wrong integer tag
+            "304403200cc613393c11889ed1384388c9213b7778cfa0c7c2b6fcc080f0296fc8ac87d202205788d8994d61ce901d1ee22c5210994c235f17ddb3c31e0fc0ec9730ecf084ce",
+        ];
+        for case in cases.iter() {
+            let bytes = hex::decode(case).unwrap();
+            assert!(!is_strict_der(&bytes), "{}", case);
+            match Signature::from_der_strict(&bytes) {
+                Err(Bip32Error::NonStrictDer) => {}
+                _ => panic!("expected strict DER rejection for {}", case),
+            }
+        }
+    }
+}
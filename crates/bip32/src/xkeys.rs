@@ -312,6 +312,17 @@ impl XPub {
     pub fn pubkey_hash160(&self) -> Hash160Digest {
         Hash160::digest_marked(self.key.to_sec1_bytes().as_ref())
     }
+
+    /// Search the first `gap_limit` non-hardened children for one with the given pubkey, and
+    /// return its index. Useful when recovering the derivation of a key seen on-chain from its
+    /// account xpub.
+    pub fn find_child_index(&self, pubkey: &ecdsa::VerifyingKey, gap_limit: u32) -> Option<u32> {
+        (0..gap_limit.min(BIP32_HARDEN)).find(|index| {
+            self.derive_child(*index)
+                .map(|child| &child.key == pubkey)
+                .unwrap_or(false)
+        })
+    }
 }
 
 impl PartialEq for XPub {
@@ -581,6 +592,19 @@ mod test {
         assert_eq!(&recovered.to_sec1_bytes(), &child_xpub.key.to_sec1_bytes());
     }
 
+    #[test]
+    fn it_finds_child_indices() {
+        let xpriv_str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi".to_owned();
+        let xpub = MainnetEncoder::xpriv_from_base58(&xpriv_str)
+            .unwrap()
+            .verify_key();
+
+        let child = xpub.derive_child(17).unwrap();
+        assert_eq!(xpub.find_child_index(&child.key, 20), Some(17));
+        assert_eq!(xpub.find_child_index(&child.key, 17), None);
+        assert_eq!(xpub.find_child_index(&xpub.key, 20), None);
+    }
+
     #[test]
     fn it_can_read_keys() {
         let xpriv_str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi".to_owned();
@@ -1,16 +1,43 @@
 use crate::{Wordlist, WordlistError};
 use bitvec::prelude::*;
-use coins_bip32::{path::DerivationPath, xkeys::XPriv, Bip32Error};
+use coins_bip32::{
+    derived::DerivedXPriv,
+    path::DerivationPath,
+    xkeys::{Parent, XPriv},
+    Bip32Error,
+};
 use hmac::Hmac;
 use pbkdf2::pbkdf2;
-use rand::Rng;
+use rand::{CryptoRng, Rng};
 use sha2::{Digest, Sha256, Sha512};
 use std::{convert::TryInto, marker::PhantomData};
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
 
 const PBKDF2_ROUNDS: u32 = 2048;
 const PBKDF2_BYTES: usize = 64;
 
+/// Overwrite secret intermediate buffers, if the `zeroize` feature is enabled.
+#[cfg(feature = "zeroize")]
+fn wipe<Z: zeroize::Zeroize + ?Sized>(secret: &mut Z) {
+    secret.zeroize();
+}
+
+#[cfg(not(feature = "zeroize"))]
+fn wipe<Z: ?Sized>(_secret: &mut Z) {}
+
+/// Returns the entropy length in bytes for a phrase of `count` words.
+const fn entropy_bytes_for(count: usize) -> Result<usize, MnemonicError> {
+    match count {
+        12 => Ok(16),
+        15 => Ok(20),
+        18 => Ok(24),
+        21 => Ok(28),
+        24 => Ok(32),
+        wc => Err(MnemonicError::InvalidWordCount(wc)),
+    }
+}
+
 #[derive(Debug, Error)]
 /// The error type returned while interacting with mnemonics.
 pub enum MnemonicError {
@@ -121,6 +148,19 @@ impl AsRef<[u8]> for Entropy {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Entropy {
+    fn zeroize(&mut self) {
+        match self {
+            Entropy::Sixteen(arr) => arr.zeroize(),
+            Entropy::Twenty(arr) => arr.zeroize(),
+            Entropy::TwentyFour(arr) => arr.zeroize(),
+            Entropy::TwentyEight(arr) => arr.zeroize(),
+            Entropy::ThirtyTwo(arr) => arr.zeroize(),
+        }
+    }
+}
+
 impl Entropy {
     /// Attempts to instantiate Entropy from a slice. Fails if the slice is not
     /// a valid entropy length
@@ -178,6 +218,29 @@ where
     _wordlist: PhantomData<W>,
 }
 
+#[cfg(feature = "zeroize")]
+impl<W> zeroize::Zeroize for Mnemonic<W>
+where
+    W: Wordlist,
+{
+    fn zeroize(&mut self) {
+        self.entropy.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<W> Drop for Mnemonic<W>
+where
+    W: Wordlist,
+{
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(self);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<W> zeroize::ZeroizeOnDrop for Mnemonic<W> where W: Wordlist {}
+
 impl<W> std::str::FromStr for Mnemonic<W>
 where
     W: Wordlist,
@@ -210,30 +273,75 @@ where
         }
     }
 
-    /// Returns a new mnemonic given the word count, generated using the provided random number
-    /// generator.
-    pub fn new_with_count<R: Rng>(rng: &mut R, count: usize) -> Result<Self, MnemonicError> {
-        let bytes: usize = match count {
-            12 => 16,
-            15 => 20,
-            18 => 24,
-            21 => 28,
-            24 => 32,
-            wc => return Err(MnemonicError::InvalidWordCount(wc)),
-        };
+    /// Returns a new mnemonic instantiated from raw entropy bytes. The entropy must be 16, 20,
+    /// 24, 28, or 32 bytes, producing a 12, 15, 18, 21, or 24 word phrase respectively.
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, MnemonicError> {
+        match entropy.len() {
+            16 | 20 | 24 | 28 | 32 => Ok(Self::new_from_entropy(Entropy::from_slice(entropy)?)),
+            len => Err(MnemonicError::InvalidEntropyLength(len)),
+        }
+    }
+
+    /// Returns a new mnemonic given the word count, generated using the provided
+    /// cryptographically secure random number generator. The word count must be 12, 15, 18,
+    /// 21, or 24.
+    pub fn new_with_count<R: Rng + CryptoRng>(
+        rng: &mut R,
+        count: usize,
+    ) -> Result<Self, MnemonicError> {
         Ok(Self {
-            entropy: Entropy::from_rng(bytes, rng)?,
+            entropy: Entropy::from_rng(entropy_bytes_for(count)?, rng)?,
             _wordlist: PhantomData,
         })
     }
 
+    /// Returns a new mnemonic of `count` words, mixing user-supplied entropy (e.g. dice rolls
+    /// or a shuffled deck of cards) with randomness from `rng`.
+    ///
+    /// The entropy is the first `count * 4 / 3` bytes of
+    /// `SHA256(rng_bytes || external)`, where `rng_bytes` is 32 bytes drawn from `rng`. The
+    /// phrase is unpredictable so long as either source is, so a weak or compromised RNG is
+    /// covered by good external entropy, and vice versa. For the external entropy alone to
+    /// suffice, it must carry at least as many bits as the phrase. E.g. 50 rolls of a six-sided
+    /// die provide ~128 bits, and 99 rolls provide ~256 bits.
+    pub fn from_external_entropy<R: Rng + CryptoRng>(
+        external: &[u8],
+        rng: &mut R,
+        count: usize,
+    ) -> Result<Self, MnemonicError> {
+        let bytes = entropy_bytes_for(count)?;
+        let mut rng_bytes: [u8; 32] = rng.gen();
+
+        let mut hasher = Sha256::new();
+        hasher.update(rng_bytes);
+        hasher.update(external);
+        let mut digest = hasher.finalize();
+        let entropy = Entropy::from_slice(&digest[..bytes]);
+
+        wipe(&mut rng_bytes);
+        wipe(digest.as_mut_slice());
+        Ok(Self::new_from_entropy(entropy?))
+    }
+
     /// Returns a new mnemonic for a given phrase. The 12-24 space-separated words are used to
-    /// calculate the entropy that must have produced it.
+    /// calculate the entropy that must have produced it. The phrase is NFKD-normalized first,
+    /// so words with precomposed accented characters are accepted.
     pub fn new_from_phrase(phrase: &str) -> Result<Self, MnemonicError> {
+        let mut normalized: String = phrase.nfkd().collect();
+        let result = Self::new_from_normalized_phrase(&normalized);
+        wipe(&mut normalized);
+        result.map_err(|e| match e {
+            MnemonicError::InvalidPhrase(_) => MnemonicError::InvalidPhrase(phrase.into()),
+            e => e,
+        })
+    }
+
+    fn new_from_normalized_phrase(phrase: &str) -> Result<Self, MnemonicError> {
+        // NFKD maps the ideographic space to an ASCII space
         let words = phrase.split(' ').collect::<Vec<&str>>();
 
         let mut entropy: BitVec<u8, Msb0> = BitVec::new();
-        for word in words {
+        for word in words.iter() {
             let index = W::get_index(word)?;
             let index_u8: [u8; 2] = (index as u16).to_be_bytes();
 
@@ -243,13 +351,15 @@ where
             entropy.append(&mut BitVec::<u8, Msb0>::from_bitslice(index_slice));
         }
 
+        let entropy_bytes = Entropy::from_slice(entropy.as_raw_slice());
+        wipe(entropy.as_raw_mut_slice());
         let mnemonic = Self {
-            entropy: Entropy::from_slice(entropy.as_raw_slice())?,
+            entropy: entropy_bytes?,
             _wordlist: PhantomData,
         };
 
         // Ensures the checksum word matches the checksum word in the given phrase.
-        match phrase == mnemonic.to_phrase() {
+        match words == mnemonic.words() {
             true => Ok(mnemonic),
             false => Err(MnemonicError::InvalidPhrase(phrase.into())),
         }
@@ -257,15 +367,20 @@ where
 
     /// Converts the mnemonic into phrase.
     pub fn to_phrase(&self) -> String {
+        self.words().join(W::separator())
+    }
+
+    fn words(&self) -> Vec<&'static str> {
         let length = self.word_count();
 
         // Compute checksum. Checksum is the most significant (ENTROPY_BYTES/4) bits. That is also
         // equivalent to (WORD_COUNT/3).
         let mut hasher = Sha256::new();
         hasher.update(self.entropy.as_ref());
-        let hash = hasher.finalize();
+        let mut hash = hasher.finalize();
         let hash_0 = BitVec::<u8, Msb0>::from_element(hash[0]);
         let (checksum, _) = hash_0.split_at(length / 3);
+        wipe(hash.as_mut_slice());
 
         // Convert the entropy bytes into bits and append the checksum.
         let mut encoding = BitVec::<u8, Msb0>::from_slice(self.entropy.as_ref());
@@ -280,8 +395,9 @@ where
                 wordlist[index as usize]
             })
             .collect::<Vec<&str>>();
+        wipe(encoding.as_raw_mut_slice());
 
-        phrase.join(" ")
+        phrase
     }
 
     const fn word_count(&self) -> usize {
@@ -290,10 +406,33 @@ where
 
     /// Returns the master private key of the corresponding mnemonic.
     pub fn master_key(&self, password: Option<&str>) -> Result<XPriv, MnemonicError> {
-        Ok(XPriv::root_from_seed(
-            self.to_seed(password)?.as_slice(),
-            None,
-        )?)
+        self.derive_root_xpriv(password)
+    }
+
+    /// Returns the BIP32 root private key of the mnemonic, stretching the phrase and optional
+    /// passphrase into a seed along the way.
+    pub fn derive_root_xpriv(&self, passphrase: Option<&str>) -> Result<XPriv, MnemonicError> {
+        let mut seed = self.to_seed(passphrase)?;
+        let root = XPriv::root_from_seed(seed.as_slice(), None);
+        wipe(&mut seed);
+        Ok(root?)
+    }
+
+    /// Returns the private key at `path` below the root key of the mnemonic, coupled with its
+    /// derivation from the root. E.g. an account key for `m/84'/0'/0'`.
+    pub fn derive_at<E, P>(
+        &self,
+        path: P,
+        passphrase: Option<&str>,
+    ) -> Result<DerivedXPriv, MnemonicError>
+    where
+        E: Into<Bip32Error>,
+        P: TryInto<DerivationPath, Error = E>,
+    {
+        let mut seed = self.to_seed(passphrase)?;
+        let root = DerivedXPriv::root_from_seed(seed.as_slice(), None);
+        wipe(&mut seed);
+        Ok(root?.derive_path(path)?)
     }
 
     /// Returns the derived child private key of the corresponding mnemonic at the given index.
@@ -305,20 +444,29 @@ where
         Ok(self.master_key(password)?.derive_path(path)?)
     }
 
-    /// Convert to a bip23 seed
+    /// Convert to a bip39 seed. The passphrase is NFKD-normalized, as BIP39 requires.
     pub fn to_seed(&self, password: Option<&str>) -> Result<[u8; PBKDF2_BYTES], MnemonicError> {
         let mut seed = [0u8; PBKDF2_BYTES];
-        let salt = format!("mnemonic{}", password.unwrap_or(""));
-        pbkdf2::<Hmac<Sha512>>(
-            self.to_phrase().as_bytes(),
-            salt.as_bytes(),
-            PBKDF2_ROUNDS,
-            &mut seed,
-        )
-        .expect("cannot have invalid length");
+        let mut phrase: String = self.to_phrase().nfkd().collect();
+        let mut salt: String = format!("mnemonic{}", password.unwrap_or(""))
+            .nfkd()
+            .collect();
+        pbkdf2::<Hmac<Sha512>>(phrase.as_bytes(), salt.as_bytes(), PBKDF2_ROUNDS, &mut seed)
+            .expect("cannot have invalid length");
+        wipe(&mut phrase);
+        wipe(&mut salt);
 
         Ok(seed)
     }
+
+    /// Convert to a bip39 seed, which is zeroized when dropped.
+    #[cfg(feature = "zeroize")]
+    pub fn to_seed_zeroizing(
+        &self,
+        password: Option<&str>,
+    ) -> Result<zeroize::Zeroizing<[u8; PBKDF2_BYTES]>, MnemonicError> {
+        Ok(zeroize::Zeroizing::new(self.to_seed(password)?))
+    }
 }
 
 #[cfg(all(test, feature = "english"))]
@@ -547,6 +695,141 @@ mod tests {
             })
     }
 
+    #[test]
+    fn test_from_entropy() {
+        TESTCASES
+            .iter()
+            .for_each(|(entropy_str, expected_phrase, _, _)| {
+                let entropy = hex::decode(entropy_str).unwrap();
+                let mnemonic = Mnemonic::<W>::from_entropy(&entropy).unwrap();
+                assert_eq!(mnemonic.to_phrase(), expected_phrase.to_string())
+            });
+
+        for len in [0, 15, 17, 33].iter() {
+            match Mnemonic::<W>::from_entropy(&vec![0u8; *len]) {
+                Err(MnemonicError::InvalidEntropyLength(l)) => assert_eq!(l, *len),
+                _ => panic!("expected invalid entropy length"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_with_count() {
+        let mut rng = rand::thread_rng();
+        for count in [12, 15, 18, 21, 24].iter() {
+            let mnemonic = Mnemonic::<W>::new_with_count(&mut rng, *count).unwrap();
+            let phrase = mnemonic.to_phrase();
+            assert_eq!(phrase.split(' ').count(), *count);
+            assert_eq!(Mnemonic::<W>::new_from_phrase(&phrase).unwrap(), mnemonic);
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize() {
+        use zeroize::Zeroize;
+
+        let mut mnemonic = Mnemonic::<W>::from_entropy(&[0x7f; 16]).unwrap();
+        let seed = mnemonic.to_seed_zeroizing(None).unwrap();
+        assert_eq!(*seed, mnemonic.to_seed(None).unwrap());
+
+        mnemonic.zeroize();
+        assert_eq!(mnemonic.entropy, Entropy::Sixteen([0u8; 16]));
+    }
+
+    #[cfg(feature = "french")]
+    #[test]
+    fn test_nfkd_normalization() {
+        use crate::French;
+
+        // Find a phrase containing accented words
+        let (mnemonic, decomposed, composed) = (0..=255u8)
+            .map(|i| {
+                let mnemonic = Mnemonic::<French>::from_entropy(&[i; 16]).unwrap();
+                let decomposed = mnemonic.to_phrase();
+                let composed: String = decomposed.nfc().collect();
+                (mnemonic, decomposed, composed)
+            })
+            .find(|(_, decomposed, composed)| decomposed != composed)
+            .unwrap();
+        assert_ne!(composed.len(), decomposed.len());
+        assert_eq!(
+            Mnemonic::<French>::new_from_phrase(&composed).unwrap(),
+            mnemonic
+        );
+
+        assert_eq!(
+            mnemonic.to_seed(Some("caf\u{e9}")).unwrap(),
+            mnemonic.to_seed(Some("cafe\u{301}")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_from_external_entropy() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let dice = b"3141526535897932384626433832795028841971693993751058209749445923";
+        for count in [12, 15, 18, 21, 24].iter() {
+            let a =
+                Mnemonic::<W>::from_external_entropy(dice, &mut StdRng::seed_from_u64(1), *count)
+                    .unwrap();
+            let b =
+                Mnemonic::<W>::from_external_entropy(dice, &mut StdRng::seed_from_u64(1), *count)
+                    .unwrap();
+            let c =
+                Mnemonic::<W>::from_external_entropy(dice, &mut StdRng::seed_from_u64(2), *count)
+                    .unwrap();
+            assert_eq!(a, b);
+            assert_ne!(a, c);
+            assert_eq!(a.to_phrase().split(' ').count(), *count);
+        }
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let rng_bytes: [u8; 32] = rng.gen();
+        let mut hasher = Sha256::new();
+        hasher.update(rng_bytes);
+        hasher.update(dice);
+        let expected = Mnemonic::<W>::from_entropy(&hasher.finalize()[..16]).unwrap();
+        assert_eq!(
+            Mnemonic::<W>::from_external_entropy(dice, &mut StdRng::seed_from_u64(1), 12).unwrap(),
+            expected
+        );
+
+        assert!(
+            Mnemonic::<W>::from_external_entropy(dice, &mut StdRng::seed_from_u64(1), 13).is_err()
+        );
+    }
+
+    #[cfg(feature = "japanese")]
+    #[test]
+    fn test_japanese_separator() {
+        use crate::Japanese;
+
+        let phrase = "あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あおぞら";
+        let passphrase = "㍍ガバヴァぱばぐゞちぢ十人十色";
+        let seed = "a262d6fb6122ecf45be09c50492b31f92e9beb7d9a845987a02cefda57a15f9c467a17872029a9e92299b5cbdf306e3a0ee620245cbd508959b6cb7ca637bd55";
+
+        let mnemonic = Mnemonic::<Japanese>::new_from_phrase(phrase).unwrap();
+        assert_eq!(mnemonic.entropy, Entropy::Sixteen([0u8; 16]));
+        assert_eq!(
+            hex::encode(mnemonic.to_seed(Some(passphrase)).unwrap()),
+            seed
+        );
+
+        let rendered = mnemonic.to_phrase();
+        assert_eq!(rendered.split('\u{3000}').count(), 12);
+        assert_eq!(
+            rendered.nfc().collect::<String>(),
+            phrase.nfc().collect::<String>()
+        );
+
+        let ascii = rendered.replace('\u{3000}', " ");
+        assert_eq!(
+            Mnemonic::<Japanese>::new_from_phrase(&ascii).unwrap(),
+            mnemonic
+        );
+    }
+
     #[test]
     fn test_to_seed() {
         TESTCASES
@@ -589,4 +872,24 @@ mod tests {
         mnemonic.derive_key(0, None).unwrap();
         mnemonic.derive_key("m/44'/61'/0'/0", None).unwrap();
     }
+
+    #[test]
+    fn test_derive_at() {
+        use coins_bip32::derived::DerivedKey;
+
+        let (_, phrase, _, expected_root) = TESTCASES[0];
+        let mnemonic = Mnemonic::<W>::new_from_phrase(phrase).unwrap();
+
+        let root = mnemonic.derive_root_xpriv(Some("TREZOR")).unwrap();
+        assert_eq!(
+            root,
+            MainnetEncoder::xpriv_from_base58(expected_root).unwrap()
+        );
+
+        let account = mnemonic.derive_at("m/84'/0'/0'", Some("TREZOR")).unwrap();
+        assert_eq!(account.derivation().root, root.fingerprint());
+        assert_eq!(account.derivation().path, "m/84'/0'/0'".parse().unwrap());
+        let xpriv: &XPriv = account.as_ref();
+        assert_eq!(xpriv, &root.derive_path("m/84'/0'/0'").unwrap());
+    }
 }
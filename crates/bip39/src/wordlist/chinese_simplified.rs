@@ -7,6 +7,13 @@ pub const RAW_CHINESE_SIMPLIFIED: &str = include_str!("./words/chinese_simplifie
 /// ChineseSimplified word list, split into words
 pub static PARSED: Lazy<Vec<&'static str>> = Lazy::new(|| RAW_CHINESE_SIMPLIFIED.lines().collect());
 
+/// ChineseSimplified word list, sorted for prefix search
+pub static SORTED: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    let mut words = PARSED.clone();
+    words.sort_unstable();
+    words
+});
+
 #[derive(Clone, Debug, PartialEq, Eq, Copy)]
 /// The ChineseSimplified wordlist that implements the Wordlist trait.
 pub struct ChineseSimplified;
@@ -15,6 +22,10 @@ impl Wordlist for ChineseSimplified {
     fn get_all() -> &'static [&'static str] {
         PARSED.as_slice()
     }
+
+    fn get_sorted() -> &'static [&'static str] {
+        SORTED.as_slice()
+    }
 }
 
 #[cfg(test)]
@@ -8,6 +8,13 @@ pub const RAW_CHINESE_TRADITIONAL: &str = include_str!("./words/chinese_traditio
 pub static PARSED: Lazy<Vec<&'static str>> =
     Lazy::new(|| RAW_CHINESE_TRADITIONAL.lines().collect());
 
+/// ChineseTraditional word list, sorted for prefix search
+pub static SORTED: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    let mut words = PARSED.clone();
+    words.sort_unstable();
+    words
+});
+
 #[derive(Clone, Debug, PartialEq, Eq, Copy)]
 /// The ChineseTraditional wordlist that implements the Wordlist trait.
 pub struct ChineseTraditional;
@@ -16,6 +23,10 @@ impl Wordlist for ChineseTraditional {
     fn get_all() -> &'static [&'static str] {
         PARSED.as_slice()
     }
+
+    fn get_sorted() -> &'static [&'static str] {
+        SORTED.as_slice()
+    }
 }
 
 #[cfg(test)]
@@ -7,6 +7,13 @@ pub const RAW_CZECH: &str = include_str!("./words/czech.txt");
 /// Czech word list, split into words
 pub static PARSED: Lazy<Vec<&'static str>> = Lazy::new(|| RAW_CZECH.lines().collect());
 
+/// Czech word list, sorted for prefix search
+pub static SORTED: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    let mut words = PARSED.clone();
+    words.sort_unstable();
+    words
+});
+
 #[derive(Clone, Debug, PartialEq, Eq, Copy)]
 /// The Czech wordlist that implements the Wordlist trait.
 pub struct Czech;
@@ -15,6 +22,10 @@ impl Wordlist for Czech {
     fn get_all() -> &'static [&'static str] {
         PARSED.as_slice()
     }
+
+    fn get_sorted() -> &'static [&'static str] {
+        SORTED.as_slice()
+    }
 }
 
 #[cfg(test)]
@@ -51,4 +51,21 @@ mod tests {
     fn test_get_all() {
         assert_eq!(English::get_all().len(), 2048);
     }
+
+    #[test]
+    fn test_starts_with() {
+        assert_eq!(English::starts_with("zo"), &["zone", "zoo"]);
+        assert_eq!(English::starts_with("abandon"), &["abandon"]);
+        assert_eq!(English::starts_with("").len(), 2048);
+        assert!(English::starts_with("zz").is_empty());
+    }
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(English::suggest("imune")[0], "immune");
+        assert_eq!(English::suggest("immnue")[0], "immune");
+        assert_eq!(English::suggest("zebra")[0], "zebra");
+        assert!(English::suggest("zebra").contains(&"zero"));
+        assert!(English::suggest("somerandomword").is_empty());
+    }
 }
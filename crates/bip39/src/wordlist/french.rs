@@ -7,6 +7,13 @@ pub const RAW_FRENCH: &str = include_str!("./words/french.txt");
 /// French word list, split into words
 pub static PARSED: Lazy<Vec<&'static str>> = Lazy::new(|| RAW_FRENCH.lines().collect());
 
+/// French word list, sorted for prefix search
+pub static SORTED: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    let mut words = PARSED.clone();
+    words.sort_unstable();
+    words
+});
+
 #[derive(Clone, Debug, PartialEq, Eq, Copy)]
 /// The French wordlist that implements the Wordlist trait.
 pub struct French;
@@ -15,6 +22,10 @@ impl Wordlist for French {
     fn get_all() -> &'static [&'static str] {
         PARSED.as_slice()
     }
+
+    fn get_sorted() -> &'static [&'static str] {
+        SORTED.as_slice()
+    }
 }
 
 #[cfg(test)]
@@ -7,6 +7,13 @@ pub const RAW_JAPANESE: &str = include_str!("./words/japanese.txt");
 /// Japanese word list, split into words
 pub static PARSED: Lazy<Vec<&'static str>> = Lazy::new(|| RAW_JAPANESE.lines().collect());
 
+/// Japanese word list, sorted for prefix search
+pub static SORTED: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    let mut words = PARSED.clone();
+    words.sort_unstable();
+    words
+});
+
 #[derive(Clone, Debug, PartialEq, Eq, Copy)]
 /// The Japanese wordlist that implements the Wordlist trait.
 pub struct Japanese;
@@ -15,6 +22,15 @@ impl Wordlist for Japanese {
     fn get_all() -> &'static [&'static str] {
         PARSED.as_slice()
     }
+
+    fn get_sorted() -> &'static [&'static str] {
+        SORTED.as_slice()
+    }
+
+    /// Japanese phrases are joined with an ideographic space (U+3000)
+    fn separator() -> &'static str {
+        "\u{3000}"
+    }
 }
 
 #[cfg(test)]
@@ -44,4 +60,21 @@ mod tests {
     fn test_get_all() {
         assert_eq!(Japanese::get_all().len(), 2048);
     }
+
+    #[test]
+    fn test_starts_with() {
+        // The list is not in byte order, so prefix search goes through `get_sorted`
+        let word = Japanese::get(3).unwrap();
+        let prefix: String = word.chars().take(2).collect();
+        let words = Japanese::starts_with(&prefix);
+        assert!(words.contains(&word));
+        assert!(words.iter().all(|w| w.starts_with(&prefix)));
+        assert_eq!(
+            words.len(),
+            Japanese::get_all()
+                .iter()
+                .filter(|w| w.starts_with(&prefix))
+                .count()
+        );
+    }
 }
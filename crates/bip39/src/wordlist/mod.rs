@@ -14,49 +14,49 @@ pub use self::chinese_simplified::ChineseSimplified;
 #[cfg(feature = "chinese-traditional")]
 pub mod chinese_traditional;
 #[cfg(feature = "chinese-traditional")]
-pub use super::chinese_traditional::ChineseTraditional;
+pub use self::chinese_traditional::ChineseTraditional;
 
 /// The Czech wordlist
 #[cfg(feature = "czech")]
 pub mod czech;
 #[cfg(feature = "czech")]
-pub use super::czech::Czech;
+pub use self::czech::Czech;
 
 /// The French wordlist
 #[cfg(feature = "french")]
 pub mod french;
 #[cfg(feature = "french")]
-pub use super::french::French;
+pub use self::french::French;
 
 /// The Italian wordlist
 #[cfg(feature = "italian")]
 pub mod italian;
 #[cfg(feature = "italian")]
-pub use super::italian::Italian;
+pub use self::italian::Italian;
 
 /// The Japanese wordlist
 #[cfg(feature = "japanese")]
 pub mod japanese;
 #[cfg(feature = "japanese")]
-pub use super::japanese::Japanese;
+pub use self::japanese::Japanese;
 
 /// The Korean wordlist
 #[cfg(feature = "korean")]
 pub mod korean;
 #[cfg(feature = "korean")]
-pub use super::korean::Korean;
+pub use self::korean::Korean;
 
 /// The Portuguese wordlist
 #[cfg(feature = "portuguese")]
 pub mod portuguese;
 #[cfg(feature = "portuguese")]
-pub use super::portuguese::Portuguese;
+pub use self::portuguese::Portuguese;
 
 /// The Spanish wordlist
 #[cfg(feature = "spanish")]
 pub mod spanish;
 #[cfg(feature = "spanish")]
-pub use super::spanish::Spanish;
+pub use self::spanish::Spanish;
 
 use thiserror::Error;
 
@@ -71,13 +71,85 @@ pub enum WordlistError {
     InvalidWord(String),
 }
 
+/// The maximum edit distance of words returned by `Wordlist::suggest`.
+pub const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// Optimal string alignment distance between two words, counted in chars. This is the
+/// Levenshtein distance, extended to count a transposition of adjacent chars as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut prev2 = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        cur[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            cur[j] = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                cur[j] = cur[j].min(prev2[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
 /// The Wordlist trait that every language's wordlist must implement.
 pub trait Wordlist {
     /// Returns the word list as a string.
     ///
-    /// Implementor's note: this MUST be sorted
+    /// Implementor's note: this MUST be in the order specified by BIP39, which is not
+    /// necessarily sorted. See `get_sorted`.
     fn get_all() -> &'static [&'static str];
 
+    /// Returns the separator placed between words when rendering a phrase.
+    fn separator() -> &'static str {
+        " "
+    }
+
+    /// Returns the word list sorted in byte order.
+    ///
+    /// Implementor's note: the default implementation returns `get_all`. Lists that are not
+    /// already sorted MUST override this.
+    fn get_sorted() -> &'static [&'static str] {
+        Self::get_all()
+    }
+
+    /// Returns the words beginning with `prefix`, in sorted order. Useful for autocompletion
+    /// while a phrase is being entered.
+    fn starts_with(prefix: &str) -> &'static [&'static str] {
+        let sorted = Self::get_sorted();
+        let start = sorted.partition_point(|word| *word < prefix);
+        let len = sorted[start..].partition_point(|word| word.starts_with(prefix));
+        &sorted[start..start + len]
+    }
+
+    /// Returns the words within `MAX_SUGGESTION_DISTANCE` edits of `word`, nearest first.
+    /// Insertions, deletions, substitutions, and transpositions of adjacent characters each
+    /// count as one edit. If `word` is in the list, it is the first suggestion.
+    fn suggest(word: &str) -> Vec<&'static str> {
+        let len = word.chars().count();
+        let mut candidates: Vec<(usize, &'static str)> = Self::get_all()
+            .iter()
+            .filter(|candidate| {
+                let candidate_len = candidate.chars().count();
+                candidate_len.max(len) - candidate_len.min(len) <= MAX_SUGGESTION_DISTANCE
+            })
+            .map(|candidate| (edit_distance(word, candidate), *candidate))
+            .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+            .collect();
+        candidates.sort_by_key(|(distance, _)| *distance);
+        candidates
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .collect()
+    }
+
     /// Returns the word of a given index from the word list.
     fn get(index: usize) -> Result<&'static str, WordlistError> {
         Self::get_all()
@@ -7,6 +7,13 @@ pub const RAW_SPANISH: &str = include_str!("./words/spanish.txt");
 /// Spanish word list, split into words
 pub static PARSED: Lazy<Vec<&'static str>> = Lazy::new(|| RAW_SPANISH.lines().collect());
 
+/// Spanish word list, sorted for prefix search
+pub static SORTED: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    let mut words = PARSED.clone();
+    words.sort_unstable();
+    words
+});
+
 #[derive(Clone, Debug, PartialEq, Eq, Copy)]
 /// The Spanish wordlist that implements the Wordlist trait.
 pub struct Spanish;
@@ -15,6 +22,10 @@ impl Wordlist for Spanish {
     fn get_all() -> &'static [&'static str] {
         PARSED.as_slice()
     }
+
+    fn get_sorted() -> &'static [&'static str] {
+        SORTED.as_slice()
+    }
 }
 
 #[cfg(test)]
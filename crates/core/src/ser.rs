@@ -367,8 +367,13 @@ impl ByteFormat for u8 {
     {
         match mode {
             ReadSeqMode::Exactly(number) => {
-                let mut v = vec![0u8; number];
-                reader.read_exact(v.as_mut_slice())?;
+                // Grow the vector as bytes are read, so that an untrusted length prefix can not
+                // allocate more than the reader holds
+                let mut v = vec![];
+                reader.take(number as u64).read_to_end(&mut v)?;
+                if v.len() != number {
+                    return Err(IOError::from(std::io::ErrorKind::UnexpectedEof).into());
+                }
                 Ok(v)
             }
             ReadSeqMode::AtMost(limit) => {
@@ -460,6 +465,13 @@ mod test {
             u8::read_seq_from(&mut buf.clone().as_slice(), ReadSeqMode::Exactly(1)).unwrap();
         assert_eq!(exact_first, vec![0]);
 
+        // A huge length with little data fails without allocating it
+        let exact_huge = u8::read_seq_from(
+            &mut buf.clone().as_slice(),
+            ReadSeqMode::Exactly(usize::MAX / 2),
+        );
+        assert!(exact_huge.is_err());
+
         // Read exactly no elements
         let exact_none =
             u8::read_seq_from(&mut buf.clone().as_slice(), ReadSeqMode::Exactly(0)).unwrap();
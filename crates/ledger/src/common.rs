@@ -123,6 +123,68 @@ impl APDUCommand {
     }
 }
 
+impl TryFrom<&[u8]> for APDUCommand {
+    type Error = LedgerError;
+
+    /// Parse a serialized APDU command, as produced by
+    /// [`APDUCommand::serialize`].
+    fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
+        let malformed = || LedgerError::MalformedCommand(buf.to_vec());
+        if buf.len() < 4 {
+            return Err(malformed());
+        }
+
+        // a single trailing byte is the response length. Empty data is
+        // serialized without a length prefix
+        let body = &buf[4..];
+        let (data, response_len) = match body.len() {
+            0 => (&[][..], None),
+            1 => (&[][..], Some(body[0])),
+            len => {
+                let data_len = body[0] as usize;
+                let data = body.get(1..1 + data_len).ok_or_else(malformed)?;
+                match len - 1 - data_len {
+                    0 => (data, None),
+                    1 => (data, Some(body[len - 1])),
+                    _ => return Err(malformed()),
+                }
+            }
+        };
+
+        Ok(Self {
+            cla: buf[0],
+            ins: buf[1],
+            p1: buf[2],
+            p2: buf[3],
+            data: data.into(),
+            response_len,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for APDUCommand {
+    /// Serialized as the hex of the serialized APDU
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&hex::encode(APDUCommand::serialize(self)))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for APDUCommand {
+    fn deserialize<D>(deserializer: D) -> Result<APDUCommand, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s: String = serde::Deserialize::deserialize(deserializer)?;
+        let buf = hex::decode(s).map_err(serde::de::Error::custom)?;
+        APDUCommand::try_from(&buf[..]).map_err(serde::de::Error::custom)
+    }
+}
+
 /// An APDU response is a wrapper around some response bytes. To avoid unnecessary clones, it
 /// exposes the retcode and response data as getters.
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -161,10 +223,15 @@ impl APDUAnswer {
 
     /// Return false if the response status is an error.
     pub fn is_success(&self) -> bool {
-        match self.response_status() {
-            Some(opcode) => opcode.is_success(),
-            None => false,
-        }
+        self.status_word().is_success()
+    }
+
+    /// Get the status word from the response packet. Unlike
+    /// [`APDUAnswer::response_status`], this preserves unknown codes.
+    ///
+    /// Panics if the buffer is too short (some device error).
+    pub fn status_word(&self) -> StatusWord {
+        StatusWord(self.retcode())
     }
 
     /// Get the integer response code from the response packet.
@@ -183,6 +250,15 @@ impl APDUAnswer {
         self.retcode().try_into().ok()
     }
 
+    /// The error corresponding to the response code. Unknown codes are
+    /// reported as `UnknownAPDUCode`.
+    pub(crate) fn status_error(&self) -> LedgerError {
+        match self.response_status() {
+            Some(status) => status.into(),
+            None => LedgerError::UnknownAPDUCode(self.retcode()),
+        }
+    }
+
     /// Return a reference to the response data, or None if the response errored
     pub fn data(&self) -> Option<&[u8]> {
         if self.is_success() {
@@ -193,19 +269,261 @@ impl APDUAnswer {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for APDUAnswer {
+    /// Serialized as the hex of the raw answer, including the status word
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&hex::encode(&self.response))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for APDUAnswer {
+    fn deserialize<D>(deserializer: D) -> Result<APDUAnswer, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s: String = serde::Deserialize::deserialize(deserializer)?;
+        let buf = hex::decode(s).map_err(serde::de::Error::custom)?;
+        APDUAnswer::from_answer(buf).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The name and version of the app running on the device, as returned by the
+/// dashboard-level "get app and version" APDU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppInfo {
+    /// The app name, e.g. `Bitcoin` or `Bitcoin Test`. This is `BOLOS` when
+    /// the dashboard is open.
+    pub name: String,
+    /// The app version, e.g. `2.1.0`
+    pub version: String,
+    /// App-specific flags
+    pub flags: Vec<u8>,
+}
+
+impl AppInfo {
+    /// The "get app and version" APDU. This is handled by the OS, so it is
+    /// supported by every app, and by the dashboard.
+    pub fn command() -> APDUCommand {
+        APDUCommand {
+            cla: 0xb0,
+            ins: 0x01,
+            p1: 0x00,
+            p2: 0x00,
+            data: APDUData::new(&[]),
+            response_len: None,
+        }
+    }
+
+    /// True if the dashboard, rather than an app, is open.
+    pub fn is_dashboard(&self) -> bool {
+        self.name == "BOLOS"
+    }
+
+    /// Parse the answer to [`AppInfo::command`].
+    pub fn from_answer(answer: &APDUAnswer) -> Result<Self, LedgerError> {
+        let data = answer.data().ok_or_else(|| answer.status_error())?;
+        let malformed = || LedgerError::MalformedAnswer(data.to_vec());
+
+        // format byte, then length-prefixed name, version, and flags
+        let (format, mut rest) = data.split_first().ok_or_else(malformed)?;
+        if *format != 0x01 {
+            return Err(malformed());
+        }
+        let mut next = || -> Result<&[u8], LedgerError> {
+            let (len, tail) = rest.split_first().ok_or_else(malformed)?;
+            let len = *len as usize;
+            if tail.len() < len {
+                return Err(malformed());
+            }
+            let (field, tail) = tail.split_at(len);
+            rest = tail;
+            Ok(field)
+        };
+
+        let name = String::from_utf8(next()?.to_vec()).map_err(|_| malformed())?;
+        let version = String::from_utf8(next()?.to_vec()).map_err(|_| malformed())?;
+        // older firmware omits the flags
+        let flags = next().map(<[u8]>::to_vec).unwrap_or_default();
+        Ok(Self {
+            name,
+            version,
+            flags,
+        })
+    }
+}
+
+/// How the P1 and P2 bytes are set on each APDU of a chunked payload. Apps
+/// differ in how they mark continuation, so this must match the app.
+#[derive(Debug, Clone, Copy)]
+pub enum ChunkParams {
+    /// P1 is `first` on the first chunk, and `more` on the others. P2 is
+    /// fixed. E.g. the Ethereum app uses `first: 0x00, more: 0x80`.
+    FirstAndMore {
+        /// P1 of the first chunk
+        first: u8,
+        /// P1 of the subsequent chunks
+        more: u8,
+        /// P2 of all chunks
+        p2: u8,
+    },
+    /// P1 is `more` on every chunk but the last, and `last` on the last. P2
+    /// is fixed.
+    MoreAndLast {
+        /// P1 of all chunks but the last
+        more: u8,
+        /// P1 of the last chunk
+        last: u8,
+        /// P2 of all chunks
+        p2: u8,
+    },
+    /// P1 is `0x00` on the first chunk, `0x01` on intermediate chunks, and
+    /// `0x02` on the last chunk, as in Zondax apps. P2 is fixed. A payload
+    /// is always sent after an initial chunk, so at least 2 chunks are sent.
+    InitAddLast {
+        /// P2 of all chunks
+        p2: u8,
+    },
+    /// P1 and P2 are computed from the chunk index and the chunk count.
+    Custom(fn(usize, usize) -> (u8, u8)),
+}
+
+impl ChunkParams {
+    /// The P1 and P2 bytes of the chunk at `index`, out of `count` chunks.
+    pub fn params(&self, index: usize, count: usize) -> (u8, u8) {
+        let is_last = index + 1 == count;
+        match *self {
+            ChunkParams::FirstAndMore { first, more, p2 } => {
+                (if index == 0 { first } else { more }, p2)
+            }
+            ChunkParams::MoreAndLast { more, last, p2 } => (if is_last { last } else { more }, p2),
+            ChunkParams::InitAddLast { p2 } => match (index, is_last) {
+                (0, _) => (0x00, p2),
+                (_, false) => (0x01, p2),
+                (_, true) => (0x02, p2),
+            },
+            ChunkParams::Custom(f) => f(index, count),
+        }
+    }
+}
+
+/// Splits payloads larger than a single APDU into a sequence of APDUs.
+///
+/// ```
+/// use coins_ledger::common::{APDUChunker, ChunkParams};
+///
+/// let chunker = APDUChunker::new(
+///     0xe0,
+///     0x04,
+///     ChunkParams::FirstAndMore { first: 0x00, more: 0x80, p2: 0x00 },
+/// );
+/// let commands = chunker.chunk(&[0u8; 300]);
+/// assert_eq!(commands.len(), 2);
+/// assert_eq!(commands[0].data.len(), 255);
+/// assert_eq!(commands[1].p1, 0x80);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct APDUChunker {
+    cla: u8,
+    ins: u8,
+    params: ChunkParams,
+    chunk_size: usize,
+}
+
+impl APDUChunker {
+    /// Instantiate a chunker producing full 255-byte chunks.
+    pub const fn new(cla: u8, ins: u8, params: ChunkParams) -> Self {
+        Self {
+            cla,
+            ins,
+            params,
+            chunk_size: MAX_DATA_SIZE,
+        }
+    }
+
+    /// Set the chunk size. This is clamped to between 1 and 255 bytes. Some
+    /// apps require smaller chunks, e.g. to align with structure boundaries.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.clamp(1, MAX_DATA_SIZE);
+        self
+    }
+
+    /// Split `payload` into a sequence of APDUs. An empty payload produces
+    /// a single APDU with no data.
+    pub fn chunk(&self, payload: &[u8]) -> Vec<APDUCommand> {
+        let mut chunks: Vec<&[u8]> = payload.chunks(self.chunk_size).collect();
+        if let ChunkParams::InitAddLast { .. } = self.params {
+            chunks.insert(0, &[]);
+        }
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+
+        let count = chunks.len();
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let (p1, p2) = self.params.params(index, count);
+                APDUCommand {
+                    cla: self.cla,
+                    ins: self.ins,
+                    p1,
+                    p2,
+                    data: chunk.into(),
+                    response_len: None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reassemble a multi-part answer by concatenating the data of each answer.
+/// Fails if any answer has an error status.
+pub fn reassemble<'a, I>(answers: I) -> Result<Vec<u8>, LedgerError>
+where
+    I: IntoIterator<Item = &'a APDUAnswer>,
+{
+    let mut data = vec![];
+    for answer in answers {
+        match answer.data() {
+            Some(chunk) => data.extend_from_slice(chunk),
+            None => return Err(answer.status_error()),
+        }
+    }
+    Ok(data)
+}
+
 #[repr(u16)]
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-/// APDU Response codes. These are the last 2 bytes of the APDU packet. Please see APDU and
-/// Ledger documentation for each error type.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+/// Known APDU response codes. These are the last 2 bytes of the APDU packet. Please see ISO 7816-4
+/// and Ledger documentation for each error type. Use [`StatusWord`] to preserve unknown and
+/// app-specific codes.
 pub enum APDUResponseCodes {
     /// No Error
     NoError = 0x9000,
+    /// UserRefusedOnDevice
+    UserRefusedOnDevice = 0x5501,
+    /// LockedDevice
+    LockedDevice = 0x5515,
+    /// AuthenticationFailed
+    AuthenticationFailed = 0x6300,
     /// ExecutionError
     ExecutionError = 0x6400,
+    /// MemoryFailure
+    MemoryFailure = 0x6581,
     /// WrongLength
     WrongLength = 0x6700,
+    /// MissingCriticalParameter
+    MissingCriticalParameter = 0x6800,
     /// UnlockDeviceError
     UnlockDeviceError = 0x6804,
+    /// IncompatibleFileStructure
+    IncompatibleFileStructure = 0x6981,
     /// EmptyBuffer
     EmptyBuffer = 0x6982,
     /// OutputBufferTooSmall
@@ -218,16 +536,36 @@ pub enum APDUResponseCodes {
     CommandNotAllowed = 0x6986,
     /// InvalidData
     InvalidData = 0x6A80,
+    /// FunctionNotSupported
+    FunctionNotSupported = 0x6A81,
+    /// FileNotFound
+    FileNotFound = 0x6A82,
+    /// NotEnoughMemory
+    NotEnoughMemory = 0x6A84,
+    /// IncorrectP1P2
+    IncorrectP1P2 = 0x6A86,
+    /// ReferencedDataNotFound
+    ReferencedDataNotFound = 0x6A88,
+    /// FileAlreadyExists
+    FileAlreadyExists = 0x6A89,
     /// InvalidP1P2
     InvalidP1P2 = 0x6B00,
+    /// LockedDeviceLegacy
+    LockedDeviceLegacy = 0x6B0C,
     /// InsNotSupported
     InsNotSupported = 0x6D00,
+    /// UnknownApdu
+    UnknownApdu = 0x6D02,
+    /// DeviceNotOnboarded
+    DeviceNotOnboarded = 0x6D07,
     /// ClaNotSupported
     ClaNotSupported = 0x6E00,
     /// Unknown
     Unknown = 0x6F00,
     /// SignVerifyError
     SignVerifyError = 0x6F01,
+    /// Halted
+    Halted = 0x6FAA,
 }
 
 impl std::fmt::Display for APDUResponseCodes {
@@ -242,40 +580,50 @@ impl APDUResponseCodes {
         matches!(self, APDUResponseCodes::NoError)
     }
 
+    /// True if the response indicates that the device is locked, and the
+    /// user must enter their PIN. Older firmware reports this as
+    /// `LockedDeviceLegacy`.
+    pub const fn is_locked(self) -> bool {
+        matches!(
+            self,
+            APDUResponseCodes::LockedDevice | APDUResponseCodes::LockedDeviceLegacy
+        )
+    }
+
     /// Return a description of the response code.
     pub const fn description(self) -> &'static str {
         match self {
             APDUResponseCodes::NoError => "[APDU_CODE_NOERROR]",
-            APDUResponseCodes::ExecutionError => {
-                "[APDU_CODE_EXECUTION_ERROR] No information given (NV-Ram not changed)"
-            }
+            APDUResponseCodes::UserRefusedOnDevice => "[APDU_CODE_USER_REFUSED_ON_DEVICE] The user refused the action on the device",
+            APDUResponseCodes::LockedDevice => "[APDU_CODE_LOCKED_DEVICE] Device is locked",
+            APDUResponseCodes::AuthenticationFailed => "[APDU_CODE_AUTHENTICATION_FAILED] Authentication failed",
+            APDUResponseCodes::ExecutionError => "[APDU_CODE_EXECUTION_ERROR] No information given (NV-Ram not changed)",
+            APDUResponseCodes::MemoryFailure => "[APDU_CODE_MEMORY_FAILURE] Memory failure",
             APDUResponseCodes::WrongLength => "[APDU_CODE_WRONG_LENGTH] Wrong length",
-            APDUResponseCodes::UnlockDeviceError => {
-                "[APDU_CODE_UNLOCK_DEVICE_ERROR] Device is locked"
-            }
+            APDUResponseCodes::MissingCriticalParameter => "[APDU_CODE_MISSING_CRITICAL_PARAMETER] Functions in CLA not supported",
+            APDUResponseCodes::UnlockDeviceError => "[APDU_CODE_UNLOCK_DEVICE_ERROR] Device is locked",
+            APDUResponseCodes::IncompatibleFileStructure => "[APDU_CODE_INCOMPATIBLE_FILE_STRUCTURE] Command incompatible with file structure",
             APDUResponseCodes::EmptyBuffer => "[APDU_CODE_EMPTY_BUFFER]",
             APDUResponseCodes::OutputBufferTooSmall => "[APDU_CODE_OUTPUT_BUFFER_TOO_SMALL]",
-            APDUResponseCodes::DataInvalid => {
-                "[APDU_CODE_DATA_INVALID] data reversibly blocked (invalidated)"
-            }
-            APDUResponseCodes::ConditionsNotSatisfied => {
-                "[APDU_CODE_CONDITIONS_NOT_SATISFIED] Conditions of use not satisfied"
-            }
-            APDUResponseCodes::CommandNotAllowed => {
-                "[APDU_CODE_COMMAND_NOT_ALLOWED] Command not allowed (no current EF)"
-            }
-            APDUResponseCodes::InvalidData => {
-                "[APDU_CODE_INVALID_DATA] The parameters in the data field are incorrect"
-            }
+            APDUResponseCodes::DataInvalid => "[APDU_CODE_DATA_INVALID] data reversibly blocked (invalidated)",
+            APDUResponseCodes::ConditionsNotSatisfied => "[APDU_CODE_CONDITIONS_NOT_SATISFIED] Conditions of use not satisfied",
+            APDUResponseCodes::CommandNotAllowed => "[APDU_CODE_COMMAND_NOT_ALLOWED] Command not allowed (no current EF)",
+            APDUResponseCodes::InvalidData => "[APDU_CODE_INVALID_DATA] The parameters in the data field are incorrect",
+            APDUResponseCodes::FunctionNotSupported => "[APDU_CODE_FUNCTION_NOT_SUPPORTED] Function not supported",
+            APDUResponseCodes::FileNotFound => "[APDU_CODE_FILE_NOT_FOUND] File or application not found",
+            APDUResponseCodes::NotEnoughMemory => "[APDU_CODE_NOT_ENOUGH_MEMORY] Not enough memory space in the file",
+            APDUResponseCodes::IncorrectP1P2 => "[APDU_CODE_INCORRECT_P1P2] Incorrect parameters P1-P2",
+            APDUResponseCodes::ReferencedDataNotFound => "[APDU_CODE_REFERENCED_DATA_NOT_FOUND] Referenced data not found",
+            APDUResponseCodes::FileAlreadyExists => "[APDU_CODE_FILE_ALREADY_EXISTS] File already exists",
             APDUResponseCodes::InvalidP1P2 => "[APDU_CODE_INVALIDP1P2] Wrong parameter(s) P1-P2",
-            APDUResponseCodes::InsNotSupported => {
-                "[APDU_CODE_INS_NOT_SUPPORTED] Instruction code not supported or invalid. Hint: Is the correct application open on the device?"
-            }
-            APDUResponseCodes::ClaNotSupported => {
-                "[APDU_CODE_CLA_NOT_SUPPORTED] Class not supported"
-            }
+            APDUResponseCodes::LockedDeviceLegacy => "[APDU_CODE_LOCKED_DEVICE] Device is locked",
+            APDUResponseCodes::InsNotSupported => "[APDU_CODE_INS_NOT_SUPPORTED] Instruction code not supported or invalid. Hint: Is the correct application open on the device?",
+            APDUResponseCodes::UnknownApdu => "[APDU_CODE_UNKNOWN_APDU] Unknown APDU. Hint: Is the correct application open on the device?",
+            APDUResponseCodes::DeviceNotOnboarded => "[APDU_CODE_DEVICE_NOT_ONBOARDED] The device is not set up",
+            APDUResponseCodes::ClaNotSupported => "[APDU_CODE_CLA_NOT_SUPPORTED] Class not supported",
             APDUResponseCodes::Unknown => "[APDU_CODE_UNKNOWN]",
             APDUResponseCodes::SignVerifyError => "[APDU_CODE_SIGN_VERIFY_ERROR]",
+            APDUResponseCodes::Halted => "[APDU_CODE_HALTED] The device is halted. Hint: Restart the device.",
         }
     }
 }
@@ -286,29 +634,179 @@ impl TryFrom<u16> for APDUResponseCodes {
     fn try_from(code: u16) -> Result<Self, Self::Error> {
         match code {
             0x9000 => Ok(APDUResponseCodes::NoError),
+            0x5501 => Ok(APDUResponseCodes::UserRefusedOnDevice),
+            0x5515 => Ok(APDUResponseCodes::LockedDevice),
+            0x6300 => Ok(APDUResponseCodes::AuthenticationFailed),
             0x6400 => Ok(APDUResponseCodes::ExecutionError),
+            0x6581 => Ok(APDUResponseCodes::MemoryFailure),
             0x6700 => Ok(APDUResponseCodes::WrongLength),
+            0x6800 => Ok(APDUResponseCodes::MissingCriticalParameter),
             0x6804 => Ok(APDUResponseCodes::UnlockDeviceError),
+            0x6981 => Ok(APDUResponseCodes::IncompatibleFileStructure),
             0x6982 => Ok(APDUResponseCodes::EmptyBuffer),
             0x6983 => Ok(APDUResponseCodes::OutputBufferTooSmall),
             0x6984 => Ok(APDUResponseCodes::DataInvalid),
             0x6985 => Ok(APDUResponseCodes::ConditionsNotSatisfied),
             0x6986 => Ok(APDUResponseCodes::CommandNotAllowed),
             0x6A80 => Ok(APDUResponseCodes::InvalidData),
+            0x6A81 => Ok(APDUResponseCodes::FunctionNotSupported),
+            0x6A82 => Ok(APDUResponseCodes::FileNotFound),
+            0x6A84 => Ok(APDUResponseCodes::NotEnoughMemory),
+            0x6A86 => Ok(APDUResponseCodes::IncorrectP1P2),
+            0x6A88 => Ok(APDUResponseCodes::ReferencedDataNotFound),
+            0x6A89 => Ok(APDUResponseCodes::FileAlreadyExists),
             0x6B00 => Ok(APDUResponseCodes::InvalidP1P2),
+            0x6B0C => Ok(APDUResponseCodes::LockedDeviceLegacy),
             0x6D00 => Ok(APDUResponseCodes::InsNotSupported),
+            0x6D02 => Ok(APDUResponseCodes::UnknownApdu),
+            0x6D07 => Ok(APDUResponseCodes::DeviceNotOnboarded),
             0x6E00 => Ok(APDUResponseCodes::ClaNotSupported),
             0x6F00 => Ok(APDUResponseCodes::Unknown),
             0x6F01 => Ok(APDUResponseCodes::SignVerifyError),
+            0x6FAA => Ok(APDUResponseCodes::Halted),
             _ => Err(LedgerError::UnknownAPDUCode(code)),
         }
     }
 }
 
+/// An ISO 7816 status word. Unlike [`APDUResponseCodes`], this preserves
+/// unknown and app-specific values, so apps can branch on vendor-specific
+/// codes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct StatusWord(pub u16);
+
+impl StatusWord {
+    /// The first status byte. This identifies the status class, e.g. `0x90`
+    /// for success, or `0x6A` for wrong parameters.
+    pub const fn sw1(self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    /// The second status byte. This qualifies the status class.
+    pub const fn sw2(self) -> u8 {
+        self.0 as u8
+    }
+
+    /// True if the status word is `0x9000`.
+    pub const fn is_success(self) -> bool {
+        self.0 == APDUResponseCodes::NoError as u16
+    }
+
+    /// True if the status word is an ISO 7816 warning (`0x62XX` or
+    /// `0x63XX`).
+    pub const fn is_warning(self) -> bool {
+        matches!(self.sw1(), 0x62 | 0x63)
+    }
+
+    /// The known response code, if any.
+    pub fn code(self) -> Option<APDUResponseCodes> {
+        self.0.try_into().ok()
+    }
+}
+
+impl From<u16> for StatusWord {
+    fn from(sw: u16) -> Self {
+        Self(sw)
+    }
+}
+
+impl From<StatusWord> for u16 {
+    fn from(sw: StatusWord) -> Self {
+        sw.0
+    }
+}
+
+impl From<APDUResponseCodes> for StatusWord {
+    fn from(code: APDUResponseCodes) -> Self {
+        Self(code as u16)
+    }
+}
+
+impl std::fmt::Display for StatusWord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.code() {
+            Some(code) => code.fmt(f),
+            None => write!(f, "Code {:x} (unknown)", self.0),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn it_parses_commands() {
+        let commands = [
+            APDUCommand {
+                cla: 0xe0,
+                ins: 0x01,
+                p1: 0x02,
+                p2: 0x03,
+                data: vec![].into(),
+                response_len: None,
+            },
+            APDUCommand {
+                cla: 0xe0,
+                ins: 0x01,
+                p1: 0x02,
+                p2: 0x03,
+                data: vec![].into(),
+                response_len: Some(0x20),
+            },
+            APDUCommand {
+                cla: 0xe0,
+                ins: 0x01,
+                p1: 0x02,
+                p2: 0x03,
+                data: vec![1, 2, 3].into(),
+                response_len: None,
+            },
+            APDUCommand {
+                cla: 0xe0,
+                ins: 0x01,
+                p1: 0x02,
+                p2: 0x03,
+                data: vec![1, 2, 3].into(),
+                response_len: Some(0x20),
+            },
+        ];
+        for command in commands {
+            let buf = command.serialize();
+            assert_eq!(APDUCommand::try_from(&buf[..]).unwrap(), command);
+        }
+
+        assert!(APDUCommand::try_from(&[0xe0, 0x01, 0x02][..]).is_err());
+        assert!(APDUCommand::try_from(&[0xe0, 0x01, 0x02, 0x03, 0x03, 0x01][..]).is_err());
+        assert!(
+            APDUCommand::try_from(&[0xe0, 0x01, 0x02, 0x03, 0x01, 0x01, 0x02, 0x03][..]).is_err()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_serializes_as_hex() {
+        let command = APDUCommand {
+            cla: 0xe0,
+            ins: 0x01,
+            p1: 0x00,
+            p2: 0x00,
+            data: vec![0xaa, 0xbb].into(),
+            response_len: None,
+        };
+        let json = serde_json::to_string(&command).unwrap();
+        assert_eq!(json, "\"e001000002aabb\"");
+        assert_eq!(serde_json::from_str::<APDUCommand>(&json).unwrap(), command);
+
+        let answer = APDUAnswer::from_answer(vec![0x01, 0x90, 0x00]).unwrap();
+        let json = serde_json::to_string(&answer).unwrap();
+        assert_eq!(json, "\"019000\"");
+        assert_eq!(serde_json::from_str::<APDUAnswer>(&json).unwrap(), answer);
+
+        assert!(serde_json::from_str::<APDUAnswer>("\"90\"").is_err());
+        assert!(serde_json::from_str::<APDUCommand>("\"zz\"").is_err());
+    }
+
     #[test]
     fn serialize() {
         let data: &[u8] = &[0, 0, 0, 1, 0, 0, 0, 1];
@@ -339,6 +837,136 @@ mod test {
         let expected = vec![224, 1, 0, 0, 8, 0, 0, 0, 1, 0, 0, 0, 1, 13];
         assert_eq!(serialized_command, expected)
     }
+
+    #[test]
+    fn chunk() {
+        let payload: Vec<u8> = (0..=255).chain(0..=255).map(|b| b as u8).collect();
+
+        let chunker = APDUChunker::new(
+            0xe0,
+            0x04,
+            ChunkParams::MoreAndLast {
+                more: 0x00,
+                last: 0x80,
+                p2: 0x02,
+            },
+        );
+        let commands = chunker.chunk(&payload);
+        assert_eq!(commands.len(), 3);
+        assert_eq!(
+            commands.iter().map(|c| (c.p1, c.p2)).collect::<Vec<_>>(),
+            vec![(0x00, 0x02), (0x00, 0x02), (0x80, 0x02)]
+        );
+        let rejoined: Vec<u8> = commands.iter().flat_map(|c| c.data.to_vec()).collect();
+        assert_eq!(rejoined, payload);
+
+        let commands = chunker.chunk_size(100).chunk(&payload);
+        assert_eq!(commands.len(), 6);
+        assert_eq!(commands[5].data.len(), 12);
+
+        let chunker = APDUChunker::new(0x55, 0x02, ChunkParams::InitAddLast { p2: 0 });
+        let p1s: Vec<u8> = chunker.chunk(&payload).iter().map(|c| c.p1).collect();
+        assert_eq!(p1s, vec![0, 1, 1, 2]);
+        assert!(chunker.chunk(&payload)[0].data.is_empty());
+
+        assert_eq!(chunker.chunk(&[]).len(), 1);
+    }
+
+    #[test]
+    fn status_words() {
+        let answer = APDUAnswer::from_answer(vec![0x01, 0x6a, 0x82]).unwrap();
+        let sw = answer.status_word();
+        assert_eq!((sw.sw1(), sw.sw2()), (0x6a, 0x82));
+        assert_eq!(sw.code(), Some(APDUResponseCodes::FileNotFound));
+        assert!(!answer.is_success());
+        assert_eq!(answer.data(), None);
+
+        // app-specific codes are preserved
+        let answer = APDUAnswer::from_answer(vec![0xb0, 0x07]).unwrap();
+        assert_eq!(answer.status_word(), StatusWord(0xb007));
+        assert_eq!(answer.status_word().code(), None);
+        assert_eq!(answer.response_status(), None);
+        assert_eq!(answer.status_word().to_string(), "Code b007 (unknown)");
+
+        assert!(StatusWord(0x63c2).is_warning());
+        assert!(StatusWord::from(APDUResponseCodes::NoError).is_success());
+        for code in [0x9000u16, 0x5501, 0x6a86, 0x6d02, 0x6faa] {
+            let known = APDUResponseCodes::try_from(code).unwrap();
+            assert_eq!(known as u16, code);
+        }
+    }
+
+    #[test]
+    fn locked_device() {
+        let answer = APDUAnswer::from_answer(vec![0x55, 0x15]).unwrap();
+        assert!(answer.response_status().unwrap().is_locked());
+        assert!(matches!(
+            answer.status_error(),
+            LedgerError::DeviceLocked(APDUResponseCodes::LockedDevice)
+        ));
+
+        let answer = APDUAnswer::from_answer(vec![0x6b, 0x0c]).unwrap();
+        assert!(matches!(
+            answer.status_error(),
+            LedgerError::DeviceLocked(APDUResponseCodes::LockedDeviceLegacy)
+        ));
+
+        let answer = APDUAnswer::from_answer(vec![0x69, 0x85]).unwrap();
+        assert!(matches!(
+            answer.status_error(),
+            LedgerError::BadRetcode(APDUResponseCodes::ConditionsNotSatisfied)
+        ));
+
+        // "security status not satisfied" is not retried as a locked device
+        let answer = APDUAnswer::from_answer(vec![0x69, 0x82]).unwrap();
+        assert!(matches!(
+            answer.status_error(),
+            LedgerError::BadRetcode(APDUResponseCodes::EmptyBuffer)
+        ));
+    }
+
+    #[test]
+    fn app_info() {
+        let answer =
+            APDUAnswer::from_answer(hex::decode("0107426974636f696e05322e312e3001029000").unwrap())
+                .unwrap();
+        let info = AppInfo::from_answer(&answer).unwrap();
+        assert_eq!(info.name, "Bitcoin");
+        assert_eq!(info.version, "2.1.0");
+        assert_eq!(info.flags, vec![0x02]);
+        assert!(!info.is_dashboard());
+
+        let answer =
+            APDUAnswer::from_answer(hex::decode("0105424f4c4f5305312e302e309000").unwrap())
+                .unwrap();
+        let info = AppInfo::from_answer(&answer).unwrap();
+        assert!(info.is_dashboard());
+        assert!(info.flags.is_empty());
+
+        let answer = APDUAnswer::from_answer(hex::decode("01084269749000").unwrap()).unwrap();
+        match AppInfo::from_answer(&answer) {
+            Err(LedgerError::MalformedAnswer(_)) => {}
+            _ => panic!("expected malformed answer"),
+        }
+    }
+
+    #[test]
+    fn reassemble_answers() {
+        let answers = [
+            APDUAnswer::from_answer(vec![1, 2, 0x90, 0x00]).unwrap(),
+            APDUAnswer::from_answer(vec![3, 0x90, 0x00]).unwrap(),
+        ];
+        assert_eq!(reassemble(&answers).unwrap(), vec![1, 2, 3]);
+
+        let answers = [
+            APDUAnswer::from_answer(vec![1, 2, 0x90, 0x00]).unwrap(),
+            APDUAnswer::from_answer(vec![0x69, 0x85]).unwrap(),
+        ];
+        match reassemble(&answers) {
+            Err(LedgerError::BadRetcode(APDUResponseCodes::ConditionsNotSatisfied)) => {}
+            _ => panic!("expected bad retcode"),
+        }
+    }
 }
 
 /*******************************************************************************
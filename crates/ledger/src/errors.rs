@@ -13,10 +13,44 @@ pub enum LedgerError {
     #[error("Ledger device: APDU Response error `{0}`")]
     BadRetcode(APDUResponseCodes),
 
+    /// The device is locked. The user must unlock it with their PIN.
+    #[error("Ledger device is locked ({0}). Hint: Unlock your Ledger.")]
+    DeviceLocked(APDUResponseCodes),
+
     /// Ledger returned an unknown APDU
     #[error("Ledger returned an unknown response status code {0:x}. This is a bug. Please file an issue at https://github.com/summa-tx/coins-rs/issues")]
     UnknownAPDUCode(u16),
 
+    /// The device answered with data that could not be parsed.
+    #[error("Malformed answer from device: {0:?}")]
+    MalformedAnswer(Vec<u8>),
+
+    /// A serialized APDU command could not be parsed.
+    #[error("Malformed APDU command: {0:?}")]
+    MalformedCommand(Vec<u8>),
+
+    /// The running app is not one of the expected apps.
+    #[error("Expected one of the apps {expected:?} to be open on the device. Got `{running}`. Hint: Open the app on the device.")]
+    WrongApp {
+        /// The expected app names
+        expected: Vec<String>,
+        /// The name of the running app. This is `BOLOS` if no app is open.
+        running: String,
+    },
+
+    /// A mock transport received a command other than the next scripted one.
+    #[error("Unexpected command {got:?}. Expected {expected:?}")]
+    UnexpectedCommand {
+        /// The next scripted command, if any
+        expected: Option<Vec<u8>>,
+        /// The command received
+        got: Vec<u8>,
+    },
+
+    /// A recording could not be parsed.
+    #[error("Invalid recording: {0}")]
+    InvalidRecording(String),
+
     /// The backend has been disconnected.
     #[error("The backend has been disconnected.")]
     BackendGone,
@@ -41,6 +75,17 @@ impl From<wasm_bindgen::prelude::JsValue> for LedgerError {
 
 impl From<APDUResponseCodes> for LedgerError {
     fn from(r: APDUResponseCodes) -> Self {
-        LedgerError::BadRetcode(r)
+        if r.is_locked() {
+            LedgerError::DeviceLocked(r)
+        } else {
+            LedgerError::BadRetcode(r)
+        }
+    }
+}
+
+impl LedgerError {
+    /// True if the error indicates that the device is locked.
+    pub const fn is_locked(&self) -> bool {
+        matches!(self, LedgerError::DeviceLocked(_))
     }
 }
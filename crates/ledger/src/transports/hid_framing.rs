@@ -0,0 +1,88 @@
+//! The Ledger HID framing protocol, as spoken by the WebHID transport. It has
+//! no browser dependencies, so that it may be tested natively.
+
+pub(crate) const LEDGER_CHANNEL: u16 = 0x0101;
+pub(crate) const LEDGER_TAG: u8 = 0x05;
+// WebHID passes the report ID separately, so unlike native HID there is no
+// leading 0x00 byte
+pub(crate) const LEDGER_PACKET_SIZE: usize = 64;
+
+/// Split an APDU into HID packets.
+pub(crate) fn frame_apdu(apdu: &[u8]) -> Vec<[u8; LEDGER_PACKET_SIZE]> {
+    let mut data = Vec::with_capacity(apdu.len() + 2);
+    data.extend_from_slice(&(apdu.len() as u16).to_be_bytes());
+    data.extend_from_slice(apdu);
+
+    data.chunks(LEDGER_PACKET_SIZE - 5)
+        .enumerate()
+        .map(|(sequence_idx, chunk)| {
+            let mut packet = [0u8; LEDGER_PACKET_SIZE];
+            packet[..2].copy_from_slice(&LEDGER_CHANNEL.to_be_bytes());
+            packet[2] = LEDGER_TAG;
+            packet[3..5].copy_from_slice(&(sequence_idx as u16).to_be_bytes());
+            packet[5..5 + chunk.len()].copy_from_slice(chunk);
+            packet
+        })
+        .collect()
+}
+
+/// Check the header of the `sequence_idx`th HID packet of a response, and
+/// return its body. The body of the first packet starts with the response
+/// length as u16, successive packets do not.
+pub(crate) fn packet_body(packet: &[u8], sequence_idx: u16) -> Result<&[u8], &'static str> {
+    if (sequence_idx == 0 && packet.len() < 7) || packet.len() < 5 {
+        return Err("Read error. Incomplete header");
+    }
+    if packet[..2] != LEDGER_CHANNEL.to_be_bytes() || packet[2] != LEDGER_TAG {
+        return Err("Read error. Unexpected channel or tag");
+    }
+    if u16::from_be_bytes([packet[3], packet[4]]) != sequence_idx {
+        return Err("Read error. Sequence mismatch");
+    }
+    Ok(&packet[5..])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_frames_apdus() {
+        let apdu: Vec<u8> = (0..100).collect();
+        let packets = frame_apdu(&apdu);
+        assert_eq!(packets.len(), 2);
+
+        assert_eq!(&packets[0][..7], &[0x01, 0x01, 0x05, 0x00, 0x00, 0x00, 100]);
+        assert_eq!(&packets[0][7..], &apdu[..57]);
+        assert_eq!(&packets[1][..5], &[0x01, 0x01, 0x05, 0x00, 0x01]);
+        assert_eq!(&packets[1][5..48], &apdu[57..]);
+        // the last packet is zero-padded
+        assert!(packets[1][48..].iter().all(|b| *b == 0));
+
+        // a short APDU fits in one packet
+        let packets = frame_apdu(&[0xe0, 0x01, 0x00, 0x00, 0x00]);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(
+            &packets[0][..12],
+            &[0x01, 0x01, 0x05, 0x00, 0x00, 0x00, 0x05, 0xe0, 0x01, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn it_checks_packet_headers() {
+        let packets = frame_apdu(&[0x90, 0x00]);
+        assert_eq!(
+            &packet_body(&packets[0], 0).unwrap()[..4],
+            &[0x00, 0x02, 0x90, 0x00]
+        );
+
+        assert!(packet_body(&packets[0], 1).is_err());
+        assert!(packet_body(&packets[0][..6], 0).is_err());
+        let mut wrong_channel = packets[0];
+        wrong_channel[1] = 0x02;
+        assert!(packet_body(&wrong_channel, 0).is_err());
+        let mut wrong_tag = packets[0];
+        wrong_tag[2] = 0x06;
+        assert!(packet_body(&wrong_tag, 0).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! Scriptable mock transport. Exchanges are scripted in advance, or recorded
+//! from a real device, and replayed in order. This allows unit-testing
+//! device flows without a device.
+//!
+//! Recordings use the same text format as the `@ledgerhq/hw-transport-mocker`
+//! `RecordStore`: one `=> <command hex>` line, followed by one
+//! `<= <answer hex>` line, per exchange.
+
+use std::{collections::VecDeque, path::Path, sync::Mutex};
+
+use async_trait::async_trait;
+
+use crate::{
+    common::{APDUAnswer, APDUCommand},
+    errors::LedgerError,
+    transports::LedgerAsync,
+};
+
+/// A recorded APDU exchange, as raw command and answer bytes. The answer
+/// includes the status word.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording(Vec<(Vec<u8>, Vec<u8>)>);
+
+impl Recording {
+    /// Append an exchange to the recording.
+    pub fn push(&mut self, command: Vec<u8>, answer: Vec<u8>) {
+        self.0.push((command, answer));
+    }
+
+    /// The recorded exchanges, in order.
+    pub fn exchanges(&self) -> &[(Vec<u8>, Vec<u8>)] {
+        &self.0
+    }
+
+    /// Load a recording fixture from a file in the text format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LedgerError> {
+        let path = path.as_ref();
+        std::fs::read_to_string(path)
+            .map_err(|e| LedgerError::InvalidRecording(format!("{}: {}", path.display(), e)))?
+            .parse()
+    }
+}
+
+/// A recorded exchange, as serialized by serde.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerdeExchange {
+    command: APDUCommand,
+    answer: APDUAnswer,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Recording {
+    /// Serialized as a sequence of `{ command, answer }` hex pairs
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::Error;
+
+        self.0
+            .iter()
+            .map(|(command, answer)| {
+                Ok(SerdeExchange {
+                    command: APDUCommand::try_from(&command[..]).map_err(S::Error::custom)?,
+                    answer: APDUAnswer::from_answer(answer.clone()).map_err(S::Error::custom)?,
+                })
+            })
+            .collect::<Result<Vec<_>, S::Error>>()?
+            .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Recording {
+    fn deserialize<D>(deserializer: D) -> Result<Recording, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let exchanges: Vec<SerdeExchange> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(Recording(
+            exchanges
+                .into_iter()
+                .map(|exchange| (exchange.command.serialize(), exchange.answer.to_vec()))
+                .collect(),
+        ))
+    }
+}
+
+impl std::fmt::Display for Recording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (command, answer) in self.0.iter() {
+            writeln!(f, "=> {}", hex::encode(command))?;
+            writeln!(f, "<= {}", hex::encode(answer))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Recording {
+    type Err = LedgerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad_line = |line: &str| LedgerError::InvalidRecording(line.to_owned());
+
+        let mut recording = Recording::default();
+        let mut command = None;
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (prefix, data) = line.split_at(std::cmp::min(2, line.len()));
+            let data = hex::decode(data.trim()).map_err(|_| bad_line(line))?;
+            match (prefix, command.take()) {
+                ("=>", None) => command = Some(data),
+                ("<=", Some(command)) => recording.push(command, data),
+                _ => return Err(bad_line(line)),
+            }
+        }
+        if command.is_some() {
+            return Err(LedgerError::InvalidRecording(
+                "command without answer".to_owned(),
+            ));
+        }
+        Ok(recording)
+    }
+}
+
+/// A mock transport that replays scripted exchanges. Each exchange must
+/// match the next scripted command exactly, or it fails with
+/// `UnexpectedCommand`.
+///
+/// ```
+/// use coins_ledger::{common::APDUCommand, transports::mock::MockTransport};
+///
+/// let command = APDUCommand {
+///     cla: 0xe0,
+///     ins: 0x06,
+///     p1: 0x00,
+///     p2: 0x00,
+///     data: vec![].into(),
+///     response_len: None,
+/// };
+/// let mock = MockTransport::new().expect(&command, &[0x01, 0x02, 0x90, 0x00]);
+/// let answer = mock.exchange(&command).unwrap();
+/// assert_eq!(answer.data(), Some(&[0x01, 0x02][..]));
+/// assert!(mock.is_done());
+/// ```
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    exchanges: Mutex<VecDeque<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl From<Recording> for MockTransport {
+    fn from(recording: Recording) -> Self {
+        Self {
+            exchanges: Mutex::new(recording.0.into()),
+        }
+    }
+}
+
+impl MockTransport {
+    /// Instantiate a mock with no scripted exchanges.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instantiate a mock replaying the recording fixture at `path`. See
+    /// [`Recording::load`].
+    pub fn from_fixture<P: AsRef<Path>>(path: P) -> Result<Self, LedgerError> {
+        Recording::load(path).map(Into::into)
+    }
+
+    /// Script an exchange. `answer` is the raw answer, including the status
+    /// word.
+    pub fn expect(self, command: &APDUCommand, answer: &[u8]) -> Self {
+        self.push(command.serialize(), answer.to_vec());
+        self
+    }
+
+    /// Script an exchange from raw command and answer bytes.
+    pub fn push(&self, command: Vec<u8>, answer: Vec<u8>) {
+        self.exchanges.lock().unwrap().push_back((command, answer));
+    }
+
+    /// True if all scripted exchanges have been replayed.
+    pub fn is_done(&self) -> bool {
+        self.exchanges.lock().unwrap().is_empty()
+    }
+
+    /// Exchange an APDU with the mock. Non-success status words are returned
+    /// as errors, as with device transports.
+    pub fn exchange(&self, command: &APDUCommand) -> Result<APDUAnswer, LedgerError> {
+        let got = command.serialize();
+        let answer = {
+            let mut exchanges = self.exchanges.lock().unwrap();
+            match exchanges.front() {
+                Some((expected, _)) if *expected == got => exchanges.pop_front().unwrap().1,
+                expected => {
+                    return Err(LedgerError::UnexpectedCommand {
+                        expected: expected.map(|(expected, _)| expected.clone()),
+                        got,
+                    })
+                }
+            }
+        };
+
+        let answer = APDUAnswer::from_answer(answer)?;
+
+        match answer.response_status() {
+            None => Ok(answer),
+            Some(response) => {
+                if response.is_success() {
+                    Ok(answer)
+                } else {
+                    Err(response.into())
+                }
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl LedgerAsync for MockTransport {
+    /// Instantiate a mock with no scripted exchanges.
+    async fn init() -> Result<Self, LedgerError> {
+        Ok(Self::new())
+    }
+
+    async fn exchange(&self, packet: &APDUCommand) -> Result<APDUAnswer, LedgerError> {
+        MockTransport::exchange(self, packet)
+    }
+}
+
+/// Wraps a transport, and records each exchange, e.g. to produce fixtures
+/// for [`MockTransport`].
+#[derive(Debug)]
+pub struct Recorder<T> {
+    inner: T,
+    recording: Mutex<Recording>,
+}
+
+impl<T> Recorder<T> {
+    /// Wrap a transport.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            recording: Default::default(),
+        }
+    }
+
+    /// A copy of the exchanges recorded so far.
+    pub fn recording(&self) -> Recording {
+        self.recording.lock().unwrap().clone()
+    }
+
+    /// Consume the recorder, and return the wrapped transport.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<T> LedgerAsync for Recorder<T>
+where
+    T: LedgerAsync + Send + Sync,
+{
+    async fn init() -> Result<Self, LedgerError> {
+        Ok(Self::new(T::init().await?))
+    }
+
+    async fn exchange(&self, packet: &APDUCommand) -> Result<APDUAnswer, LedgerError> {
+        let answer = self.inner.exchange(packet).await;
+        // status word errors are answers too, so that they can be replayed
+        let raw = match &answer {
+            Ok(answer) => Some(answer.to_vec()),
+            Err(LedgerError::BadRetcode(code) | LedgerError::DeviceLocked(code)) => {
+                Some((*code as u16).to_be_bytes().to_vec())
+            }
+            Err(LedgerError::UnknownAPDUCode(code)) => Some(code.to_be_bytes().to_vec()),
+            Err(_) => None,
+        };
+        if let Some(raw) = raw {
+            self.recording.lock().unwrap().push(packet.serialize(), raw);
+        }
+        answer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command(ins: u8) -> APDUCommand {
+        APDUCommand {
+            cla: 0xe0,
+            ins,
+            p1: 0x00,
+            p2: 0x00,
+            data: vec![1, 2, 3].into(),
+            response_len: None,
+        }
+    }
+
+    #[test]
+    fn it_replays_in_order() {
+        let mock = MockTransport::new()
+            .expect(&command(1), &[0xaa, 0x90, 0x00])
+            .expect(&command(2), &[0x69, 0x85]);
+
+        match mock.exchange(&command(2)) {
+            Err(LedgerError::UnexpectedCommand { expected, got }) => {
+                assert_eq!(expected, Some(command(1).serialize()));
+                assert_eq!(got, command(2).serialize());
+            }
+            _ => panic!("expected unexpected command"),
+        }
+
+        assert_eq!(
+            mock.exchange(&command(1)).unwrap().data(),
+            Some(&[0xaa][..])
+        );
+        match mock.exchange(&command(2)) {
+            Err(LedgerError::BadRetcode(code)) => assert_eq!(code as u16, 0x6985),
+            _ => panic!("expected bad retcode"),
+        }
+        assert!(mock.is_done());
+    }
+
+    #[tokio::test]
+    async fn it_records_and_replays() {
+        let device = MockTransport::new()
+            .expect(&command(1), &[0xaa, 0x90, 0x00])
+            .expect(&command(2), &[0x69, 0x85])
+            .expect(&command(3), &[0x55, 0x15]);
+        let recorder = Recorder::new(device);
+        LedgerAsync::exchange(&recorder, &command(1)).await.unwrap();
+        LedgerAsync::exchange(&recorder, &command(2))
+            .await
+            .unwrap_err();
+        assert!(LedgerAsync::exchange(&recorder, &command(3))
+            .await
+            .unwrap_err()
+            .is_locked());
+
+        let text = recorder.recording().to_string();
+        assert_eq!(
+            text,
+            "=> e001000003010203\n<= aa9000\n=> e002000003010203\n<= 6985\n=> e003000003010203\n<= 5515\n"
+        );
+
+        let recording: Recording = text.parse().unwrap();
+        assert_eq!(recording, recorder.recording());
+        let replay = MockTransport::from(recording);
+        assert_eq!(
+            replay.exchange(&command(1)).unwrap().data(),
+            Some(&[0xaa][..])
+        );
+
+        assert!("=> e001\n".parse::<Recording>().is_err());
+        assert!("<= 9000\n".parse::<Recording>().is_err());
+        assert!("=> zz\n<= 9000".parse::<Recording>().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_serializes_recordings() {
+        let recording: Recording = "=> e001000003010203\n<= aa9000\n".parse().unwrap();
+        let json = serde_json::to_string(&recording).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"command":"e001000003010203","answer":"aa9000"}]"#
+        );
+        assert_eq!(serde_json::from_str::<Recording>(&json).unwrap(), recording);
+    }
+}
@@ -1,11 +1,18 @@
 //! Abstract ledger tranport trait with WASM and native HID instantiations.
 
 use crate::{
-    common::{APDUAnswer, APDUCommand},
+    common::{APDUAnswer, APDUCommand, AppInfo},
     errors::LedgerError,
 };
 use async_trait::async_trait;
 
+/// Scriptable mock transport, for testing device flows without a device.
+pub mod mock;
+
+/// HID framing for the WebHID transport.
+#[cfg(any(test, all(target_arch = "wasm32", feature = "browser")))]
+mod hid_framing;
+
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "wasm32")] {
         /// APDU Transport wrapper for JS/WASM transports.
@@ -20,16 +27,106 @@ cfg_if::cfg_if! {
         pub mod native;
         pub use native::LedgerHandle as DefaultTransport;
 
+        /// APDU Transport for the Speculos emulator's TCP APDU server.
+        pub mod tcp;
+
+        /// Speculos REST automation helpers, for unattended end-to-end tests.
+        #[cfg(feature = "speculos")]
+        pub mod speculos;
+
         use tracing::{debug, error};
     }
 }
 
 /// A Ledger device connection. This wraps the default transport type. In
-/// native code, this is the `hidapi` library. When the `node` or `browser`
-/// feature is selected, it is a Ledger JS transport library.
+/// native code, this is the `hidapi` library, or a Speculos emulator when the
+/// `LEDGER_SPECULOS_ADDR` environment variable is set. When the `node` or
+/// `browser` feature is selected, it is a Ledger JS transport library.
 #[derive(Debug)]
 pub struct Ledger(DefaultTransport);
 
+impl Ledger {
+    /// Get the name and version of the app running on the device.
+    pub async fn get_app_info(&self) -> Result<AppInfo, LedgerError> {
+        let answer = self.exchange(&AppInfo::command()).await?;
+        AppInfo::from_answer(&answer)
+    }
+
+    /// Check that one of the apps named in `expected` is running, e.g.
+    /// `&["Bitcoin", "Bitcoin Test"]`. Fails with `WrongApp` otherwise.
+    pub async fn require_app(&self, expected: &[&str]) -> Result<AppInfo, LedgerError> {
+        let info = self.get_app_info().await?;
+        if expected.contains(&info.name.as_str()) {
+            Ok(info)
+        } else {
+            Err(LedgerError::WrongApp {
+                expected: expected.iter().map(|name| name.to_string()).collect(),
+                running: info.name,
+            })
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Ledger {
+    /// Connect to the HID device at `path`. Use
+    /// [`TransportNativeHID::list_devices`][native::hid::TransportNativeHID::list_devices]
+    /// to enumerate connected devices, e.g. to let the user pick one.
+    pub fn init_path(path: &std::ffi::CStr) -> Result<Self, LedgerError> {
+        Ok(Self(DefaultTransport::init_path(path)?))
+    }
+
+    /// Connect to a Speculos emulator's APDU server at `addr`, e.g.
+    /// `127.0.0.1:9999`. This allows exercising Ledger integrations without
+    /// physical hardware.
+    pub fn init_tcp<A: std::net::ToSocketAddrs>(addr: A) -> Result<Self, LedgerError> {
+        Ok(Self(DefaultTransport::init_tcp(addr)?))
+    }
+
+    /// Connect to a mock transport, which replays scripted exchanges. This
+    /// allows testing protocols against a [`Ledger`] without a device.
+    pub fn init_mock(mock: mock::MockTransport) -> Result<Self, LedgerError> {
+        Ok(Self(DefaultTransport::init_mock(mock)?))
+    }
+
+    /// Set the policy for reconnecting after the device connection is lost,
+    /// e.g. because the device was unplugged. See
+    /// [`ReconnectPolicy`][native::ReconnectPolicy]. By default, no
+    /// reconnect is attempted.
+    pub fn set_reconnect_policy(&self, policy: Option<native::ReconnectPolicy>) {
+        self.0.set_reconnect_policy(policy)
+    }
+
+    /// Exchange a packet with the device. If the device is locked, call
+    /// `on_locked` once, e.g. to prompt the user to unlock it, and then
+    /// retry until the device is unlocked, or `wait.timeout` elapses. If the
+    /// timeout elapses, `DeviceLocked` is returned.
+    pub async fn exchange_when_unlocked<F>(
+        &self,
+        packet: &APDUCommand,
+        wait: native::UnlockWait,
+        on_locked: F,
+    ) -> Result<APDUAnswer, LedgerError>
+    where
+        F: FnOnce() + Send,
+    {
+        let deadline = tokio::time::Instant::now() + wait.timeout;
+        let mut on_locked = Some(on_locked);
+        loop {
+            match self.exchange(packet).await {
+                Err(err) if err.is_locked() && tokio::time::Instant::now() < deadline => {
+                    if let Some(on_locked) = on_locked.take() {
+                        on_locked();
+                    }
+                    debug!("device locked, waiting for unlock");
+                    tokio::time::sleep(wait.interval).await;
+                }
+                resp => return resp,
+            }
+        }
+    }
+}
+
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 /// An asynchronous interface to the Ledger device. It is critical that the
@@ -0,0 +1,354 @@
+//! Bluetooth Low Energy APDU transport for Ledger Nano X, Stax and Flex
+//! devices.
+//!
+//! APDUs are written to the device's write characteristic, and answers are
+//! received as notifications. Both directions use the Ledger BLE framing:
+//! a 0x05 tag byte and a big-endian u16 sequence index, followed on the first
+//! frame by a big-endian u16 payload length. Frames are at most one MTU long.
+
+use std::{pin::Pin, time::Duration};
+
+use async_trait::async_trait;
+use btleplug::{
+    api::{
+        Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, ValueNotification,
+        WriteType,
+    },
+    platform::{Manager, Peripheral},
+};
+use futures_util::{Stream, StreamExt};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::{
+    common::{APDUAnswer, APDUCommand},
+    errors::LedgerError,
+    transports::LedgerAsync,
+};
+
+use super::NativeTransportError;
+
+const LEDGER_TAG: u8 = 0x05;
+const MTU_TAG: u8 = 0x08;
+/// The MTU assumed until the device reports its own.
+const DEFAULT_MTU: usize = 20;
+/// How long to wait for the device to answer the MTU request.
+const MTU_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long [`LedgerAsync::init`] scans for devices.
+pub const DEFAULT_SCAN_DURATION: Duration = Duration::from_secs(3);
+
+/// The GATT UUIDs of a Ledger BLE model.
+struct BleSpec {
+    service: Uuid,
+    notify: Uuid,
+    write: Uuid,
+}
+
+const SPECS: [BleSpec; 3] = [
+    // Nano X
+    BleSpec {
+        service: Uuid::from_u128(0x13d63400_2c97_0004_0000_4c6564676572),
+        notify: Uuid::from_u128(0x13d63400_2c97_0004_0001_4c6564676572),
+        write: Uuid::from_u128(0x13d63400_2c97_0004_0002_4c6564676572),
+    },
+    // Stax
+    BleSpec {
+        service: Uuid::from_u128(0x13d63400_2c97_6004_0000_4c6564676572),
+        notify: Uuid::from_u128(0x13d63400_2c97_6004_0001_4c6564676572),
+        write: Uuid::from_u128(0x13d63400_2c97_6004_0002_4c6564676572),
+    },
+    // Flex
+    BleSpec {
+        service: Uuid::from_u128(0x13d63400_2c97_3004_0000_4c6564676572),
+        notify: Uuid::from_u128(0x13d63400_2c97_3004_0001_4c6564676572),
+        write: Uuid::from_u128(0x13d63400_2c97_3004_0002_4c6564676572),
+    },
+];
+
+type Notifications = Pin<Box<dyn Stream<Item = ValueNotification> + Send>>;
+
+/// Split an APDU into BLE frames of at most `mtu` bytes.
+fn frame_apdu(apdu: &[u8], mtu: usize) -> Vec<Vec<u8>> {
+    let mut data = Vec::with_capacity(apdu.len() + 2);
+    data.extend_from_slice(&(apdu.len() as u16).to_be_bytes());
+    data.extend_from_slice(apdu);
+
+    data.chunks(mtu - 3)
+        .enumerate()
+        .map(|(sequence_idx, chunk)| {
+            let mut frame = Vec::with_capacity(chunk.len() + 3);
+            frame.push(LEDGER_TAG);
+            frame.extend_from_slice(&(sequence_idx as u16).to_be_bytes());
+            frame.extend_from_slice(chunk);
+            frame
+        })
+        .collect()
+}
+
+/// Reassembles an answer from BLE frames.
+#[derive(Debug, Default)]
+struct AnswerBuilder {
+    sequence_idx: u16,
+    expected_len: usize,
+    answer: Vec<u8>,
+}
+
+impl AnswerBuilder {
+    /// Add a frame. Returns the answer once it is complete.
+    fn push(&mut self, frame: &[u8]) -> Result<Option<Vec<u8>>, NativeTransportError> {
+        // The first frame contains the answer length as u16, successive
+        // frames do not.
+        if (self.sequence_idx == 0 && frame.len() < 5) || frame.len() < 3 {
+            return Err(NativeTransportError::Comm("Read error. Incomplete header"));
+        }
+        if frame[0] != LEDGER_TAG {
+            return Err(NativeTransportError::Comm("Read error. Unexpected tag"));
+        }
+        let got = u16::from_be_bytes([frame[1], frame[2]]);
+        if got != self.sequence_idx {
+            return Err(NativeTransportError::SequenceMismatch {
+                got,
+                expected: self.sequence_idx,
+            });
+        }
+
+        let mut body = &frame[3..];
+        if self.sequence_idx == 0 {
+            self.expected_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+            body = &body[2..];
+        }
+
+        let missing = self.expected_len - self.answer.len();
+        self.answer
+            .extend_from_slice(&body[..std::cmp::min(body.len(), missing)]);
+        self.sequence_idx += 1;
+
+        if self.answer.len() >= self.expected_len {
+            Ok(Some(std::mem::take(&mut self.answer)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// BLE transport for Ledger Nano X, Stax and Flex devices
+pub struct TransportBle {
+    peripheral: Peripheral,
+    write: Characteristic,
+    notify: Uuid,
+    mtu: usize,
+    notifications: Mutex<Notifications>,
+}
+
+impl std::fmt::Debug for TransportBle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransportBle")
+            .field("peripheral", &self.peripheral)
+            .field("mtu", &self.mtu)
+            .finish()
+    }
+}
+
+impl TransportBle {
+    /// Scan all adapters for `scan_duration`, and return the Ledger devices
+    /// found.
+    pub async fn list_devices(
+        scan_duration: Duration,
+    ) -> Result<Vec<Peripheral>, NativeTransportError> {
+        let manager = Manager::new().await?;
+        let filter = ScanFilter {
+            services: SPECS.iter().map(|spec| spec.service).collect(),
+        };
+
+        let mut devices = vec![];
+        for adapter in manager.adapters().await? {
+            adapter.start_scan(filter.clone()).await?;
+            tokio::time::sleep(scan_duration).await;
+            adapter.stop_scan().await?;
+
+            for peripheral in adapter.peripherals().await? {
+                let is_ledger = peripheral
+                    .properties()
+                    .await?
+                    .map(|props| {
+                        props
+                            .services
+                            .iter()
+                            .any(|uuid| SPECS.iter().any(|spec| spec.service == *uuid))
+                    })
+                    .unwrap_or_default();
+                if is_ledger {
+                    devices.push(peripheral);
+                }
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Connect to a Ledger device found by [`TransportBle::list_devices`].
+    pub async fn connect(peripheral: Peripheral) -> Result<Self, NativeTransportError> {
+        if !peripheral.is_connected().await? {
+            peripheral.connect().await?;
+        }
+        peripheral.discover_services().await?;
+
+        let characteristics = peripheral.characteristics();
+        let find = |uuid: Uuid| characteristics.iter().find(|c| c.uuid == uuid).cloned();
+        let (write, notify) = SPECS
+            .iter()
+            .find_map(|spec| Some((find(spec.write)?, find(spec.notify)?)))
+            .ok_or(NativeTransportError::DeviceNotFound)?;
+
+        peripheral.subscribe(&notify).await?;
+        let notifications = peripheral.notifications().await?;
+
+        let mut transport = Self {
+            peripheral,
+            write,
+            notify: notify.uuid,
+            mtu: DEFAULT_MTU,
+            notifications: Mutex::new(notifications),
+        };
+        transport.mtu = transport.infer_mtu().await?;
+        tracing::debug!(mtu = transport.mtu, "Connected to BLE device");
+        Ok(transport)
+    }
+
+    /// Scan for `scan_duration`, and connect to the first Ledger device
+    /// found.
+    pub async fn connect_first(scan_duration: Duration) -> Result<Self, NativeTransportError> {
+        let peripheral = Self::list_devices(scan_duration)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(NativeTransportError::DeviceNotFound)?;
+        Self::connect(peripheral).await
+    }
+
+    /// Disconnect from the device.
+    pub async fn disconnect(&self) -> Result<(), NativeTransportError> {
+        Ok(self.peripheral.disconnect().await?)
+    }
+
+    /// Wait for the next notification on the answer characteristic.
+    async fn next_frame(
+        &self,
+        notifications: &mut Notifications,
+    ) -> Result<Vec<u8>, NativeTransportError> {
+        loop {
+            let notification = notifications
+                .next()
+                .await
+                .ok_or(NativeTransportError::Comm("BLE notification stream closed"))?;
+            if notification.uuid == self.notify {
+                return Ok(notification.value);
+            }
+        }
+    }
+
+    /// Ask the device for its MTU. Falls back to the default if the device
+    /// does not answer with an MTU frame within `MTU_TIMEOUT`.
+    async fn infer_mtu(&self) -> Result<usize, NativeTransportError> {
+        let mut notifications = self.notifications.lock().await;
+        self.peripheral
+            .write(&self.write, &[MTU_TAG, 0, 0, 0, 0], WriteType::WithResponse)
+            .await?;
+        let frame =
+            match tokio::time::timeout(MTU_TIMEOUT, self.next_frame(&mut notifications)).await {
+                Ok(frame) => frame?,
+                Err(_) => {
+                    tracing::debug!("device did not answer the MTU request");
+                    return Ok(DEFAULT_MTU);
+                }
+            };
+        match frame.as_slice() {
+            [MTU_TAG, _, _, _, _, mtu, ..] if *mtu as usize > 5 => Ok(*mtu as usize),
+            _ => Ok(DEFAULT_MTU),
+        }
+    }
+
+    /// Exchange an APDU with the device.
+    pub async fn exchange(&self, command: &APDUCommand) -> Result<APDUAnswer, LedgerError> {
+        let answer = {
+            // held for the whole exchange, so that APDUs are not interleaved
+            let mut notifications = self.notifications.lock().await;
+
+            let apdu = command.serialize();
+            tracing::debug!(apdu = %hex::encode(&apdu), bytes = apdu.len(), "Writing APDU to device");
+            for frame in frame_apdu(&apdu, self.mtu) {
+                self.peripheral
+                    .write(&self.write, &frame, WriteType::WithResponse)
+                    .await
+                    .map_err(NativeTransportError::from)?;
+            }
+
+            let mut builder = AnswerBuilder::default();
+            loop {
+                let frame = self.next_frame(&mut notifications).await?;
+                if let Some(answer) = builder.push(&frame)? {
+                    break answer;
+                }
+            }
+        };
+
+        let answer = APDUAnswer::from_answer(answer)?;
+
+        match answer.response_status() {
+            None => Ok(answer),
+            Some(response) => {
+                if response.is_success() {
+                    Ok(answer)
+                } else {
+                    Err(response.into())
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LedgerAsync for TransportBle {
+    async fn init() -> Result<Self, LedgerError> {
+        Ok(Self::connect_first(DEFAULT_SCAN_DURATION).await?)
+    }
+
+    async fn exchange(&self, packet: &APDUCommand) -> Result<APDUAnswer, LedgerError> {
+        TransportBle::exchange(self, packet).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_frames_apdus() {
+        let apdu: Vec<u8> = (0..40).collect();
+        let frames = frame_apdu(&apdu, 20);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(&frames[0][..5], &[LEDGER_TAG, 0, 0, 0, 40]);
+        assert_eq!(&frames[1][..3], &[LEDGER_TAG, 0, 1]);
+        assert_eq!(&frames[2][..3], &[LEDGER_TAG, 0, 2]);
+        assert!(frames.iter().all(|frame| frame.len() <= 20));
+
+        // frames round-trip through the answer builder
+        let mut builder = AnswerBuilder::default();
+        assert_eq!(builder.push(&frames[0]).unwrap(), None);
+        assert_eq!(builder.push(&frames[1]).unwrap(), None);
+        assert_eq!(builder.push(&frames[2]).unwrap(), Some(apdu));
+    }
+
+    #[test]
+    fn it_rejects_out_of_order_frames() {
+        let frames = frame_apdu(&[0u8; 40], 20);
+        let mut builder = AnswerBuilder::default();
+        builder.push(&frames[0]).unwrap();
+        match builder.push(&frames[2]) {
+            Err(NativeTransportError::SequenceMismatch {
+                got: 2,
+                expected: 1,
+            }) => {}
+            _ => panic!("expected sequence mismatch"),
+        }
+    }
+}
@@ -16,9 +16,20 @@ pub enum NativeTransportError {
     /// Device not found error
     #[error("Ledger device not found")]
     DeviceNotFound,
+    /// The HID API could not be initialized. It is re-initialized on the
+    /// next attempt.
+    #[error("Could not initialize HID API. {0}")]
+    HidInit(hidapi_rusb::HidError),
+    /// The transport panicked during an exchange. The connection was dropped,
+    /// and is re-established on the next exchange.
+    #[error("Ledger transport panicked: {0}")]
+    Panicked(String),
     /// Device open error.
     #[error("Error opening device. {0}. Hint: This usually means that the device is already in use by another transport instance.")]
     CantOpen(hidapi_rusb::HidError),
+    /// The device is already open in this process.
+    #[error("Ledger device at {0:?} is already open. Hint: Each device may only have one handle at a time.")]
+    AlreadyOpen(std::ffi::CString),
     /// SequenceMismatch
     #[error("Sequence mismatch. Got {got} from device. Expected {expected}")]
     SequenceMismatch {
@@ -27,6 +38,9 @@ pub enum NativeTransportError {
         /// The expected sequence
         expected: u16,
     },
+    /// The exchange was cancelled by the caller.
+    #[error("Exchange cancelled")]
+    Cancelled,
     /// Communication error
     #[error("Ledger device: communication error `{0}`")]
     Comm(&'static str),
@@ -39,6 +53,14 @@ pub enum NativeTransportError {
     /// HID error
     #[error(transparent)]
     Hid(#[from] hidapi_rusb::HidError),
+    /// BLE error
+    #[cfg(feature = "ble")]
+    #[error(transparent)]
+    Ble(#[from] btleplug::Error),
+    /// Speculos automation error
+    #[cfg(feature = "speculos")]
+    #[error("Speculos automation error: {0}")]
+    Automation(String),
     /// UT8F error
     #[error(transparent)]
     UTF8(#[from] std::str::Utf8Error),
@@ -48,3 +70,20 @@ pub enum NativeTransportError {
     #[error("Invalid TERMUX_USB_FD variable. Are you using termux-usb?")]
     InvalidTermuxUsbFd,
 }
+
+impl NativeTransportError {
+    /// True if the error indicates that the connection to the device was
+    /// lost, e.g. because it was unplugged or the app was closed. A new
+    /// connection may succeed.
+    pub const fn is_disconnect(&self) -> bool {
+        matches!(
+            self,
+            Self::DeviceNotFound
+                | Self::Comm(_)
+                | Self::Io(_)
+                | Self::Hid(_)
+                | Self::HidInit(_)
+                | Self::Panicked(_)
+        )
+    }
+}
@@ -1,6 +1,19 @@
+use std::{
+    ffi::{CStr, CString},
+    net::{SocketAddr, ToSocketAddrs},
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::sync::{mpsc, oneshot};
 
-use crate::{APDUAnswer, APDUCommand, LedgerError};
+use crate::{
+    transports::{
+        mock::MockTransport,
+        tcp::{TransportTcp, SPECULOS_ADDR_ENV},
+    },
+    APDUAnswer, APDUCommand, LedgerError,
+};
 
 mod error;
 pub use error::NativeTransportError;
@@ -8,6 +21,13 @@ pub use error::NativeTransportError;
 pub mod hid;
 use hid::TransportNativeHID;
 
+mod watcher;
+pub use watcher::{DeviceEvent, DeviceWatcher};
+
+/// BLE transport for Nano X, Stax and Flex devices
+#[cfg(feature = "ble")]
+pub mod ble;
+
 /// A packet exchange request.
 struct APDUExchange {
     /// The command to send to the device.
@@ -30,28 +50,231 @@ impl APDUExchange {
     }
 }
 
+/// The blocking transport driven by a [`LedgerTask`].
+#[derive(Debug)]
+enum NativeTransport {
+    /// A USB HID device.
+    Hid(TransportNativeHID),
+    /// A Speculos emulator.
+    Tcp(TransportTcp),
+    /// A mock transport.
+    Mock(Arc<MockTransport>),
+}
+
+impl NativeTransport {
+    /// Exchange a packet with the device. Only HID exchanges may be
+    /// cancelled while waiting for the answer.
+    fn exchange(
+        &self,
+        command: &APDUCommand,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<APDUAnswer, LedgerError> {
+        match self {
+            Self::Hid(hid) => hid.exchange_cancellable(command, cancelled),
+            Self::Tcp(tcp) => tcp.exchange(command),
+            Self::Mock(mock) => mock.exchange(command),
+        }
+    }
+}
+
+/// How a [`LedgerTask`] (re)establishes its connection.
+#[derive(Debug, Clone)]
+enum Connector {
+    /// The first HID device found.
+    FirstHid,
+    /// The HID device at a specific path.
+    HidPath(CString),
+    /// A Speculos emulator.
+    Tcp(Vec<SocketAddr>),
+    /// A mock transport.
+    Mock(Arc<MockTransport>),
+}
+
+impl Connector {
+    /// Open a new connection.
+    fn connect(&self) -> Result<NativeTransport, NativeTransportError> {
+        match self {
+            Self::FirstHid => TransportNativeHID::new().map(NativeTransport::Hid),
+            Self::HidPath(path) => TransportNativeHID::open(path).map(NativeTransport::Hid),
+            Self::Tcp(addrs) => TransportTcp::new(&addrs[..]).map(NativeTransport::Tcp),
+            Self::Mock(mock) => Ok(NativeTransport::Mock(mock.clone())),
+        }
+    }
+}
+
+/// A policy for re-establishing the device connection after it is lost,
+/// e.g. because the device was unplugged, or the app was closed.
+///
+/// When an exchange fails with a disconnect error, the task reconnects with
+/// exponential backoff, re-enumerating devices on each attempt, and then
+/// resends the command. If all attempts fail, the original error is
+/// returned, and the next exchange will try again.
+///
+/// Device state is not preserved across reconnects. Multi-APDU protocols
+/// should rely on [`LedgerProtocol::recover`][crate::LedgerProtocol::recover]
+/// to restore the app state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// The maximum number of reconnect attempts per failed exchange.
+    pub max_attempts: u32,
+    /// The delay before the first attempt.
+    pub initial_backoff: Duration,
+    /// The maximum delay between attempts. The delay doubles after each
+    /// failed attempt, up to this limit.
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(4),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The delay before the attempt at index `attempt` (starting at 0).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// How long to wait for the user to unlock the device. See
+/// [`Ledger::exchange_when_unlocked`][crate::Ledger::exchange_when_unlocked].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnlockWait {
+    /// The delay between retries.
+    pub interval: Duration,
+    /// The total time to wait before giving up.
+    pub timeout: Duration,
+}
+
+impl Default for UnlockWait {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
 /// A task that manages Ledger packet exchange.
 struct LedgerTask {
-    ledger: TransportNativeHID,
+    ledger: Option<NativeTransport>,
+    connector: Connector,
+    reconnect: Arc<Mutex<Option<ReconnectPolicy>>>,
     rx: tokio::sync::mpsc::Receiver<APDUExchange>,
 }
 
 impl LedgerTask {
     /// Create a new task.
-    fn new(ledger: TransportNativeHID) -> (Self, tokio::sync::mpsc::Sender<APDUExchange>) {
+    fn new(
+        ledger: NativeTransport,
+        connector: Connector,
+        reconnect: Arc<Mutex<Option<ReconnectPolicy>>>,
+    ) -> (Self, tokio::sync::mpsc::Sender<APDUExchange>) {
         let (tx, rx) = tokio::sync::mpsc::channel(1);
-        (Self { ledger, rx }, tx)
+        (
+            Self {
+                ledger: Some(ledger),
+                connector,
+                reconnect,
+                rx,
+            },
+            tx,
+        )
+    }
+
+    /// Exchange a packet. Errors that leave the connection unusable are
+    /// contained here: the connection is dropped, and re-established on this
+    /// exchange according to the reconnect policy, or else on the next one.
+    fn exchange(
+        &mut self,
+        command: &APDUCommand,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<APDUAnswer, LedgerError> {
+        let err = match self.try_exchange(command, cancelled) {
+            Err(LedgerError::NativeTransportError(err)) if err.is_disconnect() => err,
+            answer => return answer,
+        };
+        self.drop_connection(&err);
+
+        let policy = match *self.reconnect.lock().unwrap() {
+            Some(policy) => policy,
+            None => return Err(err.into()),
+        };
+
+        for attempt in 0..policy.max_attempts {
+            std::thread::sleep(policy.backoff(attempt));
+            if cancelled() {
+                return Err(NativeTransportError::Cancelled.into());
+            }
+            match self.try_exchange(command, cancelled) {
+                Err(LedgerError::NativeTransportError(err)) if err.is_disconnect() => {
+                    tracing::debug!(attempt, %err, "reconnect attempt failed");
+                    self.drop_connection(&err);
+                }
+                answer => {
+                    tracing::info!(attempt, "reconnected to device");
+                    return answer;
+                }
+            }
+        }
+        tracing::error!(%err, "could not reconnect to device");
+        Err(err.into())
+    }
+
+    /// Exchange a packet, connecting first if there is no connection. Panics
+    /// are caught, and returned as `Panicked` errors.
+    fn try_exchange(
+        &mut self,
+        command: &APDUCommand,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<APDUAnswer, LedgerError> {
+        std::panic::catch_unwind(AssertUnwindSafe(|| {
+            if self.ledger.is_none() {
+                self.ledger = Some(self.connector.connect()?);
+            }
+            self.ledger.as_ref().unwrap().exchange(command, cancelled)
+        }))
+        .unwrap_or_else(|panic| {
+            let msg = panic
+                .downcast_ref::<&str>()
+                .map(ToString::to_string)
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(NativeTransportError::Panicked(msg).into())
+        })
+    }
+
+    /// Drop a connection that failed with `err`. HID errors also drop the HID
+    /// API context, so that it is re-initialized on reconnect.
+    fn drop_connection(&mut self, err: &NativeTransportError) {
+        self.ledger = None;
+        if let NativeTransportError::Hid(_) | NativeTransportError::HidInit(_) = err {
+            hid::reset_api();
+        }
     }
 
     /// Spawn the task that will run Ledger protocols.
     fn spawn(mut self) {
         let fut = async move {
             while let Some(exchange) = self.rx.recv().await {
+                // the caller dropped the exchange future before it started
+                if exchange.answer.is_closed() {
+                    tracing::debug!("skipping cancelled exchange");
+                    continue;
+                }
                 // blocking IO
-                let answer = self.ledger.exchange(&exchange.command);
-                let answer = exchange.answer.send(answer);
-                if let Err(Err(err)) = answer {
-                    tracing::error!(err = %err, "could not send answer to exchange");
+                let cancelled = || exchange.answer.is_closed();
+                let answer = self.exchange(&exchange.command, &cancelled);
+                if exchange.answer.send(answer).is_err() {
+                    tracing::debug!("caller dropped the exchange");
                 }
             }
         };
@@ -69,21 +292,77 @@ impl LedgerTask {
 /// A handle to the Ledger device. This handle is not clone, as it is critical
 /// that only one connection to the device is active at a time. APDUs may NOT
 /// be interleaved.
+///
+/// Handles to distinct devices may be used concurrently. Each has its own
+/// worker task, which serializes the APDUs sent to its device. Opening a
+/// device that already has a handle in this process fails with
+/// `AlreadyOpen`, and [`LedgerHandle::init`] skips devices that already
+/// have a handle.
 #[derive(Debug)]
 pub struct LedgerHandle {
     tx: mpsc::Sender<APDUExchange>,
+    reconnect: Arc<Mutex<Option<ReconnectPolicy>>>,
 }
 
 impl LedgerHandle {
     /// Init a handle, and spawn a task to manage Ledger packet exchange.
+    ///
+    /// If the `LEDGER_SPECULOS_ADDR` environment variable is set, this
+    /// connects to the Speculos emulator at that address. Otherwise it
+    /// connects to the first HID device found.
     pub fn init() -> Result<Self, LedgerError> {
-        let ledger = TransportNativeHID::new()?;
-        let (task, tx) = LedgerTask::new(ledger);
+        match std::env::var(SPECULOS_ADDR_ENV) {
+            Ok(addr) => Self::init_tcp(addr),
+            Err(_) => Self::spawn(Connector::FirstHid),
+        }
+    }
+
+    /// Init a handle connected to the HID device at `path`, as returned by
+    /// [`TransportNativeHID::list_devices`], and spawn a task to manage
+    /// Ledger packet exchange.
+    pub fn init_path(path: &CStr) -> Result<Self, LedgerError> {
+        Self::spawn(Connector::HidPath(path.to_owned()))
+    }
+
+    /// Init a handle connected to the Speculos emulator APDU server at
+    /// `addr`, and spawn a task to manage Ledger packet exchange.
+    pub fn init_tcp<A: ToSocketAddrs>(addr: A) -> Result<Self, LedgerError> {
+        let addrs = addr
+            .to_socket_addrs()
+            .map_err(NativeTransportError::from)?
+            .collect();
+        Self::spawn(Connector::Tcp(addrs))
+    }
+
+    /// Init a handle connected to a mock transport, and spawn a task to
+    /// manage packet exchange.
+    pub fn init_mock(mock: MockTransport) -> Result<Self, LedgerError> {
+        Self::spawn(Connector::Mock(Arc::new(mock)))
+    }
+
+    /// Connect, and spawn a task to manage packet exchange.
+    fn spawn(connector: Connector) -> Result<Self, LedgerError> {
+        let ledger = connector.connect()?;
+        let reconnect = Arc::new(Mutex::new(None));
+        let (task, tx) = LedgerTask::new(ledger, connector, reconnect.clone());
         task.spawn();
-        Ok(Self { tx })
+        Ok(Self { tx, reconnect })
+    }
+
+    /// Set the policy for reconnecting after the device connection is lost.
+    /// By default, the failed exchange returns its error, and the next
+    /// exchange makes a single reconnect attempt.
+    pub fn set_reconnect_policy(&self, policy: Option<ReconnectPolicy>) {
+        *self.reconnect.lock().unwrap() = policy;
     }
 
     /// Exchange a packet with the device.
+    ///
+    /// This is cancellation-safe. If the returned future is dropped, the
+    /// command is not sent if it has not been sent yet. If it has been sent
+    /// to a HID device, the worker stops waiting for the answer, and
+    /// discards it when it arrives, so that it is never delivered to a later
+    /// exchange.
     pub async fn exchange(&self, apdu: APDUCommand) -> Result<APDUAnswer, LedgerError> {
         let (exchange, rx) = APDUExchange::new(apdu);
         self.tx
@@ -93,3 +372,100 @@ impl LedgerHandle {
         rx.await.map_err(|_| LedgerError::BackendGone)?
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Serve `connections` connections, each answering a single APDU with
+    /// `9000` and then hanging up. Returns the address, and a count of the
+    /// connections accepted so far.
+    fn flaky_speculos(connections: usize) -> (SocketAddr, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        std::thread::spawn(move || {
+            for _ in 0..connections {
+                let (mut stream, _) = listener.accept().unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut len = [0u8; 4];
+                stream.read_exact(&mut len).unwrap();
+                let mut apdu = vec![0u8; u32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut apdu).unwrap();
+                stream.write_all(&[0, 0, 0, 0, 0x90, 0x00]).unwrap();
+            }
+        });
+        (addr, accepted)
+    }
+
+    fn command() -> APDUCommand {
+        APDUCommand {
+            cla: 0xe0,
+            ins: 0x06,
+            p1: 0x00,
+            p2: 0x00,
+            data: vec![].into(),
+            response_len: None,
+        }
+    }
+
+    #[test]
+    fn it_computes_backoff() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(250));
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.backoff(4), Duration::from_secs(4));
+        assert_eq!(policy.backoff(40), Duration::from_secs(4));
+    }
+
+    #[tokio::test]
+    async fn it_recovers_on_next_exchange() {
+        let (addr, _) = flaky_speculos(2);
+        let handle = LedgerHandle::init_tcp(addr).unwrap();
+        handle.exchange(command()).await.unwrap();
+        // the emulator hung up. Without a reconnect policy, the error is
+        // surfaced, and the next exchange reconnects
+        assert!(handle.exchange(command()).await.is_err());
+        handle.exchange(command()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_skips_cancelled_exchanges() {
+        let mock = MockTransport::new().expect(&command(), &[0x90, 0x00]);
+        let handle = LedgerHandle::init_mock(mock).unwrap();
+
+        // queue an exchange whose caller has gone away
+        let (exchange, rx) = APDUExchange::new(command());
+        drop(rx);
+        handle.tx.send(exchange).await.unwrap();
+
+        // the mock only expects one exchange
+        handle.exchange(command()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_reconnects() {
+        let (addr, accepted) = flaky_speculos(2);
+        let handle = LedgerHandle::init_tcp(addr).unwrap();
+        handle.set_reconnect_policy(Some(ReconnectPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        }));
+        handle.exchange(command()).await.unwrap();
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+        // the emulator hung up. The exchange reconnects, and is resent
+        handle.exchange(command()).await.unwrap();
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+
+        // the emulator is gone. Every attempt fails, and the error is surfaced
+        assert!(handle.exchange(command()).await.is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Device connect/disconnect events.
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use tokio::time::{Interval, MissedTickBehavior};
+
+use super::hid::{LedgerDeviceInfo, TransportNativeHID};
+
+/// A change in the set of connected Ledger devices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// A device was connected.
+    Connected(LedgerDeviceInfo),
+    /// A device was disconnected.
+    Disconnected(LedgerDeviceInfo),
+}
+
+/// A stream of [`DeviceEvent`]s, produced by periodically enumerating HID
+/// devices. Devices connected when the watcher is created are reported as
+/// `Connected` on the first poll.
+///
+/// Enumeration errors are logged, and the devices are enumerated again on
+/// the next tick. The stream never ends. It must be polled within a tokio
+/// runtime with the time driver enabled.
+///
+/// Note that a device is briefly disconnected and re-connected when an app
+/// is opened or closed on it, as its USB interfaces change.
+#[derive(Debug)]
+pub struct DeviceWatcher {
+    interval: Interval,
+    devices: Vec<LedgerDeviceInfo>,
+    events: VecDeque<DeviceEvent>,
+}
+
+impl DeviceWatcher {
+    /// The default polling interval.
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Instantiate a watcher that enumerates devices every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            interval,
+            devices: vec![],
+            events: Default::default(),
+        }
+    }
+
+    /// The devices connected as of the last enumeration.
+    pub fn devices(&self) -> &[LedgerDeviceInfo] {
+        &self.devices
+    }
+
+    /// Queue events for the difference between the known devices and
+    /// `devices`. Devices are identified by path.
+    fn update(&mut self, devices: Vec<LedgerDeviceInfo>) {
+        for known in self.devices.iter() {
+            if !devices.iter().any(|dev| dev.path == known.path) {
+                self.events
+                    .push_back(DeviceEvent::Disconnected(known.clone()));
+            }
+        }
+        for dev in devices.iter() {
+            if !self.devices.iter().any(|known| known.path == dev.path) {
+                self.events.push_back(DeviceEvent::Connected(dev.clone()));
+            }
+        }
+        self.devices = devices;
+    }
+}
+
+impl Default for DeviceWatcher {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_INTERVAL)
+    }
+}
+
+impl Stream for DeviceWatcher {
+    type Item = DeviceEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            if self.interval.poll_tick(cx).is_pending() {
+                return Poll::Pending;
+            }
+            // enumeration is blocking, but short
+            match TransportNativeHID::list_devices() {
+                Ok(devices) => self.update(devices),
+                Err(err) => tracing::debug!(%err, "could not enumerate devices"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+
+    use super::*;
+    use crate::transports::native::hid::LedgerModel;
+
+    fn device(path: &str) -> LedgerDeviceInfo {
+        LedgerDeviceInfo {
+            model: LedgerModel::NanoSPlus,
+            path: CString::new(path).unwrap(),
+            serial: None,
+            product: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_diffs_devices() {
+        let mut watcher = DeviceWatcher::default();
+        watcher.update(vec![device("a"), device("b")]);
+        watcher.update(vec![device("b"), device("c")]);
+        watcher.update(vec![device("b"), device("c")]);
+
+        let events: Vec<_> = watcher.events.drain(..).collect();
+        assert_eq!(
+            events,
+            vec![
+                DeviceEvent::Connected(device("a")),
+                DeviceEvent::Connected(device("b")),
+                DeviceEvent::Disconnected(device("a")),
+                DeviceEvent::Connected(device("c")),
+            ]
+        );
+        assert_eq!(watcher.devices(), &[device("b"), device("c")]);
+    }
+}
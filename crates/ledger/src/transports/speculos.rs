@@ -0,0 +1,308 @@
+//! Helpers driving the Speculos emulator's REST automation API, so that
+//! end-to-end tests can navigate and approve on-screen prompts unattended.
+//!
+//! The REST API is served on port 5000 by default. Buttons are pressed with
+//! `POST /button/{left,right,both}`, and the text on the current screen is
+//! read with `GET /events?currentscreenonly=true`.
+//!
+//! These helpers block. Run them on a separate thread (e.g. with
+//! `tokio::task::spawn_blocking`) while the exchange awaiting the approval is
+//! in flight.
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use crate::transports::native::NativeTransportError;
+
+/// The environment variable used to locate the Speculos REST API.
+pub const SPECULOS_API_ENV: &str = "LEDGER_SPECULOS_API";
+
+/// The default address of the Speculos REST API.
+pub const DEFAULT_SPECULOS_API: &str = "127.0.0.1:5000";
+
+/// A button on the emulated device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    /// The left button
+    Left,
+    /// The right button
+    Right,
+    /// Both buttons at once
+    Both,
+}
+
+impl Button {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Both => "both",
+        }
+    }
+}
+
+/// A text event, as reported by the REST API.
+#[derive(Debug, serde::Deserialize)]
+struct Event {
+    text: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Events {
+    events: Vec<Event>,
+}
+
+const fn automation_err(msg: String) -> NativeTransportError {
+    NativeTransportError::Automation(msg)
+}
+
+/// A client for the Speculos REST automation API.
+#[derive(Debug, Clone, Copy)]
+pub struct SpeculosAutomation {
+    addr: SocketAddr,
+    poll_interval: Duration,
+}
+
+impl SpeculosAutomation {
+    /// Instantiate a client for the REST API at `addr`.
+    pub fn new<A: ToSocketAddrs>(addr: A) -> Result<Self, NativeTransportError> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| automation_err("no address for the REST API".to_owned()))?;
+        Ok(Self {
+            addr,
+            poll_interval: Duration::from_millis(100),
+        })
+    }
+
+    /// Instantiate a client for the REST API at the address in the
+    /// `LEDGER_SPECULOS_API` environment variable, or at the default address
+    /// if it is not set.
+    pub fn from_env() -> Result<Self, NativeTransportError> {
+        match std::env::var(SPECULOS_API_ENV) {
+            Ok(addr) => Self::new(addr),
+            Err(_) => Self::new(DEFAULT_SPECULOS_API),
+        }
+    }
+
+    /// Set how often the screen is polled while waiting for text.
+    pub const fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Make an HTTP/1.0 request, and return the response body.
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> Result<String, NativeTransportError> {
+        let request = format!(
+            "{method} {path} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            self.addr,
+            body.len()
+        );
+        let mut stream = TcpStream::connect(self.addr)?;
+        stream.write_all(request.as_bytes())?;
+        stream.flush()?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| automation_err(format!("malformed response to {method} {path}")))?;
+
+        let status = head.split_whitespace().nth(1).unwrap_or_default();
+        if !status.starts_with('2') {
+            return Err(automation_err(format!(
+                "{method} {path} failed with status {status}"
+            )));
+        }
+        Ok(body.to_owned())
+    }
+
+    /// Press and release a button.
+    pub fn press(&self, button: Button) -> Result<(), NativeTransportError> {
+        tracing::debug!(button = button.as_str(), "pressing emulator button");
+        self.request(
+            "POST",
+            &format!("/button/{}", button.as_str()),
+            r#"{"action":"press-and-release"}"#,
+        )?;
+        Ok(())
+    }
+
+    /// The text currently shown on the screen, one entry per text element.
+    pub fn screen_text(&self) -> Result<Vec<String>, NativeTransportError> {
+        let body = self.request("GET", "/events?currentscreenonly=true", "")?;
+        let events: Events = serde_json::from_str(&body)
+            .map_err(|e| automation_err(format!("malformed events: {e}")))?;
+        Ok(events.events.into_iter().map(|event| event.text).collect())
+    }
+
+    /// True if any text element on the current screen contains `text`.
+    pub fn screen_contains(&self, text: &str) -> Result<bool, NativeTransportError> {
+        Ok(self.screen_text()?.iter().any(|t| t.contains(text)))
+    }
+
+    /// Wait until the screen shows `text`, or fail after `timeout`.
+    pub fn wait_for_text(&self, text: &str, timeout: Duration) -> Result<(), NativeTransportError> {
+        let started = Instant::now();
+        loop {
+            let screen = self.screen_text()?;
+            if screen.iter().any(|t| t.contains(text)) {
+                return Ok(());
+            }
+            if started.elapsed() >= timeout {
+                return Err(automation_err(format!(
+                    "timed out waiting for {text:?}. Screen shows {screen:?}"
+                )));
+            }
+            std::thread::sleep(self.poll_interval);
+        }
+    }
+
+    /// Press `button` until the screen shows `text`, up to `max_presses`
+    /// times.
+    pub fn press_until(
+        &self,
+        button: Button,
+        text: &str,
+        max_presses: usize,
+    ) -> Result<(), NativeTransportError> {
+        for _ in 0..max_presses {
+            if self.screen_contains(text)? {
+                return Ok(());
+            }
+            self.press(button)?;
+            std::thread::sleep(self.poll_interval);
+        }
+        if self.screen_contains(text)? {
+            return Ok(());
+        }
+        Err(automation_err(format!(
+            "{text:?} not shown after {max_presses} presses of {} button",
+            button.as_str()
+        )))
+    }
+
+    /// Navigate right to the screen showing `text`, e.g. "Approve", and
+    /// press both buttons to select it. Waits up to `timeout` for the first
+    /// screen of the prompt to appear.
+    pub fn approve(
+        &self,
+        text: &str,
+        timeout: Duration,
+        max_presses: usize,
+    ) -> Result<(), NativeTransportError> {
+        let started = Instant::now();
+        // wait for the prompt, i.e. for the screen to change from the idle
+        // screen
+        let idle = self.screen_text()?;
+        while self.screen_text()? == idle && !self.screen_contains(text)? {
+            if started.elapsed() >= timeout {
+                return Err(automation_err(format!(
+                    "timed out waiting for a prompt. Screen shows {idle:?}"
+                )));
+            }
+            std::thread::sleep(self.poll_interval);
+        }
+        self.press_until(Button::Right, text, max_presses)?;
+        self.press(Button::Both)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    /// Serve a fake REST API. Each right press advances through `screens`.
+    /// Returns the address, and the log of requests.
+    fn mock_api(screens: &'static [&'static str]) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let log = Arc::new(Mutex::new(vec![]));
+        let requests = log.clone();
+        std::thread::spawn(move || {
+            let mut screen = 0;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_owned();
+                // skip the headers and body
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim_end().is_empty() {
+                        break;
+                    }
+                    if let Some(len) = header.strip_prefix("Content-Length: ") {
+                        content_length = len.trim_end().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0u8; content_length]).unwrap();
+
+                if line.starts_with("POST /button/right") {
+                    screen = std::cmp::min(screen + 1, screens.len() - 1);
+                }
+                requests.lock().unwrap().push(line);
+
+                let body = format!(
+                    r#"{{"events": [{{"text": "{}", "x": 0, "y": 0}}]}}"#,
+                    screens[screen]
+                );
+                write!(
+                    stream,
+                    "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{body}"
+                )
+                .unwrap();
+            }
+        });
+        (addr, log)
+    }
+
+    #[test]
+    fn it_navigates_to_text() {
+        let (addr, log) = mock_api(&["Review transaction", "Amount", "Approve"]);
+        let auto = SpeculosAutomation::new(addr)
+            .unwrap()
+            .poll_interval(Duration::from_millis(1));
+
+        assert_eq!(auto.screen_text().unwrap(), vec!["Review transaction"]);
+        auto.press_until(Button::Right, "Approve", 5).unwrap();
+        auto.press(Button::Both).unwrap();
+
+        let presses: Vec<_> = log
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|line| line.starts_with("POST"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            presses,
+            vec![
+                "POST /button/right HTTP/1.0",
+                "POST /button/right HTTP/1.0",
+                "POST /button/both HTTP/1.0",
+            ]
+        );
+
+        assert!(auto.press_until(Button::Right, "Reject", 2).is_err());
+        auto.wait_for_text("Approve", Duration::from_millis(10))
+            .unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! TCP APDU transport for the Speculos Ledger emulator.
+//!
+//! Speculos exposes an APDU server (port 9999 by default). Commands are sent as a 4-byte
+//! big-endian length prefix followed by the raw APDU. Responses are a 4-byte big-endian length
+//! of the response data, followed by the data and the 2-byte status word.
+
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+};
+
+use crate::{
+    common::{APDUAnswer, APDUCommand},
+    errors::LedgerError,
+    transports::native::NativeTransportError,
+};
+
+/// The environment variable used to select the TCP transport. When set, `Ledger::init`
+/// connects to the Speculos APDU server at this address instead of a HID device.
+pub const SPECULOS_ADDR_ENV: &str = "LEDGER_SPECULOS_ADDR";
+
+/// The default address of the Speculos APDU server.
+pub const DEFAULT_SPECULOS_ADDR: &str = "127.0.0.1:9999";
+
+/// TCP transport for the Speculos Ledger emulator
+pub struct TransportTcp {
+    stream: Mutex<TcpStream>,
+}
+
+impl std::fmt::Debug for TransportTcp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let peer = self
+            .stream
+            .lock()
+            .ok()
+            .and_then(|stream| stream.peer_addr().ok());
+        f.debug_struct("TransportTcp").field("peer", &peer).finish()
+    }
+}
+
+impl TransportTcp {
+    /// Connect to a Speculos APDU server at `addr`.
+    pub fn new<A: ToSocketAddrs>(addr: A) -> Result<Self, NativeTransportError> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream: Mutex::new(stream),
+        })
+    }
+
+    /// Connect to the Speculos APDU server at the address in the `LEDGER_SPECULOS_ADDR`
+    /// environment variable. Returns `None` if the variable is not set.
+    pub fn from_env() -> Option<Result<Self, NativeTransportError>> {
+        std::env::var(SPECULOS_ADDR_ENV).ok().map(Self::new)
+    }
+
+    /// Exchange an APDU with the emulator.
+    pub fn exchange(&self, command: &APDUCommand) -> Result<APDUAnswer, LedgerError> {
+        let answer = {
+            let mut stream = self.stream.lock().unwrap();
+            write_apdu(&mut *stream, &command.serialize())?;
+            read_response_apdu(&mut *stream)?
+        };
+
+        let answer = APDUAnswer::from_answer(answer)?;
+
+        match answer.response_status() {
+            None => Ok(answer),
+            Some(response) => {
+                if response.is_success() {
+                    Ok(answer)
+                } else {
+                    Err(response.into())
+                }
+            }
+        }
+    }
+}
+
+fn write_apdu<W: Write>(writer: &mut W, apdu_command: &[u8]) -> Result<(), NativeTransportError> {
+    tracing::debug!(apdu = %hex::encode(apdu_command), bytes = apdu_command.len(), "Writing APDU to emulator");
+
+    let mut buf = Vec::with_capacity(apdu_command.len() + 4);
+    buf.extend_from_slice(&(apdu_command.len() as u32).to_be_bytes());
+    buf.extend_from_slice(apdu_command);
+    writer.write_all(&buf)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a response APDU, including the status word.
+fn read_response_apdu<R: Read>(reader: &mut R) -> Result<Vec<u8>, NativeTransportError> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    tracing::trace!(
+        expected_response_len = len,
+        "Received response length from emulator"
+    );
+
+    // response data is followed by the 2-byte status word
+    let mut answer_buf = vec![0u8; len + 2];
+    reader.read_exact(&mut answer_buf)?;
+    Ok(answer_buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Serve a single connection, echoing each APDU's data back with `sw`.
+    fn mock_speculos(sw: u16) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            loop {
+                let mut len = [0u8; 4];
+                if stream.read_exact(&mut len).is_err() {
+                    return;
+                }
+                let mut apdu = vec![0u8; u32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut apdu).unwrap();
+
+                let data = if apdu.len() > 5 { &apdu[5..] } else { &[][..] };
+                let mut resp = (data.len() as u32).to_be_bytes().to_vec();
+                resp.extend_from_slice(data);
+                resp.extend_from_slice(&sw.to_be_bytes());
+                stream.write_all(&resp).unwrap();
+            }
+        });
+        addr
+    }
+
+    fn command(data: &[u8]) -> APDUCommand {
+        APDUCommand {
+            cla: 0xe0,
+            ins: 0x06,
+            p1: 0x00,
+            p2: 0x00,
+            data: data.into(),
+            response_len: None,
+        }
+    }
+
+    #[test]
+    fn it_exchanges_apdus() {
+        let transport = TransportTcp::new(mock_speculos(0x9000)).unwrap();
+
+        let answer = transport.exchange(&command(&[1, 2, 3])).unwrap();
+        assert_eq!(answer.data(), Some(&[1u8, 2, 3][..]));
+        assert_eq!(answer.retcode(), 0x9000);
+
+        let answer = transport.exchange(&command(&[])).unwrap();
+        assert_eq!(answer.data(), Some(&[][..]));
+    }
+
+    #[test]
+    fn it_surfaces_status_words() {
+        let transport = TransportTcp::new(mock_speculos(0x6985)).unwrap();
+        match transport.exchange(&command(&[1])) {
+            Err(LedgerError::BadRetcode(code)) => assert_eq!(code as u16, 0x6985),
+            _ => panic!("expected bad retcode"),
+        }
+    }
+}
@@ -6,6 +6,10 @@ use crate::{
     errors::LedgerError,
 };
 
+/// Direct WebHID transport, without the `@ledgerhq` JS transport libraries.
+#[cfg(feature = "browser")]
+pub mod webhid;
+
 // Compilation would fail either way, since the following `extern "C"` block
 // would not be linked to anything
 #[cfg(not(any(feature = "node", feature = "browser")))]
@@ -0,0 +1,285 @@
+//! Direct WebHID transport for Ledger devices. This speaks the Ledger HID
+//! framing protocol over `navigator.hid`, and does not depend on the
+//! `@ledgerhq` JS transport libraries.
+
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use wasm_bindgen::{closure::Closure, prelude::*, JsCast};
+use wasm_bindgen_futures::JsFuture;
+
+use async_trait::async_trait;
+
+use crate::{
+    common::{APDUAnswer, APDUCommand},
+    errors::LedgerError,
+    transports::{
+        hid_framing::{frame_apdu, packet_body},
+        LedgerAsync,
+    },
+};
+
+const LEDGER_VID: u16 = 0x2c97;
+
+#[wasm_bindgen]
+extern "C" {
+    /// The `navigator.hid` object.
+    #[wasm_bindgen(extends = js_sys::Object)]
+    type Hid;
+
+    // `hid.requestDevice(options): Promise<HIDDevice[]>`
+    #[wasm_bindgen(method, js_name = requestDevice)]
+    fn request_device(this: &Hid, options: &JsValue) -> js_sys::Promise;
+
+    // `hid.getDevices(): Promise<HIDDevice[]>`
+    #[wasm_bindgen(method, js_name = getDevices)]
+    fn get_devices(this: &Hid) -> js_sys::Promise;
+
+    /// A WebHID `HIDDevice`.
+    #[wasm_bindgen(extends = js_sys::Object)]
+    pub type HidDevice;
+
+    #[wasm_bindgen(method, getter)]
+    fn opened(this: &HidDevice) -> bool;
+
+    #[wasm_bindgen(method, getter, js_name = vendorId)]
+    fn vendor_id(this: &HidDevice) -> u16;
+
+    #[wasm_bindgen(method, getter, js_name = productId)]
+    fn product_id(this: &HidDevice) -> u16;
+
+    #[wasm_bindgen(method, getter, js_name = productName)]
+    fn product_name(this: &HidDevice) -> String;
+
+    #[wasm_bindgen(method)]
+    fn open(this: &HidDevice) -> js_sys::Promise;
+
+    #[wasm_bindgen(method)]
+    fn close(this: &HidDevice) -> js_sys::Promise;
+
+    // `device.sendReport(reportId, data): Promise<undefined>`
+    #[wasm_bindgen(method, js_name = sendReport)]
+    fn send_report(this: &HidDevice, report_id: u8, data: &[u8]) -> js_sys::Promise;
+
+    #[wasm_bindgen(method, setter, js_name = oninputreport)]
+    fn set_oninputreport(this: &HidDevice, handler: Option<&js_sys::Function>);
+
+    /// A WebHID `HIDInputReportEvent`.
+    #[wasm_bindgen(extends = js_sys::Object)]
+    type HidInputReportEvent;
+
+    #[wasm_bindgen(method, getter)]
+    fn data(this: &HidInputReportEvent) -> js_sys::DataView;
+}
+
+fn js_err(err: JsValue) -> LedgerError {
+    LedgerError::JsError(format!("{:?}", &err))
+}
+
+fn comm_err(msg: &str) -> LedgerError {
+    LedgerError::JsError(format!("Ledger device: communication error `{}`", msg))
+}
+
+/// Get `navigator.hid`, if the browser supports WebHID.
+fn navigator_hid() -> Result<Hid, LedgerError> {
+    let navigator = js_sys::Reflect::get(&js_sys::global(), &"navigator".into()).map_err(js_err)?;
+    let hid = js_sys::Reflect::get(&navigator, &"hid".into()).map_err(js_err)?;
+    if hid.is_undefined() {
+        return Err(LedgerError::JsError(
+            "WebHID is not supported in this environment".to_owned(),
+        ));
+    }
+    Ok(hid.unchecked_into())
+}
+
+/// Input reports received from the device, and the resolver of a pending
+/// read, if any.
+#[derive(Default)]
+struct Reports {
+    queue: VecDeque<Vec<u8>>,
+    waiting: Option<js_sys::Function>,
+}
+
+/// A Ledger transport over WebHID. Requires a browser supporting WebHID.
+///
+/// This transport is not clone. APDUs may NOT be interleaved, so callers
+/// must await each exchange before starting the next.
+pub struct WebHidTransport {
+    device: HidDevice,
+    reports: Rc<RefCell<Reports>>,
+    _on_report: Closure<dyn FnMut(HidInputReportEvent)>,
+}
+
+impl std::fmt::Debug for WebHidTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebHidTransport")
+            .field("product_name", &self.device.product_name())
+            .field("product_id", &self.device.product_id())
+            .finish()
+    }
+}
+
+impl WebHidTransport {
+    /// Prompt the user to select a Ledger device, and connect to it.
+    ///
+    /// Browsers only allow this in response to a user gesture, e.g. a click.
+    pub async fn request() -> Result<Self, LedgerError> {
+        let filter = js_sys::Object::new();
+        js_sys::Reflect::set(&filter, &"vendorId".into(), &LEDGER_VID.into()).map_err(js_err)?;
+        let options = js_sys::Object::new();
+        js_sys::Reflect::set(
+            &options,
+            &"filters".into(),
+            &js_sys::Array::of1(&filter).into(),
+        )
+        .map_err(js_err)?;
+
+        let devices = JsFuture::from(navigator_hid()?.request_device(&options))
+            .await
+            .map_err(js_err)?;
+        let device = js_sys::Array::from(&devices).get(0);
+        if device.is_undefined() {
+            return Err(LedgerError::JsError("No device selected".to_owned()));
+        }
+        Self::from_device(device.unchecked_into()).await
+    }
+
+    /// Connect to a Ledger device the user has previously granted access to,
+    /// without prompting. Returns `None` if there is no such device.
+    pub async fn open_granted() -> Result<Option<Self>, LedgerError> {
+        let devices = JsFuture::from(navigator_hid()?.get_devices())
+            .await
+            .map_err(js_err)?;
+        let device = js_sys::Array::from(&devices)
+            .iter()
+            .map(JsCast::unchecked_into::<HidDevice>)
+            .find(|device| device.vendor_id() == LEDGER_VID);
+        match device {
+            Some(device) => Self::from_device(device).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Connect to a WebHID device. The device is opened if necessary.
+    pub async fn from_device(device: HidDevice) -> Result<Self, LedgerError> {
+        if !device.opened() {
+            JsFuture::from(device.open()).await.map_err(js_err)?;
+        }
+
+        let reports = Rc::new(RefCell::new(Reports::default()));
+        let on_report = {
+            let reports = reports.clone();
+            Closure::wrap(Box::new(move |event: HidInputReportEvent| {
+                let data = event.data();
+                let report = js_sys::Uint8Array::new_with_byte_offset_and_length(
+                    &data.buffer(),
+                    data.byte_offset() as u32,
+                    data.byte_length() as u32,
+                )
+                .to_vec();
+
+                let waiting = {
+                    let mut reports = reports.borrow_mut();
+                    reports.queue.push_back(report);
+                    reports.waiting.take()
+                };
+                if let Some(resolve) = waiting {
+                    let _ = resolve.call0(&JsValue::NULL);
+                }
+            }) as Box<dyn FnMut(HidInputReportEvent)>)
+        };
+        device.set_oninputreport(Some(on_report.as_ref().unchecked_ref()));
+
+        Ok(Self {
+            device,
+            reports,
+            _on_report: on_report,
+        })
+    }
+
+    /// Wait for the next input report from the device.
+    async fn next_report(&self) -> Result<Vec<u8>, LedgerError> {
+        loop {
+            if let Some(report) = self.reports.borrow_mut().queue.pop_front() {
+                return Ok(report);
+            }
+            let reports = self.reports.clone();
+            let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+                reports.borrow_mut().waiting = Some(resolve);
+            });
+            JsFuture::from(promise).await.map_err(js_err)?;
+        }
+    }
+
+    /// Read a response APDU from the ledger channel.
+    async fn read_response_apdu(&self) -> Result<Vec<u8>, LedgerError> {
+        let mut answer_buf = vec![];
+        let mut expected_response_len = 0usize;
+        let mut sequence_idx = 0u16;
+
+        loop {
+            let report = self.next_report().await?;
+
+            let mut body = packet_body(&report, sequence_idx).map_err(comm_err)?;
+            if sequence_idx == 0 {
+                expected_response_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                body = &body[2..];
+            }
+
+            let missing = expected_response_len - answer_buf.len();
+            answer_buf.extend_from_slice(&body[..std::cmp::min(body.len(), missing)]);
+
+            if answer_buf.len() >= expected_response_len {
+                return Ok(answer_buf);
+            }
+            sequence_idx += 1;
+        }
+    }
+
+    /// Send an APDU command to the device, and receive a response
+    pub async fn exchange(&self, apdu_command: &APDUCommand) -> Result<APDUAnswer, LedgerError> {
+        // discard any reports left over from an abandoned exchange
+        self.reports.borrow_mut().queue.clear();
+
+        for packet in frame_apdu(&apdu_command.serialize()) {
+            JsFuture::from(self.device.send_report(0, &packet))
+                .await
+                .map_err(js_err)?;
+        }
+
+        let answer = APDUAnswer::from_answer(self.read_response_apdu().await?)?;
+
+        match answer.response_status() {
+            None => Ok(answer),
+            Some(response) => {
+                if response.is_success() {
+                    Ok(answer)
+                } else {
+                    Err(response.into())
+                }
+            }
+        }
+    }
+
+    /// Close the device, and release the input report handler.
+    pub async fn close(self) -> Result<(), LedgerError> {
+        self.device.set_oninputreport(None);
+        JsFuture::from(self.device.close()).await.map_err(js_err)?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl LedgerAsync for WebHidTransport {
+    /// Connect to a previously granted device, or else prompt the user to
+    /// select one.
+    async fn init() -> Result<Self, LedgerError> {
+        match Self::open_granted().await? {
+            Some(transport) => Ok(transport),
+            None => Self::request().await,
+        }
+    }
+
+    async fn exchange(&self, packet: &APDUCommand) -> Result<APDUAnswer, LedgerError> {
+        WebHidTransport::exchange(self, packet).await
+    }
+}
@@ -36,3 +36,53 @@ async fn exchange() {
     let result = transport.exchange(&command).await.unwrap();
     println!("{result}");
 }
+
+#[tokio::test]
+async fn require_app() {
+    let answer = hex::decode("0107426974636f696e05322e312e3001029000").unwrap();
+    let mock = transports::mock::MockTransport::new()
+        .expect(&AppInfo::command(), &answer)
+        .expect(&AppInfo::command(), &answer);
+    let transport = transports::Ledger::init_mock(mock).unwrap();
+
+    let info = transport
+        .require_app(&["Bitcoin", "Bitcoin Test"])
+        .await
+        .unwrap();
+    assert_eq!(info.version, "2.1.0");
+
+    match transport.require_app(&["Ethereum"]).await {
+        Err(coins_ledger::LedgerError::WrongApp { running, .. }) => assert_eq!(running, "Bitcoin"),
+        _ => panic!("expected wrong app"),
+    }
+}
+
+#[tokio::test]
+async fn exchange_when_unlocked() {
+    let mock = transports::mock::MockTransport::new()
+        .expect(&AppInfo::command(), &[0x55, 0x15])
+        .expect(&AppInfo::command(), &[0x55, 0x15])
+        .expect(&AppInfo::command(), &[0x90, 0x00]);
+    let transport = transports::Ledger::init_mock(mock).unwrap();
+
+    let wait = transports::native::UnlockWait {
+        interval: std::time::Duration::from_millis(1),
+        timeout: std::time::Duration::from_secs(5),
+    };
+    let mut prompts = 0;
+    transport
+        .exchange_when_unlocked(&AppInfo::command(), wait, || prompts += 1)
+        .await
+        .unwrap();
+    assert_eq!(prompts, 1);
+}
+
+#[tokio::test]
+async fn replay_fixture() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/app_info.apdus");
+    let mock = transports::mock::MockTransport::from_fixture(path).unwrap();
+    let transport = transports::Ledger::init_mock(mock).unwrap();
+
+    let info = transport.get_app_info().await.unwrap();
+    assert_eq!(info.name, "Bitcoin");
+}